@@ -0,0 +1,30 @@
+//! Feeds arbitrary input to the readability port so that panics and inputs that make it hang
+//! are found. Inputs found by the fuzzer can be checked against the whole export pipeline with
+//! `paperoni debug-extract`.
+#![no_main]
+// Only part of the included modules is used by the fuzz target
+#![allow(dead_code)]
+
+#[macro_use]
+extern crate lazy_static;
+
+use libfuzzer_sys::fuzz_target;
+
+// paperoni is only built as a binary so its extractor is included from its sources along
+// with the modules it uses
+#[path = "../../src/cjk.rs"]
+mod cjk;
+#[path = "../../src/errors.rs"]
+mod errors;
+#[path = "../../src/moz_readability/mod.rs"]
+mod moz_readability;
+
+use moz_readability::Readability;
+
+fuzz_target!(|data: &[u8]| {
+    // Pages are not required to be valid UTF-8 either
+    let html = String::from_utf8_lossy(data);
+    let mut readability = Readability::new(&html);
+    // Pages without an article fail to be extracted, only panics and hangs are crashes
+    let _ = readability.parse("https://example.com/fuzz");
+});
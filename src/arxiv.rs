@@ -0,0 +1,289 @@
+use kuchiki::{traits::*, NodeRef};
+use log::{debug, info};
+use url::Url;
+
+use crate::errors::PaperoniError;
+use crate::extractor::new_element;
+use crate::http::fetch_html;
+
+/// Selector of the content of the HTML renderings of papers made with LaTeXML
+pub const PAPER_SELECTOR: &str = "article.ltx_document";
+
+/// Where the HTML renderings of papers are looked for in order. arXiv renders the recent
+/// papers itself and ar5iv renders most of the older ones.
+const RENDERING_URLS: [&str; 2] = [
+    "https://arxiv.org/html/",
+    "https://ar5iv.labs.arxiv.org/html/",
+];
+
+/// The details of a paper from the citation meta tags of its abstract page
+#[derive(Debug, Default, PartialEq)]
+struct PaperMetadata {
+    title: Option<String>,
+    authors: Vec<String>,
+    published: Option<String>,
+    abstract_text: Option<String>,
+    pdf_url: Option<String>,
+}
+
+/// Returns the id of the paper of an arXiv abstract, PDF or HTML url e.g `2301.01234v2` or
+/// `hep-th/9901001` for the older ids
+pub fn paper_id(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?.trim_start_matches("www.");
+    if ![
+        "arxiv.org",
+        "export.arxiv.org",
+        "ar5iv.labs.arxiv.org",
+        "ar5iv.org",
+    ]
+    .contains(&host)
+    {
+        return None;
+    }
+    let path = url.path().trim_matches('/');
+    let id = ["abs/", "pdf/", "html/"]
+        .iter()
+        .find_map(|prefix| path.strip_prefix(prefix))?
+        .trim_end_matches(".pdf");
+    if id.is_empty() {
+        None
+    } else {
+        Some(id.to_owned())
+    }
+}
+
+/// Fetches a paper as the HTML rendering of its LaTeX source, which keeps its math and
+/// references, with the authors and the abstract of the paper in its meta tags. A document
+/// of the abstract linking to the PDF is returned for papers without a rendering.
+pub async fn fetch_paper(
+    client: &surf::Client,
+    id: &str,
+    max_redirects: u8,
+) -> Result<(String, String), PaperoniError> {
+    let abstract_url = format!("https://arxiv.org/abs/{}", id);
+    let (_, abstract_html) = fetch_html(client, &abstract_url, max_redirects).await?;
+    let metadata = parse_abstract_page(&abstract_html);
+
+    for rendering_url in RENDERING_URLS.iter() {
+        let url = format!("{}{}", rendering_url, id);
+        match fetch_html(client, &url, max_redirects).await {
+            Ok((url, html)) => {
+                let doc = kuchiki::parse_html().one(html);
+                if doc.select_first(PAPER_SELECTOR).is_ok() {
+                    info!("Using the HTML rendering of arXiv paper {} at {}", id, url);
+                    add_paper_metadata(&doc, &metadata);
+                    return Ok((url, doc.to_string()));
+                }
+            }
+            Err(e) => debug!("No HTML rendering of {} at {}: {}", id, url, e),
+        }
+    }
+    info!(
+        "arXiv paper {} has no HTML rendering so only its abstract is kept",
+        id
+    );
+    Ok((abstract_url, abstract_document(&metadata)))
+}
+
+fn parse_abstract_page(html: &str) -> PaperMetadata {
+    let doc = kuchiki::parse_html().one(html);
+    let meta_values = |name: &str| {
+        doc.select(&format!("meta[name=\"{}\"][content]", name))
+            .unwrap()
+            .filter_map(|meta| {
+                meta.attributes
+                    .borrow()
+                    .get("content")
+                    .map(|content| content.trim().to_owned())
+            })
+            .filter(|content| !content.is_empty())
+            .collect::<Vec<_>>()
+    };
+    let meta_value = |name: &str| meta_values(name).into_iter().next();
+    PaperMetadata {
+        title: meta_value("citation_title"),
+        authors: meta_values("citation_author")
+            .into_iter()
+            .map(|author| author_display_name(&author))
+            .collect(),
+        published: meta_value("citation_date").map(|date| date.replace('/', "-")),
+        abstract_text: meta_value("citation_abstract"),
+        pdf_url: meta_value("citation_pdf_url"),
+    }
+}
+
+/// Citation meta tags name the authors as "Last, First"
+fn author_display_name(author: &str) -> String {
+    let mut names = author.splitn(2, ", ");
+    match (names.next(), names.next()) {
+        (Some(last), Some(first)) => format!("{} {}", first, last),
+        _ => author.to_owned(),
+    }
+}
+
+fn metadata_tags(metadata: &PaperMetadata) -> Vec<NodeRef> {
+    let authors = metadata.authors.join(", ");
+    let meta_tags = [
+        ("name", "dc:title", metadata.title.as_deref()),
+        (
+            "name",
+            "dc:creator",
+            Some(authors.as_str()).filter(|a| !a.is_empty()),
+        ),
+        (
+            "property",
+            "article:published_time",
+            metadata.published.as_deref(),
+        ),
+        ("name", "dc:description", metadata.abstract_text.as_deref()),
+    ];
+    meta_tags
+        .iter()
+        .filter_map(|(attr, key, content)| {
+            Some(new_element(
+                "meta",
+                &[(*attr, *key), ("content", (*content)?)],
+            ))
+        })
+        .collect()
+}
+
+/// Adds the metadata of a paper to its rendering and the abstract when the rendering has none
+fn add_paper_metadata(doc: &NodeRef, metadata: &PaperMetadata) {
+    if let Ok(head) = doc.select_first("head") {
+        // The meta tags are added first so that they are read before the ones already there
+        for meta in metadata_tags(metadata).into_iter().rev() {
+            head.as_node().prepend(meta);
+        }
+    }
+    let article = match doc.select_first(PAPER_SELECTOR) {
+        Ok(article) => article,
+        Err(_) => return,
+    };
+    if article.as_node().select_first(".ltx_abstract").is_err() {
+        if let Some(abstract_text) = &metadata.abstract_text {
+            let title = article.as_node().select_first(".ltx_title_document").ok();
+            let section = abstract_section(abstract_text);
+            match title {
+                Some(title) => title.as_node().insert_after(section),
+                None => article.as_node().prepend(section),
+            }
+        }
+    }
+}
+
+fn abstract_section(abstract_text: &str) -> NodeRef {
+    let section = new_element("div", &[("class", "ltx_abstract")]);
+    let heading = new_element("h6", &[("class", "ltx_title ltx_title_abstract")]);
+    heading.append(NodeRef::new_text("Abstract"));
+    section.append(heading);
+    let paragraph = new_element("p", &[]);
+    paragraph.append(NodeRef::new_text(abstract_text));
+    section.append(paragraph);
+    section
+}
+
+/// A document of the abstract of a paper with a link to its PDF
+fn abstract_document(metadata: &PaperMetadata) -> String {
+    let doc = kuchiki::parse_html().one("");
+    let head = doc.select_first("head").unwrap();
+    for meta in metadata_tags(metadata) {
+        head.as_node().append(meta);
+    }
+    let article = new_element("article", &[("class", "ltx_document")]);
+    if let Some(title) = &metadata.title {
+        let heading = new_element("h1", &[("class", "ltx_title ltx_title_document")]);
+        heading.append(NodeRef::new_text(title));
+        article.append(heading);
+    }
+    if let Some(abstract_text) = &metadata.abstract_text {
+        article.append(abstract_section(abstract_text));
+    }
+    if let Some(pdf_url) = &metadata.pdf_url {
+        let paragraph = new_element("p", &[]);
+        let link = new_element("a", &[("href", pdf_url)]);
+        link.append(NodeRef::new_text("Read the full paper as a PDF"));
+        paragraph.append(link);
+        article.append(paragraph);
+    }
+    doc.select_first("body").unwrap().as_node().append(article);
+    doc.to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ABSTRACT_PAGE: &str = r#"<html><head>
+        <meta name="citation_title" content="Attention Is All You Need">
+        <meta name="citation_author" content="Vaswani, Ashish">
+        <meta name="citation_author" content="Shazeer, Noam">
+        <meta name="citation_date" content="2017/06/12">
+        <meta name="citation_pdf_url" content="https://arxiv.org/pdf/1706.03762">
+        <meta name="citation_abstract" content="The dominant sequence transduction models...">
+    </head><body></body></html>"#;
+
+    #[test]
+    fn test_paper_id() {
+        assert_eq!(
+            Some("1706.03762v5".to_owned()),
+            paper_id("https://arxiv.org/abs/1706.03762v5")
+        );
+        assert_eq!(
+            Some("1706.03762".to_owned()),
+            paper_id("https://arxiv.org/pdf/1706.03762.pdf")
+        );
+        assert_eq!(
+            Some("hep-th/9901001".to_owned()),
+            paper_id("https://ar5iv.labs.arxiv.org/html/hep-th/9901001")
+        );
+        assert_eq!(None, paper_id("https://arxiv.org/list/cs.CL/recent"));
+        assert_eq!(None, paper_id("https://example.com/abs/1706.03762"));
+    }
+
+    #[test]
+    fn test_parse_abstract_page() {
+        let metadata = parse_abstract_page(ABSTRACT_PAGE);
+        assert_eq!(vec!["Ashish Vaswani", "Noam Shazeer"], metadata.authors);
+        assert_eq!(Some("2017-06-12"), metadata.published.as_deref());
+        assert_eq!(
+            Some("https://arxiv.org/pdf/1706.03762"),
+            metadata.pdf_url.as_deref()
+        );
+    }
+
+    #[test]
+    fn test_add_paper_metadata() {
+        let metadata = parse_abstract_page(ABSTRACT_PAGE);
+        let doc = kuchiki::parse_html().one(
+            r#"<html><head><title>[1706.03762] Attention Is All You Need</title></head><body>
+            <article class="ltx_document">
+                <h1 class="ltx_title ltx_title_document">Attention Is All You Need</h1>
+                <section><math alttext="x^2"><msup><mi>x</mi><mn>2</mn></msup></math></section>
+            </article></body></html>"#,
+        );
+        add_paper_metadata(&doc, &metadata);
+        let creator = doc.select_first("meta[name=\"dc:creator\"]").unwrap();
+        assert_eq!(
+            Some("Ashish Vaswani, Noam Shazeer"),
+            creator.attributes.borrow().get("content")
+        );
+        let abstract_section = doc
+            .select_first("h1.ltx_title_document + .ltx_abstract")
+            .unwrap();
+        assert!(abstract_section
+            .text_contents()
+            .contains("The dominant sequence transduction models"));
+        assert!(doc.select_first("math").is_ok());
+
+        let document = kuchiki::parse_html().one(abstract_document(&metadata));
+        let link = document
+            .select_first(&format!("{} a", PAPER_SELECTOR))
+            .unwrap();
+        assert_eq!(
+            Some("https://arxiv.org/pdf/1706.03762"),
+            link.attributes.borrow().get("href")
+        );
+    }
+}
@@ -0,0 +1 @@
+(function(){var d=document,r=d.documentElement,s=window.localStorage,k="paperoni-reader-",z=parseFloat(s&&s.getItem(k+"font-size"))||100;function f(v){z=Math.max(60,Math.min(200,v));r.style.fontSize=z+"%";s&&s.setItem(k+"font-size",z)}function t(on){r.classList.toggle("paperoni-dark",on);s&&s.setItem(k+"dark",on?"1":"0")}var p=d.querySelector(".paperoni-reader-progress");function u(){var h=r.scrollHeight-r.clientHeight;p.style.width=(h>0?r.scrollTop/h*100:100)+"%"}f(z);t(s?s.getItem(k+"dark")==="1":false);d.querySelector("[data-reader=smaller]").onclick=function(){f(z-10)};d.querySelector("[data-reader=larger]").onclick=function(){f(z+10)};d.querySelector("[data-reader=dark]").onclick=function(){t(!r.classList.contains("paperoni-dark"))};window.addEventListener("scroll",u,{passive:true});window.addEventListener("resize",u);u()})();
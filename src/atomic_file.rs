@@ -0,0 +1,121 @@
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// The file of an export which is written to a temporary file in the same directory and only
+/// takes the place of the export once it is complete. Interrupted runs then leave the previous
+/// file, or none at all, instead of a truncated file that readers refuse to open. The temporary
+/// file is removed when it is dropped before being persisted.
+pub struct AtomicFile {
+    /// Closed before the file is moved as open files cannot be renamed on Windows
+    file: Option<File>,
+    path: PathBuf,
+    temp_path: PathBuf,
+    is_persisted: bool,
+}
+
+impl AtomicFile {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let temp_path = temp_path(&path);
+        let file = File::create(&temp_path)?;
+        Ok(Self {
+            file: Some(file),
+            path,
+            temp_path,
+            is_persisted: false,
+        })
+    }
+
+    /// Moves the complete file to its path, replacing the file already there
+    pub fn persist(mut self) -> io::Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        fs::rename(&self.temp_path, &self.path)?;
+        self.is_persisted = true;
+        Ok(())
+    }
+
+    fn file(&mut self) -> &mut File {
+        self.file
+            .as_mut()
+            .expect("The file stays open until it is persisted")
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file().flush()
+    }
+}
+
+impl Seek for AtomicFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file().seek(pos)
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.is_persisted {
+            drop(self.file.take());
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// Writes the contents to a file like [fs::write] without ever leaving it partly written
+pub fn write_atomic(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(contents.as_ref())?;
+    file.persist()
+}
+
+/// A hidden file next to the file of the export, named after the process so that runs writing
+/// to the same directory do not write to the same temporary file
+fn temp_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_atomic_file() {
+        let dir = std::env::temp_dir().join(format!("paperoni-test-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("article.html");
+        fs::write(&path, "previous").unwrap();
+
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"<html>").unwrap();
+        // The export is left as it was while the new one is written
+        assert_eq!("previous", fs::read_to_string(&path).unwrap());
+        let temp_path = file.temp_path.clone();
+        assert!(temp_path.exists());
+        drop(file);
+        assert!(!temp_path.exists());
+        assert_eq!("previous", fs::read_to_string(&path).unwrap());
+
+        write_atomic(&path, "<html></html>").unwrap();
+        assert_eq!("<html></html>", fs::read_to_string(&path).unwrap());
+        assert_eq!(
+            vec![path],
+            fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect::<Vec<_>>()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use async_std::task;
+use comfy_table::presets::UTF8_HORIZONTAL_BORDERS_ONLY;
+use comfy_table::{Cell, CellAlignment, ContentArrangement, Table};
+use url::Url;
+
+use crate::cli::{AppConfig, BenchOptions};
+use crate::errors::{ErrorKind, PaperoniError};
+use crate::extractor::Article;
+use crate::http::{build_client, fetch_html};
+
+/// The durations of a step of the extraction across the iterations of the benchmark
+struct StepTimings {
+    step: &'static str,
+    durations: Vec<Duration>,
+}
+
+impl StepTimings {
+    fn min(&self) -> Duration {
+        self.durations.iter().min().copied().unwrap_or_default()
+    }
+
+    fn max(&self) -> Duration {
+        self.durations.iter().max().copied().unwrap_or_default()
+    }
+
+    fn mean(&self) -> Duration {
+        if self.durations.is_empty() {
+            return Duration::default();
+        }
+        self.durations.iter().sum::<Duration>() / self.durations.len() as u32
+    }
+}
+
+/// Runs the extraction of an article repeatedly and prints how long each step took. The
+/// HTML is read from a file or fetched once so that only the extraction is measured.
+pub fn run_bench(app_config: &AppConfig, options: &BenchOptions) -> Result<(), PaperoniError> {
+    let (url, html) = load_html(app_config, &options.input)?;
+    println!(
+        "Benchmarking the extraction of {} over {} iterations",
+        url, options.iterations
+    );
+
+    let mut timings: Vec<StepTimings> = Vec::new();
+    let mut record = |step: &'static str, duration: Duration| match timings
+        .iter_mut()
+        .find(|timings| timings.step == step)
+    {
+        Some(timings) => timings.durations.push(duration),
+        None => timings.push(StepTimings {
+            step,
+            durations: vec![duration],
+        }),
+    };
+    for _ in 0..options.iterations {
+        let start = Instant::now();
+        let mut article = Article::from_html(&html, &url);
+        record("parse_html", start.elapsed());
+
+        let start = Instant::now();
+        article.extract_content()?;
+        let extract_duration = start.elapsed();
+        let mut readability_duration = Duration::default();
+        for (step, duration) in article.readability_timings() {
+            record(*step, *duration);
+            readability_duration += *duration;
+        }
+        record(
+            "wrap_article",
+            extract_duration
+                .checked_sub(readability_duration)
+                .unwrap_or_default(),
+        );
+
+        let start = Instant::now();
+        let serialized = article.node_ref().to_string();
+        record("serialize", start.elapsed());
+        drop(serialized);
+    }
+
+    println!("{}", timings_table(&timings));
+    Ok(())
+}
+
+/// Reads the HTML of a file or fetches it when the input is a url
+pub fn load_html(app_config: &AppConfig, input: &str) -> Result<(String, String), PaperoniError> {
+    let path = Path::new(input);
+    if path.is_file() {
+        let html = fs::read_to_string(path)?;
+        let url = fs::canonicalize(path)
+            .ok()
+            .and_then(|path| Url::from_file_path(path).ok())
+            .map(String::from)
+            .unwrap_or_else(|| input.to_owned());
+        return Ok((url, html));
+    }
+    if Url::parse(input).is_err() {
+        return Err(ErrorKind::HTTPError(format!("{} is neither a file nor a url", input)).into());
+    }
+    let client = build_client(app_config, None);
+    task::block_on(fetch_html(&client, input, app_config.max_redirects))
+}
+
+fn timings_table(timings: &[StepTimings]) -> Table {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_HORIZONTAL_BORDERS_ONLY)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["Step", "Min", "Mean", "Max"]);
+    let format_duration = |duration: Duration| {
+        Cell::new(format!("{:.2} ms", duration.as_secs_f64() * 1000.0))
+            .set_alignment(CellAlignment::Right)
+    };
+    let mut total = Duration::default();
+    for step_timings in timings {
+        total += step_timings.mean();
+        table.add_row(vec![
+            Cell::new(step_timings.step),
+            format_duration(step_timings.min()),
+            format_duration(step_timings.mean()),
+            format_duration(step_timings.max()),
+        ]);
+    }
+    table.add_row(vec![
+        Cell::new("total"),
+        Cell::new(""),
+        format_duration(total),
+        Cell::new(""),
+    ]);
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_step_timings() {
+        let timings = StepTimings {
+            step: "grab_article",
+            durations: vec![
+                Duration::from_millis(30),
+                Duration::from_millis(10),
+                Duration::from_millis(20),
+            ],
+        };
+        assert_eq!(Duration::from_millis(10), timings.min());
+        assert_eq!(Duration::from_millis(20), timings.mean());
+        assert_eq!(Duration::from_millis(30), timings.max());
+    }
+}
@@ -0,0 +1,262 @@
+use kuchiki::NodeRef;
+use log::debug;
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::errors::{ErrorKind, PaperoniError};
+use crate::extractor::new_element;
+
+/// Id of the element holding the content of an article fetched from an API so it can be
+/// extracted with a selector
+pub const API_ARTICLE_ID: &str = "paperoni-api-article";
+
+/// Paths of dev.to that are not articles even though they look like `/username/slug`
+const DEV_TO_RESERVED_PATHS: [&str; 6] = ["t", "search", "settings", "api", "videos", "tags"];
+
+const HASHNODE_QUERY: &str = "query Post($host: String!, $slug: String!) {
+  publication(host: $host) {
+    post(slug: $slug) {
+      title
+      brief
+      publishedAt
+      content { html }
+      author { name }
+      coverImage { url }
+      tags { name }
+    }
+  }
+}";
+
+/// Blogging platforms whose articles are fetched from their public APIs instead of being
+/// scraped, which keeps their code blocks intact
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlogApi {
+    DevTo { username: String, slug: String },
+    Hashnode { host: String, slug: String },
+}
+
+/// The parts of an article returned by the API of a platform
+#[derive(Debug, Default, PartialEq)]
+pub struct ApiArticle {
+    pub title: String,
+    pub html: String,
+    pub author: Option<String>,
+    pub published: Option<String>,
+    pub description: Option<String>,
+    pub cover_image: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl BlogApi {
+    /// Recognizes the urls of articles on dev.to and on blogs hosted by Hashnode
+    pub fn from_url(url: &str) -> Option<Self> {
+        let url = Url::parse(url).ok()?;
+        let host = url.host_str()?.trim_start_matches("www.");
+        let segments = url
+            .path_segments()?
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+        match segments.as_slice() {
+            [username, slug] if host == "dev.to" && !DEV_TO_RESERVED_PATHS.contains(username) => {
+                Some(BlogApi::DevTo {
+                    username: (*username).to_owned(),
+                    slug: (*slug).to_owned(),
+                })
+            }
+            [slug] if host.ends_with(".hashnode.dev") => Some(BlogApi::Hashnode {
+                host: host.to_owned(),
+                slug: (*slug).to_owned(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn site_name(&self) -> &'static str {
+        match self {
+            BlogApi::DevTo { .. } => "DEV",
+            BlogApi::Hashnode { .. } => "Hashnode",
+        }
+    }
+
+    /// Fetches the article from the API of its platform as an HTML document for extraction
+    /// with the `API_ARTICLE_ID` selector
+    pub async fn fetch_document(&self, client: &surf::Client) -> Result<String, PaperoniError> {
+        let req = match self {
+            BlogApi::DevTo { username, slug } => {
+                surf::get(format!("https://dev.to/api/articles/{}/{}", username, slug))
+            }
+            BlogApi::Hashnode { host, slug } => surf::post("https://gql.hashnode.com")
+                .body(json!({
+                    "query": HASHNODE_QUERY,
+                    "variables": {"host": host, "slug": slug},
+                }))
+                .content_type("application/json"),
+        };
+        debug!("Fetching the article from the {} API", self.site_name());
+        let mut res = client.send(req.header("User-Agent", "paperoni")).await?;
+        if !res.status().is_success() {
+            let msg = format!("{} API responded with {}", self.site_name(), res.status());
+            return Err(ErrorKind::HTTPError(msg).into());
+        }
+        let body = res.body_json::<Value>().await?;
+        let article = match self {
+            BlogApi::DevTo { .. } => parse_dev_to_article(&body),
+            BlogApi::Hashnode { .. } => parse_hashnode_post(&body),
+        };
+        article.map(|article| article.document()).ok_or_else(|| {
+            let msg = format!("Unexpected response from the {} API", self.site_name());
+            ErrorKind::HTTPError(msg).into()
+        })
+    }
+}
+
+impl ApiArticle {
+    /// An HTML document with the metadata of the article in the meta tags read by readability
+    pub fn document(&self) -> String {
+        let head = new_element("head", &[]);
+        let title = new_element("title", &[]);
+        title.append(NodeRef::new_text(&self.title));
+        head.append(title);
+        let meta_tags = [
+            ("name", "author", self.author.as_deref()),
+            (
+                "property",
+                "article:published_time",
+                self.published.as_deref(),
+            ),
+            ("name", "description", self.description.as_deref()),
+            ("property", "og:image", self.cover_image.as_deref()),
+        ];
+        for (attr, key, content) in meta_tags.iter() {
+            if let Some(content) = content {
+                head.append(new_element("meta", &[(*attr, *key), ("content", *content)]));
+            }
+        }
+        for tag in &self.tags {
+            head.append(new_element(
+                "meta",
+                &[("property", "article:tag"), ("content", tag)],
+            ));
+        }
+        format!(
+            r#"<html>{}<body><article id="{}">{}</article></body></html>"#,
+            head.to_string(),
+            API_ARTICLE_ID,
+            self.html
+        )
+    }
+}
+
+fn parse_dev_to_article(article: &Value) -> Option<ApiArticle> {
+    // The tags are a list in some responses and a comma separated string in others
+    let tags = match (&article["tags"], &article["tag_list"]) {
+        (Value::Array(tags), _) | (_, Value::Array(tags)) => tags
+            .iter()
+            .filter_map(|tag| tag.as_str().map(str::to_owned))
+            .collect(),
+        (Value::String(tags), _) | (_, Value::String(tags)) => tags
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_owned)
+            .collect(),
+        _ => Vec::new(),
+    };
+    Some(ApiArticle {
+        title: article["title"].as_str()?.to_owned(),
+        html: article["body_html"].as_str()?.to_owned(),
+        author: article["user"]["name"].as_str().map(str::to_owned),
+        published: article["published_at"].as_str().map(str::to_owned),
+        description: article["description"].as_str().map(str::to_owned),
+        cover_image: article["cover_image"].as_str().map(str::to_owned),
+        tags,
+    })
+}
+
+fn parse_hashnode_post(body: &Value) -> Option<ApiArticle> {
+    let post = &body["data"]["publication"]["post"];
+    Some(ApiArticle {
+        title: post["title"].as_str()?.to_owned(),
+        html: post["content"]["html"].as_str()?.to_owned(),
+        author: post["author"]["name"].as_str().map(str::to_owned),
+        published: post["publishedAt"].as_str().map(str::to_owned),
+        description: post["brief"].as_str().map(str::to_owned),
+        cover_image: post["coverImage"]["url"].as_str().map(str::to_owned),
+        tags: post["tags"]
+            .as_array()
+            .map(|tags| {
+                tags.iter()
+                    .filter_map(|tag| tag["name"].as_str().map(str::to_owned))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use kuchiki::traits::*;
+
+    #[test]
+    fn test_from_url() {
+        assert_eq!(
+            Some(BlogApi::DevTo {
+                username: "jane".into(),
+                slug: "writing-rust-1a2b".into()
+            }),
+            BlogApi::from_url("https://dev.to/jane/writing-rust-1a2b")
+        );
+        assert_eq!(None, BlogApi::from_url("https://dev.to/t/rust"));
+        assert_eq!(None, BlogApi::from_url("https://dev.to/jane"));
+        assert_eq!(
+            Some(BlogApi::Hashnode {
+                host: "jane.hashnode.dev".into(),
+                slug: "my-post".into()
+            }),
+            BlogApi::from_url("https://jane.hashnode.dev/my-post")
+        );
+        assert_eq!(None, BlogApi::from_url("https://example.com/jane/post"));
+    }
+
+    #[test]
+    fn test_parse_articles() {
+        let article = parse_dev_to_article(&json!({
+            "title": "Writing Rust",
+            "body_html": "<pre class=\"highlight rust\"><code>fn main() {}</code></pre>",
+            "user": {"name": "Jane Doe"},
+            "published_at": "2023-03-01T12:00:00Z",
+            "tag_list": "rust, beginners",
+            "cover_image": null
+        }))
+        .unwrap();
+        assert_eq!(Some("Jane Doe"), article.author.as_deref());
+        assert_eq!(vec!["rust", "beginners"], article.tags);
+        assert_eq!(None, article.cover_image);
+
+        let post = parse_hashnode_post(&json!({"data": {"publication": {"post": {
+            "title": "My post",
+            "brief": "A short summary",
+            "content": {"html": "<p>Hello</p>"},
+            "author": {"name": "Jane Doe"},
+            "coverImage": null,
+            "tags": [{"name": "rust"}]
+        }}}}))
+        .unwrap();
+        assert_eq!("<p>Hello</p>", post.html);
+        assert_eq!(vec!["rust"], post.tags);
+        assert_eq!(
+            None,
+            parse_hashnode_post(&json!({"data": {"publication": {"post": null}}}))
+        );
+
+        let doc = kuchiki::parse_html().one(post.document());
+        let meta = doc.select_first("meta[name=\"author\"]").unwrap();
+        assert_eq!(Some("Jane Doe"), meta.attributes.borrow().get("content"));
+        let content = doc
+            .select_first(&format!("#{}", API_ARTICLE_ID))
+            .unwrap()
+            .text_contents();
+        assert_eq!("Hello", content);
+    }
+}
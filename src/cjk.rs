@@ -0,0 +1,200 @@
+use kuchiki::NodeRef;
+
+/// Elements whose whitespace is shown as it is
+const PREFORMATTED_TAGS: [&str; 4] = ["code", "pre", "textarea", "xmp"];
+/// The share of the letters of a text that have to be Chinese, Japanese or Korean for the
+/// text to be in one of these languages
+const MIN_CJK_SHARE: f64 = 0.3;
+
+/// Whether the character is a Chinese ideograph or Japanese kana. These are written without
+/// spaces between words, unlike Korean Hangul.
+fn is_unspaced(c: char) -> bool {
+    matches!(c,
+        '\u{3005}'..='\u{3007}'
+        | '\u{3040}'..='\u{30FF}'
+        | '\u{31F0}'..='\u{31FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF66}'..='\u{FF9F}'
+        | '\u{20000}'..='\u{2FA1F}')
+}
+
+fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' | '\u{FF66}'..='\u{FF9F}')
+}
+
+fn is_hangul(c: char) -> bool {
+    matches!(c, '\u{1100}'..='\u{11FF}' | '\u{3130}'..='\u{318F}' | '\u{AC00}'..='\u{D7AF}')
+}
+
+/// Whether the character is CJK punctuation such as `。` or `、`, which separates words
+fn is_cjk_punctuation(c: char) -> bool {
+    matches!(c, '\u{3000}'..='\u{303F}' | '\u{FF00}'..='\u{FFEF}') && !c.is_alphanumeric()
+}
+
+/// Splits a text into words. Each Chinese ideograph and Japanese kana counts as a word as
+/// these languages do not put spaces between words.
+pub fn split_words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    for run in text.split_whitespace() {
+        let mut word_start = None;
+        for (idx, c) in run.char_indices() {
+            if is_unspaced(c) || is_cjk_punctuation(c) {
+                if let Some(start) = word_start.take() {
+                    words.push(&run[start..idx]);
+                }
+                if is_unspaced(c) {
+                    words.push(&run[idx..idx + c.len_utf8()]);
+                }
+            } else if word_start.is_none() {
+                word_start = Some(idx);
+            }
+        }
+        if let Some(start) = word_start {
+            words.push(&run[start..]);
+        }
+    }
+    words
+}
+
+/// Whether the language code is Chinese, Japanese or Korean, e.g `zh-Hant`
+pub fn is_cjk_lang(lang: &str) -> bool {
+    let primary_lang = lang.trim().split('-').next().unwrap_or_default();
+    ["zh", "ja", "ko"]
+        .iter()
+        .any(|cjk_lang| primary_lang.eq_ignore_ascii_case(cjk_lang))
+}
+
+/// Guesses whether a text is in Chinese, Japanese or Korean from its characters. Returns the
+/// language code of the language, or `None` when the text is in none of them.
+pub fn detect_cjk_lang(text: &str) -> Option<&'static str> {
+    let (mut letter_count, mut han_count, mut kana_count, mut hangul_count) = (0, 0, 0, 0);
+    for c in text.chars().filter(|c| c.is_alphanumeric()) {
+        letter_count += 1;
+        if is_kana(c) {
+            kana_count += 1;
+        } else if is_unspaced(c) {
+            han_count += 1;
+        } else if is_hangul(c) {
+            hangul_count += 1;
+        }
+    }
+    let cjk_count = han_count + kana_count + hangul_count;
+    if letter_count == 0 || (cjk_count as f64) < letter_count as f64 * MIN_CJK_SHARE {
+        None
+    } else if hangul_count > han_count + kana_count {
+        Some("ko")
+    } else if kana_count > 0 {
+        Some("ja")
+    } else {
+        Some("zh")
+    }
+}
+
+/// Removes the line breaks between Chinese and Japanese characters in the text of a document.
+/// Pages often wrap the lines of their source, and the line breaks would otherwise be shown
+/// as spaces in the middle of words. Whitespace without a line break and the whitespace of
+/// preformatted text is left as it is.
+pub fn remove_segment_breaks(root: &NodeRef) {
+    for text_node in root.inclusive_descendants().text_nodes() {
+        let is_preformatted =
+            text_node.as_node().ancestors().elements().any(|elem| {
+                PREFORMATTED_TAGS.contains(&elem.name.local.to_ascii_lowercase().as_str())
+            });
+        if is_preformatted {
+            continue;
+        }
+        let text = text_node.borrow().clone();
+        if text.contains('\n') && text.chars().any(is_unspaced) {
+            *text_node.borrow_mut() = join_segments(&text);
+        }
+    }
+}
+
+fn join_segments(text: &str) -> String {
+    let mut joined = String::with_capacity(text.len());
+    let mut whitespace = String::new();
+    for c in text.chars() {
+        if c.is_whitespace() {
+            whitespace.push(c);
+            continue;
+        }
+        let is_between_unspaced =
+            joined.chars().last().map_or(false, is_unspaced_or_punct) && is_unspaced_or_punct(c);
+        if !(is_between_unspaced && whitespace.contains('\n')) {
+            joined.push_str(&whitespace);
+        }
+        whitespace.clear();
+        joined.push(c);
+    }
+    joined.push_str(&whitespace);
+    joined
+}
+
+fn is_unspaced_or_punct(c: char) -> bool {
+    is_unspaced(c) || is_cjk_punctuation(c)
+}
+
+#[cfg(test)]
+mod test {
+    use kuchiki::traits::*;
+
+    use super::*;
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            vec!["我", "喜", "欢", "Rust", "语", "言"],
+            split_words("我喜欢Rust语言。")
+        );
+        assert_eq!(
+            vec!["東", "京", "は", "晴", "れ"],
+            split_words("東京は、 晴れ")
+        );
+        assert_eq!(
+            vec!["한국어", "문장입니다"],
+            split_words("한국어 문장입니다")
+        );
+        assert_eq!(vec!["Plain", "words."], split_words(" Plain  words. "));
+    }
+
+    #[test]
+    fn test_detect_cjk_lang() {
+        assert!(is_cjk_lang("zh-Hant"));
+        assert!(is_cjk_lang("JA"));
+        assert!(!is_cjk_lang("en-US"));
+        assert_eq!(Some("zh"), detect_cjk_lang("这是一篇关于 Rust 的文章"));
+        assert_eq!(
+            Some("ja"),
+            detect_cjk_lang("これは Rust についての記事です")
+        );
+        assert_eq!(Some("ko"), detect_cjk_lang("이것은 Rust에 관한 기사입니다"));
+        assert_eq!(
+            None,
+            detect_cjk_lang("An article about 漢字 in English text")
+        );
+        assert_eq!(None, detect_cjk_lang("1, 2, 3"));
+    }
+
+    #[test]
+    fn test_remove_segment_breaks() {
+        let doc = kuchiki::parse_html().one(
+            "<p>第一行\n    第二行。\n第三行 and\nEnglish</p><p>한국어\n문장</p><pre>代码\n代码</pre>",
+        );
+        remove_segment_breaks(&doc);
+        let texts = doc
+            .select("p, pre")
+            .unwrap()
+            .map(|elem| elem.text_contents())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "第一行第二行。第三行 and\nEnglish",
+                "한국어\n문장",
+                "代码\n代码"
+            ],
+            texts
+        );
+    }
+}
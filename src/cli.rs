@@ -1,48 +1,325 @@
-use std::{fs, num::NonZeroUsize, path::Path};
+use std::{
+    collections::HashMap,
+    env,
+    fmt::Display,
+    fs,
+    net::{IpAddr, SocketAddr},
+    num::{NonZeroU64, NonZeroUsize},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
 use clap::{load_yaml, App, ArgMatches};
 use flexi_logger::LevelFilter as LogLevel;
 use itertools::Itertools;
+use log::warn;
+use regex::Regex;
+
+use crate::clipboard;
+use crate::config::{self, Profile, SiteRule};
+use crate::cookies::CookieJar;
+use crate::dns;
+use crate::exporter::renamed_file_name;
+use crate::extractor::ImagePattern;
+use crate::history::DownloadHistory;
+use crate::http::{Authorization, HostCredentials};
+use crate::i18n::Locale;
+use crate::image_cache::{ImageCache, DEFAULT_IMAGE_CACHE_SIZE};
+use crate::image_size::ImageSizeFilter;
+use crate::limiter::{ConnLimiter, ADAPTIVE_INITIAL_CONN, ADAPTIVE_MAX_CONN};
+use crate::metrics::Metrics;
+use crate::moz_readability::{
+    ExtractionBudget, DEFAULT_CHAR_THRESHOLD, DEFAULT_EXTRACT_TIMEOUT, DEFAULT_MAX_NODES,
+};
+use crate::post_process::FILE_PLACEHOLDER;
+use crate::progress::{ProgressEvent, ProgressEvents};
+use crate::sanitize::Sanitizer;
+use crate::text_replace::ReplaceRule;
+use crate::typography::cjk_font_mime;
+use crate::url_rewrite::UrlRewriter;
 
 type Error = crate::errors::CliError<AppConfigBuilderError>;
 
 const DEFAULT_MAX_CONN: usize = 8;
+const DEFAULT_MAX_REDIRECTS: u8 = 5;
+const DEFAULT_MAX_SERVER_JOBS: usize = 2;
 
-#[derive(derive_builder::Builder, Debug)]
+#[derive(derive_builder::Builder, Clone, Debug)]
 pub struct AppConfig {
     /// Article urls
     pub urls: Vec<String>,
     pub max_conn: usize,
+    pub max_redirects: u8,
+    /// Limits on the work done extracting each article
+    pub extraction_budget: ExtractionBudget,
+    /// How long fetching an article, extracting it and downloading its images can take
+    pub article_timeout: Option<Duration>,
+    /// Exports the body of the fetched pages instead of extracting their article
+    pub is_skipping_extraction: bool,
+    /// Keeps only the articles published within a range of days
+    pub date_filter: Option<DateFilter>,
+    /// Skips the articles extracted with a confidence score below this one
+    pub min_confidence: Option<f32>,
     /// Path to file of multiple articles into a single article
     pub merged: Option<String>,
     // TODO: Change type to Path
     pub output_directory: Option<String>,
+    /// What is done with the files of exports that already exist
+    pub overwrite: OverwritePolicy,
     pub log_level: LogLevel,
     pub can_disable_progress_bar: bool,
+    /// Where the JSON progress events for programs wrapping paperoni are written
+    pub progress: Option<ProgressEvents>,
     pub start_time: DateTime<Local>,
     pub is_logging_to_file: bool,
     pub inline_toc: bool,
     pub css_config: CSSConfig,
     pub export_type: ExportType,
+    /// The flavor of Markdown exports
+    pub md_flavor: MarkdownFlavor,
     pub is_inlining_images: bool,
+    /// Adds a reading toolbar and progress bar to HTML exports
+    pub has_reader_ui: bool,
+    /// The most articles on each page of a merged HTML export. None keeps them on one page
+    pub paginate: Option<NonZeroUsize>,
+    /// Copies the canonical link, publication date and icon of the pages to HTML exports
+    pub is_preserving_meta: bool,
+    /// Skips downloading the images of the articles
+    pub is_skipping_images: bool,
+    /// Images downloaded in earlier runs. None when the cache is disabled
+    pub image_cache: Option<ImageCache>,
+    /// Timings of the links downloaded in earlier runs. None when the history is disabled
+    pub history: Option<Arc<DownloadHistory>>,
+    /// Skips the links exported in earlier runs and downloads the fastest ones first
+    pub is_resuming: bool,
+    /// The size in bytes each output file is kept under by compressing and dropping images
+    pub size_budget: Option<u64>,
+    /// The smallest images kept after they are downloaded
+    pub image_size_filter: ImageSizeFilter,
+    /// How the comments of Hacker News and Reddit threads are kept with their linked articles
+    pub discussion: DiscussionOptions,
+    /// Keeps the top-level comments of articles at their end
+    pub is_including_comments: bool,
+    /// Saves the audio of articles such as podcast episodes next to the exports
+    pub is_downloading_audio: bool,
+    /// Replaces skipped images with their alt text
+    pub has_alt_captions: bool,
+    /// Styles the first letter of each article as a drop cap
+    pub drop_caps: bool,
+    /// Starts each merged article on a new page with a chapter number
+    pub chapter_breaks: bool,
+    /// How many levels the top-level headings of articles are moved down from h1 in epubs
+    pub heading_offset: u8,
+    /// The deepest header level included in the Table of Contents navigation of epubs
+    pub toc_depth: u8,
+    /// Whether the headers of articles are included in the Table of Contents navigation of epubs
+    pub has_article_toc: bool,
+    /// Places the image an article is shared with once at the top of its content
+    pub has_lead_image: bool,
+    /// Converts downloaded SVG images to PNG
+    pub is_rasterizing_svgs: bool,
+    /// Keeps only the first frame of downloaded animated GIFs as a PNG
+    pub is_flattening_gifs: bool,
+    /// Converts downloaded images to 8-bit grayscale for e-ink displays
+    pub is_grayscale: bool,
+    /// Uses the image of an article or the icon of its site as the cover of its epub
+    pub has_auto_cover: bool,
+    /// Adds the icon and name of the site at the start of each article
+    pub has_site_logo: bool,
+    /// Adds a QR code of the url of each article to the appendix of epubs and HTML exports
+    pub has_source_qr: bool,
+    /// Path of the HTML report of the results of the run
+    pub report: Option<String>,
+    /// The language of the messages and of the text added to exports
+    pub locale: Locale,
+    /// Directory the fetched HTML and extracted article of each article are written to
+    pub debug_extraction_dir: Option<PathBuf>,
+    /// Path of the RSS feed of the HTML exports in the output directory
+    pub feed: Option<String>,
+    /// Writes a JSON manifest next to each exported file
+    pub is_writing_manifests: bool,
+    /// The command run on each exported file with `{file}` replaced by its path
+    pub post_process: Option<String>,
+    /// The reading service the extracted articles are pushed to
+    pub push: Option<PushOptions>,
+    /// The language the extracted articles are translated to and how they are translated
+    pub translate: Option<TranslateOptions>,
+    /// Address the server listens on when paperoni is run with the serve subcommand
+    pub server_address: Option<String>,
+    /// The most jobs submitted to the server that run at the same time
+    pub max_server_jobs: usize,
+    /// Options of the bench subcommand when paperoni is run with it
+    pub bench: Option<BenchOptions>,
+    /// Options of the preview subcommand when paperoni is run with it
+    pub preview: Option<PreviewOptions>,
+    /// Options of the debug-extract subcommand when paperoni is run with it
+    pub debug_extract: Option<DebugExtractOptions>,
+    /// Options of the selftest subcommand when paperoni is run with it
+    pub selftest: Option<SelftestOptions>,
+    /// Options of the watch subcommand when paperoni is run with it
+    pub watch: Option<WatchOptions>,
+    /// Options of the search subcommand when paperoni is run with it
+    pub search: Option<SearchOptions>,
+    /// Counters of the server and watch subcommands with where they are reported
+    pub metrics: Option<MetricsOptions>,
+    /// The command run when paperoni is run with the cache subcommand
+    pub cache_command: Option<CacheCommand>,
+    /// Adds a title page at the start of a merged epub
+    pub title_page: bool,
+    /// Lays out the images of the articles of a merged epub in a grid as its cover
+    pub has_cover_collage: bool,
+    /// The font embedded in epubs for the text of Chinese, Japanese and Korean articles
+    pub cjk_font: Option<PathBuf>,
+    /// How the files of the epubs are compressed
+    pub epub_compression: EpubCompression,
+    /// Overrides the title of a single article or of the merged output
+    pub title: Option<String>,
+    /// Overrides the author of a single article or of the merged output
+    pub author: Option<String>,
+    /// CSS selectors of elements removed from the extracted articles
+    pub remove_selectors: Vec<String>,
+    /// Hosts or url patterns of images that are not downloaded
+    pub skipped_image_patterns: Vec<ImagePattern>,
+    /// Strips the articles down to a whitelist of elements and attributes when set
+    pub sanitizer: Option<Sanitizer>,
+    /// How the targets of links are written for printed and e-ink copies. None leaves them as
+    /// they are
+    pub print_links: Option<PrintLinks>,
+    /// Options for articles from the url file, keyed by their url
+    pub article_options: HashMap<String, ArticleOptions>,
+    /// Options for the articles of sites from the selected profile, keyed by domain
+    pub site_rules: HashMap<String, SiteRule>,
+    /// Regex find and replace rules from the selected profile applied to the text of articles
+    pub text_replacements: Vec<ReplaceRule>,
+    /// Credentials sent with requests to matching domains
+    pub credentials: Vec<HostCredentials>,
+    pub cookie_jar: CookieJar,
+    /// Rewrites the urls of articles before they are fetched
+    pub url_rewriter: UrlRewriter,
+    /// The HTTP client requests are sent with
+    pub http_backend: HttpBackend,
+    /// How the TLS certificates of sites are verified
+    pub tls: TlsOptions,
+    /// Addresses connected to instead of resolving hosts
+    pub resolve_overrides: Vec<ResolveOverride>,
+    /// Limits the concurrent requests to each host when the connections are auto-tuned
+    pub conn_limiter: Option<ConnLimiter>,
+    /// How deep to follow links from the urls passed. A depth of 0 means no crawling
+    pub crawl_depth: u8,
+    /// Pattern used to pick out article links when crawling
+    pub crawl_pattern: Option<Regex>,
+    /// Downloads the parts of the series of the url and merges them
+    pub is_following_series: bool,
+    /// Whether articles with the same content as another article are kept in merged exports
+    pub is_keeping_duplicates: bool,
+    /// Paginates through the urls as author archives and downloads the articles in them
+    pub archive: Option<ArchiveOptions>,
 }
 
 impl AppConfig {
     pub fn init_with_cli() -> Result<AppConfig, Error> {
+        // TODO: Move the arguments to clap's derive API once the toolchain can build clap 3
         let yaml_config = load_yaml!("cli_config.yml");
         let app = App::from_yaml(yaml_config).version(clap::crate_version!());
         Self::try_from(app.get_matches())
     }
 
-    fn init_merge_file(self) -> Result<Self, Error> {
-        self.merged
-            .as_deref()
-            .map(fs::File::create)
-            .transpose()
-            .err()
-            .map(|err| Err(Error::InvalidOutputPath(err.to_string())))
-            .unwrap_or(Ok(self))
+    /// The counters that are updated when running the server or watch subcommands
+    pub fn metrics(&self) -> Option<&Metrics> {
+        self.metrics
+            .as_ref()
+            .map(|options| options.counters.as_ref())
+    }
+
+    /// Writes a progress event when they were asked for with --progress or --progress-fd
+    pub fn emit_progress(&self, event: ProgressEvent) {
+        if let Some(progress) = &self.progress {
+            progress.emit(&event);
+        }
+    }
+
+    /// The rule of the selected profile for the site of an article
+    pub fn site_rule(&self, url: &str) -> Option<&SiteRule> {
+        config::find_site_rule(&self.site_rules, url)
+    }
+
+    /// Checks that the merged file can be written, which is renamed first when it exists and
+    /// --overwrite rename is passed. With --overwrite skip, the run stops before any article is
+    /// downloaded.
+    fn init_merge_file(mut self) -> Result<Self, Error> {
+        if let Some(name) = self.merged.take() {
+            let name = match self.overwrite {
+                _ if !Path::new(&name).exists() => name,
+                OverwritePolicy::Replace => name,
+                OverwritePolicy::Skip => return Err(Error::MergedFileExists(name)),
+                OverwritePolicy::Rename => {
+                    let renamed =
+                        renamed_file_name(&name, |file_name| Path::new(file_name).exists());
+                    warn!("{:?} already exists. Merging into {:?}", name, renamed);
+                    renamed
+                }
+            };
+            // The export is only written to the file once it is complete so an existing file is
+            // not truncated and a new one is not left empty
+            let is_new = !Path::new(&name).exists();
+            fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .open(&name)
+                .map_err(|err| Error::InvalidOutputPath(err.to_string()))?;
+            if is_new {
+                let _ = fs::remove_file(&name);
+            }
+            self.merged = Some(name);
+        }
+        Ok(self)
+    }
+
+    /// Checks that the metadata overrides will not be applied to several separate articles
+    fn check_metadata_overrides(self) -> Result<Self, Error> {
+        let has_overrides = self.title.is_some() || self.author.is_some();
+        let has_many_articles = self.urls.len() > 1 || self.crawl_depth > 0;
+        if has_overrides && self.merged.is_none() && has_many_articles {
+            Err(Error::MetadataOverrideMultipleArticles)
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// Checks that a single url is passed as the start of a series
+    fn check_series_url(self) -> Result<Self, Error> {
+        if self.is_following_series && self.urls.len() > 1 {
+            Err(Error::SeriesMultipleUrls)
+        } else {
+            Ok(self)
+        }
+    }
+
+    fn check_crawl_pattern(self) -> Result<Self, Error> {
+        if self.crawl_pattern.is_some() && self.crawl_depth == 0 && self.archive.is_none() {
+            Err(Error::CrawlPatternWithoutCrawling)
+        } else {
+            Ok(self)
+        }
+    }
+
+    fn warn_insecure_tls(self) -> Self {
+        if self.tls.is_insecure {
+            warn!(
+                "TLS certificate verification is disabled by --insecure. \
+                Connections to every site can be intercepted"
+            );
+        } else if !self.tls.insecure_hosts.is_empty() {
+            warn!(
+                "TLS certificate verification is disabled by --insecure-host for {}. \
+                Connections to these sites can be intercepted",
+                self.tls.insecure_hosts.join(", ")
+            );
+        }
+        self
     }
 
     fn init_logger(self) -> Result<Self, Error> {
@@ -59,56 +336,228 @@ impl<'a> TryFrom<ArgMatches<'a>> for AppConfig {
     type Error = Error;
 
     fn try_from(arg_matches: ArgMatches<'a>) -> Result<Self, Self::Error> {
+        let env_values = parse_env_values(env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        }))?;
+        let env_value = |arg| env_values.get(arg).map(String::as_str);
+        let profile = arg_matches
+            .value_of("profile")
+            .or_else(|| env_value("profile"))
+            .map(|name| {
+                let config_path = arg_matches
+                    .value_of("config")
+                    .or_else(|| env_value("config"));
+                config::load_profile(config_path, name)
+            })
+            .transpose()?
+            .unwrap_or_default();
+        let args = ArgValues {
+            arg_matches: &arg_matches,
+            env_values: &env_values,
+            profile: &profile,
+        };
+        args.check_export_type()?;
+        let (no_css, no_header_css) = args.css_flags();
+        let is_adaptive_conn = args.value_of("max-conn") == Some("auto");
+        let compile_selector = |selector: &str| {
+            kuchiki::Selectors::compile(selector)
+                .map(|_| selector.to_owned())
+                .map_err(|_| Error::InvalidRemoveSelector(selector.to_owned()))
+        };
+        let merge_matches = arg_matches.subcommand_matches("merge");
+        let merged_name = match (
+            arg_matches.value_of("output-name"),
+            merge_matches.and_then(|matches| matches.value_of("name")),
+        ) {
+            (Some(_), Some(_)) => return Err(Error::MergeNameConflict),
+            (flag_name, subcommand_name) => flag_name.or(subcommand_name),
+        };
+        let url_file_entries = arg_matches
+            .value_of("file")
+            .into_iter()
+            .chain(
+                arg_matches
+                    .subcommand_matches("import")
+                    .and_then(|matches| matches.value_of("file")),
+            )
+            .map(|path| parse_url_file(&fs::read_to_string(path)?))
+            .collect::<Result<Vec<_>, Error>>()?
+            .concat();
+        let clipboard_urls = if arg_matches.is_present("from-clipboard") {
+            let urls = clipboard::extract_urls(
+                &clipboard::read_clipboard().map_err(Error::ClipboardError)?,
+            );
+            if urls.is_empty() {
+                return Err(Error::ClipboardError(
+                    "the clipboard does not contain any urls".to_owned(),
+                ));
+            }
+            urls
+        } else {
+            Vec::new()
+        };
+
+        let urls = {
+            let url_filter = |url: &str| {
+                let url = url.trim();
+                if !url.is_empty() {
+                    Some(url.to_owned())
+                } else {
+                    None
+                }
+            };
+            // Urls can be passed on their own or to the get and merge subcommands
+            let direct_urls = std::iter::once(&arg_matches)
+                .chain(
+                    ["get", "merge"]
+                        .iter()
+                        .filter_map(|subcommand| arg_matches.subcommand_matches(subcommand)),
+                )
+                .flat_map(|matches| {
+                    matches
+                        .values_of("urls")
+                        .and_then(|urls| urls.map(url_filter).collect::<Option<Vec<_>>>())
+                        .unwrap_or(Vec::new())
+                })
+                .collect_vec();
+            let file_urls = url_file_entries
+                .iter()
+                .map(|(url, _)| url.to_owned())
+                .collect_vec();
+
+            let urls = [direct_urls, file_urls, clipboard_urls]
+                .concat()
+                .into_iter()
+                .unique()
+                .collect_vec();
+            // The server gets the urls of articles from its requests and the other
+            // subcommands get their articles from their own arguments
+            if !urls.is_empty()
+                || [
+                    "serve",
+                    "bench",
+                    "preview",
+                    "debug-extract",
+                    "selftest",
+                    "watch",
+                    "search",
+                    "cache",
+                ]
+                .iter()
+                .any(|subcommand| arg_matches.subcommand_matches(subcommand).is_some())
+            {
+                Ok(urls)
+            } else {
+                Err(Error::NoUrls)
+            }
+        }?;
+
+        let http_backend = match arg_matches.value_of("http-backend") {
+            Some("curl") => HttpBackend::Curl,
+            Some("h1") => HttpBackend::H1,
+            Some("reqwest") => HttpBackend::Reqwest,
+            _ => HttpBackend::default(),
+        };
+        if let Some(feature) = http_backend.missing_feature() {
+            return Err(Error::HttpBackendUnavailable(http_backend.name(), feature));
+        }
+
         AppConfigBuilder::default()
-            .urls({
-                let url_filter = |url: &str| {
-                    let url = url.trim();
-                    if !url.is_empty() {
-                        Some(url.to_owned())
-                    } else {
-                        None
-                    }
-                };
-                let direct_urls = arg_matches
-                    .values_of("urls")
-                    .and_then(|urls| urls.map(url_filter).collect::<Option<Vec<_>>>())
-                    .unwrap_or(Vec::new());
-                let file_urls = arg_matches
-                    .value_of("file")
-                    .map(fs::read_to_string)
-                    .transpose()?
-                    .and_then(|content| content.lines().map(url_filter).collect::<Option<Vec<_>>>())
-                    .unwrap_or(Vec::new());
-
-                let urls = [direct_urls, file_urls]
-                    .concat()
+            .urls(urls.clone())
+            .article_options(
+                url_file_entries
                     .into_iter()
-                    .unique()
-                    .collect_vec();
-                if !urls.is_empty() {
-                    Ok(urls)
+                    .filter(|(_, options)| options != &ArticleOptions::default())
+                    .collect(),
+            )
+            .max_conn(if is_adaptive_conn {
+                ADAPTIVE_MAX_CONN
+            } else {
+                parse_value::<NonZeroUsize>(&args, "max-conn")?
+                    .map(NonZeroUsize::get)
+                    .or(profile.max_conn.map(NonZeroUsize::get))
+                    .unwrap_or(DEFAULT_MAX_CONN)
+            })
+            .conn_limiter(if is_adaptive_conn {
+                Some(ConnLimiter::new(ADAPTIVE_INITIAL_CONN, ADAPTIVE_MAX_CONN))
+            } else {
+                None
+            })
+            .max_redirects(parse_value(&args, "max-redirects")?.unwrap_or(DEFAULT_MAX_REDIRECTS))
+            .is_skipping_extraction(arg_matches.is_present("no-extract"))
+            .date_filter({
+                let after = parse_value::<NaiveDate>(&args, "published-after")?;
+                let before = parse_value::<NaiveDate>(&args, "published-before")?;
+                if let (Some(after), Some(before)) = (after, before) {
+                    if before <= after {
+                        return Err(Error::InvalidArgValue {
+                            arg: "published-before",
+                            value: before.to_string(),
+                            reason: "the date must be after the one passed to --published-after"
+                                .into(),
+                        });
+                    }
+                }
+                if after.is_some() || before.is_some() {
+                    Some(DateFilter {
+                        after,
+                        before,
+                        include_undated: !arg_matches.is_present("exclude-undated"),
+                    })
                 } else {
-                    Err(Error::NoUrls)
+                    None
+                }
+            })
+            .min_confidence(match parse_value::<f32>(&args, "min-confidence")? {
+                Some(score) if !(0.0..=1.0).contains(&score) => {
+                    return Err(Error::InvalidArgValue {
+                        arg: "min-confidence",
+                        value: score.to_string(),
+                        reason: "the score must be between 0 and 1".into(),
+                    })
                 }
-            }?)
-            .max_conn(match arg_matches.value_of("max-conn") {
-                Some(max_conn) => max_conn.parse::<NonZeroUsize>()?.get(),
-                None => DEFAULT_MAX_CONN,
+                score => score,
+            })
+            .extraction_budget(ExtractionBudget {
+                max_nodes: parse_value::<NonZeroUsize>(&args, "max-nodes")?
+                    .map_or(DEFAULT_MAX_NODES, NonZeroUsize::get),
+                max_duration: parse_value_with(&args, "extract-timeout", parse_duration)?
+                    .unwrap_or(DEFAULT_EXTRACT_TIMEOUT),
+                min_chars: parse_value::<usize>(&args, "min-chars")?
+                    .unwrap_or(DEFAULT_CHAR_THRESHOLD),
+                min_words: parse_value::<usize>(&args, "min-words")?.unwrap_or_default(),
             })
-            .merged(arg_matches.value_of("output-name").map(|name| {
-                let file_ext = format!(".{}", arg_matches.value_of("export").unwrap_or("epub"));
+            .article_timeout(parse_value_with(&args, "article-timeout", parse_duration)?)
+            .merged(merged_name.map(|name| {
+                let file_ext = format!(".{}", args.value_of("export").unwrap_or("epub"));
                 if name.ends_with(&file_ext) {
                     name.to_owned()
                 } else {
                     name.to_string() + &file_ext
                 }
             }))
+            // The progress bars are drawn to stderr where the progress events go by default
             .can_disable_progress_bar(
-                arg_matches.is_present("verbosity") && !arg_matches.is_present("log-to-file"),
+                (arg_matches.is_present("verbosity") && !args.is_present("log-to-file"))
+                    || (arg_matches.is_present("progress")
+                        && !arg_matches.is_present("progress-fd")),
             )
+            .progress(match parse_value::<i32>(&args, "progress-fd")? {
+                Some(fd) => {
+                    Some(
+                        ProgressEvents::from_fd(fd).map_err(|e| Error::InvalidArgValue {
+                            arg: "progress-fd",
+                            value: fd.to_string(),
+                            reason: e.to_string(),
+                        })?,
+                    )
+                }
+                None if arg_matches.is_present("progress") => Some(ProgressEvents::stderr()),
+                None => None,
+            })
             .log_level(match arg_matches.occurrences_of("verbosity") {
                 0 => {
-                    if !arg_matches.is_present("log-to-file") {
+                    if !args.is_present("log-to-file") {
                         LogLevel::Off
                     } else {
                         LogLevel::Debug
@@ -119,10 +568,12 @@ impl<'a> TryFrom<ArgMatches<'a>> for AppConfig {
                 3 => LogLevel::Info,
                 4..=u64::MAX => LogLevel::Debug,
             })
-            .is_logging_to_file(arg_matches.is_present("log-to-file"))
+            .is_logging_to_file(args.is_present("log-to-file"))
             .inline_toc(
-                (if arg_matches.is_present("inline-toc") {
-                    if arg_matches.value_of("export") == Some("epub") {
+                (if arg_matches.is_present("inline-toc")
+                    || merge_matches.map_or(false, |matches| matches.is_present("inline-toc"))
+                {
+                    if args.value_of("export") == Some("epub") {
                         Ok(true)
                     } else {
                         Err(Error::WrongExportInliningToC)
@@ -132,8 +583,7 @@ impl<'a> TryFrom<ArgMatches<'a>> for AppConfig {
                 })?,
             )
             .output_directory(
-                arg_matches
-                    .value_of("output-directory")
+                args.value_of("output-directory")
                     .map(|output_directory| {
                         let path = Path::new(output_directory);
                         if !path.exists() {
@@ -147,28 +597,41 @@ impl<'a> TryFrom<ArgMatches<'a>> for AppConfig {
                     })
                     .transpose()?,
             )
+            .overwrite(match arg_matches.value_of("overwrite") {
+                Some("skip") => OverwritePolicy::Skip,
+                Some("rename") => OverwritePolicy::Rename,
+                _ => OverwritePolicy::Replace,
+            })
             .start_time(Local::now())
-            .css_config(
-                match (
-                    arg_matches.is_present("no-css"),
-                    arg_matches.is_present("no-header-css"),
-                ) {
-                    (true, _) => CSSConfig::None,
-                    (_, true) => CSSConfig::NoHeaders,
-                    _ => CSSConfig::All,
-                },
-            )
+            .css_config(match (no_css, no_header_css) {
+                (true, _) => CSSConfig::None,
+                (_, true) => CSSConfig::NoHeaders,
+                _ => CSSConfig::All,
+            })
             .export_type({
-                let export_type = arg_matches.value_of("export").unwrap_or("epub");
-                if export_type == "html" {
-                    ExportType::HTML
-                } else {
-                    ExportType::EPUB
+                let export_type = args.value_of("export").unwrap_or("epub");
+                match export_type {
+                    "html" => ExportType::HTML,
+                    "ssml" => ExportType::SSML,
+                    "odt" => ExportType::ODT,
+                    "md" => ExportType::Markdown,
+                    "site" if merged_name.is_some() => return Err(Error::MergedSite),
+                    "site" => ExportType::Site,
+                    _ => ExportType::EPUB,
                 }
             })
+            .md_flavor(
+                (match arg_matches.value_of("md-flavor") {
+                    Some(_) if args.value_of("export") != Some("md") => {
+                        Err(Error::WrongExportMdFlavor)
+                    }
+                    Some("obsidian") => Ok(MarkdownFlavor::Obsidian),
+                    _ => Ok(MarkdownFlavor::CommonMark),
+                })?,
+            )
             .is_inlining_images(
-                (if arg_matches.is_present("inline-images") {
-                    if arg_matches.value_of("export") == Some("html") {
+                (if args.is_present("inline-images") {
+                    if args.value_of("export") == Some("html") {
                         Ok(true)
                     } else {
                         Err(Error::WrongExportInliningImages)
@@ -177,56 +640,1523 @@ impl<'a> TryFrom<ArgMatches<'a>> for AppConfig {
                     Ok(false)
                 })?,
             )
+            .is_preserving_meta(
+                (if args.is_present("preserve-meta") {
+                    if args.value_of("export") == Some("html") {
+                        Ok(true)
+                    } else {
+                        Err(Error::WrongExportPreserveMeta)
+                    }
+                } else {
+                    Ok(false)
+                })?,
+            )
+            .has_reader_ui(
+                (if arg_matches.is_present("reader-ui") {
+                    if args.value_of("export") == Some("html") {
+                        Ok(true)
+                    } else {
+                        Err(Error::WrongExportReaderUi)
+                    }
+                } else {
+                    Ok(false)
+                })?,
+            )
+            .paginate(match parse_value::<NonZeroUsize>(&args, "paginate")? {
+                Some(_) if args.value_of("export") != Some("html") => {
+                    return Err(Error::WrongExportPaginate)
+                }
+                page_size => page_size,
+            })
+            .is_skipping_images(args.is_present("no-images"))
+            .image_cache(if args.is_present("no-image-cache") {
+                None
+            } else {
+                let max_size = parse_value_with(&args, "image-cache-size", |value| {
+                    parse_size(value, MEGABYTE)
+                })?
+                .unwrap_or(DEFAULT_IMAGE_CACHE_SIZE);
+                ImageCache::with_default_dir(max_size)
+            })
+            .history(if args.is_present("no-history") {
+                None
+            } else {
+                DownloadHistory::with_default_dir().map(Arc::new)
+            })
+            .is_resuming(arg_matches.is_present("resume"))
+            .size_budget(parse_value_with(&args, "size-budget", |value| {
+                parse_size(value, MEGABYTE)
+            })?)
+            .image_size_filter(ImageSizeFilter {
+                min_bytes: parse_value_with(&args, "min-image-size", |value| {
+                    parse_size(value, KILOBYTE)
+                })?
+                .unwrap_or(0),
+                min_dimension: parse_value(&args, "min-image-dimension")?.unwrap_or(0),
+            })
+            .discussion(DiscussionOptions {
+                max_comments: parse_value(&args, "discussion-comments")?
+                    .unwrap_or(DEFAULT_DISCUSSION_COMMENTS),
+                depth: parse_value(&args, "discussion-depth")?.unwrap_or(DEFAULT_DISCUSSION_DEPTH),
+                min_score: parse_value(&args, "discussion-min-score")?,
+            })
+            .is_including_comments(arg_matches.is_present("include-comments"))
+            .is_downloading_audio(arg_matches.is_present("download-audio"))
+            .has_alt_captions(args.is_present("alt-captions"))
+            .drop_caps(args.is_present("drop-caps"))
+            .chapter_breaks(arg_matches.is_present("chapter-breaks"))
+            .title_page(
+                (if arg_matches.is_present("title-page") {
+                    if args.value_of("export").unwrap_or("epub") == "epub" {
+                        Ok(true)
+                    } else {
+                        Err(Error::WrongExportTitlePage)
+                    }
+                } else {
+                    Ok(false)
+                })?,
+            )
+            .has_cover_collage(
+                (if arg_matches.is_present("cover-collage") {
+                    if args.value_of("export").unwrap_or("epub") == "epub" {
+                        Ok(true)
+                    } else {
+                        Err(Error::WrongExportCoverCollage)
+                    }
+                } else {
+                    Ok(false)
+                })?,
+            )
+            .cjk_font(match args.value_of("cjk-font") {
+                Some(_) if args.value_of("export").unwrap_or("epub") != "epub" => {
+                    return Err(Error::WrongExportCjkFont)
+                }
+                Some(font) => {
+                    let font_path = PathBuf::from(font);
+                    if !font_path.is_file() {
+                        return Err(Error::InvalidArgValue {
+                            arg: "cjk-font",
+                            value: font.to_owned(),
+                            reason: "the file does not exist".into(),
+                        });
+                    }
+                    if cjk_font_mime(&font_path).is_none() {
+                        return Err(Error::InvalidArgValue {
+                            arg: "cjk-font",
+                            value: font.to_owned(),
+                            reason: "the font must be a .ttf, .otf, .woff or .woff2 file".into(),
+                        });
+                    }
+                    Some(font_path)
+                }
+                None => None,
+            })
+            .epub_compression({
+                let level = parse_value::<u32>(&args, "epub-compression")?;
+                if let Some(level) = level.filter(|level| *level > 9) {
+                    return Err(Error::InvalidArgValue {
+                        arg: "epub-compression",
+                        value: level.to_string(),
+                        reason: "the level must be between 0 and 9".to_owned(),
+                    });
+                }
+                let is_storing_images = arg_matches.is_present("store-images");
+                if (level.is_some() || is_storing_images)
+                    && args.value_of("export").unwrap_or("epub") != "epub"
+                {
+                    return Err(Error::WrongExportEpubCompression);
+                }
+                EpubCompression {
+                    level,
+                    is_storing_images,
+                }
+            })
+            .credentials({
+                // The first credentials matching a host are sent so the ones passed on the
+                // command line come before the ones of the profile and its sites
+                let basic_credentials = arg_matches
+                    .values_of("auth")
+                    .into_iter()
+                    .flatten()
+                    .chain(profile.auth.iter().map(String::as_str))
+                    .map(parse_basic_credentials);
+                let token_credentials = arg_matches
+                    .values_of("auth-token")
+                    .into_iter()
+                    .flatten()
+                    .chain(profile.auth_token.iter().map(String::as_str))
+                    .map(parse_token_credentials);
+                let site_credentials = profile.sites.iter().flat_map(|(domain, rule)| {
+                    let basic = rule.auth.as_ref().map(|user_pass| {
+                        parse_basic_credentials(&format!("{}@{}", user_pass, domain))
+                    });
+                    let token = rule
+                        .token
+                        .as_ref()
+                        .map(|token| parse_token_credentials(&format!("{}@{}", token, domain)));
+                    basic.into_iter().chain(token)
+                });
+                basic_credentials
+                    .chain(token_credentials)
+                    .chain(site_credentials)
+                    .collect::<Result<Vec<_>, _>>()?
+            })
+            .heading_offset(parse_value(&args, "heading-offset")?.unwrap_or(0))
+            .toc_depth(parse_value(&args, "toc-depth")?.unwrap_or(4))
+            .has_article_toc(!args.is_present("no-article-toc"))
+            .has_lead_image(!args.is_present("no-lead-image"))
+            .is_rasterizing_svgs(
+                (if args.is_present("rasterize-svg") {
+                    if cfg!(feature = "svg-raster") {
+                        Ok(true)
+                    } else {
+                        Err(Error::SvgRasterUnavailable)
+                    }
+                } else {
+                    Ok(false)
+                })?,
+            )
+            .is_flattening_gifs(
+                (if args.is_present("flatten-gifs") {
+                    if cfg!(feature = "gif-flatten") {
+                        Ok(true)
+                    } else {
+                        Err(Error::GifFlattenUnavailable)
+                    }
+                } else {
+                    Ok(false)
+                })?,
+            )
+            .is_grayscale(
+                (if args.is_present("grayscale")
+                    || arg_matches.value_of("optimize-for") == Some("ereader")
+                {
+                    if cfg!(feature = "grayscale") {
+                        Ok(true)
+                    } else {
+                        Err(Error::GrayscaleUnavailable)
+                    }
+                } else {
+                    Ok(false)
+                })?,
+            )
+            .has_auto_cover(
+                (if arg_matches.value_of("cover") == Some("auto") {
+                    if merged_name.is_some() {
+                        Err(Error::MergedCover)
+                    } else if args.value_of("export").unwrap_or("epub") == "epub" {
+                        Ok(true)
+                    } else {
+                        Err(Error::WrongExportCover)
+                    }
+                } else {
+                    Ok(false)
+                })?,
+            )
+            .has_site_logo(args.is_present("site-logo"))
+            .has_source_qr(
+                (if arg_matches.is_present("source-qr") {
+                    if !["epub", "html"].contains(&args.value_of("export").unwrap_or("epub")) {
+                        Err(Error::WrongExportSourceQr)
+                    } else if cfg!(feature = "source-qr") {
+                        Ok(true)
+                    } else {
+                        Err(Error::SourceQrUnavailable)
+                    }
+                } else {
+                    Ok(false)
+                })?,
+            )
+            .report(args.value_of("report").map(ToOwned::to_owned))
+            .locale(match args.value_of("locale") {
+                Some(locale) => locale.parse().map_err(|reason| Error::InvalidArgValue {
+                    arg: "locale",
+                    value: locale.to_owned(),
+                    reason,
+                })?,
+                None => Locale::default(),
+            })
+            .debug_extraction_dir(match arg_matches.value_of("debug-extraction") {
+                Some(dir) if Path::new(dir).is_file() => {
+                    return Err(Error::InvalidArgValue {
+                        arg: "debug-extraction",
+                        value: dir.to_owned(),
+                        reason: "the path is a file".into(),
+                    });
+                }
+                dir => dir.map(PathBuf::from),
+            })
+            .is_writing_manifests(args.is_present("manifest"))
+            .post_process(match args.value_of("post-process") {
+                Some(command) if !command.contains(FILE_PLACEHOLDER) => {
+                    return Err(Error::InvalidArgValue {
+                        arg: "post-process",
+                        value: command.to_owned(),
+                        reason: format!(
+                            "the command must contain {} where the path of the file goes",
+                            FILE_PLACEHOLDER
+                        ),
+                    })
+                }
+                command => command.map(ToOwned::to_owned),
+            })
+            .feed(match arg_matches.value_of("emit-feed") {
+                Some(_) if args.value_of("export") != Some("html") => {
+                    return Err(Error::WrongExportEmitFeed)
+                }
+                feed => feed.map(ToOwned::to_owned),
+            })
+            .push(
+                arg_matches
+                    .value_of("push")
+                    .map(|service| {
+                        let service = match service {
+                            "omnivore" => PushService::Omnivore,
+                            _ => PushService::Readwise,
+                        };
+                        PushOptions::from_env(service, |name| env::var(name).ok())
+                    })
+                    .transpose()?,
+            )
+            .translate(
+                args.value_of("translate")
+                    .map(|lang| {
+                        let translator = match (
+                            args.value_of("translate-command"),
+                            args.value_of("translator"),
+                        ) {
+                            (Some(command), _) => Ok(Translator::Command(command.to_owned())),
+                            (None, Some("deepl")) => {
+                                Translator::deepl_from_env(|name| env::var(name).ok())
+                            }
+                            (None, _) => Ok(Translator::LibreTranslate {
+                                url: args
+                                    .value_of("translator-url")
+                                    .unwrap_or(LIBRETRANSLATE_URL)
+                                    .trim_end_matches('/')
+                                    .to_owned(),
+                                api_key: env::var(LIBRETRANSLATE_KEY_VAR)
+                                    .ok()
+                                    .filter(|key| !key.trim().is_empty()),
+                            }),
+                        }?;
+                        Ok(TranslateOptions {
+                            target_lang: lang.trim().to_owned(),
+                            translator,
+                        })
+                    })
+                    .transpose()?,
+            )
+            .max_server_jobs(
+                match arg_matches
+                    .subcommand_matches("serve")
+                    .and_then(|server_matches| server_matches.value_of("max-jobs"))
+                {
+                    Some(max_jobs) => max_jobs
+                        .parse::<NonZeroUsize>()
+                        .map_err(|e| Error::InvalidArgValue {
+                            arg: "max-jobs",
+                            value: max_jobs.to_owned(),
+                            reason: e.to_string(),
+                        })?
+                        .get(),
+                    None => DEFAULT_MAX_SERVER_JOBS,
+                },
+            )
+            .server_address(
+                arg_matches
+                    .subcommand_matches("serve")
+                    .map(|server_matches| -> Result<String, Error> {
+                        let port = server_matches.value_of("port").unwrap_or("8080");
+                        let port = port
+                            .parse::<u16>()
+                            .map_err(|_| Error::InvalidServerPort(port.to_owned()))?;
+                        Ok(format!(
+                            "{}:{}",
+                            server_matches.value_of("host").unwrap_or("127.0.0.1"),
+                            port
+                        ))
+                    })
+                    .transpose()?,
+            )
+            .bench(
+                arg_matches
+                    .subcommand_matches("bench")
+                    .map(|bench_matches| -> Result<BenchOptions, Error> {
+                        let iterations = bench_matches.value_of("iterations").unwrap_or("10");
+                        Ok(BenchOptions {
+                            input: bench_matches
+                                .value_of("input")
+                                .unwrap_or_default()
+                                .to_owned(),
+                            iterations: iterations
+                                .parse::<NonZeroUsize>()
+                                .map_err(|err| Error::InvalidArgValue {
+                                    arg: "iterations",
+                                    value: iterations.to_owned(),
+                                    reason: err.to_string(),
+                                })?
+                                .get(),
+                        })
+                    })
+                    .transpose()?,
+            )
+            .preview(
+                arg_matches
+                    .subcommand_matches("preview")
+                    .map(|preview_matches| -> Result<PreviewOptions, Error> {
+                        let port = preview_matches.value_of("port").unwrap_or("8000");
+                        let port = port
+                            .parse::<u16>()
+                            .map_err(|_| Error::InvalidServerPort(port.to_owned()))?;
+                        Ok(PreviewOptions {
+                            input: preview_matches
+                                .value_of("input")
+                                .unwrap_or_default()
+                                .to_owned(),
+                            address: format!(
+                                "{}:{}",
+                                preview_matches.value_of("host").unwrap_or("127.0.0.1"),
+                                port
+                            ),
+                        })
+                    })
+                    .transpose()?,
+            )
+            .debug_extract(
+                arg_matches
+                    .subcommand_matches("debug-extract")
+                    .map(|debug_matches| -> Result<DebugExtractOptions, Error> {
+                        let timeout = debug_matches.value_of("timeout").unwrap_or("10");
+                        Ok(DebugExtractOptions {
+                            input: debug_matches
+                                .value_of("input")
+                                .unwrap_or_default()
+                                .to_owned(),
+                            timeout: timeout
+                                .parse::<NonZeroU64>()
+                                .map_err(|err| Error::InvalidArgValue {
+                                    arg: "timeout",
+                                    value: timeout.to_owned(),
+                                    reason: err.to_string(),
+                                })?
+                                .get(),
+                        })
+                    })
+                    .transpose()?,
+            )
+            .selftest(
+                arg_matches
+                    .subcommand_matches("selftest")
+                    .map(|selftest_matches| -> Result<SelftestOptions, Error> {
+                        let min_score = selftest_matches.value_of("min-score").unwrap_or("0.8");
+                        Ok(SelftestOptions {
+                            corpus: selftest_matches
+                                .value_of("corpus")
+                                .unwrap_or_default()
+                                .to_owned(),
+                            min_score: min_score
+                                .parse::<f64>()
+                                .map_err(|err| err.to_string())
+                                .and_then(|score| {
+                                    if (0.0..=1.0).contains(&score) {
+                                        Ok(score)
+                                    } else {
+                                        Err("expected a score between 0 and 1".to_owned())
+                                    }
+                                })
+                                .map_err(|reason| Error::InvalidArgValue {
+                                    arg: "min-score",
+                                    value: min_score.to_owned(),
+                                    reason,
+                                })?,
+                        })
+                    })
+                    .transpose()?,
+            )
+            .watch(
+                arg_matches
+                    .subcommand_matches("watch")
+                    .map(|watch_matches| -> Result<WatchOptions, Error> {
+                        let interval = watch_matches.value_of("interval").unwrap_or("5");
+                        let queue_batch = watch_matches.value_of("queue-batch").unwrap_or("10");
+                        Ok(WatchOptions {
+                            dir: watch_matches.value_of("dir").unwrap_or_default().to_owned(),
+                            interval: interval
+                                .parse::<NonZeroU64>()
+                                .map_err(|err| Error::InvalidArgValue {
+                                    arg: "interval",
+                                    value: interval.to_owned(),
+                                    reason: err.to_string(),
+                                })?
+                                .get(),
+                            is_once: watch_matches.is_present("once"),
+                            queue_batch: queue_batch
+                                .parse::<NonZeroUsize>()
+                                .map_err(|err| Error::InvalidArgValue {
+                                    arg: "queue-batch",
+                                    value: queue_batch.to_owned(),
+                                    reason: err.to_string(),
+                                })?
+                                .get(),
+                        })
+                    })
+                    .transpose()?,
+            )
+            .metrics({
+                let server_matches = arg_matches.subcommand_matches("serve");
+                let watch_matches = arg_matches.subcommand_matches("watch");
+                server_matches
+                    .or(watch_matches)
+                    .map(|subcommand_matches| MetricsOptions {
+                        counters: Arc::new(Metrics::default()),
+                        address: subcommand_matches
+                            .value_of("metrics-address")
+                            .map(str::to_owned),
+                        file: subcommand_matches
+                            .value_of("metrics-file")
+                            .map(str::to_owned),
+                    })
+                    // The server always serves the metrics so they are only kept for watching
+                    // when they are reported
+                    .filter(|options| {
+                        server_matches.is_some()
+                            || options.address.is_some()
+                            || options.file.is_some()
+                    })
+            })
+            .search(
+                arg_matches
+                    .subcommand_matches("search")
+                    .map(|search_matches| -> Result<SearchOptions, Error> {
+                        let limit = search_matches.value_of("max-results").unwrap_or("20");
+                        Ok(SearchOptions {
+                            terms: search_matches
+                                .values_of("query")
+                                .map(|terms| terms.map(ToOwned::to_owned).collect())
+                                .unwrap_or_default(),
+                            site_dir: search_matches.value_of("dir").unwrap_or(".").to_owned(),
+                            limit: limit
+                                .parse::<NonZeroUsize>()
+                                .map_err(|err| Error::InvalidArgValue {
+                                    arg: "max-results",
+                                    value: limit.to_owned(),
+                                    reason: err.to_string(),
+                                })?
+                                .get(),
+                            picks: search_matches
+                                .value_of("pick")
+                                .map(|picks| {
+                                    picks
+                                        .split(',')
+                                        .map(|pick| {
+                                            pick.trim()
+                                                .parse::<NonZeroUsize>()
+                                                .map(NonZeroUsize::get)
+                                                .map_err(|err| Error::InvalidArgValue {
+                                                    arg: "pick",
+                                                    value: picks.to_owned(),
+                                                    reason: err.to_string(),
+                                                })
+                                        })
+                                        .collect::<Result<Vec<_>, _>>()
+                                })
+                                .transpose()?
+                                .unwrap_or_default(),
+                            epub: search_matches.value_of("epub").map(ToOwned::to_owned),
+                        })
+                    })
+                    .transpose()?,
+            )
+            .cache_command(
+                arg_matches
+                    .subcommand_matches("cache")
+                    .and_then(|cache_matches| cache_matches.subcommand_matches("clean"))
+                    .map(|_| CacheCommand::Clean),
+            )
+            .crawl_depth(parse_value(&args, "crawl-depth")?.unwrap_or(0))
+            .crawl_pattern(
+                arg_matches
+                    .value_of("crawl-pattern")
+                    .map(Regex::new)
+                    .transpose()
+                    .map_err(|err| Error::InvalidCrawlPattern(err.to_string()))?,
+            )
+            .is_following_series(arg_matches.is_present("follow-series"))
+            .is_keeping_duplicates(arg_matches.is_present("keep-duplicates"))
+            .archive(if arg_matches.is_present("archive") {
+                Some(ArchiveOptions {
+                    limit: parse_value::<NonZeroUsize>(&args, "limit")?.map(NonZeroUsize::get),
+                })
+            } else {
+                None
+            })
+            .remove_selectors(
+                profile
+                    .remove_selectors
+                    .iter()
+                    .map(String::as_str)
+                    .chain(
+                        arg_matches
+                            .values_of("remove-selector")
+                            .into_iter()
+                            .flatten(),
+                    )
+                    .map(&compile_selector)
+                    .collect::<Result<Vec<_>, _>>()?,
+            )
+            .print_links(match arg_matches.value_of("print-links") {
+                Some("footnotes") => Some(PrintLinks::Footnotes),
+                Some("inline") => Some(PrintLinks::Inline),
+                _ => None,
+            })
+            .sanitizer(if args.is_present("sanitize") {
+                let allowed_values =
+                    |profile_values: &[String], arg: &'static str| -> Result<Vec<String>, Error> {
+                        profile_values
+                            .iter()
+                            .map(String::as_str)
+                            .chain(arg_matches.values_of(arg).into_iter().flatten())
+                            .map(|value| match value.trim().to_lowercase() {
+                                value if value == "script" || value.starts_with("on") => {
+                                    Err(Error::InvalidArgValue {
+                                        arg,
+                                        value,
+                                        reason: "scripts and event handlers are always removed"
+                                            .to_owned(),
+                                    })
+                                }
+                                value => Ok(value),
+                            })
+                            .collect()
+                    };
+                Some(Sanitizer::new(
+                    &allowed_values(&profile.sanitize_allow_tags, "sanitize-allow-tag")?,
+                    &allowed_values(&profile.sanitize_allow_attrs, "sanitize-allow-attr")?,
+                ))
+            } else {
+                None
+            })
+            .skipped_image_patterns(
+                profile
+                    .skip_images_from
+                    .iter()
+                    .map(String::as_str)
+                    .chain(
+                        arg_matches
+                            .values_of("skip-images-from")
+                            .into_iter()
+                            .flatten(),
+                    )
+                    .map(ImagePattern::new)
+                    .collect(),
+            )
+            .site_rules({
+                for rule in profile.sites.values() {
+                    for selector in &rule.remove_selectors {
+                        compile_selector(selector)?;
+                    }
+                }
+                profile.sites.clone()
+            })
+            .text_replacements(profile.replace.clone())
+            .title(arg_matches.value_of("title").map(ToOwned::to_owned))
+            .author(arg_matches.value_of("author").map(ToOwned::to_owned))
+            .cookie_jar(match args.value_of("cookies") {
+                Some(cookies_file) => CookieJar::from_file(Path::new(cookies_file))
+                    .map_err(|err| Error::CookieFileError(err.to_string()))?,
+                None => CookieJar::default(),
+            })
+            .url_rewriter(match args.value_of("url-rewrite") {
+                Some(rules_file) => UrlRewriter::from_file(Path::new(rules_file))
+                    .map_err(Error::UrlRewriteFileError)?,
+                None => UrlRewriter::default(),
+            })
+            .tls({
+                let tls = TlsOptions {
+                    is_insecure: args.is_present("insecure"),
+                    insecure_hosts: arg_matches
+                        .values_of("insecure-host")
+                        .into_iter()
+                        .flatten()
+                        .map(|host| host.trim().to_lowercase())
+                        .unique()
+                        .collect(),
+                    ca_cert: args.value_of("ca-cert").map(PathBuf::from),
+                };
+                if let Some(ca_cert) = &tls.ca_cert {
+                    if !ca_cert.is_file() {
+                        return Err(Error::InvalidArgValue {
+                            arg: "ca-cert",
+                            value: ca_cert.display().to_string(),
+                            reason: "the file does not exist".into(),
+                        });
+                    }
+                }
+                if tls != TlsOptions::default() && http_backend == HttpBackend::H1 {
+                    return Err(Error::TlsOptionsUnavailable);
+                }
+                tls
+            })
+            .http_backend(http_backend)
+            .resolve_overrides({
+                let mut overrides = arg_matches
+                    .values_of("resolve")
+                    .into_iter()
+                    .flatten()
+                    .map(parse_resolve_override)
+                    .collect::<Result<Vec<_>, _>>()?;
+                // curl cannot be given a resolver so the hosts of the urls passed are looked up
+                // now and pinned. Hosts only known later, such as the ones of images and
+                // redirects, are resolved by the system.
+                if let Some(dns_server) = arg_matches.value_of("dns") {
+                    let dns_server = parse_dns_server(dns_server)?;
+                    let url_hosts = urls
+                        .iter()
+                        .filter_map(|url| {
+                            let url = url::Url::parse(url).ok()?;
+                            Some((url.host_str()?.to_owned(), url.port_or_known_default()?))
+                        })
+                        .unique()
+                        .collect_vec();
+                    for (host, port) in url_hosts {
+                        if overrides
+                            .iter()
+                            .any(|resolved| resolved.host == host && resolved.port == port)
+                        {
+                            continue;
+                        }
+                        let address = dns::lookup(dns_server, &host)
+                            .map_err(|e| Error::DnsLookupError(host.clone(), e.to_string()))?[0];
+                        overrides.push(ResolveOverride {
+                            host,
+                            port,
+                            address,
+                        });
+                    }
+                }
+                if !overrides.is_empty() && http_backend != HttpBackend::Curl {
+                    return Err(Error::ResolveUnavailable);
+                }
+                overrides
+            })
             .try_init()
     }
 }
 
-impl AppConfigBuilder {
-    pub fn try_init(&self) -> Result<AppConfig, Error> {
-        self.build()
-            .map_err(Error::AppBuildError)?
-            .init_logger()?
-            .init_merge_file()
+/// Options that can be set with environment variables and the arguments they set
+const ENV_OPTIONS: &[(&str, &str)] = &[
+    ("PAPERONI_PROFILE", "profile"),
+    ("PAPERONI_CONFIG", "config"),
+    ("PAPERONI_OUTPUT_DIR", "output-directory"),
+    ("PAPERONI_EXPORT", "export"),
+    ("PAPERONI_MAX_CONN", "max-conn"),
+    ("PAPERONI_MAX_REDIRECTS", "max-redirects"),
+    ("PAPERONI_HEADING_OFFSET", "heading-offset"),
+    ("PAPERONI_TOC_DEPTH", "toc-depth"),
+    ("PAPERONI_COOKIES", "cookies"),
+    ("PAPERONI_REPORT", "report"),
+    ("PAPERONI_LOCALE", "locale"),
+];
+
+/// Flags that can be set with environment variables and the arguments they set
+const ENV_FLAGS: &[(&str, &str)] = &[
+    ("PAPERONI_NO_CSS", "no-css"),
+    ("PAPERONI_NO_HEADER_CSS", "no-header-css"),
+    ("PAPERONI_NO_IMAGES", "no-images"),
+    ("PAPERONI_ALT_CAPTIONS", "alt-captions"),
+    ("PAPERONI_INLINE_IMAGES", "inline-images"),
+    ("PAPERONI_DROP_CAPS", "drop-caps"),
+    ("PAPERONI_SITE_LOGO", "site-logo"),
+    ("PAPERONI_NO_ARTICLE_TOC", "no-article-toc"),
+    ("PAPERONI_RASTERIZE_SVG", "rasterize-svg"),
+    ("PAPERONI_LOG_TO_FILE", "log-to-file"),
+];
+
+/// Reads the options set with environment variables, keyed by the argument they set.
+/// Flags are set with `1` or `true` and unset with `0` or `false` which are stored as
+/// `"true"` and `"false"`. Empty options are ignored.
+fn parse_env_values(
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<HashMap<&'static str, String>, Error> {
+    let mut env_values = HashMap::new();
+    for (name, value) in vars {
+        if let Some((_, arg)) = ENV_OPTIONS.iter().find(|(var, _)| *var == name) {
+            if !value.trim().is_empty() {
+                env_values.insert(*arg, value);
+            }
+        } else if let Some((_, arg)) = ENV_FLAGS.iter().find(|(var, _)| *var == name) {
+            let is_set = match value.trim().to_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => true,
+                "" | "0" | "false" | "no" | "off" => false,
+                _ => return Err(Error::InvalidEnvValue(name, value)),
+            };
+            env_values.insert(*arg, is_set.to_string());
+        }
     }
+    Ok(env_values)
 }
 
-#[derive(Clone, Debug)]
-pub enum CSSConfig {
-    All,
-    NoHeaders,
-    None,
+/// The values of the options, resolved in the order of the arguments passed on the
+/// command line, the environment variables and then the profile selected with `--profile`
+struct ArgValues<'a, 'b> {
+    arg_matches: &'b ArgMatches<'a>,
+    env_values: &'b HashMap<&'static str, String>,
+    profile: &'b Profile,
 }
 
-#[derive(Clone, Debug)]
-pub enum ExportType {
-    HTML,
-    EPUB,
+impl<'a, 'b> ArgValues<'a, 'b> {
+    fn value_of(&self, arg: &str) -> Option<&str> {
+        // A merged output is not written to the output directory
+        if arg == "output-directory"
+            && (self.arg_matches.is_present("output-name")
+                || self.arg_matches.subcommand_matches("merge").is_some())
+        {
+            return None;
+        }
+        self.arg_matches
+            .value_of(arg)
+            .or_else(|| self.env_values.get(arg).map(String::as_str))
+            .or_else(|| match arg {
+                "export" => self.profile.export.as_deref(),
+                "output-directory" => self.profile.output_dir.as_deref(),
+                "locale" => self.profile.locale.as_deref(),
+                _ => None,
+            })
+    }
+
+    fn env_flag(&self, arg: &str) -> Option<bool> {
+        self.env_values.get(arg).map(|value| value == "true")
+    }
+
+    fn is_present(&self, arg: &str) -> bool {
+        if self.arg_matches.is_present(arg) {
+            return true;
+        }
+        self.env_flag(arg).unwrap_or(match arg {
+            "no-images" => self.profile.no_images,
+            "alt-captions" => self.profile.alt_captions,
+            "inline-images" => self.profile.inline_images,
+            "drop-caps" => self.profile.drop_caps,
+            "site-logo" => self.profile.site_logo,
+            "sanitize" => self.profile.sanitize,
+            _ => false,
+        })
+    }
+
+    /// The --no-css and --no-header-css flags are set together from the first place
+    /// either of them is set in as they conflict with each other
+    fn css_flags(&self) -> (bool, bool) {
+        let cli_flags = (
+            self.arg_matches.is_present("no-css"),
+            self.arg_matches.is_present("no-header-css"),
+        );
+        let env_flags = (self.env_flag("no-css"), self.env_flag("no-header-css"));
+        match (cli_flags, env_flags) {
+            ((false, false), (None, None)) => (self.profile.no_css, self.profile.no_header_css),
+            ((false, false), (no_css, no_header_css)) => {
+                (no_css.unwrap_or(false), no_header_css.unwrap_or(false))
+            }
+            (cli_flags, _) => cli_flags,
+        }
+    }
+
+    /// The export type from the command line is checked by clap but the ones from the
+    /// environment and the profile are not
+    fn check_export_type(&self) -> Result<(), Error> {
+        match self.value_of("export") {
+            Some(export) if !["html", "epub", "ssml", "odt", "md", "site"].contains(&export) => {
+                Err(Error::InvalidArgValue {
+                    arg: "export",
+                    value: export.to_owned(),
+                    reason: "expected one of html, epub, ssml, odt, md or site".to_owned(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+/// Parses the value of an argument into its type. The error names the argument and the
+/// value that could not be parsed instead of only the reason it failed.
+fn parse_value<T>(args: &ArgValues, arg: &'static str) -> Result<Option<T>, Error>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    parse_value_with(args, arg, |value| {
+        value.parse::<T>().map_err(|err| err.to_string())
+    })
+}
 
-    #[test]
-    fn test_clap_config_errors() {
-        let yaml_config = load_yaml!("cli_config.yml");
-        let app = App::from_yaml(yaml_config);
+/// Parses the value of an argument with a parser for values that are not read with
+/// [FromStr] such as sizes and durations
+fn parse_value_with<T>(
+    args: &ArgValues,
+    arg: &'static str,
+    parse: impl FnOnce(&str) -> Result<T, String>,
+) -> Result<Option<T>, Error> {
+    args.value_of(arg)
+        .map(|value| {
+            parse(value).map_err(|reason| Error::InvalidArgValue {
+                arg,
+                value: value.to_owned(),
+                reason,
+            })
+        })
+        .transpose()
+}
 
-        // It returns Ok when only a url is passed
-        let result = app
-            .clone()
-            .get_matches_from_safe(vec!["paperoni", "http://example.org"]);
-        assert!(result.is_ok());
+const KILOBYTE: u64 = 1024;
+const MEGABYTE: u64 = 1024 * KILOBYTE;
 
-        // It returns an error when no args are passed
-        let result = app.clone().get_matches_from_safe(vec!["paperoni"]);
-        assert!(result.is_err());
-        assert_eq!(
-            clap::ErrorKind::MissingArgumentOrSubcommand,
-            result.unwrap_err().kind
-        );
+/// Parses a size in bytes such as `512K`, `1.5M` or `2G`. A number without a unit is in
+/// `default_unit` bytes so that the plain numbers each option took before keep their meaning.
+fn parse_size(value: &str, default_unit: u64) -> Result<u64, String> {
+    let value = value.trim();
+    let number_end = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or_else(|| value.len());
+    let (number, unit) = value.split_at(number_end);
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| "expected a number followed by an optional unit e.g 10M".to_owned())?;
+    let unit = match unit.trim().to_ascii_uppercase().as_str() {
+        "" => default_unit,
+        "B" => 1,
+        "K" | "KB" | "KIB" => KILOBYTE,
+        "M" | "MB" | "MIB" => MEGABYTE,
+        "G" | "GB" | "GIB" => 1024 * MEGABYTE,
+        unit => {
+            return Err(format!(
+                "unknown size unit {:?}. Expected B, K, M or G",
+                unit
+            ))
+        }
+    };
+    Ok((number * unit as f64) as u64)
+}
 
-        // It returns an error when both output-dir and merge are used
+/// Parses a duration such as `90`, `90s`, `5m` or `1h`. A number without a unit is in seconds.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let number_end = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or_else(|| value.len());
+    let (number, unit) = value.split_at(number_end);
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| "expected a whole number followed by an optional unit e.g 90s".to_owned())?;
+    let unit_secs = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        unit => {
+            return Err(format!(
+                "unknown duration unit {:?}. Expected s, m or h",
+                unit
+            ))
+        }
+    };
+    if number == 0 {
+        return Err("the duration must be greater than zero".to_owned());
+    }
+    Ok(Duration::from_secs(number * unit_secs))
+}
+
+/// Parses the content of a file of urls where each url is on its own line. A url can be
+/// followed by options that only apply to its article e.g
+/// `https://example.com/a | selector=#content title="Custom Title"`
+fn parse_url_file(content: &str) -> Result<Vec<(String, ArticleOptions)>, Error> {
+    let mut entries = Vec::new();
+    for line in content.lines() {
+        if let Some(entry) = parse_url_file_line(line)? {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+pub fn parse_url_file_line(line: &str) -> Result<Option<(String, ArticleOptions)>, Error> {
+    lazy_static! {
+        static ref OPTION_REGEX: Regex = Regex::new(r#"([\w-]+)=(?:"([^"]*)"|(\S+))"#).unwrap();
+    }
+    let mut parts = line.splitn(2, '|');
+    let url = parts.next().unwrap_or_default().trim();
+    if url.is_empty() {
+        return Ok(None);
+    }
+    let mut options = ArticleOptions::default();
+    if let Some(options_str) = parts.next() {
+        let unparsed = OPTION_REGEX.replace_all(options_str, "");
+        if !unparsed.trim().is_empty() {
+            return Err(Error::InvalidUrlOptions(format!(
+                "Unable to parse {:?} for {}",
+                unparsed.trim(),
+                url
+            )));
+        }
+        for captures in OPTION_REGEX.captures_iter(options_str) {
+            let value = captures
+                .get(2)
+                .or_else(|| captures.get(3))
+                .map(|value| value.as_str().to_owned())
+                .unwrap_or_default();
+            match &captures[1] {
+                "selector" => options.selector = Some(value),
+                "title" => options.title = Some(value),
+                "priority" => {
+                    options.priority = value.parse().map_err(|_| {
+                        Error::InvalidUrlOptions(format!(
+                            "Invalid priority {:?} for {}. Expected a whole number",
+                            value, url
+                        ))
+                    })?
+                }
+                "not-before" => {
+                    options.not_before = Some(parse_schedule_time(&value).ok_or_else(|| {
+                        Error::InvalidUrlOptions(format!(
+                            "Invalid time {:?} for {}. Expected a time such as 2021-06-01T08:00",
+                            value, url
+                        ))
+                    })?)
+                }
+                key => {
+                    return Err(Error::InvalidUrlOptions(format!(
+                        "Unknown option {:?} for {}",
+                        key, url
+                    )))
+                }
+            }
+        }
+    }
+    Ok(Some((url.to_owned(), options)))
+}
+
+/// Parses a time such as `2021-06-01T08:00:00+02:00`, `2021-06-01T08:00` or `2021-06-01`.
+/// Times without an offset are in the local time zone and dates start at midnight.
+fn parse_schedule_time(value: &str) -> Option<DateTime<Local>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.with_timezone(&Local));
+    }
+    let naive_time = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_hms(0, 0, 0))
+        })?;
+    Local.from_local_datetime(&naive_time).earliest()
+}
+
+/// Splits a `value@domain` string at the last `@` as the value itself may contain one
+fn split_credentials_domain(credentials: &str) -> Result<(&str, String), Error> {
+    match credentials.rsplitn(2, '@').collect_tuple() {
+        Some((domain, value)) if !domain.trim().is_empty() && !value.is_empty() => {
+            Ok((value, domain.trim().to_lowercase()))
+        }
+        _ => Err(Error::InvalidCredentials(credentials.to_owned())),
+    }
+}
+
+/// Parses credentials passed in the form `user:pass@domain`
+fn parse_basic_credentials(credentials: &str) -> Result<HostCredentials, Error> {
+    let (user_pass, domain) = split_credentials_domain(credentials)?;
+    match user_pass.splitn(2, ':').collect_tuple() {
+        Some((username, password)) if !username.is_empty() => Ok(HostCredentials::new(
+            &domain,
+            Authorization::Basic {
+                username: username.to_owned(),
+                password: password.to_owned(),
+            },
+        )),
+        _ => Err(Error::InvalidCredentials(credentials.to_owned())),
+    }
+}
+
+/// Parses a bearer token passed in the form `token@domain`
+fn parse_token_credentials(credentials: &str) -> Result<HostCredentials, Error> {
+    let (token, domain) = split_credentials_domain(credentials)?;
+    Ok(HostCredentials::new(
+        &domain,
+        Authorization::Bearer(token.to_owned()),
+    ))
+}
+
+/// Parses an address override passed in the form `host:port:address`
+fn parse_resolve_override(value: &str) -> Result<ResolveOverride, Error> {
+    let invalid_resolve = |reason: &str| Error::InvalidArgValue {
+        arg: "resolve",
+        value: value.to_owned(),
+        reason: reason.to_owned(),
+    };
+    let (host, port, address) = value
+        .splitn(3, ':')
+        .collect_tuple()
+        .ok_or_else(|| invalid_resolve("expected host:port:address"))?;
+    if host.is_empty() {
+        return Err(invalid_resolve("the host is empty"));
+    }
+    let port = port
+        .parse()
+        .map_err(|_| invalid_resolve("the port is not a number between 0 and 65535"))?;
+    let address = address
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .map_err(|_| invalid_resolve("the address is not an IPv4 or IPv6 address"))?;
+    Ok(ResolveOverride {
+        host: host.to_lowercase(),
+        port,
+        address,
+    })
+}
+
+/// Parses the address of a DNS server with an optional port which defaults to 53
+fn parse_dns_server(value: &str) -> Result<SocketAddr, Error> {
+    value
+        .parse::<SocketAddr>()
+        .or_else(|_| value.parse::<IpAddr>().map(|address| (address, 53).into()))
+        .map_err(|_| Error::InvalidArgValue {
+            arg: "dns",
+            value: value.to_owned(),
+            reason: "expected an IPv4 or IPv6 address with an optional port".into(),
+        })
+}
+
+impl AppConfigBuilder {
+    pub fn try_init(&self) -> Result<AppConfig, Error> {
+        self.build()
+            .map_err(Error::AppBuildError)?
+            .check_metadata_overrides()?
+            .check_series_url()?
+            .check_crawl_pattern()?
+            .init_logger()?
+            .warn_insecure_tls()
+            .init_merge_file()
+    }
+}
+
+/// Options from the url file that only apply to a single article
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ArticleOptions {
+    /// CSS selector of the element containing the article. This skips the readability algorithm
+    pub selector: Option<String>,
+    /// Overrides the title extracted from the article
+    pub title: Option<String>,
+    /// Links of queue files with a higher priority are downloaded first by the watch subcommand
+    pub priority: i32,
+    /// Links of queue files are not downloaded by the watch subcommand before this time
+    pub not_before: Option<DateTime<Local>>,
+}
+
+/// Keeps only the articles published within a range of days
+#[derive(Clone, Debug, PartialEq)]
+pub struct DateFilter {
+    /// Articles published before this day are skipped
+    pub after: Option<NaiveDate>,
+    /// Articles published on or after this day are skipped
+    pub before: Option<NaiveDate>,
+    /// Whether articles without a publication date are kept
+    pub include_undated: bool,
+}
+
+impl DateFilter {
+    pub fn allows(&self, published: Option<NaiveDate>) -> bool {
+        match published {
+            Some(date) => {
+                self.after.map_or(true, |after| date >= after)
+                    && self.before.map_or(true, |before| date < before)
+            }
+            None => self.include_undated,
+        }
+    }
+}
+
+/// Options of --archive which downloads the articles listed in author archives
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchiveOptions {
+    /// The most articles downloaded from the archives
+    pub limit: Option<usize>,
+}
+
+/// Options of the bench subcommand which times the extraction of an article
+#[derive(Clone, Debug)]
+pub struct BenchOptions {
+    /// Url or path of an HTML file of the article
+    pub input: String,
+    pub iterations: usize,
+}
+
+/// How the files of epubs are compressed when they are written
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EpubCompression {
+    /// The deflate level from 0 to 9 where 0 stores the files. None keeps the default level
+    pub level: Option<u32>,
+    /// Stores images instead of compressing them as they are already compressed
+    pub is_storing_images: bool,
+}
+
+/// Options of the preview subcommand which serves an extracted article to try options on
+#[derive(Clone, Debug)]
+pub struct PreviewOptions {
+    /// Url or path of an HTML file of the article
+    pub input: String,
+    /// Address the preview is served on
+    pub address: String,
+}
+
+/// A reading service articles can be pushed to with --push
+#[derive(Clone, Debug, PartialEq)]
+pub enum PushService {
+    Readwise,
+    Omnivore,
+}
+
+impl PushService {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PushService::Readwise => "Readwise Reader",
+            PushService::Omnivore => "Omnivore",
+        }
+    }
+
+    /// The environment variable the API token of the service is read from
+    fn token_env_var(&self) -> &'static str {
+        match self {
+            PushService::Readwise => "PAPERONI_READWISE_TOKEN",
+            PushService::Omnivore => "PAPERONI_OMNIVORE_API_KEY",
+        }
+    }
+}
+
+/// The service articles are pushed to and the token used to authenticate with it
+#[derive(Clone)]
+pub struct PushOptions {
+    pub service: PushService,
+    pub token: String,
+}
+
+impl PushOptions {
+    /// Reads the API token of the service from its environment variable
+    fn from_env(
+        service: PushService,
+        get_var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, Error> {
+        let env_var = service.token_env_var();
+        match get_var(env_var).filter(|token| !token.trim().is_empty()) {
+            Some(token) => Ok(Self {
+                service,
+                token: token.trim().to_owned(),
+            }),
+            None => Err(Error::MissingPushToken(service.name(), env_var)),
+        }
+    }
+}
+
+// The token is left out so that it does not end up in logs
+impl std::fmt::Debug for PushOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PushOptions")
+            .field("service", &self.service)
+            .finish()
+    }
+}
+
+const LIBRETRANSLATE_URL: &str = "https://libretranslate.com";
+const LIBRETRANSLATE_KEY_VAR: &str = "PAPERONI_LIBRETRANSLATE_API_KEY";
+const DEEPL_KEY_VAR: &str = "PAPERONI_DEEPL_API_KEY";
+
+/// The backend articles are translated with by --translate
+#[derive(Clone, PartialEq)]
+pub enum Translator {
+    LibreTranslate {
+        url: String,
+        api_key: Option<String>,
+    },
+    DeepL {
+        api_key: String,
+    },
+    /// A shell command reading one paragraph per line and writing its translation
+    Command(String),
+}
+
+impl Translator {
+    /// Reads the API key of DeepL from its environment variable
+    fn deepl_from_env(get_var: impl Fn(&str) -> Option<String>) -> Result<Self, Error> {
+        match get_var(DEEPL_KEY_VAR).filter(|key| !key.trim().is_empty()) {
+            Some(api_key) => Ok(Translator::DeepL {
+                api_key: api_key.trim().to_owned(),
+            }),
+            None => Err(Error::MissingTranslatorKey("DeepL", DEEPL_KEY_VAR)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Translator::LibreTranslate { .. } => "LibreTranslate",
+            Translator::DeepL { .. } => "DeepL",
+            Translator::Command(_) => "the translate command",
+        }
+    }
+}
+
+// The API keys are left out so that they do not end up in logs
+impl std::fmt::Debug for Translator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Translator::LibreTranslate { url, .. } => {
+                f.debug_struct("LibreTranslate").field("url", url).finish()
+            }
+            Translator::DeepL { .. } => f.debug_struct("DeepL").finish(),
+            Translator::Command(command) => f.debug_tuple("Command").field(command).finish(),
+        }
+    }
+}
+
+/// Options of --translate
+#[derive(Clone, Debug, PartialEq)]
+pub struct TranslateOptions {
+    /// The language code the articles are translated to e.g `de` or `pt-BR`
+    pub target_lang: String,
+    pub translator: Translator,
+}
+
+/// Options of the debug-extract subcommand which reproduces crashes of the extractor
+#[derive(Clone, Debug)]
+pub struct DebugExtractOptions {
+    /// Path of an HTML file or of a directory of files
+    pub input: String,
+    /// Seconds an input can take before it is reported as stuck
+    pub timeout: u64,
+}
+
+/// Options of the selftest subcommand which compares extracted articles with expected ones
+#[derive(Clone, Debug)]
+pub struct SelftestOptions {
+    /// Directory of the test pages
+    pub corpus: String,
+    /// The similarity to the expected article a page needs to pass
+    pub min_score: f64,
+}
+
+/// The number of top comments kept from each level of a discussion thread
+const DEFAULT_DISCUSSION_COMMENTS: usize = 20;
+/// How many levels of replies of a discussion thread are kept, counting the top comments
+const DEFAULT_DISCUSSION_DEPTH: usize = 2;
+
+/// Options for the comments of Hacker News and Reddit threads exported with their articles
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscussionOptions {
+    /// The number of comments kept at each level of the thread
+    pub max_comments: usize,
+    /// The levels of comments kept where 1 only keeps the top-level comments
+    pub depth: usize,
+    /// The lowest score of kept comments. Only Reddit gives the scores of comments
+    pub min_score: Option<i64>,
+}
+
+/// Options for verifying the TLS certificates of sites such as intranets with self-signed
+/// certificates
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TlsOptions {
+    /// Accepts invalid certificates and host names
+    pub is_insecure: bool,
+    /// Domains whose invalid certificates and host names are accepted along with their
+    /// subdomains
+    pub insecure_hosts: Vec<String>,
+    /// A PEM file of the certificate authorities that are trusted. The curl backend trusts
+    /// them instead of the ones of the system while reqwest trusts them on top of its own.
+    pub ca_cert: Option<PathBuf>,
+}
+
+/// The HTTP clients requests can be sent with. Each one is built with its own feature.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HttpBackend {
+    /// libcurl through isahc
+    Curl,
+    /// The pure Rust HTTP/1.1 client of async-h1
+    H1,
+    /// reqwest on hyper, which supports HTTP/2 without a C library
+    Reqwest,
+}
+
+impl HttpBackend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HttpBackend::Curl => "curl",
+            HttpBackend::H1 => "h1",
+            HttpBackend::Reqwest => "reqwest",
+        }
+    }
+
+    /// The feature the backend is built with when Paperoni was built without it
+    fn missing_feature(&self) -> Option<&'static str> {
+        match self {
+            HttpBackend::Curl if !cfg!(feature = "curl-client") => Some("curl-client"),
+            HttpBackend::H1 if !cfg!(feature = "h1-client") => Some("h1-client"),
+            HttpBackend::Reqwest if !cfg!(feature = "reqwest-client") => Some("reqwest-client"),
+            _ => None,
+        }
+    }
+}
+
+impl Default for HttpBackend {
+    /// The first backend that is built in the order curl, reqwest and h1
+    fn default() -> Self {
+        if cfg!(feature = "curl-client") {
+            HttpBackend::Curl
+        } else if cfg!(feature = "reqwest-client") {
+            HttpBackend::Reqwest
+        } else {
+            HttpBackend::H1
+        }
+    }
+}
+
+/// An address connected to for a host and port like curl's --resolve
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolveOverride {
+    pub host: String,
+    pub port: u16,
+    pub address: IpAddr,
+}
+
+/// Where the metrics of the long-running subcommands are reported
+#[derive(Clone, Debug)]
+pub struct MetricsOptions {
+    pub counters: Arc<Metrics>,
+    /// Address of an HTTP endpoint serving the metrics
+    pub address: Option<String>,
+    /// Path of a stats file the metrics are periodically written to
+    pub file: Option<String>,
+}
+
+/// Options of the watch subcommand which downloads the links of files dropped in a directory
+#[derive(Clone, Debug)]
+pub struct WatchOptions {
+    /// Directory the files of links are dropped in
+    pub dir: String,
+    /// Seconds between checks of the directory
+    pub interval: u64,
+    /// Processes the files already in the directory once instead of watching it
+    pub is_once: bool,
+    /// The most links of queue files downloaded at each check of the directory
+    pub queue_batch: usize,
+}
+
+/// Options of the search subcommand which searches the articles of a site built with
+/// `--export site`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchOptions {
+    /// Words every matching article contains
+    pub terms: Vec<String>,
+    /// Directory of the site
+    pub site_dir: String,
+    /// The most results listed
+    pub limit: usize,
+    /// Numbers of the listed results exported to the EPUB. All of them are exported when empty.
+    pub picks: Vec<usize>,
+    /// Name of the EPUB the results are exported to
+    pub epub: Option<String>,
+}
+
+/// The commands of the cache subcommand
+#[derive(Clone, Debug, PartialEq)]
+pub enum CacheCommand {
+    /// Removes every image from the image cache
+    Clean,
+}
+
+#[derive(Clone, Debug)]
+pub enum CSSConfig {
+    All,
+    NoHeaders,
+    None,
+}
+
+#[derive(Clone, Debug)]
+pub enum ExportType {
+    HTML,
+    EPUB,
+    SSML,
+    ODT,
+    Markdown,
+    /// A static reading site of HTML pages with an index
+    Site,
+}
+
+/// How --print-links writes the targets of links
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PrintLinks {
+    /// Numbered notes listed at the end of each article
+    Footnotes,
+    /// The url in brackets after each link
+    Inline,
+}
+
+/// What --overwrite does when the file of an export already exists
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverwritePolicy {
+    /// Keeps the existing file without writing the export
+    Skip,
+    Replace,
+    /// Writes the export to a name with a numbered suffix that is not taken
+    Rename,
+}
+
+impl Default for OverwritePolicy {
+    fn default() -> Self {
+        OverwritePolicy::Replace
+    }
+}
+
+/// The dialect of Markdown written by Markdown exports
+#[derive(Clone, Debug, PartialEq)]
+pub enum MarkdownFlavor {
+    CommonMark,
+    /// Notes for Obsidian vaults with YAML frontmatter and images in an attachments folder
+    Obsidian,
+}
+
+impl ExportType {
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            ExportType::HTML => "html",
+            ExportType::EPUB => "epub",
+            ExportType::SSML => "ssml",
+            ExportType::ODT => "odt",
+            ExportType::Markdown => "md",
+            ExportType::Site => "html",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clap_config_errors() {
+        let yaml_config = load_yaml!("cli_config.yml");
+        let app = App::from_yaml(yaml_config);
+
+        // It returns Ok when only a url is passed
+        let result = app
+            .clone()
+            .get_matches_from_safe(vec!["paperoni", "http://example.org"]);
+        assert!(result.is_ok());
+
+        // It returns an error when no args are passed
+        let result = app.clone().get_matches_from_safe(vec!["paperoni"]);
+        assert!(result.is_err());
+        assert_eq!(
+            clap::ErrorKind::MissingArgumentOrSubcommand,
+            result.unwrap_err().kind
+        );
+
+        // It returns an error when both output-dir and merge are used
         let result = app.clone().get_matches_from_safe(vec![
             "paperoni",
             "http://example.org",
@@ -331,5 +2261,1534 @@ mod test {
         assert!(AppConfig::try_from(matches).is_ok());
 
         // It returns an error when inline-images is used when exporting to epub
+
+        // It returns an error when title is used with multiple unmerged articles
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "http://example.com",
+            "--title",
+            "Custom Title",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        assert!(app_config.is_err());
+        assert_eq!(
+            Error::MetadataOverrideMultipleArticles,
+            app_config.unwrap_err()
+        );
+        // It returns an Ok when title and author are used with a single article
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--title",
+            "Custom Title",
+            "--author",
+            "Jane Doe",
+        ]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert_eq!(Some("Custom Title"), app_config.title.as_deref());
+        assert_eq!(Some("Jane Doe"), app_config.author.as_deref());
+
+        // It returns an error when a series is followed from several urls
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org/part-1",
+            "http://example.com/part-1",
+            "--follow-series",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        assert!(app_config.is_err());
+        assert_eq!(Error::SeriesMultipleUrls, app_config.unwrap_err());
+
+        // It returns an error when crawl-pattern is used without crawling
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org/author/jane",
+            "--crawl-pattern",
+            "/posts/",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        assert!(app_config.is_err());
+        assert_eq!(Error::CrawlPatternWithoutCrawling, app_config.unwrap_err());
+        // It returns an Ok when crawl-pattern is used with an archive
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org/author/jane",
+            "--archive",
+            "--limit",
+            "20",
+            "--crawl-pattern",
+            "/posts/",
+        ]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert_eq!(Some(ArchiveOptions { limit: Some(20) }), app_config.archive);
+
+        // It returns an error when title-page is used when exporting to HTML
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--merge",
+            "foo",
+            "--export",
+            "html",
+            "--title-page",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        assert!(app_config.is_err());
+        assert_eq!(Error::WrongExportTitlePage, app_config.unwrap_err());
+
+        // It returns an error when cover-collage is used when exporting to HTML
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--merge",
+            "foo",
+            "--export",
+            "html",
+            "--cover-collage",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        assert_eq!(Error::WrongExportCoverCollage, app_config.unwrap_err());
+
+        // It only embeds CJK fonts that exist in epubs
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--export",
+            "html",
+            "--cjk-font",
+            "Cargo.toml",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        assert_eq!(Error::WrongExportCjkFont, app_config.unwrap_err());
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--cjk-font",
+            "Cargo.toml",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        assert!(matches!(
+            app_config.unwrap_err(),
+            Error::InvalidArgValue {
+                arg: "cjk-font",
+                ..
+            }
+        ));
+
+        // It returns an error when reader-ui is used when exporting to EPUB
+        let matches =
+            app.clone()
+                .get_matches_from(vec!["paperoni", "http://example.org", "--reader-ui"]);
+        let app_config = AppConfig::try_from(matches);
+        assert_eq!(Error::WrongExportReaderUi, app_config.unwrap_err());
+
+        // It returns an error when emit-feed is used when exporting to EPUB
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--emit-feed",
+            "feed.xml",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        assert_eq!(Error::WrongExportEmitFeed, app_config.unwrap_err());
+
+        // It returns an error when paginate is used when exporting to EPUB
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--merge",
+            "foo",
+            "--paginate",
+            "10",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        assert_eq!(Error::WrongExportPaginate, app_config.unwrap_err());
+
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--merge",
+            "foo.html",
+            "--export",
+            "html",
+            "--paginate",
+            "10",
+        ]);
+        assert_eq!(
+            NonZeroUsize::new(10),
+            AppConfig::try_from(matches).unwrap().paginate
+        );
+
+        // It returns an error when a site export is merged
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--merge",
+            "foo",
+            "--export",
+            "site",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        assert_eq!(Error::MergedSite, app_config.unwrap_err());
+
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--export",
+            "site",
+        ]);
+        assert!(matches!(
+            AppConfig::try_from(matches).unwrap().export_type,
+            ExportType::Site
+        ));
+
+        // It returns an error when cover is used when exporting to HTML
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--export",
+            "html",
+            "--cover",
+            "auto",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        assert!(app_config.is_err());
+        assert_eq!(Error::WrongExportCover, app_config.unwrap_err());
+
+        // It returns an error when the epub compression is set when exporting to HTML
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--export",
+            "html",
+            "--store-images",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        assert_eq!(Error::WrongExportEpubCompression, app_config.unwrap_err());
+
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--epub-compression",
+            "10",
+        ]);
+        assert!(matches!(
+            AppConfig::try_from(matches),
+            Err(Error::InvalidArgValue {
+                arg: "epub-compression",
+                ..
+            })
+        ));
+
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--epub-compression",
+            "0",
+            "--store-images",
+        ]);
+        assert_eq!(
+            EpubCompression {
+                level: Some(0),
+                is_storing_images: true,
+            },
+            AppConfig::try_from(matches).unwrap().epub_compression
+        );
+
+        // It returns an error when md-flavor is used when exporting to EPUB
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--md-flavor",
+            "obsidian",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        assert!(app_config.is_err());
+        assert_eq!(Error::WrongExportMdFlavor, app_config.unwrap_err());
+
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--export",
+            "md",
+            "--md-flavor",
+            "obsidian",
+        ]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert!(matches!(app_config.export_type, ExportType::Markdown));
+        assert_eq!(MarkdownFlavor::Obsidian, app_config.md_flavor);
+
+        // It reads the hosts and url patterns of skipped images
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--skip-images-from",
+            "gravatar.com",
+            "--skip-images-from",
+            "*/pixel.gif*",
+        ]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert!(matches!(
+            app_config.skipped_image_patterns.as_slice(),
+            [ImagePattern::Domain(domain), ImagePattern::Url(_)] if domain == "gravatar.com"
+        ));
+
+        // It returns an error when a selector to remove is invalid
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--remove-selector",
+            ".newsletter",
+            "--remove-selector",
+            "div[",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        assert!(app_config.is_err());
+        assert_eq!(
+            Error::InvalidRemoveSelector("div[".into()),
+            app_config.unwrap_err()
+        );
+
+        // It reads the options of the serve subcommand without any urls
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "serve",
+            "--port",
+            "9000",
+            "--max-jobs",
+            "4",
+        ]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert_eq!(Some("127.0.0.1:9000".to_owned()), app_config.server_address);
+        assert_eq!(4, app_config.max_server_jobs);
+        let matches = app
+            .clone()
+            .get_matches_from(vec!["paperoni", "serve", "--max-jobs", "0"]);
+        assert!(matches!(
+            AppConfig::try_from(matches),
+            Err(Error::InvalidArgValue {
+                arg: "max-jobs",
+                ..
+            })
+        ));
+
+        // It reads the options of the bench subcommand without any urls
+        let matches =
+            app.clone()
+                .get_matches_from(vec!["paperoni", "bench", "page.html", "-n", "5"]);
+        let bench_options = AppConfig::try_from(matches).unwrap().bench.unwrap();
+        assert_eq!("page.html", bench_options.input);
+        assert_eq!(5, bench_options.iterations);
+
+        // It reads the options of the debug-extract subcommand without any urls
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "debug-extract",
+            "fuzz/corpus",
+            "--timeout",
+            "3",
+        ]);
+        let debug_options = AppConfig::try_from(matches).unwrap().debug_extract.unwrap();
+        assert_eq!("fuzz/corpus", debug_options.input);
+        assert_eq!(3, debug_options.timeout);
+
+        // It reads the options of the watch subcommand without any urls
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "watch",
+            "inbox",
+            "--interval",
+            "30",
+            "--once",
+        ]);
+        let watch_options = AppConfig::try_from(matches).unwrap().watch.unwrap();
+        assert_eq!("inbox", watch_options.dir);
+        assert_eq!(30, watch_options.interval);
+        assert!(watch_options.is_once);
+
+        // It only keeps metrics when watching if they are reported
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "watch",
+            "inbox",
+            "--metrics-file",
+            "paperoni.prom",
+        ]);
+        let metrics_options = AppConfig::try_from(matches).unwrap().metrics.unwrap();
+        assert_eq!(Some("paperoni.prom".to_owned()), metrics_options.file);
+        assert_eq!(None, metrics_options.address);
+        let matches = app
+            .clone()
+            .get_matches_from(vec!["paperoni", "watch", "inbox"]);
+        assert!(AppConfig::try_from(matches).unwrap().metrics.is_none());
+
+        // It reads the cache subcommand without any urls
+        let matches = app
+            .clone()
+            .get_matches_from(vec!["paperoni", "cache", "clean"]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert_eq!(Some(CacheCommand::Clean), app_config.cache_command);
+
+        // It reads the search subcommand without any urls
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "search",
+            "pepperoni",
+            "salami",
+            "--dir",
+            "archive",
+            "--epub",
+            "cured",
+            "--pick",
+            "1, 3",
+        ]);
+        assert_eq!(
+            Some(SearchOptions {
+                terms: vec!["pepperoni".to_owned(), "salami".to_owned()],
+                site_dir: "archive".to_owned(),
+                limit: 20,
+                picks: vec![1, 3],
+                epub: Some("cured".to_owned()),
+            }),
+            AppConfig::try_from(matches).unwrap().search
+        );
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "search",
+            "pepperoni",
+            "--epub",
+            "cured",
+            "--pick",
+            "0",
+        ]);
+        assert!(matches!(
+            AppConfig::try_from(matches),
+            Err(Error::InvalidArgValue { arg: "pick", .. })
+        ));
+
+        // It reads the options of discussion threads with their defaults
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "https://news.ycombinator.com/item?id=1",
+            "--discussion-depth",
+            "1",
+            "--discussion-min-score",
+            "10",
+        ]);
+        assert_eq!(
+            DiscussionOptions {
+                max_comments: DEFAULT_DISCUSSION_COMMENTS,
+                depth: 1,
+                min_score: Some(10),
+            },
+            AppConfig::try_from(matches).unwrap().discussion
+        );
+
+        // It only turns off certificate verification for the domains of --insecure-host
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--insecure-host",
+            "Intranet.example.com",
+            "--insecure-host",
+            "staging.example.org",
+        ]);
+        assert_eq!(
+            TlsOptions {
+                is_insecure: false,
+                insecure_hosts: vec!["intranet.example.com".into(), "staging.example.org".into()],
+                ca_cert: None,
+            },
+            AppConfig::try_from(matches).unwrap().tls
+        );
+
+        // It rejects a CA certificate that does not exist
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "https://intranet.example.com",
+            "--ca-cert",
+            "/nonexistent/ca.pem",
+        ]);
+        assert_eq!(
+            Error::InvalidArgValue {
+                arg: "ca-cert",
+                value: "/nonexistent/ca.pem".into(),
+                reason: "the file does not exist".into(),
+            },
+            AppConfig::try_from(matches).unwrap_err()
+        );
+
+        // It reads address overrides with IPv4 and IPv6 addresses
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "https://staging.example.com",
+            "--resolve",
+            "Staging.example.com:443:10.0.0.5",
+            "--resolve",
+            "staging.example.com:80:[2001:db8::1]",
+        ]);
+        assert_eq!(
+            vec![
+                ResolveOverride {
+                    host: "staging.example.com".into(),
+                    port: 443,
+                    address: "10.0.0.5".parse().unwrap(),
+                },
+                ResolveOverride {
+                    host: "staging.example.com".into(),
+                    port: 80,
+                    address: "2001:db8::1".parse().unwrap(),
+                }
+            ],
+            AppConfig::try_from(matches).unwrap().resolve_overrides
+        );
+        assert!(parse_resolve_override("staging.example.com:443").is_err());
+        assert!(parse_resolve_override("staging.example.com:https:10.0.0.5").is_err());
+        assert_eq!(
+            "[2001:db8::53]:5353".parse::<SocketAddr>().unwrap(),
+            parse_dns_server("[2001:db8::53]:5353").unwrap()
+        );
+        assert_eq!(
+            "1.1.1.1:53".parse::<SocketAddr>().unwrap(),
+            parse_dns_server("1.1.1.1").unwrap()
+        );
+
+        // It only sends requests with the HTTP backends Paperoni is built with
+        let matches = app
+            .clone()
+            .get_matches_from(vec!["paperoni", "http://example.org"]);
+        assert_eq!(
+            HttpBackend::default(),
+            AppConfig::try_from(matches).unwrap().http_backend
+        );
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--http-backend",
+            "reqwest",
+        ]);
+        let result = AppConfig::try_from(matches);
+        if cfg!(feature = "reqwest-client") {
+            assert_eq!(HttpBackend::Reqwest, result.unwrap().http_backend);
+        } else {
+            assert_eq!(
+                Error::HttpBackendUnavailable("reqwest", "reqwest-client"),
+                result.unwrap_err()
+            );
+        }
+        if cfg!(feature = "h1-client") {
+            let matches = app.clone().get_matches_from(vec![
+                "paperoni",
+                "http://example.org",
+                "--http-backend",
+                "h1",
+                "--insecure-host",
+                "example.org",
+            ]);
+            assert_eq!(
+                Error::TlsOptionsUnavailable,
+                AppConfig::try_from(matches).unwrap_err()
+            );
+        }
+
+        // It reads the size budget in megabytes
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--size-budget",
+            "1.5",
+        ]);
+        assert_eq!(
+            Some(1_572_864),
+            AppConfig::try_from(matches).unwrap().size_budget
+        );
+
+        // It reads the thresholds of images removed for being too small
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--min-image-size",
+            "2",
+            "--min-image-dimension",
+            "50",
+        ]);
+        assert_eq!(
+            ImageSizeFilter {
+                min_bytes: 2048,
+                min_dimension: 50
+            },
+            AppConfig::try_from(matches).unwrap().image_size_filter
+        );
+
+        // It requires the grayscale feature to convert images for e-readers
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--optimize-for",
+            "ereader",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        if cfg!(feature = "grayscale") {
+            assert!(app_config.unwrap().is_grayscale);
+        } else {
+            assert_eq!(Error::GrayscaleUnavailable, app_config.unwrap_err());
+        }
+
+        // It only adds QR codes of the sources to epub and html exports
+        let matches =
+            app.clone()
+                .get_matches_from(vec!["paperoni", "http://example.org", "--source-qr"]);
+        let app_config = AppConfig::try_from(matches);
+        if cfg!(feature = "source-qr") {
+            assert!(app_config.unwrap().has_source_qr);
+        } else {
+            assert_eq!(Error::SourceQrUnavailable, app_config.unwrap_err());
+        }
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--source-qr",
+            "--export",
+            "md",
+        ]);
+        let app_config = AppConfig::try_from(matches);
+        assert_eq!(Error::WrongExportSourceQr, app_config.unwrap_err());
+
+        // It disables the image cache with no-image-cache
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--no-image-cache",
+        ]);
+        assert!(AppConfig::try_from(matches).unwrap().image_cache.is_none());
+
+        // It only accepts similarity scores between 0 and 1 for selftest
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "selftest",
+            "test-pages",
+            "--min-score",
+            "0.75",
+        ]);
+        let selftest_options = AppConfig::try_from(matches).unwrap().selftest.unwrap();
+        assert_eq!("test-pages", selftest_options.corpus);
+        assert!((selftest_options.min_score - 0.75).abs() < f64::EPSILON);
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "selftest",
+            "test-pages",
+            "--min-score",
+            "75",
+        ]);
+        assert!(matches!(
+            AppConfig::try_from(matches),
+            Err(Error::InvalidArgValue {
+                arg: "min-score",
+                ..
+            })
+        ));
+
+        // It auto-tunes the connections to each host when max-conn is auto
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--max-conn",
+            "auto",
+        ]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert!(app_config.conn_limiter.is_some());
+        assert_eq!(ADAPTIVE_MAX_CONN, app_config.max_conn);
+
+        // It returns an error naming the argument when a value cannot be parsed
+        let matches =
+            app.clone()
+                .get_matches_from(vec!["paperoni", "http://example.org", "--max-conn", "0"]);
+        assert_eq!(
+            Error::InvalidArgValue {
+                arg: "max-conn",
+                value: "0".into(),
+                reason: "number would be zero for non-zero type".into(),
+            },
+            AppConfig::try_from(matches).unwrap_err()
+        );
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--max-redirects",
+            "lots",
+        ]);
+        assert_eq!(
+            Error::InvalidArgValue {
+                arg: "max-redirects",
+                value: "lots".into(),
+                reason: "invalid digit found in string".into(),
+            },
+            AppConfig::try_from(matches).unwrap_err()
+        );
+        // It filters articles by their publication date
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--published-after",
+            "2023-05-01",
+            "--exclude-undated",
+        ]);
+        let date_filter = AppConfig::try_from(matches).unwrap().date_filter.unwrap();
+        assert_eq!(NaiveDate::from_ymd_opt(2023, 5, 1), date_filter.after);
+        assert_eq!(None, date_filter.before);
+        assert!(!date_filter.include_undated);
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--published-after",
+            "2023-05-01",
+            "--published-before",
+            "2023-04-01",
+        ]);
+        assert!(matches!(
+            AppConfig::try_from(matches),
+            Err(Error::InvalidArgValue {
+                arg: "published-before",
+                ..
+            })
+        ));
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--published-before",
+            "last week",
+        ]);
+        assert!(matches!(
+            AppConfig::try_from(matches),
+            Err(Error::InvalidArgValue {
+                arg: "published-before",
+                ..
+            })
+        ));
+
+        // It sets the limits on extracting each article
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--extract-timeout",
+            "5",
+            "--max-nodes",
+            "1000",
+            "--min-chars",
+            "200",
+            "--min-words",
+            "150",
+        ]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert_eq!(
+            ExtractionBudget {
+                max_nodes: 1000,
+                max_duration: Duration::from_secs(5),
+                min_chars: 200,
+                min_words: 150,
+            },
+            app_config.extraction_budget
+        );
+        let matches = app
+            .clone()
+            .get_matches_from(vec!["paperoni", "http://example.org"]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert_eq!(ExtractionBudget::default(), app_config.extraction_budget);
+        assert_eq!(None, app_config.article_timeout);
+
+        // It bounds the time spent on each article
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--article-timeout",
+            "60",
+        ]);
+        assert_eq!(
+            Some(Duration::from_secs(60)),
+            AppConfig::try_from(matches).unwrap().article_timeout
+        );
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--article-timeout",
+            "0",
+        ]);
+        assert!(matches!(
+            AppConfig::try_from(matches),
+            Err(Error::InvalidArgValue {
+                arg: "article-timeout",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_min_confidence() {
+        let yaml_config = load_yaml!("cli_config.yml");
+        let app = App::from_yaml(yaml_config);
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "https://example.com/article",
+            "--min-confidence",
+            "0.4",
+        ]);
+        assert_eq!(
+            Some(0.4),
+            AppConfig::try_from(matches).unwrap().min_confidence
+        );
+        let matches = app.get_matches_from(vec![
+            "paperoni",
+            "https://example.com/article",
+            "--min-confidence",
+            "40",
+        ]);
+        assert!(matches!(
+            AppConfig::try_from(matches),
+            Err(Error::InvalidArgValue {
+                arg: "min-confidence",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_post_process() {
+        let yaml_config = load_yaml!("cli_config.yml");
+        let app = App::from_yaml(yaml_config);
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "https://example.com/article",
+            "--post-process",
+            "ebook-convert {file} {file}.mobi",
+        ]);
+        assert_eq!(
+            Some("ebook-convert {file} {file}.mobi".to_owned()),
+            AppConfig::try_from(matches).unwrap().post_process
+        );
+        let matches = app.get_matches_from(vec![
+            "paperoni",
+            "https://example.com/article",
+            "--post-process",
+            "ebook-convert",
+        ]);
+        assert!(matches!(
+            AppConfig::try_from(matches),
+            Err(Error::InvalidArgValue {
+                arg: "post-process",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_locale() {
+        let yaml_config = load_yaml!("cli_config.yml");
+        let app = App::from_yaml(yaml_config);
+        let locale = |code: &str| {
+            let matches = app.clone().get_matches_from(vec![
+                "paperoni",
+                "https://example.com/article",
+                "--locale",
+                code,
+            ]);
+            AppConfig::try_from(matches).map(|app_config| app_config.locale)
+        };
+        assert_eq!(Ok(Locale::De), locale("de-AT"));
+        assert_eq!(Ok(Locale::Fr), locale("fr_FR.UTF-8"));
+        assert!(matches!(
+            locale("xx"),
+            Err(Error::InvalidArgValue { arg: "locale", .. })
+        ));
+        let matches = app.get_matches_from(vec!["paperoni", "https://example.com/article"]);
+        assert_eq!(Locale::En, AppConfig::try_from(matches).unwrap().locale);
+    }
+
+    #[test]
+    fn test_debug_extraction() {
+        let yaml_config = load_yaml!("cli_config.yml");
+        let app = App::from_yaml(yaml_config);
+        let debug_dir = std::env::temp_dir().join("paperoni-test-debug-extraction");
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "https://example.com/article",
+            "--debug-extraction",
+            debug_dir.to_str().unwrap(),
+        ]);
+        assert_eq!(
+            Some(debug_dir),
+            AppConfig::try_from(matches).unwrap().debug_extraction_dir
+        );
+        let matches = app.get_matches_from(vec![
+            "paperoni",
+            "https://example.com/article",
+            "--debug-extraction",
+            "Cargo.toml",
+        ]);
+        assert!(matches!(
+            AppConfig::try_from(matches),
+            Err(Error::InvalidArgValue {
+                arg: "debug-extraction",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_progress() {
+        let yaml_config = load_yaml!("cli_config.yml");
+        let app = App::from_yaml(yaml_config);
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "https://example.com/article",
+            "--progress",
+            "json",
+        ]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert!(app_config.progress.is_some());
+        assert!(app_config.can_disable_progress_bar);
+        let matches = app
+            .clone()
+            .get_matches_from(vec!["paperoni", "https://example.com/article"]);
+        assert!(AppConfig::try_from(matches).unwrap().progress.is_none());
+        // Negative descriptors are never open
+        let matches = app.get_matches_from(vec![
+            "paperoni",
+            "https://example.com/article",
+            "--progress-fd=-1",
+        ]);
+        assert!(matches!(
+            AppConfig::try_from(matches),
+            Err(Error::InvalidArgValue {
+                arg: "progress-fd",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_print_links() {
+        let yaml_config = load_yaml!("cli_config.yml");
+        let app = App::from_yaml(yaml_config);
+        let print_links = |mode: &str| {
+            let matches = app.clone().get_matches_from(vec![
+                "paperoni",
+                "https://example.com/article",
+                "--print-links",
+                mode,
+            ]);
+            AppConfig::try_from(matches).unwrap().print_links
+        };
+        assert_eq!(Some(PrintLinks::Footnotes), print_links("footnotes"));
+        assert_eq!(Some(PrintLinks::Inline), print_links("inline"));
+        assert_eq!(None, print_links("none"));
+    }
+
+    #[test]
+    fn test_overwrite() {
+        let yaml_config = load_yaml!("cli_config.yml");
+        let app = App::from_yaml(yaml_config);
+        let dir = std::env::temp_dir().join(format!("paperoni-test-merge-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let merged_path = dir.join("book.epub");
+        fs::write(&merged_path, "existing").unwrap();
+        let merged_name = merged_path.to_str().unwrap();
+        let init_config = |policy: Option<&str>| {
+            let mut args = vec![
+                "paperoni",
+                "https://example.com/article",
+                "--merge",
+                merged_name,
+            ];
+            if let Some(policy) = policy {
+                args.extend_from_slice(&["--overwrite", policy]);
+            }
+            AppConfig::try_from(app.clone().get_matches_from(args))
+        };
+
+        let app_config = init_config(None).unwrap();
+        assert_eq!(OverwritePolicy::Replace, app_config.overwrite);
+        assert_eq!(Some(merged_name), app_config.merged.as_deref());
+        // The file is only replaced once the export is written
+        assert_eq!("existing", fs::read_to_string(&merged_path).unwrap());
+        assert!(matches!(
+            init_config(Some("skip")),
+            Err(Error::MergedFileExists(name)) if name == merged_name
+        ));
+        let app_config = init_config(Some("rename")).unwrap();
+        assert_eq!(OverwritePolicy::Rename, app_config.overwrite);
+        let renamed_path = dir.join("book_1.epub");
+        assert_eq!(renamed_path.to_str(), app_config.merged.as_deref());
+        assert!(!renamed_path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_subcommands() {
+        let yaml_config = load_yaml!("cli_config.yml");
+        let app = App::from_yaml(yaml_config);
+        let dir =
+            std::env::temp_dir().join(format!("paperoni-test-subcommands-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "get",
+            "https://example.com/a",
+            "https://example.com/b",
+        ]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert_eq!(
+            vec!["https://example.com/a", "https://example.com/b"],
+            app_config.urls
+        );
+        assert_eq!(None, app_config.merged);
+
+        let merged_path = dir.join("digest");
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "--export",
+            "html",
+            "merge",
+            "--inline-toc",
+            merged_path.to_str().unwrap(),
+            "https://example.com/a",
+        ]);
+        // The inline ToC is only added to epubs, which shows it was read after the subcommand
+        assert!(matches!(
+            AppConfig::try_from(matches),
+            Err(Error::WrongExportInliningToC)
+        ));
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "merge",
+            merged_path.to_str().unwrap(),
+            "https://example.com/a",
+        ]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert_eq!(vec!["https://example.com/a"], app_config.urls);
+        assert_eq!(
+            dir.join("digest.epub").to_str(),
+            app_config.merged.as_deref()
+        );
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "--merge",
+            "other.epub",
+            "merge",
+            merged_path.to_str().unwrap(),
+            "https://example.com/a",
+        ]);
+        assert!(matches!(
+            AppConfig::try_from(matches),
+            Err(Error::MergeNameConflict)
+        ));
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "--cover",
+            "auto",
+            "merge",
+            merged_path.to_str().unwrap(),
+            "https://example.com/a",
+        ]);
+        assert!(matches!(
+            AppConfig::try_from(matches),
+            Err(Error::MergedCover)
+        ));
+
+        let links_path = dir.join("links.txt");
+        fs::write(
+            &links_path,
+            "https://example.com/a\nhttps://example.com/b | title=\"B\"\n",
+        )
+        .unwrap();
+        let matches =
+            app.clone()
+                .get_matches_from(vec!["paperoni", "import", links_path.to_str().unwrap()]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert_eq!(
+            vec!["https://example.com/a", "https://example.com/b"],
+            app_config.urls
+        );
+        assert_eq!(1, app_config.article_options.len());
+
+        let matches = app
+            .clone()
+            .get_matches_from(vec!["paperoni", "server", "--port", "9000"]);
+        assert!(matches.subcommand_matches("serve").is_some());
+        assert!(AppConfig::try_from(matches).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_size_and_duration() {
+        assert_eq!(Ok(2048), parse_size("2", KILOBYTE));
+        assert_eq!(Ok(1_572_864), parse_size("1.5M", KILOBYTE));
+        assert_eq!(Ok(1_572_864), parse_size("1.5", MEGABYTE));
+        assert_eq!(Ok(512 * 1024), parse_size("512kb", MEGABYTE));
+        assert_eq!(Ok(1 << 30), parse_size("1 GiB", MEGABYTE));
+        assert_eq!(Ok(100), parse_size("100B", MEGABYTE));
+        assert!(parse_size("M", MEGABYTE).is_err());
+        assert!(parse_size("10T", MEGABYTE).is_err());
+
+        assert_eq!(Ok(Duration::from_secs(90)), parse_duration("90"));
+        assert_eq!(Ok(Duration::from_secs(90)), parse_duration("90s"));
+        assert_eq!(Ok(Duration::from_secs(300)), parse_duration("5m"));
+        assert_eq!(Ok(Duration::from_secs(7200)), parse_duration("2h"));
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("1.5m").is_err());
+        assert!(parse_duration("10d").is_err());
+
+        let yaml_config = load_yaml!("cli_config.yml");
+        let app = App::from_yaml(yaml_config);
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--size-budget",
+            "20M",
+            "--extract-timeout",
+            "2m",
+        ]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert_eq!(Some(20 * MEGABYTE), app_config.size_budget);
+        assert_eq!(
+            Duration::from_secs(120),
+            app_config.extraction_budget.max_duration
+        );
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--min-image-size",
+            "2X",
+        ]);
+        assert!(matches!(
+            AppConfig::try_from(matches),
+            Err(Error::InvalidArgValue {
+                arg: "min-image-size",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_sanitizer() {
+        let yaml_config = load_yaml!("cli_config.yml");
+        let app = App::from_yaml(yaml_config);
+        let matches = app
+            .clone()
+            .get_matches_from(vec!["paperoni", "https://example.com/article"]);
+        assert!(AppConfig::try_from(matches).unwrap().sanitizer.is_none());
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "https://example.com/article",
+            "--sanitize",
+            "--sanitize-allow-tag",
+            "video",
+        ]);
+        assert!(AppConfig::try_from(matches).unwrap().sanitizer.is_some());
+        let matches = app.get_matches_from(vec![
+            "paperoni",
+            "https://example.com/article",
+            "--sanitize",
+            "--sanitize-allow-attr",
+            "onClick",
+        ]);
+        assert_eq!(
+            Err(Error::InvalidArgValue {
+                arg: "sanitize-allow-attr",
+                value: "onclick".to_owned(),
+                reason: "scripts and event handlers are always removed".to_owned(),
+            }),
+            AppConfig::try_from(matches).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_date_filter() {
+        let date = |day| NaiveDate::from_ymd_opt(2023, 5, day);
+        let date_filter = DateFilter {
+            after: date(1),
+            before: date(10),
+            include_undated: true,
+        };
+        assert!(date_filter.allows(date(1)));
+        assert!(date_filter.allows(date(9)));
+        assert!(!date_filter.allows(date(10)));
+        assert!(!date_filter.allows(NaiveDate::from_ymd_opt(2023, 4, 30)));
+        assert!(date_filter.allows(None));
+        let date_filter = DateFilter {
+            after: None,
+            before: date(10),
+            include_undated: false,
+        };
+        assert!(date_filter.allows(NaiveDate::from_ymd_opt(1999, 1, 1)));
+        assert!(!date_filter.allows(None));
+    }
+
+    #[test]
+    fn test_profile_options() {
+        let yaml_config = load_yaml!("cli_config.yml");
+        let app = App::from_yaml(yaml_config);
+        let config_path = std::env::temp_dir().join("paperoni-test-profiles.toml");
+        fs::write(
+            &config_path,
+            r#"
+[profiles.news]
+export = "html"
+no-css = true
+no-images = true
+remove-selectors = [".newsletter"]
+auth-token = ["abc123@api.example.org"]
+
+[profiles.news.sites."example.org"]
+selector = "article"
+auth = "user:pass"
+
+[profiles.broken]
+export = "pdf"
+"#,
+        )
+        .unwrap();
+        let config_path = config_path.to_str().unwrap();
+
+        // The options of the profile are used when they are not passed
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--profile",
+            "news",
+            "--config",
+            config_path,
+            "--remove-selector",
+            ".related",
+        ]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert!(matches!(app_config.export_type, ExportType::HTML));
+        assert!(matches!(app_config.css_config, CSSConfig::None));
+        assert!(app_config.is_skipping_images);
+        assert_eq!(
+            vec![".newsletter".to_owned(), ".related".to_owned()],
+            app_config.remove_selectors
+        );
+        let site_rule = app_config.site_rule("https://www.example.org/a").unwrap();
+        assert_eq!(Some("article"), site_rule.selector.as_deref());
+        // The credentials of the profile and its sites are sent like the ones of the flags
+        assert_eq!(
+            vec![
+                HostCredentials::new("api.example.org", Authorization::Bearer("abc123".into())),
+                HostCredentials::new(
+                    "example.org",
+                    Authorization::Basic {
+                        username: "user".into(),
+                        password: "pass".into()
+                    }
+                ),
+            ],
+            app_config.credentials
+        );
+
+        // The options passed on the command line take precedence
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--profile",
+            "news",
+            "--config",
+            config_path,
+            "--export",
+            "epub",
+            "--no-header-css",
+        ]);
+        let app_config = AppConfig::try_from(matches).unwrap();
+        assert!(matches!(app_config.export_type, ExportType::EPUB));
+        assert!(matches!(app_config.css_config, CSSConfig::NoHeaders));
+
+        // It returns an error when the export type of the profile is invalid
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--profile",
+            "broken",
+            "--config",
+            config_path,
+        ]);
+        assert_eq!(
+            Error::InvalidArgValue {
+                arg: "export",
+                value: "pdf".into(),
+                reason: "expected one of html, epub, ssml or odt".into(),
+            },
+            AppConfig::try_from(matches).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_env_values() {
+        let yaml_config = load_yaml!("cli_config.yml");
+        let app = App::from_yaml(yaml_config);
+        let vars = |vars: &[(&str, &str)]| {
+            parse_env_values(
+                vars.iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string())),
+            )
+        };
+        let env_values = vars(&[
+            ("PAPERONI_EXPORT", "html"),
+            ("PAPERONI_MAX_CONN", "4"),
+            ("PAPERONI_OUTPUT_DIR", ""),
+            ("PAPERONI_NO_IMAGES", "0"),
+            ("PAPERONI_NO_HEADER_CSS", "true"),
+            ("HOME", "/root"),
+        ])
+        .unwrap();
+        assert_eq!(4, env_values.len());
+        let profile = Profile {
+            export: Some("odt".to_owned()),
+            output_dir: Some("/tmp".to_owned()),
+            no_images: true,
+            no_css: true,
+            drop_caps: true,
+            ..Profile::default()
+        };
+
+        // The environment takes precedence over the profile
+        let matches = app
+            .clone()
+            .get_matches_from(vec!["paperoni", "http://example.org"]);
+        let args = ArgValues {
+            arg_matches: &matches,
+            env_values: &env_values,
+            profile: &profile,
+        };
+        assert_eq!(Some("html"), args.value_of("export"));
+        assert_eq!(Some("4"), args.value_of("max-conn"));
+        assert_eq!(Some("/tmp"), args.value_of("output-directory"));
+        assert!(!args.is_present("no-images"));
+        assert!(args.is_present("drop-caps"));
+        assert_eq!((false, true), args.css_flags());
+
+        // The command line takes precedence over the environment
+        let matches = app.clone().get_matches_from(vec![
+            "paperoni",
+            "http://example.org",
+            "--export",
+            "epub",
+            "--no-images",
+            "--no-css",
+        ]);
+        let args = ArgValues {
+            arg_matches: &matches,
+            env_values: &env_values,
+            profile: &profile,
+        };
+        assert_eq!(Some("epub"), args.value_of("export"));
+        assert!(args.is_present("no-images"));
+        assert_eq!((true, false), args.css_flags());
+
+        // The export types from the environment and the profile are checked like the ones
+        // passed on the command line
+        let matches = app
+            .clone()
+            .get_matches_from(vec!["paperoni", "http://example.org"]);
+        let site_env_values = vars(&[("PAPERONI_EXPORT", "site")]).unwrap();
+        let args = ArgValues {
+            arg_matches: &matches,
+            env_values: &site_env_values,
+            profile: &profile,
+        };
+        assert_eq!(Ok(()), args.check_export_type());
+        let site_profile = Profile {
+            export: Some("site".to_owned()),
+            ..Profile::default()
+        };
+        let args = ArgValues {
+            arg_matches: &matches,
+            env_values: &HashMap::new(),
+            profile: &site_profile,
+        };
+        assert_eq!(Ok(()), args.check_export_type());
+        let pdf_env_values = vars(&[("PAPERONI_EXPORT", "pdf")]).unwrap();
+        let args = ArgValues {
+            arg_matches: &matches,
+            env_values: &pdf_env_values,
+            profile: &profile,
+        };
+        assert!(matches!(
+            args.check_export_type(),
+            Err(Error::InvalidArgValue { arg: "export", .. })
+        ));
+
+        // It returns an error when a flag has an unknown value
+        assert_eq!(
+            Err(Error::InvalidEnvValue(
+                "PAPERONI_DROP_CAPS".into(),
+                "maybe".into()
+            )),
+            vars(&[("PAPERONI_DROP_CAPS", "maybe")])
+        );
+    }
+
+    #[test]
+    fn test_parse_url_file() {
+        let content = r#"https://example.com/a | selector=#content title="Custom Title"
+
+  https://example.com/b
+https://example.com/c|selector="div.post > .body"
+"#;
+        let entries = parse_url_file(content).unwrap();
+        assert_eq!(
+            vec![
+                (
+                    "https://example.com/a".to_owned(),
+                    ArticleOptions {
+                        selector: Some("#content".into()),
+                        title: Some("Custom Title".into()),
+                        ..Default::default()
+                    }
+                ),
+                (
+                    "https://example.com/b".to_owned(),
+                    ArticleOptions::default()
+                ),
+                (
+                    "https://example.com/c".to_owned(),
+                    ArticleOptions {
+                        selector: Some("div.post > .body".into()),
+                        title: None,
+                        ..Default::default()
+                    }
+                ),
+            ],
+            entries
+        );
+
+        assert_eq!(
+            Err(Error::InvalidUrlOptions(
+                "Unknown option \"color\" for https://example.com/a".into()
+            )),
+            parse_url_file("https://example.com/a | color=red")
+        );
+        assert_eq!(
+            Err(Error::InvalidUrlOptions(
+                "Unable to parse \"oops\" for https://example.com/a".into()
+            )),
+            parse_url_file("https://example.com/a | title=Ok oops")
+        );
+    }
+
+    #[test]
+    fn test_parse_queue_options() {
+        let (_, options) = parse_url_file_line(
+            "https://example.com/a | priority=-2 not-before=\"2021-06-01 08:30\"",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(-2, options.priority);
+        assert_eq!(
+            Some(Local.ymd(2021, 6, 1).and_hms(8, 30, 0)),
+            options.not_before
+        );
+        assert_eq!(
+            Some(Local.ymd(2021, 6, 1).and_hms(0, 0, 0)),
+            parse_schedule_time("2021-06-01")
+        );
+        // 2021-06-01T08:00:00Z
+        assert_eq!(
+            Some(1622534400),
+            parse_schedule_time("2021-06-01T10:00:00+02:00").map(|time| time.timestamp())
+        );
+        assert!(matches!(
+            parse_url_file_line("https://example.com/a | priority=high"),
+            Err(Error::InvalidUrlOptions(_))
+        ));
+        assert!(matches!(
+            parse_url_file_line("https://example.com/a | not-before=tomorrow"),
+            Err(Error::InvalidUrlOptions(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_credentials() {
+        assert_eq!(
+            Ok(HostCredentials::new(
+                "wiki.example.org",
+                Authorization::Basic {
+                    username: "user".into(),
+                    password: "p@ss:word".into()
+                }
+            )),
+            parse_basic_credentials("user:p@ss:word@Wiki.example.org")
+        );
+        assert_eq!(
+            Ok(HostCredentials::new(
+                "api.example.org",
+                Authorization::Bearer("abc123".into())
+            )),
+            parse_token_credentials("abc123@api.example.org")
+        );
+        assert_eq!(
+            Err(Error::InvalidCredentials("user@example.org".into())),
+            parse_basic_credentials("user@example.org")
+        );
+        assert_eq!(
+            Err(Error::InvalidCredentials("user:pass".into())),
+            parse_basic_credentials("user:pass")
+        );
+        assert_eq!(
+            Err(Error::InvalidCredentials("abc123@".into())),
+            parse_token_credentials("abc123@")
+        );
+    }
+
+    #[test]
+    fn test_push_options_from_env() {
+        let options = PushOptions::from_env(PushService::Omnivore, |name| {
+            Some(format!(" {}-value\n", name))
+        })
+        .unwrap();
+        assert_eq!(PushService::Omnivore, options.service);
+        assert_eq!("PAPERONI_OMNIVORE_API_KEY-value", options.token);
+        assert!(!format!("{:?}", options).contains("value"));
+
+        let missing_token = PushOptions::from_env(PushService::Readwise, |_| Some(" ".into()));
+        assert_eq!(
+            Error::MissingPushToken("Readwise Reader", "PAPERONI_READWISE_TOKEN"),
+            missing_token.unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_translate_options() {
+        let yaml_config = load_yaml!("cli_config.yml");
+        let app = App::from_yaml(yaml_config);
+        let app_config = AppConfig::try_from(app.get_matches_from(vec![
+            "paperoni",
+            "https://example.com/article",
+            "--translate",
+            "de",
+            "--translate-command",
+            "argos-translate --to {lang}",
+        ]))
+        .unwrap();
+        assert_eq!(
+            Some(TranslateOptions {
+                target_lang: "de".into(),
+                translator: Translator::Command("argos-translate --to {lang}".into()),
+            }),
+            app_config.translate
+        );
+
+        let deepl = Translator::deepl_from_env(|_| Some(" secret-key:fx\n".into())).unwrap();
+        assert_eq!(
+            Translator::DeepL {
+                api_key: "secret-key:fx".into()
+            },
+            deepl
+        );
+        assert!(!format!("{:?}", deepl).contains("secret"));
+        assert_eq!(
+            Error::MissingTranslatorKey("DeepL", "PAPERONI_DEEPL_API_KEY"),
+            Translator::deepl_from_env(|_| None).unwrap_err()
+        );
     }
 }
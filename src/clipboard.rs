@@ -0,0 +1,74 @@
+use std::process::Command;
+
+use itertools::Itertools;
+use regex::Regex;
+
+/// Commands that print the text of the system clipboard. The first one that runs is used.
+#[cfg(target_os = "macos")]
+const CLIPBOARD_COMMANDS: &[&[&str]] = &[&["pbpaste"]];
+#[cfg(target_os = "windows")]
+const CLIPBOARD_COMMANDS: &[&[&str]] =
+    &[&["powershell", "-NoProfile", "-Command", "Get-Clipboard"]];
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const CLIPBOARD_COMMANDS: &[&[&str]] = &[
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-selection", "clipboard", "-out"],
+    &["xsel", "--clipboard", "--output"],
+];
+
+/// Reads the text of the system clipboard with the clipboard tool of the platform. Returns the
+/// reason it could not be read otherwise.
+pub fn read_clipboard() -> Result<String, String> {
+    let mut failures = Vec::new();
+    for command in CLIPBOARD_COMMANDS {
+        match Command::new(command[0]).args(&command[1..]).output() {
+            Ok(output) if output.status.success() => {
+                return Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+            }
+            Ok(output) => failures.push(format!(
+                "{} failed: {}",
+                command[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(_) => failures.push(format!("{} was not found", command[0])),
+        }
+    }
+    Err(failures.join(", "))
+}
+
+/// Finds the http and https urls in a text such as the content of the clipboard. Punctuation
+/// that ends a sentence or closes brackets around a url is not kept as part of it.
+pub fn extract_urls(text: &str) -> Vec<String> {
+    lazy_static! {
+        static ref URL_REGEX: Regex = Regex::new(r#"https?://[^\s<>"'`]+"#).unwrap();
+    }
+    URL_REGEX
+        .find_iter(text)
+        .map(|url| {
+            url.as_str()
+                .trim_end_matches(|c| matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | ')' | ']'))
+                .to_owned()
+        })
+        .unique()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_extract_urls() {
+        let text = "Read https://example.com/post-1. Also (see http://blog.example.org/a?b=1)\n\
+            https://example.com/post-1 and <https://example.com/post-2>, not ftp://example.com";
+        assert_eq!(
+            vec![
+                "https://example.com/post-1",
+                "http://blog.example.org/a?b=1",
+                "https://example.com/post-2"
+            ],
+            extract_urls(text)
+        );
+        assert!(extract_urls("no links here").is_empty());
+    }
+}
@@ -0,0 +1,225 @@
+use kuchiki::{traits::*, NodeRef};
+
+use crate::extractor::new_element;
+
+/// The most top-level comments kept for an article
+const MAX_COMMENTS: usize = 100;
+/// Parts of comments that are not kept such as reply buttons and avatars
+const REMOVED_SELECTORS: &str =
+    "script, style, form, img, svg, button, .reply, .comment-reply-link, .navs";
+
+struct Comment {
+    author: Option<String>,
+    date: Option<String>,
+    body: NodeRef,
+}
+
+/// Finds the top-level comments of a page from Hacker News, the static fallback of Disqus or
+/// a WordPress theme. The comments are returned as a section to add at the end of the
+/// article since readability strips them from the content. Replies are left out.
+pub fn extract_comments(html: &str) -> Option<NodeRef> {
+    let doc = kuchiki::parse_html().one(html);
+    let comments = [hacker_news_comments, disqus_comments, wordpress_comments]
+        .iter()
+        .map(|find_comments| find_comments(&doc))
+        .find(|comments| !comments.is_empty())?;
+    Some(comments_section(comments))
+}
+
+fn hacker_news_comments(doc: &NodeRef) -> Vec<Comment> {
+    doc.select("tr.athing.comtr")
+        .unwrap()
+        .filter(|row| {
+            // Replies are indented with an indent attribute or a spacer image of their depth
+            row.as_node()
+                .select_first("td.ind")
+                .map(|indent| {
+                    let indent_node = indent.as_node();
+                    let indent_attrs = indent.attributes.borrow();
+                    match indent_attrs.get("indent") {
+                        Some(depth) => depth == "0",
+                        None => indent_node
+                            .select_first("img")
+                            .map(|img| img.attributes.borrow().get("width") == Some("0"))
+                            .unwrap_or(true),
+                    }
+                })
+                .unwrap_or(false)
+        })
+        .filter_map(|row| {
+            let row = row.as_node();
+            Some(Comment {
+                author: select_text(row, ".hnuser"),
+                date: select_text(row, ".age"),
+                body: row.select_first(".commtext").ok()?.as_node().clone(),
+            })
+        })
+        .collect()
+}
+
+fn disqus_comments(doc: &NodeRef) -> Vec<Comment> {
+    doc.select("#dsq-comments > li")
+        .unwrap()
+        .filter_map(|item| {
+            let item = item.as_node();
+            Some(Comment {
+                author: select_text(item, "cite"),
+                date: select_text(item, ".dsq-comment-header .dsq-comment-date"),
+                body: item
+                    .select_first(".dsq-comment-message")
+                    .ok()?
+                    .as_node()
+                    .clone(),
+            })
+        })
+        .collect()
+}
+
+fn wordpress_comments(doc: &NodeRef) -> Vec<Comment> {
+    doc.select("ol.commentlist > li, ol.comment-list > li")
+        .unwrap()
+        .filter_map(|item| {
+            let item = item.as_node();
+            // The body is the first one found so that the bodies of replies are not used
+            let body = item
+                .select_first(".comment-content")
+                .or_else(|_| item.select_first(".comment-body"))
+                .ok()?
+                .as_node()
+                .clone();
+            Some(Comment {
+                author: select_text(item, ".comment-author .fn")
+                    .or_else(|| select_text(item, ".fn")),
+                date: select_text(item, ".comment-metadata time")
+                    .or_else(|| select_text(item, ".comment-meta a")),
+                body,
+            })
+        })
+        .collect()
+}
+
+fn select_text(node: &NodeRef, selector: &str) -> Option<String> {
+    node.select_first(selector)
+        .ok()
+        .map(|elem| elem.text_contents().trim().to_owned())
+        .filter(|text| !text.is_empty())
+}
+
+fn comments_section(comments: Vec<Comment>) -> NodeRef {
+    let section = new_element("section", &[("class", "paperoni-comments")]);
+    let heading = new_element("h2", &[]);
+    heading.append(NodeRef::new_text("Comments"));
+    section.append(heading);
+    for comment in comments.into_iter().take(MAX_COMMENTS) {
+        let comment_node = new_element("article", &[("class", "paperoni-comment")]);
+        if comment.author.is_some() || comment.date.is_some() {
+            let meta = new_element("p", &[("class", "paperoni-comment-meta")]);
+            if let Some(author) = &comment.author {
+                let author_node = new_element("strong", &[]);
+                author_node.append(NodeRef::new_text(author));
+                meta.append(author_node);
+            }
+            if let Some(date) = &comment.date {
+                let separator = if comment.author.is_some() { " · " } else { "" };
+                meta.append(NodeRef::new_text(format!("{}{}", separator, date)));
+            }
+            comment_node.append(meta);
+        }
+        for removed in comment
+            .body
+            .select(REMOVED_SELECTORS)
+            .unwrap()
+            .collect::<Vec<_>>()
+        {
+            removed.as_node().detach();
+        }
+        // Nested replies in WordPress bodies are left out along with their list
+        for replies in comment
+            .body
+            .select("ol.children, ul.children")
+            .unwrap()
+            .collect::<Vec<_>>()
+        {
+            replies.as_node().detach();
+        }
+        for child in comment.body.children().collect::<Vec<_>>() {
+            comment_node.append(child);
+        }
+        section.append(comment_node);
+    }
+    section
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn comment_texts(section: &NodeRef) -> Vec<String> {
+        section
+            .select("article.paperoni-comment")
+            .unwrap()
+            .map(|comment| {
+                comment
+                    .text_contents()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hacker_news_comments() {
+        let html = r#"<table>
+            <tr class="athing comtr"><td><table><tr>
+                <td class="ind" indent="0"><img src="s.gif" height="1" width="0"></td>
+                <td class="default"><span class="comhead"><a class="hnuser">pg</a> <span class="age">2 hours ago</span></span>
+                <div class="comment"><div class="commtext c00">Top level comment<p>Second paragraph</p></div>
+                <div class="reply"><a>reply</a></div></div></td>
+            </tr></table></td></tr>
+            <tr class="athing comtr"><td><table><tr>
+                <td class="ind" indent="1"><img src="s.gif" height="1" width="40"></td>
+                <td class="default"><a class="hnuser">dang</a><div class="commtext c00">A reply</div></td>
+            </tr></table></td></tr>
+        </table>"#;
+        let section = extract_comments(html).unwrap();
+        assert_eq!(
+            vec!["pg · 2 hours ago Top level comment Second paragraph".to_owned()],
+            comment_texts(&section)
+        );
+    }
+
+    #[test]
+    fn test_wordpress_comments() {
+        let html = r##"<ol class="comment-list">
+            <li class="comment"><article class="comment-body">
+                <footer class="comment-meta"><div class="comment-author"><img class="avatar" src="a.png"><b class="fn">Ann</b></div>
+                <div class="comment-metadata"><a href="#c1"><time>May 1, 2021</time></a></div></footer>
+                <div class="comment-content"><p>Great post</p></div>
+                <div class="reply"><a class="comment-reply-link">Reply</a></div>
+            </article>
+            <ol class="children"><li class="comment"><div class="comment-content"><p>Thanks</p></div></li></ol></li>
+            <li class="comment"><div class="comment-body"><p>Anonymous comment</p></div></li>
+        </ol>"##;
+        let section = extract_comments(html).unwrap();
+        assert_eq!(
+            vec![
+                "Ann · May 1, 2021 Great post".to_owned(),
+                "Anonymous comment".to_owned()
+            ],
+            comment_texts(&section)
+        );
+        assert_eq!(0, section.select("img").unwrap().count());
+    }
+
+    #[test]
+    fn test_disqus_comments() {
+        let html = r#"<div id="disqus_thread"><div id="dsq-content"><ul id="dsq-comments">
+            <li class="comment"><div class="dsq-comment-header"><cite><span>Bob</span></cite></div>
+            <div class="dsq-comment-body"><div class="dsq-comment-message"><p>First!</p></div></div></li>
+        </ul></div></div>"#;
+        let section = extract_comments(html).unwrap();
+        assert_eq!(vec!["Bob First!".to_owned()], comment_texts(&section));
+        assert!(extract_comments("<p>No comments here</p>").is_none());
+    }
+}
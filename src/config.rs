@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+
+use directories::UserDirs;
+use serde::Deserialize;
+use url::Url;
+
+use crate::errors::ConfigError;
+use crate::moz_readability::regexes::PatternOverrides;
+use crate::text_replace::ReplaceRule;
+
+/// The config file read from `~/.paperoni/config.toml` or the path passed to `--config`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+}
+
+/// A named set of options selected with `--profile`. The options passed on the
+/// command line take precedence over the ones in the profile.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Profile {
+    pub export: Option<String>,
+    pub output_dir: Option<String>,
+    pub max_conn: Option<NonZeroUsize>,
+    pub locale: Option<String>,
+    pub no_css: bool,
+    pub no_header_css: bool,
+    pub no_images: bool,
+    pub alt_captions: bool,
+    pub inline_images: bool,
+    pub drop_caps: bool,
+    pub site_logo: bool,
+    pub remove_selectors: Vec<String>,
+    pub sanitize: bool,
+    /// Elements kept by --sanitize on top of its whitelist
+    pub sanitize_allow_tags: Vec<String>,
+    /// Attributes kept by --sanitize on top of its whitelist
+    pub sanitize_allow_attrs: Vec<String>,
+    /// Hosts or url patterns of images that are not downloaded
+    pub skip_images_from: Vec<String>,
+    /// Regex find and replace rules applied to the text of every article
+    pub replace: Vec<ReplaceRule>,
+    /// Basic auth credentials in the form `user:pass@domain` like --auth
+    pub auth: Vec<String>,
+    /// Bearer tokens in the form `token@domain` like --auth-token
+    pub auth_token: Vec<String>,
+    /// Rules for the articles of a site, keyed by its domain
+    pub sites: HashMap<String, SiteRule>,
+}
+
+/// Options applied to the articles of a site and its subdomains
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SiteRule {
+    /// CSS selector of the element containing the article. This skips the readability algorithm
+    pub selector: Option<String>,
+    /// CSS selectors of elements removed from the articles of the site
+    pub remove_selectors: Vec<String>,
+    /// Regex find and replace rules applied to the text of the articles of the site after the
+    /// ones of the profile
+    pub replace: Vec<ReplaceRule>,
+    /// Patterns of classes and ids that are not stripped as unlikely candidates, such as a
+    /// `sidebar` holding the article
+    pub allow_unlikely: Vec<String>,
+    /// Patterns of classes and ids that do not lower the score of an element
+    pub allow_negative: Vec<String>,
+    /// Basic auth credentials in the form `user:pass` sent to the site
+    pub auth: Option<String>,
+    /// Bearer token sent to the site
+    pub token: Option<String>,
+}
+
+impl SiteRule {
+    /// The exceptions of the site to the patterns readability uses to find the article
+    pub fn pattern_overrides(&self) -> Result<PatternOverrides, regex::Error> {
+        PatternOverrides::new(&self.allow_unlikely, &self.allow_negative)
+    }
+}
+
+fn default_config_path() -> Result<PathBuf, ConfigError> {
+    UserDirs::new()
+        .map(|user_dirs| user_dirs.home_dir().join(".paperoni").join("config.toml"))
+        .ok_or(ConfigError::UserDirectoriesError)
+}
+
+/// Reads the profile with the given name from the config file
+pub fn load_profile(config_path: Option<&str>, name: &str) -> Result<Profile, ConfigError> {
+    let path = match config_path {
+        Some(path) => PathBuf::from(path),
+        None => default_config_path()?,
+    };
+    let path_str = path.to_string_lossy().into_owned();
+    let content =
+        fs::read_to_string(&path).map_err(|err| ConfigError::ReadError(path_str.clone(), err))?;
+    parse_profile(&content, name).map_err(|err| match err {
+        ConfigError::ParseError(_, msg) => ConfigError::ParseError(path_str, msg),
+        ConfigError::UnknownProfile(name, _) => ConfigError::UnknownProfile(name, path_str),
+        err => err,
+    })
+}
+
+fn parse_profile(content: &str, name: &str) -> Result<Profile, ConfigError> {
+    let mut config: ConfigFile = toml::from_str(content)
+        .map_err(|err| ConfigError::ParseError(String::new(), err.to_string()))?;
+    let mut profile = config
+        .profiles
+        .remove(name)
+        .ok_or_else(|| ConfigError::UnknownProfile(name.to_owned(), String::new()))?;
+    // Domains are matched against hosts which are lower case
+    profile.sites = profile
+        .sites
+        .into_iter()
+        .map(|(domain, rule)| (domain.trim().to_lowercase(), rule))
+        .collect();
+    for (domain, rule) in &profile.sites {
+        rule.pattern_overrides().map_err(|err| {
+            ConfigError::ParseError(
+                String::new(),
+                format!("invalid readability pattern for {}: {}", domain, err),
+            )
+        })?;
+    }
+    Ok(profile)
+}
+
+/// Finds the rule of the most specific domain matching the host of a url
+pub fn find_site_rule<'a>(
+    site_rules: &'a HashMap<String, SiteRule>,
+    url: &str,
+) -> Option<&'a SiteRule> {
+    let host = Url::parse(url).ok()?.host_str()?.to_lowercase();
+    site_rules
+        .iter()
+        .filter(|(domain, _)| host == **domain || host.ends_with(&format!(".{}", domain)))
+        .max_by_key(|(domain, _)| domain.len())
+        .map(|(_, rule)| rule)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[profiles.news]
+export = "html"
+output-dir = "/tmp/news"
+no-images = true
+alt-captions = true
+remove-selectors = [".newsletter"]
+
+[[profiles.news.replace]]
+find = "(?m)^Advertisement$"
+
+[profiles.news.sites."Example.com"]
+selector = "article"
+
+[profiles.news.sites."blog.example.com"]
+remove-selectors = [".comments"]
+allow-unlikely = ["^sidebar$"]
+token = "abc123"
+
+[[profiles.news.sites."blog.example.com".replace]]
+find = "â€™"
+with = "’"
+
+[profiles.papers]
+no-css = true
+max-conn = 2
+auth = ["user:pass@wiki.example.org"]
+auth-token = ["abc123@api.example.org"]
+"#;
+
+    #[test]
+    fn test_parse_profile() {
+        let profile = parse_profile(CONFIG, "news").unwrap();
+        assert_eq!(Some("html"), profile.export.as_deref());
+        assert_eq!(Some("/tmp/news"), profile.output_dir.as_deref());
+        assert!(profile.no_images && profile.alt_captions && !profile.no_css);
+        assert_eq!(vec![".newsletter".to_owned()], profile.remove_selectors);
+        assert_eq!(1, profile.replace.len());
+
+        let rule = find_site_rule(&profile.sites, "https://www.example.com/a").unwrap();
+        assert_eq!(Some("article"), rule.selector.as_deref());
+        let rule = find_site_rule(&profile.sites, "https://blog.example.com/a").unwrap();
+        assert_eq!(None, rule.selector);
+        assert_eq!(vec![".comments".to_owned()], rule.remove_selectors);
+        assert_eq!(1, rule.replace.len());
+        assert_eq!(Some("abc123"), rule.token.as_deref());
+        assert_eq!(None, rule.auth);
+        assert!(!rule
+            .pattern_overrides()
+            .unwrap()
+            .is_match_unlikely("sidebar"));
+        assert!(find_site_rule(&profile.sites, "https://notexample.com/a").is_none());
+
+        let profile = parse_profile(CONFIG, "papers").unwrap();
+        assert!(profile.no_css);
+        assert_eq!(Some(2), profile.max_conn.map(NonZeroUsize::get));
+        assert_eq!(vec!["user:pass@wiki.example.org".to_owned()], profile.auth);
+        assert_eq!(
+            vec!["abc123@api.example.org".to_owned()],
+            profile.auth_token
+        );
+
+        assert!(matches!(
+            parse_profile(CONFIG, "recipes"),
+            Err(ConfigError::UnknownProfile(name, _)) if name == "recipes"
+        ));
+        assert!(matches!(
+            parse_profile("[profiles.news]\nexprot = \"html\"", "news"),
+            Err(ConfigError::ParseError(..))
+        ));
+        assert!(matches!(
+            parse_profile("[[profiles.news.replace]]\nfind = \"(unclosed\"", "news"),
+            Err(ConfigError::ParseError(_, msg)) if msg.contains("invalid find pattern")
+        ));
+        assert!(matches!(
+            parse_profile("[profiles.news.sites.\"example.com\"]\nallow-negative = [\"(\"]", "news"),
+            Err(ConfigError::ParseError(_, msg)) if msg.starts_with("invalid readability pattern for example.com")
+        ));
+    }
+}
@@ -0,0 +1,221 @@
+use std::{fs, path::Path, sync::Arc};
+
+use log::{debug, warn};
+use url::Url;
+
+/// A cookie read from a Netscape cookies.txt file
+#[derive(Clone, Debug, PartialEq)]
+struct Cookie {
+    domain: String,
+    include_subdomains: bool,
+    path: String,
+    is_secure: bool,
+    /// Unix timestamp of when the cookie expires. Session cookies have a value of 0
+    expires: i64,
+    name: String,
+    value: String,
+}
+
+impl Cookie {
+    fn matches(&self, url: &Url, now: i64) -> bool {
+        let host = match url.host_str() {
+            Some(host) => host.to_lowercase(),
+            None => return false,
+        };
+        let domain = self.domain.trim_start_matches('.');
+        let domain_matches =
+            host == domain || (self.include_subdomains && host.ends_with(&format!(".{}", domain)));
+        domain_matches
+            && path_matches(&self.path, url.path())
+            && (!self.is_secure || url.scheme() == "https")
+            && (self.expires == 0 || self.expires > now)
+    }
+}
+
+/// Checks if the path of a cookie matches the path of a request as in RFC 6265 section 5.1.4.
+/// The cookie path has to match whole segments so `/premium` does not match `/premium-free`.
+fn path_matches(cookie_path: &str, request_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
+/// Cookies loaded from a file which are sent with requests to matching hosts. This is used
+/// to reuse a logged in browser session exported by a browser extension.
+#[derive(Clone, Debug, Default)]
+pub struct CookieJar {
+    cookies: Arc<Vec<Cookie>>,
+}
+
+impl CookieJar {
+    /// Reads a cookies.txt file in the Netscape format used by curl and wget
+    pub fn from_file(path: &Path) -> Result<Self, std::io::Error> {
+        let content = fs::read_to_string(path)?;
+        Ok(Self::parse(&content))
+    }
+
+    /// Parses the content of a cookies.txt file. Malformed lines are skipped.
+    fn parse(content: &str) -> Self {
+        const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+        let cookies = content
+            .lines()
+            .enumerate()
+            .filter_map(|(line_idx, line)| {
+                let line = line.trim();
+                // HttpOnly cookies are written as comments with a special prefix
+                let line = if let Some(http_only_line) = line.strip_prefix(HTTP_ONLY_PREFIX) {
+                    http_only_line
+                } else if line.is_empty() || line.starts_with('#') {
+                    return None;
+                } else {
+                    line
+                };
+                let fields = line.split('\t').collect::<Vec<_>>();
+                if fields.len() != 7 {
+                    warn!("Skipping malformed line {} in cookies file", line_idx + 1);
+                    return None;
+                }
+                let expires = match fields[4].parse::<i64>() {
+                    Ok(expires) => expires,
+                    Err(_) => {
+                        warn!(
+                            "Skipping line {} in cookies file with invalid expiry",
+                            line_idx + 1
+                        );
+                        return None;
+                    }
+                };
+                Some(Cookie {
+                    domain: fields[0].to_lowercase(),
+                    include_subdomains: fields[1].eq_ignore_ascii_case("TRUE"),
+                    path: fields[2].to_owned(),
+                    is_secure: fields[3].eq_ignore_ascii_case("TRUE"),
+                    expires,
+                    name: fields[5].to_owned(),
+                    value: fields[6].to_owned(),
+                })
+            })
+            .collect::<Vec<_>>();
+        debug!("Loaded {} cookies", cookies.len());
+        Self {
+            cookies: Arc::new(cookies),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
+    /// Returns the value of the Cookie header for a request to the given url
+    fn cookie_header(&self, url: &Url) -> Option<String> {
+        let now = chrono::Utc::now().timestamp();
+        let header = self
+            .cookies
+            .iter()
+            .filter(|cookie| cookie.matches(url, now))
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if header.is_empty() {
+            None
+        } else {
+            Some(header)
+        }
+    }
+}
+
+#[surf::utils::async_trait]
+impl surf::middleware::Middleware for CookieJar {
+    async fn handle(
+        &self,
+        mut req: surf::Request,
+        client: surf::Client,
+        next: surf::middleware::Next<'_>,
+    ) -> surf::Result<surf::Response> {
+        if let Some(cookie_header) = self.cookie_header(req.url()) {
+            req.insert_header(surf::http::headers::COOKIE, cookie_header);
+        }
+        next.run(req, client).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const COOKIES_TXT: &str = "# Netscape HTTP Cookie File
+# This is a generated file! Do not edit.
+
+.example.com\tTRUE\t/\tFALSE\t0\tsession\tabc
+news.example.org\tFALSE\t/premium\tTRUE\t4102444800\tsubscriber\t1
+#HttpOnly_.example.com\tTRUE\t/\tFALSE\t0\ttoken\txyz
+expired.example.net\tFALSE\t/\tFALSE\t1\told\tvalue
+malformed line
+";
+
+    #[test]
+    fn test_parse() {
+        let jar = CookieJar::parse(COOKIES_TXT);
+        assert_eq!(4, jar.cookies.len());
+        assert_eq!(
+            Cookie {
+                domain: "news.example.org".into(),
+                include_subdomains: false,
+                path: "/premium".into(),
+                is_secure: true,
+                expires: 4102444800,
+                name: "subscriber".into(),
+                value: "1".into(),
+            },
+            jar.cookies[1]
+        );
+        assert_eq!("token", jar.cookies[2].name);
+    }
+
+    #[test]
+    fn test_path_matches() {
+        assert!(path_matches("/", "/"));
+        assert!(path_matches("/", "/any/path"));
+        assert!(path_matches("/docs", "/docs"));
+        assert!(path_matches("/docs", "/docs/page"));
+        assert!(path_matches("/docs/", "/docs/page"));
+        assert!(!path_matches("/docs", "/docsearch"));
+        assert!(!path_matches("/docs/", "/docs"));
+        assert!(!path_matches("/docs", "/"));
+    }
+
+    #[test]
+    fn test_cookie_header() {
+        let jar = CookieJar::parse(COOKIES_TXT);
+        let cookie_header = |url: &str| jar.cookie_header(&Url::parse(url).unwrap());
+
+        assert_eq!(
+            Some("session=abc; token=xyz".to_owned()),
+            cookie_header("http://example.com/")
+        );
+        assert_eq!(
+            Some("session=abc; token=xyz".to_owned()),
+            cookie_header("https://blog.example.com/post")
+        );
+        assert_eq!(
+            Some("subscriber=1".to_owned()),
+            cookie_header("https://news.example.org/premium/article")
+        );
+        // Secure cookies are not sent over http
+        assert_eq!(
+            None,
+            cookie_header("http://news.example.org/premium/article")
+        );
+        // The path must match whole segments
+        assert_eq!(None, cookie_header("https://news.example.org/free"));
+        assert_eq!(
+            Some("subscriber=1".to_owned()),
+            cookie_header("https://news.example.org/premium")
+        );
+        assert_eq!(None, cookie_header("https://news.example.org/premium-free"));
+        // Subdomains are excluded when the flag is not set
+        assert_eq!(None, cookie_header("https://www.news.example.org/premium"));
+        // Expired cookies are not sent
+        assert_eq!(None, cookie_header("http://expired.example.net/"));
+    }
+}
@@ -0,0 +1,171 @@
+use std::fs;
+
+use base64::encode;
+use log::{debug, warn};
+
+use crate::extractor::Article;
+
+/// The size of the collage, which has the 3:4 aspect ratio of most e-reader screens
+const COLLAGE_WIDTH: usize = 1200;
+const COLLAGE_HEIGHT: usize = 1600;
+/// The height of the band under the grid of images with the title and date of the digest
+const TITLE_BAND_HEIGHT: usize = 400;
+/// The space between the images of the grid
+const TILE_GAP: usize = 8;
+/// The most images laid out in the grid
+const MAX_COLLAGE_IMAGES: usize = 9;
+/// The most characters of a line of the title before it is wrapped, and the most lines shown
+const TITLE_LINE_CHARS: usize = 26;
+const MAX_TITLE_LINES: usize = 3;
+
+/// Generates the SVG cover of a merged epub from the covers downloaded for its articles, which
+/// are the images they are shared with or the icons of their sites. Articles without a cover
+/// are skipped.
+pub fn cover_collage(title: &str, date: &str, articles: &[Article]) -> String {
+    let images = articles
+        .iter()
+        .filter_map(|article| article.cover_img.as_ref())
+        .filter_map(|(cover_path, cover_mime)| {
+            let file_path = std::env::temp_dir().join(cover_path);
+            match fs::read(&file_path) {
+                Ok(bytes) => Some((
+                    bytes,
+                    cover_mime.as_deref().unwrap_or("image/png").to_owned(),
+                )),
+                Err(e) => {
+                    warn!(
+                        "Unable to read cover {:?} for the collage: {}",
+                        file_path, e
+                    );
+                    None
+                }
+            }
+        })
+        .take(MAX_COLLAGE_IMAGES)
+        .collect::<Vec<_>>();
+    debug!("Laying out {} images in the cover collage", images.len());
+    render_collage(title, date, &images)
+}
+
+/// Lays out the images, passed with their mime types, in a grid above the title and date of
+/// the digest. The images are cropped to fill their tile and embedded in the SVG.
+fn render_collage(title: &str, date: &str, images: &[(Vec<u8>, String)]) -> String {
+    let images = &images[..images.len().min(MAX_COLLAGE_IMAGES)];
+    let grid_height = COLLAGE_HEIGHT - TITLE_BAND_HEIGHT;
+    let mut tiles = String::new();
+    if !images.is_empty() {
+        let columns = (1..).find(|n| n * n >= images.len()).unwrap();
+        let rows = (images.len() + columns - 1) / columns;
+        let tile_width = (COLLAGE_WIDTH - TILE_GAP * (columns + 1)) / columns;
+        let tile_height = (grid_height - TILE_GAP * (rows + 1)) / rows;
+        for (idx, (bytes, mime)) in images.iter().enumerate() {
+            let (column, row) = (idx % columns, idx / columns);
+            tiles.push_str(&format!(
+                r#"<image x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="xMidYMid slice" xlink:href="data:{};base64,{}"/>"#,
+                TILE_GAP + column * (tile_width + TILE_GAP),
+                TILE_GAP + row * (tile_height + TILE_GAP),
+                tile_width,
+                tile_height,
+                mime,
+                encode(bytes)
+            ));
+        }
+    }
+
+    let title_lines = wrap_title(title);
+    let line_height = 84;
+    let title_top = grid_height + (TITLE_BAND_HEIGHT - line_height * (title_lines.len() + 1)) / 2;
+    let title_tspans: String = title_lines
+        .iter()
+        .enumerate()
+        .map(|(idx, line)| {
+            format!(
+                r#"<tspan x="{}" y="{}">{}</tspan>"#,
+                COLLAGE_WIDTH / 2,
+                title_top + line_height * (idx + 1),
+                escape_text(line)
+            )
+        })
+        .collect();
+    format!(
+        r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" version="1.1" width="{width}" height="{height}" viewBox="0 0 {width} {height}">
+<rect width="{width}" height="{height}" fill="#222"/>
+{tiles}
+<text text-anchor="middle" font-family="serif" font-size="72" font-weight="bold" fill="#fff">{title}</text>
+<text x="{center}" y="{date_y}" text-anchor="middle" font-family="serif" font-size="40" fill="#ccc">{date}</text>
+</svg>"##,
+        width = COLLAGE_WIDTH,
+        height = COLLAGE_HEIGHT,
+        tiles = tiles,
+        title = title_tspans,
+        center = COLLAGE_WIDTH / 2,
+        date_y = title_top + line_height * (title_lines.len() + 1),
+        date = escape_text(date)
+    )
+}
+
+/// Wraps the title on whole words. Titles longer than the lines of the band are cut short
+/// with an ellipsis.
+fn wrap_title(title: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in title.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= TITLE_LINE_CHARS => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_owned()),
+        }
+    }
+    if lines.len() > MAX_TITLE_LINES {
+        lines.truncate(MAX_TITLE_LINES);
+        lines[MAX_TITLE_LINES - 1].push('…');
+    }
+    lines
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace("&", "&amp;")
+        .replace("<", "&lt;")
+        .replace(">", "&gt;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wrap_title() {
+        assert_eq!(vec!["Weekly digest"], wrap_title("Weekly  digest"));
+        assert_eq!(
+            vec!["Everything you wanted to", "know about pepperoni"],
+            wrap_title("Everything you wanted to know about pepperoni")
+        );
+        assert_eq!(
+            vec![
+                "a a a a a a a a a a a a a",
+                "a a a a a a a a a a a a a",
+                "a a a a a a a a a a a a a…"
+            ],
+            wrap_title(&"a ".repeat(50))
+        );
+    }
+
+    #[test]
+    fn test_render_collage() {
+        let images = vec![(b"png".to_vec(), "image/png".to_owned()); 5];
+        let collage = render_collage("Pizza & pasta", "1 June 2021", &images);
+        assert!(collage.starts_with("<?xml"));
+        assert_eq!(5, collage.matches("<image ").count());
+        // Five images are laid out in a grid of three columns and two rows
+        assert!(collage.contains(
+            r#"<image x="405" y="604" width="389" height="588" preserveAspectRatio="xMidYMid slice" xlink:href="data:image/png;base64,cG5n"/>"#
+        ));
+        assert!(collage.contains(">Pizza &amp; pasta</tspan>"));
+        assert!(collage.contains(">1 June 2021</text>"));
+
+        let collage = render_collage("Digest", "1 June 2021", &[]);
+        assert_eq!(0, collage.matches("<image ").count());
+    }
+}
@@ -0,0 +1,418 @@
+use async_std::{stream, task};
+use futures::StreamExt;
+use itertools::Itertools;
+use kuchiki::{traits::*, NodeRef};
+use log::{debug, info};
+use regex::Regex;
+use url::Url;
+
+use crate::cli::{AppConfig, ArchiveOptions};
+use crate::errors::PaperoniError;
+use crate::http::{build_client, fetch_html};
+use crate::newsletter::Newsletter;
+
+/// Fetches the index pages passed as urls and returns the links to the articles found in them.
+/// Index pages that fail to download are returned as errors.
+pub fn crawl_index_pages(app_config: &AppConfig) -> (Vec<String>, Vec<PaperoniError>) {
+    task::block_on(async {
+        let client = build_client(app_config, None);
+        let urls_iter = app_config
+            .urls
+            .iter()
+            .map(|url| fetch_html(&client, url, app_config.max_redirects));
+        let mut responses = stream::from_iter(urls_iter).buffered(app_config.max_conn);
+        let mut article_links = Vec::new();
+        let mut errors = Vec::new();
+        while let Some(fetch_result) = responses.next().await {
+            match fetch_result {
+                Ok((index_url, html)) => {
+                    let links =
+                        extract_article_links(&html, &index_url, app_config.crawl_pattern.as_ref());
+                    info!("Found {} article links in {}", links.len(), index_url);
+                    article_links.extend(links);
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+        (article_links.into_iter().unique().collect(), errors)
+    })
+}
+
+/// The most pages of an archive that are paginated through
+const MAX_ARCHIVE_PAGES: usize = 100;
+
+lazy_static! {
+    static ref NEXT_PAGE_TEXT_REGEX: Regex =
+        Regex::new(r"(?i)^(?:older(?: posts| entries| articles)?|next(?: page)?|more(?: posts| articles)?|load more|[»›→]+)\s*[»›→]*$")
+            .unwrap();
+    static ref ARCHIVE_SECTION_REGEX: Regex =
+        Regex::new(r"/(?:author|authors|tag|tags|category|categories|page|archive)(?:/|$)").unwrap();
+}
+
+/// The articles found by paginating through author archives
+#[derive(Debug, Default, PartialEq)]
+pub struct Archive {
+    /// The name of the author when a single archive was crawled
+    pub author: Option<String>,
+    pub links: Vec<String>,
+}
+
+/// A page of an author archive
+#[derive(Debug, Default, PartialEq)]
+struct ArchivePage {
+    author: Option<String>,
+    /// The name of the site such as the name of a newsletter
+    site_name: Option<String>,
+    links: Vec<String>,
+    next_page: Option<String>,
+}
+
+/// Paginates through the archives passed as urls and returns the links to the articles listed
+/// in them, newest first as archives list them. Pages that fail to download end the
+/// pagination of their archive and are returned as errors.
+pub fn crawl_archives(
+    app_config: &AppConfig,
+    options: &ArchiveOptions,
+) -> (Archive, Vec<PaperoniError>) {
+    task::block_on(async {
+        let client = build_client(app_config, None);
+        let mut archive = Archive::default();
+        let mut authors = Vec::new();
+        let mut errors = Vec::new();
+        for archive_url in &app_config.urls {
+            let mut page_url = Some(archive_url.to_owned());
+            let mut page_count = 0;
+            while let Some(url) = page_url.take() {
+                let is_limit_reached = options
+                    .limit
+                    .map_or(false, |limit| archive.links.len() >= limit);
+                if page_count >= MAX_ARCHIVE_PAGES || is_limit_reached {
+                    break;
+                }
+                page_count += 1;
+                let (url, html) = match fetch_html(&client, &url, app_config.max_redirects).await {
+                    Ok(resource) => resource,
+                    Err(e) => {
+                        errors.push(e);
+                        break;
+                    }
+                };
+                let page = parse_archive_page(&html, &url, app_config.crawl_pattern.as_ref());
+                let newsletter = if page_count == 1 {
+                    Newsletter::detect(&url, &html)
+                } else {
+                    None
+                };
+                // The posts of newsletters are listed by their platform instead of paginating
+                if let Some(newsletter) = newsletter {
+                    authors.push(page.site_name.or(page.author));
+                    let limit = options.limit.map(|limit| limit - archive.links.len());
+                    match newsletter.fetch_archive(&client, &url, limit).await {
+                        Ok(links) => {
+                            info!(
+                                "Found {} posts in the {} archive of {}",
+                                links.len(),
+                                newsletter.name(),
+                                url
+                            );
+                            for link in links {
+                                if !archive.links.contains(&link) {
+                                    archive.links.push(link);
+                                }
+                            }
+                        }
+                        Err(e) => errors.push(e),
+                    }
+                    break;
+                }
+                info!("Found {} article links in {}", page.links.len(), url);
+                if page_count == 1 {
+                    authors.push(page.author);
+                }
+                for link in page.links {
+                    if !archive.links.contains(&link) {
+                        archive.links.push(link);
+                    }
+                }
+                page_url = page.next_page;
+            }
+        }
+        if let Some(limit) = options.limit {
+            archive.links.truncate(limit);
+        }
+        if authors.len() == 1 {
+            archive.author = authors.remove(0);
+        }
+        (archive, errors)
+    })
+}
+
+/// Reads the author, the article links and the link to the next page from a page of an
+/// archive. Without a pattern, the article links are the ones in the headings and `article`
+/// elements of the page that do not lead to other archive pages such as tags or categories.
+fn parse_archive_page(html: &str, page_url: &str, pattern: Option<&Regex>) -> ArchivePage {
+    let page_url = match Url::parse(page_url) {
+        Ok(url) => url,
+        Err(_) => return ArchivePage::default(),
+    };
+    let doc = kuchiki::parse_html().one(html);
+    let next_page = find_next_page(&doc, &page_url);
+    let selector = if pattern.is_some() {
+        "a[href]"
+    } else {
+        "article a[href], h1 a[href], h2 a[href], h3 a[href], h4 a[href], \
+        .post-title a[href], .entry-title a[href]"
+    };
+    let links = doc
+        .select(selector)
+        .unwrap()
+        .filter_map(|anchor| {
+            let attrs = anchor.attributes.borrow();
+            page_url.join(attrs.get("href")?).ok()
+        })
+        .filter(|link_url| link_url.scheme() == "http" || link_url.scheme() == "https")
+        .map(|mut link_url| {
+            link_url.set_fragment(None);
+            link_url
+        })
+        .filter(|link_url| match pattern {
+            Some(pattern) => pattern.is_match(link_url.as_str()),
+            None => {
+                link_url.host_str() == page_url.host_str()
+                    && !link_url.path().trim_end_matches('/').is_empty()
+                    && !ARCHIVE_SECTION_REGEX.is_match(link_url.path())
+            }
+        })
+        .filter(|link_url| link_url != &page_url && Some(link_url.as_str()) != next_page.as_deref())
+        .map(String::from)
+        .unique()
+        .collect();
+    ArchivePage {
+        author: archive_author(&doc),
+        site_name: doc
+            .select_first("meta[property=\"og:site_name\"][content]")
+            .ok()
+            .and_then(|meta| meta.attributes.borrow().get("content").map(str::to_owned)),
+        links,
+        next_page,
+    }
+}
+
+/// Finds the link to the next page of older articles of an archive
+fn find_next_page(doc: &NodeRef, page_url: &Url) -> Option<String> {
+    let rel_next = doc
+        .select("link[rel][href], a[rel][href]")
+        .unwrap()
+        .find(|elem| {
+            elem.attributes
+                .borrow()
+                .get("rel")
+                .map_or(false, |rel| rel.split_whitespace().any(|r| r == "next"))
+        });
+    let next_anchor = rel_next.or_else(|| {
+        doc.select("a[href]").unwrap().find(|anchor| {
+            let is_next_class = anchor
+                .attributes
+                .borrow()
+                .get("class")
+                .map_or(false, |classes| {
+                    classes
+                        .split_whitespace()
+                        .any(|class| class == "next" || class == "older-posts")
+                });
+            is_next_class || NEXT_PAGE_TEXT_REGEX.is_match(anchor.text_contents().trim())
+        })
+    })?;
+    let next_url = page_url
+        .join(next_anchor.attributes.borrow().get("href")?)
+        .ok()?;
+    if next_url.host_str() == page_url.host_str() && &next_url != page_url {
+        Some(next_url.to_string())
+    } else {
+        None
+    }
+}
+
+/// Returns the name of the author of an archive from its heading or title such as
+/// "Posts by Jane Doe" or "Jane Doe, Author at Example"
+fn archive_author(doc: &NodeRef) -> Option<String> {
+    lazy_static! {
+        static ref AUTHOR_PREFIX_REGEX: Regex = Regex::new(
+            r"(?i)^(?:all\s+)?(?:posts|articles|stories|writing|author)(?:\s+by)?\s*:?\s+"
+        )
+        .unwrap();
+        static ref AUTHOR_SUFFIX_REGEX: Regex =
+            Regex::new(r"(?i)(?:,?\s+(?:author|writer|contributor)\s+at\s+.*|\s+[|–—-]\s+.*)$")
+                .unwrap();
+    }
+    let heading = doc
+        .select_first("h1")
+        .ok()
+        .map(|h1| h1.text_contents())
+        .or_else(|| {
+            doc.select_first("title")
+                .ok()
+                .map(|title| title.text_contents())
+        })?;
+    let heading = heading.split_whitespace().collect::<Vec<_>>().join(" ");
+    let author = AUTHOR_SUFFIX_REGEX.replace(&heading, "");
+    let author = AUTHOR_PREFIX_REGEX.replace(&author, "");
+    if author.is_empty() {
+        None
+    } else {
+        Some(author.into_owned())
+    }
+}
+
+/// Returns the absolute urls of the links in an index page that are likely to be articles.
+/// When no pattern is given, a link is considered an article if it is on the same host and
+/// nested under the path of the index page e.g `/blog/some-post` for an index at `/blog/`,
+/// unless it leads to another listing such as `/blog/page/2` or `/blog/tag/rust`.
+fn extract_article_links(html: &str, index_url: &str, pattern: Option<&Regex>) -> Vec<String> {
+    let index_url = match Url::parse(index_url) {
+        Ok(url) => url,
+        Err(_) => return Vec::new(),
+    };
+    let index_path = index_url.path().trim_end_matches('/');
+    let doc = kuchiki::parse_html().one(html);
+    doc.select("a[href]")
+        .unwrap()
+        .filter_map(|anchor| {
+            let attrs = anchor.attributes.borrow();
+            index_url.join(attrs.get("href")?).ok()
+        })
+        .filter(|link_url| link_url.scheme() == "http" || link_url.scheme() == "https")
+        .map(|mut link_url| {
+            link_url.set_fragment(None);
+            link_url
+        })
+        .filter(|link_url| match pattern {
+            Some(pattern) => pattern.is_match(link_url.as_str()),
+            None => {
+                let link_path = link_url.path().trim_end_matches('/');
+                link_url.host_str() == index_url.host_str()
+                    && link_path.len() > index_path.len()
+                    && link_path.starts_with(index_path)
+                    && link_path[index_path.len()..].starts_with('/')
+                    && !ARCHIVE_SECTION_REGEX.is_match(&link_path[index_path.len()..])
+            }
+        })
+        .filter(|link_url| link_url != &index_url)
+        .map(|link_url| {
+            debug!("Found article link {}", link_url);
+            link_url.to_string()
+        })
+        .unique()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const INDEX_HTML: &str = r##"
+        <html>
+            <body>
+                <nav>
+                    <a href="/">Home</a>
+                    <a href="/about">About</a>
+                    <a href="/blog/">Blog</a>
+                </nav>
+                <ul>
+                    <li><a href="/blog/first-post">First post</a></li>
+                    <li><a href="second-post#comments">Second post</a></li>
+                    <li><a href="https://example.com/blog/first-post">First post again</a></li>
+                    <li><a href="https://other.example.org/blog/post">Elsewhere</a></li>
+                    <li><a href="mailto:writer@example.com">Contact</a></li>
+                    <li><a href="/blog/tag/rust/">Rust</a></li>
+                </ul>
+                <a href="/blog/page/2">Older posts</a>
+            </body>
+        </html>
+    "##;
+
+    const ARCHIVE_HTML: &str = r##"
+        <html>
+            <head>
+                <title>Jane Doe, Author at Example News</title>
+                <link rel="canonical" href="https://example.com/author/jane/">
+            </head>
+            <body>
+                <h1>Posts by Jane Doe</h1>
+                <article>
+                    <h2><a href="/2023/05/first-story">First story</a></h2>
+                    <a href="/author/jane/">Jane Doe</a>
+                    <a href="/category/science/">Science</a>
+                </article>
+                <article>
+                    <h2 class="entry-title"><a href="/2023/04/second-story#more">Second story</a></h2>
+                </article>
+                <aside><a href="/2022/01/popular-story">Popular story</a></aside>
+                <nav class="pagination"><a href="/author/jane/page/2/">Older posts &raquo;</a></nav>
+            </body>
+        </html>
+    "##;
+
+    #[test]
+    fn test_parse_archive_page() {
+        let page = parse_archive_page(ARCHIVE_HTML, "https://example.com/author/jane/", None);
+        assert_eq!(Some("Jane Doe"), page.author.as_deref());
+        assert_eq!(
+            vec![
+                "https://example.com/2023/05/first-story",
+                "https://example.com/2023/04/second-story",
+            ],
+            page.links
+        );
+        assert_eq!(
+            Some("https://example.com/author/jane/page/2/"),
+            page.next_page.as_deref()
+        );
+
+        let pattern = Regex::new(r"/\d{4}/\d{2}/").unwrap();
+        let page = parse_archive_page(
+            ARCHIVE_HTML,
+            "https://example.com/author/jane/",
+            Some(&pattern),
+        );
+        assert_eq!(3, page.links.len());
+
+        // The last page of an archive has no next page
+        let page = parse_archive_page(
+            "<title>Stories - Example</title><h2><a href='/a'>A</a></h2>",
+            "https://example.com/author/jane/page/9/",
+            None,
+        );
+        assert_eq!(Some("Stories"), page.author.as_deref());
+        assert_eq!(None, page.next_page);
+    }
+
+    #[test]
+    fn test_extract_article_links() {
+        let links = extract_article_links(INDEX_HTML, "https://example.com/blog/", None);
+        assert_eq!(
+            vec![
+                "https://example.com/blog/first-post",
+                "https://example.com/blog/second-post",
+            ],
+            links
+        );
+        // Only the part of the path below the index is checked for listings
+        let html = r#"<a href="/category/news/some-story">Story</a>
+            <a href="/category/news/page/2/">Next</a>"#;
+        assert_eq!(
+            vec!["https://example.com/category/news/some-story"],
+            extract_article_links(html, "https://example.com/category/news/", None)
+        );
+
+        let pattern = Regex::new(r"/blog/[a-z]+-post$").unwrap();
+        let links = extract_article_links(INDEX_HTML, "https://example.com/blog/", Some(&pattern));
+        assert_eq!(
+            vec![
+                "https://example.com/blog/first-post",
+                "https://example.com/blog/second-post",
+            ],
+            links
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use std::any::Any;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use url::Url;
+
+use crate::cli::DebugExtractOptions;
+use crate::epub::serialize_to_xhtml;
+use crate::errors::{ErrorKind, PaperoniError};
+use crate::extractor::{clean_internal_attrs, Article};
+use crate::ssml::article_to_ssml;
+
+/// How running the extraction and the serializers on an input ended
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// The article was extracted and serialized to HTML of the given length
+    Extracted(usize),
+    /// The extraction returned an error which is expected for pages without an article
+    Failed(String),
+    Panicked(String),
+    /// The extraction did not finish in time which most likely means it is stuck in a loop
+    TimedOut,
+}
+
+impl Outcome {
+    fn is_crash(&self) -> bool {
+        matches!(self, Outcome::Panicked(_) | Outcome::TimedOut)
+    }
+}
+
+/// Runs the extraction and serializers on an HTML file or on every file in a directory such as
+/// a fuzz corpus, printing how each of them ended. Inputs that panic or time out are reported
+/// as an error.
+pub fn run_debug_extract(options: &DebugExtractOptions) -> Result<(), PaperoniError> {
+    let path = Path::new(&options.input);
+    let inputs = if path.is_dir() {
+        let mut inputs = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        inputs.sort();
+        inputs
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let timeout = Duration::from_secs(options.timeout);
+    let mut crash_count = 0;
+    for input in &inputs {
+        // Fuzz inputs are arbitrary bytes so they are not required to be valid UTF-8
+        let html = String::from_utf8_lossy(&fs::read(input)?).into_owned();
+        let outcome = check_input(html, &file_url(input), timeout);
+        match &outcome {
+            Outcome::Extracted(len) => {
+                println!("{}: extracted {} bytes of HTML", input.display(), len)
+            }
+            Outcome::Failed(err) => println!("{}: failed with {}", input.display(), err),
+            Outcome::Panicked(msg) => println!("{}: PANICKED with {:?}", input.display(), msg),
+            Outcome::TimedOut => println!(
+                "{}: TIMED OUT after {} seconds",
+                input.display(),
+                options.timeout
+            ),
+        }
+        if outcome.is_crash() {
+            crash_count += 1;
+        }
+    }
+
+    if crash_count > 0 {
+        Err(ErrorKind::ReadabilityError(format!(
+            "{} of {} inputs crashed the extractor",
+            crash_count,
+            inputs.len()
+        ))
+        .into())
+    } else {
+        Ok(())
+    }
+}
+
+fn file_url(path: &Path) -> String {
+    fs::canonicalize(path)
+        .ok()
+        .and_then(|path| Url::from_file_path(path).ok())
+        .map(String::from)
+        .unwrap_or_else(|| "https://example.com/".to_owned())
+}
+
+/// Extracts and serializes the article of the HTML on its own thread so that a panic or an
+/// infinite loop is reported instead of taking down the caller
+pub fn check_input(html: String, url: &str, timeout: Duration) -> Outcome {
+    let (sender, receiver) = mpsc::channel();
+    let url = url.to_owned();
+    thread::spawn(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| extract_and_serialize(&html, &url)));
+        let outcome = match result {
+            Ok(Ok(len)) => Outcome::Extracted(len),
+            Ok(Err(err)) => Outcome::Failed(err.to_string()),
+            Err(payload) => Outcome::Panicked(panic_message(payload)),
+        };
+        sender.send(outcome).ok();
+    });
+    receiver.recv_timeout(timeout).unwrap_or(Outcome::TimedOut)
+}
+
+fn extract_and_serialize(html: &str, url: &str) -> Result<usize, PaperoniError> {
+    let mut article = Article::from_html(html, url);
+    article.extract_content()?;
+    clean_internal_attrs(article.node_ref());
+    let html = article.node_ref().to_string();
+    let mut xhtml = Vec::new();
+    serialize_to_xhtml(article.node_ref(), &mut xhtml)?;
+    article_to_ssml(&article);
+    Ok(html.len())
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.to_owned()
+    } else {
+        "unknown panic".to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    const TEST_HTML: &str = include_str!("../test_html/simple.html");
+    const TEST_URL: &str = "https://example.com/blog/post";
+    const TIMEOUT: Duration = Duration::from_secs(20);
+
+    const TAGS: &[&str] = &[
+        "div", "p", "span", "a", "img", "table", "tr", "td", "th", "tbody", "ul", "li", "h1", "h2",
+        "pre", "code", "figure", "picture", "section", "article", "br", "hr", "noscript", "svg",
+        "math", "iframe", "form", "base",
+    ];
+    const ATTRS: &[&str] = &[
+        "class", "id", "href", "src", "srcset", "data-src", "style", "rowspan", "colspan", "rel",
+        "itemprop", "lang", "dir", "hidden", "role",
+    ];
+
+    /// Generates documents from random trees of the elements and attributes the readability
+    /// algorithm looks at
+    fn html_document() -> impl Strategy<Value = String> {
+        let leaf = prop_oneof![
+            "[a-zA-Z0-9 ,.;:&#<>/\"']{0,60}",
+            Just("<br>".to_owned()),
+            Just("<img src=\"data:image/png;base64,AAAA\">".to_owned()),
+        ];
+        let tree = leaf.prop_recursive(5, 128, 8, |inner| {
+            (
+                prop::sample::select(TAGS),
+                prop::collection::vec((prop::sample::select(ATTRS), "[a-z0-9 %:/.#-]{0,12}"), 0..3),
+                prop::collection::vec(inner, 0..8),
+            )
+                .prop_map(|(tag, attrs, children)| {
+                    let attrs = attrs
+                        .iter()
+                        .map(|(name, value)| format!(" {}=\"{}\"", name, value))
+                        .collect::<String>();
+                    format!("<{0}{1}>{2}</{0}>", tag, attrs, children.concat())
+                })
+        });
+        tree.prop_map(|body| format!("<html><body>{}</body></html>", body))
+    }
+
+    /// Generates documents by replacing random ranges of a real article with markup fragments
+    fn mutated_document() -> impl Strategy<Value = String> {
+        prop::collection::vec(
+            (
+                any::<prop::sample::Index>(),
+                0..64usize,
+                "[<>/\"'=&#;a-z0-9 ]{0,12}",
+            ),
+            1..8,
+        )
+        .prop_map(|mutations| {
+            let mut chars = TEST_HTML.chars().collect::<Vec<_>>();
+            for (index, len, fragment) in mutations {
+                let start = index.index(chars.len());
+                let end = (start + len).min(chars.len());
+                chars.splice(start..end, fragment.chars());
+            }
+            chars.into_iter().collect()
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_generated_documents_do_not_crash(html in html_document()) {
+            let outcome = check_input(html, TEST_URL, TIMEOUT);
+            prop_assert!(!outcome.is_crash(), "{:?}", outcome);
+        }
+
+        #[test]
+        fn test_mutated_documents_do_not_crash(html in mutated_document()) {
+            let outcome = check_input(html, TEST_URL, TIMEOUT);
+            prop_assert!(!outcome.is_crash(), "{:?}", outcome);
+        }
+    }
+
+    #[test]
+    fn test_check_input() {
+        assert!(matches!(
+            check_input(TEST_HTML.to_owned(), TEST_URL, TIMEOUT),
+            Outcome::Extracted(len) if len > 0
+        ));
+        assert_eq!(
+            "index out of bounds",
+            panic_message(panic::catch_unwind(|| panic!("index out of bounds")).unwrap_err())
+        );
+    }
+}
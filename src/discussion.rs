@@ -0,0 +1,439 @@
+use kuchiki::{traits::*, NodeRef};
+use log::debug;
+use serde_json::Value;
+use url::Url;
+
+use crate::cli::DiscussionOptions;
+use crate::errors::{ErrorKind, PaperoniError};
+use crate::extractor::new_element;
+
+/// Id of the element holding the text of a self post so it can be extracted with a selector
+pub const SELF_POST_ID: &str = "paperoni-self-post";
+
+/// A discussion thread whose linked article is exported along with its comments
+#[derive(Clone, Debug, PartialEq)]
+pub enum Thread {
+    HackerNews(String),
+    Reddit(String),
+}
+
+impl Thread {
+    /// Recognizes the urls of Hacker News items and Reddit posts
+    pub fn from_url(url: &str) -> Option<Self> {
+        let url = Url::parse(url).ok()?;
+        let host = url.host_str()?.trim_start_matches("www.");
+        match host {
+            "news.ycombinator.com" if url.path() == "/item" => url
+                .query_pairs()
+                .find(|(key, _)| key == "id")
+                .map(|(_, id)| id.into_owned())
+                .filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+                .map(Thread::HackerNews),
+            "redd.it" => url
+                .path_segments()?
+                .next()
+                .filter(|id| !id.is_empty())
+                .map(|id| Thread::Reddit(id.to_owned())),
+            _ if host == "reddit.com" || host.ends_with(".reddit.com") => {
+                let mut segments = url.path_segments()?;
+                segments.find(|segment| *segment == "comments")?;
+                segments
+                    .next()
+                    .filter(|id| !id.is_empty())
+                    .map(|id| Thread::Reddit(id.to_owned()))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn site_name(&self) -> &'static str {
+        match self {
+            Thread::HackerNews(_) => "Hacker News",
+            Thread::Reddit(_) => "Reddit",
+        }
+    }
+
+    fn api_url(&self, options: &DiscussionOptions) -> String {
+        match self {
+            Thread::HackerNews(id) => format!("https://hn.algolia.com/api/v1/items/{}", id),
+            Thread::Reddit(id) => format!(
+                "https://www.reddit.com/comments/{}.json?raw_json=1&depth={}",
+                id, options.depth
+            ),
+        }
+    }
+}
+
+/// A thread fetched from the public API of its site
+#[derive(Clone, Debug, PartialEq)]
+pub struct Discussion {
+    pub thread_url: String,
+    pub site_name: &'static str,
+    pub title: String,
+    /// The article the thread links to. Self posts such as Ask HN have none
+    pub link: Option<String>,
+    /// The HTML of the text of a self post
+    pub text: Option<String>,
+    pub comments: Vec<DiscussionComment>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscussionComment {
+    pub author: String,
+    pub html: String,
+    /// Hacker News does not give the scores of comments
+    pub score: Option<i64>,
+    pub replies: Vec<DiscussionComment>,
+}
+
+impl Discussion {
+    /// An HTML document of a self post for extraction with the `SELF_POST_ID` selector
+    pub fn self_post_html(&self) -> String {
+        let title = NodeRef::new_text(&self.title).to_string();
+        format!(
+            r#"<html><head><title>{}</title></head><body><article id="{}">{}</article></body></html>"#,
+            title,
+            SELF_POST_ID,
+            self.text.as_deref().unwrap_or_default()
+        )
+    }
+
+    /// The comments as a section added at the end of the article. EPUB exports make it a
+    /// chapter of its own.
+    pub fn section(&self) -> NodeRef {
+        let section = new_element("section", &[("class", "paperoni-comments")]);
+        let heading = new_element("h2", &[]);
+        heading.append(NodeRef::new_text("Discussion"));
+        section.append(heading);
+        let source = new_element("p", &[("class", "paperoni-discussion-source")]);
+        let link = new_element("a", &[("href", &self.thread_url)]);
+        link.append(NodeRef::new_text(format!(
+            "View the discussion on {}",
+            self.site_name
+        )));
+        source.append(link);
+        section.append(source);
+        for comment in &self.comments {
+            section.append(comment_node(comment));
+        }
+        section
+    }
+}
+
+/// Fetches a thread with its comments from the public API of its site
+pub async fn fetch_discussion(
+    client: &surf::Client,
+    thread_url: &str,
+    thread: &Thread,
+    options: &DiscussionOptions,
+) -> Result<Discussion, PaperoniError> {
+    let api_url = thread.api_url(options);
+    debug!("Fetching the {} thread {}", thread.site_name(), api_url);
+    let req = surf::get(&api_url).header("User-Agent", "paperoni");
+    let mut res = client.send(req).await?;
+    if !res.status().is_success() {
+        let msg = format!(
+            "{} responded with {} for {}",
+            thread.site_name(),
+            res.status(),
+            thread_url
+        );
+        return Err(ErrorKind::HTTPError(msg).into());
+    }
+    let thread_json = res.body_json::<Value>().await?;
+    let discussion = match thread {
+        Thread::HackerNews(_) => parse_hacker_news_item(&thread_json, options),
+        Thread::Reddit(_) => parse_reddit_listings(&thread_json, options),
+    };
+    discussion
+        .map(|mut discussion| {
+            discussion.thread_url = thread_url.to_owned();
+            discussion
+        })
+        .ok_or_else(|| {
+            ErrorKind::HTTPError(format!(
+                "Unexpected response from {} for {}",
+                thread.site_name(),
+                thread_url
+            ))
+            .into()
+        })
+}
+
+fn parse_hacker_news_item(item: &Value, options: &DiscussionOptions) -> Option<Discussion> {
+    Some(Discussion {
+        thread_url: String::new(),
+        site_name: "Hacker News",
+        title: item["title"].as_str()?.to_owned(),
+        link: item["url"].as_str().map(ToOwned::to_owned),
+        text: item["text"].as_str().map(ToOwned::to_owned),
+        comments: hacker_news_comments(&item["children"], options, 1),
+    })
+}
+
+fn hacker_news_comments(
+    children: &Value,
+    options: &DiscussionOptions,
+    depth: usize,
+) -> Vec<DiscussionComment> {
+    if depth > options.depth {
+        return Vec::new();
+    }
+    children
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|child| {
+            // Deleted comments have no author or text
+            Some(DiscussionComment {
+                author: child["author"].as_str()?.to_owned(),
+                html: child["text"].as_str()?.to_owned(),
+                score: child["points"].as_i64(),
+                replies: hacker_news_comments(&child["children"], options, depth + 1),
+            })
+        })
+        .filter(|comment| is_above_min_score(comment, options))
+        .take(options.max_comments)
+        .collect()
+}
+
+/// Reddit returns the listing of the post followed by the listing of its comments
+fn parse_reddit_listings(listings: &Value, options: &DiscussionOptions) -> Option<Discussion> {
+    let post = &listings[0]["data"]["children"][0]["data"];
+    let is_self = post["is_self"].as_bool().unwrap_or(false);
+    Some(Discussion {
+        thread_url: String::new(),
+        site_name: "Reddit",
+        title: post["title"].as_str()?.to_owned(),
+        link: if is_self {
+            None
+        } else {
+            post["url"].as_str().map(ToOwned::to_owned)
+        },
+        text: post["selftext_html"].as_str().map(unescape_reddit_html),
+        comments: reddit_comments(&listings[1], options, 1),
+    })
+}
+
+fn reddit_comments(
+    listing: &Value,
+    options: &DiscussionOptions,
+    depth: usize,
+) -> Vec<DiscussionComment> {
+    if depth > options.depth {
+        return Vec::new();
+    }
+    listing["data"]["children"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        // Other kinds are links to load more comments
+        .filter(|child| child["kind"] == "t1")
+        .map(|child| &child["data"])
+        .filter(|data| data["author"] != "[deleted]")
+        .filter_map(|data| {
+            Some(DiscussionComment {
+                author: data["author"].as_str()?.to_owned(),
+                html: unescape_reddit_html(data["body_html"].as_str()?),
+                score: data["score"].as_i64(),
+                // Comments without replies have an empty string instead of a listing
+                replies: reddit_comments(&data["replies"], options, depth + 1),
+            })
+        })
+        .filter(|comment| is_above_min_score(comment, options))
+        .take(options.max_comments)
+        .collect()
+}
+
+/// Comments without a score, such as the ones of Hacker News, are always kept
+fn is_above_min_score(comment: &DiscussionComment, options: &DiscussionOptions) -> bool {
+    match (options.min_score, comment.score) {
+        (Some(min_score), Some(score)) => score >= min_score,
+        _ => true,
+    }
+}
+
+/// Reddit escapes the HTML of posts and comments unless it is asked for raw JSON
+fn unescape_reddit_html(html: &str) -> String {
+    if html.trim_start().starts_with("&lt;") {
+        kuchiki::parse_html()
+            .one(format!("<body>{}</body>", html))
+            .text_contents()
+    } else {
+        html.to_owned()
+    }
+}
+
+fn comment_node(comment: &DiscussionComment) -> NodeRef {
+    let node = new_element("article", &[("class", "paperoni-comment")]);
+    let meta = new_element("p", &[("class", "paperoni-comment-meta")]);
+    let author = new_element("strong", &[]);
+    author.append(NodeRef::new_text(&comment.author));
+    meta.append(author);
+    if let Some(score) = comment.score {
+        meta.append(NodeRef::new_text(format!(
+            " · {} point{}",
+            score,
+            if score == 1 { "" } else { "s" }
+        )));
+    }
+    node.append(meta);
+    let body = kuchiki::parse_html().one(format!("<body>{}</body>", comment.html));
+    if let Ok(body) = body.select_first("body") {
+        for child in body.as_node().children().collect::<Vec<_>>() {
+            node.append(child);
+        }
+    }
+    if !comment.replies.is_empty() {
+        let replies = new_element("div", &[("class", "paperoni-comment-replies")]);
+        for reply in &comment.replies {
+            replies.append(comment_node(reply));
+        }
+        node.append(replies);
+    }
+    node
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn options() -> DiscussionOptions {
+        DiscussionOptions {
+            max_comments: 2,
+            depth: 2,
+            min_score: None,
+        }
+    }
+
+    #[test]
+    fn test_thread_from_url() {
+        assert_eq!(
+            Some(Thread::HackerNews("8863".to_owned())),
+            Thread::from_url("https://news.ycombinator.com/item?id=8863")
+        );
+        assert_eq!(
+            Some(Thread::Reddit("abc123".to_owned())),
+            Thread::from_url("https://www.reddit.com/r/rust/comments/abc123/a_post/")
+        );
+        assert_eq!(
+            Some(Thread::Reddit("abc123".to_owned())),
+            Thread::from_url("https://old.reddit.com/comments/abc123")
+        );
+        assert_eq!(
+            Some(Thread::Reddit("abc123".to_owned())),
+            Thread::from_url("https://redd.it/abc123")
+        );
+        assert_eq!(None, Thread::from_url("https://news.ycombinator.com/news"));
+        assert_eq!(None, Thread::from_url("https://www.reddit.com/r/rust/"));
+        assert_eq!(None, Thread::from_url("https://example.com/item?id=1"));
+    }
+
+    #[test]
+    fn test_parse_hacker_news_item() {
+        let item = json!({
+            "title": "My YC app",
+            "url": "http://www.getdropbox.com/u/2/screencast.html",
+            "text": null,
+            "children": [
+                {"author": "a", "text": "<p>First</p>", "points": null, "children": [
+                    {"author": "b", "text": "Reply", "children": [
+                        {"author": "c", "text": "Too deep", "children": []}
+                    ]}
+                ]},
+                {"author": null, "text": null, "children": []},
+                {"author": "d", "text": "Second", "children": []},
+                {"author": "e", "text": "Third", "children": []}
+            ]
+        });
+        let discussion = parse_hacker_news_item(&item, &options()).unwrap();
+        assert_eq!(
+            Some("http://www.getdropbox.com/u/2/screencast.html"),
+            discussion.link.as_deref()
+        );
+        let authors = discussion
+            .comments
+            .iter()
+            .map(|comment| comment.author.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["a", "d"], authors);
+        assert_eq!(1, discussion.comments[0].replies.len());
+        assert!(discussion.comments[0].replies[0].replies.is_empty());
+    }
+
+    #[test]
+    fn test_parse_reddit_listings() {
+        let listings = json!([
+            {"data": {"children": [{"kind": "t3", "data": {
+                "title": "Ask r/rust", "is_self": true,
+                "url": "https://www.reddit.com/r/rust/comments/abc123/ask/",
+                "selftext_html": "&lt;div class=\"md\"&gt;&lt;p&gt;Question&lt;/p&gt;&lt;/div&gt;"
+            }}]}},
+            {"data": {"children": [
+                {"kind": "t1", "data": {"author": "low", "body_html": "<p>Meh</p>", "score": 1, "replies": ""}},
+                {"kind": "t1", "data": {"author": "[deleted]", "body_html": "<p>[removed]</p>", "score": 9, "replies": ""}},
+                {"kind": "t1", "data": {"author": "high", "body_html": "<p>Great</p>", "score": 42, "replies": {"data": {"children": [
+                    {"kind": "t1", "data": {"author": "reply", "body_html": "<p>Agreed</p>", "score": 7, "replies": ""}},
+                    {"kind": "more", "data": {"count": 3}}
+                ]}}}}
+            ]}}
+        ]);
+        let options = DiscussionOptions {
+            min_score: Some(5),
+            ..options()
+        };
+        let discussion = parse_reddit_listings(&listings, &options).unwrap();
+        assert_eq!(None, discussion.link);
+        assert_eq!(
+            Some(r#"<div class="md"><p>Question</p></div>"#),
+            discussion.text.as_deref()
+        );
+        assert_eq!(1, discussion.comments.len());
+        assert_eq!("high", discussion.comments[0].author);
+        assert_eq!("reply", discussion.comments[0].replies[0].author);
+    }
+
+    #[test]
+    fn test_discussion_section() {
+        let discussion = Discussion {
+            thread_url: "https://news.ycombinator.com/item?id=1".to_owned(),
+            site_name: "Hacker News",
+            title: "A <b>title</b>".to_owned(),
+            link: None,
+            text: Some("<p>Self text</p>".to_owned()),
+            comments: vec![DiscussionComment {
+                author: "a".to_owned(),
+                html: "<p>Top</p>".to_owned(),
+                score: Some(3),
+                replies: vec![DiscussionComment {
+                    author: "b".to_owned(),
+                    html: "Reply".to_owned(),
+                    score: None,
+                    replies: Vec::new(),
+                }],
+            }],
+        };
+        let section = discussion.section();
+        assert_eq!(
+            "Discussion",
+            section.select_first("h2").unwrap().text_contents()
+        );
+        assert_eq!(
+            2,
+            section.select("article.paperoni-comment").unwrap().count()
+        );
+        assert_eq!(
+            "a · 3 points",
+            section
+                .select_first(".paperoni-comment-meta")
+                .unwrap()
+                .text_contents()
+        );
+        assert!(discussion
+            .self_post_html()
+            .contains("<title>A &lt;b&gt;title&lt;/b&gt;</title>"));
+    }
+}
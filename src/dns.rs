@@ -0,0 +1,170 @@
+use std::convert::TryInto;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+/// How long a DNS server is waited on for each query
+const DNS_TIMEOUT: Duration = Duration::from_secs(5);
+const RECORD_A: u16 = 1;
+const RECORD_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+/// Resolves the IPv4 and IPv6 addresses of a host by querying a DNS server directly instead
+/// of the resolver of the system.
+pub fn lookup(server: SocketAddr, host: &str) -> io::Result<Vec<IpAddr>> {
+    let bind_addr: SocketAddr = if server.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind_addr)?;
+    socket.set_read_timeout(Some(DNS_TIMEOUT))?;
+    socket.connect(server)?;
+
+    let mut addresses = Vec::new();
+    for (idx, record_type) in [RECORD_A, RECORD_AAAA].iter().enumerate() {
+        let id = (std::process::id() as u16).wrapping_add(idx as u16);
+        socket.send(&build_query(id, host, *record_type)?)?;
+        let mut response = [0u8; 512];
+        let len = socket.recv(&mut response)?;
+        addresses.extend(parse_response(id, &response[..len])?);
+    }
+    if addresses.is_empty() {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} has no addresses", host),
+        ))
+    } else {
+        Ok(addresses)
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_owned())
+}
+
+/// Builds a recursive query for the records of a host
+fn build_query(id: u16, host: &str, record_type: u16) -> io::Result<Vec<u8>> {
+    let mut query = Vec::with_capacity(host.len() + 18);
+    query.extend_from_slice(&id.to_be_bytes());
+    // Only the recursion desired flag is set and there is a single question
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} is not a valid host name", host),
+            ));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&record_type.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+/// Reads the A and AAAA records from the answers of a response. Other records such as the
+/// CNAMEs leading to the addresses are skipped.
+fn parse_response(id: u16, response: &[u8]) -> io::Result<Vec<IpAddr>> {
+    let read_u16 = |pos: usize| -> io::Result<u16> {
+        response
+            .get(pos..pos + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .ok_or_else(|| invalid_data("truncated DNS response"))
+    };
+    if read_u16(0)? != id {
+        return Err(invalid_data("DNS response does not match the query"));
+    }
+    match read_u16(2)? & 0x000f {
+        0 => (),
+        3 => return Ok(Vec::new()),
+        rcode => {
+            return Err(invalid_data(&format!(
+                "DNS server responded with error code {}",
+                rcode
+            )))
+        }
+    }
+    let question_count = read_u16(4)?;
+    let answer_count = read_u16(6)?;
+
+    let mut pos = 12;
+    for _ in 0..question_count {
+        pos = skip_name(response, pos)? + 4;
+    }
+    let mut addresses = Vec::new();
+    for _ in 0..answer_count {
+        pos = skip_name(response, pos)?;
+        let record_type = read_u16(pos)?;
+        let data_len = read_u16(pos + 8)? as usize;
+        let data = response
+            .get(pos + 10..pos + 10 + data_len)
+            .ok_or_else(|| invalid_data("truncated DNS response"))?;
+        match (record_type, data.len()) {
+            (RECORD_A, 4) => {
+                let octets: [u8; 4] = data.try_into().unwrap();
+                addresses.push(IpAddr::V4(Ipv4Addr::from(octets)));
+            }
+            (RECORD_AAAA, 16) => {
+                let octets: [u8; 16] = data.try_into().unwrap();
+                addresses.push(IpAddr::V6(Ipv6Addr::from(octets)));
+            }
+            _ => (),
+        }
+        pos += 10 + data_len;
+    }
+    Ok(addresses)
+}
+
+/// Returns the position after a possibly compressed name
+fn skip_name(response: &[u8], mut pos: usize) -> io::Result<usize> {
+    loop {
+        let len = *response
+            .get(pos)
+            .ok_or_else(|| invalid_data("truncated DNS response"))?;
+        if len == 0 {
+            return Ok(pos + 1);
+        } else if len & 0xc0 == 0xc0 {
+            return Ok(pos + 2);
+        }
+        pos += len as usize + 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_query() {
+        let query = build_query(0x1234, "a.bc.", RECORD_A).unwrap();
+        assert_eq!(
+            vec![
+                0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0, 1, b'a', 2, b'b', b'c', 0, 0, 1, 0,
+                1
+            ],
+            query
+        );
+        assert!(build_query(1, "a..b", RECORD_A).is_err());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let mut response = build_query(7, "example.com", RECORD_A).unwrap();
+        // The answer count and the flags of a response
+        response[2] = 0x81;
+        response[3] = 0x80;
+        response[7] = 2;
+        // A CNAME pointing to the question name followed by an A record using compression
+        response.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 2, 0xc0, 12]);
+        response.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 10, 0, 0, 7]);
+        assert_eq!(
+            vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 7))],
+            parse_response(7, &response).unwrap()
+        );
+        assert!(parse_response(8, &response).is_err());
+        assert!(parse_response(7, &response[..response.len() - 2]).is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use log::info;
+
+use crate::cjk;
+use crate::extractor::Article;
+
+/// The number of words in each shingle hashed into the fingerprint of an article
+const SHINGLE_SIZE: usize = 3;
+/// Articles with fewer words are only duplicates when their text is the same as they have too
+/// few shingles for their fingerprints to be compared
+const MIN_FINGERPRINT_WORDS: usize = 50;
+/// The most bits two fingerprints can differ by for their articles to be duplicates. This lets
+/// syndicated posts with a different byline or footer through while unrelated articles differ
+/// by around half of the 64 bits.
+const MAX_DUPLICATE_DISTANCE: u32 = 8;
+
+/// An article left out of a merged export as its content is the same as another article's
+pub struct DuplicateArticle {
+    pub link: String,
+    pub duplicate_of: String,
+}
+
+/// Removes the articles whose content is the same or nearly the same as that of an article
+/// earlier in the list, such as syndicated posts and mirrors
+pub fn remove_duplicates(articles: &mut Vec<Article>) -> Vec<DuplicateArticle> {
+    let texts = articles
+        .iter()
+        .map(|article| article.node_ref().text_contents())
+        .collect::<Vec<_>>();
+    let duplicates = find_duplicates(&texts);
+    let duplicate_articles = duplicates
+        .iter()
+        .map(|(idx, original_idx)| {
+            info!(
+                "Leaving out {} as it is a duplicate of {}",
+                articles[*idx].url, articles[*original_idx].url
+            );
+            DuplicateArticle {
+                link: articles[*idx].url.clone(),
+                duplicate_of: articles[*original_idx].url.clone(),
+            }
+        })
+        .collect();
+
+    let mut idx = 0;
+    articles.retain(|_| {
+        let is_duplicate = duplicates
+            .iter()
+            .any(|(duplicate_idx, _)| *duplicate_idx == idx);
+        idx += 1;
+        !is_duplicate
+    });
+    duplicate_articles
+}
+
+/// Finds the texts that are the same or nearly the same as an earlier text. Returns the index
+/// of each duplicate with the index of the first text it duplicates.
+fn find_duplicates(texts: &[String]) -> Vec<(usize, usize)> {
+    let mut checksums: HashMap<u64, usize> = HashMap::new();
+    let mut fingerprints: Vec<(u64, usize)> = Vec::new();
+    let mut duplicates = Vec::new();
+    for (idx, text) in texts.iter().enumerate() {
+        let words = normalized_words(text);
+        if words.is_empty() {
+            continue;
+        }
+        let checksum = fnv1a(words.join(" ").as_bytes());
+        if let Some(original_idx) = checksums.get(&checksum) {
+            duplicates.push((idx, *original_idx));
+            continue;
+        }
+        checksums.insert(checksum, idx);
+        if words.len() < MIN_FINGERPRINT_WORDS {
+            continue;
+        }
+        let fingerprint = simhash(&words);
+        match fingerprints
+            .iter()
+            .find(|(other, _)| (fingerprint ^ other).count_ones() <= MAX_DUPLICATE_DISTANCE)
+        {
+            Some((_, original_idx)) => duplicates.push((idx, *original_idx)),
+            None => fingerprints.push((fingerprint, idx)),
+        }
+    }
+    duplicates
+}
+
+/// The lowercased words of a text without punctuation
+fn normalized_words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .flat_map(cjk::split_words)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The 64-bit FNV-1a hash, used as it stays the same across runs and platforms
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The simhash of the shingles of the words. Texts sharing most of their shingles have
+/// fingerprints differing by a few bits.
+fn simhash(words: &[String]) -> u64 {
+    let mut weights = [0i64; 64];
+    for shingle in words.windows(SHINGLE_SIZE) {
+        let hash = fnv1a(shingle.join(" ").as_bytes());
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash >> bit & 1 == 1 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    }
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0, |fingerprint, (bit, _)| fingerprint | 1 << bit)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PEPPERONI: &str = "Pepperoni is an American variety of spicy salami made from cured pork and beef seasoned with paprika or other chili pepper. Prior to cooking, pepperoni is characteristically soft, slightly smoky, and bright red. Thinly sliced pepperoni is one of the most popular pizza toppings in American pizzerias. The term pepperoni is a borrowing from peperoni, the plural of peperone, the Italian word for bell pepper. The first use of pepperoni to refer to a sausage dates to 1919. Pepperoni is usually made from a mixture of pork and beef trimmings. Its characteristic red color comes from the paprika. Pepperoni is a cured sausage and is preserved with salt and nitrates which prevent the growth of bacteria and add to its color. Pepperoni is made in large quantities and sold to pizzerias across the country, where it is often the most requested topping.";
+    const SALAMI: &str = "Salami is a cured sausage consisting of fermented and air-dried meat, typically pork. Historically, salami was popular among Southern, Eastern, and Central European peasants because it can be stored at room temperature for up to forty days once cut, supplementing a potentially meager or inconsistent supply of fresh meat. Countries and regions across Europe make their own traditional varieties of salami. The word salami comes from the Italian salame, the singular form of a noun meaning a type of salted meat. Salami is made from a mixture of ground meat and fat, with seasonings such as garlic, salt, spices and herbs, and sometimes wine or vinegar, and is then left to ferment and dry for a period of days to months in a cool place.";
+
+    #[test]
+    fn test_find_duplicates() {
+        let syndicated = format!("{} Originally published on the Example blog.", PEPPERONI);
+        let texts = vec![
+            PEPPERONI.to_owned(),
+            SALAMI.to_owned(),
+            syndicated,
+            // Texts differing only in whitespace and case are the same
+            SALAMI.to_uppercase().replace(". ", ".\n\n"),
+            "Short note".to_owned(),
+            "A short note".to_owned(),
+            String::new(),
+            String::new(),
+        ];
+        assert_eq!(vec![(2, 0), (3, 1)], find_duplicates(&texts));
+    }
+
+    #[test]
+    fn test_simhash_distance() {
+        let pepperoni = simhash(&normalized_words(PEPPERONI));
+        let salami = simhash(&normalized_words(SALAMI));
+        assert!((pepperoni ^ salami).count_ones() > MAX_DUPLICATE_DISTANCE);
+        let edited = simhash(&normalized_words(&PEPPERONI.replace("1919", "1920")));
+        assert!((pepperoni ^ edited).count_ones() <= MAX_DUPLICATE_DISTANCE);
+    }
+}
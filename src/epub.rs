@@ -1,243 +1,409 @@
 use std::collections::HashMap;
 use std::fs::File;
-
-use comfy_table::{Attribute, Cell, CellAlignment, Color, ContentArrangement, Table};
-use epub_builder::{EpubBuilder, EpubContent, TocElement, ZipLibrary};
-use html5ever::tendril::fmt::Slice;
-use indicatif::{ProgressBar, ProgressStyle};
-use kuchiki::NodeRef;
+use std::io::{Cursor, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use epub_builder::{EpubBuilder, EpubContent, ReferenceType, TocElement, ZipLibrary};
+use html5ever::{LocalName, Namespace, QualName};
+use itertools::Itertools;
+use kuchiki::{traits::*, NodeRef};
 use log::{debug, error, info};
-
-use crate::{cli::AppConfig, errors::PaperoniError, extractor::Article};
+use uuid::Uuid;
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    atomic_file::AtomicFile,
+    cli::{AppConfig, EpubCompression},
+    cover_collage::cover_collage,
+    errors::PaperoniError,
+    exporter::{file_safe_title, ExportFileNames, Exporter},
+    extractor::{clean_internal_attrs, link_merged_articles, Article, ResourceInfo},
+    i18n::{Locale, Message},
+    page_list::{collect_page_targets, insert_nav_page_list, insert_ncx_page_list, PageTarget},
+    source_qr::source_qr_svg,
+    typography::{apply_typography, cjk_font_file_name, cjk_font_mime, typography_css},
+};
+
+/// Extensions of image formats that are already compressed
+const COMPRESSED_IMAGE_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "gif", "webp"];
+/// How many non-merged epubs are zipped and written at the same time
+const EPUB_THREADS: usize = 4;
+const XHTML_NS: &str = "http://www.w3.org/1999/xhtml";
+const SVG_NS: &str = "http://www.w3.org/2000/svg";
+const MATHML_NS: &str = "http://www.w3.org/1998/Math/MathML";
+const XLINK_NS: &str = "http://www.w3.org/1999/xlink";
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+const XMLNS_NS: &str = "http://www.w3.org/2000/xmlns/";
+/// Elements that can never have content and must be self-closing in XHTML
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
 
 lazy_static! {
     static ref ESC_SEQ_REGEX: regex::Regex = regex::Regex::new(r#"(&|<|>|'|")"#).unwrap();
     static ref VALID_ATTR_CHARS_REGEX: regex::Regex = regex::Regex::new(r#"[a-z0-9\-_:]"#).unwrap();
 }
 
-pub fn generate_epubs(
-    articles: Vec<Article>,
-    app_config: &AppConfig,
-    successful_articles_table: &mut Table,
-) -> Result<(), Vec<PaperoniError>> {
-    if articles.is_empty() {
-        return Ok(());
+/// Merges the articles into a single epub with a chapter for each of them
+pub struct MergedEpubExporter<'a> {
+    app_config: &'a AppConfig,
+    name: &'a str,
+    epub: Option<EpubBuilder<ZipLibrary>>,
+    page_targets: Vec<PageTarget>,
+    written_files: Vec<String>,
+}
+
+impl<'a> MergedEpubExporter<'a> {
+    pub fn new(app_config: &'a AppConfig, name: &'a str) -> Self {
+        Self {
+            app_config,
+            name,
+            epub: None,
+            page_targets: Vec::new(),
+            written_files: Vec::new(),
+        }
     }
+}
 
-    let bar = if app_config.can_disable_progress_bar {
-        ProgressBar::hidden()
-    } else {
-        let enabled_bar = ProgressBar::new(articles.len() as u64);
-        let style = ProgressStyle::default_bar().template(
-            "{spinner:.cyan} [{elapsed_precise}] {bar:40.white} {:>8} epub {pos}/{len:7} {msg:.green}",
-        );
-        enabled_bar.set_style(style);
-        if !articles.is_empty() {
-            enabled_bar.set_message("Generating epubs");
+impl Exporter for MergedEpubExporter<'_> {
+    fn format(&self) -> &'static str {
+        "epub"
+    }
+
+    fn prepare(&mut self, articles: &[Article]) -> Result<(), PaperoniError> {
+        let app_config = self.app_config;
+        let name = self.name;
+        let mut epub = EpubBuilder::new(ZipLibrary::new()?)?;
+        debug!("Creating {:?}", name);
+
+        if app_config.inline_toc {
+            epub.inline_toc();
         }
-        enabled_bar
-    };
 
-    let mut errors: Vec<PaperoniError> = Vec::new();
-
-    match app_config.merged {
-        Some(ref name) => {
-            successful_articles_table.set_header(vec![Cell::new("Table of Contents")
-                .add_attribute(Attribute::Bold)
-                .set_alignment(CellAlignment::Center)
-                .fg(Color::Green)]);
-
-            let mut epub = match EpubBuilder::new(match ZipLibrary::new() {
-                Ok(zip_library) => zip_library,
-                Err(err) => {
-                    let mut paperoni_err: PaperoniError = err.into();
-                    paperoni_err.set_article_source(name);
-                    errors.push(paperoni_err);
-                    return Err(errors);
-                }
-            }) {
-                Ok(epub) => epub,
-                Err(err) => {
-                    let mut paperoni_err: PaperoniError = err.into();
-                    paperoni_err.set_article_source(name);
-                    errors.push(paperoni_err);
-                    return Err(errors);
-                }
-            };
-            debug!("Creating {:?}", name);
+        epub.metadata(
+            "title",
+            replace_escaped_characters(app_config.title.as_deref().unwrap_or(name)),
+        )?;
+        epub.set_uuid(book_uuid(
+            &articles
+                .iter()
+                .map(Article::canonical_url)
+                .collect::<Vec<_>>(),
+        ));
+        if let Some(author) = &app_config.author {
+            epub.metadata("author", replace_escaped_characters(author))?;
+        }
 
-            if app_config.inline_toc {
-                epub.inline_toc();
-            }
+        // The subjects of a merged epub are the keywords of all of its articles
+        let keywords = articles
+            .iter()
+            .flat_map(|article| article.metadata().keywords())
+            .unique_by(|keyword| keyword.to_lowercase());
+        for keyword in keywords {
+            epub.metadata("subject", replace_escaped_characters(keyword))?;
+        }
 
-            match add_stylesheets(&mut epub, app_config) {
-                Ok(_) => (),
-                Err(e) => {
-                    error!("Unable to add stylesheets to epub file");
-                    let mut paperoni_err: PaperoniError = e.into();
-                    paperoni_err.set_article_source(name);
-                    errors.push(paperoni_err);
-                    return Err(errors);
-                }
+        if let Err(err) = add_stylesheet(&mut epub, &stylesheet(app_config)) {
+            error!("Unable to add stylesheets to epub file");
+            return Err(err.into());
+        }
+        add_cjk_font(&mut epub, app_config.cjk_font.as_deref())?;
+
+        let digest_title = app_config.title.as_deref().unwrap_or_else(|| {
+            Path::new(name)
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or(name)
+        });
+        let digest_date = app_config.locale.format_date(&app_config.start_time);
+        if app_config.has_cover_collage {
+            let collage = cover_collage(digest_title, &digest_date, articles);
+            if let Err(err) = epub.add_cover_image("cover.svg", collage.as_bytes(), "image/svg+xml")
+            {
+                error!("Unable to add the cover collage to epub file");
+                return Err(err.into());
             }
-            articles
-                .iter()
-                .enumerate()
-                .fold(&mut epub, |epub, (idx, article)| {
-                    let mut article_result = || -> Result<(), PaperoniError> {
-                        let content_url = format!("article_{}.xhtml", idx);
-                        let mut xhtml_buf = Vec::new();
-                        let header_level_tocs =
-                            get_header_level_toc_vec(&content_url, article.node_ref());
-
-                        serialize_to_xhtml(article.node_ref(), &mut xhtml_buf)?;
-                        let xhtml_str = std::str::from_utf8(&xhtml_buf)?;
-                        let section_name = article.metadata().title();
-                        let mut content = EpubContent::new(&content_url, xhtml_str.as_bytes())
-                            .title(replace_escaped_characters(section_name));
-
-                        for toc_element in header_level_tocs {
-                            content = content.child(toc_element);
-                        }
-
-                        epub.metadata("title", replace_escaped_characters(name))?;
-                        epub.add_content(content)?;
-                        info!("Adding images for {:?}", name);
-                        article.img_urls.iter().for_each(|img| {
-                            // TODO: Add error handling and return errors as a vec
-                            let mut file_path = std::env::temp_dir();
-                            file_path.push(&img.0);
-
-                            let img_buf = File::open(&file_path).expect("Can't read file");
-                            epub.add_resource(
-                                file_path.file_name().unwrap(),
-                                img_buf,
-                                img.1.as_ref().unwrap(),
-                            )
-                            .unwrap();
-                        });
-                        info!("Added images for {:?}", name);
-                        Ok(())
-                    };
-                    if let Err(mut error) = article_result() {
-                        error.set_article_source(&article.url);
-                        errors.push(error);
-                    }
-                    bar.inc(1);
-                    successful_articles_table.add_row(vec![article.metadata().title()]);
-                    epub
-                });
-            let appendix = generate_appendix(articles.iter().collect());
+        }
+        if app_config.title_page {
+            let title_page = generate_title_page(
+                digest_title,
+                &digest_date,
+                &app_config.locale.article_count(articles.len()),
+            );
             if let Err(err) = epub.add_content(
-                EpubContent::new("appendix.xhtml", appendix.as_bytes())
-                    .title(replace_escaped_characters("Article Sources")),
+                EpubContent::new("title_page.xhtml", title_page.as_bytes())
+                    .title(replace_escaped_characters(digest_title))
+                    .reftype(ReferenceType::TitlePage),
             ) {
-                let mut paperoni_err: PaperoniError = err.into();
-                paperoni_err.set_article_source(&name);
-                errors.push(paperoni_err);
-                return Err(errors);
+                error!("Unable to add title page to epub file");
+                return Err(err.into());
             }
+        }
 
-            let mut out_file = File::create(&name).unwrap();
-            match epub.generate(&mut out_file) {
-                Ok(_) => (),
-                Err(err) => {
-                    let mut paperoni_err: PaperoniError = err.into();
-                    paperoni_err.set_article_source(&name);
-                    errors.push(paperoni_err);
-                    error!("Failed to generate epub: {}", name);
-                    bar.finish_with_message("epub generation failed\n");
-                    return Err(errors);
-                }
-            }
+        link_merged_articles(articles, |idx, fragment| match fragment {
+            Some(fragment) => format!("article_{}.xhtml#{}", idx, fragment),
+            None => format!("article_{}.xhtml", idx),
+        });
+        self.epub = Some(epub);
+        Ok(())
+    }
 
-            bar.finish_with_message("Generated epub\n");
-            debug!("Created {:?}", name);
-            println!("Created {:?}", name);
+    fn add_article(&mut self, idx: usize, article: &Article) -> Result<(), PaperoniError> {
+        let app_config = self.app_config;
+        let epub = self
+            .epub
+            .as_mut()
+            .expect("The epub is created before articles are added");
+        let content_url = format!("article_{}.xhtml", idx);
+        apply_typography(article.node_ref(), app_config, Some(idx + 1));
+        let mut xhtml_buf = Vec::new();
+        normalize_heading_levels(article.node_ref(), app_config.heading_offset);
+        add_landmarks(article.node_ref());
+        let comments_chapter = take_comments_chapter(article.node_ref())?;
+        self.page_targets
+            .extend(collect_page_targets(article.node_ref(), &content_url));
+        let header_level_tocs = if app_config.has_article_toc {
+            get_header_level_toc_vec(
+                &content_url,
+                article.node_ref(),
+                app_config.toc_depth as usize,
+            )
+        } else {
+            Vec::new()
+        };
+
+        clean_internal_attrs(article.node_ref());
+        serialize_to_xhtml(article.node_ref(), &mut xhtml_buf)?;
+        let xhtml_str = std::str::from_utf8(&xhtml_buf)?;
+        let section_name = article.metadata().title();
+        let mut content = EpubContent::new(&content_url, xhtml_str.as_bytes())
+            .title(replace_escaped_characters(section_name));
+
+        for toc_element in header_level_tocs {
+            content = content.child(toc_element);
         }
-        None => {
-            successful_articles_table
-                .set_header(vec![Cell::new("Downloaded articles")
-                    .add_attribute(Attribute::Bold)
-                    .set_alignment(CellAlignment::Center)
-                    .fg(Color::Green)])
-                .set_content_arrangement(ContentArrangement::Dynamic);
-
-            for article in &articles {
-                let mut result = || -> Result<(), PaperoniError> {
-                    let mut epub = EpubBuilder::new(ZipLibrary::new()?)?;
-                    let file_name = format!(
-                        "{}/{}.epub",
-                        app_config.output_directory.as_deref().unwrap_or("."),
-                        article
-                            .metadata()
-                            .title()
-                            .replace("/", " ")
-                            .replace("\\", " ")
-                    );
-                    debug!("Creating {:?}", file_name);
-                    let mut out_file = File::create(&file_name).unwrap();
-                    let mut xhtml_buf = Vec::new();
-                    let header_level_tocs =
-                        get_header_level_toc_vec("index.xhtml", article.node_ref());
-                    serialize_to_xhtml(article.node_ref(), &mut xhtml_buf)
-                        .expect("Unable to serialize to xhtml");
-                    let xhtml_str = std::str::from_utf8(&xhtml_buf).unwrap();
-
-                    if let Some(author) = article.metadata().byline() {
-                        epub.metadata("author", replace_escaped_characters(author))?;
-                    }
 
-                    add_stylesheets(&mut epub, app_config)?;
-                    let title = replace_escaped_characters(article.metadata().title());
-                    epub.metadata("title", &title)?;
+        epub.add_content(content)?;
+        if let Some((comments_heading, comments_xhtml)) = comments_chapter {
+            epub.add_content(
+                EpubContent::new(format!("comments_{}.xhtml", idx), comments_xhtml.as_slice())
+                    .title(replace_escaped_characters(&format!(
+                        "{}: {}",
+                        comments_heading, section_name
+                    ))),
+            )?;
+        }
+        info!("Adding images for {:?}", self.name);
+        for img in &article.img_urls {
+            let mut file_path = std::env::temp_dir();
+            file_path.push(&img.0);
+
+            let img_buf = File::open(&file_path)?;
+            epub.add_resource(
+                file_path.file_name().unwrap(),
+                img_buf,
+                img.1.as_ref().unwrap(),
+            )?;
+        }
+        info!("Added images for {:?}", self.name);
+        Ok(())
+    }
 
-                    let mut content =
-                        EpubContent::new("index.xhtml", xhtml_str.as_bytes()).title(title);
+    fn finalize(
+        &mut self,
+        articles: &[Article],
+    ) -> Result<(), Vec<(Option<usize>, PaperoniError)>> {
+        let name = self.name;
+        let mut epub = self
+            .epub
+            .take()
+            .expect("The epub is created before it is finalized");
+        let locale = self.app_config.locale;
+        let (appendix, source_qr_codes) = generate_appendix(
+            articles.iter().collect(),
+            self.app_config.has_source_qr,
+            locale,
+        );
+        let has_images = articles.iter().any(|article| !article.img_urls.is_empty());
+        let mut epub_buf = Vec::new();
+        let generate_result = add_source_qr_codes(&mut epub, &source_qr_codes)
+            .and_then(|_| {
+                epub.add_content(
+                    EpubContent::new("appendix.xhtml", appendix.as_bytes()).title(
+                        replace_escaped_characters(locale.text(Message::ArticleSources)),
+                    ),
+                )?;
+                epub.generate(&mut epub_buf)
+            })
+            .map_err(PaperoniError::from)
+            .and_then(|_| {
+                let mut out_file = AtomicFile::create(name)?;
+                post_process_epub(
+                    &epub_buf,
+                    has_images,
+                    &self.page_targets,
+                    self.app_config.epub_compression,
+                    &mut out_file,
+                )?;
+                out_file.persist()?;
+                Ok(())
+            });
+        if let Err(err) = generate_result {
+            error!("Failed to generate epub: {}", name);
+            return Err(vec![(None, err)]);
+        }
 
-                    for toc_element in header_level_tocs {
-                        content = content.child(toc_element);
-                    }
+        debug!("Created {:?}", name);
+        println!("Created {:?}", name);
+        self.written_files.push(name.to_owned());
+        Ok(())
+    }
+
+    fn written_files(&self) -> &[String] {
+        &self.written_files
+    }
 
-                    epub.add_content(content)?;
+    fn article_file(&self, _idx: usize) -> Option<&str> {
+        // Every article is in the merged file once it is written
+        Some(self.name).filter(|name| self.written_files.iter().any(|file| file == name))
+    }
+}
 
-                    for img in &article.img_urls {
-                        let mut file_path = std::env::temp_dir();
-                        file_path.push(&img.0);
+/// Exports each article to its own epub. The epubs are zipped and written on worker threads
+/// while the next articles are serialized.
+pub struct EpubExporter<'a> {
+    app_config: &'a AppConfig,
+    job_sender: Option<mpsc::SyncSender<(usize, EpubJob)>>,
+    result_receiver: Option<mpsc::Receiver<(usize, Result<(), PaperoniError>)>>,
+    workers: Vec<thread::JoinHandle<()>>,
+    file_names: ExportFileNames,
+    /// The files of the articles sent to the workers by their index
+    pending_files: HashMap<usize, String>,
+    /// The files of the articles written by the workers by their index
+    article_files: HashMap<usize, String>,
+    written_files: Vec<String>,
+}
 
-                        let img_buf = File::open(&file_path).expect("Can't read image file");
-                        epub.add_resource(
-                            file_path.file_name().unwrap(),
-                            img_buf,
-                            img.1.as_ref().unwrap(),
-                        )?;
+impl<'a> EpubExporter<'a> {
+    pub fn new(app_config: &'a AppConfig) -> Self {
+        Self {
+            app_config,
+            job_sender: None,
+            result_receiver: None,
+            workers: Vec::new(),
+            file_names: ExportFileNames::new(app_config.overwrite),
+            pending_files: HashMap::new(),
+            article_files: HashMap::new(),
+            written_files: Vec::new(),
+        }
+    }
+}
+
+impl Exporter for EpubExporter<'_> {
+    fn format(&self) -> &'static str {
+        "epub"
+    }
+
+    fn prepare(&mut self, articles: &[Article]) -> Result<(), PaperoniError> {
+        let stylesheet = Arc::new(stylesheet(self.app_config));
+        let compression = self.app_config.epub_compression;
+        // Adding articles waits for the workers once a few jobs are queued so that the
+        // serialized articles do not pile up in memory when zipping is the slower part.
+        // The results are not bounded so the workers never wait on them.
+        let (job_sender, job_receiver) = mpsc::sync_channel::<(usize, EpubJob)>(EPUB_THREADS);
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let (result_sender, result_receiver) = mpsc::channel();
+        self.workers = (0..EPUB_THREADS.min(articles.len()))
+            .map(|_| {
+                let job_receiver = Arc::clone(&job_receiver);
+                let result_sender = result_sender.clone();
+                let stylesheet = Arc::clone(&stylesheet);
+                thread::spawn(move || loop {
+                    let job = job_receiver.lock().unwrap().recv();
+                    let (idx, job) = match job {
+                        Ok(job) => job,
+                        Err(_) => break,
+                    };
+                    let file_name = job.file_name.clone();
+                    let result = write_epub(job, &stylesheet, compression);
+                    if result.is_ok() {
+                        debug!("Created {:?}", file_name);
+                    }
+                    if result_sender.send((idx, result)).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+        self.job_sender = Some(job_sender);
+        self.result_receiver = Some(result_receiver);
+        Ok(())
+    }
+
+    // The documents of the articles cannot be sent to other threads so they are serialized
+    // here while the earlier articles are zipped by the workers
+    fn add_article(&mut self, idx: usize, article: &Article) -> Result<(), PaperoniError> {
+        let output_dir = self.app_config.output_directory.as_deref().unwrap_or(".");
+        let file_name = match self
+            .file_names
+            .target(output_dir, &file_safe_title(article), "epub")
+        {
+            Some(file_name) => file_name,
+            None => return Ok(()),
+        };
+        let job = prepare_epub(article, self.app_config, file_name)?;
+        self.pending_files.insert(idx, job.file_name.clone());
+        self.job_sender
+            .as_ref()
+            .expect("The epub threads are started before articles are added")
+            .send((idx, job))
+            .expect("The epub threads stopped unexpectedly");
+        Ok(())
+    }
+
+    fn finalize(
+        &mut self,
+        _articles: &[Article],
+    ) -> Result<(), Vec<(Option<usize>, PaperoniError)>> {
+        drop(self.job_sender.take());
+        let results = self
+            .result_receiver
+            .take()
+            .map(|result_receiver| result_receiver.iter().collect::<Vec<_>>())
+            .unwrap_or_default();
+        for worker in self.workers.drain(..) {
+            if let Err(panic) = worker.join() {
+                std::panic::resume_unwind(panic);
+            }
+        }
+        let mut errors = Vec::new();
+        for (idx, result) in results.into_iter().sorted_by_key(|(idx, _)| *idx) {
+            match result {
+                Ok(_) => {
+                    if let Some(file_name) = self.pending_files.remove(&idx) {
+                        self.written_files.push(file_name.clone());
+                        self.article_files.insert(idx, file_name);
                     }
-                    let appendix = generate_appendix(vec![&article]);
-                    epub.add_content(
-                        EpubContent::new("appendix.xhtml", appendix.as_bytes())
-                            .title(replace_escaped_characters("Article Source")),
-                    )?;
-                    epub.generate(&mut out_file)?;
-                    bar.inc(1);
-
-                    successful_articles_table.add_row(vec![article.metadata().title()]);
-
-                    debug!("Created {:?}", file_name);
-                    Ok(())
-                };
-                if let Err(mut error) = result() {
-                    error.set_article_source(&article.url);
-                    errors.push(error);
                 }
+                Err(err) => errors.push((Some(idx), err)),
             }
-            bar.finish_with_message("Generated epubs\n");
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
         }
     }
 
-    if errors.is_empty() {
-        Ok(())
-    } else {
-        Err(errors)
+    fn written_files(&self) -> &[String] {
+        &self.written_files
+    }
+
+    fn article_file(&self, idx: usize) -> Option<&str> {
+        self.article_files.get(&idx).map(String::as_str)
     }
 }
 
@@ -249,55 +415,334 @@ fn replace_escaped_characters(value: &str) -> String {
         .replace(">", "&gt;")
 }
 
-fn add_stylesheets<T: epub_builder::Zip>(
-    epub: &mut EpubBuilder<T>,
+/// The article of a non-merged epub with everything needed to write it. The content is read
+/// from the document of the article beforehand so that the epub can be zipped on another thread.
+struct EpubJob {
+    file_name: String,
+    title: String,
+    author: Option<String>,
+    keywords: Vec<String>,
+    xhtml: Vec<u8>,
+    header_level_tocs: Vec<TocElement>,
+    comments_chapter: Option<(String, Vec<u8>)>,
+    page_targets: Vec<PageTarget>,
+    img_urls: Vec<ResourceInfo>,
+    cover_img: Option<ResourceInfo>,
+    uuid: Uuid,
+    appendix: String,
+    appendix_title: &'static str,
+    /// The SVG images of the QR codes in the appendix with their file names
+    source_qr_codes: Vec<(String, String)>,
+    cjk_font: Option<PathBuf>,
+}
+
+/// Applies the options to the document of an article and serializes it for its epub
+fn prepare_epub(
+    article: &Article,
     app_config: &AppConfig,
-) -> Result<(), epub_builder::Error> {
+    file_name: String,
+) -> Result<EpubJob, PaperoniError> {
+    apply_typography(article.node_ref(), app_config, None);
+    let mut xhtml_buf = Vec::new();
+    normalize_heading_levels(article.node_ref(), app_config.heading_offset);
+    add_landmarks(article.node_ref());
+    let comments_chapter = take_comments_chapter(article.node_ref())?;
+    let page_targets = collect_page_targets(article.node_ref(), "index.xhtml");
+    let header_level_tocs = if app_config.has_article_toc {
+        get_header_level_toc_vec(
+            "index.xhtml",
+            article.node_ref(),
+            app_config.toc_depth as usize,
+        )
+    } else {
+        Vec::new()
+    };
+    clean_internal_attrs(article.node_ref());
+    serialize_to_xhtml(article.node_ref(), &mut xhtml_buf)?;
+    let (appendix, source_qr_codes) =
+        generate_appendix(vec![article], app_config.has_source_qr, app_config.locale);
+    Ok(EpubJob {
+        file_name,
+        title: replace_escaped_characters(article.metadata().title()),
+        author: article.metadata().byline().map(replace_escaped_characters),
+        keywords: article
+            .metadata()
+            .keywords()
+            .iter()
+            .map(|keyword| replace_escaped_characters(keyword))
+            .collect(),
+        xhtml: xhtml_buf,
+        header_level_tocs,
+        comments_chapter,
+        page_targets,
+        img_urls: article.img_urls.clone(),
+        cover_img: article.cover_img.clone(),
+        uuid: book_uuid(&[article.canonical_url()]),
+        appendix,
+        appendix_title: app_config.locale.text(Message::ArticleSources),
+        source_qr_codes,
+        cjk_font: app_config.cjk_font.clone(),
+    })
+}
+
+/// The unique identifier of the epub of the articles, which is a UUID of their canonical urls
+/// rather than a random one. Readers and libraries then recognize the epubs exported again
+/// from the same articles as the same book.
+fn book_uuid(canonical_urls: &[String]) -> Uuid {
+    Uuid::new_v5(&Uuid::NAMESPACE_URL, canonical_urls.join("\n").as_bytes())
+}
+
+/// Zips the epub of an article and writes it to its file
+fn write_epub(
+    job: EpubJob,
+    stylesheet: &[u8],
+    compression: EpubCompression,
+) -> Result<(), PaperoniError> {
+    let mut epub = EpubBuilder::new(ZipLibrary::new()?)?;
+    epub.set_uuid(job.uuid);
+    if let Some(author) = &job.author {
+        epub.metadata("author", author)?;
+    }
+    for keyword in &job.keywords {
+        epub.metadata("subject", keyword)?;
+    }
+    add_stylesheet(&mut epub, stylesheet)?;
+    add_cjk_font(&mut epub, job.cjk_font.as_deref())?;
+    if let Some((cover_path, cover_mime)) = &job.cover_img {
+        let mut file_path = std::env::temp_dir();
+        file_path.push(cover_path);
+        let cover_name = format!(
+            "cover.{}",
+            file_path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or("png")
+        );
+        let cover_buf = File::open(&file_path)?;
+        epub.add_cover_image(
+            cover_name,
+            cover_buf,
+            cover_mime.as_deref().unwrap_or("image/png"),
+        )?;
+    }
+    epub.metadata("title", &job.title)?;
+
+    let mut content =
+        EpubContent::new("index.xhtml", job.xhtml.as_slice()).title(job.title.as_str());
+    for toc_element in job.header_level_tocs {
+        content = content.child(toc_element);
+    }
+    epub.add_content(content)?;
+    if let Some((comments_heading, comments_xhtml)) = &job.comments_chapter {
+        epub.add_content(
+            EpubContent::new("comments.xhtml", comments_xhtml.as_slice())
+                .title(replace_escaped_characters(comments_heading)),
+        )?;
+    }
+
+    for img in &job.img_urls {
+        let mut file_path = std::env::temp_dir();
+        file_path.push(&img.0);
+
+        let img_buf = File::open(&file_path)?;
+        epub.add_resource(
+            file_path.file_name().unwrap(),
+            img_buf,
+            img.1.as_ref().unwrap(),
+        )?;
+    }
+    add_source_qr_codes(&mut epub, &job.source_qr_codes)?;
+    epub.add_content(
+        EpubContent::new("appendix.xhtml", job.appendix.as_bytes())
+            .title(replace_escaped_characters(job.appendix_title)),
+    )?;
+    let mut epub_buf = Vec::new();
+    epub.generate(&mut epub_buf)?;
+    let mut out_file = AtomicFile::create(&job.file_name)?;
+    post_process_epub(
+        &epub_buf,
+        !job.img_urls.is_empty(),
+        &job.page_targets,
+        compression,
+        &mut out_file,
+    )?;
+    out_file.persist()?;
+    Ok(())
+}
+
+/// The stylesheets of the enabled CSS and typography options
+fn stylesheet(app_config: &AppConfig) -> Vec<u8> {
     let body_stylesheet: &[u8] = include_bytes!("./assets/body.min.css");
     let header_stylesheet: &[u8] = include_bytes!("./assets/headers.min.css");
-    match app_config.css_config {
-        crate::cli::CSSConfig::All => {
-            epub.stylesheet([header_stylesheet, body_stylesheet].concat().as_bytes())?;
-            Ok(())
-        }
-        crate::cli::CSSConfig::NoHeaders => {
-            epub.stylesheet(body_stylesheet.as_bytes())?;
-            Ok(())
-        }
-        _ => Ok(()),
+    let mut stylesheet = match app_config.css_config {
+        crate::cli::CSSConfig::All => [header_stylesheet, body_stylesheet].concat(),
+        crate::cli::CSSConfig::NoHeaders => body_stylesheet.to_vec(),
+        _ => Vec::new(),
+    };
+    stylesheet.extend_from_slice(typography_css(app_config).as_bytes());
+    stylesheet
+}
+
+fn add_stylesheet<T: epub_builder::Zip>(
+    epub: &mut EpubBuilder<T>,
+    stylesheet: &[u8],
+) -> Result<(), epub_builder::Error> {
+    if !stylesheet.is_empty() {
+        epub.stylesheet(stylesheet)?;
     }
+    Ok(())
+}
+
+/// Embeds the font passed to --cjk-font which the stylesheet uses for CJK articles
+fn add_cjk_font<T: epub_builder::Zip>(
+    epub: &mut EpubBuilder<T>,
+    font_path: Option<&Path>,
+) -> Result<(), PaperoniError> {
+    if let Some(font_path) = font_path {
+        let font_file = File::open(font_path)?;
+        epub.add_resource(
+            cjk_font_file_name(font_path),
+            font_file,
+            cjk_font_mime(font_path).unwrap_or("font/otf"),
+        )?;
+    }
+    Ok(())
+}
+
+/// Generates the title page of a merged epub showing its title, the date it was
+/// generated and the number of articles it contains
+fn generate_title_page(title: &str, date: &str, article_count: &str) -> String {
+    format!(
+        r#"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+    <head>
+        <link rel="stylesheet" href="stylesheet.css" type="text/css"/>
+        <style>.title-page{{margin-top:30%;text-align:center}}.title-page h1{{margin-bottom:2em}}</style>
+    </head>
+    <body>
+        <section class="title-page" epub:type="titlepage">
+            <h1>{}</h1>
+            <p>{}</p>
+            <p>{}</p>
+        </section>
+    </body>
+</html>"#,
+        replace_escaped_characters(title),
+        replace_escaped_characters(date),
+        replace_escaped_characters(article_count)
+    )
 }
 
 //TODO: The type signature of the argument should change as it requires that merged articles create an entirely new Vec of references
-fn generate_appendix(articles: Vec<&Article>) -> String {
+/// Generates the appendix listing the sources of the articles. With `has_source_qr`, a QR code
+/// of each source is shown under its link and the SVG images of the QR codes are returned with
+/// their file names to be added to the epub.
+fn generate_appendix(
+    articles: Vec<&Article>,
+    has_source_qr: bool,
+    locale: Locale,
+) -> (String, Vec<(String, String)>) {
+    let mut source_qr_codes = Vec::new();
     let link_tags: String = articles
         .iter()
-        .map(|article| {
+        .enumerate()
+        .map(|(idx, article)| {
             let article_name = if !article.metadata().title().is_empty() {
                 article.metadata().title()
             } else {
                 &article.url
             };
-            format!(
-                "<a href=\"{}\">{}</a><br></br>",
+            let link = format!(
+                "<a href=\"{}\">{}</a>",
                 replace_escaped_characters(&article.url),
                 replace_escaped_characters(article_name)
-            )
+            );
+            let link_tag = match article.redirected_from() {
+                Some(requested_url) => format!(
+                    "{} ({} <a href=\"{}\">{}</a>)<br/>",
+                    link,
+                    locale.text(Message::RedirectedFrom),
+                    replace_escaped_characters(requested_url),
+                    replace_escaped_characters(requested_url)
+                ),
+                None => link + "<br/>",
+            };
+            let source_qr = if has_source_qr {
+                source_qr_svg(&article.url)
+            } else {
+                None
+            };
+            match source_qr {
+                Some(svg) => {
+                    let file_name = format!("source_qr_{}.svg", idx);
+                    let img_tag = format!(
+                        "<img class=\"source-qr\" src=\"{}\" alt=\"QR code of {}\"/><br/>",
+                        file_name,
+                        replace_escaped_characters(&article.url)
+                    );
+                    source_qr_codes.push((file_name, svg));
+                    link_tag + &img_tag
+                }
+                None => link_tag,
+            }
         })
         .collect();
     let template = format!(
         r#"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
     <head>
-        <link rel="stylesheet" href="stylesheet.css" type="text/css"></link>
+        <link rel="stylesheet" href="stylesheet.css" type="text/css"/>
     </head>
     <body>
-        <h2>Appendix</h2><h3>Article sources</h3>
+        <section role="doc-appendix">
+        <h2>{}</h2><h3>{}</h3>
         {}
+        </section>
     </body>
 </html>"#,
+        locale.text(Message::Appendix),
+        locale.text(Message::ArticleSources),
         link_tags
     );
-    template
+    (template, source_qr_codes)
+}
+
+fn add_source_qr_codes<T: epub_builder::Zip>(
+    epub: &mut EpubBuilder<T>,
+    source_qr_codes: &[(String, String)],
+) -> Result<(), epub_builder::Error> {
+    for (file_name, svg) in source_qr_codes {
+        epub.add_resource(file_name, svg.as_bytes(), "image/svg+xml")?;
+    }
+    Ok(())
+}
+
+/// Moves the comments captured with `--include-comments` or the discussion of a Hacker News
+/// or Reddit thread out of an article into a chapter of their own that follows it. Returns
+/// the heading of the comments with the XHTML of the chapter.
+fn take_comments_chapter(root_node: &NodeRef) -> Result<Option<(String, Vec<u8>)>, PaperoniError> {
+    let comments = match root_node.select_first("section.paperoni-comments") {
+        Ok(comments) => comments.as_node().clone(),
+        Err(_) => return Ok(None),
+    };
+    comments.detach();
+    let heading = comments
+        .select_first("h2")
+        .map(|heading| heading.text_contents().trim().to_owned())
+        .unwrap_or_else(|_| "Comments".to_owned());
+    let doc = kuchiki::parse_html().one(
+        r#"<!DOCTYPE html>
+        <html>
+            <head>
+                <link rel="stylesheet" href="stylesheet.css" type="text/css">
+            </head>
+            <body>
+            </body>
+        </html>"#,
+    );
+    doc.select_first("body").unwrap().as_node().append(comments);
+    clean_internal_attrs(&doc);
+    let mut xhtml_buf = Vec::new();
+    serialize_to_xhtml(&doc, &mut xhtml_buf)?;
+    Ok(Some((heading, xhtml_buf)))
 }
 
 /// Adds an id attribute to header elements and assigns a value based on
@@ -306,7 +751,7 @@ fn generate_appendix(articles: Vec<&Article>) -> String {
 /// readability removes headers with no text.
 fn generate_header_ids(root_node: &NodeRef) {
     let headers = root_node
-        .select("h1, h2, h3, h4")
+        .select("h1, h2, h3, h4, h5, h6")
         .expect("Unable to create selector for headings");
     let headers_no_id = headers.filter(|node_data_ref| {
         let attrs = node_data_ref.attributes.borrow();
@@ -327,28 +772,196 @@ fn generate_header_ids(root_node: &NodeRef) {
     }
 }
 
-/// Returns a vector of `TocElement` from a NodeRef used for adding to the Table of Contents for navigation
-fn get_header_level_toc_vec(content_url: &str, article: &NodeRef) -> Vec<TocElement> {
+/// Rebases the levels of the headings so that there are no jumps such as from an h1 to an h4
+/// as screen readers and the TOC builder use the levels to navigate. The relative order of the
+/// levels is kept and the top-level headings become h1 moved down by `heading_offset` levels.
+fn normalize_heading_levels(root_node: &NodeRef, heading_offset: u8) {
+    // Each entry is the original level of a heading and the level it was changed to
+    let mut level_stack: Vec<(usize, usize)> = Vec::new();
+    let headings = root_node
+        .select("h1, h2, h3, h4, h5, h6")
+        .unwrap()
+        .collect::<Vec<_>>();
+    for heading in headings {
+        let level = heading.name.local[1..].parse::<usize>().unwrap();
+        while matches!(level_stack.last(), Some((prev_level, _)) if *prev_level >= level) {
+            level_stack.pop();
+        }
+        let new_level = level_stack.last().map_or(1, |(_, new_level)| new_level + 1);
+        level_stack.push((level, new_level));
+        let offset_level = (new_level + heading_offset as usize).min(6);
+        if offset_level != level {
+            rename_element(heading.as_node(), &format!("h{}", offset_level));
+        }
+    }
+}
+
+/// Replaces an element with one of a different tag name while keeping its attributes and children
+fn rename_element(node_ref: &NodeRef, name: &str) {
+    let elem = node_ref.as_element().unwrap();
+    let renamed = NodeRef::new_element(
+        QualName::new(None, elem.name.ns.clone(), LocalName::from(name)),
+        elem.attributes
+            .borrow()
+            .map
+            .iter()
+            .map(|(name, attr)| (name.clone(), attr.clone())),
+    );
+    for child in node_ref.children() {
+        renamed.append(child);
+    }
+    node_ref.insert_after(renamed);
+    node_ref.detach();
+}
+
+/// Marks the article content as the main landmark for assistive technologies
+fn add_landmarks(root_node: &NodeRef) {
+    if let Ok(page) = root_node.select_first("div[id=\"readability-page-1\"]") {
+        page.attributes
+            .borrow_mut()
+            .insert("role", "main".to_string());
+    }
+}
+
+/// Copies a generated epub to the writer with accessibility metadata added to its package
+/// document and the printed pages of its articles added to its navigation. epub-builder does
+/// not support adding custom metadata or page lists so the files are edited after they are
+/// generated.
+fn post_process_epub<W: Write + Seek>(
+    epub_buf: &[u8],
+    has_images: bool,
+    page_targets: &[PageTarget],
+    compression: EpubCompression,
+    writer: W,
+) -> Result<(), PaperoniError> {
+    let mut archive = ZipArchive::new(Cursor::new(epub_buf))?;
+    let mut zip_writer = ZipWriter::new(writer);
+    for idx in 0..archive.len() {
+        let mut file = archive.by_index(idx)?;
+        let file_name = file.name().to_owned();
+        if file.is_dir() {
+            zip_writer.add_directory(file_name, FileOptions::default())?;
+            continue;
+        }
+        let (method, level) = entry_compression(&file_name, file.compression(), compression);
+        let options = FileOptions::default()
+            .compression_method(method)
+            .compression_level(level);
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        if file_name.ends_with(".opf") {
+            content = insert_accessibility_metadata(
+                std::str::from_utf8(&content)?,
+                has_images,
+                !page_targets.is_empty(),
+            )
+            .into_bytes();
+        } else if !page_targets.is_empty() && file_name.ends_with(".ncx") {
+            content =
+                insert_ncx_page_list(std::str::from_utf8(&content)?, page_targets).into_bytes();
+        } else if !page_targets.is_empty() && file_name.ends_with("nav.xhtml") {
+            content =
+                insert_nav_page_list(std::str::from_utf8(&content)?, page_targets).into_bytes();
+        }
+        zip_writer.start_file(file_name, options)?;
+        zip_writer.write_all(&content)?;
+    }
+    zip_writer.finish()?;
+    Ok(())
+}
+
+/// The compression method and level of a file of an epub. Files stored by the epub builder,
+/// such as the mimetype file which has to be stored, are kept stored.
+fn entry_compression(
+    file_name: &str,
+    method: CompressionMethod,
+    compression: EpubCompression,
+) -> (CompressionMethod, Option<i32>) {
+    let is_image = Path::new(file_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| {
+            COMPRESSED_IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+        });
+    if method == CompressionMethod::Stored
+        || compression.level == Some(0)
+        || (compression.is_storing_images && is_image)
+    {
+        return (CompressionMethod::Stored, None);
+    }
+    (method, compression.level.map(|level| level as i32))
+}
+
+/// Inserts the schema.org accessibility metadata in a package document using the `meta`
+/// syntax of its EPUB version
+fn insert_accessibility_metadata(opf: &str, has_images: bool, has_page_list: bool) -> String {
+    let is_epub3 = opf.contains("version=\"3.0\"");
+    let mut entries = vec![("schema:accessMode", "textual")];
+    if has_images {
+        entries.push(("schema:accessMode", "visual"));
+        entries.push(("schema:accessModeSufficient", "textual,visual"));
+    } else {
+        entries.push(("schema:accessModeSufficient", "textual"));
+    }
+    entries.extend_from_slice(&[
+        ("schema:accessibilityFeature", "structuralNavigation"),
+        ("schema:accessibilityFeature", "tableOfContents"),
+    ]);
+    if has_page_list {
+        entries.extend_from_slice(&[
+            ("schema:accessibilityFeature", "pageBreakMarkers"),
+            ("schema:accessibilityFeature", "pageNavigation"),
+        ]);
+    }
+    entries.extend_from_slice(&[
+        ("schema:accessibilityHazard", "unknown"),
+        (
+            "schema:accessibilitySummary",
+            "This publication was generated from web articles. Its headings are structured for navigation and a table of contents is provided.",
+        ),
+    ]);
+    let meta_tags: String = entries
+        .iter()
+        .map(|(property, value)| {
+            if is_epub3 {
+                format!("<meta property=\"{}\">{}</meta>\n", property, value)
+            } else {
+                format!("<meta name=\"{}\" content=\"{}\"/>\n", property, value)
+            }
+        })
+        .collect();
+    opf.replacen("</metadata>", &format!("{}</metadata>", meta_tags), 1)
+}
+
+/// Returns a vector of `TocElement` from a NodeRef used for adding to the Table of Contents for navigation.
+/// Only the headers up to `max_depth` are included e.g a depth of 2 includes the h1 and h2 headers.
+fn get_header_level_toc_vec(
+    content_url: &str,
+    article: &NodeRef,
+    max_depth: usize,
+) -> Vec<TocElement> {
     // Depth starts from 1
-    const HEADER_LEVEL_MAX_DEPTH: usize = 4;
+    let header_level_max_depth = max_depth.max(1).min(6);
     let mut headers_vec: Vec<TocElement> = Vec::new();
 
-    let mut header_levels = HashMap::with_capacity(HEADER_LEVEL_MAX_DEPTH);
-    header_levels.insert("h1", 1);
-    header_levels.insert("h2", 2);
-    header_levels.insert("h3", 3);
-    header_levels.insert("h4", 4);
+    let header_levels = (1..=header_level_max_depth)
+        .map(|level| (format!("h{}", level), level))
+        .collect::<HashMap<_, _>>();
 
     generate_header_ids(article);
 
+    let headings_selector = (1..=header_level_max_depth)
+        .map(|level| format!("h{}", level))
+        .collect::<Vec<_>>()
+        .join(", ");
     let headings = article
-        .select("h1, h2, h3, h4")
+        .select(&headings_selector)
         .expect("Unable to create selector for headings");
 
     // The header list will be generated using some sort of backtracking algorithm
-    // There will be a stack of maximum size 4 (since it only goes to h4 now)
+    // There will be a stack with the size of the maximum depth
     let mut stack: Vec<Option<TocElement>> = std::iter::repeat(None)
-        .take(HEADER_LEVEL_MAX_DEPTH)
+        .take(header_level_max_depth)
         .collect::<_>();
 
     for heading in headings {
@@ -368,7 +981,7 @@ fn get_header_level_toc_vec(content_url: &str, article: &NodeRef) -> Vec<TocElem
             // of a lower hierarchy e.g if the existing toc is a h2, then the h3 and h4 in the stack
             // will be consumed.
             // We collapse the children by folding from the right to the left of the stack.
-            let descendants_levels = HEADER_LEVEL_MAX_DEPTH - level;
+            let descendants_levels = header_level_max_depth - level;
             let folded_descendants = stack
                 .iter_mut()
                 .rev()
@@ -429,7 +1042,7 @@ fn get_header_level_toc_vec(content_url: &str, article: &NodeRef) -> Vec<TocElem
 
 /// Serializes a NodeRef to a string that is XHTML compatible
 /// The only DOM nodes serialized are Text and Element nodes
-fn serialize_to_xhtml<W: std::io::Write>(
+pub fn serialize_to_xhtml<W: std::io::Write>(
     node_ref: &NodeRef,
     mut w: &mut W,
 ) -> Result<(), PaperoniError> {
@@ -439,7 +1052,7 @@ fn serialize_to_xhtml<W: std::io::Write>(
             .select_first("html")
             .expect("Unable to get <html> element in article");
         let mut html_attrs = html_elem.attributes.borrow_mut();
-        html_attrs.insert("xmlns", "http://www.w3.org/1999/xhtml".into());
+        html_attrs.insert("xmlns", XHTML_NS.into());
         html_attrs.insert("xmlns:epub", "http://www.idpf.org/2007/ops".into());
     }
     let mut escape_map = HashMap::new();
@@ -448,42 +1061,53 @@ fn serialize_to_xhtml<W: std::io::Write>(
     escape_map.insert("&", "&amp;");
     escape_map.insert("\"", "&quot;");
     escape_map.insert("'", "&apos;");
+    let escape = |value: &str| {
+        ESC_SEQ_REGEX
+            .replace_all(value, |captures: &regex::Captures| escape_map[&captures[1]])
+            .into_owned()
+    };
     for edge in node_ref.traverse_inclusive() {
         match edge {
             kuchiki::iter::NodeEdge::Start(n) => match n.data() {
                 kuchiki::NodeData::Text(rc_text) => {
                     let text = rc_text.borrow();
-                    let esc_text = ESC_SEQ_REGEX
-                        .replace_all(&text, |captures: &regex::Captures| escape_map[&captures[1]]);
-                    write!(&mut w, "{}", esc_text)?;
+                    write!(&mut w, "{}", escape(&text))?;
                 }
                 kuchiki::NodeData::Element(elem_data) => {
                     let attrs = elem_data.attributes.borrow();
-                    let attrs_str = attrs
-                        .map
+                    let namespace_decls = foreign_namespace_decls(&n, &elem_data.name.ns);
+                    let attrs_str = namespace_decls
                         .iter()
-                        .filter(|(k, _)| {
-                            let attr_key: &str = &k.local;
-                            attr_key.is_ascii() && VALID_ATTR_CHARS_REGEX.is_match(attr_key)
-                        })
-                        .map(|(k, v)| {
-                            format!(
-                                "{}=\"{}\"",
-                                k.local,
-                                ESC_SEQ_REGEX
-                                    .replace_all(&v.value, |captures: &regex::Captures| {
-                                        escape_map[&captures[1]]
-                                    })
-                            )
-                        })
+                        .map(|(name, value)| format!("{}=\"{}\"", name, value))
+                        .chain(
+                            attrs
+                                .map
+                                .iter()
+                                .filter(|(k, _)| {
+                                    let attr_key: &str = &k.local;
+                                    attr_key.is_ascii() && VALID_ATTR_CHARS_REGEX.is_match(attr_key)
+                                })
+                                .map(|(k, v)| (qualified_attr_name(&k.ns, &k.local), v))
+                                // The declarations written above are not repeated
+                                .filter(|(name, _)| {
+                                    !namespace_decls.iter().any(|(decl, _)| decl == name)
+                                })
+                                .map(|(name, v)| format!("{}=\"{}\"", name, escape(&v.value))),
+                        )
                         .fold("".to_string(), |acc, val| acc + " " + &val);
-                    write!(&mut w, "<{}{}>", &elem_data.name.local, attrs_str)?;
+                    if is_void_element(&n, &elem_data.name.local) {
+                        write!(&mut w, "<{}{}/>", &elem_data.name.local, attrs_str)?;
+                    } else {
+                        write!(&mut w, "<{}{}>", &elem_data.name.local, attrs_str)?;
+                    }
                 }
                 _ => (),
             },
             kuchiki::iter::NodeEdge::End(n) => match n.data() {
                 kuchiki::NodeData::Element(elem_data) => {
-                    write!(&mut w, "</{}>", &elem_data.name.local)?;
+                    if !is_void_element(&n, &elem_data.name.local) {
+                        write!(&mut w, "</{}>", &elem_data.name.local)?;
+                    }
                 }
                 _ => (),
             },
@@ -492,11 +1116,165 @@ fn serialize_to_xhtml<W: std::io::Write>(
     Ok(())
 }
 
+/// Checks whether an element has to be written as a self-closing tag. Void elements
+/// that somehow ended up with children are written out in full so no content is lost.
+fn is_void_element(node_ref: &NodeRef, local_name: &str) -> bool {
+    VOID_ELEMENTS.contains(&local_name) && node_ref.first_child().is_none()
+}
+
+/// Returns the `xmlns` declarations needed when an element switches into the SVG or
+/// MathML namespace e.g an `<svg>` embedded in the article's `<body>`.
+fn foreign_namespace_decls(
+    node_ref: &NodeRef,
+    ns: &Namespace,
+) -> Vec<(&'static str, &'static str)> {
+    let ns: &str = ns;
+    if ns != SVG_NS && ns != MATHML_NS {
+        return Vec::new();
+    }
+    let parent_ns = node_ref
+        .parent()
+        .and_then(|parent| parent.as_element().map(|elem| elem.name.ns.clone()));
+    if parent_ns.as_deref() == Some(ns) {
+        return Vec::new();
+    }
+    if ns == SVG_NS {
+        vec![("xmlns", SVG_NS), ("xmlns:xlink", XLINK_NS)]
+    } else {
+        vec![("xmlns", MATHML_NS)]
+    }
+}
+
+/// Returns the name of an attribute with the prefix of its namespace if it has one.
+/// The HTML parser strips these prefixes so attributes such as `xlink:href` have to be rebuilt.
+fn qualified_attr_name(ns: &Namespace, local: &str) -> String {
+    let ns: &str = ns;
+    match ns {
+        XLINK_NS => format!("xlink:{}", local),
+        XML_NS => format!("xml:{}", local),
+        // The parser keeps the declarations of foreign elements as attributes named after
+        // their prefix, or `xmlns` for the default namespace
+        XMLNS_NS if local == "xmlns" => local.to_string(),
+        XMLNS_NS => format!("xmlns:{}", local),
+        _ => local.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use kuchiki::traits::*;
 
-    use super::{generate_header_ids, get_header_level_toc_vec, replace_escaped_characters};
+    use super::{
+        generate_header_ids, get_header_level_toc_vec, replace_escaped_characters,
+        serialize_to_xhtml,
+    };
+
+    #[test]
+    fn test_prepare_and_write_epub() {
+        use std::convert::TryFrom;
+        use std::io::Read;
+
+        use super::{book_uuid, prepare_epub, stylesheet, write_epub};
+        use crate::cli::AppConfig;
+        use crate::extractor::Article;
+
+        let dir = std::env::temp_dir().join(format!("paperoni-epub-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let app_config = AppConfig::try_from(
+            clap::App::from_yaml(clap::load_yaml!("cli_config.yml")).get_matches_from(vec![
+                "paperoni",
+                "https://example.com/post",
+                "--output-dir",
+                dir.to_str().unwrap(),
+            ]),
+        )
+        .unwrap();
+        let mut article = Article::from_html(
+            "<html><head><title>A &amp; B</title></head><body><article><h1>A &amp; B</h1><p>Text</p></article></body></html>",
+            "https://example.com/post",
+        );
+        article.extract_content_with_selector("article").unwrap();
+
+        let epub_path = dir.join("A & B.epub");
+        let job = prepare_epub(
+            &article,
+            &app_config,
+            epub_path.to_str().unwrap().to_owned(),
+        )
+        .unwrap();
+        assert_eq!("A &amp; B", job.title);
+        let uuid = book_uuid(&["https://example.com/post".to_owned()]);
+        assert_eq!(uuid, job.uuid);
+        assert_eq!(5, uuid.get_version_num());
+        assert_ne!(uuid, book_uuid(&["https://example.com/other".to_owned()]));
+        let file_name = job.file_name.clone();
+        // The job is written on another thread like when exporting articles
+        let stylesheet = stylesheet(&app_config);
+        let compression = app_config.epub_compression;
+        std::thread::spawn(move || write_epub(job, &stylesheet, compression))
+            .join()
+            .unwrap()
+            .unwrap();
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&file_name).unwrap()).unwrap();
+        assert!((0..archive.len()).any(|idx| archive
+            .by_index(idx)
+            .unwrap()
+            .name()
+            .ends_with("index.xhtml")));
+        let mut opf = String::new();
+        archive
+            .by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut opf)
+            .unwrap();
+        assert!(opf.contains(&format!("urn:uuid:{}", uuid)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_entry_compression() {
+        use super::entry_compression;
+        use crate::cli::EpubCompression;
+        use zip::CompressionMethod;
+
+        let deflated = CompressionMethod::Deflated;
+        let default = EpubCompression::default();
+        assert_eq!(
+            (deflated, None),
+            entry_compression("OEBPS/index.xhtml", deflated, default)
+        );
+        assert_eq!(
+            (CompressionMethod::Stored, None),
+            entry_compression("mimetype", CompressionMethod::Stored, default)
+        );
+
+        let compression = EpubCompression {
+            level: Some(9),
+            is_storing_images: true,
+        };
+        assert_eq!(
+            (deflated, Some(9)),
+            entry_compression("OEBPS/index.xhtml", deflated, compression)
+        );
+        assert_eq!(
+            (CompressionMethod::Stored, None),
+            entry_compression("OEBPS/photo.JPG", deflated, compression)
+        );
+        assert_eq!(
+            (deflated, Some(9)),
+            entry_compression("OEBPS/diagram.svg", deflated, compression)
+        );
+
+        let stored = EpubCompression {
+            level: Some(0),
+            is_storing_images: false,
+        };
+        assert_eq!(
+            (CompressionMethod::Stored, None),
+            entry_compression("OEBPS/index.xhtml", deflated, stored)
+        );
+    }
 
     #[test]
     fn test_replace_escaped_characters() {
@@ -514,6 +1292,32 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_generate_title_page() {
+        let title_page =
+            generate_title_page("Tom & Jerry's Digest", "17 October 2026", "3 articles");
+        assert!(title_page.contains("<h1>Tom &amp; Jerry's Digest</h1>"));
+        assert!(title_page.contains("<p>17 October 2026</p>"));
+        assert!(title_page.contains("<p>3 articles</p>"));
+
+        let title_page = generate_title_page("Digest", "17 October 2026", "1 article");
+        assert!(title_page.contains("<p>1 article</p>"));
+    }
+
+    #[test]
+    fn test_take_comments_chapter() {
+        let doc = kuchiki::parse_html().one(
+            r#"<html><body><p>Article</p><section class="paperoni-comments"><h2>Comments</h2><p>Nice</p></section></body></html>"#,
+        );
+        let (heading, chapter) = take_comments_chapter(&doc).unwrap().unwrap();
+        let chapter = std::str::from_utf8(&chapter).unwrap();
+        assert_eq!("Comments", heading);
+        assert!(chapter.contains("<h2>Comments</h2>"));
+        assert!(chapter.contains("stylesheet.css"));
+        assert!(doc.select_first("section").is_err());
+        assert!(take_comments_chapter(&doc).unwrap().is_none());
+    }
+
     #[test]
     fn test_generate_header_ids() {
         let html_str = r#"
@@ -553,6 +1357,85 @@ mod test {
         assert_eq!(true, doc.select_first(selector).is_ok());
     }
 
+    #[test]
+    fn test_normalize_heading_levels() {
+        let html_str = r#"
+        <html>
+            <body>
+                <h3 id="first">First</h3>
+                <p>Lorem ipsum</p>
+                <h5 class="sub">Sub<em>heading</em></h5>
+                <h4>Jumped</h4>
+                <h3>Second</h3>
+                <h6>Deep</h6>
+            </body>
+        </html>
+        "#;
+        let heading_names = |doc: &NodeRef| {
+            doc.select("h1, h2, h3, h4, h5, h6")
+                .unwrap()
+                .map(|heading| (heading.name.local.to_string(), heading.text_contents()))
+                .collect::<Vec<_>>()
+        };
+        let doc = kuchiki::parse_html().one(html_str);
+        normalize_heading_levels(&doc, 0);
+        let headings = heading_names(&doc);
+        assert_eq!(
+            vec![
+                ("h1".to_string(), "First".to_string()),
+                ("h2".to_string(), "Subheading".to_string()),
+                ("h2".to_string(), "Jumped".to_string()),
+                ("h1".to_string(), "Second".to_string()),
+                ("h2".to_string(), "Deep".to_string()),
+            ],
+            headings
+        );
+        // Attributes are kept on the renamed headings
+        assert_eq!(1, doc.select("h1#first").unwrap().count());
+        assert_eq!(1, doc.select("h2.sub").unwrap().count());
+
+        let doc = kuchiki::parse_html().one(html_str);
+        normalize_heading_levels(&doc, 1);
+        let headings = heading_names(&doc);
+        assert_eq!(
+            vec!["h2", "h3", "h3", "h2", "h3"],
+            headings
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        // Levels past h6 are kept at h6
+        let doc = kuchiki::parse_html().one(html_str);
+        normalize_heading_levels(&doc, 5);
+        assert_eq!(5, doc.select("h6").unwrap().count());
+    }
+
+    #[test]
+    fn test_insert_accessibility_metadata() {
+        let opf =
+            r#"<package version="2.0"><metadata><dc:title>Test</dc:title></metadata></package>"#;
+        let updated_opf = insert_accessibility_metadata(opf, true, false);
+        assert!(updated_opf.contains(r#"<meta name="schema:accessMode" content="visual"/>"#));
+        assert!(updated_opf
+            .contains(r#"<meta name="schema:accessModeSufficient" content="textual,visual"/>"#));
+        assert!(updated_opf.ends_with("</metadata></package>"));
+
+        let opf =
+            r#"<package version="3.0"><metadata><dc:title>Test</dc:title></metadata></package>"#;
+        let updated_opf = insert_accessibility_metadata(opf, false, false);
+        assert!(updated_opf.contains(r#"<meta property="schema:accessMode">textual</meta>"#));
+        assert!(!updated_opf.contains("visual"));
+        assert!(updated_opf.contains(
+            r#"<meta property="schema:accessibilityFeature">structuralNavigation</meta>"#
+        ));
+        assert!(!updated_opf.contains("pageNavigation"));
+
+        let updated_opf = insert_accessibility_metadata(opf, false, true);
+        assert!(updated_opf
+            .contains(r#"<meta property="schema:accessibilityFeature">pageNavigation</meta>"#));
+    }
+
     #[test]
     fn test_get_header_level_toc_vec() {
         // NOTE: Due to `TocElement` not implementing PartialEq, the tests here
@@ -567,7 +1450,7 @@ mod test {
         "#;
         let doc = kuchiki::parse_html().one(html_str);
 
-        let toc_vec = get_header_level_toc_vec("index.xhtml", &doc);
+        let toc_vec = get_header_level_toc_vec("index.xhtml", &doc, 4);
         assert_eq!(0, toc_vec.len());
 
         let html_str = r#"
@@ -590,7 +1473,7 @@ mod test {
         "#;
         let doc = kuchiki::parse_html().one(html_str);
 
-        let toc_vec = get_header_level_toc_vec("index.xhtml", &doc);
+        let toc_vec = get_header_level_toc_vec("index.xhtml", &doc, 4);
         assert_eq!(2, toc_vec.len());
 
         let first_h1_toc = toc_vec.first().unwrap();
@@ -631,7 +1514,7 @@ mod test {
         "#;
         let doc = kuchiki::parse_html().one(html_str);
 
-        let toc_vec = get_header_level_toc_vec("index.xhtml", &doc);
+        let toc_vec = get_header_level_toc_vec("index.xhtml", &doc, 4);
         assert_eq!(1, toc_vec.len());
 
         let h1_toc = toc_vec.first().unwrap();
@@ -645,5 +1528,93 @@ mod test {
         let h3_toc = first_h2_toc.children.first().unwrap();
         assert_eq!("Subheading 3", h3_toc.title);
         assert_eq!(0, h3_toc.children.len());
+
+        // Headers deeper than the maximum depth are left out
+        let toc_vec = get_header_level_toc_vec("index.xhtml", &doc, 2);
+        assert_eq!(1, toc_vec.len());
+
+        let h1_toc = toc_vec.first().unwrap();
+        assert_eq!(3, h1_toc.children.len());
+        assert!(h1_toc
+            .children
+            .iter()
+            .all(|h2_toc| h2_toc.children.is_empty()));
+    }
+
+    #[test]
+    fn test_serialize_to_xhtml() {
+        let serialize = |html_str: &str| {
+            let doc = kuchiki::parse_html().one(html_str);
+            let mut buf = Vec::new();
+            serialize_to_xhtml(&doc, &mut buf).expect("Unable to serialize to xhtml");
+            String::from_utf8(buf).unwrap()
+        };
+
+        // Void elements are self-closing and never get an end tag
+        let xhtml = serialize(
+            r#"<html><head><link rel="stylesheet" href="stylesheet.css"></head>
+            <body><p>Line<br>break</p><hr><img src="img.png" alt="An image"><br></br></body></html>"#,
+        );
+        // Attributes are written in sorted order
+        assert!(xhtml.contains(r#"<link href="stylesheet.css" rel="stylesheet"/>"#));
+        assert!(xhtml.contains("<p>Line<br/>break</p><hr/>"));
+        assert!(xhtml.contains(r#"<img alt="An image" src="img.png"/>"#));
+        assert!(!xhtml.contains("</br>"));
+        assert!(!xhtml.contains("</img>"));
+        assert!(!xhtml.contains("</hr>"));
+        assert!(!xhtml.contains("</link>"));
+        // The HTML parser treats a stray </br> as another <br>
+        assert_eq!(3, xhtml.matches("<br/>").count());
+
+        // Text and attribute values are escaped
+        let xhtml = serialize(
+            r#"<html><body><p title="&quot;quoted&quot; &amp; 'single'">1 &lt; 2 &amp;&amp; 3 &gt; 2</p></body></html>"#,
+        );
+        assert!(xhtml.contains(r#"title="&quot;quoted&quot; &amp; &apos;single&apos;""#));
+        assert!(xhtml.contains("1 &lt; 2 &amp;&amp; 3 &gt; 2"));
+
+        // Inline SVG and MathML declare their namespaces and keep prefixed attributes
+        let xhtml = serialize(
+            r##"<html><body>
+            <svg viewBox="0 0 10 10"><use xlink:href="#dot"></use><circle r="1"/></svg>
+            <math><mi>x</mi></math>
+            </body></html>"##,
+        );
+        assert!(xhtml.contains(r#"<svg xmlns="http://www.w3.org/2000/svg""#));
+        assert!(xhtml.contains(r#"xmlns:xlink="http://www.w3.org/1999/xlink""#));
+        assert!(xhtml.contains(r##"<use xlink:href="#dot"></use>"##));
+        assert!(xhtml.contains("<circle r=\"1\"></circle>"));
+        assert!(
+            xhtml.contains(r#"<math xmlns="http://www.w3.org/1998/Math/MathML"><mi>x</mi></math>"#)
+        );
+        // Only the root of a foreign subtree gets the namespace declaration
+        assert_eq!(
+            1,
+            xhtml
+                .matches("xmlns=\"http://www.w3.org/2000/svg\"")
+                .count()
+        );
+
+        // Declarations written in the page are not repeated or stripped of their prefix
+        let xhtml = serialize(
+            r##"<html><body>
+            <svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" viewBox="0 0 10 10"><use xlink:href="#dot"></use></svg>
+            </body></html>"##,
+        );
+        assert!(xhtml.contains(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" viewBox="0 0 10 10"><use xlink:href="#dot"></use></svg>"##
+        ));
+        assert_eq!(
+            1,
+            xhtml
+                .matches("xmlns=\"http://www.w3.org/2000/svg\"")
+                .count()
+        );
+        assert_eq!(1, xhtml.matches("xmlns:xlink=").count());
+        assert!(!xhtml.contains(" xlink=\""));
+
+        // The root element is given the XHTML namespaces
+        assert!(xhtml.contains(r#"xmlns="http://www.w3.org/1999/xhtml""#));
+        assert!(xhtml.contains(r#"xmlns:epub="http://www.idpf.org/2007/ops""#));
     }
 }
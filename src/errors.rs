@@ -15,6 +15,56 @@ pub enum ErrorKind {
     UTF8Error(String),
     #[error("[ReadabilityError]: {0}")]
     ReadabilityError(String),
+    /// The page was too large or took too long to extract within the extraction budget
+    #[error("[ReadabilityTimeout]: {0}")]
+    ReadabilityTimeout(String),
+    /// Fetching, extracting and downloading the images of the article took longer than
+    /// --article-timeout
+    #[error("[ArticleTimeout]: {0}")]
+    ArticleTimeout(String),
+    /// The extracted article has fewer words than --min-words
+    #[error("[ExtractionTooShort]: {0}")]
+    ExtractionTooShort(String),
+    #[error("[ZipError]: {0}")]
+    ZipError(String),
+    #[error("[SvgError]: {0}")]
+    SvgError(String),
+    #[error("[GifError]: {0}")]
+    GifError(String),
+    #[error("[GrayscaleError]: {0}")]
+    GrayscaleError(String),
+    /// A reading service did not accept an article pushed to it
+    #[error("[PushError]: {0}")]
+    PushError(String),
+    /// The translator failed to translate an article passed to --translate
+    #[error("[TranslationError]: {0}")]
+    TranslationError(String),
+    /// The command passed to --post-process failed for an exported file
+    #[error("[PostProcessError]: {0}")]
+    PostProcessError(String),
+}
+
+impl ErrorKind {
+    /// The name of the kind used to group errors such as in the metrics of failures
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::EpubError(_) => "EpubError",
+            ErrorKind::HTTPError(_) => "HTTPError",
+            ErrorKind::IOError(_) => "IOError",
+            ErrorKind::UTF8Error(_) => "UTF8Error",
+            ErrorKind::ReadabilityError(_) => "ReadabilityError",
+            ErrorKind::ReadabilityTimeout(_) => "ReadabilityTimeout",
+            ErrorKind::ArticleTimeout(_) => "ArticleTimeout",
+            ErrorKind::ExtractionTooShort(_) => "ExtractionTooShort",
+            ErrorKind::ZipError(_) => "ZipError",
+            ErrorKind::SvgError(_) => "SvgError",
+            ErrorKind::GifError(_) => "GifError",
+            ErrorKind::GrayscaleError(_) => "GrayscaleError",
+            ErrorKind::PushError(_) => "PushError",
+            ErrorKind::TranslationError(_) => "TranslationError",
+            ErrorKind::PostProcessError(_) => "PostProcessError",
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -122,6 +172,12 @@ impl From<std::io::Error> for PaperoniError {
     }
 }
 
+impl From<zip::result::ZipError> for PaperoniError {
+    fn from(err: zip::result::ZipError) -> Self {
+        PaperoniError::with_kind(ErrorKind::ZipError(err.to_string()))
+    }
+}
+
 impl From<std::str::Utf8Error> for PaperoniError {
     fn from(err: std::str::Utf8Error) -> Self {
         PaperoniError::with_kind(ErrorKind::UTF8Error(err.to_string()))
@@ -146,28 +202,124 @@ impl PartialEq for LogError {
     }
 }
 
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Unable to get user directories to find the config file")]
+    UserDirectoriesError,
+    #[error("Failed to read config file {0}: {1}")]
+    ReadError(String, std::io::Error),
+    #[error("Failed to parse config file {0}: {1}")]
+    ParseError(String, String),
+    #[error("No profile named {0:?} in config file {1}")]
+    UnknownProfile(String, String),
+}
+
 #[derive(Debug, Error)]
 pub enum CliError<BuilderError: Debug + Display> {
     #[error("Failed to open file with urls: {0}")]
     UrlFileError(#[from] std::io::Error),
-    #[error("Failed to parse max connection value: {0}")]
-    InvalidMaxConnectionCount(#[from] std::num::ParseIntError),
+    #[error("Invalid value {value:?} for --{arg}: {reason}")]
+    InvalidArgValue {
+        arg: &'static str,
+        value: String,
+        reason: String,
+    },
+    #[error("Invalid crawl pattern: {0}")]
+    InvalidCrawlPattern(String),
+    #[error("The --follow-series flag takes a single url of a part of the series")]
+    SeriesMultipleUrls,
+    #[error("The --crawl-pattern flag can only be used with --crawl-depth or --archive")]
+    CrawlPatternWithoutCrawling,
+    #[error("Invalid options in url file: {0}")]
+    InvalidUrlOptions(String),
+    #[error("Invalid value {1:?} for environment variable {0}. Expected 1, true, 0 or false")]
+    InvalidEnvValue(String, String),
     #[error("No urls were provided")]
     NoUrls,
+    #[error("Failed to read urls from the clipboard: {0}")]
+    ClipboardError(String),
+    #[error("Invalid server port: {0}")]
+    InvalidServerPort(String),
     #[error("Failed to build cli application: {0}")]
     AppBuildError(BuilderError),
     #[error("Invalid output path name for merged epubs: {0}")]
     InvalidOutputPath(String),
+    #[error("The merged file {0:?} already exists and is kept with --overwrite skip")]
+    MergedFileExists(String),
     #[error("Wrong output directory")]
     WrongOutputDirectory,
     #[error("Output directory does not exist")]
     OutputDirectoryNotExists,
     #[error("Unable to start logger!\n{0}")]
     LogError(#[from] LogError),
+    #[error(transparent)]
+    ConfigError(#[from] ConfigError),
     #[error("The --inline-toc flag can only be used when exporting to epub")]
     WrongExportInliningToC,
     #[error("The --inline-images flag can only be used when exporting to html")]
     WrongExportInliningImages,
+    #[error("The --preserve-meta flag can only be used when exporting to html")]
+    WrongExportPreserveMeta,
+    #[error("The --paginate flag can only be used when exporting to html")]
+    WrongExportPaginate,
+    #[error("The --reader-ui flag can only be used when exporting to html")]
+    WrongExportReaderUi,
+    #[error("The --emit-feed flag can only be used when exporting to html")]
+    WrongExportEmitFeed,
+    #[error("The site export cannot be merged into a single file")]
+    MergedSite,
+    #[error("The --merge flag cannot be used with the merge subcommand")]
+    MergeNameConflict,
+    #[error("The --cover flag cannot be used when merging articles")]
+    MergedCover,
+    #[error("The --title-page flag can only be used when exporting to epub")]
+    WrongExportTitlePage,
+    #[error("The --cover-collage flag can only be used when exporting to epub")]
+    WrongExportCoverCollage,
+    #[error("The --cjk-font flag can only be used when exporting to epub")]
+    WrongExportCjkFont,
+    #[error("The --cover flag can only be used when exporting to epub")]
+    WrongExportCover,
+    #[error("The --source-qr flag can only be used when exporting to epub or html")]
+    WrongExportSourceQr,
+    #[error(
+        "The --epub-compression and --store-images flags can only be used when exporting to epub"
+    )]
+    WrongExportEpubCompression,
+    #[error("The --md-flavor flag can only be used when exporting to md")]
+    WrongExportMdFlavor,
+    #[error("Pushing to {0} requires the {1} environment variable to be set")]
+    MissingPushToken(&'static str, &'static str),
+    #[error("Translating with {0} requires the {1} environment variable to be set")]
+    MissingTranslatorKey(&'static str, &'static str),
+    #[error("The --rasterize-svg flag requires Paperoni to be built with the svg-raster feature")]
+    SvgRasterUnavailable,
+    #[error("The --flatten-gifs flag requires Paperoni to be built with the gif-flatten feature")]
+    GifFlattenUnavailable,
+    #[error("The --grayscale flag requires Paperoni to be built with the grayscale feature")]
+    GrayscaleUnavailable,
+    #[error("The --source-qr flag requires Paperoni to be built with the source-qr feature")]
+    SourceQrUnavailable,
+    #[error("Invalid CSS selector {0:?} passed to --remove-selector")]
+    InvalidRemoveSelector(String),
+    #[error(
+        "The --title and --author flags can only be used with a single article or when merging"
+    )]
+    MetadataOverrideMultipleArticles,
+    #[error("Invalid credentials {0:?}. Expected user:pass@domain or token@domain")]
+    InvalidCredentials(String),
+    #[error("Failed to read cookies file: {0}")]
+    CookieFileError(String),
+    #[error("Failed to read url rewrite rules: {0}")]
+    UrlRewriteFileError(String),
+    #[error("The {0} HTTP backend requires Paperoni to be built with the {1} feature")]
+    HttpBackendUnavailable(&'static str, &'static str),
+    #[error("The --insecure and --ca-cert flags require the curl or reqwest HTTP backend")]
+    TlsOptionsUnavailable,
+    #[error("The --resolve and --dns flags require the curl HTTP backend")]
+    ResolveUnavailable,
+    #[error("Failed to resolve {0} with the DNS server: {1}")]
+    DnsLookupError(String, String),
 }
 
 // dumb hack to allow for comparing errors in testing.
@@ -0,0 +1,391 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use comfy_table::{Attribute, Cell, CellAlignment, Color, ContentArrangement, Table};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error, info, warn};
+
+use crate::{
+    cli::{AppConfig, ExportType, OverwritePolicy},
+    epub::{EpubExporter, MergedEpubExporter},
+    errors::PaperoniError,
+    extractor::Article,
+    html::{HtmlExporter, MergedHtmlExporter},
+    i18n::Message,
+    markdown::MarkdownExporter,
+    odt::OdtExporter,
+    site::generate_site,
+    ssml::SsmlExporter,
+};
+
+/// A format articles are exported to. An export calls `prepare` once, `add_article` for each
+/// article in order and then `finalize`, while the progress bar, the table of exported
+/// articles and the errors are handled by [export_articles].
+pub trait Exporter {
+    /// The name of the format shown in the progress bar e.g `epub`
+    fn format(&self) -> &'static str;
+
+    /// Sets up the export before any article is added, such as the header of a merged file
+    fn prepare(&mut self, _articles: &[Article]) -> Result<(), PaperoniError> {
+        Ok(())
+    }
+
+    /// Exports an article or adds it to the merged export. `idx` is its position in the
+    /// articles passed to `prepare`.
+    fn add_article(&mut self, idx: usize, article: &Article) -> Result<(), PaperoniError>;
+
+    /// Writes what is left of the export such as the file of a merged export. Errors are
+    /// returned with the index of the article they failed, or `None` when the whole export
+    /// failed.
+    fn finalize(
+        &mut self,
+        _articles: &[Article],
+    ) -> Result<(), Vec<(Option<usize>, PaperoniError)>> {
+        Ok(())
+    }
+
+    /// The files written by the export, which are all known once it is finalized
+    fn written_files(&self) -> &[String];
+
+    /// The file an article was written to once the export is finalized, which is the merged
+    /// file when the articles are merged. `None` when the article was not written.
+    fn article_file(&self, idx: usize) -> Option<&str>;
+}
+
+/// The files written by an export
+#[derive(Debug, Default)]
+pub struct ExportedFiles {
+    /// Every file written in the order they were written
+    pub written: Vec<String>,
+    /// The file of each article by its position in the exported articles
+    pub articles: Vec<Option<String>>,
+}
+
+/// Exports the articles to the format of the export type of the config. The files written
+/// are added to `exported_files` even when some of the articles failed to export.
+pub fn export_articles(
+    articles: Vec<Article>,
+    app_config: &AppConfig,
+    successful_articles_table: &mut Table,
+    exported_files: &mut ExportedFiles,
+) -> Result<(), Vec<PaperoniError>> {
+    let mut exporter: Box<dyn Exporter + '_> =
+        match (&app_config.export_type, app_config.merged.as_deref()) {
+            (ExportType::EPUB, Some(name)) => Box::new(MergedEpubExporter::new(app_config, name)),
+            (ExportType::EPUB, None) => Box::new(EpubExporter::new(app_config)),
+            (ExportType::HTML, Some(name)) => Box::new(MergedHtmlExporter::new(app_config, name)),
+            (ExportType::HTML, None) => Box::new(HtmlExporter::new(app_config)),
+            (ExportType::SSML, _) => Box::new(SsmlExporter::new(app_config)),
+            (ExportType::ODT, _) => Box::new(OdtExporter::new(app_config)),
+            (ExportType::Markdown, _) => Box::new(MarkdownExporter::new(app_config)),
+            // A site is built from the pages of an HTML export
+            (ExportType::Site, _) => {
+                return generate_site(
+                    articles,
+                    app_config,
+                    successful_articles_table,
+                    exported_files,
+                )
+            }
+        };
+    run_exporter(
+        exporter.as_mut(),
+        articles,
+        app_config,
+        successful_articles_table,
+        exported_files,
+    )
+}
+
+fn run_exporter(
+    exporter: &mut dyn Exporter,
+    articles: Vec<Article>,
+    app_config: &AppConfig,
+    successful_articles_table: &mut Table,
+    exported_files: &mut ExportedFiles,
+) -> Result<(), Vec<PaperoniError>> {
+    exported_files.articles = vec![None; articles.len()];
+    if articles.is_empty() {
+        return Ok(());
+    }
+    let format = exporter.format();
+
+    let bar = if app_config.can_disable_progress_bar {
+        ProgressBar::hidden()
+    } else {
+        let enabled_bar = ProgressBar::new(articles.len() as u64);
+        let style = ProgressStyle::default_bar().template(&format!(
+            "{{spinner:.cyan}} [{{elapsed_precise}}] {{bar:40.white}} {{:>8}} {} {{pos}}/{{len:7}} {{msg:.green}}",
+            format
+        ));
+        enabled_bar.set_style(style);
+        enabled_bar.set_message(
+            app_config
+                .locale
+                .text(Message::GeneratingFiles)
+                .replace("{format}", format),
+        );
+        enabled_bar
+    };
+
+    let header = app_config.locale.text(if app_config.merged.is_some() {
+        Message::TableOfContents
+    } else {
+        Message::DownloadedArticles
+    });
+    successful_articles_table
+        .set_header(vec![Cell::new(header)
+            .add_attribute(Attribute::Bold)
+            .set_alignment(CellAlignment::Center)
+            .fg(Color::Green)])
+        .set_content_arrangement(ContentArrangement::Dynamic);
+
+    // Errors of the whole export are reported for the merged file
+    let set_export_source = |err: &mut PaperoniError| {
+        if let (None, Some(name)) = (err.article_source(), &app_config.merged) {
+            err.set_article_source(name);
+        }
+    };
+
+    if let Err(mut err) = exporter.prepare(&articles) {
+        error!("Unable to start the {} export", format);
+        set_export_source(&mut err);
+        bar.finish_with_message(format!(
+            "{}\n",
+            app_config
+                .locale
+                .text(Message::GenerationFailed)
+                .replace("{format}", format)
+        ));
+        return Err(vec![err]);
+    }
+
+    let mut errors = Vec::new();
+    let mut failed_articles = HashSet::new();
+    for (idx, article) in articles.iter().enumerate() {
+        if let Err(mut err) = exporter.add_article(idx, article) {
+            err.set_article_source(&article.url);
+            errors.push(err);
+            failed_articles.insert(idx);
+        }
+        bar.inc(1);
+    }
+
+    let finalize_result = exporter.finalize(&articles);
+    exported_files
+        .written
+        .extend_from_slice(exporter.written_files());
+    exported_files.articles = (0..articles.len())
+        .map(|idx| exporter.article_file(idx).map(str::to_owned))
+        .collect();
+    if let Err(finalize_errors) = finalize_result {
+        let mut has_export_failed = false;
+        for (idx, mut err) in finalize_errors {
+            match idx {
+                Some(idx) => {
+                    err.set_article_source(&articles[idx].url);
+                    failed_articles.insert(idx);
+                }
+                None => {
+                    set_export_source(&mut err);
+                    has_export_failed = true;
+                }
+            }
+            errors.push(err);
+        }
+        if has_export_failed {
+            error!("Failed to generate the {} export", format);
+            bar.finish_with_message(format!(
+                "{}\n",
+                app_config
+                    .locale
+                    .text(Message::GenerationFailed)
+                    .replace("{format}", format)
+            ));
+            return Err(errors);
+        }
+    }
+
+    // The articles are listed once the ones failing in `finalize` are known
+    for (idx, article) in articles.iter().enumerate() {
+        if !failed_articles.contains(&idx) {
+            successful_articles_table.add_row(vec![article.metadata().title()]);
+        }
+    }
+    bar.finish_with_message(format!(
+        "{}\n",
+        app_config
+            .locale
+            .text(Message::GeneratedFiles)
+            .replace("{format}", format)
+    ));
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Names the files of articles exported to their own files. Articles sharing a name are told
+/// apart by a numbered suffix.
+#[derive(Default)]
+pub struct ExportFileNames {
+    file_names: HashSet<String>,
+    overwrite: OverwritePolicy,
+}
+
+impl ExportFileNames {
+    /// Names the files following what --overwrite does with the files that already exist
+    pub fn new(overwrite: OverwritePolicy) -> Self {
+        Self {
+            file_names: HashSet::new(),
+            overwrite,
+        }
+    }
+
+    /// The path of the export of an article in the output directory. `name` has to be safe to
+    /// use as a file name.
+    pub fn unique(&mut self, output_dir: &str, name: &str, extension: &str) -> String {
+        let base_name = format!("{}/{}", output_dir, name);
+        let mut file_name = format!("{}.{}", base_name, extension);
+        if self.file_names.contains(&file_name) {
+            info!("Article name {:?} already exists", file_name);
+            // The suffix is moved on when a renamed file already has it
+            let mut suffix = self.file_names.len();
+            while self.file_names.contains(&file_name) {
+                file_name = format!("{}_{}.{}", base_name, suffix, extension);
+                suffix += 1;
+            }
+            info!("Renamed to {:?}", file_name);
+        }
+        self.file_names.insert(file_name.clone());
+        debug!("Creating {:?}", file_name);
+        file_name
+    }
+
+    /// The path of the export of an article like [ExportFileNames::unique] that follows the
+    /// overwrite policy when a file with the same name already exists from an earlier run.
+    /// Returns `None` when the existing file is kept and the article is not written.
+    pub fn target(&mut self, output_dir: &str, name: &str, extension: &str) -> Option<String> {
+        let file_name = self.unique(output_dir, name, extension);
+        if !Path::new(&file_name).exists() {
+            return Some(file_name);
+        }
+        match self.overwrite {
+            OverwritePolicy::Replace => {
+                info!("Replacing {:?}", file_name);
+                Some(file_name)
+            }
+            OverwritePolicy::Skip => {
+                warn!("Keeping {:?} as it already exists", file_name);
+                None
+            }
+            OverwritePolicy::Rename => {
+                let file_names = &self.file_names;
+                let renamed = renamed_file_name(&file_name, |name| {
+                    file_names.contains(name) || Path::new(name).exists()
+                });
+                info!("{:?} already exists. Renamed to {:?}", file_name, renamed);
+                self.file_names.insert(renamed.clone());
+                Some(renamed)
+            }
+        }
+    }
+}
+
+/// The first of `name_1.ext`, `name_2.ext` and so on for a file name that is not taken
+pub fn renamed_file_name(file_name: &str, is_taken: impl Fn(&str) -> bool) -> String {
+    let (base_name, extension) = match file_name.rfind('.') {
+        Some(idx) if !file_name[idx..].contains('/') => file_name.split_at(idx),
+        _ => (file_name, ""),
+    };
+    let mut suffix = 1;
+    loop {
+        let renamed = format!("{}_{}{}", base_name, suffix, extension);
+        if !is_taken(&renamed) {
+            return renamed;
+        }
+        suffix += 1;
+    }
+}
+
+/// The file of an article of an export that writes articles to their own files unless they
+/// are merged
+pub fn own_or_merged_file<'a>(
+    idx: usize,
+    merged: Option<&str>,
+    article_files: &'a HashMap<usize, String>,
+    written_files: &'a [String],
+) -> Option<&'a str> {
+    match merged {
+        Some(name) => written_files
+            .iter()
+            .find(|file| file.as_str() == name)
+            .map(String::as_str),
+        None => article_files.get(&idx).map(String::as_str),
+    }
+}
+
+/// The title of an article without the characters that separate directories
+pub fn file_safe_title(article: &Article) -> String {
+    article
+        .metadata()
+        .title()
+        .replace("/", " ")
+        .replace("\\", " ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_export_file_names() {
+        let mut file_names = ExportFileNames::default();
+        assert_eq!("./a.html", file_names.unique(".", "a", "html"));
+        assert_eq!("./b.html", file_names.unique(".", "b", "html"));
+        assert_eq!("./a_2.html", file_names.unique(".", "a", "html"));
+        assert_eq!("out/a.md", file_names.unique("out", "a", "md"));
+    }
+
+    #[test]
+    fn test_overwrite_policy() {
+        let dir =
+            std::env::temp_dir().join(format!("paperoni-test-overwrite-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir_name = dir.to_str().unwrap();
+        std::fs::write(dir.join("a.html"), "").unwrap();
+        std::fs::write(dir.join("a_1.html"), "").unwrap();
+        let existing = format!("{}/a.html", dir_name);
+
+        let mut file_names = ExportFileNames::new(OverwritePolicy::Replace);
+        assert_eq!(
+            Some(existing.clone()),
+            file_names.target(dir_name, "a", "html")
+        );
+        let mut file_names = ExportFileNames::new(OverwritePolicy::Skip);
+        assert_eq!(None, file_names.target(dir_name, "a", "html"));
+        assert_eq!(
+            Some(format!("{}/b.html", dir_name)),
+            file_names.target(dir_name, "b", "html")
+        );
+        let mut file_names = ExportFileNames::new(OverwritePolicy::Rename);
+        assert_eq!(
+            Some(format!("{}/a_2.html", dir_name)),
+            file_names.target(dir_name, "a", "html")
+        );
+        // The name given to the first article is not reused for the next one
+        assert_eq!(
+            Some(format!("{}/a_3.html", dir_name)),
+            file_names.target(dir_name, "a", "html")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_renamed_file_name() {
+        assert_eq!("out/a_1.epub", renamed_file_name("out/a.epub", |_| false));
+        assert_eq!("./a_2", renamed_file_name("./a", |name| name == "./a_1"));
+    }
+}
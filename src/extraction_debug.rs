@@ -0,0 +1,287 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use kuchiki::traits::*;
+use kuchiki::NodeRef;
+
+use crate::feed::escape_xml;
+
+/// Elements whose text is compared between the original page and the extracted article
+const BLOCK_TAGS: [&str; 18] = [
+    "article",
+    "blockquote",
+    "dd",
+    "div",
+    "dt",
+    "figcaption",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "li",
+    "p",
+    "pre",
+    "section",
+    "td",
+    "th",
+];
+/// Elements whose text is never shown on the page
+const HIDDEN_TAGS: [&str; 5] = ["noscript", "script", "style", "template", "title"];
+/// The most characters of a url kept in the name of the directory of its files
+const MAX_NAME_CHARS: usize = 60;
+
+const DIFF_STYLE: &str = "body{font-family:sans-serif;max-width:60em;margin:2em auto;padding:0 1em;color:#222}.block{padding:.3em .6em;margin:.2em 0;border-left:4px solid #ddd;white-space:pre-wrap}.dropped{background:#fdecea;border-color:#b00020}.added{background:#e8f5e9;border-color:#2e7d32}del,ins{text-decoration:none}";
+
+/// How a block of text of the original page ended up in the extracted article
+#[derive(Debug, PartialEq)]
+enum BlockChange<'a> {
+    Kept(&'a str),
+    Dropped(&'a str),
+    /// Text which is only in the article such as its header or notes added after extraction
+    Added(&'a str),
+}
+
+/// Writes the fetched HTML of an article, the article extracted from it and a view of the
+/// blocks of text that were kept and dropped to their own directory in `dir`. The extracted
+/// article is `None` when the extraction failed. Returns the directory of the files.
+pub fn write_extraction_debug(
+    dir: &Path,
+    url: &str,
+    original_html: &str,
+    extracted: Option<&NodeRef>,
+) -> io::Result<PathBuf> {
+    let article_dir = dir.join(dir_name(url));
+    fs::create_dir_all(&article_dir)?;
+    fs::write(article_dir.join("original.html"), original_html)?;
+    let original_doc = kuchiki::parse_html().one(original_html);
+    let original_blocks = text_blocks(&original_doc);
+    let extracted_blocks = match extracted {
+        Some(extracted) => {
+            fs::write(article_dir.join("extracted.html"), extracted.to_string())?;
+            text_blocks(extracted)
+        }
+        None => Vec::new(),
+    };
+    let changes = diff_blocks(&original_blocks, &extracted_blocks);
+    fs::write(
+        article_dir.join("diff.html"),
+        render_diff(url, extracted.is_some(), &changes),
+    )?;
+    Ok(article_dir)
+}
+
+/// A name for the directory of an article made from its url, which is suffixed with a hash
+/// of the url as different urls can have the same name once shortened
+fn dir_name(url: &str) -> String {
+    let bare_url = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    let mut name = String::new();
+    for c in bare_url.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c.to_ascii_lowercase());
+        } else if !name.ends_with('-') {
+            name.push('-');
+        }
+        if name.len() >= MAX_NAME_CHARS {
+            break;
+        }
+    }
+    let digest = format!("{:x}", md5::compute(url));
+    format!("{}-{}", name.trim_matches('-'), &digest[..8])
+}
+
+/// The normalized text of the innermost block elements of a document in document order.
+/// Text which is not shown such as scripts is left out.
+fn text_blocks(root: &NodeRef) -> Vec<String> {
+    root.inclusive_descendants()
+        .elements()
+        .filter(|elem| {
+            let node = elem.as_node();
+            is_block(node)
+                && !node.descendants().any(|node| is_block(&node))
+                && !node.ancestors().any(|node| is_hidden(&node))
+        })
+        .map(|elem| {
+            let text = elem
+                .as_node()
+                .descendants()
+                .text_nodes()
+                .filter(|text| !text.as_node().ancestors().any(|node| is_hidden(&node)))
+                .map(|text| text.borrow().clone())
+                .collect::<String>();
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        })
+        .filter(|text| !text.is_empty())
+        .collect()
+}
+
+fn is_block(node: &NodeRef) -> bool {
+    node.as_element().map_or(false, |elem| {
+        BLOCK_TAGS.contains(&elem.name.local.to_lowercase().as_str())
+    })
+}
+
+fn is_hidden(node: &NodeRef) -> bool {
+    node.as_element().map_or(false, |elem| {
+        HIDDEN_TAGS.contains(&elem.name.local.to_lowercase().as_str())
+    })
+}
+
+/// Compares the blocks of the original page with the ones of the article using their longest
+/// common subsequence
+fn diff_blocks<'a>(original: &'a [String], extracted: &'a [String]) -> Vec<BlockChange<'a>> {
+    let (rows, cols) = (original.len(), extracted.len());
+    // lengths[i][j] is the length of the longest common subsequence of the blocks from i and j
+    let mut lengths = vec![vec![0u32; cols + 1]; rows + 1];
+    for i in (0..rows).rev() {
+        for j in (0..cols).rev() {
+            lengths[i][j] = if original[i] == extracted[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < rows && j < cols {
+        if original[i] == extracted[j] {
+            changes.push(BlockChange::Kept(&original[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            changes.push(BlockChange::Dropped(&original[i]));
+            i += 1;
+        } else {
+            changes.push(BlockChange::Added(&extracted[j]));
+            j += 1;
+        }
+    }
+    changes.extend(original[i..].iter().map(|text| BlockChange::Dropped(text)));
+    changes.extend(extracted[j..].iter().map(|text| BlockChange::Added(text)));
+    changes
+}
+
+fn render_diff(url: &str, is_extracted: bool, changes: &[BlockChange]) -> String {
+    let (mut kept_count, mut dropped_count, mut added_count) = (0, 0, 0);
+    let blocks = changes
+        .iter()
+        .map(|change| match change {
+            BlockChange::Kept(text) => {
+                kept_count += 1;
+                format!("<div class=\"block kept\">{}</div>\n", escape_xml(text))
+            }
+            BlockChange::Dropped(text) => {
+                dropped_count += 1;
+                format!(
+                    "<div class=\"block dropped\"><del>{}</del></div>\n",
+                    escape_xml(text)
+                )
+            }
+            BlockChange::Added(text) => {
+                added_count += 1;
+                format!(
+                    "<div class=\"block added\"><ins>{}</ins></div>\n",
+                    escape_xml(text)
+                )
+            }
+        })
+        .collect::<String>();
+    let summary = if is_extracted {
+        format!(
+            "Blocks of text kept: {}, dropped: {}, added: {}",
+            kept_count, dropped_count, added_count
+        )
+    } else {
+        "The extraction failed so every block of text was dropped".to_owned()
+    };
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Extraction of {url}</title>\n<style>{style}</style>\n</head>\n<body>\n<h1>Extraction of <a href=\"{url}\">{url}</a></h1>\n<p>{summary}</p>\n{blocks}</body>\n</html>\n",
+        url = escape_xml(url),
+        style = DIFF_STYLE,
+        summary = summary,
+        blocks = blocks
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ORIGINAL: &str = r#"<html><head><title>Pizza</title><script>track()</script></head><body>
+<div class="menu"><ul><li>Home</li><li>Recipes</li></ul></div>
+<article><h1>Pizza</h1><p>The  dough<noscript>Enable scripts</noscript>
+rests overnight.</p><div class="ad">Advertisement</div><p>Bake it hot.</p></article>
+<footer><p>Copyright</p></footer></body></html>"#;
+
+    #[test]
+    fn test_text_blocks() {
+        let doc = kuchiki::parse_html().one(ORIGINAL);
+        assert_eq!(
+            vec![
+                "Home",
+                "Recipes",
+                "Pizza",
+                "The dough rests overnight.",
+                "Advertisement",
+                "Bake it hot.",
+                "Copyright"
+            ],
+            text_blocks(&doc)
+        );
+    }
+
+    #[test]
+    fn test_diff_blocks() {
+        let original = text_blocks(&kuchiki::parse_html().one(ORIGINAL));
+        let extracted = text_blocks(&kuchiki::parse_html().one(
+            "<div id=\"readability-page-1\"><p>The dough rests overnight.</p><p>Bake it hot.</p><p>Notes</p></div>",
+        ));
+        assert_eq!(
+            vec![
+                BlockChange::Dropped("Home"),
+                BlockChange::Dropped("Recipes"),
+                BlockChange::Dropped("Pizza"),
+                BlockChange::Kept("The dough rests overnight."),
+                BlockChange::Dropped("Advertisement"),
+                BlockChange::Kept("Bake it hot."),
+                BlockChange::Dropped("Copyright"),
+                BlockChange::Added("Notes"),
+            ],
+            diff_blocks(&original, &extracted)
+        );
+    }
+
+    #[test]
+    fn test_write_extraction_debug() {
+        assert_eq!(
+            "example-com-2021-06-pizza-html-",
+            &dir_name("https://example.com/2021/06/pizza.html")[..31]
+        );
+        let dir = std::env::temp_dir().join("paperoni-test-extraction-debug");
+        let extracted = kuchiki::parse_html().one("<p>Bake it hot.</p>");
+        let article_dir = write_extraction_debug(
+            &dir,
+            "https://example.com/pizza",
+            ORIGINAL,
+            Some(&extracted),
+        )
+        .unwrap();
+        assert_eq!(
+            ORIGINAL,
+            fs::read_to_string(article_dir.join("original.html")).unwrap()
+        );
+        assert!(fs::read_to_string(article_dir.join("extracted.html"))
+            .unwrap()
+            .contains("<p>Bake it hot.</p>"));
+        let diff = fs::read_to_string(article_dir.join("diff.html")).unwrap();
+        assert!(diff.contains("Blocks of text kept: 1, dropped: 6, added: 0"));
+        assert!(diff.contains("<div class=\"block dropped\"><del>Advertisement</del></div>"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
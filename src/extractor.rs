@@ -1,40 +1,144 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Local};
+use html5ever::{LocalName, Namespace, QualName};
 use itertools::Itertools;
 use kuchiki::{traits::*, NodeRef};
+use log::debug;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use url::Url;
 
+use crate::cjk;
 use crate::errors::PaperoniError;
-use crate::moz_readability::{MetaData, Readability};
+use crate::medium;
+use crate::moz_readability::regexes::{self, PatternOverrides};
+use crate::moz_readability::{Confidence, ExtractionBudget, MetaData, Readability};
+use crate::newsletter::Newsletter;
+use crate::page_list;
+use crate::svg;
+
+/// The image types of `<source>` elements that e-readers can display
+const SUPPORTED_SOURCE_TYPES: [&str; 4] = ["image/jpeg", "image/png", "image/gif", "image/svg+xml"];
+
+/// Keeps the url an image was downloaded from after its src is replaced with the local copy
+pub const ORIGINAL_SRC_ATTR: &str = "data-paperoni-src";
+/// Marks the block that replaces an audio element with the url of the audio
+const AUDIO_ATTR: &str = "data-paperoni-audio";
+/// Extensions of image files whose urls only tell apart the sizes of an image in their query
+const IMAGE_EXTENSIONS: [&str; 8] = ["avif", "gif", "jpeg", "jpg", "png", "svg", "webp", "bmp"];
+/// Attributes used to keep track of nodes while extracting and downloading images
+pub const INTERNAL_ATTRS: [&str; 4] = [
+    "readability-score",
+    "readability-data-table",
+    ORIGINAL_SRC_ATTR,
+    AUDIO_ATTR,
+];
 
 /// A tuple of the url and an Option of the resource's MIME type
 pub type ResourceInfo = (String, Option<String>);
 
+/// An audio file in an article such as a podcast episode
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioItem {
+    pub url: String,
+    pub title: String,
+    /// The duration given by the page, formatted as minutes and seconds when it is a number
+    pub duration: Option<String>,
+}
+
 pub struct Article {
     node_ref_opt: Option<NodeRef>,
     pub img_urls: Vec<ResourceInfo>,
+    /// The downloaded image used as the cover of the article's epub
+    pub cover_img: Option<ResourceInfo>,
     readability: Readability,
+    /// The url the article was fetched from after following redirects
     pub url: String,
+    /// The url that was originally requested for the article
+    pub requested_url: String,
+    /// The SHA-256 checksum of the HTML the article was extracted from
+    pub source_checksum: String,
+    /// When the content of the article was extracted
+    pub extracted_at: Option<DateTime<Local>>,
 }
 
 impl Article {
     /// Create a new instance of an HTML extractor given an HTML string
     pub fn from_html(html_str: &str, url: &str) -> Self {
+        let readability = Readability::new(html_str);
+        page_list::mark_page_breaks(readability.root_node());
+        if let Some(newsletter) = Newsletter::detect(url, html_str) {
+            newsletter.prepare_post(readability.root_node(), url);
+        } else if medium::is_medium(url, html_str) {
+            medium::prepare_post(readability.root_node());
+        }
         Self {
             node_ref_opt: None,
             img_urls: Vec::new(),
-            readability: Readability::new(html_str),
+            cover_img: None,
+            readability,
             url: url.to_string(),
+            requested_url: url.to_string(),
+            source_checksum: format!("{:x}", Sha256::digest(html_str.as_bytes())),
+            extracted_at: None,
         }
     }
 
+    /// The document the article is extracted from, which can be changed before extracting
+    pub fn source_document(&self) -> &NodeRef {
+        self.readability.root_node()
+    }
+
+    /// The url of the canonical link of the page of the article, or the url it was fetched from
+    /// when the page has none
+    pub fn canonical_url(&self) -> String {
+        self.source_document()
+            .select_first("link[rel~=\"canonical\"]")
+            .ok()
+            .and_then(|link| {
+                let href = link.attributes.borrow().get("href")?.trim().to_owned();
+                Url::parse(&self.url).ok()?.join(&href).ok()
+            })
+            .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+            .map(String::from)
+            .unwrap_or_else(|| self.url.clone())
+    }
+
+    /// Sets the limits on the work done extracting the article
+    pub fn set_extraction_budget(&mut self, budget: ExtractionBudget) {
+        self.readability.budget = budget;
+    }
+
+    /// Sets the exceptions of the site of the article to the patterns of unlikely candidates
+    /// and negative classes
+    pub fn set_pattern_overrides(&mut self, pattern_overrides: PatternOverrides) {
+        self.readability.pattern_overrides = pattern_overrides;
+    }
+
     /// Locates and extracts the HTML in a document which is determined to be
     /// the source of the content
     pub fn extract_content(&mut self) -> Result<(), PaperoniError> {
         self.readability.parse(&self.url)?;
+        self.wrap_article_node();
+        Ok(())
+    }
+
+    /// Extracts the HTML of the first element matching the CSS selector as the content
+    pub fn extract_content_with_selector(&mut self, selector: &str) -> Result<(), PaperoniError> {
+        self.readability.parse_with_selector(&self.url, selector)?;
+        self.wrap_article_node();
+        Ok(())
+    }
+
+    /// Places the extracted article node in a document that links to the stylesheet
+    fn wrap_article_node(&mut self) {
         if let Some(article_node_ref) = &self.readability.article_node {
             let template = r#"
             <!DOCTYPE html>
             <html>
                 <head>
-                    <link rel="stylesheet" href="stylesheet.css" type="text/css"></link>
+                    <link rel="stylesheet" href="stylesheet.css" type="text/css">
                 </head>
                 <body>
                 </body>
@@ -43,9 +147,316 @@ impl Article {
             let doc = kuchiki::parse_html().one(template);
             let body = doc.select_first("body").unwrap();
             body.as_node().append(article_node_ref.clone());
+            resolve_picture_sources(&doc);
+            add_missing_img_alts(&doc);
+            svg::sanitize_svg_elements(&doc);
+            cjk::remove_segment_breaks(&doc);
+            self.set_content_lang(article_node_ref);
             self.node_ref_opt = Some(doc);
+            self.extracted_at = Some(Local::now());
+        }
+    }
+
+    /// Sets the language of Chinese, Japanese and Korean content, taken from the source page
+    /// or detected from its text, so that exports apply the line breaking rules of the language
+    fn set_content_lang(&self, article_node_ref: &NodeRef) {
+        let page_lang = self
+            .readability
+            .root_node()
+            .select_first("html")
+            .ok()
+            .and_then(|html| html.attributes.borrow().get("lang").map(ToOwned::to_owned))
+            .filter(|lang| cjk::is_cjk_lang(lang));
+        let lang = match page_lang {
+            Some(lang) => lang,
+            None => match cjk::detect_cjk_lang(&article_node_ref.text_contents()) {
+                Some(lang) => lang.to_owned(),
+                None => return,
+            },
+        };
+        if let Some(elem) = article_node_ref.as_element() {
+            let mut attrs = elem.attributes.borrow_mut();
+            if !attrs.contains("lang") {
+                attrs.insert("lang", lang);
+            }
+        }
+    }
+
+    /// Removes the images from the extracted content. When `has_alt_captions` is set, images
+    /// with alt text are replaced by a caption containing it.
+    pub fn remove_images(&mut self, has_alt_captions: bool) {
+        if let Some(content_ref) = &self.node_ref_opt {
+            for img_ref in content_ref.select("img").unwrap().collect::<Vec<_>>() {
+                let alt = img_ref
+                    .attributes
+                    .borrow()
+                    .get("alt")
+                    .map(|alt| alt.trim().to_owned())
+                    .unwrap_or_default();
+                let target = image_removal_target(img_ref.as_node());
+                if has_alt_captions && !alt.is_empty() {
+                    target.insert_before(create_alt_caption(&target, &alt));
+                }
+                target.detach();
+            }
+        }
+    }
+
+    /// Removes the images that are not worth downloading: the ones from the hosts or urls
+    /// matching the patterns and the tracking pixels whose size is set to 1x1 or less
+    pub fn remove_unwanted_images(&mut self, skipped_patterns: &[ImagePattern]) {
+        let base_url = Url::parse(&self.url).ok();
+        if let Some(content_ref) = &self.node_ref_opt {
+            for img_ref in content_ref.select("img").unwrap().collect::<Vec<_>>() {
+                let is_unwanted = {
+                    let attrs = img_ref.attributes.borrow();
+                    let is_pixel_sized = ["width", "height"].iter().all(|dimension| {
+                        attrs
+                            .get(*dimension)
+                            .and_then(|value| {
+                                value.trim().trim_end_matches("px").parse::<f32>().ok()
+                            })
+                            .map_or(false, |size| size <= 1.0)
+                    });
+                    is_pixel_sized
+                        || attrs
+                            .get("src")
+                            .and_then(|src| base_url.as_ref()?.join(src).ok())
+                            .map_or(false, |img_url| {
+                                skipped_patterns
+                                    .iter()
+                                    .any(|pattern| pattern.matches(img_url.as_str()))
+                            })
+                };
+                if is_unwanted {
+                    image_removal_target(img_ref.as_node()).detach();
+                }
+            }
+        }
+    }
+
+    /// Adds the comments captured from the page at the end of the content
+    pub fn append_comments(&mut self, comments: NodeRef) {
+        if let Some(content_ref) = &self.node_ref_opt {
+            if let Ok(body) = content_ref.select_first("body") {
+                body.as_node().append(comments);
+            }
+        }
+    }
+
+    /// Replaces the `<audio>` elements, which e-readers and most exports cannot play, with a
+    /// block linking to the audio. The blocks are completed with the size of the audio by
+    /// `fill_audio_block` once it is known.
+    pub fn replace_audio_elements(&mut self) -> Vec<AudioItem> {
+        let base_url = Url::parse(&self.url).ok();
+        let mut audio_items = Vec::new();
+        if let Some(content_ref) = &self.node_ref_opt {
+            for audio_ref in content_ref.select("audio").unwrap().collect::<Vec<_>>() {
+                let audio_node = audio_ref.as_node();
+                let src = audio_ref
+                    .attributes
+                    .borrow()
+                    .get("src")
+                    .map(str::to_owned)
+                    .or_else(|| {
+                        audio_node
+                            .select("source[src]")
+                            .unwrap()
+                            .find_map(|source| {
+                                source.attributes.borrow().get("src").map(str::to_owned)
+                            })
+                    });
+                let url = match src.and_then(|src| base_url.as_ref()?.join(&src).ok()) {
+                    Some(url) => url,
+                    None => {
+                        audio_node.detach();
+                        continue;
+                    }
+                };
+                let attrs = audio_ref.attributes.borrow();
+                let title = ["title", "aria-label"]
+                    .iter()
+                    .filter_map(|attr| attrs.get(*attr))
+                    .map(str::trim)
+                    .find(|title| !title.is_empty())
+                    .map(ToOwned::to_owned)
+                    .or_else(|| {
+                        url.path_segments()
+                            .and_then(|segments| segments.last())
+                            .filter(|name| !name.is_empty())
+                            .map(ToOwned::to_owned)
+                    })
+                    .unwrap_or_else(|| url.to_string());
+                let duration = ["data-duration", "duration"]
+                    .iter()
+                    .find_map(|attr| attrs.get(*attr))
+                    .map(format_duration);
+                let audio_item = AudioItem {
+                    url: url.to_string(),
+                    title,
+                    duration,
+                };
+                audio_node.insert_before(new_element(
+                    "p",
+                    &[("class", "paperoni-audio"), (AUDIO_ATTR, &audio_item.url)],
+                ));
+                audio_node.detach();
+                audio_items.push(audio_item);
+            }
+        }
+        for audio_item in &audio_items {
+            self.fill_audio_block(audio_item, None, None);
+        }
+        audio_items
+    }
+
+    /// Writes the link of an audio in its block along with its duration, size in bytes and the
+    /// name of the file it was saved to when they are known
+    pub fn fill_audio_block(
+        &mut self,
+        audio_item: &AudioItem,
+        size: Option<u64>,
+        saved_as: Option<&str>,
+    ) {
+        if let Some(content_ref) = &self.node_ref_opt {
+            let block_refs = content_ref
+                .select("p.paperoni-audio")
+                .unwrap()
+                .collect::<Vec<_>>();
+            for block_ref in block_refs {
+                if block_ref.attributes.borrow().get(AUDIO_ATTR) != Some(&audio_item.url) {
+                    continue;
+                }
+                let block_node = block_ref.as_node();
+                for child in block_node.children().collect::<Vec<_>>() {
+                    child.detach();
+                }
+                block_node.append(NodeRef::new_text("Audio: "));
+                let link = new_element("a", &[("href", &audio_item.url)]);
+                link.append(NodeRef::new_text(&audio_item.title));
+                block_node.append(link);
+                let details = audio_item
+                    .duration
+                    .clone()
+                    .into_iter()
+                    .chain(size.map(format_size))
+                    .chain(saved_as.map(|file_name| format!("saved as {}", file_name)))
+                    .collect::<Vec<_>>();
+                if !details.is_empty() {
+                    block_node.append(NodeRef::new_text(format!(" ({})", details.join(", "))));
+                }
+            }
+        }
+    }
+
+    /// Removes the images with the src, e.g. once they turn out to be tracking pixels after
+    /// being downloaded
+    pub fn remove_image(&mut self, src: &str) {
+        if let Some(content_ref) = &self.node_ref_opt {
+            let img_refs = content_ref
+                .select("img")
+                .unwrap()
+                .filter(|img_ref| img_ref.attributes.borrow().get("src") == Some(src))
+                .collect::<Vec<_>>();
+            for img_ref in img_refs {
+                image_removal_target(img_ref.as_node()).detach();
+            }
+        }
+    }
+
+    /// Adds a small header with the icon and name of the site at the start of the content.
+    /// Nothing is added when the site has no icon.
+    pub fn add_site_logo(&mut self) {
+        let icon_url = match self
+            .metadata()
+            .site_icon()
+            .and_then(|icon| Url::parse(&self.url).ok()?.join(icon).ok())
+        {
+            Some(icon_url) => icon_url,
+            None => return,
+        };
+        let site_name = self
+            .metadata()
+            .site_name()
+            .map(ToOwned::to_owned)
+            .or_else(|| {
+                icon_url
+                    .host_str()
+                    .map(|host| host.trim_start_matches("www.").to_owned())
+            })
+            .unwrap_or_default();
+        if let Some(content_ref) = &self.node_ref_opt {
+            let page = match content_ref.select_first("div[id=\"readability-page-1\"]") {
+                Ok(page) => page,
+                Err(_) => return,
+            };
+            let site_logo = new_element("div", &[("class", "site-logo")]);
+            site_logo.append(new_element(
+                "img",
+                &[("alt", ""), ("src", icon_url.as_str())],
+            ));
+            let name = new_element("span", &[]);
+            name.append(NodeRef::new_text(site_name));
+            site_logo.append(name);
+            page.as_node().prepend(site_logo);
+        }
+    }
+
+    /// Places the image the article is shared with, e.g its og:image, once at the top of the
+    /// content. Sites often leave this hero image out of the article body or repeat it
+    /// further down, so it is added when missing and its copies are removed.
+    pub fn place_lead_image(&mut self) {
+        let lead_url = match self
+            .metadata()
+            .image()
+            .and_then(|image| Url::parse(&self.url).ok()?.join(image).ok())
+        {
+            Some(lead_url) => lead_url,
+            None => return,
+        };
+        if let Some(content_ref) = &self.node_ref_opt {
+            if let Ok(page) = content_ref.select_first("div[id=\"readability-page-1\"]") {
+                place_lead_image(page.as_node(), &lead_url);
+            }
+        }
+    }
+
+    /// Finds the downloaded copy of the image at the url, such as the lead image of the
+    /// article, so that it can be reused e.g as its cover
+    pub fn downloaded_image(&self, url: &str) -> Option<ResourceInfo> {
+        let article_url = Url::parse(&self.url).ok()?;
+        let content_ref = self.node_ref_opt.as_ref()?;
+        let img_ref = content_ref
+            .select(&format!("img[{}]", ORIGINAL_SRC_ATTR))
+            .ok()?
+            .find(|img_ref| {
+                img_ref
+                    .attributes
+                    .borrow()
+                    .get(ORIGINAL_SRC_ATTR)
+                    .and_then(|src| article_url.join(src).ok())
+                    .map_or(false, |src| src.as_str() == url)
+            })?;
+        let attrs = img_ref.attributes.borrow();
+        let img_path = attrs.get("src")?;
+        self.img_urls
+            .iter()
+            .find(|(downloaded_path, _)| downloaded_path == img_path)
+            .cloned()
+    }
+
+    /// Removes the elements matching the CSS selectors from the extracted content
+    pub fn remove_elements(&mut self, selectors: &[String]) {
+        if let Some(content_ref) = &self.node_ref_opt {
+            for selector in selectors {
+                if let Ok(matches) = content_ref.select(selector) {
+                    // The matches are collected first as detaching nodes while iterating stops the traversal
+                    for node in matches.collect::<Vec<_>>() {
+                        node.as_node().detach();
+                    }
+                }
+            }
         }
-        Ok(())
     }
 
     /// Traverses the DOM tree of the content and retrieves the IMG URLs
@@ -74,9 +485,374 @@ impl Article {
         )
     }
 
+    /// Returns the originally requested url if the article was fetched from a different url
+    pub fn redirected_from(&self) -> Option<&str> {
+        if self.requested_url != self.url {
+            Some(&self.requested_url)
+        } else {
+            None
+        }
+    }
+
     pub fn metadata(&self) -> &MetaData {
         &self.readability.metadata
     }
+
+    /// How sure readability is of the content it extracted. Content extracted with a
+    /// selector has no confidence as it was not guessed.
+    pub fn confidence(&self) -> Option<&Confidence> {
+        self.readability.confidence.as_ref()
+    }
+
+    /// How long each step of the readability algorithm took when extracting the content
+    pub fn readability_timings(&self) -> &[(&'static str, std::time::Duration)] {
+        &self.readability.timings
+    }
+
+    /// Replaces the extracted title of the article
+    pub fn set_title(&mut self, title: &str) {
+        self.readability.metadata.set_title(title);
+    }
+
+    /// Replaces the extracted author of the article
+    pub fn set_byline(&mut self, byline: &str) {
+        self.readability.metadata.set_byline(byline);
+    }
+}
+
+/// Sets the source of the image in each `<picture>` to the largest candidate of its `<source>`
+/// elements so that it is downloaded along with the other images. The `<source>` elements are
+/// removed as readers would otherwise try to display them instead of the downloaded image.
+fn resolve_picture_sources(root: &NodeRef) {
+    for picture_ref in root.select("picture").unwrap().collect::<Vec<_>>() {
+        let picture = picture_ref.as_node();
+        let sources = picture.select("source").unwrap().collect::<Vec<_>>();
+        let best_candidate = sources
+            .iter()
+            .filter(|source| {
+                source
+                    .attributes
+                    .borrow()
+                    .get("type")
+                    .map(|mime| SUPPORTED_SOURCE_TYPES.contains(&mime.trim()))
+                    .unwrap_or(true)
+            })
+            .filter_map(|source| {
+                source
+                    .attributes
+                    .borrow()
+                    .get("srcset")
+                    .and_then(get_best_srcset_candidate)
+            })
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(url, _)| url);
+
+        if let Some(url) = best_candidate {
+            match picture.select_first("img") {
+                Ok(img_ref) => {
+                    let mut attrs = img_ref.attributes.borrow_mut();
+                    attrs.insert("src", url);
+                    attrs.remove("srcset");
+                }
+                Err(_) => picture.append(new_element("img", &[("src", &url)])),
+            }
+        }
+        for source in sources {
+            source.as_node().detach();
+        }
+    }
+}
+
+/// Returns the url of the largest candidate in a srcset along with its size. Width
+/// descriptors are ranked above pixel density descriptors which default to 1x.
+fn get_best_srcset_candidate(srcset: &str) -> Option<(String, (bool, f64))> {
+    regexes::SRCSET_CAPTURE_REGEX
+        .captures_iter(srcset)
+        .filter_map(|captures| {
+            let url = captures[1].trim_end_matches(',').to_string();
+            if url.is_empty() || url.starts_with("data:") {
+                return None;
+            }
+            let size = match captures.get(2).map(|descriptor| descriptor.as_str().trim()) {
+                Some(descriptor) => {
+                    let (value, unit) = descriptor.split_at(descriptor.len() - 1);
+                    (unit == "w", value.parse::<f64>().ok()?)
+                }
+                None => (false, 1.0),
+            };
+            Some((url, size))
+        })
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+}
+
+/// Creates an HTML element with the attributes
+pub fn new_element(name: &str, attributes: &[(&str, &str)]) -> NodeRef {
+    let attrs = attributes
+        .iter()
+        .map(|(name, value)| {
+            (
+                kuchiki::ExpandedName::new("", *name),
+                kuchiki::Attribute {
+                    prefix: None,
+                    value: value.to_string(),
+                },
+            )
+        })
+        .collect::<BTreeMap<_, _>>();
+    NodeRef::new_element(
+        QualName::new(
+            None,
+            Namespace::from("http://www.w3.org/1999/xhtml"),
+            LocalName::from(name),
+        ),
+        attrs,
+    )
+}
+
+/// Adds an empty alt attribute to images without one so that they are marked as decorative
+fn add_missing_img_alts(root: &NodeRef) {
+    for img_ref in root.select("img").unwrap() {
+        let mut attrs = img_ref.attributes.borrow_mut();
+        if !attrs.contains("alt") {
+            attrs.insert("alt", String::new());
+        }
+    }
+}
+
+/// Removes the attributes used internally while extracting and the class and id attributes
+/// left empty so that they do not end up in the exports. This runs right before an article
+/// is serialized by the exporters.
+pub fn clean_internal_attrs(root: &NodeRef) {
+    for elem in root.inclusive_descendants().elements() {
+        let mut attrs = elem.attributes.borrow_mut();
+        for attr in INTERNAL_ATTRS.iter() {
+            attrs.remove(*attr);
+        }
+        for attr in ["class", "id"].iter() {
+            if attrs
+                .get(*attr)
+                .map_or(false, |value| value.trim().is_empty())
+            {
+                attrs.remove(*attr);
+            }
+        }
+    }
+}
+
+/// Formats a duration in seconds as minutes and seconds. Durations that are not a number of
+/// seconds, such as `1:02:03`, are kept as they are.
+fn format_duration(duration: &str) -> String {
+    match duration.trim().parse::<f64>() {
+        Ok(seconds) if seconds >= 0.0 => {
+            let seconds = seconds.round() as u64;
+            if seconds >= 3600 {
+                format!(
+                    "{}:{:02}:{:02}",
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60
+                )
+            } else {
+                format!("{}:{:02}", seconds / 60, seconds % 60)
+            }
+        }
+        _ => duration.trim().to_owned(),
+    }
+}
+
+fn format_size(size: u64) -> String {
+    if size >= 1024 * 1024 {
+        format!("{:.1} MB", size as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{} KB", (size + 1023) / 1024)
+    }
+}
+
+/// Rewrites the links between merged articles such as the parts of a series so that they point
+/// to the chapter of the linked article in the export instead of the web. `chapter_link`
+/// returns the link to an article from its index with the fragment of the original link.
+pub fn link_merged_articles(
+    articles: &[Article],
+    chapter_link: impl Fn(usize, Option<&str>) -> String,
+) {
+    let chapters = articles
+        .iter()
+        .enumerate()
+        .flat_map(|(idx, article)| {
+            std::iter::once(&article.url)
+                .chain(article.redirected_from().map(|_| &article.requested_url))
+                .filter_map(|url| Url::parse(url).ok())
+                .map(move |url| (normalize_link_url(url), idx))
+        })
+        .collect::<BTreeMap<_, _>>();
+    for article in articles {
+        for link in article.node_ref().select("a[href]").unwrap() {
+            let mut attrs = link.attributes.borrow_mut();
+            let url = match attrs
+                .get("href")
+                .and_then(|href| Url::parse(&article.url).ok()?.join(href).ok())
+            {
+                Some(url) => url,
+                None => continue,
+            };
+            let fragment = url.fragment().map(str::to_owned);
+            if let Some(idx) = chapters.get(&normalize_link_url(url)) {
+                attrs.insert("href", chapter_link(*idx, fragment.as_deref()));
+            }
+        }
+    }
+}
+
+/// Drops the parts of a url that do not change the page it links to
+fn normalize_link_url(mut url: Url) -> String {
+    url.set_fragment(None);
+    if url.scheme() == "http" {
+        let _ = url.set_scheme("https");
+    }
+    let mut normalized = String::from(url);
+    if normalized.ends_with('/') && normalized.matches('/').count() > 3 {
+        normalized.pop();
+    }
+    normalized
+}
+
+/// Keeps the first image of the page showing the lead image, or adds one, as the first block
+/// of the page and removes the other copies of the lead image
+fn place_lead_image(page: &NodeRef, lead_url: &Url) {
+    let lead_key = image_key(lead_url);
+    let lead_imgs = page
+        .select("img[src]")
+        .unwrap()
+        .filter(|img_ref| {
+            img_ref
+                .attributes
+                .borrow()
+                .get("src")
+                .and_then(|src| lead_url.join(src).ok())
+                .map_or(false, |src| image_key(&src) == lead_key)
+        })
+        .collect::<Vec<_>>();
+    let mut lead_imgs = lead_imgs
+        .iter()
+        .map(|img_ref| lead_image_block(img_ref.as_node()));
+    let lead_block = match lead_imgs.next() {
+        Some(lead_block) => lead_block,
+        None => {
+            let figure = new_element("figure", &[("class", "lead-image")]);
+            figure.append(new_element(
+                "img",
+                &[("alt", ""), ("src", lead_url.as_str())],
+            ));
+            figure
+        }
+    };
+    for duplicate in lead_imgs {
+        debug!("Removing a copy of the lead image {}", lead_url);
+        duplicate.detach();
+    }
+    let has_text_before = page
+        .descendants()
+        .take_while(|node| *node != lead_block)
+        .any(|node| {
+            node.as_text()
+                .map_or(false, |text| !text.borrow().trim().is_empty())
+        });
+    if lead_block.parent().is_none() || has_text_before {
+        page.prepend(lead_block);
+    }
+}
+
+/// The block showing an image, which is its figure or picture when it is in one
+fn lead_image_block(img_node: &NodeRef) -> NodeRef {
+    let target = image_removal_target(img_node);
+    match target.parent() {
+        Some(parent)
+            if parent
+                .as_element()
+                .map(|elem| &elem.name.local == "figure")
+                .unwrap_or(false) =>
+        {
+            parent
+        }
+        _ => target,
+    }
+}
+
+/// The url of an image without its scheme and fragment, used to tell whether two urls show
+/// the same image. The query is left out for image files as it usually only sets their size.
+fn image_key(url: &Url) -> String {
+    let path = url.path();
+    let is_image_file = path.rsplit('.').next().map_or(false, |ext| {
+        IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+    });
+    match url.query() {
+        Some(query) if !is_image_file => {
+            format!("{}{}?{}", url.host_str().unwrap_or_default(), path, query)
+        }
+        _ => format!("{}{}", url.host_str().unwrap_or_default(), path),
+    }
+}
+
+/// The node removed along with an image. A `<picture>` only holds the sources of the image
+/// so it is removed with it.
+fn image_removal_target(img_node: &NodeRef) -> NodeRef {
+    match img_node.parent() {
+        Some(parent)
+            if parent
+                .as_element()
+                .map(|elem| &elem.name.local == "picture")
+                .unwrap_or(false) =>
+        {
+            parent
+        }
+        _ => img_node.clone(),
+    }
+}
+
+/// A pattern passed to `--skip-images-from` of the images that are not downloaded
+#[derive(Clone, Debug)]
+pub enum ImagePattern {
+    /// Matches the images of a host and its subdomains e.g `gravatar.com`
+    Domain(String),
+    /// Matches the urls of images with `*` matching any text e.g `*/pixel.gif*`
+    Url(Regex),
+}
+
+impl ImagePattern {
+    pub fn new(pattern: &str) -> Self {
+        let pattern = pattern.trim();
+        if pattern.contains('/') || pattern.contains('*') {
+            let regex = pattern.split('*').map(regex::escape).join(".*");
+            ImagePattern::Url(Regex::new(&format!("(?i)^{}$", regex)).unwrap())
+        } else {
+            ImagePattern::Domain(pattern.trim_start_matches('.').to_lowercase())
+        }
+    }
+
+    pub fn matches(&self, img_url: &str) -> bool {
+        match self {
+            ImagePattern::Domain(domain) => Url::parse(img_url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_lowercase))
+                .map_or(false, |host| {
+                    host == *domain || host.ends_with(&format!(".{}", domain))
+                }),
+            ImagePattern::Url(regex) => regex.is_match(img_url),
+        }
+    }
+}
+
+/// Creates the caption that replaces an image. A `<figcaption>` is used when the image is
+/// in a `<figure>` and a `<span>` otherwise as the image may be part of a paragraph.
+fn create_alt_caption(img_node: &NodeRef, alt: &str) -> NodeRef {
+    let is_in_figure = img_node
+        .parent()
+        .and_then(|parent| parent.as_element().map(|elem| &elem.name.local == "figure"))
+        .unwrap_or(false);
+    let tag_name = if is_in_figure { "figcaption" } else { "span" };
+    let caption = new_element(tag_name, &[("class", "img-alt")]);
+    caption.append(NodeRef::new_text(format!("[Image: {}]", alt)));
+    caption
 }
 
 #[cfg(test)]
@@ -125,4 +901,384 @@ mod test {
             article.img_urls
         );
     }
+
+    #[test]
+    fn test_extract_content_with_selector() {
+        let mut article = Article::from_html(TEST_HTML, "http://example.com/");
+        article
+            .extract_content_with_selector("footer")
+            .expect("Article extraction failed unexpectedly");
+        let content = article
+            .node_ref()
+            .select_first("#readability-page-1")
+            .unwrap();
+        assert_eq!("Made in HTML", content.text_contents().trim());
+        assert_eq!("Testing Paperoni", article.metadata().title());
+
+        let mut article = Article::from_html(TEST_HTML, "http://example.com/");
+        assert!(article.extract_content_with_selector("#missing").is_err());
+    }
+
+    #[test]
+    fn test_canonical_url() {
+        let article = Article::from_html(
+            r#"<html><head><link rel="canonical" href="/posts/pepperoni"></head><body></body></html>"#,
+            "https://example.com/posts/pepperoni?utm_source=feed",
+        );
+        assert_eq!(
+            "https://example.com/posts/pepperoni",
+            article.canonical_url()
+        );
+        let article = Article::from_html(TEST_HTML, "http://example.com/");
+        assert_eq!("http://example.com/", article.canonical_url());
+    }
+
+    #[test]
+    fn test_remove_elements() {
+        let mut article = Article::from_html(TEST_HTML, "http://example.com/");
+        article
+            .extract_content()
+            .expect("Article extraction failed unexpectedly");
+        article.remove_elements(&["img".to_string(), "h1".to_string()]);
+        article.extract_img_urls();
+
+        assert!(article.img_urls.is_empty());
+        assert_eq!(0, article.node_ref().select("img, h1").unwrap().count());
+        assert!(article.node_ref().select("p").unwrap().count() > 0);
+    }
+
+    #[test]
+    fn test_add_missing_img_alts() {
+        let mut article = Article::from_html(TEST_HTML, "http://example.com/");
+        article
+            .extract_content()
+            .expect("Article extraction failed unexpectedly");
+        let alts = article
+            .node_ref()
+            .select("img")
+            .unwrap()
+            .map(|img| img.attributes.borrow().get("alt").map(ToString::to_string))
+            .collect::<Vec<_>>();
+        assert!(alts.iter().all(Option::is_some));
+        assert!(alts.contains(&Some("Random image".to_string())));
+    }
+
+    #[test]
+    fn test_resolve_picture_sources() {
+        let html_str = r#"
+            <picture id="widths">
+                <source type="image/webp" srcset="./hero.webp 2000w">
+                <source srcset="./hero-small.jpg 480w, ./hero-large.jpg 1200w">
+                <img src="./hero-fallback.jpg" srcset="./hero-fallback-2x.jpg 2x">
+            </picture>
+            <picture id="no-img">
+                <source srcset="./photo.png, ./photo-2x.png 2x">
+            </picture>
+            <picture id="unsupported">
+                <source type="image/avif" srcset="./photo.avif">
+                <img src="./photo.jpg">
+            </picture>
+        "#;
+        let doc = kuchiki::parse_html().one(html_str);
+        resolve_picture_sources(&doc);
+        assert_eq!(0, doc.select("source").unwrap().count());
+
+        let img_src = |picture_id: &str| {
+            let img = doc
+                .select_first(&format!("picture#{} img", picture_id))
+                .unwrap();
+            let attrs = img.attributes.borrow();
+            (
+                attrs.get("src").map(ToString::to_string),
+                attrs.get("srcset").map(ToString::to_string),
+            )
+        };
+        assert_eq!(
+            (Some("./hero-large.jpg".to_string()), None),
+            img_src("widths")
+        );
+        assert_eq!(
+            (Some("./photo-2x.png".to_string()), None),
+            img_src("no-img")
+        );
+        assert_eq!(
+            (Some("./photo.jpg".to_string()), None),
+            img_src("unsupported")
+        );
+    }
+
+    #[test]
+    fn test_clean_internal_attrs() {
+        let html_str = r#"
+            <div id="readability-page-1" class="page" readability-score="12.5">
+                <p class=" " id="">Text</p>
+                <table readability-data-table="true" class="data"><tr><td>1</td></tr></table>
+            </div>
+        "#;
+        let doc = kuchiki::parse_html().one(html_str);
+        clean_internal_attrs(&doc);
+        let page = doc.select_first("div").unwrap();
+        let page_attrs = page.attributes.borrow();
+        assert_eq!(Some("readability-page-1"), page_attrs.get("id"));
+        assert_eq!(Some("page"), page_attrs.get("class"));
+        assert!(!page_attrs.contains("readability-score"));
+        let p = doc.select_first("p").unwrap();
+        assert!(p.attributes.borrow().map.is_empty());
+        let table = doc.select_first("table").unwrap();
+        let table_attrs = table.attributes.borrow();
+        assert!(!table_attrs.contains("readability-data-table"));
+        assert_eq!(Some("data"), table_attrs.get("class"));
+    }
+
+    #[test]
+    fn test_add_site_logo() {
+        let html_str = r#"
+            <html>
+                <head>
+                    <link rel="icon" href="/favicon.png">
+                    <meta property="og:site_name" content="Example Blog">
+                </head>
+                <body>
+                    <article>
+                        <p>Some Lorem Ipsum text here that is long enough to be picked as the content</p>
+                    </article>
+                </body>
+            </html>
+        "#;
+        let mut article = Article::from_html(html_str, "http://example.com/posts/1");
+        article
+            .extract_content()
+            .expect("Article extraction failed unexpectedly");
+        article.add_site_logo();
+        let site_logo = article.node_ref().select_first("div.site-logo").unwrap();
+        assert_eq!("Example Blog", site_logo.text_contents());
+        let img = site_logo.as_node().select_first("img").unwrap();
+        assert_eq!(
+            Some("http://example.com/favicon.png"),
+            img.attributes.borrow().get("src")
+        );
+
+        // Nothing is added when there is no icon
+        let mut article = Article::from_html(TEST_HTML, "http://example.com/");
+        article
+            .extract_content()
+            .expect("Article extraction failed unexpectedly");
+        article.add_site_logo();
+        assert!(article.node_ref().select_first("div.site-logo").is_err());
+    }
+
+    #[test]
+    fn test_remove_images() {
+        let html = r#"
+            <p>An inline <img src="./inline.png" alt=" A chart "> image</p>
+            <figure><picture><source srcset="./fig.webp"><img src="./fig.png" alt="A figure"></picture></figure>
+            <p><img src="./decorative.png" alt=""></p>
+        "#;
+        let mut article = Article::from_html(html, "http://example.com/");
+        article.node_ref_opt = Some(kuchiki::parse_html().one(html));
+
+        article.remove_images(true);
+        assert_eq!(
+            0,
+            article.node_ref().select("img, picture").unwrap().count()
+        );
+        let captions = article
+            .node_ref()
+            .select(".img-alt")
+            .unwrap()
+            .map(|caption| (caption.name.local.to_string(), caption.text_contents()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("span".to_string(), "[Image: A chart]".to_string()),
+                ("figcaption".to_string(), "[Image: A figure]".to_string()),
+            ],
+            captions
+        );
+    }
+
+    #[test]
+    fn test_place_lead_image() {
+        let lead_url = Url::parse("https://example.com/img/hero.jpg?w=1200").unwrap();
+        let page_html = |doc: &NodeRef| {
+            let page = doc.select_first("#readability-page-1").unwrap();
+            let mut html = Vec::new();
+            for child in page.as_node().children() {
+                child.serialize(&mut html).unwrap();
+            }
+            String::from_utf8(html).unwrap()
+        };
+
+        // The first copy is moved to the top and the others are removed
+        let doc = kuchiki::parse_html().one(
+            r#"<div id="readability-page-1"><p>Intro</p><figure><img src="/img/hero.jpg?w=600"><figcaption>Hero</figcaption></figure><p>Text</p><img src="https://example.com/img/hero.jpg"></div>"#,
+        );
+        place_lead_image(doc.select_first("div").unwrap().as_node(), &lead_url);
+        assert_eq!(
+            r#"<figure><img src="/img/hero.jpg?w=600"><figcaption>Hero</figcaption></figure><p>Intro</p><p>Text</p>"#,
+            page_html(&doc)
+        );
+
+        // The lead image is added when the content left it out
+        let doc = kuchiki::parse_html()
+            .one(r#"<div id="readability-page-1"><p>Text</p><img src="/img/other.jpg"></div>"#);
+        place_lead_image(doc.select_first("div").unwrap().as_node(), &lead_url);
+        assert_eq!(
+            r#"<figure class="lead-image"><img alt="" src="https://example.com/img/hero.jpg?w=1200"></figure><p>Text</p><img src="/img/other.jpg">"#,
+            page_html(&doc)
+        );
+
+        // A lead image already at the top is left in place
+        let html = r#"<div id="readability-page-1"><div><picture><img src="/img/hero.jpg"></picture></div><p>Text</p></div>"#;
+        let doc = kuchiki::parse_html().one(html);
+        place_lead_image(doc.select_first("div").unwrap().as_node(), &lead_url);
+        assert_eq!(
+            r#"<div><picture><img src="/img/hero.jpg"></picture></div><p>Text</p>"#,
+            page_html(&doc)
+        );
+    }
+
+    #[test]
+    fn test_image_key() {
+        let key = |url: &str| image_key(&Url::parse(url).unwrap());
+        assert_eq!(
+            "example.com/a.JPG",
+            key("https://example.com/a.JPG?w=100#top")
+        );
+        assert_eq!(
+            "example.com/image.php?id=3",
+            key("http://example.com/image.php?id=3")
+        );
+    }
+
+    #[test]
+    fn test_image_pattern() {
+        let domain = ImagePattern::new("Gravatar.com");
+        assert!(domain.matches("https://gravatar.com/avatar/1.png"));
+        assert!(domain.matches("https://secure.gravatar.com/avatar/1.png"));
+        assert!(!domain.matches("https://notgravatar.com/avatar/1.png"));
+        assert!(!domain.matches("./avatar.png"));
+
+        let url = ImagePattern::new("*/pixel.gif*");
+        assert!(url.matches("https://example.com/t/pixel.gif?id=1"));
+        assert!(!url.matches("https://example.com/pixel.gifs/cat.png"));
+        let url = ImagePattern::new("https://ads.example.com/*");
+        assert!(url.matches("https://ads.example.com/banner.png"));
+        assert!(!url.matches("https://example.com/ads.example.com/banner.png"));
+    }
+
+    #[test]
+    fn test_remove_unwanted_images() {
+        let html = r#"
+            <p>A tracked <img src="https://example.com/t.gif" width="1" height="1"> paragraph</p>
+            <p><img src="//secure.gravatar.com/avatar/1.png"></p>
+            <picture><source srcset="https://ads.example.com/a.webp"><img src="https://ads.example.com/a.png"></picture>
+            <p><img src="https://example.com/chart.png" width="1" height="300"></p>
+        "#;
+        let mut article = Article::from_html(html, "http://example.com/");
+        article.node_ref_opt = Some(kuchiki::parse_html().one(html));
+        article.remove_unwanted_images(&[
+            ImagePattern::new("gravatar.com"),
+            ImagePattern::new("https://ads.example.com/*"),
+        ]);
+        assert_eq!(0, article.node_ref().select("picture").unwrap().count());
+        article.remove_image("https://example.com/missing.png");
+        article.extract_img_urls();
+        assert_eq!(
+            vec![("https://example.com/chart.png".to_owned(), None)],
+            article.img_urls
+        );
+        article.remove_image("https://example.com/chart.png");
+        assert_eq!(0, article.node_ref().select("img").unwrap().count());
+    }
+
+    #[test]
+    fn test_replace_audio_elements() {
+        let html = r#"
+            <p>Listen to the episode</p>
+            <audio controls title="Episode 12" data-duration="2712.4"><source src="/media/ep12.mp3" type="audio/mpeg"></audio>
+            <audio src="https://cdn.example.com/clips/intro.ogg"></audio>
+            <audio controls></audio>
+        "#;
+        let mut article = Article::from_html(html, "http://example.com/podcast/");
+        article.node_ref_opt = Some(kuchiki::parse_html().one(html));
+        let audio_items = article.replace_audio_elements();
+        assert_eq!(
+            vec![
+                AudioItem {
+                    url: "http://example.com/media/ep12.mp3".to_owned(),
+                    title: "Episode 12".to_owned(),
+                    duration: Some("45:12".to_owned()),
+                },
+                AudioItem {
+                    url: "https://cdn.example.com/clips/intro.ogg".to_owned(),
+                    title: "intro.ogg".to_owned(),
+                    duration: None,
+                },
+            ],
+            audio_items
+        );
+        assert_eq!(0, article.node_ref().select("audio").unwrap().count());
+
+        article.fill_audio_block(&audio_items[0], Some(43_400_000), Some("ep12.mp3"));
+        let blocks = article
+            .node_ref()
+            .select("p.paperoni-audio")
+            .unwrap()
+            .map(|block| block.text_contents())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "Audio: Episode 12 (45:12, 41.4 MB, saved as ep12.mp3)".to_owned(),
+                "Audio: intro.ogg".to_owned()
+            ],
+            blocks
+        );
+    }
+
+    #[test]
+    fn test_link_merged_articles() {
+        let mut part_one = Article::from_html(
+            r##"<div id="post"><p>Read <a href="/series/part-2/#setup">part two</a>, <a href="https://example.com/p?id=2">its short link</a>, <a href="http://example.com/series/part-1">this part</a> and <a href="https://example.org/other">another site</a></p></div>"##,
+            "https://example.com/series/part-1",
+        );
+        part_one.extract_content_with_selector("#post").unwrap();
+        let mut part_two = Article::from_html(
+            r#"<div id="post"><p>Back to <a href="https://example.com/p?id=1">part one</a></p></div>"#,
+            "https://example.com/series/part-2/",
+        );
+        part_two.requested_url = "https://example.com/p?id=2".into();
+        part_two.extract_content_with_selector("#post").unwrap();
+        let articles = vec![part_one, part_two];
+
+        link_merged_articles(&articles, |idx, fragment| {
+            format!("article_{}.xhtml#{}", idx, fragment.unwrap_or("top"))
+        });
+        let hrefs = |article: &Article| {
+            article
+                .node_ref()
+                .select("a")
+                .unwrap()
+                .map(|link| link.attributes.borrow().get("href").unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![
+                "article_1.xhtml#setup",
+                "article_1.xhtml#top",
+                "article_0.xhtml#top",
+                "https://example.org/other"
+            ],
+            hrefs(&articles[0])
+        );
+        // Links to pages that are not merged are kept
+        assert_eq!(vec!["https://example.com/p?id=1"], hrefs(&articles[1]));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!("0:59", format_duration("59"));
+        assert_eq!("1:02:03", format_duration("3723"));
+        assert_eq!("1:02:03", format_duration(" 1:02:03 "));
+    }
 }
@@ -0,0 +1,175 @@
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use kuchiki::traits::*;
+use url::Url;
+
+use crate::atomic_file::write_atomic;
+use crate::errors::PaperoniError;
+
+/// An HTML export listed in the feed
+#[derive(Debug, PartialEq)]
+struct FeedItem {
+    title: String,
+    /// The path of the export relative to the feed
+    link: String,
+    date: DateTime<FixedOffset>,
+}
+
+/// Writes an RSS feed of the HTML exports in a directory, newest first. The feed is rebuilt
+/// from the files on every run so exports from earlier runs stay listed and deleted ones are
+/// dropped. Returns the number of exports listed.
+pub fn write_feed(feed_path: &str, export_dir: &str) -> Result<usize, PaperoniError> {
+    let feed_dir = Path::new(feed_path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let mut items = Vec::new();
+    for entry in fs::read_dir(export_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("html") || !path.is_file() {
+            continue;
+        }
+        let html = fs::read_to_string(&path)?;
+        let modified: DateTime<Utc> = fs::metadata(&path)?.modified()?.into();
+        let file_stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let link = relative_link(feed_dir, &path);
+        items.push(feed_item(&html, &file_stem, link, modified.into()));
+    }
+    items.sort_by(|a, b| b.date.cmp(&a.date));
+    write_atomic(feed_path, render_feed(&items, Utc::now().into()))?;
+    Ok(items.len())
+}
+
+/// Reads the title and publication date of an export, falling back to its file name and
+/// to when it was written
+fn feed_item(
+    html: &str,
+    file_stem: &str,
+    link: String,
+    modified: DateTime<FixedOffset>,
+) -> FeedItem {
+    let doc = kuchiki::parse_html().one(html);
+    let title = doc
+        .select_first("title")
+        .map(|title| title.text_contents().trim().to_owned())
+        .ok()
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| file_stem.to_owned());
+    let date = doc
+        .select_first("meta[property=\"article:published_time\"]")
+        .ok()
+        .and_then(|meta| {
+            let attrs = meta.attributes.borrow();
+            DateTime::parse_from_rfc3339(attrs.get("content")?).ok()
+        })
+        .unwrap_or(modified);
+    FeedItem { title, link, date }
+}
+
+/// The path of an export from the directory of the feed with its segments percent-encoded
+fn relative_link(feed_dir: &Path, path: &Path) -> String {
+    let feed_dir = fs::canonicalize(feed_dir).unwrap_or_else(|_| feed_dir.to_path_buf());
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    match path.strip_prefix(&feed_dir) {
+        Ok(relative) => relative
+            .iter()
+            .map(|segment| percent_encode(&segment.to_string_lossy()))
+            .collect::<Vec<_>>()
+            .join("/"),
+        // Exports outside of the directory of the feed are linked with their full path
+        Err(_) => Url::from_file_path(&path)
+            .map(String::from)
+            .unwrap_or_else(|_| path.to_string_lossy().into_owned()),
+    }
+}
+
+pub fn percent_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+pub fn escape_xml(value: &str) -> String {
+    value
+        .replace("&", "&amp;")
+        .replace("<", "&lt;")
+        .replace(">", "&gt;")
+        .replace("\"", "&quot;")
+}
+
+fn render_feed(items: &[FeedItem], build_date: DateTime<FixedOffset>) -> String {
+    let items = items
+        .iter()
+        .map(|item| {
+            format!(
+                "    <item>\n      <title>{}</title>\n      <link>{}</link>\n      <guid isPermaLink=\"false\">{}</guid>\n      <pubDate>{}</pubDate>\n    </item>\n",
+                escape_xml(&item.title),
+                escape_xml(&item.link),
+                escape_xml(&item.link),
+                item.date.to_rfc2822()
+            )
+        })
+        .collect::<String>();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\">\n  <channel>\n    <title>Paperoni exports</title>\n    <link>./</link>\n    <description>Articles exported by paperoni</description>\n    <lastBuildDate>{}</lastBuildDate>\n{}  </channel>\n</rss>\n",
+        build_date.to_rfc2822(),
+        items
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_feed_item() {
+        let modified = DateTime::parse_from_rfc3339("2023-02-01T10:00:00Z").unwrap();
+        let item = feed_item(
+            r#"<html><head><title>A &amp; B</title><meta property="article:published_time" content="2023-01-15T08:30:00+01:00"></head><body></body></html>"#,
+            "A & B",
+            "A%20%26%20B.html".to_owned(),
+            modified,
+        );
+        assert_eq!("A & B", item.title);
+        assert_eq!(
+            DateTime::parse_from_rfc3339("2023-01-15T08:30:00+01:00").unwrap(),
+            item.date
+        );
+
+        let item = feed_item(
+            "<html><body></body></html>",
+            "my notes",
+            "my%20notes.html".to_owned(),
+            modified,
+        );
+        assert_eq!("my notes", item.title);
+        assert_eq!(modified, item.date);
+    }
+
+    #[test]
+    fn test_render_feed() {
+        let date = DateTime::parse_from_rfc3339("2023-01-15T08:30:00Z").unwrap();
+        let feed = render_feed(
+            &[FeedItem {
+                title: "A & B".to_owned(),
+                link: percent_encode("A & B.html"),
+                date,
+            }],
+            date,
+        );
+        assert!(feed.contains("<title>A &amp; B</title>"));
+        assert!(feed.contains("<link>A%20%26%20B.html</link>"));
+        assert!(feed.contains("<pubDate>Sun, 15 Jan 2023 08:30:00 +0000</pubDate>"));
+    }
+}
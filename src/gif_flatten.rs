@@ -0,0 +1,90 @@
+use crate::errors::{ErrorKind, ImgError};
+
+/// Converts the first frame of an animated GIF to a PNG. Returns None when the GIF has a
+/// single frame as it is then smaller kept as it is.
+#[cfg(feature = "gif-flatten")]
+pub fn flatten_gif(gif_bytes: &[u8]) -> Result<Option<Vec<u8>>, ImgError> {
+    let gif_err = |msg: String| -> ImgError { ErrorKind::GifError(msg).into() };
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options
+        .read_info(gif_bytes)
+        .map_err(|e| gif_err(e.to_string()))?;
+    let (width, height) = (decoder.width() as usize, decoder.height() as usize);
+    let mut canvas = vec![0u8; width * height * 4];
+    {
+        let frame = decoder
+            .read_next_frame()
+            .map_err(|e| gif_err(e.to_string()))?
+            .ok_or_else(|| gif_err("The GIF has no frames".to_owned()))?;
+        // The first frame may only cover part of the canvas
+        let (left, top) = (frame.left as usize, frame.top as usize);
+        let frame_width = (frame.width as usize).min(width.saturating_sub(left));
+        for (row_idx, row) in frame
+            .buffer
+            .chunks(frame.width as usize * 4)
+            .take(height.saturating_sub(top))
+            .enumerate()
+        {
+            let start = ((top + row_idx) * width + left) * 4;
+            canvas[start..start + frame_width * 4].copy_from_slice(&row[..frame_width * 4]);
+        }
+    }
+    let is_animated = decoder
+        .read_next_frame()
+        .map_err(|e| gif_err(e.to_string()))?
+        .is_some();
+    if !is_animated {
+        return Ok(None);
+    }
+
+    let mut png_bytes = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png_bytes, width as u32, height as u32);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| gif_err(e.to_string()))?;
+        writer
+            .write_image_data(&canvas)
+            .map_err(|e| gif_err(e.to_string()))?;
+    }
+    Ok(Some(png_bytes))
+}
+
+#[cfg(not(feature = "gif-flatten"))]
+pub fn flatten_gif(_gif_bytes: &[u8]) -> Result<Option<Vec<u8>>, ImgError> {
+    Err(ErrorKind::GifError("Paperoni was built without the gif-flatten feature".to_owned()).into())
+}
+
+#[cfg(all(test, feature = "gif-flatten"))]
+mod test {
+    use super::*;
+    use crate::image_size::image_dimensions;
+
+    fn encode_gif(frame_count: usize) -> Vec<u8> {
+        let mut gif_bytes = Vec::new();
+        {
+            let mut encoder =
+                gif::Encoder::new(&mut gif_bytes, 2, 2, &[0, 0, 0, 255, 255, 255]).unwrap();
+            for idx in 0..frame_count {
+                let frame = gif::Frame {
+                    width: 2,
+                    height: 2,
+                    buffer: vec![(idx % 2) as u8; 4].into(),
+                    ..gif::Frame::default()
+                };
+                encoder.write_frame(&frame).unwrap();
+            }
+        }
+        gif_bytes
+    }
+
+    #[test]
+    fn test_flatten_gif() {
+        let png_bytes = flatten_gif(&encode_gif(3)).unwrap().unwrap();
+        assert!(png_bytes.starts_with(b"\x89PNG"));
+        assert_eq!(Some((2, 2)), image_dimensions(&png_bytes));
+        assert_eq!(None, flatten_gif(&encode_gif(1)).unwrap());
+        assert!(flatten_gif(b"not a gif").is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use crate::errors::{ErrorKind, ImgError};
+
+/// A converted image with its MIME type and extension
+pub type GrayscaleImage = (Vec<u8>, &'static str, &'static str);
+
+/// Converts an image to 8-bit grayscale. JPEGs are kept as JPEGs while other images are saved
+/// as PNGs so that their transparency is kept. Only the first frame of animated images is kept.
+#[cfg(feature = "grayscale")]
+pub fn to_grayscale(img_bytes: &[u8], img_mime: &str) -> Result<GrayscaleImage, ImgError> {
+    use image::{DynamicImage, ImageOutputFormat};
+
+    let gray_err = |msg: String| -> ImgError { ErrorKind::GrayscaleError(msg).into() };
+    let img = image::load_from_memory(img_bytes).map_err(|e| gray_err(e.to_string()))?;
+    let mut gray_bytes = Vec::new();
+    if img_mime == "image/jpeg" {
+        DynamicImage::ImageLuma8(img.to_luma8())
+            .write_to(&mut gray_bytes, ImageOutputFormat::Jpeg(85))
+            .map_err(|e| gray_err(e.to_string()))?;
+        Ok((gray_bytes, "image/jpeg", "jpeg"))
+    } else {
+        let gray_img = if img.color().has_alpha() {
+            DynamicImage::ImageLumaA8(img.to_luma_alpha8())
+        } else {
+            DynamicImage::ImageLuma8(img.to_luma8())
+        };
+        gray_img
+            .write_to(&mut gray_bytes, ImageOutputFormat::Png)
+            .map_err(|e| gray_err(e.to_string()))?;
+        Ok((gray_bytes, "image/png", "png"))
+    }
+}
+
+#[cfg(not(feature = "grayscale"))]
+pub fn to_grayscale(_img_bytes: &[u8], _img_mime: &str) -> Result<GrayscaleImage, ImgError> {
+    Err(
+        ErrorKind::GrayscaleError("Paperoni was built without the grayscale feature".to_owned())
+            .into(),
+    )
+}
+
+#[cfg(all(test, feature = "grayscale"))]
+mod test {
+    use super::*;
+    use image::{ColorType, DynamicImage, ImageOutputFormat, Rgb, RgbImage, Rgba, RgbaImage};
+
+    fn encode(img: DynamicImage, format: ImageOutputFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        img.write_to(&mut bytes, format).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_to_grayscale() {
+        let jpeg = encode(
+            DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, Rgb([200, 30, 30]))),
+            ImageOutputFormat::Jpeg(90),
+        );
+        let (gray_jpeg, mime, ext) = to_grayscale(&jpeg, "image/jpeg").unwrap();
+        assert_eq!(("image/jpeg", "jpeg"), (mime, ext));
+        assert_eq!(
+            ColorType::L8,
+            image::load_from_memory(&gray_jpeg).unwrap().color()
+        );
+
+        let png = encode(
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([30, 200, 30, 128]))),
+            ImageOutputFormat::Png,
+        );
+        let (gray_png, mime, ext) = to_grayscale(&png, "image/png").unwrap();
+        assert_eq!(("image/png", "png"), (mime, ext));
+        assert_eq!(
+            ColorType::La8,
+            image::load_from_memory(&gray_png).unwrap().color()
+        );
+
+        assert!(to_grayscale(b"not an image", "image/png").is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::Utc;
+use directories::UserDirs;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// The most links kept in the history. The links recorded the longest ago are dropped first.
+const MAX_HISTORY_ENTRIES: usize = 10_000;
+
+/// How long a link took to download in an earlier run and whether it was exported
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct HistoryEntry {
+    /// Milliseconds from the start of the fetch to the end of the image downloads
+    millis: u64,
+    /// Bytes of the page and its images
+    bytes: u64,
+    #[serde(default)]
+    exported: bool,
+    /// When the link was last downloaded as a unix timestamp
+    recorded: i64,
+}
+
+/// The timings of the links downloaded in earlier runs. They are used to estimate how long a
+/// batch takes and to download the fastest links first when resuming a batch.
+#[derive(Debug)]
+pub struct DownloadHistory {
+    path: PathBuf,
+    entries: Mutex<BTreeMap<String, HistoryEntry>>,
+}
+
+impl DownloadHistory {
+    /// Reads the history from a file. A missing file gives an empty history.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = match fs::read_to_string(&path) {
+            Ok(history_json) => serde_json::from_str(&history_json).unwrap_or_else(|e| {
+                warn!(
+                    "Ignoring the download history in {} as it is invalid: {}",
+                    path.display(),
+                    e
+                );
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Self {
+            path,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// The history in `~/.paperoni/history.json`
+    pub fn with_default_dir() -> Option<Self> {
+        UserDirs::new().map(|user_dirs| {
+            Self::load(user_dirs.home_dir().join(".paperoni").join("history.json"))
+        })
+    }
+
+    pub fn record(&self, url: &str, duration: Duration, bytes: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        let exported = entries.get(url).map_or(false, |entry| entry.exported);
+        entries.insert(
+            url.to_owned(),
+            HistoryEntry {
+                millis: duration.as_millis() as u64,
+                bytes,
+                exported,
+                recorded: Utc::now().timestamp(),
+            },
+        );
+    }
+
+    pub fn mark_exported(&self, url: &str) {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(url) {
+            entry.exported = true;
+        }
+    }
+
+    /// The milliseconds each link is expected to take. Links missing from the history are
+    /// expected to take the mean time of the recorded ones.
+    fn expected_millis(&self, urls: &[String]) -> Option<Vec<(u64, bool)>> {
+        let entries = self.entries.lock().unwrap();
+        if entries.is_empty() {
+            return None;
+        }
+        let mean_millis =
+            entries.values().map(|entry| entry.millis).sum::<u64>() / entries.len() as u64;
+        Some(
+            urls.iter()
+                .map(|url| match entries.get(url) {
+                    Some(entry) => (entry.millis, true),
+                    None => (mean_millis, false),
+                })
+                .collect(),
+        )
+    }
+
+    /// Estimates how long downloading the links takes with `max_conn` of them downloaded at a
+    /// time. Returns the estimate with how many of the links were downloaded before.
+    pub fn estimate(&self, urls: &[String], max_conn: usize) -> Option<(Duration, usize)> {
+        let expected = self.expected_millis(urls)?;
+        let known_count = expected.iter().filter(|(_, is_known)| *is_known).count();
+        let total_millis = expected.iter().map(|(millis, _)| millis).sum::<u64>();
+        let parallel_count = max_conn.min(urls.len()).max(1) as u64;
+        Some((
+            Duration::from_millis(total_millis / parallel_count),
+            known_count,
+        ))
+    }
+
+    /// Leaves out the links exported in earlier runs and orders the rest from the fastest to
+    /// the slowest to download. Returns the remaining links with how many were left out.
+    pub fn resume(&self, urls: &[String]) -> (Vec<String>, usize) {
+        let remaining = {
+            let entries = self.entries.lock().unwrap();
+            urls.iter()
+                .filter(|url| !entries.get(*url).map_or(false, |entry| entry.exported))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let skipped_count = urls.len() - remaining.len();
+        let mut ordered = match self.expected_millis(&remaining) {
+            Some(expected) => remaining.into_iter().zip(expected).collect::<Vec<_>>(),
+            None => return (remaining, skipped_count),
+        };
+        // The sort is stable so links taking the same time keep their order
+        ordered.sort_by_key(|(_, (millis, _))| *millis);
+        (
+            ordered.into_iter().map(|(url, _)| url).collect(),
+            skipped_count,
+        )
+    }
+
+    /// Writes the history back to its file, keeping the most recently downloaded links
+    pub fn save(&self) -> io::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() > MAX_HISTORY_ENTRIES {
+            let mut recorded = entries
+                .values()
+                .map(|entry| entry.recorded)
+                .collect::<Vec<_>>();
+            recorded.sort_unstable_by(|a, b| b.cmp(a));
+            let oldest_kept = recorded[MAX_HISTORY_ENTRIES - 1];
+            let kept = entries
+                .iter()
+                .filter(|(_, entry)| entry.recorded >= oldest_kept)
+                .map(|(url, entry)| (url.clone(), entry.clone()))
+                .collect();
+            *entries = kept;
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_string(&*entries)?)
+    }
+}
+
+/// Formats a duration as `1h 05m`, `3m 20s` or `45s`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, mins, secs) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {:02}m", hours, mins)
+    } else if mins > 0 {
+        format!("{}m {:02}s", mins, secs)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn urls(urls: &[&str]) -> Vec<String> {
+        urls.iter().map(|url| url.to_string()).collect()
+    }
+
+    #[test]
+    fn test_estimate_and_resume() {
+        let history = DownloadHistory::load(PathBuf::from("/nonexistent/history.json"));
+        let batch = urls(&["https://a.com", "https://b.com", "https://c.com"]);
+        assert_eq!(None, history.estimate(&batch, 2));
+        assert_eq!((batch.clone(), 0), history.resume(&batch));
+
+        history.record("https://a.com", Duration::from_millis(3000), 100);
+        history.record("https://b.com", Duration::from_millis(1000), 100);
+        history.record("https://d.com", Duration::from_millis(5000), 100);
+        // c.com is expected to take the mean of 3s
+        assert_eq!(
+            Some((Duration::from_millis(3500), 2)),
+            history.estimate(&batch, 2)
+        );
+        assert_eq!(
+            (
+                urls(&["https://b.com", "https://a.com", "https://c.com"]),
+                0
+            ),
+            history.resume(&batch)
+        );
+
+        history.mark_exported("https://b.com");
+        history.mark_exported("https://unknown.com");
+        // Downloading a link again keeps whether it was exported
+        history.record("https://b.com", Duration::from_millis(1000), 100);
+        assert_eq!(
+            (urls(&["https://a.com", "https://c.com"]), 1),
+            history.resume(&batch)
+        );
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir()
+            .join(format!("paperoni-history-test-{}", std::process::id()))
+            .join("history.json");
+        let history = DownloadHistory::load(path.clone());
+        history.record("https://a.com", Duration::from_millis(1200), 2048);
+        history.mark_exported("https://a.com");
+        history.save().unwrap();
+
+        let loaded = DownloadHistory::load(path.clone());
+        assert_eq!(
+            *history.entries.lock().unwrap(),
+            *loaded.entries.lock().unwrap()
+        );
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!("45s", format_duration(Duration::from_secs(45)));
+        assert_eq!("3m 05s", format_duration(Duration::from_secs(185)));
+        assert_eq!("1h 02m", format_duration(Duration::from_secs(3720)));
+    }
+}
@@ -1,21 +1,25 @@
 use std::{
-    collections::{BTreeMap, HashSet},
-    fs::{self, File},
-    path::Path,
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
 };
 
 use base64::encode;
-use comfy_table::{Attribute, Cell, CellAlignment, Color, ContentArrangement, Table};
 use html5ever::{LocalName, Namespace, QualName};
-use indicatif::{ProgressBar, ProgressStyle};
 use kuchiki::{traits::*, NodeRef};
 use log::{debug, error, info};
+use url::Url;
 
 use crate::{
+    atomic_file::AtomicFile,
     cli::{self, AppConfig, CSSConfig},
     errors::PaperoniError,
-    extractor::Article,
+    exporter::{file_safe_title, ExportFileNames, Exporter},
+    extractor::{clean_internal_attrs, link_merged_articles, new_element, Article},
+    i18n::{Locale, Message},
     moz_readability::MetaData,
+    source_qr::source_qr_svg,
+    typography::{apply_typography, typography_css},
 };
 
 const HEAD_ELEM_NOT_FOUND: &str =
@@ -27,226 +31,320 @@ const BASE_HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
 </head>
 <body></body>
 </html>"#;
+/// The elements of the head of the original page kept by --preserve-meta
+const PRESERVED_META_SELECTOR: &str = "link[rel~=\"canonical\"], link[rel~=\"icon\"], \
+    meta[property=\"article:published_time\"], meta[property=\"article:modified_time\"]";
+
+/// Exports each article to its own HTML file with its images in a folder named after it
+pub struct HtmlExporter<'a> {
+    app_config: &'a AppConfig,
+    file_names: ExportFileNames,
+    /// The files of the articles written so far by their index
+    article_files: HashMap<usize, String>,
+    written_files: Vec<String>,
+}
 
-pub fn generate_html_exports(
-    articles: Vec<Article>,
-    app_config: &AppConfig,
-    successful_articles_table: &mut Table,
-) -> Result<(), Vec<PaperoniError>> {
-    if articles.is_empty() {
-        return Ok(());
-    }
-
-    let bar = if app_config.can_disable_progress_bar {
-        ProgressBar::hidden()
-    } else {
-        let enabled_bar = ProgressBar::new(articles.len() as u64);
-        let style = ProgressStyle::default_bar().template(
-            "{spinner:.cyan} [{elapsed_precise}] {bar:40.white} {:>8} html {pos}/{len:7} {msg:.green}",
-        );
-        enabled_bar.set_style(style);
-        if !articles.is_empty() {
-            enabled_bar.set_message("Generating html files");
+impl<'a> HtmlExporter<'a> {
+    pub fn new(app_config: &'a AppConfig) -> Self {
+        Self {
+            app_config,
+            file_names: ExportFileNames::new(app_config.overwrite),
+            article_files: HashMap::new(),
+            written_files: Vec::new(),
         }
-        enabled_bar
-    };
+    }
+}
 
-    let mut errors: Vec<PaperoniError> = Vec::new();
-
-    match app_config.merged {
-        Some(ref name) => {
-            successful_articles_table.set_header(vec![Cell::new("Table of Contents")
-                .add_attribute(Attribute::Bold)
-                .set_alignment(CellAlignment::Center)
-                .fg(Color::Green)]);
-
-            debug!("Creating {:?}", name);
-
-            let base_html_elem = kuchiki::parse_html().one(BASE_HTML_TEMPLATE);
-            let body_elem = base_html_elem.select_first("body").unwrap();
-            let base_path = Path::new(app_config.output_directory.as_deref().unwrap_or("."));
-            let img_dirs_path_name = name.trim_end_matches(".html");
-            let imgs_dir_path = base_path.join(img_dirs_path_name);
-
-            if !(app_config.is_inlining_images || imgs_dir_path.exists()) {
-                info!("Creating imgs dir in {:?} for {}", imgs_dir_path, name);
-                if let Err(e) = std::fs::create_dir(&imgs_dir_path) {
-                    error!("Unable to create imgs dir for HTML file");
-                    let err: PaperoniError = e.into();
-                    errors.push(err);
-                    return Err(errors);
-                };
+impl Exporter for HtmlExporter<'_> {
+    fn format(&self) -> &'static str {
+        "html"
+    }
+
+    fn add_article(&mut self, idx: usize, article: &Article) -> Result<(), PaperoniError> {
+        let app_config = self.app_config;
+        let output_dir = app_config.output_directory.as_deref().unwrap_or(".");
+        let file_name = match self
+            .file_names
+            .target(output_dir, &file_safe_title(article), "html")
+        {
+            Some(file_name) => file_name,
+            None => return Ok(()),
+        };
+        let mut out_file = AtomicFile::create(&file_name)?;
+
+        if app_config.is_inlining_images {
+            update_imgs_base64(article)?;
+        } else {
+            let base_path = Path::new(output_dir);
+            let imgs_dir_name = article.metadata().title();
+
+            if !base_path.join(imgs_dir_name).exists() {
+                std::fs::create_dir(base_path.join(imgs_dir_name))?;
             }
 
-            for (idx, article) in articles.iter().enumerate() {
-                let article_elem = article
-                    .node_ref()
-                    .select_first("div[id=\"readability-page-1\"]")
-                    .unwrap();
+            let imgs_dir_path = base_path.join(imgs_dir_name);
+            update_img_urls(article, &imgs_dir_path)?;
+        }
+
+        let utf8_encoding = NodeRef::new_element(create_qualname("meta"), BTreeMap::new());
+        if let Some(elem_node) = utf8_encoding.as_element() {
+            let mut elem_attrs = elem_node.attributes.borrow_mut();
+            elem_attrs.insert("charset", "UTF-8".into());
+        }
 
-                let title = article.metadata().title();
+        if let Ok(head_elem) = article.node_ref().select_first("head") {
+            let head_elem_node = head_elem.as_node();
+            head_elem_node.append(utf8_encoding);
+        };
 
-                let mut elem_attr = article_elem.attributes.borrow_mut();
-                if let Some(id_attr) = elem_attr.get_mut("id") {
-                    *id_attr = format!("readability-page-{}", idx);
-                }
+        insert_title_elem(article.node_ref(), article.metadata().title());
+        if app_config.is_preserving_meta {
+            insert_preserved_meta(article);
+        }
+        insert_appendix(
+            article.node_ref(),
+            vec![(article.metadata(), &article.url, article.redirected_from())],
+            app_config.has_source_qr,
+            app_config.locale,
+        );
+        apply_typography(article.node_ref(), app_config, None);
+        inline_css(article.node_ref(), &app_config.css_config);
+        inline_typography_css(article.node_ref(), app_config);
+        remove_existing_stylesheet_link(article.node_ref());
+        if app_config.has_reader_ui {
+            insert_reader_ui(article.node_ref());
+        }
+        clean_internal_attrs(article.node_ref());
+
+        article.node_ref().serialize(&mut out_file)?;
+        out_file.persist()?;
+        debug!("Created {:?}", file_name);
+        self.article_files.insert(idx, file_name.clone());
+        self.written_files.push(file_name);
+        Ok(())
+    }
+
+    fn written_files(&self) -> &[String] {
+        &self.written_files
+    }
+
+    fn article_file(&self, idx: usize) -> Option<&str> {
+        self.article_files.get(&idx).map(String::as_str)
+    }
+}
+
+/// Merges the articles into a single HTML file, or into pages of a number of articles with
+/// an index of them when paginated
+pub struct MergedHtmlExporter<'a> {
+    app_config: &'a AppConfig,
+    name: &'a str,
+    imgs_dir_path: PathBuf,
+    page_size: usize,
+    page_names: Vec<String>,
+    /// The links of the pages from each other, which are empty without pagination
+    page_links: Vec<String>,
+    page_docs: Vec<NodeRef>,
+    written_files: Vec<String>,
+}
+
+impl<'a> MergedHtmlExporter<'a> {
+    pub fn new(app_config: &'a AppConfig, name: &'a str) -> Self {
+        let base_path = Path::new(app_config.output_directory.as_deref().unwrap_or("."));
+        let imgs_dir_path = base_path.join(name.trim_end_matches(".html"));
+        Self {
+            app_config,
+            name,
+            imgs_dir_path,
+            page_size: 1,
+            page_names: Vec::new(),
+            page_links: Vec::new(),
+            page_docs: Vec::new(),
+            written_files: Vec::new(),
+        }
+    }
+}
+
+impl Exporter for MergedHtmlExporter<'_> {
+    fn format(&self) -> &'static str {
+        "html"
+    }
 
-                if app_config.is_inlining_images {
-                    info!("Inlining images for {}", title);
-                    let result = update_imgs_base64(article);
+    fn prepare(&mut self, articles: &[Article]) -> Result<(), PaperoniError> {
+        let name = self.name;
+        debug!("Creating {:?}", name);
 
-                    if let Err(e) = result {
-                        let mut err: PaperoniError = e.into();
-                        err.set_article_source(title);
-                        error!("Unable to copy images to imgs dir for {}", title);
-                        errors.push(err);
-                    }
+        if !(self.app_config.is_inlining_images || self.imgs_dir_path.exists()) {
+            info!("Creating imgs dir in {:?} for {}", self.imgs_dir_path, name);
+            if let Err(e) = std::fs::create_dir(&self.imgs_dir_path) {
+                error!("Unable to create imgs dir for HTML file");
+                return Err(e.into());
+            };
+        }
 
-                    info!("Completed inlining images for {}", title);
+        // Without --paginate every article is on a single page written to the merged file
+        self.page_size = self
+            .app_config
+            .paginate
+            .map_or(articles.len(), |page_size| page_size.get());
+        let page_count = (articles.len() + self.page_size - 1) / self.page_size;
+        self.page_names = if page_count > 1 {
+            (1..=page_count)
+                .map(|page| paginated_file_name(name, page))
+                .collect::<Vec<_>>()
+        } else {
+            vec![name.to_owned()]
+        };
+        self.page_links = self
+            .page_names
+            .iter()
+            .map(|page_name| {
+                if page_count > 1 {
+                    file_name_of(page_name)
                 } else {
-                    info!("Copying images to imgs dir for {}", title);
-                    let result = update_img_urls(article, &imgs_dir_path).map_err(|e| {
-                        let mut err: PaperoniError = e.into();
-                        err.set_article_source(title);
-                        err
-                    });
-                    if let Err(e) = result {
-                        error!("Unable to copy images to imgs dir for {}", title);
-                        errors.push(e);
-                    } else {
-                        info!("Successfully copied images to imgs dir for {}", title);
-                    }
+                    String::new()
                 }
+            })
+            .collect::<Vec<_>>();
+        self.page_docs = (0..page_count)
+            .map(|_| kuchiki::parse_html().one(BASE_HTML_TEMPLATE))
+            .collect::<Vec<_>>();
+
+        let (page_links, page_size) = (&self.page_links, self.page_size);
+        link_merged_articles(articles, |idx, fragment| match fragment {
+            Some(fragment) => format!("{}#{}", page_links[idx / page_size], fragment),
+            None => format!("{}#readability-page-{}", page_links[idx / page_size], idx),
+        });
+        Ok(())
+    }
 
-                bar.inc(1);
-                successful_articles_table.add_row(vec![title]);
-                body_elem.as_node().append(article_elem.as_node().clone());
-                debug!("Added {} to the export HTML file", title);
-            }
+    fn add_article(&mut self, idx: usize, article: &Article) -> Result<(), PaperoniError> {
+        let app_config = self.app_config;
+        let body_elem = self.page_docs[idx / self.page_size]
+            .select_first("body")
+            .unwrap();
+        apply_typography(article.node_ref(), app_config, Some(idx + 1));
+        clean_internal_attrs(article.node_ref());
+        let article_elem = article
+            .node_ref()
+            .select_first("div[id=\"readability-page-1\"]")
+            .unwrap();
+
+        let title = article.metadata().title();
+
+        if let Some(id_attr) = article_elem.attributes.borrow_mut().get_mut("id") {
+            *id_attr = format!("readability-page-{}", idx);
+        }
+
+        // The article is merged even when its images could not be added
+        let imgs_result = if app_config.is_inlining_images {
+            info!("Inlining images for {}", title);
+            update_imgs_base64(article).map_err(PaperoniError::from)
+        } else {
+            info!("Copying images to imgs dir for {}", title);
+            update_img_urls(article, &self.imgs_dir_path).map_err(PaperoniError::from)
+        };
+        match &imgs_result {
+            Ok(_) => info!("Added the images of {}", title),
+            Err(_) => error!("Unable to copy images to imgs dir for {}", title),
+        }
+
+        body_elem.as_node().append(article_elem.as_node().clone());
+        debug!("Added {} to the export HTML file", title);
+        imgs_result
+    }
 
-            insert_title_elem(&base_html_elem, name);
+    fn finalize(
+        &mut self,
+        articles: &[Article],
+    ) -> Result<(), Vec<(Option<usize>, PaperoniError)>> {
+        let app_config = self.app_config;
+        let name = self.name;
+        let page_count = self.page_docs.len();
+        let title = app_config.title.as_deref().unwrap_or(name);
+        for (page, (page_doc, page_articles)) in self
+            .page_docs
+            .iter()
+            .zip(articles.chunks(self.page_size))
+            .enumerate()
+        {
+            if page_count > 1 {
+                insert_title_elem(
+                    page_doc,
+                    &format!("{} ({}/{})", title, page + 1, page_count),
+                );
+                insert_page_nav(page_doc, &self.page_links, page, &file_name_of(name));
+            } else {
+                insert_title_elem(page_doc, title);
+            }
             insert_appendix(
-                &base_html_elem,
-                articles
+                page_doc,
+                page_articles
                     .iter()
-                    .map(|article| (article.metadata(), article.url.as_str()))
+                    .map(|article| {
+                        (
+                            article.metadata(),
+                            article.url.as_str(),
+                            article.redirected_from(),
+                        )
+                    })
                     .collect(),
+                self.app_config.has_source_qr,
+                self.app_config.locale,
+            );
+        }
+        if page_count > 1 {
+            let index_doc = kuchiki::parse_html().one(BASE_HTML_TEMPLATE);
+            insert_title_elem(&index_doc, title);
+            insert_page_index(
+                &index_doc,
+                title,
+                articles,
+                &self.page_links,
+                self.page_size,
             );
-            inline_css(&base_html_elem, &app_config.css_config);
-            remove_existing_stylesheet_link(&base_html_elem);
+            self.page_docs.push(index_doc);
+            self.page_names.push(name.to_owned());
+        }
 
-            info!("Added title, footer and inlined styles for {}", name);
+        for (page_doc, page_name) in self.page_docs.iter().zip(&self.page_names) {
+            inline_css(page_doc, &app_config.css_config);
+            inline_typography_css(page_doc, app_config);
+            remove_existing_stylesheet_link(page_doc);
+            if app_config.has_reader_ui {
+                insert_reader_ui(page_doc);
+            }
 
-            info!("Creating export HTML file: {}", name);
-            if let Err(mut err) = File::create(name)
-                .and_then(|mut out_file| base_html_elem.serialize(&mut out_file))
+            info!("Creating export HTML file: {}", page_name);
+            if let Err(mut err) = AtomicFile::create(page_name)
+                .and_then(|mut out_file| {
+                    page_doc.serialize(&mut out_file)?;
+                    out_file.persist()
+                })
                 .map_err(|e| -> PaperoniError { e.into() })
             {
-                error!("Failed to serialize articles to file: {}", name);
-                err.set_article_source(&name);
-                errors.push(err);
-                bar.finish_with_message("html generation failed");
-                return Err(errors);
+                error!("Failed to serialize articles to file: {}", page_name);
+                err.set_article_source(page_name);
+                return Err(vec![(None, err)]);
             };
+            self.written_files.push(page_name.clone());
+        }
 
-            bar.finish_with_message("Generated html file\n");
-            debug!("Created {:?}", name);
+        debug!("Created {:?}", name);
+        if page_count > 1 {
+            println!("Created {:?} with {} pages", name, page_count);
+        } else {
             println!("Created {:?}", name);
         }
-        None => {
-            successful_articles_table
-                .set_header(vec![Cell::new("Downloaded articles")
-                    .add_attribute(Attribute::Bold)
-                    .set_alignment(CellAlignment::Center)
-                    .fg(Color::Green)])
-                .set_content_arrangement(ContentArrangement::Dynamic);
-
-            let mut file_names: HashSet<String> = HashSet::new();
-
-            for article in &articles {
-                let mut file_name = format!(
-                    "{}/{}.html",
-                    app_config.output_directory.as_deref().unwrap_or("."),
-                    article
-                        .metadata()
-                        .title()
-                        .replace("/", " ")
-                        .replace("\\", " ")
-                );
-
-                if file_names.contains(&file_name) {
-                    info!("Article name {:?} already exists", file_name);
-                    file_name = format!(
-                        "{}/{}_{}.html",
-                        app_config.output_directory.as_deref().unwrap_or("."),
-                        article
-                            .metadata()
-                            .title()
-                            .replace("/", " ")
-                            .replace("\\", " "),
-                        file_names.len()
-                    );
-                    info!("Renamed to {:?}", file_name);
-                }
-                file_names.insert(file_name.clone());
-
-                debug!("Creating {:?}", file_name);
-                let export_article = || -> Result<(), PaperoniError> {
-                    let mut out_file = File::create(&file_name)?;
-
-                    if app_config.is_inlining_images {
-                        update_imgs_base64(article)?;
-                    } else {
-                        let base_path =
-                            Path::new(app_config.output_directory.as_deref().unwrap_or("."));
-                        let imgs_dir_name = article.metadata().title();
-
-                        if !base_path.join(imgs_dir_name).exists() {
-                            std::fs::create_dir(base_path.join(imgs_dir_name))?;
-                        }
-
-                        let imgs_dir_path = base_path.join(imgs_dir_name);
-                        update_img_urls(article, &imgs_dir_path)?;
-                    }
-
-                    let utf8_encoding =
-                        NodeRef::new_element(create_qualname("meta"), BTreeMap::new());
-                    if let Some(elem_node) = utf8_encoding.as_element() {
-                        let mut elem_attrs = elem_node.attributes.borrow_mut();
-                        elem_attrs.insert("charset", "UTF-8".into());
-                    }
-
-                    if let Ok(head_elem) = article.node_ref().select_first("head") {
-                        let head_elem_node = head_elem.as_node();
-                        head_elem_node.append(utf8_encoding);
-                    };
-
-                    insert_title_elem(article.node_ref(), article.metadata().title());
-                    insert_appendix(article.node_ref(), vec![(article.metadata(), &article.url)]);
-                    inline_css(article.node_ref(), &app_config.css_config);
-                    remove_existing_stylesheet_link(article.node_ref());
-
-                    article.node_ref().serialize(&mut out_file)?;
-                    Ok(())
-                };
-
-                if let Err(mut err) = export_article() {
-                    err.set_article_source(&article.url);
-                    errors.push(err);
-                }
-                debug!("Created {:?}", file_name);
+        Ok(())
+    }
 
-                bar.inc(1);
-                successful_articles_table.add_row(vec![article.metadata().title()]);
-            }
-            bar.finish_with_message("Generated HTML files\n");
-        }
+    fn written_files(&self) -> &[String] {
+        &self.written_files
     }
 
-    if errors.is_empty() {
-        Ok(())
-    } else {
-        Err(errors)
+    fn article_file(&self, _idx: usize) -> Option<&str> {
+        // Every article is in the merged file once it is written
+        Some(self.name).filter(|name| self.written_files.iter().any(|file| file == name))
     }
 }
 
@@ -305,6 +403,94 @@ fn update_img_urls(article: &Article, imgs_dir_path: &Path) -> Result<(), std::i
     Ok(())
 }
 
+/// The name of a page of a paginated export, e.g. `out-2.html` for the second page of `out.html`
+fn paginated_file_name(name: &str, page: usize) -> String {
+    match name.strip_suffix(".html") {
+        Some(stem) => format!("{}-{}.html", stem, page),
+        None => format!("{}-{}", name, page),
+    }
+}
+
+/// The name of a file without its directory, used to link the pages of an export to each other
+fn file_name_of(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_owned())
+}
+
+/// Adds the links to the index and to the previous and next pages at the top and bottom of a
+/// page of a paginated export
+fn insert_page_nav(page_doc: &NodeRef, page_links: &[String], page: usize, index_link: &str) {
+    let body_elem = page_doc.select_first("body").unwrap();
+    body_elem
+        .as_node()
+        .prepend(page_nav(page_links, page, index_link));
+    body_elem
+        .as_node()
+        .append(page_nav(page_links, page, index_link));
+}
+
+fn page_nav(page_links: &[String], page: usize, index_link: &str) -> NodeRef {
+    let nav = new_element("nav", &[("class", "paperoni-page-nav")]);
+    let mut links = Vec::new();
+    if page > 0 {
+        links.push((page_links[page - 1].as_str(), "Previous".to_owned()));
+    }
+    links.push((
+        index_link,
+        format!("Page {} of {}", page + 1, page_links.len()),
+    ));
+    if page + 1 < page_links.len() {
+        links.push((page_links[page + 1].as_str(), "Next".to_owned()));
+    }
+    for (idx, (href, text)) in links.into_iter().enumerate() {
+        if idx > 0 {
+            nav.append(NodeRef::new_text(" · "));
+        }
+        let link = new_element("a", &[("href", href)]);
+        link.append(NodeRef::new_text(text));
+        nav.append(link);
+    }
+    nav
+}
+
+/// Fills the index page of a paginated export with the articles of each page
+fn insert_page_index(
+    index_doc: &NodeRef,
+    title: &str,
+    articles: &[Article],
+    page_links: &[String],
+    page_size: usize,
+) {
+    let body_elem = index_doc.select_first("body").unwrap();
+    let heading = new_element("h1", &[]);
+    heading.append(NodeRef::new_text(title));
+    body_elem.as_node().append(heading);
+    for (page, page_articles) in articles.chunks(page_size).enumerate() {
+        let page_heading = new_element("h2", &[]);
+        let page_link = new_element("a", &[("href", page_links[page].as_str())]);
+        page_link.append(NodeRef::new_text(format!("Page {}", page + 1)));
+        page_heading.append(page_link);
+        body_elem.as_node().append(page_heading);
+
+        let list = new_element("ul", &[]);
+        for (offset, article) in page_articles.iter().enumerate() {
+            let href = format!(
+                "{}#readability-page-{}",
+                page_links[page],
+                page * page_size + offset
+            );
+            let item = new_element("li", &[]);
+            let link = new_element("a", &[("href", href.as_str())]);
+            link.append(NodeRef::new_text(article.metadata().title()));
+            item.append(link);
+            list.append(item);
+        }
+        body_elem.as_node().append(list);
+    }
+}
+
 /// Creates a `<title>` element in an HTML document with the value set to the article's title
 fn insert_title_elem(root_node: &NodeRef, title: &str) {
     let title_content = NodeRef::new_text(title);
@@ -324,21 +510,98 @@ fn insert_title_elem(root_node: &NodeRef, title: &str) {
     }
 }
 
-/// Creates the appendix in an HTML document where article sources are added in a `<footer>` element
-fn insert_appendix(root_node: &NodeRef, article_links: Vec<(&MetaData, &str)>) {
+/// Creates the appendix in an HTML document where article sources are added in a `<footer>` element.
+/// Each source is a tuple of the article's metadata, its url and the url it was redirected from.
+/// Copies the canonical link, publication dates and icons from the head of the original page
+/// to the head of the export, with their links made absolute
+fn insert_preserved_meta(article: &Article) {
+    let head_elem = match article.node_ref().select_first("head") {
+        Ok(head_elem) => head_elem,
+        Err(_) => return,
+    };
+    for elem in preserved_meta_elements(article.source_document(), &article.url) {
+        head_elem.as_node().append(elem);
+    }
+}
+
+fn preserved_meta_elements(source_doc: &NodeRef, article_url: &str) -> Vec<NodeRef> {
+    let base_url = Url::parse(article_url).ok();
+    source_doc
+        .select(PRESERVED_META_SELECTOR)
+        .unwrap()
+        .map(|elem| {
+            let attrs = elem
+                .attributes
+                .borrow()
+                .map
+                .iter()
+                .map(|(name, attr)| {
+                    let value = match (&*name.local, &base_url) {
+                        ("href", Some(base_url)) => base_url
+                            .join(&attr.value)
+                            .map(String::from)
+                            .unwrap_or_else(|_| attr.value.clone()),
+                        _ => attr.value.clone(),
+                    };
+                    (name.local.to_string(), value)
+                })
+                .collect::<Vec<_>>();
+            let attrs = attrs
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect::<Vec<_>>();
+            new_element(&elem.name.local, &attrs)
+        })
+        .collect()
+}
+
+/// Appends the appendix listing the sources of the articles. With `has_source_qr`, a QR code
+/// of each source is inlined as an image under its link.
+fn insert_appendix(
+    root_node: &NodeRef,
+    article_links: Vec<(&MetaData, &str, Option<&str>)>,
+    has_source_qr: bool,
+    locale: Locale,
+) {
     let link_tags: String = article_links
         .iter()
-        .map(|(meta_data, url)| {
+        .map(|(meta_data, url, redirected_from)| {
             let article_name = if !meta_data.title().is_empty() {
                 meta_data.title()
             } else {
                 url
             };
-            format!("<a href=\"{}\">{}</a><br></br>", url, article_name)
+            let link_tag = match redirected_from {
+                Some(requested_url) => format!(
+                    "<a href=\"{}\">{}</a> ({} <a href=\"{}\">{}</a>)<br>",
+                    url,
+                    article_name,
+                    locale.text(Message::RedirectedFrom),
+                    requested_url,
+                    requested_url
+                ),
+                None => format!("<a href=\"{}\">{}</a><br>", url, article_name),
+            };
+            let source_qr = if has_source_qr {
+                source_qr_svg(url)
+            } else {
+                None
+            };
+            match source_qr {
+                Some(svg) => format!(
+                    "{}<img class=\"source-qr\" src=\"data:image/svg+xml;base64,{}\" alt=\"QR code of {}\"><br>",
+                    link_tag,
+                    encode(svg),
+                    url
+                ),
+                None => link_tag,
+            }
         })
         .collect();
     let footer_inner_html = format!(
-        "<footer><h2>Appendix</h2><h3>Article sources</h3>{}</footer>",
+        "<footer><h2>{}</h2><h3>{}</h3>{}</footer>",
+        locale.text(Message::Appendix),
+        locale.text(Message::ArticleSources),
         link_tags
     );
     let footer_container =
@@ -349,7 +612,7 @@ fn insert_appendix(root_node: &NodeRef, article_links: Vec<(&MetaData, &str)>) {
 }
 
 /// Inlines the CSS stylesheets into the HTML article node
-fn inline_css(root_node: &NodeRef, css_config: &CSSConfig) {
+pub fn inline_css(root_node: &NodeRef, css_config: &CSSConfig) {
     let body_stylesheet = include_str!("./assets/body.min.css");
     let header_stylesheet = include_str!("./assets/headers.min.css");
     let mut css_str = String::new();
@@ -374,6 +637,60 @@ fn inline_css(root_node: &NodeRef, css_config: &CSSConfig) {
 }
 
 /// Removes the <link> of the stylesheet. This is used when inlining styles
+/// Appends the stylesheet of the enabled typographic options to the `<head>` element
+pub fn inline_typography_css(root_node: &NodeRef, app_config: &AppConfig) {
+    let css_str = typography_css(app_config);
+    if css_str.is_empty() {
+        return;
+    }
+    let style_elem = NodeRef::new_element(create_qualname("style"), BTreeMap::new());
+    style_elem.append(NodeRef::new_text(css_str));
+    let head_elem = root_node.select_first("head").expect(HEAD_ELEM_NOT_FOUND);
+    head_elem.as_node().append(style_elem);
+}
+
+/// Adds the reading toolbar with font size controls and a dark mode toggle, and the bar
+/// showing how far the page has been read
+fn insert_reader_ui(root_node: &NodeRef) {
+    let (head_elem, body_elem) = match (
+        root_node.select_first("head"),
+        root_node.select_first("body"),
+    ) {
+        (Ok(head_elem), Ok(body_elem)) => (head_elem, body_elem),
+        _ => return,
+    };
+    let style_elem = new_element("style", &[]);
+    style_elem.append(NodeRef::new_text(include_str!(
+        "./assets/reader-ui.min.css"
+    )));
+    head_elem.as_node().append(style_elem);
+
+    let toolbar = new_element("div", &[("class", "paperoni-reader-toolbar")]);
+    for (action, label, text) in &[
+        ("smaller", "Decrease the font size", "A-"),
+        ("larger", "Increase the font size", "A+"),
+        ("dark", "Toggle dark mode", "◐"),
+    ] {
+        let button = new_element(
+            "button",
+            &[
+                ("type", "button"),
+                ("data-reader", *action),
+                ("title", *label),
+            ],
+        );
+        button.append(NodeRef::new_text(*text));
+        toolbar.append(button);
+    }
+    body_elem.as_node().prepend(toolbar);
+    body_elem
+        .as_node()
+        .prepend(new_element("div", &[("class", "paperoni-reader-progress")]));
+    let script_elem = new_element("script", &[]);
+    script_elem.append(NodeRef::new_text(include_str!("./assets/reader-ui.min.js")));
+    body_elem.as_node().append(script_elem);
+}
+
 fn remove_existing_stylesheet_link(root_node: &NodeRef) {
     if let Ok(style_link_elem) = root_node.select_first("link[href=\"stylesheet.css\"]") {
         style_link_elem.as_node().detach();
@@ -395,6 +712,94 @@ mod test {
         assert_eq!(title, doc.select_first("title").unwrap().text_contents());
     }
 
+    #[test]
+    fn test_paginated_file_name() {
+        assert_eq!("out-2.html", paginated_file_name("out.html", 2));
+        assert_eq!("dir/out-1.html", paginated_file_name("dir/out.html", 1));
+        assert_eq!("out-3", paginated_file_name("out", 3));
+        assert_eq!("out-1.html", file_name_of("dir/out-1.html"));
+    }
+
+    #[test]
+    fn test_insert_page_nav() {
+        let page_links = vec![
+            "out-1.html".to_owned(),
+            "out-2.html".to_owned(),
+            "out-3.html".to_owned(),
+        ];
+        let nav_links = |page| {
+            let doc = kuchiki::parse_html().one(BASE_HTML_TEMPLATE);
+            insert_page_nav(&doc, &page_links, page, "out.html");
+            assert_eq!(2, doc.select("nav").unwrap().count());
+            doc.select_first("nav")
+                .unwrap()
+                .as_node()
+                .select("a")
+                .unwrap()
+                .map(|link| link.attributes.borrow().get("href").unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["out.html", "out-2.html"], nav_links(0));
+        assert_eq!(vec!["out-1.html", "out.html", "out-3.html"], nav_links(1));
+        assert_eq!(vec!["out-2.html", "out.html"], nav_links(2));
+    }
+
+    #[test]
+    fn test_insert_reader_ui() {
+        let doc = kuchiki::parse_html().one(BASE_HTML_TEMPLATE);
+        doc.select_first("body")
+            .unwrap()
+            .as_node()
+            .append(new_element("article", &[]));
+        insert_reader_ui(&doc);
+        assert_eq!(1, doc.select("head style").unwrap().count());
+        assert_eq!(
+            3,
+            doc.select(".paperoni-reader-toolbar button")
+                .unwrap()
+                .count()
+        );
+        // The script is last so that the toolbar exists when it runs
+        let body = doc.select_first("body").unwrap();
+        assert_eq!(
+            "script",
+            &*body
+                .as_node()
+                .last_child()
+                .unwrap()
+                .as_element()
+                .unwrap()
+                .name
+                .local
+        );
+        assert!(doc.to_string().contains("paperoni-reader-progress"));
+    }
+
+    #[test]
+    fn test_preserved_meta_elements() {
+        let doc = kuchiki::parse_html().one(
+            r#"<html><head>
+            <link rel="canonical" href="/posts/pepperoni">
+            <link rel="shortcut icon" href="favicon.ico">
+            <link rel="stylesheet" href="style.css">
+            <meta property="article:published_time" content="2021-06-01T12:00:00Z">
+            <meta name="viewport" content="width=device-width">
+            </head><body></body></html>"#,
+        );
+        let elems = preserved_meta_elements(&doc, "https://example.com/blog/post?utm=1")
+            .iter()
+            .map(|elem| elem.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                r#"<link href="https://example.com/posts/pepperoni" rel="canonical">"#,
+                r#"<link href="https://example.com/blog/favicon.ico" rel="shortcut icon">"#,
+                r#"<meta content="2021-06-01T12:00:00Z" property="article:published_time">"#,
+            ],
+            elems
+        );
+    }
+
     #[test]
     fn test_create_qualname() {
         let name = "div";
@@ -463,7 +868,12 @@ mod test {
 
         assert_eq!(0, doc.select("footer").unwrap().count());
 
-        insert_appendix(&doc, vec![(&meta_data, "http://example.org")]);
+        insert_appendix(
+            &doc,
+            vec![(&meta_data, "http://example.org", None)],
+            false,
+            Locale::En,
+        );
 
         assert_eq!(1, doc.select("footer").unwrap().count());
         assert_eq!(1, doc.select("footer > h2").unwrap().count());
@@ -482,5 +892,52 @@ mod test {
         assert_eq!("http://example.org", anchor_elem.text_contents());
         let anchor_attrs = anchor_elem.attributes.borrow();
         assert_eq!(Some("http://example.org"), anchor_attrs.get("href"));
+
+        // The requested url is linked when the article was redirected
+        let doc = kuchiki::parse_html().one(html_str);
+        insert_appendix(
+            &doc,
+            vec![(
+                &meta_data,
+                "https://example.org/final",
+                Some("http://example.org/start"),
+            )],
+            false,
+            Locale::En,
+        );
+        assert_eq!(2, doc.select("footer a").unwrap().count());
+        let footer_text = doc.select_first("footer").unwrap().text_contents();
+        assert!(footer_text.contains("(redirected from http://example.org/start)"));
+
+        // A QR code of the source follows its link with --source-qr
+        let doc = kuchiki::parse_html().one(html_str);
+        insert_appendix(
+            &doc,
+            vec![(&meta_data, "http://example.org", None)],
+            cfg!(feature = "source-qr"),
+            Locale::En,
+        );
+        let qr_img_count = doc.select("footer img.source-qr").unwrap().count();
+        assert_eq!(
+            if cfg!(feature = "source-qr") { 1 } else { 0 },
+            qr_img_count
+        );
+
+        // The headings are written in the language of the locale
+        let doc = kuchiki::parse_html().one(html_str);
+        insert_appendix(
+            &doc,
+            vec![(&meta_data, "http://example.org", None)],
+            false,
+            Locale::Fr,
+        );
+        assert_eq!(
+            "Annexe",
+            doc.select_first("footer > h2").unwrap().text_contents()
+        );
+        assert_eq!(
+            "Sources des articles",
+            doc.select_first("footer > h3").unwrap().text_contents()
+        );
     }
 }
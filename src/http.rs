@@ -1,15 +1,45 @@
+use std::cell::Cell;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_std::future;
 use async_std::io::prelude::*;
 use async_std::task;
 use async_std::{fs::File, stream};
+use encoding_rs::{Encoding, UTF_8};
 use futures::StreamExt;
+use http_client::HttpClient;
 use indicatif::ProgressBar;
 use log::warn;
 use log::{debug, info};
 use url::Url;
 
-use crate::cli::AppConfig;
+use crate::arxiv;
+use crate::blog_api::{BlogApi, API_ARTICLE_ID};
+use crate::cli::{AppConfig, ArticleOptions, ExportType, HttpBackend, ResolveOverride, TlsOptions};
+use crate::comments::extract_comments;
+use crate::discussion::{fetch_discussion, Discussion, Thread, SELF_POST_ID};
 use crate::errors::{ErrorKind, ImgError, PaperoniError};
-use crate::extractor::Article;
+use crate::extraction_debug::write_extraction_debug;
+use crate::extractor::{Article, AudioItem, ORIGINAL_SRC_ATTR};
+use crate::gif_flatten;
+use crate::grayscale;
+use crate::i18n::{Locale, Message};
+use crate::image_cache::ImageCache;
+use crate::image_size::ImageSizeFilter;
+use crate::medium;
+use crate::moz_readability::Confidence;
+use crate::print_links::annotate_links;
+use crate::progress::{ProgressEvent, ProgressEvents};
+#[cfg(feature = "reqwest-client")]
+use crate::reqwest_client::ReqwestClient;
+use crate::stack_exchange;
+use crate::svg;
+use crate::text_replace::replace_text;
+use crate::translate::translate_article;
+use crate::youtube;
+/// A tuple of the url the HTML was fetched from after following redirects and the HTML itself
 type HTMLResource = (String, String);
 
 pub fn download(
@@ -17,24 +47,264 @@ pub fn download(
     bar: &ProgressBar,
     partial_downloads: &mut Vec<PartialDownload>,
     errors: &mut Vec<PaperoniError>,
+    skipped_urls: &mut Vec<String>,
 ) -> Vec<Article> {
     task::block_on(async {
-        let urls_iter = app_config.urls.iter().map(|url| fetch_html(url));
+        // The clients are shared by all requests so that connections to the same host are reused
+        let client = build_client(app_config, None);
+        let img_client = build_client(
+            app_config,
+            Some(surf::middleware::Redirect::new(app_config.max_redirects)),
+        );
+        let img_conversions = ImgConversions::from_config(app_config);
+        let urls_iter = app_config.urls.iter().map(|url| {
+            let client = &client;
+            // The time is taken when the fetch starts rather than when it is queued
+            async move {
+                app_config.emit_progress(ProgressEvent::Started { url });
+                let fetch_start = Instant::now();
+                let fetch = fetch_article_html(client, url, app_config);
+                let fetch_result = match app_config.article_timeout {
+                    Some(article_timeout) => future::timeout(article_timeout, fetch)
+                        .await
+                        .unwrap_or_else(|_| Err(article_timeout_error(url, article_timeout))),
+                    None => fetch.await,
+                };
+                (url, fetch_result, fetch_start, fetch_start.elapsed())
+            }
+        });
         let mut responses = stream::from_iter(urls_iter).buffered(app_config.max_conn);
         let mut articles = Vec::new();
-        while let Some(fetch_result) = responses.next().await {
+        while let Some((requested_url, fetch_result, fetch_start, fetch_duration)) =
+            responses.next().await
+        {
             match fetch_result {
-                Ok((url, html)) => {
+                Ok(FetchedArticle {
+                    resource: (url, html),
+                    discussion,
+                    content_selector,
+                }) => {
+                    // The time waiting for the articles before it to be processed is not counted
+                    let deadline = app_config.article_timeout.map(|article_timeout| {
+                        Instant::now()
+                            + article_timeout
+                                .checked_sub(fetch_duration)
+                                .unwrap_or_default()
+                    });
+                    app_config.emit_progress(ProgressEvent::Fetched {
+                        url: requested_url,
+                        final_url: &url,
+                        bytes: html.len(),
+                    });
                     debug!("Extracting {}", &url);
+                    if let Some(metrics) = app_config.metrics() {
+                        metrics.record_downloaded_bytes(html.len() as u64);
+                    }
                     let mut extractor = Article::from_html(&html, &url);
-                    bar.set_message("Extracting...");
-                    match extractor.extract_content() {
+                    extractor.requested_url = requested_url.to_owned();
+                    let mut extraction_budget = app_config.extraction_budget;
+                    if let Some(deadline) = deadline {
+                        extraction_budget.max_duration = extraction_budget
+                            .max_duration
+                            .min(deadline.saturating_duration_since(Instant::now()));
+                    }
+                    extractor.set_extraction_budget(extraction_budget);
+                    if medium::is_medium(&url, &html) {
+                        medium::inline_gists(&img_client, extractor.source_document(), &url).await;
+                    }
+                    // The thumbnail of a video is its cover even when covers were not asked for
+                    let has_cover = app_config.has_auto_cover
+                        || app_config.has_cover_collage
+                        || (youtube::video_id(&url).is_some()
+                            && matches!(app_config.export_type, ExportType::EPUB));
+                    bar.set_message(app_config.locale.text(Message::Extracting));
+                    // Comments are captured before readability strips them from the page
+                    let comments = if app_config.is_including_comments {
+                        extract_comments(&html)
+                    } else {
+                        None
+                    };
+                    let article_options = app_config.article_options.get(requested_url);
+                    let site_rule = app_config.site_rule(&url);
+                    let selector = content_selector.as_deref().or_else(|| {
+                        article_options
+                            .and_then(|options| options.selector.as_deref())
+                            .or_else(|| {
+                                if app_config.is_skipping_extraction {
+                                    Some("body")
+                                } else {
+                                    None
+                                }
+                            })
+                            .or_else(|| site_rule.and_then(|rule| rule.selector.as_deref()))
+                    });
+                    if let Some(pattern_overrides) =
+                        site_rule.and_then(|rule| rule.pattern_overrides().ok())
+                    {
+                        extractor.set_pattern_overrides(pattern_overrides);
+                    }
+                    let extract_start = Instant::now();
+                    let extract_result = match selector {
+                        Some(selector) => extractor.extract_content_with_selector(selector),
+                        None => extractor.extract_content(),
+                    };
+                    if let Some(metrics) = app_config.metrics() {
+                        metrics.record_extraction(extract_start.elapsed());
+                    }
+                    if let Some(debug_dir) = &app_config.debug_extraction_dir {
+                        let extracted = extract_result.as_ref().ok().map(|_| extractor.node_ref());
+                        match write_extraction_debug(debug_dir, &url, &html, extracted) {
+                            Ok(article_dir) => {
+                                debug!("Wrote the extraction of {} to {:?}", url, article_dir)
+                            }
+                            Err(e) => warn!(
+                                "Unable to write the extraction of {} for debugging: {}",
+                                url, e
+                            ),
+                        }
+                    }
+                    match extract_result {
                         Ok(_) => {
+                            if let Some(date_filter) = &app_config.date_filter {
+                                let metadata = extractor.metadata();
+                                if !date_filter.allows(metadata.published_date()) {
+                                    info!(
+                                        "Skipping {} published on {}",
+                                        url,
+                                        metadata.published().unwrap_or("an unknown date")
+                                    );
+                                    app_config.emit_progress(ProgressEvent::Skipped {
+                                        url: requested_url,
+                                        reason: "published outside of the date range",
+                                    });
+                                    skipped_urls.push(url.clone());
+                                    bar.inc(1);
+                                    continue;
+                                }
+                            }
+                            if let Some(min_confidence) = app_config.min_confidence {
+                                let score = extractor.confidence().map(Confidence::score);
+                                if score.map_or(false, |score| score < min_confidence) {
+                                    warn!(
+                                        "Skipping {} extracted with a confidence of {:.2}",
+                                        url,
+                                        score.unwrap()
+                                    );
+                                    app_config.emit_progress(ProgressEvent::Skipped {
+                                        url: requested_url,
+                                        reason:
+                                            "extracted with a confidence below --min-confidence",
+                                    });
+                                    skipped_urls.push(url.clone());
+                                    bar.inc(1);
+                                    continue;
+                                }
+                            }
+                            apply_metadata_overrides(&mut extractor, app_config, article_options);
+                            if let Some(comments) = comments {
+                                extractor.append_comments(comments);
+                            }
+                            if let Some(discussion) = &discussion {
+                                extractor.append_comments(discussion.section());
+                            }
+                            if app_config.has_lead_image {
+                                extractor.place_lead_image();
+                            }
+                            extractor.remove_elements(&app_config.remove_selectors);
+                            if let Some(rule) = site_rule {
+                                extractor.remove_elements(&rule.remove_selectors);
+                            }
+                            let mut replaced_count =
+                                replace_text(extractor.node_ref(), &app_config.text_replacements);
+                            if let Some(rule) = site_rule {
+                                replaced_count += replace_text(extractor.node_ref(), &rule.replace);
+                            }
+                            if replaced_count > 0 {
+                                debug!("Replaced text in {} places of {}", replaced_count, url);
+                            }
+                            if let Some(translate) = &app_config.translate {
+                                match translate_article(&client, &extractor, translate).await {
+                                    Ok(count) => debug!(
+                                        "Translated {} paragraphs of {} to {}",
+                                        count, url, translate.target_lang
+                                    ),
+                                    Err(e) => warn!(
+                                        "Unable to translate {} so it is kept as it is\n\t\tReason {}",
+                                        url, e
+                                    ),
+                                }
+                            }
+                            if app_config.has_site_logo {
+                                extractor.add_site_logo();
+                            }
+                            if app_config.is_skipping_images {
+                                extractor.remove_images(app_config.has_alt_captions);
+                            }
+                            extractor.remove_unwanted_images(&app_config.skipped_image_patterns);
+                            for audio_item in extractor.replace_audio_elements() {
+                                let audio_dir = if app_config.is_downloading_audio {
+                                    Some(app_config.output_directory.as_deref().unwrap_or("."))
+                                } else {
+                                    None
+                                };
+                                match fetch_audio_details(&img_client, &audio_item, audio_dir).await
+                                {
+                                    Ok((size, saved_as)) => extractor.fill_audio_block(
+                                        &audio_item,
+                                        size,
+                                        saved_as.as_deref(),
+                                    ),
+                                    Err(e) => warn!(
+                                        "Unable to get the details of the audio {}\n\t\tReason {}",
+                                        audio_item.url, e
+                                    ),
+                                }
+                            }
+                            // The content is sanitized once everything added to it is in place
+                            // and before its images are collected
+                            if let Some(sanitizer) = &app_config.sanitizer {
+                                sanitizer.sanitize(extractor.node_ref());
+                            }
+                            if let Some(print_links) = app_config.print_links {
+                                annotate_links(extractor.node_ref(), print_links);
+                            }
                             extractor.extract_img_urls();
-                            if let Err(img_errors) =
-                                download_images(&mut extractor, &Url::parse(&url).unwrap(), &bar)
-                                    .await
-                            {
+                            let images_download = download_images(
+                                &mut extractor,
+                                &img_client,
+                                &Url::parse(&url).unwrap(),
+                                img_conversions,
+                                app_config.image_cache.as_ref(),
+                                &app_config.image_size_filter,
+                                &bar,
+                                app_config.locale,
+                                app_config.progress.as_ref(),
+                            );
+                            let images_result = match deadline {
+                                Some(deadline) => match future::timeout(
+                                    deadline.saturating_duration_since(Instant::now()),
+                                    images_download,
+                                )
+                                .await
+                                {
+                                    Ok(images_result) => images_result,
+                                    Err(_) => {
+                                        let e = article_timeout_error(
+                                            requested_url,
+                                            app_config.article_timeout.unwrap(),
+                                        );
+                                        app_config.emit_progress(ProgressEvent::Failed {
+                                            url: requested_url,
+                                            reason: e.to_string(),
+                                        });
+                                        errors.push(e);
+                                        bar.inc(1);
+                                        continue;
+                                    }
+                                },
+                                None => images_download.await,
+                            };
+                            if let Err(img_errors) = images_result {
                                 partial_downloads
                                     .push(PartialDownload::new(&url, extractor.metadata().title()));
                                 warn!(
@@ -51,60 +321,420 @@ pub fn download(
                                     );
                                 }
                             }
+                            if has_cover {
+                                download_cover(
+                                    &mut extractor,
+                                    &img_client,
+                                    &Url::parse(&url).unwrap(),
+                                    img_conversions,
+                                    app_config.image_cache.as_ref(),
+                                )
+                                .await;
+                            }
+                            if let Some(metrics) = app_config.metrics() {
+                                metrics.record_article();
+                                metrics.record_downloaded_bytes(downloaded_image_bytes(&extractor));
+                            }
+                            if let Some(history) = &app_config.history {
+                                history.record(
+                                    requested_url,
+                                    fetch_start.elapsed(),
+                                    html.len() as u64 + downloaded_image_bytes(&extractor),
+                                );
+                            }
                             articles.push(extractor);
                         }
                         Err(mut e) => {
+                            // Extractions cut short by the article timeout are reported as such
+                            if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                                e = article_timeout_error(
+                                    requested_url,
+                                    app_config.article_timeout.unwrap(),
+                                );
+                            }
                             e.set_article_source(&url);
+                            app_config.emit_progress(ProgressEvent::Failed {
+                                url: requested_url,
+                                reason: e.to_string(),
+                            });
                             errors.push(e);
                         }
                     }
                 }
-                Err(e) => errors.push(e),
+                Err(e) => {
+                    app_config.emit_progress(ProgressEvent::Failed {
+                        url: requested_url,
+                        reason: e.to_string(),
+                    });
+                    errors.push(e);
+                }
             }
             bar.inc(1);
         }
+        if let Some(conn_limiter) = &app_config.conn_limiter {
+            conn_limiter.log_host_rates();
+        }
+        if let Some(image_cache) = &app_config.image_cache {
+            if let Err(e) = image_cache.prune() {
+                warn!("Unable to prune the image cache: {}", e);
+            }
+        }
         articles
     })
 }
 
-pub async fn fetch_html(url: &str) -> Result<HTMLResource, PaperoniError> {
-    let client = surf::Client::new();
+/// The size of the images downloaded for an article and its cover
+fn downloaded_image_bytes(article: &Article) -> u64 {
+    article
+        .img_urls
+        .iter()
+        .chain(article.cover_img.iter())
+        .filter_map(|(file_name, _)| std::fs::metadata(std::env::temp_dir().join(file_name)).ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+#[cfg(not(any(
+    feature = "curl-client",
+    feature = "h1-client",
+    feature = "reqwest-client"
+)))]
+compile_error!("Paperoni needs one of the curl-client, h1-client or reqwest-client features");
+
+/// Creates an HTTP client with the middleware for the credentials and cookies of the app config.
+/// Redirects are handled manually when fetching HTML so the redirect middleware is only
+/// passed for clients that download images.
+///
+/// The requests of the client, such as the ones fetching HTML and images, are sent through
+/// the [http_client::HttpClient] of the backend picked with --http-backend so that none of
+/// them depend on a particular backend.
+pub fn build_client(
+    app_config: &AppConfig,
+    redirect_middleware: Option<surf::middleware::Redirect>,
+) -> surf::Client {
+    let mut client = new_backend_client(
+        app_config.http_backend,
+        &app_config.tls,
+        &app_config.resolve_overrides,
+    );
+    if let Some(redirect_middleware) = redirect_middleware {
+        client = client.with(redirect_middleware);
+    }
+    if !app_config.credentials.is_empty() {
+        client = client.with(AuthMiddleware::new(app_config.credentials.clone()));
+    }
+    if !app_config.cookie_jar.is_empty() {
+        client = client.with(app_config.cookie_jar.clone());
+    }
+    if let Some(conn_limiter) = &app_config.conn_limiter {
+        client = client.with(conn_limiter.clone());
+    }
+    client
+}
+
+/// Creates a client sending its requests with the backend. Backends that are not built and
+/// options a backend does not support are rejected when parsing the arguments.
+fn new_backend_client(
+    backend: HttpBackend,
+    tls: &TlsOptions,
+    resolve_overrides: &[ResolveOverride],
+) -> surf::Client {
+    let verified = new_http_client(backend, tls, resolve_overrides);
+    let insecure = if tls.is_insecure || tls.insecure_hosts.is_empty() {
+        None
+    } else {
+        let insecure_tls = TlsOptions {
+            is_insecure: true,
+            ..tls.clone()
+        };
+        Some(new_http_client(backend, &insecure_tls, resolve_overrides))
+    };
+    surf::Client::with_http_client(BackendClient {
+        verified,
+        insecure,
+        insecure_hosts: tls.insecure_hosts.clone(),
+    })
+}
+
+#[allow(unreachable_patterns, unused_variables)]
+fn new_http_client(
+    backend: HttpBackend,
+    tls: &TlsOptions,
+    resolve_overrides: &[ResolveOverride],
+) -> Arc<dyn HttpClient> {
+    match backend {
+        #[cfg(feature = "curl-client")]
+        HttpBackend::Curl => Arc::new(new_curl_client(tls, resolve_overrides)),
+        #[cfg(feature = "h1-client")]
+        HttpBackend::H1 => Arc::new(http_client::h1::H1Client::new()),
+        #[cfg(feature = "reqwest-client")]
+        HttpBackend::Reqwest => match ReqwestClient::new(tls) {
+            Ok(http_client) => Arc::new(http_client),
+            Err(e) => {
+                warn!(
+                    "Unable to apply the connection options to the HTTP client: {}",
+                    e
+                );
+                Arc::new(
+                    ReqwestClient::new(&TlsOptions::default())
+                        .expect("The default reqwest client is always built"),
+                )
+            }
+        },
+        _ => unreachable!("The {} HTTP backend is not built", backend.name()),
+    }
+}
+
+/// The client of a backend. The backends take their TLS options for all of their requests so
+/// the requests to the hosts of --insecure-host are sent with a second client of the backend
+/// that does not verify certificates.
+#[derive(Debug)]
+struct BackendClient {
+    verified: Arc<dyn HttpClient>,
+    insecure: Option<Arc<dyn HttpClient>>,
+    insecure_hosts: Vec<String>,
+}
+
+impl BackendClient {
+    fn client_for(&self, url: &Url) -> &Arc<dyn HttpClient> {
+        let host = url.host_str().unwrap_or_default().to_lowercase();
+        match &self.insecure {
+            Some(insecure) if is_insecure_host(&self.insecure_hosts, &host) => insecure,
+            _ => &self.verified,
+        }
+    }
+}
+
+#[surf::utils::async_trait]
+impl HttpClient for BackendClient {
+    async fn send(
+        &self,
+        req: http_client::Request,
+    ) -> Result<http_client::Response, http_client::Error> {
+        self.client_for(req.url()).send(req).await
+    }
+}
+
+/// Checks if certificates are not verified for a host, which is the case for the domains of
+/// --insecure-host and their subdomains
+fn is_insecure_host(insecure_hosts: &[String], host: &str) -> bool {
+    insecure_hosts
+        .iter()
+        .any(|domain| host == domain || host.ends_with(&format!(".{}", domain)))
+}
+
+/// Creates a curl backend that verifies certificates with the TLS options and connects to
+/// the overridden addresses of hosts. A default client is only returned when the backend
+/// cannot be built with the options. curl uses the file of --ca-cert instead of the
+/// certificate authorities of the system rather than along with them.
+#[cfg(feature = "curl-client")]
+fn new_curl_client(
+    tls: &TlsOptions,
+    resolve_overrides: &[ResolveOverride],
+) -> http_client::isahc::IsahcClient {
+    use http_client::isahc::IsahcClient;
+    use isahc::config::{CaCertificate, ResolveMap, SslOption};
+
+    if !tls.is_insecure && tls.ca_cert.is_none() && resolve_overrides.is_empty() {
+        return IsahcClient::new();
+    }
+    let mut builder = isahc::HttpClient::builder();
+    if tls.is_insecure {
+        builder = builder.ssl_options(
+            SslOption::DANGER_ACCEPT_INVALID_CERTS | SslOption::DANGER_ACCEPT_INVALID_HOSTS,
+        );
+    }
+    if let Some(ca_cert) = &tls.ca_cert {
+        builder = builder.ssl_ca_certificate(CaCertificate::file(ca_cert));
+    }
+    if !resolve_overrides.is_empty() {
+        let resolve_map =
+            resolve_overrides
+                .iter()
+                .fold(ResolveMap::new(), |resolve_map, resolved| {
+                    resolve_map.add(&resolved.host, resolved.port, resolved.address)
+                });
+        builder = builder.dns_resolve(resolve_map);
+    }
+    match builder.build() {
+        Ok(http_client) => IsahcClient::from_client(http_client),
+        Err(e) => {
+            warn!(
+                "Unable to apply the connection options to the HTTP client: {}",
+                e
+            );
+            IsahcClient::new()
+        }
+    }
+}
+
+/// The page of an article with the discussion thread it was linked from
+struct FetchedArticle {
+    resource: HTMLResource,
+    discussion: Option<Discussion>,
+    /// Selector of the content of pages built from an API response instead of downloaded
+    content_selector: Option<String>,
+}
+
+/// The error of an article that took longer than --article-timeout to fetch, extract and
+/// download the images of
+fn article_timeout_error(url: &str, article_timeout: Duration) -> PaperoniError {
+    let mut err: PaperoniError = ErrorKind::ArticleTimeout(format!(
+        "The article took longer than {:?} to download",
+        article_timeout
+    ))
+    .into();
+    err.set_article_source(url);
+    err
+}
+
+/// Fetches the HTML of an article. The url of a Hacker News or Reddit thread is replaced by
+/// the article it links to, which is returned along with the discussion of the thread. Self
+/// posts are returned as a document of their text. Articles on dev.to and Hashnode are
+/// fetched from their APIs and scraped when the API fails while arXiv papers are fetched as
+/// the HTML renderings of their source.
+async fn fetch_article_html(
+    client: &surf::Client,
+    url: &str,
+    app_config: &AppConfig,
+) -> Result<FetchedArticle, PaperoniError> {
+    let rewritten_url = rewrite_url(url, app_config);
+    let url = rewritten_url.as_deref().unwrap_or(url);
+    if let Some(paper_id) = arxiv::paper_id(url) {
+        return arxiv::fetch_paper(client, &paper_id, app_config.max_redirects)
+            .await
+            .map(|resource| FetchedArticle {
+                resource,
+                discussion: None,
+                content_selector: Some(arxiv::PAPER_SELECTOR.to_owned()),
+            });
+    }
+    if let Some(video_id) = youtube::video_id(url) {
+        return youtube::fetch_video(client, &video_id, app_config.max_redirects)
+            .await
+            .map(|resource| FetchedArticle {
+                resource,
+                discussion: None,
+                content_selector: Some(format!("#{}", API_ARTICLE_ID)),
+            });
+    }
+    if let Some(blog_api) = BlogApi::from_url(url) {
+        match blog_api.fetch_document(client).await {
+            Ok(html) => {
+                return Ok(FetchedArticle {
+                    resource: (url.to_owned(), html),
+                    discussion: None,
+                    content_selector: Some(format!("#{}", API_ARTICLE_ID)),
+                })
+            }
+            Err(e) => warn!(
+                "Unable to fetch {} from the {} API so it is scraped instead: {}",
+                url,
+                blog_api.site_name(),
+                e
+            ),
+        }
+    }
+    if let Some(question) = stack_exchange::Question::from_url(url) {
+        match question.fetch_document(client).await {
+            Ok(html) => {
+                return Ok(FetchedArticle {
+                    resource: (url.to_owned(), html),
+                    discussion: None,
+                    content_selector: Some(format!("#{}", API_ARTICLE_ID)),
+                })
+            }
+            Err(e) => warn!(
+                "Unable to fetch {} from the Stack Exchange API so it is scraped instead: {}",
+                url, e
+            ),
+        }
+    }
+    let thread = match Thread::from_url(url) {
+        Some(thread) => thread,
+        None => {
+            return fetch_html(client, url, app_config.max_redirects)
+                .await
+                .map(|resource| FetchedArticle {
+                    resource,
+                    discussion: None,
+                    content_selector: None,
+                })
+        }
+    };
+    let discussion = fetch_discussion(client, url, &thread, &app_config.discussion).await?;
+    let (resource, content_selector) = match &discussion.link {
+        Some(link) => {
+            let rewritten_link = rewrite_url(link, app_config);
+            let link = rewritten_link.as_deref().unwrap_or(link);
+            info!("Fetching {} linked from {}", link, url);
+            (
+                fetch_html(client, link, app_config.max_redirects).await?,
+                None,
+            )
+        }
+        None => (
+            (url.to_owned(), discussion.self_post_html()),
+            Some(format!("#{}", SELF_POST_ID)),
+        ),
+    };
+    Ok(FetchedArticle {
+        resource,
+        discussion: Some(discussion),
+        content_selector,
+    })
+}
+
+/// Rewrites the url of an article with the --url-rewrite rules before it is fetched. Returns
+/// `None` when no rule matches it.
+pub fn rewrite_url(url: &str, app_config: &AppConfig) -> Option<String> {
+    let rewritten_url = app_config.url_rewriter.rewrite(url)?;
+    info!("Rewriting {} to {}", url, rewritten_url);
+    Some(rewritten_url)
+}
+
+pub async fn fetch_html(
+    client: &surf::Client,
+    url: &str,
+    max_redirects: u8,
+) -> Result<HTMLResource, PaperoniError> {
     debug!("Fetching {}", url);
 
     let process_request = async {
         let mut redirect_count: u8 = 0;
-        let base_url = Url::parse(&url)?;
-        let mut url = base_url.clone();
-        while redirect_count < 5 {
-            redirect_count += 1;
+        let mut url = Url::parse(&url)?;
+        loop {
             let req = surf::get(&url);
             let mut res = client.send(req).await?;
-            if res.status().is_redirection() {
-                if let Some(location) = res.header(surf::http::headers::LOCATION) {
-                    match Url::parse(location.last().as_str()) {
-                        Ok(valid_url) => {
-                            info!("Redirecting {} to {}", url, valid_url);
-                            url = valid_url
-                        }
-                        Err(e) => match e {
-                            url::ParseError::RelativeUrlWithoutBase => {
-                                match base_url.join(location.last().as_str()) {
-                                    Ok(joined_url) => {
-                                        info!("Redirecting {} to {}", url, joined_url);
-                                        url = joined_url;
-                                    }
-                                    Err(e) => return Err(e.into()),
-                                }
-                            }
-                            e => return Err(e.into()),
-                        },
-                    };
+            if is_followed_redirect(res.status()) {
+                if redirect_count >= max_redirects {
+                    let msg = format!("Too many redirects. Stopped after {}", max_redirects);
+                    return Err(ErrorKind::HTTPError(msg).into());
                 }
+                redirect_count += 1;
+                let location = match res.header(surf::http::headers::LOCATION) {
+                    Some(location) => location.last().as_str().to_owned(),
+                    None => {
+                        let msg = format!("HTTP {} redirect has no Location", res.status());
+                        return Err(ErrorKind::HTTPError(msg).into());
+                    }
+                };
+                // Relative locations are resolved against the url that was redirected.
+                // Since only GET requests are made, 303 and 307/308 redirects are all followed
+                // with a GET and there is no request body to resend.
+                let redirect_url = url.join(&location)?;
+                info!("Redirecting {} to {}", url, redirect_url);
+                url = redirect_url;
             } else if res.status().is_success() {
                 if let Some(mime) = res.content_type() {
                     if mime.essence() == "text/html" {
                         debug!("Successfully fetched {}", url);
-                        return Ok((url.to_string(), res.body_string().await?));
+                        let content_type = res
+                            .header(surf::http::headers::CONTENT_TYPE)
+                            .map(|header| header.last().as_str().to_owned());
+                        let body = res.body_bytes().await?;
+                        let html = decode_html_bytes(&body, content_type.as_deref());
+                        return Ok((url.to_string(), html));
                     } else {
                         let msg = format!(
                             "Invalid HTTP response. Received {} instead of text/html",
@@ -121,7 +751,6 @@ pub async fn fetch_html(url: &str) -> Result<HTMLResource, PaperoniError> {
                 return Err(ErrorKind::HTTPError(msg).into());
             }
         }
-        Err(ErrorKind::HTTPError("Unable to fetch HTML".to_owned()).into())
     };
 
     process_request.await.map_err(|mut error: PaperoniError| {
@@ -130,11 +759,63 @@ pub async fn fetch_html(url: &str) -> Result<HTMLResource, PaperoniError> {
     })
 }
 
+/// Checks if the status code is a redirect that should be followed. Other 3xx responses such
+/// as 300 Multiple Choices and 304 Not Modified do not point to a new location.
+fn is_followed_redirect(status: surf::StatusCode) -> bool {
+    use surf::StatusCode;
+    matches!(
+        status,
+        StatusCode::MovedPermanently
+            | StatusCode::Found
+            | StatusCode::SeeOther
+            | StatusCode::TemporaryRedirect
+            | StatusCode::PermanentRedirect
+    )
+}
+
 type ImgItem<'a> = (&'a str, String, Option<String>);
 
+/// How downloaded images are converted before they are saved
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ImgConversions {
+    /// Converts SVG images to PNG
+    pub is_rasterizing_svgs: bool,
+    /// Keeps only the first frame of animated GIFs as a PNG
+    pub is_flattening_gifs: bool,
+    /// Converts images to 8-bit grayscale
+    pub is_grayscale: bool,
+}
+
+impl ImgConversions {
+    pub fn from_config(app_config: &AppConfig) -> Self {
+        Self {
+            is_rasterizing_svgs: app_config.is_rasterizing_svgs,
+            is_flattening_gifs: app_config.is_flattening_gifs,
+            is_grayscale: app_config.is_grayscale,
+        }
+    }
+
+    /// The key of an image in the image cache. Converted images are cached separately from
+    /// the original ones.
+    fn cache_key(&self, absolute_url: &str) -> String {
+        let mut key = absolute_url.to_owned();
+        if self.is_rasterizing_svgs {
+            key.push_str("#rasterized");
+        }
+        if self.is_flattening_gifs {
+            key.push_str("#flattened");
+        }
+        if self.is_grayscale {
+            key.push_str("#grayscale");
+        }
+        hash_url(&key)
+    }
+}
+
 async fn process_img_response<'a>(
     img_response: &mut surf::Response,
     url: &'a str,
+    conversions: ImgConversions,
 ) -> Result<ImgItem<'a>, ImgError> {
     if !img_response.status().is_success() {
         let kind = ErrorKind::HTTPError(format!(
@@ -143,13 +824,34 @@ async fn process_img_response<'a>(
         ));
         return Err(ImgError::with_kind(kind));
     }
-    let img_content: Vec<u8> = match img_response.body_bytes().await {
+    let mut img_content: Vec<u8> = match img_response.body_bytes().await {
         Ok(bytes) => bytes,
         Err(e) => return Err(e.into()),
     };
-    let img_mime = img_response
+    let mut img_mime = img_response
         .content_type()
         .map(|mime| mime.essence().to_string());
+    let mut img_ext = img_response
+        .content_type()
+        .map(|mime| map_mime_subtype_to_ext(mime.subtype()).to_string());
+
+    if svg::is_svg(img_mime.as_deref(), url, &img_content) {
+        img_content = svg::sanitize_svg_document(&img_content)?;
+        img_mime = Some("image/svg+xml".to_owned());
+        img_ext = Some("svg".to_owned());
+        if conversions.is_rasterizing_svgs {
+            img_content = svg::rasterize_svg(&img_content)?;
+            img_mime = Some("image/png".to_owned());
+            img_ext = Some("png".to_owned());
+        }
+    } else if conversions.is_flattening_gifs && img_mime.as_deref() == Some("image/gif") {
+        if let Some(png_content) = gif_flatten::flatten_gif(&img_content)? {
+            img_content = png_content;
+            img_mime = Some("image/png".to_owned());
+            img_ext = Some("png".to_owned());
+        }
+    }
+
     if let Some(mime_str) = &img_mime {
         if !mime_str.starts_with("image/") {
             return Err(ErrorKind::HTTPError(format!(
@@ -159,10 +861,23 @@ async fn process_img_response<'a>(
             .into());
         }
     }
-    let img_ext = match img_response
-        .content_type()
-        .map(|mime| map_mime_subtype_to_ext(mime.subtype()).to_string())
-    {
+    if conversions.is_grayscale {
+        match img_mime.as_deref() {
+            Some(mime_str) if mime_str != "image/svg+xml" => {
+                match grayscale::to_grayscale(&img_content, mime_str) {
+                    Ok((gray_content, gray_mime, gray_ext)) => {
+                        img_content = gray_content;
+                        img_mime = Some(gray_mime.to_owned());
+                        img_ext = Some(gray_ext.to_owned());
+                    }
+                    // Images in formats that cannot be decoded are kept in color
+                    Err(e) => debug!("Unable to convert {} to grayscale: {}", url, e),
+                }
+            }
+            _ => (),
+        }
+    }
+    let img_ext = match img_ext {
         Some(mime_str) => mime_str,
         None => return Err(ErrorKind::HTTPError("Image has no Content-Type".to_owned()).into()),
     };
@@ -193,10 +908,172 @@ async fn process_img_response<'a>(
     ))
 }
 
+/// Downloads an image or restores it from the image cache when it was downloaded in an
+/// earlier run. Downloaded images are added to the cache right away.
+async fn fetch_image<'a>(
+    client: &surf::Client,
+    url: &'a str,
+    absolute_url: &str,
+    conversions: ImgConversions,
+    image_cache: Option<&ImageCache>,
+) -> Result<ImgItem<'a>, ImgError> {
+    let cache_key = conversions.cache_key(absolute_url);
+    let img_path = std::env::temp_dir().join(hash_url(url));
+    if let Some((restored_path, img_mime)) =
+        image_cache.and_then(|cache| cache.restore(&cache_key, &img_path))
+    {
+        let file_name = restored_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        return Ok((url, file_name, img_mime));
+    }
+
+    let mut img_response = client.get(absolute_url).await?;
+    let img_item = process_img_response(&mut img_response, url, conversions).await?;
+    if let Some(cache) = image_cache {
+        let (_, file_name, img_mime) = &img_item;
+        let downloaded_path = std::env::temp_dir().join(file_name);
+        if let Err(e) = cache.store(
+            &cache_key,
+            absolute_url,
+            &downloaded_path,
+            img_mime.as_deref(),
+        ) {
+            warn!("Unable to add {} to the image cache: {}", absolute_url, e);
+        }
+    }
+    Ok(img_item)
+}
+
+/// Downloads the image of an article from its metadata to use as the cover of its epub,
+/// falling back to the icon of its site. No cover is used when neither can be downloaded.
+async fn download_cover(
+    extractor: &mut Article,
+    client: &surf::Client,
+    article_origin: &Url,
+    conversions: ImgConversions,
+    image_cache: Option<&ImageCache>,
+) {
+    let cover_urls = vec![
+        extractor.metadata().image(),
+        extractor.metadata().site_icon(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|cover_url| get_absolute_url(cover_url, article_origin))
+    .collect::<Vec<_>>();
+    for cover_url in cover_urls {
+        // The lead image is usually downloaded with the other images already
+        if let Some(cover_img) = extractor.downloaded_image(&cover_url) {
+            debug!("Using the downloaded image {} as the cover", cover_url);
+            extractor.cover_img = Some(cover_img);
+            return;
+        }
+        debug!("Downloading cover {} for {}", cover_url, article_origin);
+        let cover_result =
+            fetch_image(client, &cover_url, &cover_url, conversions, image_cache).await;
+        match cover_result {
+            Ok((_, cover_path, cover_mime)) => {
+                extractor.cover_img = Some((cover_path, cover_mime));
+                return;
+            }
+            Err(e) => warn!("Unable to download cover {}\n\t\tReason {}", cover_url, e),
+        }
+    }
+    info!("No cover was found for {}", article_origin);
+}
+
+/// Gets the size of an audio from the Content-Length of a HEAD request or downloads it to
+/// the audio directory when one is passed. Returns the size with the name of the saved file.
+async fn fetch_audio_details(
+    client: &surf::Client,
+    audio_item: &AudioItem,
+    audio_dir: Option<&str>,
+) -> Result<(Option<u64>, Option<String>), PaperoniError> {
+    let audio_dir = match audio_dir {
+        Some(audio_dir) => audio_dir,
+        None => {
+            let res = client.send(surf::head(&audio_item.url)).await?;
+            let size = res
+                .header(surf::http::headers::CONTENT_LENGTH)
+                .and_then(|length| length.last().as_str().parse::<u64>().ok())
+                .filter(|_| res.status().is_success());
+            return Ok((size, None));
+        }
+    };
+    debug!("Downloading the audio {}", audio_item.url);
+    let mut res = client.get(&audio_item.url).await?;
+    if !res.status().is_success() {
+        let msg = format!("Non-success HTTP status code ({})", res.status());
+        return Err(ErrorKind::HTTPError(msg).into());
+    }
+    let audio_content = res.body_bytes().await?;
+    let file_name = audio_file_name(&audio_item.url);
+    let mut audio_file = File::create(Path::new(audio_dir).join(&file_name)).await?;
+    audio_file.write_all(&audio_content).await?;
+    info!("Saved the audio {} as {}", audio_item.url, file_name);
+    Ok((Some(audio_content.len() as u64), Some(file_name)))
+}
+
+/// Names a downloaded audio after the last segment of its url, prefixed with a hash of the
+/// url so that audio files with the same name from different sites do not overwrite each
+/// other
+fn audio_file_name(url: &str) -> String {
+    let url_hash = hash_url(url);
+    let name = Url::parse(url)
+        .ok()
+        .and_then(|url| {
+            url.path_segments()
+                .and_then(|segments| segments.last().map(ToOwned::to_owned))
+        })
+        .map(|name| {
+            name.chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || c == '.' || c == '-' || c == '_' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect::<String>()
+        })
+        .filter(|name| !name.trim_matches('.').is_empty())
+        .unwrap_or_else(|| "audio".to_owned());
+    format!("{}-{}", &url_hash[..8], name)
+}
+
+/// Replaces the extracted metadata of an article with the values passed by the user.
+/// The `--title` and `--author` flags only apply to an article when it is not merged.
+fn apply_metadata_overrides(
+    article: &mut Article,
+    app_config: &AppConfig,
+    article_options: Option<&ArticleOptions>,
+) {
+    if let Some(title) = article_options.and_then(|options| options.title.as_ref()) {
+        article.set_title(title);
+    }
+    if app_config.merged.is_none() {
+        if let Some(title) = &app_config.title {
+            article.set_title(title);
+        }
+        if let Some(author) = &app_config.author {
+            article.set_byline(author);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn download_images(
     extractor: &mut Article,
+    client: &surf::Client,
     article_origin: &Url,
+    conversions: ImgConversions,
+    image_cache: Option<&ImageCache>,
+    size_filter: &ImageSizeFilter,
     bar: &ProgressBar,
+    locale: Locale,
+    progress: Option<&ProgressEvents>,
 ) -> Result<(), Vec<ImgError>> {
     if extractor.img_urls.len() > 0 {
         debug!(
@@ -206,40 +1083,42 @@ pub async fn download_images(
         );
     }
     let img_count = extractor.img_urls.len();
+    let requested_url = &extractor.requested_url;
+    let done_count = &Cell::new(0);
 
     let imgs_req_iter = extractor
         .img_urls
         .iter()
-        .map(|(url, _)| {
-            (
-                url,
-                surf::Client::new()
-                    .with(surf::middleware::Redirect::default())
-                    .get(get_absolute_url(&url, article_origin)),
-            )
-        })
+        .map(|(url, _)| (url, get_absolute_url(&url, article_origin)))
         .enumerate()
-        .map(|(img_idx, (url, req))| async move {
+        .map(|(img_idx, (url, absolute_url))| async move {
             bar.set_message(format!(
-                "Downloading images [{}/{}]",
+                "{} [{}/{}]",
+                locale.text(Message::DownloadingImages),
                 img_idx + 1,
                 img_count
             ));
-            match req.await {
-                Ok(mut img_response) => {
-                    let process_response =
-                        process_img_response(&mut img_response, url.as_ref()).await;
-                    process_response.map_err(|mut e: ImgError| {
-                        e.set_url(url);
-                        e
-                    })
-                }
-                Err(e) => {
-                    let mut img_err: ImgError = e.into();
-                    img_err.set_url(url);
-                    Err(img_err)
-                }
+            let img_result = fetch_image(
+                client,
+                url.as_ref(),
+                &absolute_url,
+                conversions,
+                image_cache,
+            )
+            .await
+            .map_err(|mut e: ImgError| {
+                e.set_url(url);
+                e
+            });
+            if let Some(progress) = progress {
+                done_count.set(done_count.get() + 1);
+                progress.emit(&ProgressEvent::Images {
+                    url: requested_url,
+                    done: done_count.get(),
+                    total: img_count,
+                });
             }
+            img_result
         });
 
     // A utility closure used when update the value of an image source after downloading is successful
@@ -251,6 +1130,7 @@ pub async fn download_images(
             .expect("Image node does not exist");
         let mut img_node = img_ref.attributes.borrow_mut();
         *img_node.get_mut("src").unwrap() = img_path.clone();
+        img_node.insert(ORIGINAL_SRC_ATTR, img_url.to_string());
         // srcset is removed because readers such as Foliate then fail to display
         // the image already downloaded and stored in src
         img_node.remove("srcset");
@@ -263,12 +1143,22 @@ pub async fn download_images(
         .await;
     let mut errors = Vec::new();
     let mut replaced_imgs = Vec::new();
+    let mut small_imgs = Vec::new();
     for img_req_result in imgs_req_iter {
         match img_req_result {
+            Ok((img_url, img_path, _))
+                if size_filter.is_too_small(&std::env::temp_dir().join(&img_path)) =>
+            {
+                debug!("Removing the image {} as it is too small", img_url);
+                small_imgs.push(img_url.to_owned());
+            }
             Ok(img_req) => replaced_imgs.push(replace_existing_img_src(img_req)),
             Err(e) => errors.push(e),
         }
     }
+    for img_url in small_imgs {
+        extractor.remove_image(&img_url);
+    }
     extractor.img_urls = replaced_imgs;
     if errors.is_empty() {
         Ok(())
@@ -277,6 +1167,89 @@ pub async fn download_images(
     }
 }
 
+/// The Authorization header value sent to a host
+#[derive(Clone, Debug, PartialEq)]
+pub enum Authorization {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+impl Authorization {
+    fn header_value(&self) -> String {
+        match self {
+            Authorization::Basic { username, password } => {
+                format!(
+                    "Basic {}",
+                    base64::encode(format!("{}:{}", username, password))
+                )
+            }
+            Authorization::Bearer(token) => format!("Bearer {}", token),
+        }
+    }
+}
+
+/// Credentials that are used for requests to a domain and its subdomains
+#[derive(Clone, Debug, PartialEq)]
+pub struct HostCredentials {
+    domain: String,
+    authorization: Authorization,
+}
+
+impl HostCredentials {
+    pub fn new(domain: &str, authorization: Authorization) -> Self {
+        Self {
+            domain: domain.to_owned(),
+            authorization,
+        }
+    }
+
+    fn matches_host(&self, host: &str) -> bool {
+        host == self.domain || host.ends_with(&format!(".{}", self.domain))
+    }
+}
+
+/// Middleware that adds an Authorization header to requests made to hosts with credentials.
+/// It runs for every request, including redirects, so credentials are never sent to other hosts.
+#[derive(Clone, Debug)]
+struct AuthMiddleware {
+    credentials: std::sync::Arc<Vec<HostCredentials>>,
+}
+
+impl AuthMiddleware {
+    fn new(credentials: Vec<HostCredentials>) -> Self {
+        Self {
+            credentials: std::sync::Arc::new(credentials),
+        }
+    }
+
+    fn authorization_for(&self, url: &Url) -> Option<&Authorization> {
+        let host = url.host_str()?.to_lowercase();
+        self.credentials
+            .iter()
+            .find(|credentials| credentials.matches_host(&host))
+            .map(|credentials| &credentials.authorization)
+    }
+}
+
+#[surf::utils::async_trait]
+impl surf::middleware::Middleware for AuthMiddleware {
+    async fn handle(
+        &self,
+        mut req: surf::Request,
+        client: surf::Client,
+        next: surf::middleware::Next<'_>,
+    ) -> surf::Result<surf::Response> {
+        if let Some(authorization) = self.authorization_for(req.url()) {
+            debug!("Adding credentials for {}", req.url());
+            req.insert_header(
+                surf::http::headers::AUTHORIZATION,
+                authorization.header_value(),
+            );
+        }
+        next.run(req, client).await
+    }
+}
+
 pub struct PartialDownload {
     pub link: String,
     pub title: String,
@@ -291,6 +1264,21 @@ impl PartialDownload {
     }
 }
 
+/// An article link that was redirected to a different url
+pub struct RedirectedDownload {
+    pub link: String,
+    pub final_link: String,
+}
+
+impl RedirectedDownload {
+    pub fn new(link: &str, final_link: &str) -> Self {
+        Self {
+            link: link.into(),
+            final_link: final_link.into(),
+        }
+    }
+}
+
 /// Handles getting the extension from a given MIME subtype.
 fn map_mime_subtype_to_ext(subtype: &str) -> &str {
     if subtype == ("svg+xml") {
@@ -302,6 +1290,54 @@ fn map_mime_subtype_to_ext(subtype: &str) -> &str {
     }
 }
 
+/// Decodes the bytes of an HTML response into a UTF-8 string. The charset is read from the
+/// Content-Type header and then from the document's `<meta>` tags, falling back to UTF-8.
+/// A byte order mark, if present, takes precedence over both.
+fn decode_html_bytes(bytes: &[u8], content_type: Option<&str>) -> String {
+    let encoding = content_type
+        .and_then(get_charset_label)
+        .or_else(|| sniff_meta_charset(bytes))
+        .and_then(|label| Encoding::for_label(label.trim().as_bytes()))
+        .unwrap_or(UTF_8);
+    if encoding != UTF_8 {
+        debug!("Decoding HTML from {}", encoding.name());
+    }
+    let (html, actual_encoding, had_errors) = encoding.decode(bytes);
+    if had_errors {
+        warn!(
+            "Malformed {} sequences were replaced while decoding HTML",
+            actual_encoding.name()
+        );
+    }
+    html.into_owned()
+}
+
+/// Returns the value of the charset parameter in a Content-Type string
+fn get_charset_label(content_type: &str) -> Option<String> {
+    lazy_static! {
+        static ref CHARSET_REGEX: regex::Regex =
+            regex::Regex::new(r#"(?i)charset\s*=\s*["']?([\w\-:.]+)"#).unwrap();
+    }
+    CHARSET_REGEX
+        .captures(content_type)
+        .and_then(|captures| captures.get(1))
+        .map(|label| label.as_str().to_owned())
+}
+
+/// Looks for a `<meta charset>` or `<meta http-equiv="Content-Type">` declaration at the
+/// start of the document. Only the first few kilobytes are checked as the declaration must
+/// appear early in the `<head>`.
+fn sniff_meta_charset(bytes: &[u8]) -> Option<String> {
+    const SNIFF_LIMIT: usize = 4096;
+    lazy_static! {
+        static ref META_REGEX: regex::Regex = regex::Regex::new(r#"(?i)<meta\s[^>]*>"#).unwrap();
+    }
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(SNIFF_LIMIT)]);
+    META_REGEX
+        .find_iter(&head)
+        .find_map(|meta_tag| get_charset_label(meta_tag.as_str()))
+}
+
 /// Utility for hashing URLs. This is used to help store files locally with unique values
 fn hash_url(url: &str) -> String {
     format!("{:x}", md5::compute(url.as_bytes()))
@@ -328,6 +1364,21 @@ fn get_absolute_url(url: &str, request_url: &Url) -> String {
 #[cfg(test)]
 mod test {
     use super::*;
+    #[test]
+    fn test_audio_file_name() {
+        let url = "https://cdn.example.com/shows/episode_12.mp3?token=abc";
+        assert_eq!(
+            format!("{}-episode_12.mp3", &hash_url(url)[..8]),
+            audio_file_name(url)
+        );
+        let url = "https://cdn.example.com/shows/ep%2012.mp3";
+        assert_eq!(
+            format!("{}-ep_2012.mp3", &hash_url(url)[..8]),
+            audio_file_name(url)
+        );
+        assert!(audio_file_name("https://example.com/").ends_with("-audio"));
+    }
+
     #[test]
     fn test_map_mime_type_to_ext() {
         let mime_subtypes = vec![
@@ -342,4 +1393,91 @@ mod test {
             exts
         );
     }
+
+    #[test]
+    fn test_get_charset_label() {
+        assert_eq!(
+            Some("windows-1251".to_owned()),
+            get_charset_label("text/html; charset=windows-1251")
+        );
+        assert_eq!(
+            Some("Shift_JIS".to_owned()),
+            get_charset_label("text/html;Charset=\"Shift_JIS\"")
+        );
+        assert_eq!(None, get_charset_label("text/html"));
+    }
+
+    #[test]
+    fn test_decode_html_bytes() {
+        let html = "<html><head><meta charset=\"windows-1251\"></head><body>Привет</body></html>";
+        let (win_bytes, _, _) = encoding_rs::WINDOWS_1251.encode(html);
+
+        // The meta tag is used when the header has no charset
+        assert_eq!(html, decode_html_bytes(&win_bytes, Some("text/html")));
+        assert_eq!(html, decode_html_bytes(&win_bytes, None));
+
+        // The header takes precedence over the meta tag
+        let (sjis_bytes, _, _) = encoding_rs::SHIFT_JIS.encode("<p>日本語</p>");
+        assert_eq!(
+            "<p>日本語</p>",
+            decode_html_bytes(&sjis_bytes, Some("text/html; charset=Shift_JIS"))
+        );
+
+        let html =
+            r#"<meta http-equiv="Content-Type" content="text/html; charset=gbk"><p>中文</p>"#;
+        let (gbk_bytes, _, _) = encoding_rs::GBK.encode(html);
+        assert_eq!(html, decode_html_bytes(&gbk_bytes, None));
+
+        // UTF-8 is the default
+        assert_eq!(
+            "<p>café</p>",
+            decode_html_bytes("<p>café</p>".as_bytes(), None)
+        );
+    }
+
+    #[test]
+    fn test_auth_middleware() {
+        let middleware = AuthMiddleware::new(vec![
+            HostCredentials::new(
+                "wiki.example.org",
+                Authorization::Basic {
+                    username: "user".into(),
+                    password: "pass".into(),
+                },
+            ),
+            HostCredentials::new("example.com", Authorization::Bearer("abc123".into())),
+        ]);
+        let authorization_for = |url: &str| middleware.authorization_for(&Url::parse(url).unwrap());
+
+        assert_eq!(
+            Some("Basic dXNlcjpwYXNz".to_owned()),
+            authorization_for("https://wiki.example.org/page").map(Authorization::header_value)
+        );
+        assert_eq!(
+            Some("Bearer abc123".to_owned()),
+            authorization_for("https://api.example.com/v1").map(Authorization::header_value)
+        );
+        assert_eq!(
+            Some("Bearer abc123".to_owned()),
+            authorization_for("https://EXAMPLE.com/").map(Authorization::header_value)
+        );
+        assert_eq!(None, authorization_for("https://example.org/"));
+        assert_eq!(None, authorization_for("https://notexample.com/"));
+    }
+
+    #[test]
+    fn test_is_insecure_host() {
+        let insecure_hosts = vec!["intranet.example.com".to_owned()];
+        assert!(is_insecure_host(&insecure_hosts, "intranet.example.com"));
+        assert!(is_insecure_host(
+            &insecure_hosts,
+            "wiki.intranet.example.com"
+        ));
+        assert!(!is_insecure_host(&insecure_hosts, "example.com"));
+        assert!(!is_insecure_host(
+            &insecure_hosts,
+            "notintranet.example.com"
+        ));
+        assert!(!is_insecure_host(&[], "intranet.example.com"));
+    }
 }
@@ -0,0 +1,599 @@
+use std::str::FromStr;
+
+use chrono::Datelike;
+
+/// The locales the messages and exports of paperoni are translated to
+pub const SUPPORTED_LOCALES: [&str; 4] = ["en", "de", "es", "fr"];
+
+/// The language of the summary and progress messages and of the text paperoni adds to exports
+/// such as the appendix, along with how dates are written in them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Locale {
+    En,
+    De,
+    Es,
+    Fr,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale::En
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    /// Parses a language code such as `fr`, `pt-BR` or `de_DE.UTF-8` by its primary language
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let primary_lang = s
+            .trim()
+            .split(|c| c == '-' || c == '_' || c == '.')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        match primary_lang.as_str() {
+            "en" => Ok(Locale::En),
+            "de" => Ok(Locale::De),
+            "es" => Ok(Locale::Es),
+            "fr" => Ok(Locale::Fr),
+            _ => Err(format!(
+                "the supported locales are {}",
+                SUPPORTED_LOCALES.join(", ")
+            )),
+        }
+    }
+}
+
+/// A message of the command line or a heading of the exports
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Message {
+    Appendix,
+    ArticleSources,
+    /// Written before the url an article was requested from when it was redirected
+    RedirectedFrom,
+    TableOfContents,
+    DownloadedArticles,
+    PartiallyFailedDownloads,
+    RedirectedLinks,
+    DuplicateArticles,
+    FailedDownloads,
+    Link,
+    Title,
+    FinalLink,
+    DuplicateOf,
+    Reason,
+    Extracting,
+    DownloadingImages,
+    /// Has a `{format}` placeholder for the export format
+    GeneratingFiles,
+    /// Has a `{format}` placeholder for the export format
+    GeneratedFiles,
+    /// Has a `{format}` placeholder for the export format
+    GenerationFailed,
+    /// Has a `{count}` placeholder for the number of index pages
+    CrawlingPages,
+    /// Has a `{count}` placeholder for the number of archives
+    CollectingArchiveLinks,
+    /// Has a `{count}` placeholder for the number of links found
+    FoundArticleLinks,
+    /// Has a `{url}` placeholder for the first part of the series
+    FollowingSeries,
+    /// Has a `{count}` placeholder for the number of parts found
+    FoundSeriesParts,
+    /// Has a `{count}` placeholder for the number of articles exported in earlier runs
+    SkippingExported,
+    /// Has `{duration}` and `{count}` placeholders for the estimate and the number of
+    /// downloads it is based on
+    EstimatedDuration,
+    /// Has a `{dir}` placeholder for the output directory
+    DownloadingArticle,
+    /// Has a `{dir}` placeholder for the output directory
+    DownloadingArticles,
+    /// Has `{count}` and `{reasons}` placeholders for the number of skipped articles and
+    /// the reasons they were skipped
+    SkippedArticles,
+    /// A reason of `SkippedArticles`
+    OutsideDateRange,
+    /// A reason of `SkippedArticles`
+    BelowMinConfidence,
+    /// Joins the reasons of `SkippedArticles`
+    Or,
+    /// Has a `{count}` placeholder for the number of articles
+    LowConfidenceArticles,
+    /// Has `{count}` and `{service}` placeholders for the number of articles and the
+    /// read-later service
+    PushedArticles,
+    /// Has `{count}` and `{total}` placeholders for the number of files processed
+    /// successfully and the number of exported files
+    PostProcessedFiles,
+    /// Has `{count}` and `{path}` placeholders for the number of exports and the feed file
+    FeedWritten,
+    /// Has a `{path}` placeholder for the report file
+    ReportWritten,
+    /// Has a `{path}` placeholder for the log file
+    LogWritten,
+    LogToFileHint,
+}
+
+/// How an article download ended, as counted in the summary
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Downloaded,
+    PartiallyFailed,
+    Failed,
+}
+
+/// The words of a locale used to count articles in the summary
+struct ArticleWords {
+    /// Starts the summary of a single article
+    article: &'static str,
+    /// Starts the summary when every article had the same outcome
+    all_articles: &'static str,
+    one_article: &'static str,
+    many_articles: &'static str,
+}
+
+impl Locale {
+    pub fn text(self, message: Message) -> &'static str {
+        use Message::*;
+        match self {
+            Locale::En => match message {
+                Appendix => "Appendix",
+                ArticleSources => "Article sources",
+                RedirectedFrom => "redirected from",
+                TableOfContents => "Table of Contents",
+                DownloadedArticles => "Downloaded articles",
+                PartiallyFailedDownloads => "Partially failed downloads",
+                RedirectedLinks => "Redirected links",
+                DuplicateArticles => "Duplicate articles left out of the merged export",
+                FailedDownloads => "Failed article downloads",
+                Link => "Link",
+                Title => "Title",
+                FinalLink => "Final link",
+                DuplicateOf => "Duplicate of",
+                Reason => "Reason",
+                Extracting => "Extracting...",
+                DownloadingImages => "Downloading images",
+                GeneratingFiles => "Generating {format} files",
+                GeneratedFiles => "Generated {format} files",
+                GenerationFailed => "{format} generation failed",
+                CrawlingPages => "Crawling {count} page(s) for article links",
+                CollectingArchiveLinks => "Collecting article links from {count} archive(s)",
+                FoundArticleLinks => "Found {count} article links",
+                FollowingSeries => "Following the series of {url}",
+                FoundSeriesParts => "Found {count} part(s) of the series",
+                SkippingExported => "Skipping {count} article(s) exported in earlier runs",
+                EstimatedDuration => {
+                    "Estimated to take about {duration} from the timings of {count} earlier download(s)"
+                }
+                DownloadingArticle => "Downloading article to {dir}",
+                DownloadingArticles => "Downloading articles to {dir}",
+                SkippedArticles => "Skipped {count} article(s) {reasons}",
+                OutsideDateRange => "published outside of the date range",
+                BelowMinConfidence => "extracted with a confidence below --min-confidence",
+                Or => "or",
+                LowConfidenceArticles => {
+                    "{count} article(s) were extracted with a low confidence and may be missing content:"
+                }
+                PushedArticles => "Pushed {count} article(s) to {service}",
+                PostProcessedFiles => "Post-processed {count} of {total} exported file(s)",
+                FeedWritten => "Feed of {count} export(s) written to {path}",
+                ReportWritten => "Report written to {path}",
+                LogWritten => "Log written to {path}",
+                LogToFileHint => "Run paperoni with the --log-to-file flag to create a log file",
+            },
+            Locale::De => match message {
+                Appendix => "Anhang",
+                ArticleSources => "Quellen der Artikel",
+                RedirectedFrom => "weitergeleitet von",
+                TableOfContents => "Inhaltsverzeichnis",
+                DownloadedArticles => "Heruntergeladene Artikel",
+                PartiallyFailedDownloads => "Teilweise fehlgeschlagene Downloads",
+                RedirectedLinks => "Weitergeleitete Links",
+                DuplicateArticles => "Doppelte Artikel, die nicht zusammengeführt wurden",
+                FailedDownloads => "Fehlgeschlagene Downloads",
+                Link => "Link",
+                Title => "Titel",
+                FinalLink => "Endgültiger Link",
+                DuplicateOf => "Duplikat von",
+                Reason => "Grund",
+                Extracting => "Extrahiere...",
+                DownloadingImages => "Lade Bilder herunter",
+                GeneratingFiles => "Erzeuge {format}-Dateien",
+                GeneratedFiles => "{format}-Dateien erzeugt",
+                GenerationFailed => "Erzeugen der {format}-Dateien fehlgeschlagen",
+                CrawlingPages => "Durchsuche {count} Seite(n) nach Artikel-Links",
+                CollectingArchiveLinks => "Sammle Artikel-Links aus {count} Archiv(en)",
+                FoundArticleLinks => "{count} Artikel-Links gefunden",
+                FollowingSeries => "Folge der Serie von {url}",
+                FoundSeriesParts => "{count} Teil(e) der Serie gefunden",
+                SkippingExported => {
+                    "Überspringe {count} Artikel, die in früheren Läufen exportiert wurden"
+                }
+                EstimatedDuration => {
+                    "Dauert laut den Zeiten von {count} früheren Downloads etwa {duration}"
+                }
+                DownloadingArticle => "Lade Artikel nach {dir} herunter",
+                DownloadingArticles => "Lade Artikel nach {dir} herunter",
+                SkippedArticles => "{count} Artikel übersprungen, da {reasons}",
+                OutsideDateRange => "außerhalb des Datumsbereichs veröffentlicht",
+                BelowMinConfidence => "mit einer Konfidenz unter --min-confidence extrahiert",
+                Or => "oder",
+                LowConfidenceArticles => {
+                    "{count} Artikel wurden mit geringer Konfidenz extrahiert und könnten unvollständig sein:"
+                }
+                PushedArticles => "{count} Artikel an {service} gesendet",
+                PostProcessedFiles => "{count} von {total} exportierten Dateien nachbearbeitet",
+                FeedWritten => "Feed mit {count} Export(en) nach {path} geschrieben",
+                ReportWritten => "Bericht nach {path} geschrieben",
+                LogWritten => "Protokoll nach {path} geschrieben",
+                LogToFileHint => {
+                    "Starte paperoni mit der Option --log-to-file, um eine Protokolldatei zu erstellen"
+                }
+            },
+            Locale::Es => match message {
+                Appendix => "Apéndice",
+                ArticleSources => "Fuentes de los artículos",
+                RedirectedFrom => "redirigido desde",
+                TableOfContents => "Índice",
+                DownloadedArticles => "Artículos descargados",
+                PartiallyFailedDownloads => "Descargas parcialmente fallidas",
+                RedirectedLinks => "Enlaces redirigidos",
+                DuplicateArticles => "Artículos duplicados excluidos de la exportación combinada",
+                FailedDownloads => "Descargas fallidas",
+                Link => "Enlace",
+                Title => "Título",
+                FinalLink => "Enlace final",
+                DuplicateOf => "Duplicado de",
+                Reason => "Motivo",
+                Extracting => "Extrayendo...",
+                DownloadingImages => "Descargando imágenes",
+                GeneratingFiles => "Generando archivos {format}",
+                GeneratedFiles => "Archivos {format} generados",
+                GenerationFailed => "Falló la generación de {format}",
+                CrawlingPages => "Rastreando {count} página(s) en busca de enlaces a artículos",
+                CollectingArchiveLinks => "Recopilando enlaces a artículos de {count} archivo(s)",
+                FoundArticleLinks => "Se encontraron {count} enlaces a artículos",
+                FollowingSeries => "Siguiendo la serie de {url}",
+                FoundSeriesParts => "Se encontraron {count} parte(s) de la serie",
+                SkippingExported => {
+                    "Omitiendo {count} artículo(s) exportados en ejecuciones anteriores"
+                }
+                EstimatedDuration => {
+                    "Se estima que tardará unos {duration} según los tiempos de {count} descarga(s) anteriores"
+                }
+                DownloadingArticle => "Descargando el artículo en {dir}",
+                DownloadingArticles => "Descargando los artículos en {dir}",
+                SkippedArticles => "Se omitieron {count} artículo(s) {reasons}",
+                OutsideDateRange => "publicados fuera del rango de fechas",
+                BelowMinConfidence => "extraídos con una confianza inferior a --min-confidence",
+                Or => "o",
+                LowConfidenceArticles => {
+                    "{count} artículo(s) se extrajeron con poca confianza y puede faltarles contenido:"
+                }
+                PushedArticles => "Se enviaron {count} artículo(s) a {service}",
+                PostProcessedFiles => "Se procesaron {count} de {total} archivo(s) exportados",
+                FeedWritten => "Feed de {count} exportación(es) escrito en {path}",
+                ReportWritten => "Informe escrito en {path}",
+                LogWritten => "Registro escrito en {path}",
+                LogToFileHint => {
+                    "Ejecute paperoni con la opción --log-to-file para crear un archivo de registro"
+                }
+            },
+            Locale::Fr => match message {
+                Appendix => "Annexe",
+                ArticleSources => "Sources des articles",
+                RedirectedFrom => "redirigé depuis",
+                TableOfContents => "Table des matières",
+                DownloadedArticles => "Articles téléchargés",
+                PartiallyFailedDownloads => "Téléchargements partiellement échoués",
+                RedirectedLinks => "Liens redirigés",
+                DuplicateArticles => "Articles en double exclus de l'export fusionné",
+                FailedDownloads => "Téléchargements échoués",
+                Link => "Lien",
+                Title => "Titre",
+                FinalLink => "Lien final",
+                DuplicateOf => "Doublon de",
+                Reason => "Raison",
+                Extracting => "Extraction...",
+                DownloadingImages => "Téléchargement des images",
+                GeneratingFiles => "Génération des fichiers {format}",
+                GeneratedFiles => "Fichiers {format} générés",
+                GenerationFailed => "Échec de la génération {format}",
+                CrawlingPages => {
+                    "Exploration de {count} page(s) à la recherche de liens d'articles"
+                }
+                CollectingArchiveLinks => "Collecte des liens d'articles de {count} archive(s)",
+                FoundArticleLinks => "{count} liens d'articles trouvés",
+                FollowingSeries => "Suivi de la série de {url}",
+                FoundSeriesParts => "{count} partie(s) de la série trouvée(s)",
+                SkippingExported => {
+                    "{count} article(s) exporté(s) lors d'exécutions précédentes ignoré(s)"
+                }
+                EstimatedDuration => {
+                    "Durée estimée à environ {duration} d'après les temps de {count} téléchargement(s) précédent(s)"
+                }
+                DownloadingArticle => "Téléchargement de l'article dans {dir}",
+                DownloadingArticles => "Téléchargement des articles dans {dir}",
+                SkippedArticles => "{count} article(s) ignoré(s), {reasons}",
+                OutsideDateRange => "publiés en dehors de la plage de dates",
+                BelowMinConfidence => "extraits avec une confiance inférieure à --min-confidence",
+                Or => "ou",
+                LowConfidenceArticles => {
+                    "{count} article(s) extrait(s) avec une faible confiance, du contenu peut manquer :"
+                }
+                PushedArticles => "{count} article(s) envoyé(s) à {service}",
+                PostProcessedFiles => "{count} fichier(s) exporté(s) sur {total} post-traité(s)",
+                FeedWritten => "Flux de {count} export(s) écrit dans {path}",
+                ReportWritten => "Rapport écrit dans {path}",
+                LogWritten => "Journal écrit dans {path}",
+                LogToFileHint => {
+                    "Lancez paperoni avec l'option --log-to-file pour créer un fichier journal"
+                }
+            },
+        }
+    }
+
+    /// The number of articles such as `3 articles`
+    pub fn article_count(self, count: usize) -> String {
+        let words = self.article_words();
+        let noun = if count == 1 {
+            words.one_article
+        } else {
+            words.many_articles
+        };
+        format!("{} {}", count, noun)
+    }
+
+    /// Summarizes how many of the articles had the outcome, such as `All articles failed to
+    /// download` or `2 articles downloaded successfully`
+    pub fn summary(self, outcome: Outcome, count: usize, total: usize) -> String {
+        let words = self.article_words();
+        let (singular, plural) = self.outcome_phrases(outcome);
+        if count == 1 && count == total {
+            format!("{} {}", words.article, singular)
+        } else if count == total {
+            format!("{} {}", words.all_articles, plural)
+        } else {
+            format!(
+                "{} {}",
+                self.article_count(count),
+                if count == 1 { singular } else { plural }
+            )
+        }
+    }
+
+    fn article_words(self) -> ArticleWords {
+        let (article, all_articles, one_article, many_articles) = match self {
+            Locale::En => ("Article", "All articles", "article", "articles"),
+            Locale::De => ("Artikel", "Alle Artikel", "Artikel", "Artikel"),
+            Locale::Es => ("Artículo", "Todos los artículos", "artículo", "artículos"),
+            Locale::Fr => ("Article", "Tous les articles", "article", "articles"),
+        };
+        ArticleWords {
+            article,
+            all_articles,
+            one_article,
+            many_articles,
+        }
+    }
+
+    /// The phrases of an outcome following a singular and a plural subject
+    fn outcome_phrases(self, outcome: Outcome) -> (&'static str, &'static str) {
+        match (self, outcome) {
+            (Locale::En, Outcome::Downloaded) => {
+                ("downloaded successfully", "downloaded successfully")
+            }
+            (Locale::En, Outcome::PartiallyFailed) => (
+                "partially failed to download",
+                "partially failed to download",
+            ),
+            (Locale::En, Outcome::Failed) => ("failed to download", "failed to download"),
+            (Locale::De, Outcome::Downloaded) => {
+                ("erfolgreich heruntergeladen", "erfolgreich heruntergeladen")
+            }
+            (Locale::De, Outcome::PartiallyFailed) => {
+                ("teilweise heruntergeladen", "teilweise heruntergeladen")
+            }
+            (Locale::De, Outcome::Failed) => ("nicht heruntergeladen", "nicht heruntergeladen"),
+            (Locale::Es, Outcome::Downloaded) => {
+                ("descargado correctamente", "descargados correctamente")
+            }
+            (Locale::Es, Outcome::PartiallyFailed) => {
+                ("descargado parcialmente", "descargados parcialmente")
+            }
+            (Locale::Es, Outcome::Failed) => ("no descargado", "no descargados"),
+            (Locale::Fr, Outcome::Downloaded) => {
+                ("téléchargé avec succès", "téléchargés avec succès")
+            }
+            (Locale::Fr, Outcome::PartiallyFailed) => {
+                ("partiellement téléchargé", "partiellement téléchargés")
+            }
+            (Locale::Fr, Outcome::Failed) => ("non téléchargé", "non téléchargés"),
+        }
+    }
+
+    /// Writes a date the way it is written in the locale, such as `1 June 2021` or
+    /// `1. Juni 2021`
+    pub fn format_date(self, date: &impl Datelike) -> String {
+        let month_idx = date.month0() as usize;
+        match self {
+            Locale::En => format!("{} {} {}", date.day(), EN_MONTHS[month_idx], date.year()),
+            Locale::De => format!("{}. {} {}", date.day(), DE_MONTHS[month_idx], date.year()),
+            Locale::Es => format!(
+                "{} de {} de {}",
+                date.day(),
+                ES_MONTHS[month_idx],
+                date.year()
+            ),
+            Locale::Fr => {
+                // The first day of the month is an ordinal in French
+                let day = if date.day() == 1 {
+                    "1er".to_owned()
+                } else {
+                    date.day().to_string()
+                };
+                format!("{} {} {}", day, FR_MONTHS[month_idx], date.year())
+            }
+        }
+    }
+}
+
+const EN_MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+const DE_MONTHS: [&str; 12] = [
+    "Januar",
+    "Februar",
+    "März",
+    "April",
+    "Mai",
+    "Juni",
+    "Juli",
+    "August",
+    "September",
+    "Oktober",
+    "November",
+    "Dezember",
+];
+const ES_MONTHS: [&str; 12] = [
+    "enero",
+    "febrero",
+    "marzo",
+    "abril",
+    "mayo",
+    "junio",
+    "julio",
+    "agosto",
+    "septiembre",
+    "octubre",
+    "noviembre",
+    "diciembre",
+];
+const FR_MONTHS: [&str; 12] = [
+    "janvier",
+    "février",
+    "mars",
+    "avril",
+    "mai",
+    "juin",
+    "juillet",
+    "août",
+    "septembre",
+    "octobre",
+    "novembre",
+    "décembre",
+];
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_locale() {
+        assert_eq!(Ok(Locale::Fr), "fr".parse());
+        assert_eq!(Ok(Locale::De), "de_DE.UTF-8".parse());
+        assert_eq!(Ok(Locale::Es), "ES-mx".parse());
+        assert_eq!(
+            Err("the supported locales are en, de, es, fr".to_owned()),
+            "pt-BR".parse::<Locale>()
+        );
+    }
+
+    #[test]
+    fn test_format_date() {
+        let date = NaiveDate::from_ymd(2021, 6, 1);
+        assert_eq!("1 June 2021", Locale::En.format_date(&date));
+        assert_eq!("1. Juni 2021", Locale::De.format_date(&date));
+        assert_eq!("1 de junio de 2021", Locale::Es.format_date(&date));
+        assert_eq!("1er juin 2021", Locale::Fr.format_date(&date));
+        assert_eq!(
+            "17 octobre 2026",
+            Locale::Fr.format_date(&NaiveDate::from_ymd(2026, 10, 17))
+        );
+    }
+
+    #[test]
+    fn test_translated_placeholders() {
+        use Message::*;
+        let messages = [
+            GeneratingFiles,
+            GeneratedFiles,
+            GenerationFailed,
+            CrawlingPages,
+            CollectingArchiveLinks,
+            FoundArticleLinks,
+            FollowingSeries,
+            FoundSeriesParts,
+            SkippingExported,
+            EstimatedDuration,
+            DownloadingArticle,
+            DownloadingArticles,
+            SkippedArticles,
+            LowConfidenceArticles,
+            PushedArticles,
+            PostProcessedFiles,
+            FeedWritten,
+            ReportWritten,
+            LogWritten,
+        ];
+        let placeholders = |text: &str| {
+            let mut names = text
+                .split('{')
+                .skip(1)
+                .filter_map(|part| part.split('}').next())
+                .map(str::to_owned)
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        for message in messages.iter().copied() {
+            let expected = placeholders(Locale::En.text(message));
+            assert!(!expected.is_empty(), "{:?}", message);
+            for locale in [Locale::De, Locale::Es, Locale::Fr].iter().copied() {
+                assert_eq!(
+                    expected,
+                    placeholders(locale.text(message)),
+                    "{:?} in {:?}",
+                    message,
+                    locale
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(
+            "Article downloaded successfully",
+            Locale::En.summary(Outcome::Downloaded, 1, 1)
+        );
+        assert_eq!(
+            "Tous les articles téléchargés avec succès",
+            Locale::Fr.summary(Outcome::Downloaded, 4, 4)
+        );
+        assert_eq!(
+            "1 artículo no descargado",
+            Locale::Es.summary(Outcome::Failed, 1, 3)
+        );
+        assert_eq!(
+            "2 Artikel teilweise heruntergeladen",
+            Locale::De.summary(Outcome::PartiallyFailed, 2, 3)
+        );
+        assert_eq!("1 article", Locale::En.article_count(1));
+    }
+}
@@ -0,0 +1,243 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use directories::UserDirs;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+/// The default limit on the size of the image cache in bytes
+pub const DEFAULT_IMAGE_CACHE_SIZE: u64 = 512 * 1024 * 1024;
+
+/// A downloaded image kept in the cache with the details needed to reuse it
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct CacheEntry {
+    url: String,
+    mime: Option<String>,
+    /// Name of the file of the image in the cache directory
+    file_name: String,
+    size: u64,
+    /// Seconds since the epoch when the image was last used, which decides the images
+    /// removed first when the cache is over its size limit
+    last_used: u64,
+}
+
+/// Images downloaded in previous runs, keyed by a hash of their url. Each image is stored as
+/// soon as it is downloaded so an interrupted run still fills the cache.
+#[derive(Clone, Debug)]
+pub struct ImageCache {
+    dir: PathBuf,
+    /// The size in bytes the cache is pruned to at the end of a run
+    max_size: u64,
+}
+
+impl ImageCache {
+    pub fn new(dir: PathBuf, max_size: u64) -> Self {
+        Self { dir, max_size }
+    }
+
+    /// The cache in `~/.paperoni/cache/images` with a size limit in bytes
+    pub fn with_default_dir(max_size: u64) -> Option<Self> {
+        UserDirs::new().map(|user_dirs| {
+            let dir = user_dirs
+                .home_dir()
+                .join(".paperoni")
+                .join("cache")
+                .join("images");
+            Self::new(dir, max_size)
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Copies the cached image with the key to the path without its extension and returns
+    /// the path with the extension of the image and its MIME type
+    pub fn restore(&self, key: &str, img_path: &Path) -> Option<(PathBuf, Option<String>)> {
+        let mut entry = self.read_entry(key)?;
+        let cached_path = self.dir.join(&entry.file_name);
+        let extension = Path::new(&entry.file_name).extension()?;
+        let img_path = img_path.with_extension(extension);
+        if let Err(e) = fs::copy(&cached_path, &img_path) {
+            debug!("Unable to restore {} from the cache: {}", entry.url, e);
+            return None;
+        }
+        debug!("Restored {} from the image cache", entry.url);
+        entry.last_used = now_secs();
+        if let Err(e) = self.write_entry(key, &entry) {
+            debug!(
+                "Unable to update the image cache entry of {}: {}",
+                entry.url, e
+            );
+        }
+        Some((img_path, entry.mime))
+    }
+
+    /// Copies a downloaded image into the cache
+    pub fn store(
+        &self,
+        key: &str,
+        url: &str,
+        img_path: &Path,
+        mime: Option<&str>,
+    ) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let extension = img_path
+            .extension()
+            .map(|extension| extension.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let file_name = format!("{}.{}", key, extension);
+        let size = fs::copy(img_path, self.dir.join(&file_name))?;
+        self.write_entry(
+            key,
+            &CacheEntry {
+                url: url.to_owned(),
+                mime: mime.map(ToOwned::to_owned),
+                file_name,
+                size,
+                last_used: now_secs(),
+            },
+        )
+    }
+
+    /// Removes the least recently used images until the cache fits in its size limit.
+    /// Returns the number of images removed and the bytes freed.
+    pub fn prune(&self) -> io::Result<(usize, u64)> {
+        let mut entries = self.read_entries()?;
+        let mut total_size = entries.iter().map(|(_, entry)| entry.size).sum::<u64>();
+        entries.sort_by_key(|(_, entry)| entry.last_used);
+        let mut removed = (0, 0);
+        for (key, entry) in entries {
+            if total_size <= self.max_size {
+                break;
+            }
+            self.remove_entry(&key, &entry)?;
+            total_size -= entry.size;
+            removed = (removed.0 + 1, removed.1 + entry.size);
+        }
+        if removed.0 > 0 {
+            info!(
+                "Removed {} images from the image cache to fit in {} bytes",
+                removed.0, self.max_size
+            );
+        }
+        Ok(removed)
+    }
+
+    /// Removes every image from the cache. Returns the number of images removed and the
+    /// bytes freed.
+    pub fn clear(&self) -> io::Result<(usize, u64)> {
+        let entries = self.read_entries()?;
+        let freed = entries.iter().map(|(_, entry)| entry.size).sum();
+        for (key, entry) in &entries {
+            self.remove_entry(key, entry)?;
+        }
+        Ok((entries.len(), freed))
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    fn read_entry(&self, key: &str) -> Option<CacheEntry> {
+        let entry_json = fs::read_to_string(self.entry_path(key)).ok()?;
+        serde_json::from_str(&entry_json).ok()
+    }
+
+    fn write_entry(&self, key: &str, entry: &CacheEntry) -> io::Result<()> {
+        let entry_json = serde_json::to_string(entry)?;
+        fs::write(self.entry_path(key), entry_json)
+    }
+
+    fn read_entries(&self) -> io::Result<Vec<(String, CacheEntry)>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let path = dir_entry?.path();
+            if path
+                .extension()
+                .map_or(true, |extension| extension != "json")
+            {
+                continue;
+            }
+            let key = match path.file_stem() {
+                Some(stem) => stem.to_string_lossy().into_owned(),
+                None => continue,
+            };
+            match self.read_entry(&key) {
+                Some(entry) => entries.push((key, entry)),
+                None => warn!("Ignoring the unreadable image cache entry {:?}", path),
+            }
+        }
+        Ok(entries)
+    }
+
+    fn remove_entry(&self, key: &str, entry: &CacheEntry) -> io::Result<()> {
+        let img_path = self.dir.join(&entry.file_name);
+        if img_path.exists() {
+            fs::remove_file(img_path)?;
+        }
+        fs::remove_file(self.entry_path(key))
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_image_cache() {
+        let temp_dir =
+            std::env::temp_dir().join(format!("paperoni-image-cache-test-{}", std::process::id()));
+        fs::create_dir_all(&temp_dir).unwrap();
+        let cache = ImageCache::new(temp_dir.join("cache"), 10);
+        let downloaded_path = temp_dir.join("downloaded.png");
+        fs::write(&downloaded_path, b"12345678").unwrap();
+
+        // Nothing is restored before the image is stored
+        assert_eq!(None, cache.restore("abc", &temp_dir.join("restored")));
+        cache
+            .store(
+                "abc",
+                "https://example.com/a.png",
+                &downloaded_path,
+                Some("image/png"),
+            )
+            .unwrap();
+        assert_eq!(
+            Some((temp_dir.join("restored.png"), Some("image/png".to_owned()))),
+            cache.restore("abc", &temp_dir.join("restored"))
+        );
+        assert_eq!(
+            b"12345678".to_vec(),
+            fs::read(temp_dir.join("restored.png")).unwrap()
+        );
+
+        // The least recently used image is removed when the cache is over its limit
+        let mut older_entry = cache.read_entry("abc").unwrap();
+        older_entry.last_used -= 10;
+        cache.write_entry("abc", &older_entry).unwrap();
+        cache
+            .store("def", "https://example.com/d.png", &downloaded_path, None)
+            .unwrap();
+        assert_eq!((1, 8), cache.prune().unwrap());
+        assert_eq!(None, cache.read_entry("abc"));
+        assert!(!cache.dir().join("abc.png").exists());
+        assert_eq!((0, 0), cache.prune().unwrap());
+
+        assert_eq!((1, 8), cache.clear().unwrap());
+        assert!(cache.read_entries().unwrap().is_empty());
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
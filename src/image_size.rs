@@ -0,0 +1,182 @@
+use std::fs;
+use std::path::Path;
+
+/// Reads the width and height of a PNG, GIF, JPEG or WebP image from its header
+pub fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // The IHDR chunk always comes first
+        Some((read_u32_be(bytes, 16)?, read_u32_be(bytes, 20)?))
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some((read_u16_le(bytes, 6)? as u32, read_u16_le(bytes, 8)? as u32))
+    } else if bytes.starts_with(b"\xff\xd8") {
+        jpeg_dimensions(bytes)
+    } else if bytes.len() > 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        webp_dimensions(bytes)
+    } else {
+        None
+    }
+}
+
+/// The smallest images kept after downloading. Smaller images such as spacer GIFs and
+/// sharing icons are removed from the articles. Tracking pixels of 1x1 or less are always
+/// removed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImageSizeFilter {
+    /// The smallest file size in bytes
+    pub min_bytes: u64,
+    /// The smallest width or height in pixels. Images are only removed when both their
+    /// width and height are smaller so that thin images such as dividers are kept
+    pub min_dimension: u32,
+}
+
+impl ImageSizeFilter {
+    /// Whether a downloaded image is below the thresholds or is a tracking pixel
+    pub fn is_too_small(&self, img_path: &Path) -> bool {
+        let bytes = match fs::read(img_path) {
+            Ok(bytes) => bytes,
+            Err(_) => return false,
+        };
+        if (bytes.len() as u64) < self.min_bytes {
+            return true;
+        }
+        image_dimensions(&bytes).map_or(false, |(width, height)| {
+            let min_dimension = self.min_dimension.max(2);
+            width < min_dimension && height < min_dimension
+        })
+    }
+}
+
+/// Walks the segments of a JPEG until a start of frame segment holding the dimensions
+fn jpeg_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let mut offset = 2;
+    loop {
+        if *bytes.get(offset)? != 0xff {
+            return None;
+        }
+        let marker = *bytes.get(offset + 1)?;
+        // Markers can be padded with fill bytes
+        if marker == 0xff {
+            offset += 1;
+            continue;
+        }
+        let is_start_of_frame =
+            (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker);
+        if is_start_of_frame {
+            return Some((
+                read_u16_be(bytes, offset + 7)? as u32,
+                read_u16_be(bytes, offset + 5)? as u32,
+            ));
+        }
+        offset += 2 + read_u16_be(bytes, offset + 2)? as usize;
+    }
+}
+
+fn webp_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    match bytes.get(12..16)? {
+        b"VP8 " => Some((
+            (read_u16_le(bytes, 26)? & 0x3fff) as u32,
+            (read_u16_le(bytes, 28)? & 0x3fff) as u32,
+        )),
+        b"VP8L" => {
+            let bits = u32::from_le_bytes([
+                *bytes.get(21)?,
+                *bytes.get(22)?,
+                *bytes.get(23)?,
+                *bytes.get(24)?,
+            ]);
+            Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+        }
+        b"VP8X" => Some((read_u24_le(bytes, 24)? + 1, read_u24_le(bytes, 27)? + 1)),
+        _ => None,
+    }
+}
+
+fn read_u16_be(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([
+        *bytes.get(offset)?,
+        *bytes.get(offset + 1)?,
+    ]))
+}
+
+fn read_u16_le(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes([
+        *bytes.get(offset)?,
+        *bytes.get(offset + 1)?,
+    ]))
+}
+
+fn read_u24_le(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes([
+        *bytes.get(offset)?,
+        *bytes.get(offset + 1)?,
+        *bytes.get(offset + 2)?,
+        0,
+    ]))
+}
+
+fn read_u32_be(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes([
+        *bytes.get(offset)?,
+        *bytes.get(offset + 1)?,
+        *bytes.get(offset + 2)?,
+        *bytes.get(offset + 3)?,
+    ]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_image_dimensions() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        assert_eq!(Some((1, 1)), image_dimensions(&png));
+
+        let gif = b"GIF89a\x01\x00\x01\x00\x80\x00\x00";
+        assert_eq!(Some((1, 1)), image_dimensions(gif));
+
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0x4a, 0x46];
+        jpeg.extend_from_slice(&[0xff, 0xc0, 0x00, 0x11, 0x08, 0x01, 0x2c, 0x02, 0x80]);
+        assert_eq!(Some((640, 300)), image_dimensions(&jpeg));
+
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8X\x0a\0\0\0\0\0\0\0".to_vec();
+        webp.extend_from_slice(&[0x1f, 0x03, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(Some((800, 1)), image_dimensions(&webp));
+
+        assert_eq!(None, image_dimensions(b"<svg></svg>"));
+        assert_eq!(None, image_dimensions(b"\x89PNG\r\n\x1a\n"));
+    }
+
+    #[test]
+    fn test_is_too_small() {
+        let dir =
+            std::env::temp_dir().join(format!("paperoni-image-size-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pixel_path = dir.join("pixel.gif");
+        fs::write(&pixel_path, b"GIF89a\x01\x00\x01\x00\x80\x00\x00").unwrap();
+        let icon_path = dir.join("icon.gif");
+        fs::write(&icon_path, b"GIF89a\x20\x00\x20\x00\x80\x00\x00").unwrap();
+        let divider_path = dir.join("divider.gif");
+        fs::write(&divider_path, b"GIF89a\x58\x02\x02\x00\x80\x00\x00").unwrap();
+
+        let default_filter = ImageSizeFilter::default();
+        assert!(default_filter.is_too_small(&pixel_path));
+        assert!(!default_filter.is_too_small(&icon_path));
+        assert!(!default_filter.is_too_small(&dir.join("missing.gif")));
+
+        let dimension_filter = ImageSizeFilter {
+            min_bytes: 0,
+            min_dimension: 50,
+        };
+        assert!(dimension_filter.is_too_small(&icon_path));
+        assert!(!dimension_filter.is_too_small(&divider_path));
+
+        let bytes_filter = ImageSizeFilter {
+            min_bytes: 2048,
+            min_dimension: 0,
+        };
+        assert!(bytes_filter.is_too_small(&divider_path));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
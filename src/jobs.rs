@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use async_std::channel::{bounded, Receiver, Sender};
+use indicatif::ProgressBar;
+use log::debug;
+use serde_json::{json, Value};
+
+use crate::server::EpubRequest;
+
+/// The most jobs waiting to run before new ones are refused
+const MAX_QUEUED_JOBS: usize = 100;
+/// The most finished jobs kept for their results. The oldest ones are removed first
+const MAX_FINISHED_JOBS: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+impl JobStatus {
+    fn name(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Done => "done",
+            JobStatus::Failed => "failed",
+        }
+    }
+}
+
+/// An EPUB requested from the server that is generated in the background
+struct Job {
+    request: EpubRequest,
+    status: JobStatus,
+    /// Counts the articles downloaded while the job runs
+    bar: ProgressBar,
+    /// The reasons articles failed or the job failed
+    errors: Vec<String>,
+    epub_path: PathBuf,
+    /// The order the job finished in among the jobs of the queue
+    finished_seq: Option<usize>,
+}
+
+/// The next job to run taken from the queue
+pub struct QueuedJob {
+    pub id: String,
+    pub request: EpubRequest,
+    pub bar: ProgressBar,
+    pub epub_path: PathBuf,
+}
+
+/// The jobs submitted to the server. Jobs wait in the queue until one of the runners of the
+/// server takes them, so only as many jobs as there are runners run at the same time.
+pub struct JobQueue {
+    jobs: Mutex<HashMap<String, Job>>,
+    sender: Sender<String>,
+    receiver: Receiver<String>,
+    max_finished: usize,
+    id_counter: AtomicUsize,
+    finished_counter: AtomicUsize,
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self::with_limits(MAX_QUEUED_JOBS, MAX_FINISHED_JOBS)
+    }
+}
+
+impl JobQueue {
+    fn with_limits(max_queued: usize, max_finished: usize) -> Self {
+        let (sender, receiver) = bounded(max_queued);
+        Self {
+            jobs: Mutex::new(HashMap::new()),
+            sender,
+            receiver,
+            max_finished,
+            id_counter: AtomicUsize::new(0),
+            finished_counter: AtomicUsize::new(0),
+        }
+    }
+
+    /// Adds a job to the queue. Returns its id or `None` when the queue is full.
+    pub fn submit(&self, request: EpubRequest) -> Option<String> {
+        let id = self.new_id();
+        let job = Job {
+            request,
+            status: JobStatus::Queued,
+            bar: ProgressBar::hidden(),
+            errors: Vec::new(),
+            epub_path: std::env::temp_dir().join(format!("paperoni-job-{}.epub", id)),
+            finished_seq: None,
+        };
+        // The job is added first so a runner taking it right away finds it
+        self.jobs.lock().unwrap().insert(id.clone(), job);
+        if self.sender.try_send(id.clone()).is_err() {
+            self.jobs.lock().unwrap().remove(&id);
+            return None;
+        }
+        Some(id)
+    }
+
+    /// Waits for the next job in the queue and marks it as running
+    pub async fn next(&self) -> Option<QueuedJob> {
+        let id = self.receiver.recv().await.ok()?;
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(&id)?;
+        job.status = JobStatus::Running;
+        Some(QueuedJob {
+            id,
+            request: job.request.clone(),
+            bar: job.bar.clone(),
+            epub_path: job.epub_path.clone(),
+        })
+    }
+
+    /// Marks a running job as done or failed along with the reasons its articles failed. The
+    /// oldest finished jobs are removed with their files once there are too many of them.
+    pub fn finish(&self, id: &str, is_done: bool, errors: Vec<String>) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.get_mut(id) {
+            job.status = if is_done {
+                JobStatus::Done
+            } else {
+                JobStatus::Failed
+            };
+            job.errors = errors;
+            job.finished_seq = Some(self.finished_counter.fetch_add(1, Ordering::SeqCst));
+            if !is_done {
+                let _ = fs::remove_file(&job.epub_path);
+            }
+        }
+
+        let mut finished = jobs
+            .iter()
+            .filter_map(|(id, job)| Some((job.finished_seq?, id.clone())))
+            .collect::<Vec<_>>();
+        if finished.len() > self.max_finished {
+            finished.sort();
+            for (_, id) in &finished[..finished.len() - self.max_finished] {
+                if let Some(job) = jobs.remove(id) {
+                    debug!("Removing the finished job {}", id);
+                    let _ = fs::remove_file(&job.epub_path);
+                }
+            }
+        }
+    }
+
+    /// The status of a job with the progress of its downloads, or `None` when there is no
+    /// such job
+    pub fn status(&self, id: &str) -> Option<Value> {
+        let jobs = self.jobs.lock().unwrap();
+        let job = jobs.get(id)?;
+        let total = job.request.urls.len();
+        let downloaded = match job.status {
+            JobStatus::Queued => 0,
+            JobStatus::Running => (job.bar.position() as usize).min(total),
+            JobStatus::Done | JobStatus::Failed => total,
+        };
+        Some(json!({
+            "id": id,
+            "status": job.status.name(),
+            "progress": {
+                "downloaded": downloaded,
+                "total": total,
+            },
+            "errors": job.errors,
+        }))
+    }
+
+    /// The path of the EPUB of a job once it is done, or otherwise the status of the job.
+    /// Returns `None` when there is no such job.
+    pub fn result(&self, id: &str) -> Option<Result<PathBuf, JobStatus>> {
+        let jobs = self.jobs.lock().unwrap();
+        let job = jobs.get(id)?;
+        Some(match job.status {
+            JobStatus::Done => Ok(job.epub_path.clone()),
+            status => Err(status),
+        })
+    }
+
+    /// An id that cannot be guessed from the ids of other jobs
+    fn new_id(&self) -> String {
+        let seed = format!(
+            "{}-{}-{:?}",
+            std::process::id(),
+            self.id_counter.fetch_add(1, Ordering::SeqCst),
+            SystemTime::now()
+        );
+        format!("{:x}", md5::compute(seed))[..16].to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use async_std::task;
+
+    use super::*;
+
+    fn request(urls: &[&str]) -> EpubRequest {
+        EpubRequest {
+            urls: urls.iter().map(|url| url.to_string()).collect(),
+            title: None,
+            author: None,
+        }
+    }
+
+    #[test]
+    fn test_job_lifecycle() {
+        let queue = JobQueue::default();
+        let id = queue
+            .submit(request(&["https://example.com/a", "https://example.com/b"]))
+            .unwrap();
+        assert_eq!(16, id.len());
+        assert_eq!("queued", queue.status(&id).unwrap()["status"]);
+        assert_eq!(Some(Err(JobStatus::Queued)), queue.result(&id));
+
+        let job = task::block_on(queue.next()).unwrap();
+        assert_eq!(id, job.id);
+        job.bar.inc(1);
+        let status = queue.status(&id).unwrap();
+        assert_eq!("running", status["status"]);
+        assert_eq!(json!({"downloaded": 1, "total": 2}), status["progress"]);
+
+        queue.finish(
+            &id,
+            true,
+            vec!["https://example.com/b: [HTTPError]: 404".to_owned()],
+        );
+        let status = queue.status(&id).unwrap();
+        assert_eq!("done", status["status"]);
+        assert_eq!(2, status["progress"]["downloaded"]);
+        assert_eq!(1, status["errors"].as_array().unwrap().len());
+        assert_eq!(Some(Ok(job.epub_path)), queue.result(&id));
+        assert_eq!(None, queue.status("unknown"));
+    }
+
+    #[test]
+    fn test_queue_limits() {
+        let queue = JobQueue::with_limits(2, 1);
+        let first_id = queue.submit(request(&["https://example.com/a"])).unwrap();
+        let second_id = queue.submit(request(&["https://example.com/b"])).unwrap();
+        assert_eq!(None, queue.submit(request(&["https://example.com/c"])));
+
+        for id in &[&first_id, &second_id] {
+            let job = task::block_on(queue.next()).unwrap();
+            assert_eq!(**id, job.id);
+            queue.finish(&job.id, false, Vec::new());
+        }
+        // Only the most recently finished job is kept
+        assert_eq!(None, queue.result(&first_id));
+        assert_eq!(Some(Err(JobStatus::Failed)), queue.result(&second_id));
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_std::task;
+use log::debug;
+
+/// The number of concurrent requests to a host the adaptive mode starts with
+pub const ADAPTIVE_INITIAL_CONN: usize = 2;
+/// The most concurrent requests to a host the adaptive mode ramps up to
+pub const ADAPTIVE_MAX_CONN: usize = 32;
+/// How long a request waits before checking again whether its host has a free connection
+const ACQUIRE_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How a request to a host ended which decides whether its limit goes up or down
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    Success,
+    /// The host responded with 429 Too Many Requests or 503 Service Unavailable
+    Throttled,
+    Failed,
+}
+
+#[derive(Debug)]
+struct HostStats {
+    limit: usize,
+    in_flight: usize,
+    /// Successful requests since the limit last changed
+    successes_at_limit: usize,
+    requests: usize,
+    throttled: usize,
+    failed: usize,
+}
+
+impl HostStats {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            in_flight: 0,
+            successes_at_limit: 0,
+            requests: 0,
+            throttled: 0,
+            failed: 0,
+        }
+    }
+
+    /// Raises the limit by one after a full round of successful requests at the current
+    /// limit, halves it when the host throttles requests and lowers it by one when more
+    /// than half of the requests to the host have failed.
+    fn record(&mut self, outcome: Outcome, max_limit: usize) -> bool {
+        let prev_limit = self.limit;
+        self.requests += 1;
+        match outcome {
+            Outcome::Success => {
+                self.successes_at_limit += 1;
+                if self.successes_at_limit >= self.limit {
+                    self.limit = (self.limit + 1).min(max_limit);
+                }
+            }
+            Outcome::Throttled => {
+                self.throttled += 1;
+                self.limit = (self.limit / 2).max(1);
+            }
+            Outcome::Failed => {
+                self.failed += 1;
+                if self.failed * 2 > self.requests {
+                    self.limit = (self.limit - 1).max(1);
+                }
+            }
+        }
+        if self.limit != prev_limit || outcome == Outcome::Throttled {
+            self.successes_at_limit = 0;
+        }
+        self.limit != prev_limit
+    }
+
+    fn rate(&self, count: usize) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            count as f64 * 100.0 / self.requests as f64
+        }
+    }
+}
+
+/// Middleware that limits the concurrent requests made to each host when `--max-conn auto`
+/// is passed. Each host starts with a low limit that is ramped up while its requests succeed
+/// and backed off when it throttles requests or they fail.
+#[derive(Clone)]
+pub struct ConnLimiter {
+    hosts: Arc<Mutex<HashMap<String, HostStats>>>,
+    initial_limit: usize,
+    max_limit: usize,
+}
+
+impl fmt::Debug for ConnLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnLimiter")
+            .field("initial_limit", &self.initial_limit)
+            .field("max_limit", &self.max_limit)
+            .finish()
+    }
+}
+
+impl ConnLimiter {
+    pub fn new(initial_limit: usize, max_limit: usize) -> Self {
+        Self {
+            hosts: Arc::new(Mutex::new(HashMap::new())),
+            initial_limit,
+            max_limit,
+        }
+    }
+
+    /// Tries to take a connection to the host and returns whether it is free
+    fn try_acquire(&self, host: &str) -> bool {
+        let mut hosts = self.hosts.lock().unwrap();
+        let stats = hosts
+            .entry(host.to_owned())
+            .or_insert_with(|| HostStats::new(self.initial_limit));
+        if stats.in_flight < stats.limit {
+            stats.in_flight += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    async fn acquire(&self, host: &str) {
+        while !self.try_acquire(host) {
+            task::sleep(ACQUIRE_POLL_INTERVAL).await;
+        }
+    }
+
+    fn release(&self, host: &str, outcome: Outcome) {
+        let mut hosts = self.hosts.lock().unwrap();
+        if let Some(stats) = hosts.get_mut(host) {
+            stats.in_flight -= 1;
+            if stats.record(outcome, self.max_limit) {
+                debug!(
+                    "Connection limit for {} is now {} after {} requests ({:.1}% throttled, {:.1}% failed)",
+                    host,
+                    stats.limit,
+                    stats.requests,
+                    stats.rate(stats.throttled),
+                    stats.rate(stats.failed)
+                );
+            }
+        }
+    }
+
+    /// Logs the requests made to each host with the rates they were throttled and failed at
+    pub fn log_host_rates(&self) {
+        let hosts = self.hosts.lock().unwrap();
+        for (host, stats) in hosts.iter() {
+            debug!(
+                "{}: {} requests, {:.1}% throttled, {:.1}% failed, final connection limit {}",
+                host,
+                stats.requests,
+                stats.rate(stats.throttled),
+                stats.rate(stats.failed),
+                stats.limit
+            );
+        }
+    }
+}
+
+#[surf::utils::async_trait]
+impl surf::middleware::Middleware for ConnLimiter {
+    async fn handle(
+        &self,
+        req: surf::Request,
+        client: surf::Client,
+        next: surf::middleware::Next<'_>,
+    ) -> surf::Result<surf::Response> {
+        let host = match req.url().host_str() {
+            Some(host) => host.to_lowercase(),
+            None => return next.run(req, client).await,
+        };
+        self.acquire(&host).await;
+        let result = next.run(req, client).await;
+        let outcome = match &result {
+            Ok(res) => match res.status() {
+                surf::StatusCode::TooManyRequests | surf::StatusCode::ServiceUnavailable => {
+                    Outcome::Throttled
+                }
+                status if status.is_server_error() => Outcome::Failed,
+                _ => Outcome::Success,
+            },
+            Err(_) => Outcome::Failed,
+        };
+        self.release(&host, outcome);
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_host_stats_record() {
+        let mut stats = HostStats::new(2);
+        // The limit goes up after a round of successes at the current limit
+        assert!(!stats.record(Outcome::Success, 4));
+        assert!(stats.record(Outcome::Success, 4));
+        assert_eq!(3, stats.limit);
+        for _ in 0..3 {
+            stats.record(Outcome::Success, 4);
+        }
+        assert_eq!(4, stats.limit);
+        for _ in 0..4 {
+            stats.record(Outcome::Success, 4);
+        }
+        assert_eq!(4, stats.limit);
+
+        // Throttling halves the limit
+        assert!(stats.record(Outcome::Throttled, 4));
+        assert_eq!(2, stats.limit);
+        stats.record(Outcome::Throttled, 4);
+        stats.record(Outcome::Throttled, 4);
+        assert_eq!(1, stats.limit);
+
+        // Failures only lower the limit when most requests fail
+        let mut stats = HostStats::new(3);
+        stats.record(Outcome::Success, 4);
+        assert!(!stats.record(Outcome::Failed, 4));
+        assert!(stats.record(Outcome::Failed, 4));
+        assert_eq!(2, stats.limit);
+        assert_eq!(3, stats.requests);
+        assert!((stats.rate(stats.failed) - 66.7).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_conn_limiter_acquire() {
+        let limiter = ConnLimiter::new(1, 2);
+        assert!(limiter.try_acquire("example.com"));
+        assert!(!limiter.try_acquire("example.com"));
+        // Hosts have separate limits
+        assert!(limiter.try_acquire("example.org"));
+        limiter.release("example.com", Outcome::Success);
+        // A successful round at a limit of 1 raises it to 2
+        assert!(limiter.try_acquire("example.com"));
+        assert!(limiter.try_acquire("example.com"));
+        assert!(!limiter.try_acquire("example.com"));
+    }
+}
@@ -7,13 +7,18 @@ use comfy_table::{Cell, CellAlignment, ContentArrangement, Table};
 use flexi_logger::{FileSpec, LevelFilter};
 use log::error;
 
+use crate::duplicates::DuplicateArticle;
 use crate::errors::PaperoniError;
+use crate::i18n::{Locale, Message, Outcome};
 
 pub fn display_summary(
     initial_article_count: usize,
     successful_articles_table: Table,
     partial_downloads: Vec<PartialDownload>,
+    redirected_downloads: Vec<RedirectedDownload>,
+    duplicate_articles: Vec<DuplicateArticle>,
     errors: Vec<PaperoniError>,
+    locale: Locale,
 ) {
     let partial_downloads_count = partial_downloads.len();
     let successfully_downloaded_count =
@@ -21,12 +26,15 @@ pub fn display_summary(
 
     println!(
         "{}",
-        short_summary(DownloadCount::new(
-            initial_article_count,
-            successfully_downloaded_count,
-            partial_downloads_count,
-            errors.len()
-        ))
+        short_summary(
+            DownloadCount::new(
+                initial_article_count,
+                successfully_downloaded_count,
+                partial_downloads_count,
+                errors.len()
+            ),
+            locale,
+        )
         .bold()
     );
 
@@ -35,13 +43,19 @@ pub fn display_summary(
     }
 
     if partial_downloads_count > 0 {
-        println!("\n{}", "Partially failed downloads".yellow().bold());
+        println!(
+            "\n{}",
+            locale
+                .text(Message::PartiallyFailedDownloads)
+                .yellow()
+                .bold()
+        );
         let mut table_partial = Table::new();
         table_partial
             .load_preset(UTF8_HORIZONTAL_BORDERS_ONLY)
             .set_header(vec![
-                Cell::new("Link").set_alignment(CellAlignment::Center),
-                Cell::new("Title").set_alignment(CellAlignment::Center),
+                Cell::new(locale.text(Message::Link)).set_alignment(CellAlignment::Center),
+                Cell::new(locale.text(Message::Title)).set_alignment(CellAlignment::Center),
             ])
             .set_content_arrangement(ContentArrangement::Dynamic);
 
@@ -51,14 +65,54 @@ pub fn display_summary(
         println!("{}", table_partial);
     }
 
+    if !redirected_downloads.is_empty() {
+        println!("\n{}", locale.text(Message::RedirectedLinks).cyan().bold());
+        let mut table_redirected = Table::new();
+        table_redirected
+            .load_preset(UTF8_HORIZONTAL_BORDERS_ONLY)
+            .set_header(vec![
+                Cell::new(locale.text(Message::Link)).set_alignment(CellAlignment::Center),
+                Cell::new(locale.text(Message::FinalLink)).set_alignment(CellAlignment::Center),
+            ])
+            .set_content_arrangement(ContentArrangement::Dynamic);
+
+        for redirected in redirected_downloads {
+            table_redirected.add_row(vec![&redirected.link, &redirected.final_link]);
+        }
+        println!("{}", table_redirected);
+    }
+
+    if !duplicate_articles.is_empty() {
+        println!(
+            "\n{}",
+            locale.text(Message::DuplicateArticles).cyan().bold()
+        );
+        let mut table_duplicates = Table::new();
+        table_duplicates
+            .load_preset(UTF8_HORIZONTAL_BORDERS_ONLY)
+            .set_header(vec![
+                Cell::new(locale.text(Message::Link)).set_alignment(CellAlignment::Center),
+                Cell::new(locale.text(Message::DuplicateOf)).set_alignment(CellAlignment::Center),
+            ])
+            .set_content_arrangement(ContentArrangement::Dynamic);
+
+        for duplicate in duplicate_articles {
+            table_duplicates.add_row(vec![&duplicate.link, &duplicate.duplicate_of]);
+        }
+        println!("{}", table_duplicates);
+    }
+
     if !errors.is_empty() {
-        println!("\n{}", "Failed article downloads".bright_red().bold());
+        println!(
+            "\n{}",
+            locale.text(Message::FailedDownloads).bright_red().bold()
+        );
         let mut table_failed = Table::new();
         table_failed
             .load_preset(UTF8_HORIZONTAL_BORDERS_ONLY)
             .set_header(vec![
-                Cell::new("Link").set_alignment(CellAlignment::Center),
-                Cell::new("Reason").set_alignment(CellAlignment::Center),
+                Cell::new(locale.text(Message::Link)).set_alignment(CellAlignment::Center),
+                Cell::new(locale.text(Message::Reason)).set_alignment(CellAlignment::Center),
             ])
             .set_content_arrangement(ContentArrangement::Dynamic);
 
@@ -75,40 +129,31 @@ pub fn display_summary(
 }
 
 /// Returns a string summary of the total number of failed and successful article downloads
-fn short_summary(download_count: DownloadCount) -> String {
+fn short_summary(download_count: DownloadCount, locale: Locale) -> String {
     if download_count.total
         != download_count.successful + download_count.failed + download_count.partial
     {
         panic!("initial_count must be equal to the sum of failed and successful count")
     }
-    let get_noun = |count: usize| if count == 1 { "article" } else { "articles" };
-    let get_summary = |count, label, color: Color| {
+    let get_summary = |count, outcome, color: Color| {
         if count == 0 {
             return "".to_string();
         };
-
-        {
-            if count == 1 && count == download_count.total {
-                "Article".to_string() + label
-            } else if count == download_count.total {
-                "All ".to_string() + get_noun(count) + label
-            } else {
-                count.to_string() + " " + get_noun(count) + label
-            }
-        }
-        .color(color)
-        .to_string()
+        locale
+            .summary(outcome, count, download_count.total)
+            .color(color)
+            .to_string()
     };
 
     let mut summary = get_summary(
         download_count.successful,
-        " downloaded successfully",
+        Outcome::Downloaded,
         Color::BrightGreen,
     );
 
     let partial_summary = get_summary(
         download_count.partial,
-        " partially failed to download",
+        Outcome::PartiallyFailed,
         Color::Yellow,
     );
 
@@ -118,7 +163,7 @@ fn short_summary(download_count: DownloadCount) -> String {
         summary = summary + &partial_summary;
     }
 
-    let failed_summary = get_summary(download_count.failed, " failed to download", Color::Red);
+    let failed_summary = get_summary(download_count.failed, Outcome::Failed, Color::Red);
     if !summary.is_empty() && !failed_summary.is_empty() {
         summary = summary + ", " + &failed_summary;
     } else {
@@ -145,7 +190,7 @@ impl DownloadCount {
 }
 
 use crate::errors::LogError as Error;
-use crate::http::PartialDownload;
+use crate::http::{PartialDownload, RedirectedDownload};
 
 pub fn init_logger(
     log_level: LevelFilter,
@@ -186,29 +231,30 @@ pub fn init_logger(
 #[cfg(test)]
 mod tests {
     use super::{short_summary, DownloadCount};
+    use crate::i18n::Locale;
     use colored::*;
     #[test]
     fn test_short_summary() {
         assert_eq!(
-            short_summary(DownloadCount::new(1, 1, 0, 0)),
+            short_summary(DownloadCount::new(1, 1, 0, 0), Locale::En),
             "Article downloaded successfully".bright_green().to_string()
         );
         assert_eq!(
-            short_summary(DownloadCount::new(1, 0, 0, 1)),
+            short_summary(DownloadCount::new(1, 0, 0, 1), Locale::En),
             "Article failed to download".red().to_string()
         );
         assert_eq!(
-            short_summary(DownloadCount::new(10, 10, 0, 0)),
+            short_summary(DownloadCount::new(10, 10, 0, 0), Locale::En),
             "All articles downloaded successfully"
                 .bright_green()
                 .to_string()
         );
         assert_eq!(
-            short_summary(DownloadCount::new(10, 0, 0, 10)),
+            short_summary(DownloadCount::new(10, 0, 0, 10), Locale::En),
             "All articles failed to download".red().to_string()
         );
         assert_eq!(
-            short_summary(DownloadCount::new(10, 8, 0, 2)),
+            short_summary(DownloadCount::new(10, 8, 0, 2), Locale::En),
             format!(
                 "{}, {}",
                 "8 articles downloaded successfully".bright_green(),
@@ -216,7 +262,7 @@ mod tests {
             )
         );
         assert_eq!(
-            short_summary(DownloadCount::new(10, 1, 0, 9)),
+            short_summary(DownloadCount::new(10, 1, 0, 9), Locale::En),
             format!(
                 "{}, {}",
                 "1 article downloaded successfully".bright_green(),
@@ -224,7 +270,7 @@ mod tests {
             )
         );
         assert_eq!(
-            short_summary(DownloadCount::new(7, 6, 0, 1)),
+            short_summary(DownloadCount::new(7, 6, 0, 1), Locale::En),
             format!(
                 "{}, {}",
                 "6 articles downloaded successfully".bright_green(),
@@ -232,7 +278,7 @@ mod tests {
             )
         );
         assert_eq!(
-            short_summary(DownloadCount::new(7, 4, 2, 1)),
+            short_summary(DownloadCount::new(7, 4, 2, 1), Locale::En),
             format!(
                 "{}, {}, {}",
                 "4 articles downloaded successfully".bright_green(),
@@ -241,7 +287,7 @@ mod tests {
             )
         );
         assert_eq!(
-            short_summary(DownloadCount::new(12, 6, 6, 0)),
+            short_summary(DownloadCount::new(12, 6, 6, 0), Locale::En),
             format!(
                 "{}, {}",
                 "6 articles downloaded successfully".bright_green(),
@@ -249,7 +295,7 @@ mod tests {
             )
         );
         assert_eq!(
-            short_summary(DownloadCount::new(5, 0, 4, 1)),
+            short_summary(DownloadCount::new(5, 0, 4, 1), Locale::En),
             format!(
                 "{}, {}",
                 "4 articles partially failed to download".yellow(),
@@ -257,7 +303,7 @@ mod tests {
             )
         );
         assert_eq!(
-            short_summary(DownloadCount::new(4, 0, 4, 0)),
+            short_summary(DownloadCount::new(4, 0, 4, 0), Locale::En),
             "All articles partially failed to download"
                 .yellow()
                 .to_string()
@@ -269,6 +315,6 @@ mod tests {
         expected = "initial_count must be equal to the sum of failed and successful count"
     )]
     fn test_short_summary_panics_on_invalid_input() {
-        short_summary(DownloadCount::new(0, 12, 0, 43));
+        short_summary(DownloadCount::new(0, 12, 0, 43), Locale::En);
     }
 }
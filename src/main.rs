@@ -2,28 +2,92 @@
 extern crate lazy_static;
 
 use std::process::exit;
+use std::time::Instant;
 
 use colored::Colorize;
 use comfy_table::presets::{UTF8_FULL, UTF8_HORIZONTAL_BORDERS_ONLY};
 use comfy_table::{ContentArrangement, Table};
-use http::download;
+use http::{download, RedirectedDownload};
+use i18n::Message;
 use indicatif::{ProgressBar, ProgressStyle};
 
+mod arxiv;
+mod atomic_file;
+mod bench;
+mod blog_api;
+mod cjk;
 mod cli;
+mod clipboard;
+mod comments;
+mod config;
+mod cookies;
+mod cover_collage;
+mod crawl;
+mod debug_extract;
+mod discussion;
+mod dns;
+mod duplicates;
 mod epub;
 mod errors;
+mod exporter;
+mod extraction_debug;
 mod extractor;
+mod feed;
+mod gif_flatten;
+mod grayscale;
+mod history;
 mod html;
 /// This module is responsible for async HTTP calls for downloading
 /// the HTML content and images
 mod http;
+mod i18n;
+mod image_cache;
+mod image_size;
+mod jobs;
+mod limiter;
 mod logs;
+mod manifest;
+mod markdown;
+mod medium;
+mod metrics;
 mod moz_readability;
+mod newsletter;
+mod odt;
+mod page_list;
+mod post_process;
+mod preview;
+mod print_links;
+mod progress;
+mod push;
+mod report;
+mod reqwest_client;
+mod sanitize;
+mod search;
+mod selftest;
+mod series;
+mod server;
+mod site;
+mod size_budget;
+mod source_qr;
+mod ssml;
+mod stack_exchange;
+mod svg;
+mod text_replace;
+mod translate;
+mod typography;
+mod url_rewrite;
+mod watch;
+mod youtube;
 
 use cli::AppConfig;
-use epub::generate_epubs;
-use html::generate_html_exports;
+use crawl::{crawl_archives, crawl_index_pages};
+use exporter::{export_articles, ExportedFiles};
 use logs::display_summary;
+use manifest::{collect_manifest_entries, write_manifests};
+use moz_readability::LOW_CONFIDENCE_SCORE;
+use post_process::post_process_files;
+use progress::ProgressEvent;
+use report::{collect_report_entries, write_report};
 
 fn main() {
     let app_config = match cli::AppConfig::init_with_cli() {
@@ -34,22 +98,211 @@ fn main() {
         }
     };
 
-    if !app_config.urls.is_empty() {
-        run(app_config);
+    if let Some(metrics_options) = &app_config.metrics {
+        metrics::start_reporting(metrics_options);
+    }
+
+    if let Some(address) = app_config.server_address.clone() {
+        if let Err(err) = server::serve(app_config, &address) {
+            eprintln!("{}: {}", "ERROR".bold().bright_red(), err);
+            exit(1);
+        }
+    } else if let Some(bench_options) = &app_config.bench {
+        if let Err(err) = bench::run_bench(&app_config, bench_options) {
+            eprintln!("{}: {}", "ERROR".bold().bright_red(), err);
+            exit(1);
+        }
+    } else if let Some(preview_options) = app_config.preview.clone() {
+        if let Err(err) = preview::run_preview(app_config, &preview_options) {
+            eprintln!("{}: {}", "ERROR".bold().bright_red(), err);
+            exit(1);
+        }
+    } else if let Some(debug_extract_options) = &app_config.debug_extract {
+        if let Err(err) = debug_extract::run_debug_extract(debug_extract_options) {
+            eprintln!("{}: {}", "ERROR".bold().bright_red(), err);
+            exit(1);
+        }
+    } else if let Some(selftest_options) = &app_config.selftest {
+        if let Err(err) = selftest::run_selftest(selftest_options) {
+            eprintln!("{}: {}", "ERROR".bold().bright_red(), err);
+            exit(1);
+        }
+    } else if let Some(watch_options) = &app_config.watch {
+        if let Err(err) = watch::watch_dir(&app_config, watch_options, |file_config| {
+            run(file_config);
+        }) {
+            eprintln!("{}: {}", "ERROR".bold().bright_red(), err);
+            exit(1);
+        }
+    } else if let Some(search_options) = &app_config.search {
+        if let Err(err) = search::run_search(&app_config, search_options) {
+            eprintln!("{}: {}", "ERROR".bold().bright_red(), err);
+            exit(1);
+        }
+    } else if let Some(cli::CacheCommand::Clean) = &app_config.cache_command {
+        clean_image_cache();
+    } else if !app_config.urls.is_empty() && run(app_config) {
+        exit(1);
     }
 }
 
-fn run(app_config: AppConfig) {
+fn clean_image_cache() {
+    let image_cache =
+        match image_cache::ImageCache::with_default_dir(image_cache::DEFAULT_IMAGE_CACHE_SIZE) {
+            Some(image_cache) => image_cache,
+            None => {
+                eprintln!(
+                    "{}: Unable to find the home directory of the image cache",
+                    "ERROR".bold().bright_red()
+                );
+                exit(1);
+            }
+        };
+    match image_cache.clear() {
+        Ok((removed_count, freed_size)) => println!(
+            "Removed {} image(s) freeing {:.1} MB from {}",
+            removed_count,
+            freed_size as f64 / (1024.0 * 1024.0),
+            image_cache.dir().display()
+        ),
+        Err(err) => {
+            eprintln!("{}: {}", "ERROR".bold().bright_red(), err);
+            exit(1);
+        }
+    }
+}
+
+/// The path of the file that the articles of a series or an archive are merged into when
+/// `--merge` is not passed
+fn merged_output_name(app_config: &AppConfig, name: &str) -> String {
+    format!(
+        "{}/{}.{}",
+        app_config.output_directory.as_deref().unwrap_or("."),
+        name.replace("/", " ").replace("\\", " "),
+        app_config.export_type.file_extension()
+    )
+}
+
+/// Downloads and exports the articles. Returns whether any of them failed.
+fn run(mut app_config: AppConfig) -> bool {
     let mut errors = Vec::new();
     let mut partial_downloads = Vec::new();
+    let mut failed_index_count = 0;
+    let locale = app_config.locale;
+
+    if app_config.crawl_depth > 0 {
+        println!(
+            "{}",
+            locale
+                .text(Message::CrawlingPages)
+                .replace("{count}", &app_config.urls.len().to_string())
+        );
+        let (article_links, crawl_errors) = crawl_index_pages(&app_config);
+        println!(
+            "{}",
+            locale
+                .text(Message::FoundArticleLinks)
+                .replace("{count}", &article_links.len().to_string())
+        );
+        // Index pages that could not be fetched are reported along with the failed articles
+        failed_index_count = crawl_errors.len();
+        errors.extend(crawl_errors);
+        app_config.urls = article_links;
+    }
+
+    if let Some(archive_options) = app_config.archive.clone() {
+        println!(
+            "{}",
+            locale
+                .text(Message::CollectingArchiveLinks)
+                .replace("{count}", &app_config.urls.len().to_string())
+        );
+        let (archive, crawl_errors) = crawl_archives(&app_config, &archive_options);
+        println!(
+            "{}",
+            locale
+                .text(Message::FoundArticleLinks)
+                .replace("{count}", &archive.links.len().to_string())
+        );
+        failed_index_count = crawl_errors.len();
+        errors.extend(crawl_errors);
+        app_config.urls = archive.links;
+        let name = archive.author.as_deref().unwrap_or("Anthology");
+        if app_config.merged.is_none() {
+            app_config.merged = Some(merged_output_name(&app_config, name));
+        }
+        if app_config.title.is_none() {
+            app_config.title = archive.author.clone();
+        }
+        if app_config.author.is_none() {
+            app_config.author = archive.author;
+        }
+    }
+
+    if app_config.is_following_series {
+        println!(
+            "{}",
+            locale
+                .text(Message::FollowingSeries)
+                .replace("{url}", &app_config.urls[0])
+        );
+        match series::follow_series(&app_config) {
+            Ok(series) => {
+                println!(
+                    "{}",
+                    locale
+                        .text(Message::FoundSeriesParts)
+                        .replace("{count}", &series.urls.len().to_string())
+                );
+                let name = series.name.as_deref().unwrap_or("Series");
+                if app_config.merged.is_none() {
+                    app_config.merged = Some(merged_output_name(&app_config, name));
+                }
+                if app_config.title.is_none() {
+                    app_config.title = series.name;
+                }
+                app_config.urls = series.urls;
+            }
+            // The parts found are unknown so only the starting url is downloaded
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if let Some(history) = app_config.history.clone() {
+        if app_config.is_resuming && app_config.merged.is_none() {
+            let (remaining_urls, exported_count) = history.resume(&app_config.urls);
+            if exported_count > 0 {
+                println!(
+                    "{}",
+                    locale
+                        .text(Message::SkippingExported)
+                        .replace("{count}", &exported_count.to_string())
+                );
+            }
+            app_config.urls = remaining_urls;
+        }
+        if app_config.urls.len() > 1 {
+            if let Some((estimate, known_count)) =
+                history.estimate(&app_config.urls, app_config.max_conn)
+            {
+                println!(
+                    "{}",
+                    locale
+                        .text(Message::EstimatedDuration)
+                        .replace("{duration}", &history::format_duration(estimate))
+                        .replace("{count}", &known_count.to_string())
+                );
+            }
+        }
+    }
 
     if let Some(dir_name) = &app_config.output_directory {
-        let noun = if app_config.urls.len() > 1 {
-            "articles"
+        let message = if app_config.urls.len() > 1 {
+            Message::DownloadingArticles
         } else {
-            "article"
+            Message::DownloadingArticle
         };
-        println!("Downloading {} to {}", noun, dir_name);
+        println!("{}", locale.text(message).replace("{dir}", dir_name));
     }
 
     let bar = if app_config.can_disable_progress_bar {
@@ -64,8 +317,109 @@ fn run(app_config: AppConfig) {
         enabled_bar
     };
 
-    let articles = download(&app_config, &bar, &mut partial_downloads, &mut errors);
-    bar.finish_with_message("Downloaded articles");
+    let mut skipped_urls = Vec::new();
+    let mut articles = download(
+        &app_config,
+        &bar,
+        &mut partial_downloads,
+        &mut errors,
+        &mut skipped_urls,
+    );
+    bar.finish_with_message(locale.text(Message::DownloadedArticles));
+    if !skipped_urls.is_empty() {
+        let reasons = [
+            app_config
+                .date_filter
+                .as_ref()
+                .map(|_| locale.text(Message::OutsideDateRange)),
+            app_config
+                .min_confidence
+                .map(|_| locale.text(Message::BelowMinConfidence)),
+        ];
+        let reasons = reasons
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>()
+            .join(&format!(" {} ", locale.text(Message::Or)));
+        println!(
+            "{}",
+            locale
+                .text(Message::SkippedArticles)
+                .replace("{count}", &skipped_urls.len().to_string())
+                .replace("{reasons}", &reasons)
+        );
+    }
+    let low_confidence_articles = articles
+        .iter()
+        .filter_map(|article| {
+            let score = article.confidence()?.score();
+            if score < LOW_CONFIDENCE_SCORE {
+                Some((article, score))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    if !low_confidence_articles.is_empty() {
+        println!(
+            "{}",
+            locale
+                .text(Message::LowConfidenceArticles)
+                .replace("{count}", &low_confidence_articles.len().to_string())
+        );
+        for (article, score) in low_confidence_articles {
+            println!(
+                "  {:.2}  {} ({})",
+                score,
+                article.metadata().title(),
+                article.url
+            );
+        }
+    }
+
+    let duplicate_articles = if app_config.merged.is_some() && !app_config.is_keeping_duplicates {
+        duplicates::remove_duplicates(&mut articles)
+    } else {
+        Vec::new()
+    };
+
+    if let Some(size_budget) = app_config.size_budget {
+        size_budget::fit_size_budget(&mut articles, size_budget, app_config.merged.is_some());
+    }
+
+    let redirected_downloads = articles
+        .iter()
+        .filter_map(|article| {
+            article
+                .redirected_from()
+                .map(|requested_url| RedirectedDownload::new(requested_url, &article.url))
+        })
+        .collect();
+
+    if let Some(push_options) = &app_config.push {
+        let (pushed_count, push_errors) = push::push_articles(&articles, &app_config, push_options);
+        println!(
+            "{}",
+            locale
+                .text(Message::PushedArticles)
+                .replace("{count}", &pushed_count.to_string())
+                .replace("{service}", push_options.service.name())
+        );
+        errors.extend(push_errors);
+    }
+
+    let report_entries = if app_config.report.is_some() {
+        collect_report_entries(&articles)
+    } else {
+        Vec::new()
+    };
+
+    let manifest_entries = if app_config.is_writing_manifests {
+        collect_manifest_entries(&articles)
+    } else {
+        Vec::new()
+    };
 
     let mut successful_articles_table = Table::new();
     successful_articles_table
@@ -73,41 +427,162 @@ fn run(app_config: AppConfig) {
         .load_preset(UTF8_HORIZONTAL_BORDERS_ONLY)
         .set_content_arrangement(ContentArrangement::Dynamic);
 
-    match app_config.export_type {
-        cli::ExportType::EPUB => {
-            match generate_epubs(articles, &app_config, &mut successful_articles_table) {
-                Ok(_) => (),
-                Err(gen_epub_errors) => {
-                    errors.extend(gen_epub_errors);
-                }
-            };
+    // The links are only marked as exported once their exports are written
+    let downloaded_urls = articles
+        .iter()
+        .map(|article| {
+            (
+                article.requested_url.clone(),
+                article.url.clone(),
+                article.metadata().title().to_owned(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let export_start = Instant::now();
+    let mut exported_files = ExportedFiles::default();
+    if let Err(export_errors) = export_articles(
+        articles,
+        &app_config,
+        &mut successful_articles_table,
+        &mut exported_files,
+    ) {
+        errors.extend(export_errors);
+    }
+
+    errors.extend(write_manifests(manifest_entries, &exported_files.articles));
+
+    // The manifests are written first as the command may move the files
+    if let Some(command) = &app_config.post_process {
+        let written_files = &exported_files.written;
+        let post_process_errors = post_process_files(command, written_files);
+        println!(
+            "{}",
+            locale
+                .text(Message::PostProcessedFiles)
+                .replace(
+                    "{count}",
+                    &(written_files.len() - post_process_errors.len()).to_string()
+                )
+                .replace("{total}", &written_files.len().to_string())
+        );
+        errors.extend(post_process_errors);
+    }
+
+    if app_config.progress.is_some() {
+        for (requested_url, url, title) in &downloaded_urls {
+            // A merged export that failed as a whole fails each of its articles
+            let export_error = errors.iter().find(|err| {
+                let source = err.article_source().as_deref();
+                source == Some(url.as_str())
+                    || (source.is_some() && source == app_config.merged.as_deref())
+            });
+            app_config.emit_progress(match export_error {
+                Some(err) => ProgressEvent::Failed {
+                    url: requested_url,
+                    reason: err.to_string(),
+                },
+                None => ProgressEvent::Exported {
+                    url: requested_url,
+                    title,
+                },
+            });
         }
-        cli::ExportType::HTML => {
-            match generate_html_exports(articles, &app_config, &mut successful_articles_table) {
-                Ok(_) => (),
-                Err(gen_html_errors) => errors.extend(gen_html_errors),
+    }
+
+    if let Some(history) = &app_config.history {
+        for (requested_url, url, _) in &downloaded_urls {
+            if !errors
+                .iter()
+                .any(|err| err.article_source().as_deref() == Some(url.as_str()))
+            {
+                history.mark_exported(requested_url);
             }
         }
+        if let Err(err) = history.save() {
+            eprintln!(
+                "{}: Unable to save the download history: {}",
+                "ERROR".bold().bright_red(),
+                err
+            );
+        }
+    }
+
+    if let Some(metrics) = app_config.metrics() {
+        metrics.record_export(export_start.elapsed());
+        for err in &errors {
+            metrics.record_failure(err.kind());
+        }
+    }
+
+    if let Some(feed_path) = &app_config.feed {
+        let export_dir = app_config.output_directory.as_deref().unwrap_or(".");
+        match feed::write_feed(feed_path, export_dir) {
+            Ok(item_count) => println!(
+                "{}",
+                locale
+                    .text(Message::FeedWritten)
+                    .replace("{count}", &item_count.to_string())
+                    .replace("{path}", feed_path)
+            ),
+            Err(err) => eprintln!(
+                "{}: Unable to write feed to {}: {}",
+                "ERROR".bold().bright_red(),
+                feed_path,
+                err
+            ),
+        }
+    }
+
+    if let Some(report_path) = &app_config.report {
+        match write_report(
+            report_path,
+            &report_entries,
+            &exported_files.articles,
+            &partial_downloads,
+            &errors,
+            &app_config,
+        ) {
+            Ok(_) => println!(
+                "{}",
+                locale
+                    .text(Message::ReportWritten)
+                    .replace("{path}", report_path)
+            ),
+            Err(err) => eprintln!(
+                "{}: Unable to write report to {}: {}",
+                "ERROR".bold().bright_red(),
+                report_path,
+                err
+            ),
+        }
     }
 
     let has_errors = !errors.is_empty() || !partial_downloads.is_empty();
     display_summary(
-        app_config.urls.len(),
+        app_config.urls.len() + failed_index_count - skipped_urls.len(),
         successful_articles_table,
         partial_downloads,
+        redirected_downloads,
+        duplicate_articles,
         errors,
+        locale,
     );
 
     if app_config.is_logging_to_file {
-        println!(
-            "Log written to paperoni_{}.log\n",
+        let log_path = format!(
+            "paperoni_{}.log",
             app_config.start_time.format("%Y-%m-%d_%H-%M-%S")
         );
+        println!(
+            "{}\n",
+            locale
+                .text(Message::LogWritten)
+                .replace("{path}", &log_path)
+        );
     } else if has_errors && !app_config.is_logging_to_file {
-        println!("\nRun paperoni with the --log-to-file flag to create a log file");
+        println!("\n{}", locale.text(Message::LogToFileHint));
     }
 
-    if has_errors {
-        std::process::exit(1);
-    }
+    has_errors
 }
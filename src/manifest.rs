@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::Path;
+
+use itertools::Itertools;
+use log::{debug, info};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::errors::PaperoniError;
+use crate::extractor::Article;
+
+/// The details of an exported article written to the manifest of its output file
+pub struct ManifestEntry {
+    article: Value,
+}
+
+/// Collects the details of the articles for their manifests before they are exported
+pub fn collect_manifest_entries(articles: &[Article]) -> Vec<ManifestEntry> {
+    articles
+        .iter()
+        .map(|article| ManifestEntry {
+            article: json!({
+                "title": article.metadata().title(),
+                "url": article.url,
+                "requested_url": article.requested_url,
+                "extracted_at": article.extracted_at.map(|time| time.to_rfc3339()),
+                "source_sha256": article.source_checksum,
+                "confidence": article.confidence().map(|confidence| json!({
+                    "score": confidence.score(),
+                    "top_candidate_score": confidence.top_candidate_score,
+                    "link_density": confidence.link_density,
+                    "text_length": confidence.text_length,
+                })),
+            }),
+        })
+        .collect()
+}
+
+/// Writes a `.json` manifest next to each exported file with the articles in it and the
+/// checksum of the file. `article_files` are the files the articles were written to by the
+/// export, so the articles that were not written are skipped.
+pub fn write_manifests(
+    entries: Vec<ManifestEntry>,
+    article_files: &[Option<String>],
+) -> Vec<PaperoniError> {
+    let mut errors = Vec::new();
+    let outputs = entries
+        .into_iter()
+        .zip(article_files)
+        .filter_map(|(entry, output)| Some((output.clone()?, entry.article)))
+        .into_group_map();
+    for (output, articles) in outputs.into_iter().sorted_by(|(a, _), (b, _)| a.cmp(b)) {
+        let output_bytes = match fs::read(&output) {
+            Ok(output_bytes) => output_bytes,
+            Err(e) => {
+                debug!("Skipping the manifest of {}: {}", output, e);
+                continue;
+            }
+        };
+        let manifest_path = format!("{}.json", output);
+        let manifest = generate_manifest(&output, &output_bytes, articles);
+        match fs::write(Path::new(&manifest_path), manifest) {
+            Ok(_) => info!("Created manifest {}", manifest_path),
+            Err(e) => {
+                let mut err: PaperoniError = e.into();
+                err.set_article_source(&manifest_path);
+                errors.push(err);
+            }
+        }
+    }
+    errors
+}
+
+fn generate_manifest(output: &str, output_bytes: &[u8], articles: Vec<Value>) -> String {
+    let output_name = Path::new(output)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| output.to_owned());
+    let manifest = json!({
+        "output": output_name,
+        "output_sha256": format!("{:x}", Sha256::digest(output_bytes)),
+        "generator": {
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "articles": articles,
+    });
+    serde_json::to_string_pretty(&manifest).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_generate_manifest() {
+        let manifest = generate_manifest(
+            "./out/Article.epub",
+            b"abc",
+            vec![json!({"url": "https://example.com/a"})],
+        );
+        let manifest: Value = serde_json::from_str(&manifest).unwrap();
+        assert_eq!("Article.epub", manifest["output"]);
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            manifest["output_sha256"]
+        );
+        assert_eq!(env!("CARGO_PKG_VERSION"), manifest["generator"]["version"]);
+        assert_eq!("https://example.com/a", manifest["articles"][0]["url"]);
+    }
+}
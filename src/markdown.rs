@@ -0,0 +1,741 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use itertools::Itertools;
+use kuchiki::{NodeData, NodeRef};
+use log::{debug, error, info};
+use regex::Regex;
+
+use crate::{
+    atomic_file::{write_atomic, AtomicFile},
+    cli::{AppConfig, MarkdownFlavor},
+    errors::PaperoniError,
+    exporter::{file_safe_title, own_or_merged_file, ExportFileNames, Exporter},
+    extractor::{Article, ResourceInfo},
+};
+
+/// The folder images are copied to in an Obsidian vault
+const OBSIDIAN_ATTACHMENTS_DIR: &str = "attachments";
+
+/// Elements that are written as their own blocks instead of being part of the text
+/// around them
+const BLOCK_ELEMENTS: [&str; 28] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+const SKIPPED_ELEMENTS: [&str; 9] = [
+    "audio", "iframe", "math", "noscript", "script", "style", "svg", "template", "video",
+];
+
+lazy_static! {
+    static ref SPACES_REGEX: Regex = Regex::new(r" {2,}").unwrap();
+    /// Text at the start of a line that would be read as a list item or heading
+    static ref BLOCK_MARKER_REGEX: Regex = Regex::new(r"^(\d+)([.)])|^([#>+-])").unwrap();
+}
+
+/// Exports each article to its own Markdown file, or merges them into a single file with a
+/// thematic break between them
+pub struct MarkdownExporter<'a> {
+    app_config: &'a AppConfig,
+    /// The writer of the merged file with the folder its images are copied to
+    merged_writer: Option<(MarkdownWriter, PathBuf)>,
+    merged_markdown: String,
+    file_names: ExportFileNames,
+    /// The files of the articles written so far by their index
+    article_files: HashMap<usize, String>,
+    written_files: Vec<String>,
+}
+
+impl<'a> MarkdownExporter<'a> {
+    pub fn new(app_config: &'a AppConfig) -> Self {
+        Self {
+            app_config,
+            merged_writer: None,
+            merged_markdown: String::new(),
+            file_names: ExportFileNames::new(app_config.overwrite),
+            article_files: HashMap::new(),
+            written_files: Vec::new(),
+        }
+    }
+}
+
+impl Exporter for MarkdownExporter<'_> {
+    fn format(&self) -> &'static str {
+        "md"
+    }
+
+    fn prepare(&mut self, articles: &[Article]) -> Result<(), PaperoniError> {
+        let app_config = self.app_config;
+        let name = match &app_config.merged {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        debug!("Creating {:?}", name);
+        let file_path = Path::new(name);
+        let (imgs_dir_path, imgs_link_dir) = imgs_dir(file_path, &app_config.md_flavor);
+        if let MarkdownFlavor::Obsidian = app_config.md_flavor {
+            let title = app_config.title.clone().unwrap_or_else(|| {
+                file_path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            });
+            let tags = articles
+                .iter()
+                .flat_map(|article| article.metadata().keywords())
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            self.merged_markdown
+                .push_str(&frontmatter(&[("title", Some(&title))], &tags));
+        }
+        self.merged_writer = Some((MarkdownWriter::new(imgs_link_dir), imgs_dir_path));
+        Ok(())
+    }
+
+    fn add_article(&mut self, idx: usize, article: &Article) -> Result<(), PaperoniError> {
+        let app_config = self.app_config;
+        let flavor = &app_config.md_flavor;
+        if let Some((writer, _)) = &mut self.merged_writer {
+            if idx > 0 {
+                self.merged_markdown.push_str("\n---\n\n");
+            }
+            self.merged_markdown
+                .push_str(&writer.write_article(article));
+            debug!(
+                "Added {} to the export markdown file",
+                article.metadata().title()
+            );
+            return Ok(());
+        }
+
+        let title = article.metadata().title();
+        let file_name = match self.file_names.target(
+            app_config.output_directory.as_deref().unwrap_or("."),
+            &match flavor {
+                MarkdownFlavor::CommonMark => file_safe_title(article),
+                MarkdownFlavor::Obsidian => wiki_link_safe_name(title),
+            },
+            "md",
+        ) {
+            Some(file_name) => file_name,
+            None => return Ok(()),
+        };
+        let file_path = Path::new(&file_name);
+        let (imgs_dir_path, imgs_link_dir) = imgs_dir(file_path, flavor);
+        let mut writer = MarkdownWriter::new(imgs_link_dir);
+        let mut out_file = AtomicFile::create(file_path)?;
+        if let MarkdownFlavor::Obsidian = flavor {
+            let metadata = article.metadata();
+            let fields = [
+                ("title", Some(metadata.title())),
+                ("url", Some(article.url.as_str())),
+                ("author", metadata.byline().map(String::as_str)),
+                ("date", metadata.published()),
+            ];
+            let tags = metadata
+                .keywords()
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            out_file.write_all(frontmatter(&fields, &tags).as_bytes())?;
+        }
+        out_file.write_all(writer.write_article(article).as_bytes())?;
+        writer.copy_images(&imgs_dir_path)?;
+        out_file.persist()?;
+        debug!("Created {:?}", file_name);
+        self.article_files.insert(idx, file_name.clone());
+        self.written_files.push(file_name);
+        Ok(())
+    }
+
+    fn finalize(
+        &mut self,
+        _articles: &[Article],
+    ) -> Result<(), Vec<(Option<usize>, PaperoniError)>> {
+        let (name, (writer, imgs_dir_path)) = match (&self.app_config.merged, &self.merged_writer) {
+            (Some(name), Some(merged_writer)) => (name, merged_writer),
+            _ => return Ok(()),
+        };
+        if let Err(mut err) = write_atomic(name, &self.merged_markdown)
+            .map_err(|e| -> PaperoniError { e.into() })
+            .and_then(|_| writer.copy_images(imgs_dir_path))
+        {
+            error!("Failed to write articles to file: {}", name);
+            err.set_article_source(name);
+            return Err(vec![(None, err)]);
+        };
+
+        debug!("Created {:?}", name);
+        println!("Created {:?}", name);
+        self.written_files.push(name.clone());
+        Ok(())
+    }
+
+    fn written_files(&self) -> &[String] {
+        &self.written_files
+    }
+
+    fn article_file(&self, idx: usize) -> Option<&str> {
+        own_or_merged_file(
+            idx,
+            self.app_config.merged.as_deref(),
+            &self.article_files,
+            &self.written_files,
+        )
+    }
+}
+
+/// The folder the images of a Markdown file are copied to and the path they are linked
+/// with from the file. Images are kept in a folder named after the file like in HTML exports
+/// except in Obsidian vaults where they go in a shared attachments folder.
+fn imgs_dir(file_path: &Path, flavor: &MarkdownFlavor) -> (PathBuf, String) {
+    let parent = file_path.parent().unwrap_or_else(|| Path::new("."));
+    let dir_name = match flavor {
+        MarkdownFlavor::CommonMark => file_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        MarkdownFlavor::Obsidian => OBSIDIAN_ATTACHMENTS_DIR.to_owned(),
+    };
+    (parent.join(&dir_name), dir_name)
+}
+
+/// Builds the YAML frontmatter of an Obsidian note. Fields without a value are left out.
+fn frontmatter(fields: &[(&str, Option<&str>)], tags: &[&str]) -> String {
+    let mut yaml = String::from("---\n");
+    for (key, value) in fields {
+        if let Some(value) = value {
+            yaml.push_str(&format!("{}: {}\n", key, yaml_string(value)));
+        }
+    }
+    let tags = tags
+        .iter()
+        .filter_map(|tag| obsidian_tag(tag))
+        .unique_by(|tag| tag.to_lowercase())
+        .collect::<Vec<_>>();
+    if tags.is_empty() {
+        yaml.push_str("tags: []\n");
+    } else {
+        yaml.push_str("tags:\n");
+        for tag in tags {
+            yaml.push_str(&format!("  - {}\n", yaml_string(&tag)));
+        }
+    }
+    yaml.push_str("---\n\n");
+    yaml
+}
+
+fn yaml_string(value: &str) -> String {
+    let escaped = value
+        .replace("\\", "\\\\")
+        .replace("\"", "\\\"")
+        .replace("\n", " ");
+    format!("\"{}\"", escaped)
+}
+
+/// Converts a keyword to an Obsidian tag which can only contain letters, numbers, `_`, `-`
+/// and `/` and must not be only numbers
+fn obsidian_tag(keyword: &str) -> Option<String> {
+    let tag = keyword
+        .split_whitespace()
+        .join("-")
+        .chars()
+        .filter(|c| c.is_alphanumeric() || "_-/".contains(*c))
+        .collect::<String>();
+    if tag.chars().all(|c| c.is_numeric() || "_-/".contains(c)) {
+        None
+    } else {
+        Some(tag)
+    }
+}
+
+/// Removes the characters that cannot be used in the name of a note linked to with
+/// `[[name]]` in Obsidian
+fn wiki_link_safe_name(title: &str) -> String {
+    let name = title
+        .replace(
+            |c: char| "[]#^|\\/:*?\"<>".contains(c) || c.is_control(),
+            " ",
+        )
+        .split_whitespace()
+        .join(" ");
+    let name = name.trim_matches('.').trim();
+    if name.is_empty() {
+        "Untitled".to_owned()
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Converts the HTML of articles to Markdown. Headings, lists, quotes, code blocks, tables,
+/// links and images are mapped to their Markdown syntax while the rest of the content is
+/// written as paragraphs.
+struct MarkdownWriter {
+    /// The images of the article being written
+    article_imgs: Vec<ResourceInfo>,
+    /// The folder images are linked from, relative to the Markdown file
+    imgs_link_dir: String,
+    /// The downloaded images linked in the document
+    linked_imgs: Vec<String>,
+}
+
+impl MarkdownWriter {
+    fn new(imgs_link_dir: String) -> Self {
+        Self {
+            article_imgs: Vec::new(),
+            imgs_link_dir,
+            linked_imgs: Vec::new(),
+        }
+    }
+
+    /// Writes the title, content and source of an article
+    fn write_article(&mut self, article: &Article) -> String {
+        self.article_imgs = article.img_urls.clone();
+        let content = match article.node_ref().select_first("body") {
+            Ok(body) => self.write_blocks(body.as_node()),
+            Err(_) => self.write_blocks(article.node_ref()),
+        };
+        format!(
+            "# {}\n\n{}\n\nSource: <{}>\n",
+            escape_text(article.metadata().title()),
+            content,
+            article.url
+        )
+    }
+
+    /// Copies the downloaded images linked in the document to the images folder
+    fn copy_images(&self, imgs_dir_path: &Path) -> Result<(), PaperoniError> {
+        if self.linked_imgs.is_empty() {
+            return Ok(());
+        }
+        if !imgs_dir_path.exists() {
+            fs::create_dir_all(imgs_dir_path)?;
+        }
+        let temp_dir = std::env::temp_dir();
+        for img_path in &self.linked_imgs {
+            let (from, to) = (temp_dir.join(img_path), imgs_dir_path.join(img_path));
+            info!("Copying {:?} to {:?}", from, to);
+            fs::copy(from, to)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the children of a block element separated by blank lines. Consecutive inline
+    /// children are grouped into a paragraph.
+    fn write_blocks(&mut self, node_ref: &NodeRef) -> String {
+        let mut blocks = Vec::new();
+        let mut inline_run = Vec::new();
+        for child in node_ref.children() {
+            if is_block(&child) {
+                blocks.push(self.write_paragraph(&inline_run));
+                inline_run.clear();
+                blocks.push(self.write_block(&child));
+            } else {
+                inline_run.push(child);
+            }
+        }
+        blocks.push(self.write_paragraph(&inline_run));
+        blocks
+            .into_iter()
+            .filter(|block| !block.is_empty())
+            .join("\n\n")
+    }
+
+    fn write_block(&mut self, node_ref: &NodeRef) -> String {
+        let name = match node_ref.as_element() {
+            Some(elem) => elem.name.local.to_string(),
+            None => return String::new(),
+        };
+        match name.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let text = self.write_paragraph(&node_ref.children().collect::<Vec<_>>());
+                if text.is_empty() {
+                    return text;
+                }
+                let level = name[1..].parse::<usize>().unwrap_or(1);
+                format!("{} {}", "#".repeat(level), text.lines().join(" "))
+            }
+            "p" | "dt" | "dd" | "figcaption" => {
+                self.write_paragraph(&node_ref.children().collect::<Vec<_>>())
+            }
+            "ul" | "ol" => self.write_list(node_ref, name == "ol"),
+            "table" => self.write_table(node_ref),
+            "pre" => write_preformatted(node_ref),
+            "blockquote" => {
+                let quote = self.write_blocks(node_ref);
+                quote
+                    .lines()
+                    .map(|line| {
+                        if line.is_empty() {
+                            ">".to_owned()
+                        } else {
+                            format!("> {}", line)
+                        }
+                    })
+                    .join("\n")
+            }
+            "hr" => "---".to_owned(),
+            _ => self.write_blocks(node_ref),
+        }
+    }
+
+    /// Writes the nodes as a paragraph if they contain any text or images
+    fn write_paragraph(&mut self, nodes: &[NodeRef]) -> String {
+        let text = nodes
+            .iter()
+            .map(|node| self.write_inline(node))
+            .collect::<String>();
+        let text = text
+            .split('\n')
+            .map(|line| SPACES_REGEX.replace_all(line.trim(), " "))
+            .join("\n");
+        let text = trim_trailing_break(text.trim());
+        BLOCK_MARKER_REGEX
+            .replace(text, |caps: &regex::Captures| match caps.get(3) {
+                Some(marker) => format!("\\{}", marker.as_str()),
+                None => format!("{}\\{}", &caps[1], &caps[2]),
+            })
+            .into_owned()
+    }
+
+    fn write_list(&mut self, node_ref: &NodeRef, is_ordered: bool) -> String {
+        node_ref
+            .children()
+            .filter(|child| is_element_named(child, "li"))
+            .enumerate()
+            .map(|(idx, item)| {
+                let marker = if is_ordered {
+                    format!("{}. ", idx + 1)
+                } else {
+                    "- ".to_owned()
+                };
+                let indent = " ".repeat(marker.len());
+                let content = self.write_blocks(&item);
+                // Continuation lines of an item are indented to the start of its content
+                content
+                    .lines()
+                    .enumerate()
+                    .map(|(line_idx, line)| match (line_idx, line.is_empty()) {
+                        (0, _) => format!("{}{}", marker, line),
+                        (_, true) => String::new(),
+                        _ => format!("{}{}", indent, line),
+                    })
+                    .join("\n")
+            })
+            .join("\n")
+    }
+
+    /// Writes a table as a pipe table with its first row as the header
+    fn write_table(&mut self, node_ref: &NodeRef) -> String {
+        let rows = node_ref
+            .descendants()
+            .filter(|node| is_element_named(node, "tr"))
+            .map(|row| {
+                row.children()
+                    .filter(|cell| is_element_named(cell, "td") || is_element_named(cell, "th"))
+                    .map(|cell| {
+                        self.write_paragraph(&cell.children().collect::<Vec<_>>())
+                            .lines()
+                            .join(" ")
+                            .replace("|", "\\|")
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .filter(|cells| !cells.is_empty())
+            .collect::<Vec<_>>();
+        let column_count = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if column_count == 0 {
+            return String::new();
+        }
+        let format_row = |cells: &[String]| {
+            let padding = vec![String::new(); column_count - cells.len()];
+            format!("| {} |", cells.iter().chain(padding.iter()).join(" | "))
+        };
+        let mut table = vec![
+            format_row(&rows[0]),
+            format!("|{}", " --- |".repeat(column_count)),
+        ];
+        table.extend(rows[1..].iter().map(|cells| format_row(cells)));
+        table.join("\n")
+    }
+
+    fn write_inline_children(&mut self, node_ref: &NodeRef) -> String {
+        node_ref
+            .children()
+            .map(|child| self.write_inline(&child))
+            .collect()
+    }
+
+    fn write_inline(&mut self, node_ref: &NodeRef) -> String {
+        match node_ref.data() {
+            NodeData::Text(text) => escape_text(
+                &text
+                    .borrow()
+                    .replace(|c: char| c.is_ascii_whitespace(), " "),
+            ),
+            NodeData::Element(elem) => match elem.name.local.as_ref() {
+                _ if is_skipped(node_ref) => String::new(),
+                "strong" | "b" => self.write_emphasis(node_ref, "**"),
+                "em" | "i" | "cite" => self.write_emphasis(node_ref, "*"),
+                "code" | "kbd" | "samp" => write_code_span(&node_ref.text_contents()),
+                "br" => "\\\n".to_owned(),
+                "img" => self.write_image(node_ref),
+                "a" => {
+                    let text = self.write_inline_children(node_ref);
+                    let href = elem.attributes.borrow().get("href").map(ToOwned::to_owned);
+                    match href {
+                        Some(href) if !href.starts_with('#') && !text.trim().is_empty() => {
+                            format!("[{}]({})", text.trim(), escape_link(&href))
+                        }
+                        _ => text,
+                    }
+                }
+                _ => self.write_inline_children(node_ref),
+            },
+            _ => String::new(),
+        }
+    }
+
+    /// Wraps the text of an element in emphasis markers. The markers must be next to the
+    /// text so the whitespace around it is moved outside of them.
+    fn write_emphasis(&mut self, node_ref: &NodeRef, marker: &str) -> String {
+        let text = self.write_inline_children(node_ref);
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            return text;
+        }
+        let leading = if text.starts_with(char::is_whitespace) {
+            " "
+        } else {
+            ""
+        };
+        let trailing = if text.ends_with(char::is_whitespace) {
+            " "
+        } else {
+            ""
+        };
+        format!("{}{}{}{}{}", leading, marker, trimmed, marker, trailing)
+    }
+
+    /// Links an image that was downloaded from the images folder. Images that failed to
+    /// download are linked from their original url when it is absolute.
+    fn write_image(&mut self, node_ref: &NodeRef) -> String {
+        let attrs = node_ref.as_element().unwrap().attributes.borrow();
+        let src = match attrs.get("src") {
+            Some(src) => src,
+            None => return String::new(),
+        };
+        let alt = escape_text(attrs.get("alt").unwrap_or(""));
+        let is_downloaded = self
+            .article_imgs
+            .iter()
+            .any(|(img_path, _)| img_path.as_str() == src);
+        if is_downloaded {
+            if !self.linked_imgs.iter().any(|img_path| img_path == src) {
+                self.linked_imgs.push(src.to_owned());
+            }
+            format!(
+                "![{}]({})",
+                alt,
+                escape_link(&format!("{}/{}", self.imgs_link_dir, src))
+            )
+        } else if src.starts_with("http://") || src.starts_with("https://") {
+            format!("![{}]({})", alt, escape_link(src))
+        } else {
+            String::new()
+        }
+    }
+}
+
+/// Removes the hard line breaks left at the end of a paragraph by trailing `<br>` elements.
+/// An even number of backslashes at the end are escaped backslashes instead.
+fn trim_trailing_break(mut text: &str) -> &str {
+    while (text.len() - text.trim_end_matches('\\').len()) % 2 == 1 {
+        text = text[..text.len() - 1].trim_end();
+    }
+    text
+}
+
+/// Writes a code block fenced with backticks, or tildes when the code contains backticks
+fn write_preformatted(node_ref: &NodeRef) -> String {
+    let code = node_ref.text_contents();
+    let code = code.trim_end_matches('\n');
+    if code.trim().is_empty() {
+        return String::new();
+    }
+    let fence = if code.contains("```") { "~~~" } else { "```" };
+    format!("{0}\n{1}\n{0}", fence, code)
+}
+
+fn write_code_span(code: &str) -> String {
+    let code = code.replace('\n', " ");
+    if code.trim().is_empty() {
+        code
+    } else if code.contains('`') {
+        format!("`` {} ``", code)
+    } else {
+        format!("`{}`", code)
+    }
+}
+
+fn is_block(node_ref: &NodeRef) -> bool {
+    node_ref
+        .as_element()
+        .map(|elem| BLOCK_ELEMENTS.contains(&elem.name.local.as_ref()))
+        .unwrap_or(false)
+}
+
+fn is_skipped(node_ref: &NodeRef) -> bool {
+    node_ref
+        .as_element()
+        .map(|elem| SKIPPED_ELEMENTS.contains(&elem.name.local.as_ref()))
+        .unwrap_or(false)
+}
+
+fn is_element_named(node_ref: &NodeRef, name: &str) -> bool {
+    node_ref
+        .as_element()
+        .map(|elem| &elem.name.local == name)
+        .unwrap_or(false)
+}
+
+/// Escapes the characters that would otherwise be read as Markdown syntax
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\`*_[]<>".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn escape_link(url: &str) -> String {
+    url.replace(" ", "%20")
+        .replace("(", "%28")
+        .replace(")", "%29")
+        .replace("<", "%3C")
+        .replace(">", "%3E")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use kuchiki::traits::*;
+
+    fn write_html(html: &str, article_imgs: Vec<ResourceInfo>) -> (String, MarkdownWriter) {
+        let doc = kuchiki::parse_html().one(html);
+        let mut writer = MarkdownWriter::new("Article".to_owned());
+        writer.article_imgs = article_imgs;
+        let markdown = writer.write_blocks(doc.select_first("body").unwrap().as_node());
+        (markdown, writer)
+    }
+
+    #[test]
+    fn test_write_blocks() {
+        let (markdown, _) = write_html(
+            "<h2>A <em>heading</em></h2><p>Some <b>bold </b>text and a <a href=\"https://example.com/a b\">link</a></p><p>   </p>text outside<blockquote><p>A quote</p><p>on two lines<br>really</p></blockquote><hr><p>1999. A year with *stars*<br></p><p>C:\\</p>",
+            Vec::new(),
+        );
+        assert_eq!(
+            "## A *heading*\n\nSome **bold** text and a [link](https://example.com/a%20b)\n\ntext outside\n\n> A quote\n>\n> on two lines\\\n> really\n\n---\n\n1999\\. A year with \\*stars\\*\n\nC:\\\\",
+            markdown
+        );
+    }
+
+    #[test]
+    fn test_write_lists_and_tables() {
+        let (markdown, _) = write_html(
+            "<ul><li>One</li><li><p>Two</p><ol><li>Nested</li><li>List</li></ol></li></ul><table><tr><th>Name</th><th>Value</th></tr><tr><td>a|b</td></tr></table>",
+            Vec::new(),
+        );
+        assert_eq!(
+            "- One\n- Two\n\n  1. Nested\n  2. List\n\n| Name | Value |\n| --- | --- |\n| a\\|b |  |",
+            markdown
+        );
+    }
+
+    #[test]
+    fn test_write_code_and_images() {
+        let (markdown, writer) = write_html(
+            "<pre>fn main() {\n    a &lt; b;\n}\n</pre><p>Call <code>a`b</code></p><p><img src=\"img_1.png\" alt=\"A cat\"><img src=\"missing.png\"><img src=\"https://example.com/remote.png\"></p>",
+            vec![("img_1.png".to_owned(), Some("image/png".to_owned()))],
+        );
+        assert_eq!(
+            "```\nfn main() {\n    a < b;\n}\n```\n\nCall `` a`b ``\n\n![A cat](Article/img_1.png)![](https://example.com/remote.png)",
+            markdown
+        );
+        assert_eq!(vec!["img_1.png".to_owned()], writer.linked_imgs);
+    }
+
+    #[test]
+    fn test_frontmatter() {
+        assert_eq!(
+            "---\ntitle: \"A \\\"quoted\\\" title\"\nurl: \"https://example.com\"\ndate: \"2023-05-03\"\ntags:\n  - \"Rust\"\n  - \"Web-Development\"\n  - \"c\"\n---\n\n",
+            frontmatter(
+                &[
+                    ("title", Some("A \"quoted\" title")),
+                    ("url", Some("https://example.com")),
+                    ("author", None),
+                    ("date", Some("2023-05-03")),
+                ],
+                &["Rust", "rust", "Web Development", "2023", "c++"]
+            )
+        );
+        assert_eq!("---\ntags: []\n---\n\n", frontmatter(&[], &[]));
+    }
+
+    #[test]
+    fn test_wiki_link_safe_name() {
+        assert_eq!(
+            "Rust 2023 What's next",
+            wiki_link_safe_name("Rust [2023]: What's next?")
+        );
+        assert_eq!("C vs C++ a b", wiki_link_safe_name("C# vs C++ a|b"));
+        assert_eq!("Untitled", wiki_link_safe_name("###"));
+    }
+
+    #[test]
+    fn test_imgs_dir() {
+        let file_path = Path::new("./out/My Article.md");
+        assert_eq!(
+            (PathBuf::from("./out/My Article"), "My Article".to_owned()),
+            imgs_dir(file_path, &MarkdownFlavor::CommonMark)
+        );
+        assert_eq!(
+            (PathBuf::from("./out/attachments"), "attachments".to_owned()),
+            imgs_dir(file_path, &MarkdownFlavor::Obsidian)
+        );
+    }
+}
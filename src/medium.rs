@@ -0,0 +1,248 @@
+use kuchiki::{traits::*, NodeRef};
+use log::{debug, warn};
+use regex::Regex;
+use serde_json::Value;
+use url::Url;
+
+use crate::extractor::new_element;
+
+lazy_static! {
+    static ref MIRO_IMAGE_REGEX: Regex = Regex::new(
+        r"^https://miro\.medium\.com/(?:v2/)?(?:(?:resize:fit:\d+|max/\d+|format:\w+|quality:\d+)/)+(.+)$"
+    )
+    .unwrap();
+    static ref GIST_SCRIPT_REGEX: Regex = Regex::new(
+        r#"https://gist\.github\.com/((?:[\w-]+/)?[0-9a-f]+)\.js(?:\?file=([^"'&\s]+))?"#
+    )
+    .unwrap();
+}
+
+/// Recognizes the articles on Medium including the publications on custom domains, which load
+/// their images from the Medium CDN
+pub fn is_medium(url: &str, html: &str) -> bool {
+    let is_medium_host = Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+        .map_or(false, |host| {
+            host == "medium.com" || host.ends_with(".medium.com")
+        });
+    is_medium_host || html.contains("https://miro.medium.com/")
+}
+
+/// Prepares a Medium article for extraction by using the full size of its images and by
+/// filling in the byline, the publication date and the subtitle that Medium keeps outside of
+/// the meta tags read by readability
+pub fn prepare_post(doc: &NodeRef) {
+    let sources = doc.select("picture source").unwrap().collect::<Vec<_>>();
+    for source in sources {
+        source.as_node().detach();
+    }
+    for img in doc.select("img[src]").unwrap() {
+        let mut attrs = img.attributes.borrow_mut();
+        if let Some(full_size) = full_size_image(attrs.get("src").unwrap()) {
+            attrs.insert("src", full_size);
+            attrs.remove("srcset");
+            attrs.remove("sizes");
+        }
+    }
+
+    let head = match doc.select_first("head") {
+        Ok(head) => head,
+        Err(_) => return,
+    };
+    let linked_data = doc
+        .select("script[type=\"application/ld+json\"]")
+        .unwrap()
+        .filter_map(|script| serde_json::from_str::<Value>(&script.text_contents()).ok())
+        .find(|data| data.get("datePublished").is_some() || data.get("author").is_some());
+    let author = linked_data.as_ref().and_then(|data| match &data["author"] {
+        Value::Array(authors) => authors.first().and_then(author_name),
+        author => author_name(author),
+    });
+    let published = linked_data
+        .as_ref()
+        .and_then(|data| data["datePublished"].as_str());
+    let subtitle = doc
+        .select_first(".pw-subtitle-paragraph")
+        .ok()
+        .map(|subtitle| subtitle.text_contents().trim().to_owned())
+        .filter(|subtitle| !subtitle.is_empty());
+    // The meta tags are added first so that they are read before the ones already there
+    let meta_tags = [
+        ("name", "dc:creator", author.as_deref()),
+        ("property", "article:published_time", published),
+        ("name", "dc:description", subtitle.as_deref()),
+    ];
+    for (attr, key, content) in meta_tags.iter() {
+        if let Some(content) = content {
+            head.as_node()
+                .prepend(new_element("meta", &[(*attr, *key), ("content", *content)]));
+        }
+    }
+}
+
+fn author_name(author: &Value) -> Option<String> {
+    match author {
+        Value::String(name) => Some(name.to_owned()),
+        author => author["name"].as_str().map(str::to_owned),
+    }
+}
+
+/// Returns the url of the original image of a resized copy on the Medium CDN
+fn full_size_image(src: &str) -> Option<String> {
+    MIRO_IMAGE_REGEX
+        .captures(src)
+        .map(|captures| format!("https://miro.medium.com/v2/{}", &captures[1]))
+}
+
+/// Replaces the embedded gists of a Medium article with code blocks of their files. Medium
+/// embeds gists through its own media pages which load the script of the gist so the gist is
+/// found from the media page and its raw files are downloaded. Embeds that are not gists are
+/// kept.
+pub async fn inline_gists(client: &surf::Client, doc: &NodeRef, page_url: &str) {
+    let page_url = match Url::parse(page_url) {
+        Ok(url) => url,
+        Err(_) => return,
+    };
+    let iframes = doc.select("iframe[src]").unwrap().collect::<Vec<_>>();
+    for iframe in iframes {
+        let media_url = match iframe
+            .attributes
+            .borrow()
+            .get("src")
+            .and_then(|src| page_url.join(src).ok())
+        {
+            Some(url) if url.path().starts_with("/media/") => url,
+            _ => continue,
+        };
+        let media_html = match fetch_text(client, media_url.as_str()).await {
+            Ok(media_html) => media_html,
+            Err(e) => {
+                warn!("Unable to fetch the embed {}: {}", media_url, e);
+                continue;
+            }
+        };
+        let raw_url = match gist_raw_url(&media_html) {
+            Some(raw_url) => raw_url,
+            None => continue,
+        };
+        debug!("Inlining the gist {} embedded in {}", raw_url, page_url);
+        match fetch_text(client, &raw_url).await {
+            Ok(code) => {
+                let pre = new_element("pre", &[]);
+                let code_elem = new_element("code", &[]);
+                code_elem.append(NodeRef::new_text(code));
+                pre.append(code_elem);
+                iframe.as_node().insert_before(pre);
+                iframe.as_node().detach();
+            }
+            Err(e) => warn!("Unable to download the gist {}: {}", raw_url, e),
+        }
+    }
+}
+
+async fn fetch_text(client: &surf::Client, url: &str) -> Result<String, surf::Error> {
+    let mut res = client.get(url).await?;
+    if res.status().is_success() {
+        res.body_string().await
+    } else {
+        Err(surf::Error::from_str(
+            res.status(),
+            format!("responded with {}", res.status()),
+        ))
+    }
+}
+
+/// Finds the url of the raw file of the gist loaded by a Medium media page
+fn gist_raw_url(media_html: &str) -> Option<String> {
+    let captures = GIST_SCRIPT_REGEX.captures(media_html)?;
+    let file = captures
+        .get(2)
+        .map(|file| format!("/{}", file.as_str()))
+        .unwrap_or_default();
+    Some(format!(
+        "https://gist.github.com/{}/raw{}",
+        &captures[1], file
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_full_size_image() {
+        assert_eq!(
+            Some("https://miro.medium.com/v2/1*abc.png".to_owned()),
+            full_size_image("https://miro.medium.com/v2/resize:fit:700/format:webp/1*abc.png")
+        );
+        assert_eq!(
+            Some("https://miro.medium.com/v2/0*xyz.jpeg".to_owned()),
+            full_size_image("https://miro.medium.com/max/1400/0*xyz.jpeg")
+        );
+        // Avatars are cropped so they are kept as they are
+        assert_eq!(
+            None,
+            full_size_image("https://miro.medium.com/v2/resize:fill:88:88/1*avatar.jpeg")
+        );
+    }
+
+    #[test]
+    fn test_prepare_post() {
+        let doc = kuchiki::parse_html().one(
+            r#"<html><head>
+                <meta property="og:description" content="The start of the first paragraph">
+                <script type="application/ld+json">{"@type":"NewsArticle","author":{"@type":"Person","name":"Jane Doe"},"datePublished":"2023-04-05T10:00:00.000Z"}</script>
+            </head><body>
+                <h1>Title</h1>
+                <h2 class="pw-subtitle-paragraph">A subtitle about the article</h2>
+                <picture>
+                    <source srcset="https://miro.medium.com/v2/resize:fit:640/format:webp/1*abc.png 640w">
+                    <img src="https://miro.medium.com/v2/resize:fit:700/1*abc.png" srcset="x 700w">
+                </picture>
+            </body></html>"#,
+        );
+        prepare_post(&doc);
+        let meta_content = |selector: &str| {
+            doc.select_first(selector)
+                .ok()
+                .and_then(|meta| meta.attributes.borrow().get("content").map(str::to_owned))
+        };
+        assert_eq!(
+            Some("Jane Doe".to_owned()),
+            meta_content("meta[name=\"dc:creator\"]")
+        );
+        assert_eq!(
+            Some("2023-04-05T10:00:00.000Z".to_owned()),
+            meta_content("meta[property=\"article:published_time\"]")
+        );
+        assert_eq!(
+            Some("A subtitle about the article".to_owned()),
+            meta_content("meta[name=\"dc:description\"]")
+        );
+        assert!(doc.select_first("source").is_err());
+        let img = doc.select_first("img").unwrap();
+        assert_eq!(
+            Some("https://miro.medium.com/v2/1*abc.png"),
+            img.attributes.borrow().get("src")
+        );
+    }
+
+    #[test]
+    fn test_gist_raw_url() {
+        assert_eq!(
+            Some("https://gist.github.com/jane/0a1b2c/raw/main.rs".to_owned()),
+            gist_raw_url(
+                r#"<script src="https://gist.github.com/jane/0a1b2c.js?file=main.rs"></script>"#
+            )
+        );
+        assert_eq!(
+            Some("https://gist.github.com/0a1b2c/raw".to_owned()),
+            gist_raw_url(r#"<script src="https://gist.github.com/0a1b2c.js"></script>"#)
+        );
+        assert_eq!(
+            None,
+            gist_raw_url("<iframe src=\"https://www.youtube.com/embed/x\">")
+        );
+    }
+}
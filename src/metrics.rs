@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use async_std::task;
+use log::{error, warn};
+use tide::{Request, Response, StatusCode};
+
+use crate::cli::MetricsOptions;
+use crate::errors::ErrorKind;
+
+/// How often the stats file is rewritten
+const STATS_FILE_INTERVAL: Duration = Duration::from_secs(15);
+/// The content type of the Prometheus text format
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Counters of the work done by the long-running modes. They are shared by the clones of the
+/// app config and rendered in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    articles_processed: AtomicU64,
+    bytes_downloaded: AtomicU64,
+    failures: Mutex<BTreeMap<&'static str, u64>>,
+    extraction: DurationSummary,
+    export: DurationSummary,
+}
+
+#[derive(Debug, Default)]
+struct DurationSummary {
+    count: AtomicU64,
+    micros: AtomicU64,
+}
+
+impl DurationSummary {
+    fn record(&self, duration: Duration) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    pub fn record_article(&self) {
+        self.articles_processed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_downloaded_bytes(&self, bytes: u64) {
+        self.bytes_downloaded.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn record_failure(&self, kind: &ErrorKind) {
+        *self
+            .failures
+            .lock()
+            .unwrap()
+            .entry(kind.name())
+            .or_insert(0) += 1;
+    }
+
+    pub fn record_extraction(&self, duration: Duration) {
+        self.extraction.record(duration);
+    }
+
+    pub fn record_export(&self, duration: Duration) {
+        self.export.record(duration);
+    }
+
+    /// Renders the counters in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        let mut counter = |name: &str, help: &str, value: u64| {
+            writeln!(text, "# HELP {} {}", name, help).unwrap();
+            writeln!(text, "# TYPE {} counter", name).unwrap();
+            writeln!(text, "{} {}", name, value).unwrap();
+        };
+        counter(
+            "paperoni_articles_processed_total",
+            "Articles downloaded and extracted.",
+            self.articles_processed.load(Ordering::Relaxed),
+        );
+        counter(
+            "paperoni_downloaded_bytes_total",
+            "Bytes of the downloaded pages and images.",
+            self.bytes_downloaded.load(Ordering::Relaxed),
+        );
+
+        text.push_str("# HELP paperoni_failures_total Failures by the kind of error.\n");
+        text.push_str("# TYPE paperoni_failures_total counter\n");
+        for (kind, count) in self.failures.lock().unwrap().iter() {
+            writeln!(
+                text,
+                "paperoni_failures_total{{kind=\"{}\"}} {}",
+                kind, count
+            )
+            .unwrap();
+        }
+
+        let summaries = [
+            (
+                "paperoni_extraction_duration_seconds",
+                "Time spent extracting articles.",
+                &self.extraction,
+            ),
+            (
+                "paperoni_export_duration_seconds",
+                "Time spent exporting articles.",
+                &self.export,
+            ),
+        ];
+        for (name, help, summary) in summaries.iter() {
+            writeln!(text, "# HELP {} {}", name, help).unwrap();
+            writeln!(text, "# TYPE {} summary", name).unwrap();
+            writeln!(
+                text,
+                "{}_sum {}",
+                name,
+                summary.micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+            )
+            .unwrap();
+            writeln!(
+                text,
+                "{}_count {}",
+                name,
+                summary.count.load(Ordering::Relaxed)
+            )
+            .unwrap();
+        }
+        text
+    }
+}
+
+/// Serves the metrics on an HTTP endpoint and writes them to a stats file in the background
+/// as set in the options.
+pub fn start_reporting(options: &MetricsOptions) {
+    if let Some(address) = options.address.clone() {
+        let mut app = tide::with_state(options.counters.clone());
+        app.at("/metrics").get(metrics_endpoint);
+        println!("Serving metrics on http://{}/metrics", address);
+        task::spawn(async move {
+            if let Err(e) = app.listen(address.clone()).await {
+                error!("Unable to serve metrics on {}: {}", address, e);
+            }
+        });
+    }
+    if let Some(file) = options.file.clone() {
+        let counters = options.counters.clone();
+        thread::spawn(move || loop {
+            if let Err(e) = write_stats_file(&counters, Path::new(&file)) {
+                warn!("Unable to write metrics to {}: {}", file, e);
+            }
+            thread::sleep(STATS_FILE_INTERVAL);
+        });
+    }
+}
+
+/// Returns the metrics in the Prometheus text format
+async fn metrics_endpoint(req: Request<Arc<Metrics>>) -> tide::Result {
+    Ok(Response::builder(StatusCode::Ok)
+        .body(req.state().render())
+        .content_type(PROMETHEUS_CONTENT_TYPE)
+        .build())
+}
+
+/// Replaces the stats file in one step so that it is never read halfway written
+fn write_stats_file(metrics: &Metrics, path: &Path) -> std::io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, metrics.render())?;
+    fs::rename(&tmp_path, path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.record_article();
+        metrics.record_article();
+        metrics.record_downloaded_bytes(2048);
+        metrics.record_failure(&ErrorKind::HTTPError("404".into()));
+        metrics.record_failure(&ErrorKind::HTTPError("500".into()));
+        metrics.record_failure(&ErrorKind::ReadabilityError("empty".into()));
+        metrics.record_extraction(Duration::from_millis(1500));
+        metrics.record_extraction(Duration::from_millis(500));
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE paperoni_articles_processed_total counter\n"));
+        assert!(text.contains("\npaperoni_articles_processed_total 2\n"));
+        assert!(text.contains("\npaperoni_downloaded_bytes_total 2048\n"));
+        assert!(text.contains("\npaperoni_failures_total{kind=\"HTTPError\"} 2\n"));
+        assert!(text.contains("\npaperoni_failures_total{kind=\"ReadabilityError\"} 1\n"));
+        assert!(text.contains("\npaperoni_extraction_duration_seconds_sum 2\n"));
+        assert!(text.contains("\npaperoni_extraction_duration_seconds_count 2\n"));
+        assert!(text.contains("\npaperoni_export_duration_seconds_count 0\n"));
+    }
+}
@@ -0,0 +1,177 @@
+//! This module finds publication dates in the text of articles and normalizes them to ISO-8601
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use regex::Regex;
+
+lazy_static! {
+    /// Dates written in the text of a page e.g 2023-05-03, May 3rd, 2023 or 3 May 2023
+    static ref DATE_TEXT_REGEX: Regex = Regex::new(
+        r"(?ix)
+        \b\d{4}-\d{2}-\d{2}(?:[T\ ]\d{2}:\d{2}(?::\d{2}(?:\.\d+)?)?(?:Z|[+-]\d{2}:?\d{2})?)?
+        |\b(?:jan|feb|mar|apr|may|jun|jul|aug|sep|oct|nov|dec)[a-z]*\.?\s+\d{1,2}(?:st|nd|rd|th)?,?\s+\d{4}\b
+        |\b\d{1,2}(?:st|nd|rd|th)?\s+(?:jan|feb|mar|apr|may|jun|jul|aug|sep|oct|nov|dec)[a-z]*\.?,?\s+\d{4}\b"
+    )
+    .unwrap();
+    static ref ORDINAL_SUFFIX_REGEX: Regex = Regex::new(r"(?i)(\d)(?:st|nd|rd|th)\b").unwrap();
+    static ref BYLINE_PREFIX_REGEX: Regex = Regex::new(r"(?i)^\s*(?:written\s+)?by[:\s]+").unwrap();
+}
+
+const DATETIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+];
+const OFFSET_DATETIME_FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f%z", "%Y-%m-%d %H:%M:%S%.f%z"];
+const DATE_FORMATS: [&str; 5] = ["%Y-%m-%d", "%Y/%m/%d", "%Y%m%d", "%B %d %Y", "%d %B %Y"];
+
+/// Normalizes a date or date and time to ISO-8601. Dates with a time keep it and dates
+/// without one become e.g `2023-05-03`.
+pub fn normalize_date(input: &str) -> Option<String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return None;
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(input) {
+        return Some(datetime.to_rfc3339());
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc2822(input) {
+        return Some(datetime.to_rfc3339());
+    }
+    if let Some(datetime) = OFFSET_DATETIME_FORMATS
+        .iter()
+        .find_map(|format| DateTime::parse_from_str(input, format).ok())
+    {
+        return Some(datetime.to_rfc3339());
+    }
+    if let Some(datetime) = DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+    {
+        return Some(datetime.format("%Y-%m-%dT%H:%M:%S").to_string());
+    }
+
+    // Written dates like "Wednesday, May 3rd, 2023" are parsed without their
+    // weekday, ordinal suffixes and punctuation
+    let simplified = ORDINAL_SUFFIX_REGEX.replace_all(input, "$1");
+    let simplified = simplified
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '.')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>();
+    let without_weekday = if simplified.len() == 4 {
+        &simplified[1..]
+    } else {
+        &simplified[..]
+    };
+    let simplified = without_weekday.join(" ");
+    DATE_FORMATS
+        .iter()
+        .find_map(|format| {
+            NaiveDate::parse_from_str(input, format)
+                .or_else(|_| NaiveDate::parse_from_str(&simplified, format))
+                .ok()
+        })
+        .map(|date| date.format("%Y-%m-%d").to_string())
+}
+
+/// Finds the first date written in a text and normalizes it to ISO-8601
+pub fn find_date_in_text(text: &str) -> Option<String> {
+    DATE_TEXT_REGEX
+        .find_iter(text)
+        .find_map(|date_match| normalize_date(date_match.as_str()))
+}
+
+/// Removes the "By" prefix and any date from a visible byline such as
+/// "By John Doe · May 3, 2023", leaving only the author
+pub fn clean_byline(byline: &str) -> String {
+    let without_date = DATE_TEXT_REGEX.replace_all(byline, "");
+    let author = BYLINE_PREFIX_REGEX.replace(without_date.trim(), "");
+    let author = author
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches(|c: char| "·•|—–-,:;/".contains(c) || c.is_whitespace())
+        .to_string();
+    if author.is_empty() {
+        byline.trim().to_string()
+    } else {
+        author
+    }
+}
+
+/// Parses the day of a date normalized by [normalize_date]
+pub fn parse_normalized_date(date: &str) -> Option<NaiveDate> {
+    date.get(..10)
+        .and_then(|day| NaiveDate::parse_from_str(day, "%Y-%m-%d").ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalize_date() {
+        let cases = [
+            ("2023-05-03T10:15:00Z", Some("2023-05-03T10:15:00+00:00")),
+            (
+                "2023-05-03T10:15:00+0200",
+                Some("2023-05-03T10:15:00+02:00"),
+            ),
+            (
+                "Wed, 03 May 2023 10:15:00 GMT",
+                Some("2023-05-03T10:15:00+00:00"),
+            ),
+            ("2023-05-03 10:15", Some("2023-05-03T10:15:00")),
+            ("2023-05-03", Some("2023-05-03")),
+            ("2023/05/03", Some("2023-05-03")),
+            ("May 3, 2023", Some("2023-05-03")),
+            ("Sep. 21st, 2020", Some("2020-09-21")),
+            ("Wednesday, May 3rd, 2023", Some("2023-05-03")),
+            ("3 May 2023", Some("2023-05-03")),
+            ("2023-02-30", None),
+            ("yesterday", None),
+            ("", None),
+        ];
+        for (input, expected) in cases.iter() {
+            assert_eq!(
+                expected.map(String::from),
+                normalize_date(input),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_date_in_text() {
+        assert_eq!(
+            Some("2023-05-03".to_owned()),
+            find_date_in_text("By John Doe · May 3, 2023 · 5 min read")
+        );
+        assert_eq!(
+            Some("2021-11-08T09:30:00".to_owned()),
+            find_date_in_text("Updated 2021-11-08 09:30")
+        );
+        assert_eq!(None, find_date_in_text("Posted 3 days ago"));
+    }
+
+    #[test]
+    fn test_clean_byline() {
+        assert_eq!("John Doe", clean_byline("By John Doe · May 3, 2023"));
+        assert_eq!(
+            "Jane Roe",
+            clean_byline("  written by: Jane Roe | 2023-05-03")
+        );
+        assert_eq!("Ann Bylander", clean_byline("Ann Bylander"));
+        assert_eq!("May 3, 2023", clean_byline("May 3, 2023"));
+    }
+
+    #[test]
+    fn test_parse_normalized_date() {
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2023, 5, 3),
+            parse_normalized_date("2023-05-03T10:15:00+00:00")
+        );
+        assert_eq!(None, parse_normalized_date("May 2023"));
+    }
+}
@@ -1,35 +1,37 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 
+use chrono::NaiveDate;
 use html5ever::{LocalName, Namespace, QualName};
 use kuchiki::{
     iter::{Descendants, Elements, Select},
     traits::*,
-    NodeData, NodeRef,
+    ElementData, NodeData, NodeDataRef, NodeRef, Selectors,
 };
-use log::info;
+use log::{debug, info};
 use url::Url;
 
+use crate::cjk;
 use crate::errors::{ErrorKind, PaperoniError};
+use regexes::PatternOverrides;
 
-const DEFAULT_CHAR_THRESHOLD: usize = 500;
+pub const DEFAULT_CHAR_THRESHOLD: usize = 500;
 const FLAG_STRIP_UNLIKELYS: u32 = 0x1;
 const FLAG_WEIGHT_CLASSES: u32 = 0x2;
 const FLAG_CLEAN_CONDITIONALLY: u32 = 0x4;
 const READABILITY_SCORE: &'static str = "readability-score";
 const HTML_NS: &'static str = "http://www.w3.org/1999/xhtml";
-// TODO: Change to HashSet
-const PHRASING_ELEMS: [&str; 39] = [
-    "abbr", "audio", "b", "bdo", "br", "button", "cite", "code", "data", "datalist", "dfn", "em",
-    "embed", "i", "img", "input", "kbd", "label", "mark", "math", "meter", "noscript", "object",
-    "output", "progress", "q", "ruby", "samp", "script", "select", "small", "span", "strong",
-    "sub", "sup", "textarea", "time", "var", "wbr",
-];
-// TODO: Change to HashSet
-const DEFAULT_TAGS_TO_SCORE: [&str; 9] =
-    ["section", "h2", "h3", "h4", "h5", "h6", "p", "td", "pre"];
-// TODO: Change to HashSet
-const ALTER_TO_DIV_EXCEPTIONS: [&str; 4] = ["div", "article", "section", "p"];
+/// The most nodes a page can have before extraction is refused by default
+pub const DEFAULT_MAX_NODES: usize = 250_000;
+/// How long grabbing the article of a page can take by default
+pub const DEFAULT_EXTRACT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How many nodes are visited between checks of the extraction deadline
+const DEADLINE_CHECK_INTERVAL: usize = 256;
+
+/// The largest rowspan or colspan browsers accept
+const MAX_SPAN: usize = 65534;
 const PRESENTATIONAL_ATTRIBUTES: [&str; 12] = [
     "align",
     "background",
@@ -45,10 +47,88 @@ const PRESENTATIONAL_ATTRIBUTES: [&str; 12] = [
     "vspace",
 ];
 
-const DATA_TABLE_DESCENDANTS: [&str; 5] = ["col", "colgroup", "tfoot", "thead", "th"];
-// TODO: Change to HashSet
-const DEPRECATED_SIZE_ATTRIBUTE_ELEMS: [&str; 5] = ["table", "th", "td", "hr", "pre"];
+// The tag names checked in hot loops are kept in sets and the selectors used on every
+// candidate node are compiled once instead of on each call to `select`
+lazy_static! {
+    static ref PHRASING_ELEMS: HashSet<&'static str> = [
+        "abbr", "audio", "b", "bdo", "br", "button", "cite", "code", "data", "datalist", "dfn",
+        "em", "embed", "i", "img", "input", "kbd", "label", "mark", "math", "meter", "noscript",
+        "object", "output", "progress", "q", "ruby", "samp", "script", "select", "small", "span",
+        "strong", "sub", "sup", "textarea", "time", "var", "wbr",
+    ]
+    .iter()
+    .copied()
+    .collect();
+    static ref DEFAULT_TAGS_TO_SCORE: HashSet<&'static str> =
+        ["section", "h2", "h3", "h4", "h5", "h6", "p", "td", "pre"]
+            .iter()
+            .copied()
+            .collect();
+    static ref ALTER_TO_DIV_EXCEPTIONS: HashSet<&'static str> =
+        ["div", "article", "section", "p"].iter().copied().collect();
+    static ref DEPRECATED_SIZE_ATTRIBUTE_ELEMS: HashSet<&'static str> =
+        ["table", "th", "td", "hr", "pre"].iter().copied().collect();
+    static ref BLOCK_LEVEL_ELEMS: HashSet<&'static str> = [
+        "address",
+        "article",
+        "aside",
+        "blockquote",
+        "details",
+        "dialog",
+        "dd",
+        "div",
+        "dl",
+        "dt",
+        "fieldset",
+        "figcaption",
+        "footer",
+        "form",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "header",
+        "hgroup",
+        "hr",
+        "li",
+        "main",
+        "nav",
+        "ol",
+        "p",
+        "pre",
+        "section",
+        "table",
+        "ul",
+    ]
+    .iter()
+    .copied()
+    .collect();
+    static ref LINK_SELECTOR: Selectors = Selectors::compile("a").unwrap();
+    static ref IMG_SELECTOR: Selectors = Selectors::compile("img").unwrap();
+    static ref P_SELECTOR: Selectors = Selectors::compile("p").unwrap();
+    static ref LI_SELECTOR: Selectors = Selectors::compile("li").unwrap();
+    static ref INPUT_SELECTOR: Selectors = Selectors::compile("input").unwrap();
+    static ref BR_HR_SELECTOR: Selectors = Selectors::compile("br, hr").unwrap();
+    static ref TR_SELECTOR: Selectors = Selectors::compile("tr").unwrap();
+    static ref TD_SELECTOR: Selectors = Selectors::compile("td").unwrap();
+    static ref EMBED_SELECTOR: Selectors = Selectors::compile("object, embed, iframe").unwrap();
+    static ref MEDIA_SELECTOR: Selectors =
+        Selectors::compile("img, embed, object, iframe").unwrap();
+    static ref DATA_TABLE_DESCENDANTS_SELECTOR: Selectors =
+        Selectors::compile("col, colgroup, tfoot, thead, th").unwrap();
+}
 
+/// Selects the node and its descendants that match a precompiled selector
+fn select_compiled<'a>(
+    node_ref: &NodeRef,
+    selectors: &'a Selectors,
+) -> impl Iterator<Item = NodeDataRef<ElementData>> + 'a {
+    selectors.filter(node_ref.inclusive_descendants().elements())
+}
+
+pub mod dates;
 pub mod regexes;
 
 pub struct Readability {
@@ -59,6 +139,59 @@ pub struct Readability {
     article_dir: Option<String>,
     flags: u32,
     pub metadata: MetaData,
+    /// How long each step of the last parse took, used by the bench subcommand
+    pub timings: Vec<(&'static str, Duration)>,
+    /// The content scores of the nodes that were initialized as candidates for the article
+    content_scores: HashMap<NodeKey, f32>,
+    pub budget: ExtractionBudget,
+    /// Exceptions of the site of the article to the unlikely candidate and negative patterns
+    pub pattern_overrides: PatternOverrides,
+    /// How sure the parse is of the article it grabbed. Articles extracted with a selector
+    /// have none.
+    pub confidence: Option<Confidence>,
+}
+
+/// Limits on the work done grabbing an article so that pathological pages fail with a
+/// [ErrorKind::ReadabilityTimeout] instead of hanging, and on the length of the article it
+/// grabs so that junk pages fail with an [ErrorKind::ExtractionTooShort]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExtractionBudget {
+    /// The most nodes a page can have
+    pub max_nodes: usize,
+    /// How long grabbing the article can take
+    pub max_duration: Duration,
+    /// The characters below which grabbing the article is retried with looser rules
+    pub min_chars: usize,
+    /// The fewest words an extracted article can have. 0 keeps articles of any length
+    pub min_words: usize,
+}
+
+impl Default for ExtractionBudget {
+    fn default() -> Self {
+        Self {
+            max_nodes: DEFAULT_MAX_NODES,
+            max_duration: DEFAULT_EXTRACT_TIMEOUT,
+            min_chars: DEFAULT_CHAR_THRESHOLD,
+            min_words: 0,
+        }
+    }
+}
+
+/// A node used as a map key by its identity rather than its contents
+struct NodeKey(NodeRef);
+
+impl PartialEq for NodeKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for NodeKey {}
+
+impl Hash for NodeKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (&*self.0 as *const kuchiki::Node).hash(state);
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -77,17 +210,107 @@ impl Readability {
             article_dir: None,
             flags: FLAG_STRIP_UNLIKELYS | FLAG_WEIGHT_CLASSES | FLAG_CLEAN_CONDITIONALLY,
             metadata: MetaData::new(),
+            timings: Vec::new(),
+            content_scores: HashMap::new(),
+            budget: ExtractionBudget::default(),
+            pattern_overrides: PatternOverrides::default(),
+            confidence: None,
         }
     }
+
+    /// The document the article is extracted from
+    pub fn root_node(&self) -> &NodeRef {
+        &self.root_node
+    }
+
+    /// Runs a step of the parse and records how long it took
+    fn timed<T>(&mut self, step: &'static str, run_step: impl FnOnce(&mut Self) -> T) -> T {
+        let start = Instant::now();
+        let result = run_step(self);
+        self.timings.push((step, start.elapsed()));
+        result
+    }
+
     pub fn parse(&mut self, url: &str) -> Result<(), PaperoniError> {
+        self.timings.clear();
+        self.timed("prep_document", |readability| {
+            readability.unwrap_no_script_tags();
+            readability.remove_scripts();
+            readability.convert_background_images();
+            readability.prep_document();
+        });
+        self.timed("get_article_metadata", |readability| {
+            readability.metadata = readability.get_article_metadata();
+            readability.article_title = readability.metadata.title.clone();
+        });
+        self.timed("grab_article", Self::grab_article)?;
+        self.apply_visible_byline();
+        self.timed("post_process_content", |readability| {
+            readability.post_process_content(url)
+        })?;
+        self.check_word_count()
+    }
+
+    /// Fails when the extracted article has fewer words than the budget allows, which is
+    /// usually a page that is not an article such as an error or a login page
+    fn check_word_count(&self) -> Result<(), PaperoniError> {
+        let word_count = self
+            .article_node
+            .as_ref()
+            .map_or(0, |node| cjk::split_words(&node.text_contents()).len());
+        if word_count < self.budget.min_words {
+            return Err(ErrorKind::ExtractionTooShort(format!(
+                "Extraction too short: {} words found while at least {} are expected",
+                word_count, self.budget.min_words
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Extracts the content of the first element matching the CSS selector instead of
+    /// guessing where the article content is located.
+    pub fn parse_with_selector(&mut self, url: &str, selector: &str) -> Result<(), PaperoniError> {
         self.unwrap_no_script_tags();
         self.remove_scripts();
-        self.prep_document();
+        self.convert_background_images();
         self.metadata = self.get_article_metadata();
         self.article_title = self.metadata.title.clone();
-        self.grab_article()?;
-        self.post_process_content(url);
-        Ok(())
+        info!(
+            "Selecting {:?} in article {:?}",
+            selector, self.metadata.title
+        );
+        let selected = self
+            .root_node
+            .select_first(selector)
+            .map_err(|_| {
+                ErrorKind::ReadabilityError(format!(
+                    "No content matches the selector {:?}",
+                    selector
+                ))
+            })?
+            .as_node()
+            .clone();
+        selected.detach();
+
+        let page = NodeRef::new_element(
+            QualName::new(None, Namespace::from(HTML_NS), LocalName::from("div")),
+            BTreeMap::new(),
+        );
+        {
+            let mut page_attrs = page.as_element().unwrap().attributes.borrow_mut();
+            page_attrs.insert("id", "readability-page-1".to_string());
+            page_attrs.insert("class", "page".to_string());
+        }
+        page.append(selected);
+        let article_content = NodeRef::new_element(
+            QualName::new(None, Namespace::from(HTML_NS), LocalName::from("div")),
+            BTreeMap::new(),
+        );
+        article_content.append(page);
+        self.article_node = Some(article_content);
+        self.post_process_content(url)?;
+        self.check_word_count()
     }
 
     /// Recursively check if node is image, or if node contains exactly only one image
@@ -206,6 +429,42 @@ impl Readability {
     }
 
     /// Removes script tags from the document.
+    /// Adds an image to elements whose inline style sets a background image such as the hero
+    /// image of an article. This is done before the styles are cleaned so that the image is kept.
+    fn convert_background_images(&mut self) {
+        let styled_elems = match self.root_node.select("body [style]") {
+            Ok(styled_elems) => styled_elems.collect::<Vec<_>>(),
+            Err(_) => return,
+        };
+        for elem in styled_elems {
+            let bg_url = elem
+                .attributes
+                .borrow()
+                .get("style")
+                .and_then(|style| regexes::BACKGROUND_IMAGE_REGEX.captures(style))
+                .map(|captures| captures[1].trim().to_string())
+                .filter(|url| !(url.is_empty() || url.starts_with("data:")));
+            let bg_url = match bg_url {
+                Some(bg_url) => bg_url,
+                None => continue,
+            };
+            let node = elem.as_node();
+            if node.select_first("img").is_ok() {
+                continue;
+            }
+            let img = NodeRef::new_element(
+                QualName::new(None, Namespace::from(HTML_NS), LocalName::from("img")),
+                BTreeMap::new(),
+            );
+            img.as_element()
+                .unwrap()
+                .attributes
+                .borrow_mut()
+                .insert("src", bg_url);
+            node.prepend(img);
+        }
+    }
+
     fn remove_scripts(&mut self) {
         match self.root_node.select("script") {
             Ok(mut script_elems) => {
@@ -403,7 +662,7 @@ impl Readability {
             || match node_ref.as_element() {
                 Some(elem) => {
                     let name: &str = &elem.name.local;
-                    PHRASING_ELEMS.contains(&name)
+                    PHRASING_ELEMS.contains(name)
                         || ((name == "a" || name == "del" || name == "ins")
                             && node_ref
                                 .children()
@@ -511,6 +770,13 @@ impl Readability {
             .get("og:site_name")
             .map(|site_name| site_name.to_owned());
 
+        meta_data.published = self
+            .get_meta_published_date()
+            .or_else(|| self.get_visible_published_date());
+        meta_data.keywords = self.get_article_keywords();
+        meta_data.image = self.get_article_image();
+        meta_data.site_icon = self.get_site_icon();
+
         Self::unescape_html_entities(&mut meta_data.title);
         if meta_data.byline.is_some() {
             Self::unescape_html_entities(&mut meta_data.byline.as_mut().unwrap());
@@ -551,14 +817,210 @@ impl Readability {
                     } else {
                         unreachable!("Unable to match any of the captures");
                     };
-                    String::from_utf16_lossy(&[num.unwrap()])
+                    // References that aren't valid numbers like &#xzz; are left as they are
+                    match num {
+                        Ok(num) => String::from_utf16_lossy(&[num]),
+                        Err(_) => captures[0].to_string(),
+                    }
                 })
                 .to_string();
             *value = new_value;
         }
     }
 
-    /// Get the article title as an H1.
+    /// Returns the publication date in the article's metadata normalized to ISO-8601
+    fn get_meta_published_date(&self) -> Option<String> {
+        const PUBLISHED_KEYS: [&str; 13] = [
+            "article:published_time",
+            "og:article:published_time",
+            "datepublished",
+            "dc.date.issued",
+            "dcterms.issued",
+            "dc.date",
+            "dcterms.created",
+            "date",
+            "pubdate",
+            "publishdate",
+            "publish-date",
+            "parsely-pub-date",
+            "sailthru.date",
+        ];
+        let mut dates: HashMap<String, String> = HashMap::new();
+        if let Ok(meta_elems) = self.root_node.select("meta[content]") {
+            for meta_elem in meta_elems {
+                let attrs = meta_elem.attributes.borrow();
+                let key = match attrs
+                    .get("property")
+                    .or_else(|| attrs.get("name"))
+                    .or_else(|| attrs.get("itemprop"))
+                {
+                    Some(key) => key.trim().to_lowercase(),
+                    None => continue,
+                };
+                if PUBLISHED_KEYS.contains(&key.as_str()) && !dates.contains_key(&key) {
+                    if let Some(date) = dates::normalize_date(attrs.get("content").unwrap()) {
+                        dates.insert(key, date);
+                    }
+                }
+            }
+        }
+        PUBLISHED_KEYS.iter().find_map(|key| dates.remove(*key))
+    }
+
+    /// Looks for the publication date in the visible content of the page. A `<time>` marked
+    /// as the publication date is preferred over the first `<time>` on the page and over short
+    /// elements whose class or id suggests they hold a date.
+    fn get_visible_published_date(&self) -> Option<String> {
+        let date_of = |node_ref: &NodeDataRef<ElementData>| {
+            let attrs = node_ref.attributes.borrow();
+            attrs
+                .get("datetime")
+                .or_else(|| attrs.get("content"))
+                .and_then(dates::normalize_date)
+                .or_else(|| dates::find_date_in_text(&node_ref.text_contents()))
+        };
+        let find_selected = |selector: &str| {
+            self.root_node
+                .select(selector)
+                .ok()?
+                .find_map(|node_ref| date_of(&node_ref))
+        };
+        find_selected("time[pubdate], [itemprop~=datePublished]")
+            .or_else(|| find_selected("time[datetime]"))
+            .or_else(|| {
+                self.root_node
+                    .descendants()
+                    .elements()
+                    .find_map(|node_ref| {
+                        let match_string = {
+                            let attrs = node_ref.attributes.borrow();
+                            attrs.get("class").unwrap_or("").to_string()
+                                + " "
+                                + attrs.get("id").unwrap_or("")
+                        };
+                        if !regexes::is_match_date(&match_string) {
+                            return None;
+                        }
+                        let text = node_ref.text_contents();
+                        if text.trim().len() < 100 {
+                            dates::find_date_in_text(&text)
+                        } else {
+                            None
+                        }
+                    })
+            })
+    }
+
+    /// Uses the byline found in the content of the article when its metadata has none. A date
+    /// in the byline such as "By John Doe · May 3, 2023" is used as the publication date.
+    fn apply_visible_byline(&mut self) {
+        if let Some(byline) = &self.byline {
+            if self.metadata.published.is_none() {
+                self.metadata.published = dates::find_date_in_text(byline);
+            }
+            if self.metadata.byline.is_none() {
+                self.metadata.byline = Some(dates::clean_byline(byline));
+            }
+        }
+    }
+
+    /// Returns the keywords and tags in the article's metadata without duplicates
+    fn get_article_keywords(&self) -> Vec<String> {
+        const KEYWORD_KEYS: [&str; 5] = [
+            "keywords",
+            "news_keywords",
+            "article:tag",
+            "og:article:tag",
+            "parsely-tags",
+        ];
+        let mut keywords: Vec<String> = Vec::new();
+        if let Ok(meta_elems) = self.root_node.select("meta[content]") {
+            for meta_elem in meta_elems {
+                let attrs = meta_elem.attributes.borrow();
+                let key = match attrs
+                    .get("property")
+                    .or_else(|| attrs.get("name"))
+                    .or_else(|| attrs.get("itemprop"))
+                {
+                    Some(key) => key.trim().to_lowercase(),
+                    None => continue,
+                };
+                if !KEYWORD_KEYS.contains(&key.as_str()) {
+                    continue;
+                }
+                // An article:tag holds a single tag while the others are comma separated lists
+                let values = if key.ends_with(":tag") {
+                    vec![attrs.get("content").unwrap()]
+                } else {
+                    attrs.get("content").unwrap().split(',').collect()
+                };
+                for value in values {
+                    let mut keyword = value.split_whitespace().collect::<Vec<_>>().join(" ");
+                    Self::unescape_html_entities(&mut keyword);
+                    if !keyword.is_empty()
+                        && !keywords
+                            .iter()
+                            .any(|existing| existing.to_lowercase() == keyword.to_lowercase())
+                    {
+                        keywords.push(keyword);
+                    }
+                }
+            }
+        }
+        keywords
+    }
+
+    /// Returns the url of the image in the article's metadata used when it is shared
+    fn get_article_image(&self) -> Option<String> {
+        const IMAGE_KEYS: [&str; 4] = [
+            "og:image",
+            "og:image:url",
+            "og:image:secure_url",
+            "twitter:image",
+        ];
+        let mut images: HashMap<String, String> = HashMap::new();
+        if let Ok(meta_elems) = self.root_node.select("meta[content]") {
+            for meta_elem in meta_elems {
+                let attrs = meta_elem.attributes.borrow();
+                let key = match attrs.get("property").or_else(|| attrs.get("name")) {
+                    Some(key) => key.trim().to_lowercase(),
+                    None => continue,
+                };
+                let content = attrs.get("content").unwrap().trim();
+                if IMAGE_KEYS.contains(&key.as_str()) && !content.is_empty() {
+                    images.entry(key).or_insert_with(|| content.to_string());
+                }
+            }
+        }
+        IMAGE_KEYS.iter().find_map(|key| images.remove(*key))
+    }
+
+    /// Returns the url of the site's icon preferring the larger icons made for touch screens
+    fn get_site_icon(&self) -> Option<String> {
+        let links = self
+            .root_node
+            .select("link[rel][href]")
+            .ok()?
+            .filter_map(|link| {
+                let attrs = link.attributes.borrow();
+                let rel = attrs.get("rel").unwrap().to_lowercase();
+                let href = attrs.get("href").unwrap().trim().to_string();
+                if href.is_empty() {
+                    None
+                } else {
+                    Some((rel, href))
+                }
+            })
+            .collect::<Vec<_>>();
+        let find_icon = |icon_rel: &str| {
+            links
+                .iter()
+                .find(|(rel, _)| rel.split_whitespace().any(|rel| rel == icon_rel))
+                .map(|(_, href)| href.to_owned())
+        };
+        find_icon("apple-touch-icon").or_else(|| find_icon("icon"))
+    }
+
     fn get_article_title(&self) -> String {
         let mut cur_title = self
             .root_node
@@ -644,42 +1106,34 @@ impl Readability {
     }
 
     ///  Converts each <a> and <img> uri in the given element to an absolute URI, ignoring #ref URIs.
-    fn fix_relative_uris(&mut self, document_uri: &str) {
+    fn fix_relative_uris(&mut self, document_uri: &str) -> Result<(), PaperoniError> {
         if let Some(article_node) = &mut self.article_node {
-            let document_uri =
-                Url::parse(document_uri).expect("Unable to parse the document's URI");
+            let document_uri = Url::parse(document_uri).map_err(|e| {
+                ErrorKind::ReadabilityError(format!(
+                    "Unable to parse the document's URI {:?}: {}",
+                    document_uri, e
+                ))
+            })?;
+            // A <base> with an invalid href is ignored like browsers do
             let base_uri = self
                 .root_node
-                .select("base")
+                .select("base[href]")
                 .unwrap()
-                .filter(|node_ref| {
-                    let node_attrs = node_ref.attributes.borrow();
-                    node_attrs.contains("href")
-                })
-                .map(|node_ref| {
+                .next()
+                .and_then(|node_ref| {
                     let node_attrs = node_ref.attributes.borrow();
                     let href = node_attrs.get("href").unwrap();
-
                     match Url::parse(href) {
-                        Ok(url) => url,
-                        Err(e) => match e {
-                            url::ParseError::RelativeUrlWithoutBase => {
-                                match document_uri.join(href) {
-                                    Ok(joined_url) => joined_url,
-                                    Err(e) => panic!(
-                                        "{:} unable to parse url {:?} on element {}",
-                                        e, href, &node_ref.name.local
-                                    ),
-                                }
-                            }
-                            e => panic!(
-                                "{:} unable to parse url {:?} on element {}",
-                                e, href, &node_ref.name.local
-                            ),
-                        },
+                        Ok(url) => Some(url),
+                        Err(url::ParseError::RelativeUrlWithoutBase) => {
+                            document_uri.join(href).ok()
+                        }
+                        Err(e) => {
+                            debug!("{} unable to parse url {:?} on <base>", e, href);
+                            None
+                        }
                     }
                 })
-                .next()
                 .unwrap_or(document_uri.clone());
             let to_absolute_uri = |uri_str: &str| -> String {
                 if base_uri == document_uri && uri_str.starts_with("#") {
@@ -760,6 +1214,7 @@ impl Readability {
                 }
             }
         }
+        Ok(())
     }
 
     /// Removes readability attributes from DOM nodes as they are not needed in the final article
@@ -774,11 +1229,12 @@ impl Readability {
     }
 
     /// Run any post-process modifications to article content as necessary.
-    fn post_process_content(&mut self, url: &str) {
-        self.fix_relative_uris(url);
+    fn post_process_content(&mut self, url: &str) -> Result<(), PaperoniError> {
+        self.fix_relative_uris(url)?;
         // TODO: Add flag check
         self.clean_classes();
         self.clean_readability_attrs();
+        Ok(())
     }
 
     /// Converts an inline CSS string to a [HashMap] of property and value(s)
@@ -979,9 +1435,7 @@ impl Readability {
         node_ref.as_element().is_some()
             && node_ref.text_contents().trim().is_empty()
             && (child_count == 0
-                || child_count
-                    == node_ref.select("br").unwrap().count()
-                        + node_ref.select("hr").unwrap().count())
+                || child_count == select_compiled(node_ref, &BR_HR_SELECTOR).count())
     }
 
     /// Check if this node has only whitespace and a single element with given tag
@@ -1017,9 +1471,7 @@ impl Readability {
         if text_length == 0_f32 {
             return 0_f32;
         }
-        node_ref
-            .select("a")
-            .unwrap()
+        select_compiled(node_ref, &LINK_SELECTOR)
             .map(|a_node| Self::get_inner_text(a_node.as_node(), None).len() as f32)
             .sum::<f32>()
             / text_length
@@ -1027,45 +1479,10 @@ impl Readability {
 
     /// Determine whether element has any children block level elements.
     fn has_child_block_element(node_ref: &NodeRef) -> bool {
-        // TODO: Refer to a static HashSet
-        let block_level_elems: [&str; 32] = [
-            "address",
-            "article",
-            "aside",
-            "blockquote",
-            "details",
-            "dialog",
-            "dd",
-            "div",
-            "dl",
-            "dt",
-            "fieldset",
-            "figcaption",
-            "footer",
-            "form",
-            "h1",
-            "h2",
-            "h3",
-            "h4",
-            "h5",
-            "h6",
-            "header",
-            "hgroup",
-            "hr",
-            "li",
-            "main",
-            "nav",
-            "ol",
-            "p",
-            "pre",
-            "section",
-            "table",
-            "ul",
-        ];
         node_ref.children().any(|child_node| {
             if child_node.as_element().is_some() {
                 let child_elem = child_node.as_element().unwrap();
-                block_level_elems.contains(&&*child_elem.name.local)
+                BLOCK_LEVEL_ELEMS.contains(&*child_elem.name.local)
                     || Self::has_child_block_element(&child_node)
             } else {
                 false
@@ -1091,7 +1508,7 @@ impl Readability {
             if !id.trim().is_empty() {
                 weight = if regexes::is_match_positive(id) {
                     weight + 25
-                } else if regexes::is_match_negative(id) {
+                } else if self.pattern_overrides.is_match_negative(id) {
                     weight - 25
                 } else {
                     weight
@@ -1102,7 +1519,7 @@ impl Readability {
             if !class.trim().is_empty() {
                 weight = if regexes::is_match_positive(class) {
                     weight + 25
-                } else if regexes::is_match_negative(class) {
+                } else if self.pattern_overrides.is_match_negative(class) {
                     weight - 25
                 } else {
                     weight
@@ -1112,17 +1529,11 @@ impl Readability {
         weight
     }
 
-    /// Initialize a node with the readability attribute. Also checks the
+    /// Initialize a node with a content score. Also checks the
     /// className/id for special names to add to its score.
-    fn initialize_node(&self, node_ref: &mut NodeRef) {
+    fn initialize_node(&mut self, node_ref: &mut NodeRef) {
         if let Some(element) = node_ref.as_element() {
-            let mut score = 0.0;
-            // This must be computed first because it borrows the NodeRef which
-            // should not also be mutably borrowed
-            score += self.get_class_weight(node_ref) as f32;
-            let mut elem_attrs = element.attributes.borrow_mut();
-            elem_attrs.insert(READABILITY_SCORE, score.to_string());
-            let readability = elem_attrs.get_mut(READABILITY_SCORE);
+            let mut score = self.get_class_weight(node_ref) as f32;
             match &*element.name.local {
                 "div" => score += 5.0,
                 "pre" | "td" | "blockquote" => score += 3.0,
@@ -1130,43 +1541,51 @@ impl Readability {
                 "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => score -= 5.0,
                 _ => (),
             }
-            if let Some(x) = readability {
-                *x = score.to_string();
-            }
+            self.set_content_score(node_ref, score);
         }
     }
 
+    /// Returns the content score of a node if it has been initialized
+    fn content_score(&self, node_ref: &NodeRef) -> Option<f32> {
+        self.content_scores.get(&NodeKey(node_ref.clone())).copied()
+    }
+
+    fn set_content_score(&mut self, node_ref: &NodeRef, score: f32) {
+        self.content_scores.insert(NodeKey(node_ref.clone()), score);
+    }
+
+    /// Parses a rowspan or colspan value. Missing, malformed and zero spans count as 1 and huge
+    /// ones are capped like browsers do.
+    fn parse_span(value: &str) -> Option<usize> {
+        value
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|span| *span > 0)
+            .map(|span| span.min(MAX_SPAN))
+    }
+
     fn get_row_and_column_count(node_ref: &NodeRef) -> SizeInfo {
         let mut rows = 0;
         let mut columns = 0;
-        if let Ok(trs) = node_ref.select("tr") {
-            for tr in trs {
-                let tr_node = tr.as_node();
-                let tr_attr = tr.attributes.borrow();
-                let rowspan = tr_attr
-                    .get("rowspan")
-                    .map(|x| {
-                        x.parse::<usize>()
-                            .expect("Unable to parse rowspan value to usize")
-                    })
+        for tr in select_compiled(node_ref, &TR_SELECTOR) {
+            let tr_node = tr.as_node();
+            let tr_attr = tr.attributes.borrow();
+            let rowspan = tr_attr
+                .get("rowspan")
+                .and_then(Self::parse_span)
+                .unwrap_or(1);
+            rows += rowspan;
+            let mut columns_in_row = 0;
+            for cell in select_compiled(tr_node, &TD_SELECTOR) {
+                let cell_attr = cell.attributes.borrow();
+                let colspan = cell_attr
+                    .get("colspan")
+                    .and_then(Self::parse_span)
                     .unwrap_or(1);
-                rows += rowspan;
-                let mut columns_in_row = 0;
-                if let Ok(cells) = tr_node.select("td") {
-                    for cell in cells {
-                        let cell_attr = cell.attributes.borrow();
-                        let colspan = cell_attr
-                            .get("colspan")
-                            .map(|x| {
-                                x.parse::<usize>()
-                                    .expect("Unable to parse colspan value to usize")
-                            })
-                            .unwrap_or(1);
-                        columns_in_row += colspan;
-                    }
-                }
-                columns = columns.max(columns_in_row);
+                columns_in_row += colspan;
             }
+            columns = columns.max(columns_in_row);
         }
         SizeInfo { rows, columns }
     }
@@ -1198,9 +1617,9 @@ impl Readability {
                     }
                 }
 
-                if DATA_TABLE_DESCENDANTS
-                    .iter()
-                    .any(|tag_name| table_node.select_first(tag_name).is_ok())
+                if select_compiled(table_node, &DATA_TABLE_DESCENDANTS_SELECTOR)
+                    .next()
+                    .is_some()
                 {
                     table_attr.insert("readability-data-table", "true".to_string());
                     continue;
@@ -1321,7 +1740,11 @@ impl Readability {
             let attrs = node_elem.attributes.borrow();
             attrs.get("readability-data-table") == Some("true")
         };
-        let get_char_count = |node_ref: &NodeRef| node_ref.text_contents().matches(",").count();
+        let get_char_count = |node_ref: &NodeRef| {
+            regexes::COMMAS_REGEX
+                .find_iter(&node_ref.text_contents())
+                .count()
+        };
 
         let mut nodes = node_ref
             .descendants()
@@ -1354,10 +1777,7 @@ impl Readability {
             if get_char_count(node) >= 10 {
                 continue;
             }
-            let mut embeds = node_data_ref
-                .as_node()
-                .select("object, embed, iframe")
-                .unwrap();
+            let mut embeds = select_compiled(node_data_ref.as_node(), &EMBED_SELECTOR);
             let can_skip_embed = embeds.any(|node_data_ref| {
                 &node_data_ref.name.local == "object" || {
                     let attrs = node_data_ref.attributes.borrow();
@@ -1372,15 +1792,16 @@ impl Readability {
                 continue;
             }
 
-            let p_nodes = node_data_ref.as_node().select("p").unwrap().count();
-            let img_nodes = node_data_ref.as_node().select("img").unwrap().count();
-            let li_nodes = node_data_ref.as_node().select("li").unwrap().count() as i32 - 100;
-            let input_nodes = node_data_ref.as_node().select("input").unwrap().count();
+            let p_nodes = select_compiled(node_data_ref.as_node(), &P_SELECTOR).count();
+            let img_nodes = select_compiled(node_data_ref.as_node(), &IMG_SELECTOR).count();
+            let li_nodes =
+                select_compiled(node_data_ref.as_node(), &LI_SELECTOR).count() as i32 - 100;
+            let input_nodes = select_compiled(node_data_ref.as_node(), &INPUT_SELECTOR).count();
 
             let p = p_nodes as f32;
             let img = img_nodes as f32;
 
-            let embed_count = node.select("object, embed, iframe").unwrap().count();
+            let embed_count = select_compiled(node, &EMBED_SELECTOR).count();
             let link_density = Self::get_link_density(node);
             let content_length = Self::get_inner_text(node, None).len();
             let has_figure_ancestor = Self::has_ancestor_tag(node, "figure", None, None);
@@ -1452,7 +1873,7 @@ impl Readability {
                 PRESENTATIONAL_ATTRIBUTES.iter().for_each(|pres_attr| {
                     attrs.remove(*pres_attr);
                 });
-                if DEPRECATED_SIZE_ATTRIBUTE_ELEMS.contains(&node_data_ref.name.local.as_ref()) {
+                if DEPRECATED_SIZE_ATTRIBUTE_ELEMS.contains(&*node_data_ref.name.local) {
                     attrs.remove("width");
                     attrs.remove("height");
                 }
@@ -1529,12 +1950,8 @@ impl Readability {
 
         let mut p_nodes = node_ref.select("p").unwrap().filter(|node_data_ref| {
             let p_node = node_data_ref.as_node();
-            let img_count = p_node.select("img").unwrap().count();
-            let embed_count = p_node.select("embed").unwrap().count();
-            let object_count = p_node.select("object").unwrap().count();
-            let iframe_count = p_node.select("iframe").unwrap().count();
-            let total = img_count + embed_count + object_count + iframe_count;
-            total == 0 && Self::get_inner_text(node_data_ref.as_node(), Some(false)).is_empty()
+            let media_count = select_compiled(p_node, &MEDIA_SELECTOR).count();
+            media_count == 0 && Self::get_inner_text(p_node, Some(false)).is_empty()
         });
         let mut p_node = p_nodes.next();
         while let Some(p_node_ref) = p_node {
@@ -1590,6 +2007,14 @@ impl Readability {
         }
     }
 
+    /// Checks whether a walk up from the top candidate should stop at this node. Malformed pages
+    /// can have a top candidate outside of the <body> so the walk also stops at the document.
+    fn is_body_or_root(node_ref: &NodeRef) -> bool {
+        node_ref
+            .as_element()
+            .map_or(true, |elem| &elem.name.local == "body")
+    }
+
     fn flag_is_active(&self, flag: u32) -> bool {
         self.flags & flag > 0
     }
@@ -1610,12 +2035,37 @@ impl Readability {
             return Err(ErrorKind::ReadabilityError("Document has no <body>".into()).into());
         }
         let page = page.unwrap();
+        let node_count = self.root_node.descendants().count();
+        if node_count > self.budget.max_nodes {
+            return Err(ErrorKind::ReadabilityTimeout(format!(
+                "The page has {} nodes which is more than the limit of {}",
+                node_count, self.budget.max_nodes
+            ))
+            .into());
+        }
+        let start = Instant::now();
+        let max_duration = self.budget.max_duration;
+        let check_deadline = || -> Result<(), PaperoniError> {
+            if start.elapsed() > max_duration {
+                Err(ErrorKind::ReadabilityTimeout(format!(
+                    "Grabbing the article took longer than {} seconds",
+                    max_duration.as_secs_f32()
+                ))
+                .into())
+            } else {
+                Ok(())
+            }
+        };
         let mut attempts: Vec<ExtractAttempt> = Vec::new();
 
         // var pageCacheHtml = page.innerHTML;
         //TODO: Add page cache
 
         loop {
+            check_deadline()?;
+            // Each attempt scores the nodes from scratch. Scores left from an earlier attempt
+            // would be added to, which favours the candidates that attempt picked.
+            self.content_scores.clear();
             //   var stripUnlikelyCandidates = this._flagIsActive(this.FLAG_STRIP_UNLIKELYS);
             let strip_unlikely_candidates = self.flag_is_active(FLAG_STRIP_UNLIKELYS);
 
@@ -1629,7 +2079,12 @@ impl Readability {
                 .ok()
                 .map(|n| n.as_node().clone());
 
+            let mut visited_count = 0;
             while let Some(node_ref) = node {
+                visited_count += 1;
+                if visited_count % DEADLINE_CHECK_INTERVAL == 0 {
+                    check_deadline()?;
+                }
                 let node_elem = node_ref.as_element().unwrap();
                 let node_name: &str = node_elem.name.local.as_ref();
                 let match_string = {
@@ -1649,7 +2104,7 @@ impl Readability {
                 }
 
                 if strip_unlikely_candidates {
-                    if regexes::is_match_unlikely(&match_string)
+                    if self.pattern_overrides.is_match_unlikely(&match_string)
                         && !regexes::is_match_ok_maybe(&match_string)
                         && !Self::has_ancestor_tag(&node_ref, "table", None, None)
                         && node_name != "body"
@@ -1678,7 +2133,7 @@ impl Readability {
                     }
                     _ => (),
                 }
-                if DEFAULT_TAGS_TO_SCORE.contains(&node_name) {
+                if DEFAULT_TAGS_TO_SCORE.contains(node_name) {
                     elements_to_score.push(node_ref.clone());
                 }
                 if node_name == "div" {
@@ -1750,7 +2205,7 @@ impl Readability {
                 .for_each(|(inner_text, ancestors)| {
                     let mut content_score = 0;
                     content_score += 1;
-                    content_score += inner_text.split(",").count();
+                    content_score += regexes::COMMAS_REGEX.split(&inner_text).count();
                     content_score += (3).min(inner_text.len() / 100);
                     ancestors
                         .into_iter()
@@ -1759,12 +2214,7 @@ impl Readability {
                             node.parent().is_some() && node.parent().unwrap().as_element().is_some()
                         })
                         .for_each(|(level, mut ancestor)| {
-                            let has_readability = {
-                                let ancestor_attrs =
-                                    ancestor.as_element().unwrap().attributes.borrow();
-                                ancestor_attrs.contains(READABILITY_SCORE)
-                            };
-                            if !has_readability {
+                            if self.content_score(&ancestor).is_none() {
                                 self.initialize_node(&mut ancestor);
                                 candidates.push(ancestor.clone());
                             }
@@ -1776,14 +2226,10 @@ impl Readability {
                             } else {
                                 level as f32 * 3.0
                             };
-                            let mut ancestor_attrs =
-                                ancestor.as_element().unwrap().attributes.borrow_mut();
-                            if let Some(readability_score) =
-                                ancestor_attrs.get_mut(READABILITY_SCORE)
+                            if let Some(score) =
+                                self.content_scores.get_mut(&NodeKey(ancestor.clone()))
                             {
-                                *readability_score = (readability_score.parse::<f32>().unwrap()
-                                    + (content_score as f32 / score_divider))
-                                    .to_string();
+                                *score += content_score as f32 / score_divider;
                             }
                         });
                 });
@@ -1791,28 +2237,15 @@ impl Readability {
             let mut top_candidates: Vec<NodeRef> = Vec::new();
             for candidate in candidates {
                 let mut candidate_score = 0.0;
-                {
-                    let mut candidate_attr =
-                        candidate.as_element().unwrap().attributes.borrow_mut();
-                    if let Some(readability_score) = candidate_attr.get_mut(READABILITY_SCORE) {
-                        candidate_score = readability_score.parse::<f32>().unwrap()
-                            * (1.0 - Self::get_link_density(&candidate));
-                        *readability_score = candidate_score.to_string();
-                    }
+                if let Some(score) = self.content_score(&candidate) {
+                    candidate_score = score * (1.0 - Self::get_link_density(&candidate));
+                    self.set_content_score(&candidate, candidate_score);
                 }
                 let nb_top_candidates = 5;
                 for i in 0..nb_top_candidates {
                     let top_candidate = top_candidates.get(i);
-                    let top_candidate_score = top_candidate
-                        .as_ref()
-                        .map(|node_ref| node_ref.as_element().unwrap().attributes.borrow())
-                        .map(|attrs| {
-                            attrs
-                                .get(READABILITY_SCORE)
-                                .unwrap_or("0")
-                                .parse::<f32>()
-                                .unwrap()
-                        });
+                    let top_candidate_score =
+                        top_candidate.map(|node_ref| self.content_score(node_ref).unwrap_or(0.0));
                     if top_candidate.is_none() || candidate_score > top_candidate_score.unwrap() {
                         top_candidates.splice(i..i, vec![candidate].into_iter());
                         if top_candidates.len() > nb_top_candidates {
@@ -1851,29 +2284,13 @@ impl Readability {
             } else {
                 let alternative_candidate_ancestors: Vec<Vec<NodeRef>>;
                 top_candidate = top_candidates.get(0).unwrap().clone();
-                let top_candidate_score = {
-                    let top_candidate_node_attrs =
-                        top_candidate.as_element().unwrap().attributes.borrow();
-                    top_candidate_node_attrs
-                        .get(READABILITY_SCORE)
-                        .unwrap()
-                        .parse::<f32>()
-                        .unwrap()
-                };
+                let top_candidate_score = self.content_score(&top_candidate).unwrap();
 
                 alternative_candidate_ancestors = top_candidates
                     .iter()
                     .skip(1)
                     .filter(|top_candidate_node| {
-                        let candidate_node_score = {
-                            let top_candidate_node_attrs =
-                                top_candidate_node.as_element().unwrap().attributes.borrow();
-                            top_candidate_node_attrs
-                                .get(READABILITY_SCORE)
-                                .unwrap()
-                                .parse::<f32>()
-                                .unwrap()
-                        };
+                        let candidate_node_score = self.content_score(top_candidate_node).unwrap();
                         (candidate_node_score / top_candidate_score) >= 0.75
                     })
                     .map(|node| Self::get_node_ancestors(&node, None))
@@ -1882,7 +2299,7 @@ impl Readability {
                 let minimum_top_candidates = 3;
                 if alternative_candidate_ancestors.len() >= minimum_top_candidates {
                     parent_of_top_candidate = top_candidate.parent().unwrap();
-                    while &parent_of_top_candidate.as_element().unwrap().name.local != "body" {
+                    while !Self::is_body_or_root(&parent_of_top_candidate) {
                         let mut lists_containing_this_ancestor = alternative_candidate_ancestors
                             .iter()
                             .filter(|node_vec| node_vec.contains(&parent_of_top_candidate))
@@ -1897,45 +2314,15 @@ impl Readability {
                     }
                 }
 
-                let top_candidate_readability = {
-                    let top_candidate_attrs =
-                        top_candidate.as_element().unwrap().attributes.borrow();
-                    top_candidate_attrs
-                        .get(READABILITY_SCORE)
-                        .map(|x| x.to_owned())
-                };
-
-                if top_candidate_readability.is_none() {
+                if self.content_score(&top_candidate).is_none() {
                     self.initialize_node(&mut top_candidate);
                 }
                 parent_of_top_candidate = top_candidate.parent().unwrap();
 
-                let mut last_score = {
-                    let top_candidate_node_attrs =
-                        top_candidate.as_element().unwrap().attributes.borrow();
-                    top_candidate_node_attrs
-                        .get(READABILITY_SCORE)
-                        .unwrap()
-                        .parse::<f32>()
-                        .unwrap()
-                };
+                let mut last_score = self.content_score(&top_candidate).unwrap();
                 let score_threshold = last_score / 3.0;
-                while parent_of_top_candidate
-                    .as_element()
-                    .map(|elem| elem.name.local.as_ref())
-                    .unwrap()
-                    != "body"
-                {
-                    let parent_readability = {
-                        let parent_attrs = parent_of_top_candidate
-                            .as_element()
-                            .unwrap()
-                            .attributes
-                            .borrow();
-                        parent_attrs
-                            .get(READABILITY_SCORE)
-                            .map(|score| score.parse::<f32>().unwrap())
-                    };
+                while !Self::is_body_or_root(&parent_of_top_candidate) {
+                    let parent_readability = self.content_score(&parent_of_top_candidate);
                     if parent_readability.is_none() {
                         parent_of_top_candidate = parent_of_top_candidate.parent().unwrap();
                         continue;
@@ -1952,20 +2339,13 @@ impl Readability {
                 }
 
                 parent_of_top_candidate = top_candidate.parent().unwrap();
-                while &parent_of_top_candidate.as_element().unwrap().name.local != "body"
+                while !Self::is_body_or_root(&parent_of_top_candidate)
                     && parent_of_top_candidate.children().count() == 1
                 {
                     top_candidate = parent_of_top_candidate;
                     parent_of_top_candidate = top_candidate.parent().unwrap();
                 }
-                let top_candidate_readability = {
-                    let top_candidate_attrs =
-                        top_candidate.as_element().unwrap().attributes.borrow();
-                    top_candidate_attrs
-                        .get(READABILITY_SCORE)
-                        .map(|score| score.to_string())
-                };
-                if top_candidate_readability.is_none() {
+                if self.content_score(&top_candidate).is_none() {
                     self.initialize_node(&mut top_candidate);
                 }
             }
@@ -1973,13 +2353,7 @@ impl Readability {
                 QualName::new(None, Namespace::from(HTML_NS), LocalName::from("div")),
                 BTreeMap::new(),
             );
-            let top_candidate_score = {
-                let top_candidate_attrs = top_candidate.as_element().unwrap().attributes.borrow();
-                top_candidate_attrs
-                    .get(READABILITY_SCORE)
-                    .map(|score| score.parse::<f32>().unwrap())
-                    .unwrap()
-            };
+            let top_candidate_score = self.content_score(&top_candidate).unwrap();
 
             let sibling_score_threshold = (10.0_f32).max(top_candidate_score * 0.2);
             parent_of_top_candidate = top_candidate.parent().unwrap();
@@ -1988,17 +2362,12 @@ impl Readability {
                 .children()
                 .filter(|node| node.as_element().is_some());
 
-            let (top_candidate_class, top_candidate_score) = {
+            let top_candidate_class = {
                 let top_candidate_attrs = top_candidate.as_element().unwrap().attributes.borrow();
-                let class = top_candidate_attrs
+                top_candidate_attrs
                     .get("class")
                     .map(|class| class.to_string())
-                    .unwrap_or("".to_string());
-                let score = top_candidate_attrs
-                    .get(READABILITY_SCORE)
-                    .map(|score| score.parse::<f32>().unwrap())
-                    .unwrap();
-                (class, score)
+                    .unwrap_or("".to_string())
             };
             let mut next_sibling = siblings.next();
             while let Some(sibling) = next_sibling {
@@ -2014,9 +2383,7 @@ impl Readability {
                         .get("class")
                         .map(|class| class.to_string())
                         .unwrap_or("".to_string());
-                    let sibling_score = sibling_attrs
-                        .get(READABILITY_SCORE)
-                        .map(|score| score.parse::<f32>().unwrap());
+                    let sibling_score = self.content_score(&sibling);
 
                     if sibling_class == top_candidate_class && !top_candidate_class.is_empty() {
                         content_bonus += top_candidate_score * 0.2;
@@ -2043,12 +2410,9 @@ impl Readability {
                     }
                 }
                 if append {
-                    let new_article_child = if !ALTER_TO_DIV_EXCEPTIONS.contains(
-                        &sibling
-                            .as_element()
-                            .map(|elem| elem.name.local.as_ref())
-                            .unwrap(),
-                    ) {
+                    let new_article_child = if !ALTER_TO_DIV_EXCEPTIONS
+                        .contains(&*sibling.as_element().unwrap().name.local)
+                    {
                         Self::set_node_tag(&sibling, "div")
                     } else {
                         sibling
@@ -2078,22 +2442,39 @@ impl Readability {
                 article_content.append(div);
             }
 
-            let text_length = Self::get_inner_text(&article_content, Some(true)).len();
+            let mut text_length = Self::get_inner_text(&article_content, Some(true)).len();
+            let mut top_score = top_candidate_score;
             let mut parse_successful = true;
-            if text_length < DEFAULT_CHAR_THRESHOLD {
+            if text_length < self.budget.min_chars {
                 parse_successful = false;
                 if self.flag_is_active(FLAG_STRIP_UNLIKELYS) {
                     self.remove_flag(FLAG_STRIP_UNLIKELYS);
-                    attempts.push(ExtractAttempt::new(article_content.clone(), text_length));
+                    attempts.push(ExtractAttempt::new(
+                        article_content.clone(),
+                        text_length,
+                        top_candidate_score,
+                    ));
                 } else if self.flag_is_active(FLAG_WEIGHT_CLASSES) {
                     self.remove_flag(FLAG_WEIGHT_CLASSES);
-                    attempts.push(ExtractAttempt::new(article_content.clone(), text_length));
+                    attempts.push(ExtractAttempt::new(
+                        article_content.clone(),
+                        text_length,
+                        top_candidate_score,
+                    ));
                 } else if self.flag_is_active(FLAG_CLEAN_CONDITIONALLY) {
                     self.remove_flag(FLAG_CLEAN_CONDITIONALLY);
-                    attempts.push(ExtractAttempt::new(article_content.clone(), text_length));
+                    attempts.push(ExtractAttempt::new(
+                        article_content.clone(),
+                        text_length,
+                        top_candidate_score,
+                    ));
                 } else {
-                    attempts.push(ExtractAttempt::new(article_content.clone(), text_length));
-                    attempts.sort_by(|a, b| b.length.partial_cmp(&a.length).unwrap());
+                    attempts.push(ExtractAttempt::new(
+                        article_content.clone(),
+                        text_length,
+                        top_candidate_score,
+                    ));
+                    attempts.sort_by(|a, b| b.length.cmp(&a.length));
                     if attempts.first().as_ref().unwrap().length == 0 {
                         return Err(ErrorKind::ReadabilityError(
                             "Unable to extract content".into(),
@@ -2101,6 +2482,8 @@ impl Readability {
                         .into());
                     }
                     article_content = attempts[0].article.clone();
+                    text_length = attempts[0].length;
+                    top_score = attempts[0].top_score;
                     parse_successful = true;
                 }
             }
@@ -2123,6 +2506,11 @@ impl Readability {
                     }
                     false
                 });
+                self.confidence = Some(Confidence {
+                    top_candidate_score: top_score,
+                    link_density: Self::get_link_density(&article_content),
+                    text_length,
+                });
                 self.article_node = Some(article_content);
                 info!("Successfully grabbed article {:?}", self.metadata.title);
                 return Ok(());
@@ -2136,11 +2524,43 @@ impl Readability {
 struct ExtractAttempt {
     article: NodeRef,
     length: usize,
+    top_score: f32,
 }
 
 impl ExtractAttempt {
-    pub fn new(article: NodeRef, length: usize) -> Self {
-        ExtractAttempt { article, length }
+    pub fn new(article: NodeRef, length: usize, top_score: f32) -> Self {
+        ExtractAttempt {
+            article,
+            length,
+            top_score,
+        }
+    }
+}
+
+/// Confidence scores below this one are flagged for the article to be checked
+pub const LOW_CONFIDENCE_SCORE: f32 = 0.5;
+
+/// How sure readability is that it grabbed the article rather than some other part of the
+/// page, from the signals it used to pick it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Confidence {
+    /// The content score of the element the article was grabbed around
+    pub top_candidate_score: f32,
+    /// The share of the text of the article that is inside links
+    pub link_density: f32,
+    /// The characters of text in the article
+    pub text_length: usize,
+}
+
+impl Confidence {
+    /// Combines the signals into a score between 0 and 1. Long articles with few links grabbed
+    /// around a high scoring element get a score close to 1 while short lists of links such
+    /// as index pages get a score close to 0.
+    pub fn score(&self) -> f32 {
+        let length = (self.text_length as f32 / 2500.0).min(1.0);
+        let links = 1.0 - (self.link_density * 2.0).min(1.0);
+        let candidate = (self.top_candidate_score / 50.0).max(0.0).min(1.0);
+        0.4 * length + 0.3 * links + 0.3 * candidate
     }
 }
 
@@ -2148,6 +2568,10 @@ impl ExtractAttempt {
 pub struct MetaData {
     byline: Option<String>,
     excerpt: Option<String>,
+    image: Option<String>,
+    keywords: Vec<String>,
+    published: Option<String>,
+    site_icon: Option<String>,
     site_name: Option<String>,
     title: String,
 }
@@ -2157,6 +2581,10 @@ impl MetaData {
         MetaData {
             byline: None,
             excerpt: None,
+            image: None,
+            keywords: Vec::new(),
+            published: None,
+            site_icon: None,
             site_name: None,
             title: "".into(),
         }
@@ -2169,17 +2597,60 @@ impl MetaData {
     pub fn byline(&self) -> Option<&String> {
         self.byline.as_ref()
     }
+
+    /// The url of the image used when the article is shared e.g its og:image
+    pub fn image(&self) -> Option<&str> {
+        self.image.as_deref()
+    }
+
+    /// The keywords and tags of the article
+    pub fn keywords(&self) -> &[String] {
+        &self.keywords
+    }
+
+    /// The publication date of the article normalized to ISO-8601 e.g `2023-05-03` or
+    /// `2023-05-03T10:15:00+00:00` when the time is known
+    pub fn published(&self) -> Option<&str> {
+        self.published.as_deref()
+    }
+
+    /// The day the article was published on
+    pub fn published_date(&self) -> Option<NaiveDate> {
+        self.published
+            .as_deref()
+            .and_then(dates::parse_normalized_date)
+    }
+
+    /// The url of the icon of the site the article is from
+    pub fn site_icon(&self) -> Option<&str> {
+        self.site_icon.as_deref()
+    }
+
+    pub fn site_name(&self) -> Option<&str> {
+        self.site_name.as_deref()
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+    }
+
+    pub fn set_byline(&mut self, byline: &str) {
+        self.byline = Some(byline.to_string());
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::{
-        MetaData, Readability, SizeInfo, FLAG_CLEAN_CONDITIONALLY, FLAG_STRIP_UNLIKELYS,
-        FLAG_WEIGHT_CLASSES, HTML_NS, READABILITY_SCORE,
+        select_compiled, ExtractionBudget, MetaData, Readability, SizeInfo, BLOCK_LEVEL_ELEMS,
+        FLAG_CLEAN_CONDITIONALLY, FLAG_STRIP_UNLIKELYS, FLAG_WEIGHT_CLASSES, HTML_NS,
+        LINK_SELECTOR, READABILITY_SCORE,
     };
+    use crate::errors::ErrorKind;
     use html5ever::{LocalName, Namespace, QualName};
     use kuchiki::traits::*;
     use kuchiki::NodeRef;
+    use std::time::Duration;
 
     // TODO: Refactor not to use test file possibly
     const TEST_HTML: &'static str = include_str!("../../test_html/simple.html");
@@ -2240,6 +2711,50 @@ mod test {
         assert_eq!(0, noscript_elems.count());
     }
 
+    #[test]
+    fn test_convert_background_images() {
+        let html_str = r#"
+        <html>
+            <body style="background-image: url('page-bg.png')">
+                <div id="hero" style="color: red; background-image: url( 'hero.jpg' )"><h1>Title</h1></div>
+                <div id="short" style="background: #fff url(banner.png) no-repeat"></div>
+                <div id="data" style="background-image: url(data:image/png;base64,iVBORw0KGgo=)"></div>
+                <div id="has-img" style="background-image: url(other.jpg)"><img src="img.jpg"></div>
+                <div id="plain" style="color: blue"></div>
+            </body>
+        </html>
+        "#;
+        let mut readability = Readability::new(html_str);
+        readability.convert_background_images();
+        let img_srcs = readability
+            .root_node
+            .select("img")
+            .unwrap()
+            .map(|img| {
+                let parent = img.as_node().parent().unwrap();
+                let parent_id = parent
+                    .as_element()
+                    .unwrap()
+                    .attributes
+                    .borrow()
+                    .get("id")
+                    .map(ToString::to_string);
+                (
+                    parent_id,
+                    img.attributes.borrow().get("src").unwrap().to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (Some("hero".to_string()), "hero.jpg".to_string()),
+                (Some("short".to_string()), "banner.png".to_string()),
+                (Some("has-img".to_string()), "img.jpg".to_string()),
+            ],
+            img_srcs
+        );
+    }
+
     #[test]
     fn test_next_element() {
         let html_str = r#"
@@ -2963,6 +3478,33 @@ characters. For that reason, this <p> tag could not be a byline because it's too
         assert_eq!(-25, doc.get_class_weight(target.as_node()));
     }
 
+    #[test]
+    fn test_pattern_overrides() {
+        let html_str = r#"
+        <!DOCTYPE html>
+        <html>
+          <body>
+            <div class="sidebar">A story is told...</div>
+            <div id="related-links">Read more</div>
+          </body>
+        </html>
+        "#;
+        let mut doc = Readability::new(html_str);
+        doc.pattern_overrides = PatternOverrides::new(&["^sidebar$".to_owned()], &[]).unwrap();
+        let target = doc.root_node.select_first("div.sidebar").unwrap();
+        assert_eq!(-25, doc.get_class_weight(target.as_node()));
+        assert!(!doc.pattern_overrides.is_match_unlikely("wide sidebar "));
+        assert!(doc.pattern_overrides.is_match_unlikely("related-links"));
+
+        doc.pattern_overrides =
+            PatternOverrides::new(&["^sidebar$".to_owned()], &["Sidebar".to_owned()]).unwrap();
+        assert_eq!(0, doc.get_class_weight(target.as_node()));
+        let target = doc.root_node.select_first("div#related-links").unwrap();
+        assert_eq!(-25, doc.get_class_weight(target.as_node()));
+
+        assert!(PatternOverrides::new(&["(unclosed".to_owned()], &[]).is_err());
+    }
+
     #[test]
     fn test_initialize_node() {
         let html_str = r#"
@@ -2980,36 +3522,38 @@ characters. For that reason, this <p> tag could not be a byline because it's too
           </body>
         </html>
         "#;
-        let doc = Readability::new(html_str);
+        let mut doc = Readability::new(html_str);
         let mut target = doc.root_node.select_first("div#blog").unwrap();
         let mut node = target.as_node().clone();
         doc.initialize_node(&mut node);
-        let node_attrs = node.as_element().unwrap().attributes.borrow();
-        assert_eq!(Some("55"), node_attrs.get(READABILITY_SCORE));
+        assert_eq!(Some(55.0), doc.content_score(&node));
 
         target = doc.root_node.select_first("h1.hidden").unwrap();
         let mut node = target.as_node().clone();
         doc.initialize_node(&mut node);
-        let node_attrs = node.as_element().unwrap().attributes.borrow();
-        assert_eq!(Some("-30"), node_attrs.get(READABILITY_SCORE));
+        assert_eq!(Some(-30.0), doc.content_score(&node));
 
         target = doc.root_node.select_first("p#story").unwrap();
         let mut node = target.as_node().clone();
         doc.initialize_node(&mut node);
-        let node_attrs = node.as_element().unwrap().attributes.borrow();
-        assert_eq!(Some("25"), node_attrs.get(READABILITY_SCORE));
+        assert_eq!(Some(25.0), doc.content_score(&node));
 
         target = doc.root_node.select_first("div#comments").unwrap();
         let mut node = target.as_node().clone();
         doc.initialize_node(&mut node);
-        let node_attrs = node.as_element().unwrap().attributes.borrow();
-        assert_eq!(Some("-20"), node_attrs.get(READABILITY_SCORE));
+        assert_eq!(Some(-20.0), doc.content_score(&node));
 
         target = doc.root_node.select_first("pre.comment").unwrap();
         let mut node = target.as_node().clone();
         doc.initialize_node(&mut node);
-        let node_attrs = node.as_element().unwrap().attributes.borrow();
-        assert_eq!(Some("-22"), node_attrs.get(READABILITY_SCORE));
+        assert_eq!(Some(-22.0), doc.content_score(&node));
+        // The scores are not stored in the attributes of the nodes
+        assert!(!node
+            .as_element()
+            .unwrap()
+            .attributes
+            .borrow()
+            .contains(READABILITY_SCORE));
     }
 
     #[test]
@@ -3052,6 +3596,25 @@ characters. For that reason, this <p> tag could not be a byline because it's too
             },
             Readability::get_row_and_column_count(target.as_node())
         );
+
+        // Malformed spans count as 1 instead of aborting the extraction
+        let html_str = r#"
+        <table>
+          <tr rowspan="two"><td colspan="-1">&nbsp;</td><td colspan="3px">&nbsp;</td></tr>
+          <tr><td colspan="0">&nbsp;</td><td colspan=" 2 ">&nbsp;</td></tr>
+          <tr><td colspan="99999999999999999999999">&nbsp;</td></tr>
+        </table>
+        "#;
+        let doc = Readability::new(html_str);
+        let target = doc.root_node.select_first("table").unwrap();
+        assert_eq!(
+            SizeInfo {
+                rows: 3,
+                columns: 3
+            },
+            Readability::get_row_and_column_count(target.as_node())
+        );
+        assert_eq!(Some(65534), Readability::parse_span("100000"));
     }
 
     #[test]
@@ -3719,6 +4282,9 @@ characters. For that reason, this <p> tag could not be a byline because it's too
         input = "&#x0158;&#x016d;&#x0161;&#x0163;".to_string();
         Readability::unescape_html_entities(&mut input);
         assert_eq!("Řŭšţ", input);
+        input = "&#xzz; &#xd7ff;".to_string();
+        Readability::unescape_html_entities(&mut input);
+        assert_eq!("&#xzz; \u{d7ff}", input);
     }
 
     #[test]
@@ -3768,6 +4334,25 @@ characters. For that reason, this <p> tag could not be a byline because it's too
         result.site_name = Some("Blog Place".to_string());
         result.title = "A Longer Title".to_string();
         assert_eq!(result, doc.get_article_metadata());
+
+        html_str = r#"
+        <!DOCTYPE html>
+        <html>
+            <head>
+                <title>A Title</title>
+                <meta name="twitter:image" content="/twitter.png" />
+                <meta property="og:image" content=" https://example.com/og.png " />
+                <meta property="og:image:width" content="1200" />
+                <link rel="stylesheet" href="/style.css" />
+                <link rel="shortcut icon" href="/favicon.ico" />
+                <link rel="apple-touch-icon" href="/touch-icon.png" />
+            </head>
+        </html>
+        "#;
+        let doc = Readability::new(html_str);
+        let metadata = doc.get_article_metadata();
+        assert_eq!(Some("https://example.com/og.png"), metadata.image());
+        assert_eq!(Some("/touch-icon.png"), metadata.site_icon());
     }
 
     #[test]
@@ -3793,7 +4378,8 @@ characters. For that reason, this <p> tag could not be a byline because it's too
             .select_first("body")
             .ok()
             .map(|node_ref| node_ref.as_node().clone());
-        doc.fix_relative_uris("https://example.image.com/blog/");
+        doc.fix_relative_uris("https://example.image.com/blog/")
+            .unwrap();
 
         let node = doc.root_node.select_first("img#ex-1").unwrap();
         let node_attrs = node.attributes.borrow();
@@ -3935,6 +4521,162 @@ characters. For that reason, this <p> tag could not be a byline because it's too
         );
     }
 
+    #[test]
+    fn test_published_date_and_visible_byline() {
+        let html_str = r#"
+        <html>
+            <body>
+                <article>
+                    <p class="byline">By Jane Doe · May 3rd, 2023</p>
+                    <p>A paragraph about dates which is long enough, with commas, to be scored as
+                    part of the content of the article when its candidates are picked.</p>
+                    <p>Another paragraph about bylines which is long enough, with commas, to be
+                    scored as part of the content of the article when its candidates are picked.</p>
+                </article>
+            </body>
+        </html>
+        "#;
+        let mut doc = Readability::new(html_str);
+        doc.parse("https://example.com/post").unwrap();
+        assert_eq!(Some(&"Jane Doe".to_owned()), doc.metadata.byline());
+        assert_eq!(Some("2023-05-03"), doc.metadata.published());
+
+        // The metadata takes precedence over the visible content
+        let html_str = r#"
+        <html>
+            <head>
+                <meta property="article:published_time" content="2021-11-08T09:30:00Z">
+                <meta name="author" content="John Doe">
+            </head>
+            <body>
+                <time datetime="2020-01-01">New year</time>
+            </body>
+        </html>
+        "#;
+        let doc = Readability::new(html_str);
+        let metadata = doc.get_article_metadata();
+        assert_eq!(Some("2021-11-08T09:30:00+00:00"), metadata.published());
+        assert_eq!(
+            chrono::NaiveDate::from_ymd_opt(2021, 11, 8),
+            metadata.published_date()
+        );
+
+        let html_str = r#"
+        <html>
+            <body>
+                <time datetime="2020-01-01">New year</time>
+                <span class="post-date">Posted on 4 July 2022</span>
+                <time datetime="2022-07-04T08:00:00" pubdate>Independence day</time>
+            </body>
+        </html>
+        "#;
+        let doc = Readability::new(html_str);
+        assert_eq!(
+            Some("2022-07-04T08:00:00"),
+            doc.get_article_metadata().published()
+        );
+        let doc = Readability::new(&html_str.replace("pubdate", ""));
+        assert_eq!(Some("2020-01-01"), doc.get_article_metadata().published());
+    }
+
+    #[test]
+    fn test_get_article_keywords() {
+        let html_str = r#"
+        <html>
+            <head>
+                <meta name="keywords" content="rust, web scraping,, EPUB ">
+                <meta property="article:tag" content="Readability, ports">
+                <meta property="article:tag" content="rust">
+                <meta name="news_keywords" content="Ebooks &amp; readers">
+            </head>
+            <body></body>
+        </html>
+        "#;
+        let doc = Readability::new(html_str);
+        assert_eq!(
+            vec![
+                "rust",
+                "web scraping",
+                "EPUB",
+                "Readability, ports",
+                "Ebooks & readers"
+            ],
+            doc.get_article_metadata().keywords()
+        );
+    }
+
+    #[test]
+    fn test_extraction_budget() {
+        let mut doc = Readability::new(TEST_HTML);
+        doc.budget = ExtractionBudget {
+            max_nodes: 10,
+            ..Default::default()
+        };
+        let err = doc.parse("https://example.com/post").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::ReadabilityTimeout(_)));
+
+        let mut doc = Readability::new(TEST_HTML);
+        doc.budget = ExtractionBudget {
+            max_duration: Duration::from_secs(0),
+            ..Default::default()
+        };
+        let err = doc.parse("https://example.com/post").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::ReadabilityTimeout(_)));
+
+        let mut doc = Readability::new(TEST_HTML);
+        assert!(doc.parse("https://example.com/post").is_ok());
+
+        let mut doc = Readability::new(TEST_HTML);
+        doc.budget = ExtractionBudget {
+            min_words: 100_000,
+            ..Default::default()
+        };
+        let err = doc.parse("https://example.com/post").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::ExtractionTooShort(_)));
+    }
+
+    #[test]
+    fn test_confidence() {
+        let mut doc = Readability::new(TEST_HTML);
+        doc.parse("https://example.com/post").unwrap();
+        let confidence = doc.confidence.unwrap();
+        assert!(confidence.text_length > 0);
+        assert!(confidence.score() > 0.0 && confidence.score() <= 1.0);
+
+        let article = Confidence {
+            top_candidate_score: 80.0,
+            link_density: 0.05,
+            text_length: 6000,
+        };
+        let link_list = Confidence {
+            top_candidate_score: 4.0,
+            link_density: 0.9,
+            text_length: 300,
+        };
+        assert!(article.score() > 0.9);
+        assert!(link_list.score() < 0.1);
+    }
+
+    #[test]
+    fn test_malformed_document() {
+        let html_str = r#"
+        <html>
+            <head><base href="http://[invalid"></head>
+            <p>Paragraphs outside of a body with <a href="/link">a link</a> are scored and their
+            ancestors go all the way up to the document. Extraction should not panic on them.</p>
+            <p>Another long paragraph so that this reaches the minimum length, with the commas,
+            the words, and enough of the text, to be scored as the content of the article.</p>
+        </html>
+        "#;
+        let mut doc = Readability::new(html_str);
+        assert!(doc.parse("https://example.com/post").is_ok());
+        let content = doc.article_node.unwrap().text_contents();
+        assert!(content.contains("Another long paragraph"));
+
+        let mut doc = Readability::new(html_str);
+        assert!(doc.parse("not a url").is_err());
+    }
+
     #[test]
     fn test_post_process_content() {
         let html_str = r##"
@@ -3961,7 +4703,7 @@ characters. For that reason, this <p> tag could not be a byline because it's too
             .select_first("body")
             .ok()
             .map(|node_ref| node_ref.as_node().clone());
-        doc.post_process_content("https://foo.blog/post/");
+        doc.post_process_content("https://foo.blog/post/").unwrap();
         let has_class_attr = doc
             .root_node
             .inclusive_descendants()
@@ -4012,4 +4754,91 @@ characters. For that reason, this <p> tag could not be a byline because it's too
         assert_eq!(false, doc.flag_is_active(FLAG_WEIGHT_CLASSES));
         assert_eq!(true, doc.flag_is_active(FLAG_STRIP_UNLIKELYS));
     }
+
+    fn large_page(sections: usize) -> String {
+        let mut html = String::from("<html><body>");
+        for i in 0..sections {
+            html.push_str(&format!(
+                "<div class=\"section\"><h2>Section {0}</h2><p>Paragraph {0} with \
+                <a href=\"#{0}\">a link</a> and <span>some</span> <em>inline</em> text.</p>\
+                <ul><li><a href=\"#a\">One</a></li><li>Two</li></ul></div>",
+                i
+            ));
+        }
+        html.push_str("</body></html>");
+        html
+    }
+
+    #[test]
+    fn test_precompiled_lookups() {
+        // The precompiled selector and the set of block level elements find the same
+        // elements as the lookups they replace
+        let doc = Readability::new(&large_page(5000));
+        let divs = doc
+            .root_node
+            .select("div")
+            .unwrap()
+            .map(|div| div.as_node().clone())
+            .collect::<Vec<_>>();
+
+        let parsed_count: usize = divs
+            .iter()
+            .map(|div| div.select("a").unwrap().count())
+            .sum();
+        let compiled_count: usize = divs
+            .iter()
+            .map(|div| select_compiled(div, &LINK_SELECTOR).count())
+            .sum();
+        assert_eq!(parsed_count, compiled_count);
+
+        let block_level_elems = [
+            "address",
+            "article",
+            "aside",
+            "blockquote",
+            "details",
+            "dialog",
+            "dd",
+            "div",
+            "dl",
+            "dt",
+            "fieldset",
+            "figcaption",
+            "footer",
+            "form",
+            "h1",
+            "h2",
+            "h3",
+            "h4",
+            "h5",
+            "h6",
+            "header",
+            "hgroup",
+            "hr",
+            "li",
+            "main",
+            "nav",
+            "ol",
+            "p",
+            "pre",
+            "section",
+            "table",
+            "ul",
+        ];
+        let names = doc
+            .root_node
+            .descendants()
+            .elements()
+            .map(|elem| elem.name.local.to_string())
+            .collect::<Vec<_>>();
+        let array_count = names
+            .iter()
+            .filter(|name| block_level_elems.contains(&name.as_str()))
+            .count();
+        let set_count = names
+            .iter()
+            .filter(|name| BLOCK_LEVEL_ELEMS.contains(name.as_str()))
+            .count();
+        assert_eq!(array_count, set_count);
+    }
 }
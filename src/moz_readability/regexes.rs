@@ -10,6 +10,14 @@ pub fn is_match_byline(match_str: &str) -> bool {
     BYLINE_REGEX.is_match(match_str)
 }
 
+pub fn is_match_date(match_str: &str) -> bool {
+    lazy_static! {
+        static ref DATE_REGEX: Regex =
+            Regex::new(r"(?i)\b(?:date|dateline|pubdate|published|posted|timestamp)\b").unwrap();
+    }
+    DATE_REGEX.is_match(match_str)
+}
+
 pub fn is_match_positive(match_str: &str) -> bool {
     lazy_static! {
         static ref POSITIVE_REGEX: Regex = Regex::new(r"(?i)article|body|content|entry|hentry|h-entry|main|page|pagination|post|text|blog|story").unwrap();
@@ -38,6 +46,58 @@ pub fn is_match_unlikely(match_str: &str) -> bool {
     UNLIKELY_REGEX.is_match(match_str)
 }
 
+/// Site specific exceptions to the unlikely candidate and negative class patterns, for sites
+/// that name the element of their article with words such as `sidebar`
+#[derive(Clone, Debug, Default)]
+pub struct PatternOverrides {
+    allow_unlikely: Option<Regex>,
+    allow_negative: Option<Regex>,
+}
+
+impl PatternOverrides {
+    /// Builds the overrides from patterns of class names and ids, which are matched against
+    /// each class name and the id of an element. Elements with a match are not stripped as
+    /// unlikely candidates or scored down as negative.
+    pub fn new(allow_unlikely: &[String], allow_negative: &[String]) -> Result<Self, regex::Error> {
+        Ok(Self {
+            allow_unlikely: any_of(allow_unlikely)?,
+            allow_negative: any_of(allow_negative)?,
+        })
+    }
+
+    pub fn is_match_unlikely(&self, match_str: &str) -> bool {
+        is_match_unlikely(match_str) && !is_allowed(&self.allow_unlikely, match_str)
+    }
+
+    pub fn is_match_negative(&self, match_str: &str) -> bool {
+        is_match_negative(match_str) && !is_allowed(&self.allow_negative, match_str)
+    }
+}
+
+/// A case insensitive regex matching any of the patterns, or `None` when there are none
+fn any_of(patterns: &[String]) -> Result<Option<Regex>, regex::Error> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    for pattern in patterns {
+        Regex::new(pattern)?;
+    }
+    let alternatives = patterns
+        .iter()
+        .map(|pattern| format!("(?:{})", pattern))
+        .collect::<Vec<_>>();
+    Regex::new(&format!("(?i){}", alternatives.join("|"))).map(Some)
+}
+
+/// Whether one of the class names or the id in the string matches the allowed patterns
+fn is_allowed(allow: &Option<Regex>, match_str: &str) -> bool {
+    allow.as_ref().map_or(false, |allow| {
+        match_str
+            .split_whitespace()
+            .any(|name| allow.is_match(name))
+    })
+}
+
 pub fn is_match_ok_maybe(match_str: &str) -> bool {
     lazy_static! {
         static ref OK_MAYBE_REGEX: Regex =
@@ -112,6 +172,10 @@ pub fn is_match_has_title_separator(match_str: &str) -> bool {
 
 lazy_static! {
     pub static ref NORMALIZE_REGEX: Regex = Regex::new(r"\s{2,}").unwrap();
+    /// Commas of the scripts that use them, including the full-width comma of CJK text
+    pub static ref COMMAS_REGEX: Regex =
+        Regex::new(r"[\x{002C}\x{060C}\x{FE50}\x{FE10}\x{FE11}\x{2E41}\x{2E34}\x{2E32}\x{FF0C}]")
+            .unwrap();
     pub static ref B64_DATA_URL_REGEX: Regex =
         Regex::new(r"(?i)^data:\s*([^\s;,]+)\s*;\s*base64\s*").unwrap();
     pub static ref BASE64_REGEX: Regex = Regex::new(r"(?i)base64\s*").unwrap();
@@ -121,6 +185,9 @@ lazy_static! {
     .unwrap();
     pub static ref SRCSET_CAPTURE_REGEX: Regex =
         Regex::new(r"(\S+)(\s+[\d.]+[xw])?(\s*(?:,|$))").unwrap();
+    pub static ref BACKGROUND_IMAGE_REGEX: Regex =
+        Regex::new(r#"(?i)background(?:-image)?\s*:[^;]*?url\(\s*['"]?([^'")]+?)['"]?\s*\)"#)
+            .unwrap();
     pub static ref REPLACE_WHITESPACE_REGEX: Regex = Regex::new(r"\s").unwrap();
     pub static ref REPLACE_DOT_REGEX: Regex = Regex::new(r"\.").unwrap();
     pub static ref REPLACE_HTML_ESCAPE_REGEX: Regex =
@@ -0,0 +1,344 @@
+use kuchiki::{traits::*, NodeRef};
+use log::{debug, warn};
+use regex::Regex;
+use serde_json::Value;
+use url::Url;
+
+use crate::errors::{ErrorKind, PaperoniError};
+
+/// How many posts the archive API of Substack returns at a time
+const SUBSTACK_PAGE_SIZE: usize = 50;
+
+/// Elements asking readers to subscribe or marking where the free part of a post ends
+const SUBSTACK_SUBSCRIBE_SELECTORS: &str = ".paywall, .paywall-jump, \
+    [data-component-name=\"Paywall\"], .subscription-widget-wrap, \
+    .subscription-widget-wrap-editor, .subscribe-widget, .image-link-expand";
+const SUBSTACK_PAYWALL_SELECTOR: &str = ".paywall, [data-component-name=\"Paywall\"]";
+const GHOST_SUBSCRIBE_SELECTORS: &str = ".gh-post-upgrade-cta, .gh-cta, .kg-signup-card";
+const GHOST_PAYWALL_SELECTOR: &str = ".gh-post-upgrade-cta";
+
+lazy_static! {
+    static ref GHOST_IMAGE_SIZE_REGEX: Regex =
+        Regex::new(r"/content/images/(?:size/[^/]+/)?(?:format/[^/]+/)?").unwrap();
+}
+
+/// Newsletter platforms whose archives are listed through their APIs and whose posts are
+/// cleaned of subscription prompts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Newsletter {
+    Substack,
+    Ghost,
+}
+
+impl Newsletter {
+    /// Recognizes the newsletters hosted on Substack and Ghost including the ones on custom
+    /// domains which are told apart by the CDN and generator of their pages
+    pub fn detect(url: &str, html: &str) -> Option<Self> {
+        lazy_static! {
+            static ref GHOST_GENERATOR_REGEX: Regex =
+                Regex::new(r#"(?i)<meta[^>]+name="generator"[^>]+content="Ghost\b"#).unwrap();
+        }
+        let host = Url::parse(url).ok()?.host_str()?.to_owned();
+        if host.ends_with(".substack.com") || html.contains("https://substackcdn.com/") {
+            Some(Newsletter::Substack)
+        } else if GHOST_GENERATOR_REGEX.is_match(html) {
+            Some(Newsletter::Ghost)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Newsletter::Substack => "Substack",
+            Newsletter::Ghost => "Ghost",
+        }
+    }
+
+    /// Removes the subscription prompts from a post and replaces the resized copies of its
+    /// images on the CDN of the platform with the full size images. Posts cut short for
+    /// readers who are not subscribed are logged.
+    pub fn prepare_post(&self, doc: &NodeRef, url: &str) {
+        let (subscribe_selectors, paywall_selector) = match self {
+            Newsletter::Substack => (SUBSTACK_SUBSCRIBE_SELECTORS, SUBSTACK_PAYWALL_SELECTOR),
+            Newsletter::Ghost => (GHOST_SUBSCRIBE_SELECTORS, GHOST_PAYWALL_SELECTOR),
+        };
+        if doc.select_first(paywall_selector).is_ok() {
+            warn!(
+                "{} is only partly available without a subscription to the newsletter",
+                url
+            );
+        }
+        let prompts = doc.select(subscribe_selectors).unwrap().collect::<Vec<_>>();
+        for prompt in prompts {
+            prompt.as_node().detach();
+        }
+
+        let sources = doc.select("picture source").unwrap().collect::<Vec<_>>();
+        for source in sources {
+            source.as_node().detach();
+        }
+        for img in doc.select("img[src]").unwrap() {
+            let mut attrs = img.attributes.borrow_mut();
+            let full_size = match self {
+                Newsletter::Substack => substack_original_image(attrs.get("src").unwrap()),
+                Newsletter::Ghost => ghost_original_image(attrs.get("src").unwrap()),
+            };
+            if let Some(full_size) = full_size {
+                debug!("Using the full size image {}", full_size);
+                attrs.insert("src", full_size);
+                attrs.remove("srcset");
+                attrs.remove("sizes");
+            }
+        }
+    }
+
+    /// Lists the urls of all the posts of the newsletter of a site, newest first. Substack
+    /// lists them through its archive API and Ghost through the sitemap of its posts.
+    pub async fn fetch_archive(
+        &self,
+        client: &surf::Client,
+        site_url: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<String>, PaperoniError> {
+        let site_url = Url::parse(site_url)?;
+        let mut offset = 0;
+        let mut links = Vec::new();
+        match self {
+            Newsletter::Substack => loop {
+                let api_url = site_url.join(&format!(
+                    "/api/v1/archive?sort=new&offset={}&limit={}",
+                    offset, SUBSTACK_PAGE_SIZE
+                ))?;
+                let posts = fetch_archive_response(client, &api_url, self)
+                    .await?
+                    .body_json::<Value>()
+                    .await?;
+                let post_count = posts.as_array().map_or(0, Vec::len);
+                let is_last_page = post_count < SUBSTACK_PAGE_SIZE;
+                offset += post_count;
+                links.extend(parse_substack_archive(&posts));
+                if is_last_page || limit.map_or(false, |limit| links.len() >= limit) {
+                    break;
+                }
+            },
+            Newsletter::Ghost => {
+                let sitemap_url = site_url.join("/sitemap-posts.xml")?;
+                let sitemap = fetch_archive_response(client, &sitemap_url, self)
+                    .await?
+                    .body_string()
+                    .await?;
+                links = parse_ghost_sitemap(&sitemap);
+            }
+        }
+        if let Some(limit) = limit {
+            links.truncate(limit);
+        }
+        Ok(links)
+    }
+}
+
+async fn fetch_archive_response(
+    client: &surf::Client,
+    url: &Url,
+    newsletter: &Newsletter,
+) -> Result<surf::Response, PaperoniError> {
+    debug!("Fetching the {} archive {}", newsletter.name(), url);
+    let res = client.send(surf::get(url)).await?;
+    if res.status().is_success() {
+        Ok(res)
+    } else {
+        let msg = format!(
+            "{} responded with {} for {}",
+            newsletter.name(),
+            res.status(),
+            url
+        );
+        Err(ErrorKind::HTTPError(msg).into())
+    }
+}
+
+/// Reads the urls of the posts in a page of the archive API of Substack. Discussion threads
+/// are skipped since they are not articles.
+fn parse_substack_archive(posts: &Value) -> Vec<String> {
+    posts
+        .as_array()
+        .map(|posts| {
+            posts
+                .iter()
+                .filter(|post| post["type"].as_str() != Some("thread"))
+                .filter_map(|post| post["canonical_url"].as_str().map(str::to_owned))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Reads the urls of the posts in the sitemap of a Ghost site ordered by when they were last
+/// modified, newest first
+fn parse_ghost_sitemap(sitemap: &str) -> Vec<String> {
+    let doc = kuchiki::parse_html().one(sitemap);
+    let mut entries = doc
+        .select("url")
+        .unwrap()
+        .filter_map(|entry| {
+            let loc = entry.as_node().select_first("loc").ok()?.text_contents();
+            let lastmod = entry
+                .as_node()
+                .select_first("lastmod")
+                .map(|lastmod| lastmod.text_contents())
+                .unwrap_or_default();
+            Some((lastmod.trim().to_owned(), loc.trim().to_owned()))
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|(a, _), (b, _)| b.cmp(a));
+    entries.into_iter().map(|(_, loc)| loc).collect()
+}
+
+/// Returns the image a resized copy on the Substack CDN was made from. The original url is
+/// percent-encoded at the end of the path after the resizing parameters.
+fn substack_original_image(src: &str) -> Option<String> {
+    let url = Url::parse(src).ok()?;
+    if url.host_str()? != "substackcdn.com" {
+        return None;
+    }
+    let path = url.path();
+    let fetch_start = path.find("/image/fetch/")? + "/image/fetch/".len();
+    let (_, encoded) = path[fetch_start..].split_at(path[fetch_start..].find('/')? + 1);
+    let original = percent_decode(encoded)?;
+    Url::parse(&original).ok().map(String::from)
+}
+
+/// Returns the image a resized copy on a Ghost site was made from by dropping the size and
+/// format from its path
+fn ghost_original_image(src: &str) -> Option<String> {
+    if GHOST_IMAGE_SIZE_REGEX
+        .find(src)
+        .map_or(true, |size| size.as_str() == "/content/images/")
+    {
+        return None;
+    }
+    Some(
+        GHOST_IMAGE_SIZE_REGEX
+            .replace(src, "/content/images/")
+            .into_owned(),
+    )
+}
+
+fn percent_decode(encoded: &str) -> Option<String> {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(idx + 1..idx + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            idx += 3;
+        } else {
+            decoded.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            Some(Newsletter::Substack),
+            Newsletter::detect("https://writer.substack.com/p/post", "")
+        );
+        assert_eq!(
+            Some(Newsletter::Substack),
+            Newsletter::detect(
+                "https://newsletter.example.com/p/post",
+                "<link rel=\"preconnect\" href=\"https://substackcdn.com/\">"
+            )
+        );
+        assert_eq!(
+            Some(Newsletter::Ghost),
+            Newsletter::detect(
+                "https://blog.example.com/post/",
+                "<meta name=\"generator\" content=\"Ghost 5.45\">"
+            )
+        );
+        assert_eq!(
+            None,
+            Newsletter::detect("https://example.com/post", "<p></p>")
+        );
+    }
+
+    #[test]
+    fn test_prepare_post() {
+        let doc = kuchiki::parse_html().one(
+            r#"<article>
+                <p>Free part</p>
+                <picture>
+                    <source type="image/webp" srcset="https://substackcdn.com/image/fetch/w_424,f_webp/https%3A%2F%2Fsubstack-post-media.s3.amazonaws.com%2Fpublic%2Fimages%2Fa.png 424w">
+                    <img src="https://substackcdn.com/image/fetch/w_1456,c_limit,f_auto/https%3A%2F%2Fsubstack-post-media.s3.amazonaws.com%2Fpublic%2Fimages%2Fa.png" srcset="x 424w" sizes="100vw">
+                </picture>
+                <div class="subscription-widget-wrap"><p>Subscribe now</p></div>
+                <div data-component-name="Paywall"><h4>Keep reading with a 7-day free trial</h4></div>
+            </article>"#,
+        );
+        Newsletter::Substack.prepare_post(&doc, "https://writer.substack.com/p/post");
+        let img = doc.select_first("img").unwrap();
+        let attrs = img.attributes.borrow();
+        assert_eq!(
+            Some("https://substack-post-media.s3.amazonaws.com/public/images/a.png"),
+            attrs.get("src")
+        );
+        assert_eq!(None, attrs.get("srcset"));
+        assert!(doc.select_first("source").is_err());
+        assert!(!doc.text_contents().contains("Subscribe now"));
+        assert!(!doc.text_contents().contains("free trial"));
+        assert!(doc.text_contents().contains("Free part"));
+    }
+
+    #[test]
+    fn test_ghost_original_image() {
+        assert_eq!(
+            Some("https://blog.example.com/content/images/2023/01/photo.jpg".to_owned()),
+            ghost_original_image(
+                "https://blog.example.com/content/images/size/w600/format/webp/2023/01/photo.jpg"
+            )
+        );
+        assert_eq!(
+            None,
+            ghost_original_image("https://blog.example.com/content/images/2023/01/photo.jpg")
+        );
+    }
+
+    #[test]
+    fn test_parse_archives() {
+        let posts = json!([
+            {"canonical_url": "https://writer.substack.com/p/second", "type": "newsletter"},
+            {"canonical_url": "https://writer.substack.com/p/chat", "type": "thread"},
+            {"canonical_url": "https://writer.substack.com/p/first", "type": "podcast"}
+        ]);
+        assert_eq!(
+            vec![
+                "https://writer.substack.com/p/second",
+                "https://writer.substack.com/p/first"
+            ],
+            parse_substack_archive(&posts)
+        );
+
+        let sitemap = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                <url><loc>https://blog.example.com/old/</loc><lastmod>2022-03-01T10:00:00.000Z</lastmod></url>
+                <url><loc>https://blog.example.com/new/</loc><lastmod>2023-07-12T08:30:00.000Z</lastmod></url>
+            </urlset>"#;
+        assert_eq!(
+            vec![
+                "https://blog.example.com/new/",
+                "https://blog.example.com/old/"
+            ],
+            parse_ghost_sitemap(sitemap)
+        );
+    }
+}
@@ -0,0 +1,625 @@
+use std::{collections::HashMap, fs, io::Write};
+
+use kuchiki::{NodeData, NodeRef};
+use log::{debug, error};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    atomic_file::AtomicFile,
+    cli::AppConfig,
+    errors::PaperoniError,
+    exporter::{file_safe_title, own_or_merged_file, ExportFileNames, Exporter},
+    extractor::{Article, ResourceInfo},
+};
+
+const CONTENT_NAMESPACES: &str = r#"xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:draw="urn:oasis:names:tc:opendocument:xmlns:drawing:1.0" xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0" xmlns:xlink="http://www.w3.org/1999/xlink" xmlns:svg="urn:oasis:names:tc:opendocument:xmlns:svg-compatible:1.0""#;
+const ODT_MIME_TYPE: &str = "application/vnd.oasis.opendocument.text";
+/// The number of nested list levels given an indentation in the list styles
+const LIST_LEVELS: usize = 6;
+
+/// Elements that are laid out as their own paragraphs instead of being part of the text
+/// around them
+const BLOCK_ELEMENTS: [&str; 28] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+const SKIPPED_ELEMENTS: [&str; 9] = [
+    "audio", "iframe", "math", "noscript", "script", "style", "svg", "template", "video",
+];
+
+/// Exports each article to its own ODT file, or merges them into a single file with each
+/// article starting on a new page
+pub struct OdtExporter<'a> {
+    app_config: &'a AppConfig,
+    merged_writer: OdtWriter,
+    file_names: ExportFileNames,
+    /// The files of the articles written so far by their index
+    article_files: HashMap<usize, String>,
+    written_files: Vec<String>,
+}
+
+impl<'a> OdtExporter<'a> {
+    pub fn new(app_config: &'a AppConfig) -> Self {
+        Self {
+            app_config,
+            merged_writer: OdtWriter::default(),
+            file_names: ExportFileNames::new(app_config.overwrite),
+            article_files: HashMap::new(),
+            written_files: Vec::new(),
+        }
+    }
+}
+
+impl Exporter for OdtExporter<'_> {
+    fn format(&self) -> &'static str {
+        "odt"
+    }
+
+    fn prepare(&mut self, _articles: &[Article]) -> Result<(), PaperoniError> {
+        if let Some(name) = &self.app_config.merged {
+            debug!("Creating {:?}", name);
+        }
+        Ok(())
+    }
+
+    fn add_article(&mut self, idx: usize, article: &Article) -> Result<(), PaperoniError> {
+        if self.app_config.merged.is_some() {
+            self.merged_writer.write_article(article, idx > 0);
+            debug!(
+                "Added {} to the export ODT file",
+                article.metadata().title()
+            );
+            return Ok(());
+        }
+
+        let file_name = match self.file_names.target(
+            self.app_config.output_directory.as_deref().unwrap_or("."),
+            &file_safe_title(article),
+            "odt",
+        ) {
+            Some(file_name) => file_name,
+            None => return Ok(()),
+        };
+        let mut writer = OdtWriter::default();
+        writer.write_article(article, false);
+        writer.write_to_file(&file_name)?;
+        debug!("Created {:?}", file_name);
+        self.article_files.insert(idx, file_name.clone());
+        self.written_files.push(file_name);
+        Ok(())
+    }
+
+    fn finalize(
+        &mut self,
+        _articles: &[Article],
+    ) -> Result<(), Vec<(Option<usize>, PaperoniError)>> {
+        let name = match &self.app_config.merged {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        if let Err(mut err) = self.merged_writer.write_to_file(name) {
+            error!("Failed to write articles to file: {}", name);
+            err.set_article_source(name);
+            return Err(vec![(None, err)]);
+        };
+
+        debug!("Created {:?}", name);
+        println!("Created {:?}", name);
+        self.written_files.push(name.clone());
+        Ok(())
+    }
+
+    fn written_files(&self) -> &[String] {
+        &self.written_files
+    }
+
+    fn article_file(&self, idx: usize) -> Option<&str> {
+        own_or_merged_file(
+            idx,
+            self.app_config.merged.as_deref(),
+            &self.article_files,
+            &self.written_files,
+        )
+    }
+}
+
+/// Builds the body of an OpenDocument Text file from the HTML of articles. Headings, lists,
+/// tables and images are mapped to their ODF equivalents while the rest of the content is
+/// laid out as paragraphs.
+struct OdtWriter {
+    buf: String,
+    /// The images of the article being written
+    article_imgs: Vec<ResourceInfo>,
+    /// The images embedded in the document as (file name, MIME type)
+    embedded_imgs: Vec<(String, String)>,
+    paragraph_style: &'static str,
+    list_depth: usize,
+}
+
+impl Default for OdtWriter {
+    fn default() -> Self {
+        Self {
+            buf: String::new(),
+            article_imgs: Vec::new(),
+            embedded_imgs: Vec::new(),
+            paragraph_style: "Text_20_body",
+            list_depth: 0,
+        }
+    }
+}
+
+impl OdtWriter {
+    /// Writes the title, content and source of an article. Articles after the first one in
+    /// a merged document start on a new page.
+    fn write_article(&mut self, article: &Article, starts_new_page: bool) {
+        self.article_imgs = article.img_urls.clone();
+        let title_style = if starts_new_page {
+            "P_title_page_break"
+        } else {
+            "Heading_20_1"
+        };
+        self.buf.push_str(&format!(
+            "<text:h text:style-name=\"{}\" text:outline-level=\"1\">{}</text:h>",
+            title_style,
+            escape_text(article.metadata().title())
+        ));
+        match article.node_ref().select_first("body") {
+            Ok(body) => self.write_blocks(body.as_node()),
+            Err(_) => self.write_blocks(article.node_ref()),
+        }
+        self.buf.push_str(&format!(
+            "<text:p text:style-name=\"Text_20_body\">Source: <text:a xlink:type=\"simple\" xlink:href=\"{0}\">{0}</text:a></text:p>",
+            escape_text(&article.url)
+        ));
+    }
+
+    /// Writes the children of a block element. Consecutive inline children are grouped
+    /// into a paragraph as ODF text cannot be placed directly in the document body.
+    fn write_blocks(&mut self, node_ref: &NodeRef) {
+        let mut inline_run = Vec::new();
+        for child in node_ref.children() {
+            if is_block(&child) {
+                self.write_paragraph(&inline_run);
+                inline_run.clear();
+                self.write_block(&child);
+            } else {
+                inline_run.push(child);
+            }
+        }
+        self.write_paragraph(&inline_run);
+    }
+
+    fn write_block(&mut self, node_ref: &NodeRef) {
+        let name = match node_ref.as_element() {
+            Some(elem) => elem.name.local.to_string(),
+            None => return,
+        };
+        match name.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                if node_ref.text_contents().trim().is_empty() {
+                    return;
+                }
+                let level = &name[1..];
+                self.buf.push_str(&format!(
+                    "<text:h text:style-name=\"Heading_20_{0}\" text:outline-level=\"{0}\">",
+                    level
+                ));
+                self.write_inline_children(node_ref);
+                self.buf.push_str("</text:h>");
+            }
+            "p" | "dt" | "dd" | "figcaption" => {
+                self.write_paragraph(&node_ref.children().collect::<Vec<_>>())
+            }
+            "ul" | "ol" => self.write_list(node_ref, name == "ol"),
+            "table" => self.write_table(node_ref),
+            "pre" => self.write_preformatted(node_ref),
+            "blockquote" => {
+                let paragraph_style = self.paragraph_style;
+                self.paragraph_style = "Quotations";
+                self.write_blocks(node_ref);
+                self.paragraph_style = paragraph_style;
+            }
+            "hr" => self
+                .buf
+                .push_str("<text:p text:style-name=\"Horizontal_20_Line\"/>"),
+            _ => self.write_blocks(node_ref),
+        }
+    }
+
+    /// Writes the nodes as a paragraph if they contain any text or images
+    fn write_paragraph(&mut self, nodes: &[NodeRef]) {
+        let has_content = nodes.iter().filter(|node| !is_skipped(node)).any(|node| {
+            !node.text_contents().trim().is_empty()
+                || node
+                    .inclusive_descendants()
+                    .elements()
+                    .any(|elem| &elem.name.local == "img")
+        });
+        if !has_content {
+            return;
+        }
+        self.buf.push_str(&format!(
+            "<text:p text:style-name=\"{}\">",
+            self.paragraph_style
+        ));
+        for node in nodes {
+            self.write_inline(node);
+        }
+        self.buf.push_str("</text:p>");
+    }
+
+    fn write_list(&mut self, node_ref: &NodeRef, is_ordered: bool) {
+        // Nested lists use the levels of the style set on the outermost list
+        if self.list_depth == 0 {
+            let list_style = if is_ordered { "L_number" } else { "L_bullet" };
+            self.buf
+                .push_str(&format!("<text:list text:style-name=\"{}\">", list_style));
+        } else {
+            self.buf.push_str("<text:list>");
+        }
+        self.list_depth += 1;
+        for item in node_ref
+            .children()
+            .filter(|child| is_element_named(child, "li"))
+        {
+            self.buf.push_str("<text:list-item>");
+            self.write_blocks(&item);
+            self.buf.push_str("</text:list-item>");
+        }
+        self.list_depth -= 1;
+        self.buf.push_str("</text:list>");
+    }
+
+    fn write_table(&mut self, node_ref: &NodeRef) {
+        // Rows can either be children of the table or of its head, body and foot sections
+        let rows = node_ref
+            .children()
+            .flat_map(|child| {
+                if is_element_named(&child, "thead")
+                    || is_element_named(&child, "tbody")
+                    || is_element_named(&child, "tfoot")
+                {
+                    child.children().collect::<Vec<_>>()
+                } else {
+                    vec![child]
+                }
+            })
+            .filter(|child| is_element_named(child, "tr"))
+            .map(|row| {
+                row.children()
+                    .filter(|cell| is_element_named(cell, "td") || is_element_named(cell, "th"))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|cells| !cells.is_empty())
+            .collect::<Vec<_>>();
+        let column_count = match rows.iter().map(Vec::len).max() {
+            Some(column_count) => column_count,
+            None => return,
+        };
+        self.buf.push_str(&format!(
+            "<table:table><table:table-column table:number-columns-repeated=\"{}\"/>",
+            column_count
+        ));
+        let paragraph_style = self.paragraph_style;
+        self.paragraph_style = "Table_20_Contents";
+        for cells in rows {
+            self.buf.push_str("<table:table-row>");
+            for cell in &cells {
+                self.buf
+                    .push_str("<table:table-cell office:value-type=\"string\">");
+                self.write_blocks(cell);
+                self.buf.push_str("</table:table-cell>");
+            }
+            for _ in cells.len()..column_count {
+                self.buf.push_str("<table:table-cell/>");
+            }
+            self.buf.push_str("</table:table-row>");
+        }
+        self.paragraph_style = paragraph_style;
+        self.buf.push_str("</table:table>");
+    }
+
+    /// Writes a code block keeping its line breaks and indentation which would otherwise
+    /// be collapsed
+    fn write_preformatted(&mut self, node_ref: &NodeRef) {
+        self.buf
+            .push_str("<text:p text:style-name=\"Preformatted_20_Text\">");
+        let text = node_ref.text_contents();
+        let mut space_count = 0;
+        let mut is_line_start = true;
+        for c in text.trim_end().chars() {
+            if c == ' ' {
+                space_count += 1;
+                continue;
+            }
+            // A single space between words is kept as is but other spaces would be collapsed
+            if space_count == 1 && !is_line_start {
+                self.buf.push(' ');
+            } else if space_count > 0 {
+                self.buf
+                    .push_str(&format!("<text:s text:c=\"{}\"/>", space_count));
+            }
+            space_count = 0;
+            is_line_start = c == '\n';
+            match c {
+                '\n' => self.buf.push_str("<text:line-break/>"),
+                '\t' => self.buf.push_str("<text:tab/>"),
+                '\r' => (),
+                _ => self.buf.push_str(&escape_text(&c.to_string())),
+            }
+        }
+        self.buf.push_str("</text:p>");
+    }
+
+    fn write_inline_children(&mut self, node_ref: &NodeRef) {
+        for child in node_ref.children() {
+            self.write_inline(&child);
+        }
+    }
+
+    fn write_inline(&mut self, node_ref: &NodeRef) {
+        match node_ref.data() {
+            NodeData::Text(text) => self.buf.push_str(&escape_text(&text.borrow())),
+            NodeData::Element(elem) => match elem.name.local.as_ref() {
+                _ if is_skipped(node_ref) => (),
+                "strong" | "b" => self.write_span(node_ref, "T_bold"),
+                "em" | "i" | "cite" => self.write_span(node_ref, "T_italic"),
+                "code" | "kbd" | "samp" => self.write_span(node_ref, "T_code"),
+                "br" => self.buf.push_str("<text:line-break/>"),
+                "img" => self.write_image(node_ref),
+                "a" => {
+                    let href = elem.attributes.borrow().get("href").map(escape_text);
+                    match href {
+                        Some(href) => {
+                            self.buf.push_str(&format!(
+                                "<text:a xlink:type=\"simple\" xlink:href=\"{}\">",
+                                href
+                            ));
+                            self.write_inline_children(node_ref);
+                            self.buf.push_str("</text:a>");
+                        }
+                        None => self.write_inline_children(node_ref),
+                    }
+                }
+                _ => self.write_inline_children(node_ref),
+            },
+            _ => (),
+        }
+    }
+
+    fn write_span(&mut self, node_ref: &NodeRef, style: &str) {
+        self.buf
+            .push_str(&format!("<text:span text:style-name=\"{}\">", style));
+        self.write_inline_children(node_ref);
+        self.buf.push_str("</text:span>");
+    }
+
+    /// Embeds an image that was downloaded. Images that failed to download are skipped.
+    fn write_image(&mut self, node_ref: &NodeRef) {
+        let attrs = node_ref.as_element().unwrap().attributes.borrow();
+        let src = match attrs.get("src") {
+            Some(src) => src,
+            None => return,
+        };
+        let mime = match self
+            .article_imgs
+            .iter()
+            .find(|(img_path, _)| img_path.as_str() == src)
+            .and_then(|(_, mime)| mime.clone())
+        {
+            Some(mime) => mime,
+            None => return,
+        };
+        if !self
+            .embedded_imgs
+            .iter()
+            .any(|(img_path, _)| img_path.as_str() == src)
+        {
+            self.embedded_imgs.push((src.to_owned(), mime));
+        }
+        self.buf.push_str(&format!(
+            "<draw:frame text:anchor-type=\"as-char\" svg:width=\"15cm\" style:rel-width=\"100%\" style:rel-height=\"scale\"><draw:image xlink:href=\"Pictures/{}\" xlink:type=\"simple\" xlink:show=\"embed\" xlink:actuate=\"onLoad\"/></draw:frame>",
+            escape_text(src)
+        ));
+    }
+
+    fn content_xml(&self) -> String {
+        let list_level_props = |level: usize| {
+            format!(
+                "<style:list-level-properties text:space-before=\"{:.1}cm\" text:min-label-width=\"0.6cm\"/>",
+                (level - 1) as f32 * 0.6
+            )
+        };
+        let bullet_levels: String = (1..=LIST_LEVELS)
+            .map(|level| {
+                format!(
+                    "<text:list-level-style-bullet text:level=\"{}\" text:bullet-char=\"•\">{}</text:list-level-style-bullet>",
+                    level,
+                    list_level_props(level)
+                )
+            })
+            .collect();
+        let number_levels: String = (1..=LIST_LEVELS)
+            .map(|level| {
+                format!(
+                    "<text:list-level-style-number text:level=\"{}\" style:num-format=\"1\" style:num-suffix=\".\">{}</text:list-level-style-number>",
+                    level,
+                    list_level_props(level)
+                )
+            })
+            .collect();
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content {} office:version="1.2"><office:automatic-styles><style:style style:name="T_bold" style:family="text"><style:text-properties fo:font-weight="bold"/></style:style><style:style style:name="T_italic" style:family="text"><style:text-properties fo:font-style="italic"/></style:style><style:style style:name="T_code" style:family="text"><style:text-properties fo:font-family="'Liberation Mono', monospace"/></style:style><style:style style:name="P_title_page_break" style:family="paragraph" style:parent-style-name="Heading_20_1"><style:paragraph-properties fo:break-before="page"/></style:style><text:list-style style:name="L_bullet">{}</text:list-style><text:list-style style:name="L_number">{}</text:list-style></office:automatic-styles><office:body><office:text>{}</office:text></office:body></office:document-content>"#,
+            CONTENT_NAMESPACES, bullet_levels, number_levels, self.buf
+        )
+    }
+
+    fn manifest_xml(&self) -> String {
+        let img_entries: String = self
+            .embedded_imgs
+            .iter()
+            .map(|(img_path, mime)| {
+                format!(
+                    "<manifest:file-entry manifest:full-path=\"Pictures/{}\" manifest:media-type=\"{}\"/>",
+                    escape_text(img_path),
+                    escape_text(mime)
+                )
+            })
+            .collect();
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="1.2"><manifest:file-entry manifest:full-path="/" manifest:version="1.2" manifest:media-type="{}"/><manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/><manifest:file-entry manifest:full-path="styles.xml" manifest:media-type="text/xml"/>{}</manifest:manifest>"#,
+            ODT_MIME_TYPE, img_entries
+        )
+    }
+
+    /// Packages the document and its images into an ODT file
+    fn write_to_file(&self, file_name: &str) -> Result<(), PaperoniError> {
+        let mut zip = ZipWriter::new(AtomicFile::create(file_name)?);
+        // The mimetype must be the first file in the package and must not be compressed
+        zip.start_file(
+            "mimetype",
+            FileOptions::default().compression_method(CompressionMethod::Stored),
+        )?;
+        zip.write_all(ODT_MIME_TYPE.as_bytes())?;
+        zip.start_file("META-INF/manifest.xml", FileOptions::default())?;
+        zip.write_all(self.manifest_xml().as_bytes())?;
+        zip.start_file("styles.xml", FileOptions::default())?;
+        zip.write_all(include_bytes!("./assets/odt-styles.xml"))?;
+        zip.start_file("content.xml", FileOptions::default())?;
+        zip.write_all(self.content_xml().as_bytes())?;
+        for (img_path, _) in &self.embedded_imgs {
+            let mut file_path = std::env::temp_dir();
+            file_path.push(img_path);
+            zip.start_file(format!("Pictures/{}", img_path), FileOptions::default())?;
+            zip.write_all(&fs::read(&file_path)?)?;
+        }
+        zip.finish()?.persist()?;
+        Ok(())
+    }
+}
+
+fn is_block(node_ref: &NodeRef) -> bool {
+    node_ref
+        .as_element()
+        .map(|elem| BLOCK_ELEMENTS.contains(&elem.name.local.as_ref()))
+        .unwrap_or(false)
+}
+
+fn is_skipped(node_ref: &NodeRef) -> bool {
+    node_ref
+        .as_element()
+        .map(|elem| SKIPPED_ELEMENTS.contains(&elem.name.local.as_ref()))
+        .unwrap_or(false)
+}
+
+fn is_element_named(node_ref: &NodeRef, name: &str) -> bool {
+    node_ref
+        .as_element()
+        .map(|elem| &elem.name.local == name)
+        .unwrap_or(false)
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace("&", "&amp;")
+        .replace("<", "&lt;")
+        .replace(">", "&gt;")
+        .replace("\"", "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use kuchiki::traits::*;
+
+    fn write_html(html: &str, article_imgs: Vec<ResourceInfo>) -> OdtWriter {
+        let doc = kuchiki::parse_html().one(html);
+        let mut writer = OdtWriter::default();
+        writer.article_imgs = article_imgs;
+        writer.write_blocks(doc.select_first("body").unwrap().as_node());
+        writer
+    }
+
+    #[test]
+    fn test_write_blocks() {
+        let writer = write_html(
+            r#"<div>Loose <b>text</b><h2>Heading</h2><p>A <a href="https://example.com?a=1&amp;b=2">link</a></p><p> </p></div>"#,
+            Vec::new(),
+        );
+        assert_eq!(
+            concat!(
+                r#"<text:p text:style-name="Text_20_body">Loose <text:span text:style-name="T_bold">text</text:span></text:p>"#,
+                r#"<text:h text:style-name="Heading_20_2" text:outline-level="2">Heading</text:h>"#,
+                r#"<text:p text:style-name="Text_20_body">A <text:a xlink:type="simple" xlink:href="https://example.com?a=1&amp;b=2">link</text:a></text:p>"#,
+            ),
+            writer.buf
+        );
+    }
+
+    #[test]
+    fn test_write_lists_and_tables() {
+        let writer = write_html(
+            "<ol><li>One<ul><li>Nested</li></ul></li></ol><table><tr><th>A</th><th>B</th></tr><tr><td>1</td></tr></table>",
+            Vec::new(),
+        );
+        assert_eq!(
+            concat!(
+                r#"<text:list text:style-name="L_number"><text:list-item><text:p text:style-name="Text_20_body">One</text:p>"#,
+                r#"<text:list><text:list-item><text:p text:style-name="Text_20_body">Nested</text:p></text:list-item></text:list>"#,
+                r#"</text:list-item></text:list>"#,
+                r#"<table:table><table:table-column table:number-columns-repeated="2"/>"#,
+                r#"<table:table-row><table:table-cell office:value-type="string"><text:p text:style-name="Table_20_Contents">A</text:p></table:table-cell>"#,
+                r#"<table:table-cell office:value-type="string"><text:p text:style-name="Table_20_Contents">B</text:p></table:table-cell></table:table-row>"#,
+                r#"<table:table-row><table:table-cell office:value-type="string"><text:p text:style-name="Table_20_Contents">1</text:p></table:table-cell><table:table-cell/></table:table-row>"#,
+                r#"</table:table>"#,
+            ),
+            writer.buf
+        );
+    }
+
+    #[test]
+    fn test_write_preformatted_and_images() {
+        let writer = write_html(
+            "<pre>fn main() {\n    a < b;\n}</pre><p><img src=\"img_1.png\"><img src=\"missing.png\"></p>",
+            vec![("img_1.png".into(), Some("image/png".into()))],
+        );
+        assert_eq!(
+            concat!(
+                r#"<text:p text:style-name="Preformatted_20_Text">fn main() {<text:line-break/><text:s text:c="4"/>a &lt; b;<text:line-break/>}</text:p>"#,
+                r#"<text:p text:style-name="Text_20_body"><draw:frame text:anchor-type="as-char" svg:width="15cm" style:rel-width="100%" style:rel-height="scale"><draw:image xlink:href="Pictures/img_1.png" xlink:type="simple" xlink:show="embed" xlink:actuate="onLoad"/></draw:frame></text:p>"#,
+            ),
+            writer.buf
+        );
+        assert_eq!(
+            vec![("img_1.png".to_owned(), "image/png".to_owned())],
+            writer.embedded_imgs
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashSet;
+
+use kuchiki::NodeRef;
+use regex::Regex;
+
+use crate::extractor::new_element;
+
+/// The role of the elements marking where the pages of the printed edition of an article start
+pub const PAGE_BREAK_ROLE: &str = "doc-pagebreak";
+/// Classes used by publishers for the page numbers of the printed edition
+const PAGE_NUMBER_CLASSES: [&str; 6] = [
+    "pagenum",
+    "page-num",
+    "page-number",
+    "pagenumber",
+    "pagebreak",
+    "page-break",
+];
+
+lazy_static! {
+    static ref PAGE_LABEL_REGEX: Regex =
+        Regex::new(r"(?i)^(?:page|pg\.?|p\.)?\s*\[?([0-9]+|[ivxlcdm]+)\]?$").unwrap();
+    static ref PAGE_ANCHOR_REGEX: Regex = Regex::new(r"(?i)^(?:page|pg|p)[-_]?([0-9]+)$").unwrap();
+}
+
+/// A page of the printed edition in the page list of an epub
+#[derive(Debug, PartialEq)]
+pub struct PageTarget {
+    pub label: String,
+    /// The link to the start of the page in its content document
+    pub href: String,
+}
+
+/// Replaces the markers of printed pages in a document with empty page break spans that keep
+/// the ids of the markers so that links to the pages still work. Readability strips the
+/// classes that some of the markers are found by so this runs before the article is
+/// extracted.
+pub fn mark_page_breaks(doc: &NodeRef) {
+    let mut ids = doc
+        .descendants()
+        .elements()
+        .filter_map(|elem| elem.attributes.borrow().get("id").map(str::to_owned))
+        .collect::<HashSet<_>>();
+    let markers = doc
+        .descendants()
+        .elements()
+        .filter_map(|elem| {
+            let label = page_marker_label(elem.as_node())?;
+            Some((elem.as_node().clone(), label))
+        })
+        .collect::<Vec<_>>();
+    for (marker, label) in markers {
+        let marker_id = marker.as_element().and_then(|elem| {
+            let attrs = elem.attributes.borrow();
+            attrs
+                .get("id")
+                .or_else(|| attrs.get("name"))
+                .map(str::to_owned)
+        });
+        let id = match marker_id {
+            Some(id) => id,
+            None => {
+                let base_id = format!("page-{}", label);
+                let mut id = base_id.clone();
+                let mut count = 1;
+                while ids.contains(&id) {
+                    count += 1;
+                    id = format!("{}-{}", base_id, count);
+                }
+                id
+            }
+        };
+        ids.insert(id.clone());
+        let page_break = new_element(
+            "span",
+            &[
+                ("role", PAGE_BREAK_ROLE),
+                ("id", &id),
+                ("aria-label", &label),
+            ],
+        );
+        marker.insert_before(page_break);
+        marker.detach();
+    }
+}
+
+/// Returns the page number of an element marking the start of a printed page
+fn page_marker_label(node: &NodeRef) -> Option<String> {
+    let elem = node.as_element()?;
+    let attrs = elem.attributes.borrow();
+    let text = node.text_contents();
+    let text = text.trim();
+    let is_marked = attrs.get("epub:type").map_or(false, |epub_type| {
+        epub_type.split_whitespace().any(|t| t == "pagebreak")
+    }) || attrs.get("role") == Some(PAGE_BREAK_ROLE)
+        || attrs.get("class").map_or(false, |classes| {
+            classes
+                .split_whitespace()
+                .any(|class| PAGE_NUMBER_CLASSES.contains(&class.to_lowercase().as_str()))
+        });
+    if is_marked {
+        return [attrs.get("aria-label"), attrs.get("title"), Some(text)]
+            .iter()
+            .flatten()
+            .find_map(|candidate| {
+                PAGE_LABEL_REGEX
+                    .captures(candidate.trim())
+                    .map(|captures| captures[1].to_owned())
+            });
+    }
+    // Empty anchors such as <a id="page12"></a> link to the pages of some reports
+    if &elem.name.local == "a" && text.is_empty() {
+        return [attrs.get("id"), attrs.get("name")]
+            .iter()
+            .flatten()
+            .find_map(|anchor| {
+                PAGE_ANCHOR_REGEX
+                    .captures(anchor)
+                    .map(|captures| captures[1].to_owned())
+            });
+    }
+    None
+}
+
+/// Finds the page breaks of a content document of an epub
+pub fn collect_page_targets(root: &NodeRef, content_url: &str) -> Vec<PageTarget> {
+    root.select(&format!("span[role=\"{}\"]", PAGE_BREAK_ROLE))
+        .unwrap()
+        .filter_map(|page_break| {
+            let attrs = page_break.attributes.borrow();
+            Some(PageTarget {
+                label: attrs.get("aria-label")?.to_owned(),
+                href: format!("{}#{}", content_url, attrs.get("id")?),
+            })
+        })
+        .collect()
+}
+
+/// Adds the page list to the navigation document of an EPUB 3 package
+pub fn insert_nav_page_list(nav: &str, page_targets: &[PageTarget]) -> String {
+    let items = page_targets
+        .iter()
+        .map(|target| {
+            format!(
+                "<li><a href=\"{}\">{}</a></li>\n",
+                escape(&target.href),
+                escape(&target.label)
+            )
+        })
+        .collect::<String>();
+    let page_list = format!(
+        "<nav epub:type=\"page-list\" hidden=\"hidden\">\n<ol>\n{}</ol>\n</nav>\n",
+        items
+    );
+    nav.replacen("</body>", &format!("{}</body>", page_list), 1)
+}
+
+/// Adds the page list to the NCX of a package. The play order of the pages follows the
+/// entries of the table of contents.
+pub fn insert_ncx_page_list(ncx: &str, page_targets: &[PageTarget]) -> String {
+    lazy_static! {
+        static ref PLAY_ORDER_REGEX: Regex = Regex::new(r#"playOrder="(\d+)""#).unwrap();
+    }
+    let last_play_order = PLAY_ORDER_REGEX
+        .captures_iter(ncx)
+        .filter_map(|captures| captures[1].parse::<usize>().ok())
+        .max()
+        .unwrap_or(0);
+    let targets = page_targets
+        .iter()
+        .enumerate()
+        .map(|(idx, target)| {
+            // Only arabic page numbers are values of pages and roman ones are front matter
+            let type_value = if target.label.chars().all(|c| c.is_ascii_digit()) {
+                format!("type=\"normal\" value=\"{}\"", target.label)
+            } else {
+                "type=\"front\"".to_owned()
+            };
+            format!(
+                "<pageTarget id=\"page-target-{idx}\" {type_value} playOrder=\"{order}\">\
+                <navLabel><text>{label}</text></navLabel><content src=\"{href}\"/></pageTarget>\n",
+                idx = idx + 1,
+                type_value = type_value,
+                order = last_play_order + idx + 1,
+                label = escape(&target.label),
+                href = escape(&target.href)
+            )
+        })
+        .collect::<String>();
+    let page_list = format!(
+        "<pageList>\n<navLabel><text>Pages</text></navLabel>\n{}</pageList>\n",
+        targets
+    );
+    ncx.replacen("</ncx>", &format!("{}</ncx>", page_list), 1)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use kuchiki::traits::*;
+
+    #[test]
+    fn test_mark_page_breaks() {
+        let doc = kuchiki::parse_html().one(
+            r#"<p>Start<span class="pagenum">[12]</span>of a page</p>
+            <div epub:type="pagebreak" title="Page 13" id="p13"></div>
+            <p><a id="page14"></a>Anchored <a href="page15">link</a></p>
+            <p><span role="doc-pagebreak" aria-label="xiv"></span><span class="page-number">Not a number</span></p>"#,
+        );
+        mark_page_breaks(&doc);
+        let page_breaks = doc
+            .select("span[role=\"doc-pagebreak\"]")
+            .unwrap()
+            .map(|page_break| {
+                let attrs = page_break.attributes.borrow();
+                (
+                    attrs.get("id").unwrap().to_owned(),
+                    attrs.get("aria-label").unwrap().to_owned(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("page-12".to_owned(), "12".to_owned()),
+                ("p13".to_owned(), "13".to_owned()),
+                ("page14".to_owned(), "14".to_owned()),
+                ("page-xiv".to_owned(), "xiv".to_owned()),
+            ],
+            page_breaks
+        );
+        assert!(!doc.text_contents().contains("[12]"));
+        assert!(doc.text_contents().contains("Not a number"));
+        assert_eq!(
+            vec![PageTarget {
+                label: "13".into(),
+                href: "article_0.xhtml#p13".into()
+            }],
+            collect_page_targets(&doc, "article_0.xhtml")
+                .into_iter()
+                .filter(|target| target.label == "13")
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_insert_page_lists() {
+        let targets = vec![
+            PageTarget {
+                label: "1".into(),
+                href: "index.xhtml#page-1".into(),
+            },
+            PageTarget {
+                label: "ii".into(),
+                href: "index.xhtml#page-ii".into(),
+            },
+        ];
+        let nav = insert_nav_page_list("<body><nav epub:type=\"toc\"></nav></body>", &targets);
+        assert!(nav.contains(
+            "<nav epub:type=\"page-list\" hidden=\"hidden\">\n<ol>\n<li><a href=\"index.xhtml#page-1\">1</a></li>\n"
+        ));
+        assert!(nav.ends_with("</nav>\n</body>"));
+
+        let ncx = insert_ncx_page_list(
+            r#"<ncx><navMap><navPoint playOrder="1"/><navPoint playOrder="2"/></navMap></ncx>"#,
+            &targets,
+        );
+        assert!(ncx.contains("type=\"normal\" value=\"1\" playOrder=\"3\""));
+        assert!(ncx.contains("type=\"front\" playOrder=\"4\""));
+        assert!(ncx.ends_with("</pageList>\n</ncx>"));
+    }
+}
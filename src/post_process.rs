@@ -0,0 +1,94 @@
+use std::process::Command;
+
+use log::{debug, error};
+
+use crate::errors::{ErrorKind, PaperoniError};
+
+/// The part of the --post-process command replaced by the path of each exported file
+pub const FILE_PLACEHOLDER: &str = "{file}";
+
+/// Runs the --post-process command on each exported file. The files are processed one after
+/// the other and a command failing on one of them is reported with the file as its source.
+pub fn post_process_files(command: &str, files: &[String]) -> Vec<PaperoniError> {
+    files
+        .iter()
+        .filter_map(|file| {
+            let file_command = command.replace(FILE_PLACEHOLDER, &quote_path(file));
+            debug!("Running {:?}", file_command);
+            match run_command(&file_command) {
+                Ok(_) => None,
+                Err(reason) => {
+                    error!("Failed to post-process {}: {}", file, reason);
+                    let mut err: PaperoniError = ErrorKind::PostProcessError(format!(
+                        "{:?} failed: {}",
+                        file_command, reason
+                    ))
+                    .into();
+                    err.set_article_source(file);
+                    Some(err)
+                }
+            }
+        })
+        .collect()
+}
+
+fn run_command(command: &str) -> Result<(), String> {
+    let (shell, shell_arg) = if cfg!(target_os = "windows") {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let output = Command::new(shell)
+        .args(&[shell_arg, command])
+        .output()
+        .map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(match stderr.trim() {
+            "" => output.status.to_string(),
+            stderr => stderr.to_owned(),
+        })
+    }
+}
+
+/// Quotes a path so that the shell passes it as a single argument
+fn quote_path(path: &str) -> String {
+    if cfg!(target_os = "windows") {
+        format!("\"{}\"", path)
+    } else {
+        format!("'{}'", path.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn test_quote_path() {
+        assert_eq!("'out/My article.epub'", quote_path("out/My article.epub"));
+        assert_eq!(r"'./Don'\''t panic.epub'", quote_path("./Don't panic.epub"));
+    }
+
+    #[test]
+    fn test_post_process_files() {
+        let dir =
+            std::env::temp_dir().join(format!("paperoni-post-process-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let exported = dir.join("Don't panic.epub");
+        std::fs::write(&exported, "epub").unwrap();
+        let exported = exported.to_string_lossy().into_owned();
+        let missing = dir.join("missing.epub").to_string_lossy().into_owned();
+
+        let errors =
+            post_process_files("cp {file} {file}.bak", &[exported.clone(), missing.clone()]);
+        assert_eq!(1, errors.len());
+        assert_eq!(&Some(missing), errors[0].article_source());
+        assert!(Path::new(&format!("{}.bak", exported)).is_file());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
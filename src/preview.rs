@@ -0,0 +1,350 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use async_std::task;
+use kuchiki::{traits::*, NodeRef};
+use log::info;
+use serde::Deserialize;
+use tide::{Request, Response, StatusCode};
+
+use crate::bench::load_html;
+use crate::cli::{AppConfig, CSSConfig, PreviewOptions};
+use crate::errors::PaperoniError;
+use crate::extractor::{new_element, Article};
+use crate::html::{inline_css, inline_typography_css};
+
+/// How often the preview page checks whether the input file changed
+const RELOAD_INTERVAL_MS: u32 = 1000;
+
+#[derive(Clone)]
+struct PreviewState {
+    app_config: Arc<AppConfig>,
+    input: String,
+    /// The url and HTML of the page. Pages fetched from a url are only fetched once while
+    /// HTML files are read again on every request so that edits show up.
+    source: Arc<(String, String)>,
+}
+
+/// The options edited in the toolbar of the preview page. Options missing from the query
+/// are taken from the options passed to paperoni.
+#[derive(Debug, Default, Deserialize)]
+struct PreviewQuery {
+    selector: Option<String>,
+    /// The selectors of removed elements, one per line
+    remove: Option<String>,
+    /// Either "all", "no-header-css" or "no-css"
+    css: Option<String>,
+}
+
+/// The options an article is previewed with after merging the query with the options
+/// passed to paperoni
+#[derive(Debug, PartialEq)]
+struct PreviewSettings {
+    selector: Option<String>,
+    remove_selectors: Vec<String>,
+    css: &'static str,
+}
+
+impl PreviewSettings {
+    fn new(query: PreviewQuery, app_config: &AppConfig, url: &str) -> Self {
+        let site_rule = app_config.site_rule(url);
+        let selector = match query.selector {
+            Some(selector) => Some(selector),
+            None => site_rule.and_then(|rule| rule.selector.clone()),
+        }
+        .map(|selector| selector.trim().to_owned())
+        .filter(|selector| !selector.is_empty());
+        let remove_selectors = match query.remove {
+            Some(remove) => remove
+                .lines()
+                .map(str::trim)
+                .filter(|selector| !selector.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            None => app_config
+                .remove_selectors
+                .iter()
+                .chain(
+                    site_rule
+                        .iter()
+                        .flat_map(|rule| rule.remove_selectors.iter()),
+                )
+                .cloned()
+                .collect(),
+        };
+        let css = match query.css.as_deref() {
+            Some("no-css") => "no-css",
+            Some("no-header-css") => "no-header-css",
+            Some(_) => "all",
+            None => match app_config.css_config {
+                CSSConfig::All => "all",
+                CSSConfig::NoHeaders => "no-header-css",
+                CSSConfig::None => "no-css",
+            },
+        };
+        Self {
+            selector,
+            remove_selectors,
+            css,
+        }
+    }
+
+    fn css_config(&self) -> CSSConfig {
+        match self.css {
+            "no-css" => CSSConfig::None,
+            "no-header-css" => CSSConfig::NoHeaders,
+            _ => CSSConfig::All,
+        }
+    }
+
+    /// The options that export the article the way it is previewed
+    fn equivalent_options(&self, input: &str) -> Vec<String> {
+        let mut options = Vec::new();
+        if let Some(selector) = &self.selector {
+            options.push(format!("{} | selector={}", input, selector));
+        }
+        let mut flags = vec![format!("paperoni {}", input)];
+        for selector in &self.remove_selectors {
+            flags.push(format!("--remove-selector {:?}", selector));
+        }
+        if self.css != "all" {
+            flags.push(format!("--{}", self.css));
+        }
+        options.push(flags.join(" "));
+        options
+    }
+}
+
+/// Serves the extracted article on a local address, re-extracting it with the options
+/// edited in the page so that selectors and styles can be tried before exporting
+pub fn run_preview(app_config: AppConfig, options: &PreviewOptions) -> Result<(), PaperoniError> {
+    let source = load_html(&app_config, &options.input)?;
+    let state = PreviewState {
+        app_config: Arc::new(app_config),
+        input: options.input.clone(),
+        source: Arc::new(source),
+    };
+    let mut app = tide::with_state(state);
+    app.at("/").get(preview);
+    app.at("/version").get(version);
+
+    println!(
+        "Previewing {} on http://{}. Press Ctrl+C to stop",
+        options.input, options.address
+    );
+    task::block_on(app.listen(options.address.clone()))?;
+    Ok(())
+}
+
+/// Returns when the input file was last modified so that the page reloads after it changes.
+/// Pages fetched from a url never change.
+fn input_version(input: &str) -> String {
+    fs::metadata(Path::new(input))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|since_epoch| since_epoch.as_millis().to_string())
+        .unwrap_or_default()
+}
+
+async fn version(req: Request<PreviewState>) -> tide::Result {
+    Ok(Response::builder(StatusCode::Ok)
+        .body(input_version(&req.state().input))
+        .build())
+}
+
+async fn preview(req: Request<PreviewState>) -> tide::Result {
+    let query = req.query::<PreviewQuery>()?;
+    let state = req.state();
+    let (url, html) = if Path::new(&state.input).is_file() {
+        load_html(&state.app_config, &state.input)
+            .map_err(|e| tide::Error::from_str(StatusCode::InternalServerError, e.to_string()))?
+    } else {
+        (state.source.0.clone(), state.source.1.clone())
+    };
+    let settings = PreviewSettings::new(query, &state.app_config, &url);
+    info!("Previewing {} with {:?}", url, settings);
+
+    let mut article = Article::from_html(&html, &url);
+    article.set_extraction_budget(state.app_config.extraction_budget);
+    let extract_result = match &settings.selector {
+        Some(selector) => article.extract_content_with_selector(selector),
+        None => article.extract_content(),
+    };
+    let doc = match extract_result {
+        Ok(_) => {
+            article.remove_elements(&settings.remove_selectors);
+            let doc = article.node_ref().clone();
+            inline_css(&doc, &settings.css_config());
+            inline_typography_css(&doc, &state.app_config);
+            doc
+        }
+        Err(e) => {
+            let doc = kuchiki::parse_html().one("<html><head></head><body></body></html>");
+            let message = new_element("p", &[]);
+            message.append(NodeRef::new_text(format!(
+                "Unable to extract {}: {}",
+                url, e
+            )));
+            doc.select_first("body").unwrap().as_node().append(message);
+            doc
+        }
+    };
+    if let Ok(body) = doc.select_first("body") {
+        body.as_node().prepend(toolbar(
+            &settings,
+            &state.input,
+            &input_version(&state.input),
+        ));
+    }
+
+    Ok(Response::builder(StatusCode::Ok)
+        .body(doc.to_string())
+        .content_type(tide::http::mime::HTML)
+        .build())
+}
+
+/// The form editing the options of the preview, which is submitted whenever an option
+/// changes, followed by the options to pass to paperoni to export the article as previewed
+fn toolbar(settings: &PreviewSettings, input: &str, version: &str) -> NodeRef {
+    let toolbar = new_element(
+        "div",
+        &[(
+            "style",
+            "font: 14px sans-serif; padding: 8px; margin-bottom: 16px; background: #f3f3f3; border-bottom: 1px solid #ccc;",
+        )],
+    );
+    let form = new_element(
+        "form",
+        &[
+            ("method", "get"),
+            ("action", "/"),
+            ("onchange", "this.submit()"),
+        ],
+    );
+    let selector_label = new_element("label", &[]);
+    selector_label.append(NodeRef::new_text("Selector "));
+    selector_label.append(new_element(
+        "input",
+        &[
+            ("name", "selector"),
+            ("placeholder", "readability"),
+            ("value", settings.selector.as_deref().unwrap_or_default()),
+        ],
+    ));
+    form.append(selector_label);
+
+    let remove_label = new_element("label", &[]);
+    remove_label.append(NodeRef::new_text(" Removed elements "));
+    let remove = new_element(
+        "textarea",
+        &[
+            ("name", "remove"),
+            ("rows", "2"),
+            ("placeholder", "One selector per line"),
+        ],
+    );
+    remove.append(NodeRef::new_text(settings.remove_selectors.join("\n")));
+    remove_label.append(remove);
+    form.append(remove_label);
+
+    let css_label = new_element("label", &[]);
+    css_label.append(NodeRef::new_text(" Styles "));
+    let css = new_element("select", &[("name", "css")]);
+    for (value, text) in &[
+        ("all", "All"),
+        ("no-header-css", "No header styles"),
+        ("no-css", "None"),
+    ] {
+        let mut attrs = vec![("value", *value)];
+        if *value == settings.css {
+            attrs.push(("selected", ""));
+        }
+        let option = new_element("option", &attrs);
+        option.append(NodeRef::new_text(*text));
+        css.append(option);
+    }
+    css_label.append(css);
+    form.append(css_label);
+    let submit = new_element("button", &[("type", "submit")]);
+    submit.append(NodeRef::new_text("Extract"));
+    form.append(submit);
+    toolbar.append(form);
+
+    for option in settings.equivalent_options(input) {
+        let code = new_element("code", &[("style", "display: block; margin-top: 4px;")]);
+        code.append(NodeRef::new_text(option));
+        toolbar.append(code);
+    }
+
+    let script = new_element("script", &[]);
+    script.append(NodeRef::new_text(format!(
+        "setInterval(function () {{
+            fetch('/version').then(function (res) {{ return res.text(); }}).then(function (version) {{
+                if (version !== {:?}) location.reload();
+            }});
+        }}, {});",
+        version, RELOAD_INTERVAL_MS
+    )));
+    toolbar.append(script);
+    toolbar
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_preview_settings() {
+        let url = "https://example.com/article";
+        let app_config = AppConfig::try_from(
+            clap::App::from_yaml(clap::load_yaml!("cli_config.yml")).get_matches_from(vec![
+                "paperoni",
+                "--remove-selector",
+                ".newsletter",
+                "--no-header-css",
+                "preview",
+                url,
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            Some("127.0.0.1:8000"),
+            app_config
+                .preview
+                .as_ref()
+                .map(|options| options.address.as_str())
+        );
+
+        let settings = PreviewSettings::new(PreviewQuery::default(), &app_config, url);
+        assert_eq!(
+            PreviewSettings {
+                selector: None,
+                remove_selectors: vec![".newsletter".to_owned()],
+                css: "no-header-css",
+            },
+            settings
+        );
+
+        let query = PreviewQuery {
+            selector: Some(" div.post ".to_owned()),
+            remove: Some(".ads\n\n aside \n".to_owned()),
+            css: Some("no-css".to_owned()),
+        };
+        let settings = PreviewSettings::new(query, &app_config, url);
+        assert_eq!(Some("div.post"), settings.selector.as_deref());
+        assert_eq!(vec![".ads", "aside"], settings.remove_selectors);
+        assert!(matches!(settings.css_config(), CSSConfig::None));
+        assert_eq!(
+            vec![
+                "https://example.com/article | selector=div.post".to_owned(),
+                r#"paperoni https://example.com/article --remove-selector ".ads" --remove-selector "aside" --no-css"#
+                    .to_owned()
+            ],
+            settings.equivalent_options(url)
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use kuchiki::NodeRef;
+
+use crate::cli::PrintLinks;
+use crate::extractor::new_element;
+
+/// Class of the list of link targets appended to an article with footnoted links
+const LINK_NOTES_CLASS: &str = "paperoni-link-notes";
+
+/// Writes the targets of the links of an article next to them or as numbered notes at its
+/// end so that they are not lost when the article is printed or read on e-ink. Links within
+/// the article and links whose text is already their url are left as they are.
+pub fn annotate_links(root: &NodeRef, print_links: PrintLinks) {
+    let page = match root
+        .select_first("div[id=\"readability-page-1\"]")
+        .or_else(|_| root.select_first("body"))
+    {
+        Ok(page) => page.as_node().clone(),
+        Err(_) => root.clone(),
+    };
+    let links = match page.select("a[href]") {
+        Ok(links) => links.collect::<Vec<_>>(),
+        Err(_) => return,
+    };
+
+    let mut notes: Vec<String> = Vec::new();
+    let mut note_numbers: HashMap<String, usize> = HashMap::new();
+    for link in links {
+        let target = match link
+            .attributes
+            .borrow()
+            .get("href")
+            .and_then(printed_target)
+        {
+            Some(target) => target,
+            None => continue,
+        };
+        if bare_url(&link.text_contents()) == bare_url(&target) {
+            continue;
+        }
+        match print_links {
+            PrintLinks::Inline => link
+                .as_node()
+                .insert_after(NodeRef::new_text(format!(" ({})", target))),
+            PrintLinks::Footnotes => {
+                let number = *note_numbers.entry(target.clone()).or_insert_with(|| {
+                    notes.push(target);
+                    notes.len()
+                });
+                let marker = new_element("sup", &[]);
+                marker.append(NodeRef::new_text(format!("[{}]", number)));
+                link.as_node().insert_after(marker);
+            }
+        }
+    }
+
+    if !notes.is_empty() {
+        let section = new_element("section", &[("class", LINK_NOTES_CLASS)]);
+        section.append(new_element("hr", &[]));
+        let list = new_element("ol", &[]);
+        for note in notes {
+            let item = new_element("li", &[]);
+            item.append(NodeRef::new_text(note));
+            list.append(item);
+        }
+        section.append(list);
+        page.append(section);
+    }
+}
+
+/// The target of a link as it is printed, which is `None` for links within the article
+fn printed_target(href: &str) -> Option<String> {
+    let href = href.trim();
+    let lowercase_href = href.to_lowercase();
+    if lowercase_href.starts_with("http://") || lowercase_href.starts_with("https://") {
+        Some(href.to_owned())
+    } else if lowercase_href.starts_with("mailto:") {
+        Some(href["mailto:".len()..].to_owned())
+    } else {
+        None
+    }
+}
+
+/// A url without its scheme, `www.` and trailing slash to compare it with the text of a link
+fn bare_url(url: &str) -> &str {
+    let url = url.trim();
+    let url = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .unwrap_or(url);
+    url.trim_start_matches("www.").trim_end_matches('/')
+}
+
+#[cfg(test)]
+mod test {
+    use kuchiki::traits::*;
+
+    use super::*;
+
+    const ARTICLE: &str = r##"<html><body><div id="readability-page-1"><p>Read <a href="https://example.com/pizza">this</a>, <a href="https://example.com/pizza">that</a> and <a href="https://example.com/">example.com</a>. Mail <a href="mailto:chef@example.com">the chef</a> or go to <a href="#notes">the notes</a>.</p></div></body></html>"##;
+
+    fn annotated(print_links: PrintLinks) -> String {
+        let doc = kuchiki::parse_html().one(ARTICLE);
+        annotate_links(&doc, print_links);
+        let page = doc.select_first("div").unwrap();
+        let mut html = Vec::new();
+        for child in page.as_node().children() {
+            child.serialize(&mut html).unwrap();
+        }
+        String::from_utf8(html).unwrap()
+    }
+
+    #[test]
+    fn test_footnotes() {
+        assert_eq!(
+            r##"<p>Read <a href="https://example.com/pizza">this</a><sup>[1]</sup>, <a href="https://example.com/pizza">that</a><sup>[1]</sup> and <a href="https://example.com/">example.com</a>. Mail <a href="mailto:chef@example.com">the chef</a><sup>[2]</sup> or go to <a href="#notes">the notes</a>.</p><section class="paperoni-link-notes"><hr><ol><li>https://example.com/pizza</li><li>chef@example.com</li></ol></section>"##,
+            annotated(PrintLinks::Footnotes)
+        );
+    }
+
+    #[test]
+    fn test_inline() {
+        assert_eq!(
+            r##"<p>Read <a href="https://example.com/pizza">this</a> (https://example.com/pizza), <a href="https://example.com/pizza">that</a> (https://example.com/pizza) and <a href="https://example.com/">example.com</a>. Mail <a href="mailto:chef@example.com">the chef</a> (chef@example.com) or go to <a href="#notes">the notes</a>.</p>"##,
+            annotated(PrintLinks::Inline)
+        );
+    }
+}
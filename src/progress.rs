@@ -0,0 +1,151 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use log::debug;
+use serde::Serialize;
+
+/// An event of the progress of a run written as a line of JSON, so programs wrapping paperoni
+/// can show their own progress. Each event has the url the article was requested from.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent<'a> {
+    /// The page of the article is being fetched
+    Started { url: &'a str },
+    /// The page was fetched from `final_url` after following redirects
+    Fetched {
+        url: &'a str,
+        final_url: &'a str,
+        bytes: usize,
+    },
+    /// An image of the article finished downloading, whether it succeeded or not
+    Images {
+        url: &'a str,
+        done: usize,
+        total: usize,
+    },
+    /// The article was left out such as when it is outside of the date range
+    Skipped { url: &'a str, reason: &'a str },
+    /// The article failed to download or to export
+    Failed { url: &'a str, reason: String },
+    /// The article was written to the exports
+    Exported { url: &'a str, title: &'a str },
+}
+
+/// Where the progress events are written. It is shared by the clones of the app config.
+#[derive(Clone)]
+pub struct ProgressEvents {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl ProgressEvents {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+        }
+    }
+
+    /// Writes the events to the standard error, which the progress bars are drawn to otherwise
+    pub fn stderr() -> Self {
+        Self::new(io::stderr())
+    }
+
+    /// Writes the events to a file descriptor opened by the program running paperoni, such as
+    /// the write end of a pipe
+    #[cfg(unix)]
+    pub fn from_fd(fd: i32) -> io::Result<Self> {
+        use std::fs::File;
+        use std::os::unix::io::FromRawFd;
+
+        // The descriptor is owned by paperoni from here on and is closed when it exits
+        let file = unsafe { File::from_raw_fd(fd) };
+        // Fails when the descriptor is not open
+        file.metadata()?;
+        Ok(Self::new(file))
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_fd(_fd: i32) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "file descriptors can only be passed on Unix",
+        ))
+    }
+
+    /// Writes an event and flushes it right away so it is read while the run goes on. Failing
+    /// to write is not an error of the run, so it is only logged.
+    pub fn emit(&self, event: &ProgressEvent) {
+        let mut writer = self.writer.lock().unwrap();
+        let result = writeln!(writer, "{}", event_line(event)).and_then(|_| writer.flush());
+        if let Err(e) = result {
+            debug!("Unable to write the progress event {:?}: {}", event, e);
+        }
+    }
+}
+
+impl fmt::Debug for ProgressEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressEvents").finish()
+    }
+}
+
+fn event_line(event: &ProgressEvent) -> String {
+    serde_json::to_string(event).expect("Progress events are always serializable")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A writer whose bytes can be read by the test after it is moved into the events
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_event_line() {
+        assert_eq!(
+            r#"{"event":"started","url":"https://example.com/a"}"#,
+            event_line(&ProgressEvent::Started {
+                url: "https://example.com/a"
+            })
+        );
+        assert_eq!(
+            r#"{"event":"images","url":"https://example.com/a","done":2,"total":5}"#,
+            event_line(&ProgressEvent::Images {
+                url: "https://example.com/a",
+                done: 2,
+                total: 5
+            })
+        );
+    }
+
+    #[test]
+    fn test_emit() {
+        let buffer = SharedBuffer::default();
+        let events = ProgressEvents::new(buffer.clone());
+        events.emit(&ProgressEvent::Fetched {
+            url: "http://example.com/a",
+            final_url: "https://example.com/a",
+            bytes: 120,
+        });
+        events.clone().emit(&ProgressEvent::Failed {
+            url: "https://example.com/b",
+            reason: "[HTTPError]: 404".to_owned(),
+        });
+        assert_eq!(
+            "{\"event\":\"fetched\",\"url\":\"http://example.com/a\",\"final_url\":\"https://example.com/a\",\"bytes\":120}\n\
+            {\"event\":\"failed\",\"url\":\"https://example.com/b\",\"reason\":\"[HTTPError]: 404\"}\n",
+            String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap()
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use async_std::{stream, task};
+use futures::StreamExt;
+use kuchiki::traits::*;
+use log::{debug, info};
+use serde_json::{json, Value};
+
+use crate::cli::{AppConfig, PushOptions, PushService};
+use crate::errors::{ErrorKind, PaperoniError};
+use crate::extractor::{clean_internal_attrs, Article, ORIGINAL_SRC_ATTR};
+use crate::http::build_client;
+
+const READWISE_SAVE_URL: &str = "https://readwise.io/api/v3/save/";
+const OMNIVORE_GRAPHQL_URL: &str = "https://api-prod.omnivore.app/api/graphql";
+const OMNIVORE_SAVE_PAGE_MUTATION: &str = "mutation SavePage($input: SavePageInput!) {
+  savePage(input: $input) {
+    ... on SaveSuccess { url }
+    ... on SaveError { errorCodes message }
+  }
+}";
+
+/// Sends the extracted articles with their metadata to the reading service so they can be
+/// read there. Returns the number of articles pushed and the errors of the ones that failed.
+pub fn push_articles(
+    articles: &[Article],
+    app_config: &AppConfig,
+    options: &PushOptions,
+) -> (usize, Vec<PaperoniError>) {
+    task::block_on(async {
+        let client = build_client(app_config, None);
+        let push_iter = articles.iter().map(|article| {
+            let client = &client;
+            async move {
+                push_article(client, article, options, app_config)
+                    .await
+                    .map_err(|mut err| {
+                        err.set_article_source(&article.url);
+                        err
+                    })
+            }
+        });
+        let mut results = stream::from_iter(push_iter).buffered(app_config.max_conn);
+        let mut pushed_count = 0;
+        let mut errors = Vec::new();
+        while let Some(result) = results.next().await {
+            match result {
+                Ok(_) => pushed_count += 1,
+                Err(err) => errors.push(err),
+            }
+        }
+        (pushed_count, errors)
+    })
+}
+
+async fn push_article(
+    client: &surf::Client,
+    article: &Article,
+    options: &PushOptions,
+    app_config: &AppConfig,
+) -> Result<(), PaperoniError> {
+    let html = content_html(article);
+    let (endpoint, authorization, payload) = match options.service {
+        PushService::Readwise => (
+            READWISE_SAVE_URL,
+            format!("Token {}", options.token),
+            readwise_payload(article, &html),
+        ),
+        PushService::Omnivore => (
+            OMNIVORE_GRAPHQL_URL,
+            options.token.clone(),
+            omnivore_payload(article, &html, &app_config.start_time.to_rfc3339()),
+        ),
+    };
+    debug!("Pushing {} to {}", article.url, options.service.name());
+
+    let request = surf::post(endpoint)
+        .header("Authorization", authorization)
+        .body(surf::Body::from_json(&payload)?);
+    let mut response = client.send(request).await?;
+    let status = response.status();
+    let body = response.body_string().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(ErrorKind::PushError(format!(
+            "{} responded with {}: {}",
+            options.service.name(),
+            status,
+            body.trim()
+        ))
+        .into());
+    }
+    // GraphQL errors are returned with a successful status
+    if let PushService::Omnivore = options.service {
+        let response_json = serde_json::from_str::<Value>(&body).unwrap_or(Value::Null);
+        if let Err(reason) = check_omnivore_response(&response_json) {
+            return Err(
+                ErrorKind::PushError(format!("Omnivore rejected the article: {}", reason)).into(),
+            );
+        }
+    }
+    info!("Pushed {} to {}", article.url, options.service.name());
+    Ok(())
+}
+
+/// Serializes the content of an article with its images pointing back to their original
+/// urls as the services cannot reach the copies downloaded by paperoni
+fn content_html(article: &Article) -> String {
+    let content = article
+        .node_ref()
+        .select_first("div[id=\"readability-page-1\"]")
+        .map(|page| page.as_node().to_string())
+        .unwrap_or_else(|_| article.node_ref().to_string());
+    let doc = kuchiki::parse_html().one(content);
+    for img in doc.select("img").unwrap() {
+        let mut attrs = img.attributes.borrow_mut();
+        if let Some(original_src) = attrs.get(ORIGINAL_SRC_ATTR).map(ToOwned::to_owned) {
+            attrs.insert("src", original_src);
+        }
+    }
+    clean_internal_attrs(&doc);
+    match doc.select_first("body") {
+        Ok(body) => body
+            .as_node()
+            .children()
+            .map(|child| child.to_string())
+            .collect(),
+        Err(_) => doc.to_string(),
+    }
+}
+
+/// The body of a request to the save endpoint of the Readwise Reader API
+fn readwise_payload(article: &Article, html: &str) -> Value {
+    let metadata = article.metadata();
+    let mut payload = json!({
+        "url": article.url,
+        "html": html,
+        "should_clean_html": false,
+        "title": metadata.title(),
+        "saved_using": "paperoni",
+    });
+    if let Some(byline) = metadata.byline() {
+        payload["author"] = json!(byline);
+    }
+    if let Some(published) = metadata.published() {
+        payload["published_date"] = json!(published);
+    }
+    if let Some(image) = metadata.image() {
+        payload["image_url"] = json!(image);
+    }
+    if !metadata.keywords().is_empty() {
+        payload["tags"] = json!(metadata.keywords());
+    }
+    payload
+}
+
+/// The GraphQL request saving an article to Omnivore. The client request id lets Omnivore
+/// tell apart retries of the same save from a run.
+fn omnivore_payload(article: &Article, html: &str, run_id: &str) -> Value {
+    let metadata = article.metadata();
+    let labels = metadata
+        .keywords()
+        .iter()
+        .map(|keyword| json!({ "name": keyword }))
+        .collect::<Vec<_>>();
+    json!({
+        "query": OMNIVORE_SAVE_PAGE_MUTATION,
+        "variables": {
+            "input": {
+                "clientRequestId": client_request_id(&article.url, run_id),
+                "source": "api",
+                "url": article.url,
+                "title": metadata.title(),
+                "originalContent": html,
+                "labels": labels,
+            }
+        }
+    })
+}
+
+/// Formats a hash of the article url and the run as a UUID which Omnivore expects
+fn client_request_id(url: &str, run_id: &str) -> String {
+    let hash = format!("{:x}", md5::compute(format!("{}{}", run_id, url)));
+    format!(
+        "{}-{}-3{}-8{}-{}",
+        &hash[..8],
+        &hash[8..12],
+        &hash[13..16],
+        &hash[17..20],
+        &hash[20..32]
+    )
+}
+
+fn check_omnivore_response(response: &Value) -> Result<(), String> {
+    if let Some(errors) = response["errors"].as_array() {
+        let messages = errors
+            .iter()
+            .filter_map(|err| err["message"].as_str())
+            .collect::<Vec<_>>();
+        return Err(messages.join(", "));
+    }
+    let save_page = &response["data"]["savePage"];
+    if let Some(error_codes) = save_page["errorCodes"].as_array() {
+        let codes = error_codes
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>();
+        return Err(save_page["message"]
+            .as_str()
+            .map(ToOwned::to_owned)
+            .unwrap_or_else(|| codes.join(", ")));
+    }
+    if save_page["url"].is_string() {
+        Ok(())
+    } else {
+        Err("unexpected response".to_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEST_HTML: &str = r#"<html><head>
+        <title>A pushed article</title>
+        <meta name="author" content="Jane Doe">
+        <meta name="keywords" content="rust, reading">
+        </head><body><article>
+        <p>The first paragraph of an article that is long enough to be kept by readability, with commas, words and more words to read.</p>
+        <p><img src="https://example.com/cat.png" alt="A cat"></p>
+        <p>The second paragraph of an article that is long enough to be kept by readability, with commas, words and more words to read.</p>
+        </article></body></html>"#;
+
+    fn extracted_article() -> Article {
+        let mut article = Article::from_html(TEST_HTML, "https://example.com/post");
+        article.extract_content().unwrap();
+        article
+    }
+
+    #[test]
+    fn test_content_html() {
+        let article = extracted_article();
+        let img = article.node_ref().select_first("img").unwrap();
+        {
+            let mut attrs = img.attributes.borrow_mut();
+            attrs.insert("src", "0a1b2c.png".to_owned());
+            attrs.insert(ORIGINAL_SRC_ATTR, "https://example.com/cat.png".to_owned());
+        }
+
+        let html = content_html(&article);
+        assert!(html.contains(r#"src="https://example.com/cat.png""#));
+        assert!(!html.contains("0a1b2c.png"));
+        assert!(!html.contains(ORIGINAL_SRC_ATTR));
+        assert!(!html.contains("<body>"));
+    }
+
+    #[test]
+    fn test_payloads() {
+        let article = extracted_article();
+        let readwise = readwise_payload(&article, "<p>Hi</p>");
+        assert_eq!("https://example.com/post", readwise["url"]);
+        assert_eq!("<p>Hi</p>", readwise["html"]);
+        assert_eq!("A pushed article", readwise["title"]);
+        assert_eq!("Jane Doe", readwise["author"]);
+        assert_eq!(json!(["rust", "reading"]), readwise["tags"]);
+        assert!(readwise.get("published_date").is_none());
+
+        let omnivore = omnivore_payload(&article, "<p>Hi</p>", "run");
+        let input = &omnivore["variables"]["input"];
+        assert_eq!("https://example.com/post", input["url"]);
+        assert_eq!("<p>Hi</p>", input["originalContent"]);
+        assert_eq!(
+            json!([{"name": "rust"}, {"name": "reading"}]),
+            input["labels"]
+        );
+        assert_eq!(
+            client_request_id("https://example.com/post", "run"),
+            input["clientRequestId"]
+        );
+    }
+
+    #[test]
+    fn test_client_request_id() {
+        let id = client_request_id("https://example.com/post", "run");
+        let groups = id.split('-').map(str::len).collect::<Vec<_>>();
+        assert_eq!(vec![8, 4, 4, 4, 12], groups);
+        assert_eq!(id, client_request_id("https://example.com/post", "run"));
+        assert_ne!(
+            id,
+            client_request_id("https://example.com/post", "other run")
+        );
+    }
+
+    #[test]
+    fn test_check_omnivore_response() {
+        assert_eq!(
+            Ok(()),
+            check_omnivore_response(
+                &json!({"data": {"savePage": {"url": "https://omnivore.app/me/post"}}})
+            )
+        );
+        assert_eq!(
+            Err("Invalid url".to_owned()),
+            check_omnivore_response(
+                &json!({"data": {"savePage": {"errorCodes": ["BAD_REQUEST"], "message": "Invalid url"}}})
+            )
+        );
+        assert_eq!(
+            Err("UNAUTHORIZED".to_owned()),
+            check_omnivore_response(
+                &json!({"data": {"savePage": {"errorCodes": ["UNAUTHORIZED"]}}})
+            )
+        );
+        assert_eq!(
+            Err("Unknown field".to_owned()),
+            check_omnivore_response(&json!({"errors": [{"message": "Unknown field"}]}))
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+use url::Url;
+
+use crate::cjk;
+use crate::cli::AppConfig;
+use crate::errors::PaperoniError;
+use crate::extractor::Article;
+use crate::http::PartialDownload;
+
+const REPORT_STYLE: &str = "body{font-family:sans-serif;max-width:60em;margin:2em auto;padding:0 1em;color:#222}table{border-collapse:collapse;width:100%;margin-bottom:2em}th,td{border-bottom:1px solid #ddd;padding:.5em;text-align:left;vertical-align:middle}td.thumbnail{width:6em}td.thumbnail img{max-width:6em;max-height:4em}td.count{text-align:right}.failed{color:#b00020}.partial{color:#a15c00}";
+
+/// The details of a downloaded article shown in the report
+pub struct ReportEntry {
+    title: String,
+    url: String,
+    word_count: usize,
+    thumbnail: Option<String>,
+}
+
+/// Collects the details of the articles for the report before they are exported
+pub fn collect_report_entries(articles: &[Article]) -> Vec<ReportEntry> {
+    articles
+        .iter()
+        .map(|article| {
+            let thumbnail = article.metadata().image().and_then(|image| {
+                Url::parse(&article.url)
+                    .ok()?
+                    .join(image)
+                    .ok()
+                    .map(String::from)
+            });
+            ReportEntry {
+                title: article.metadata().title().to_owned(),
+                url: article.url.clone(),
+                word_count: cjk::split_words(&article.node_ref().text_contents()).len(),
+                thumbnail,
+            }
+        })
+        .collect()
+}
+
+/// Writes an HTML report of the results of the run to the path passed to `--report`.
+/// `article_files` are the files the articles were written to by the export.
+pub fn write_report(
+    path: &str,
+    entries: &[ReportEntry],
+    article_files: &[Option<String>],
+    partial_downloads: &[PartialDownload],
+    errors: &[PaperoniError],
+    app_config: &AppConfig,
+) -> Result<(), PaperoniError> {
+    let report = generate_report(
+        entries,
+        article_files,
+        partial_downloads,
+        errors,
+        &app_config.start_time,
+        |file_name| {
+            fs::canonicalize(file_name)
+                .ok()
+                .and_then(|path| Url::from_file_path(path).ok())
+                .map(String::from)
+        },
+    );
+    fs::write(Path::new(path), report)?;
+    Ok(())
+}
+
+/// Builds the report. `file_link` returns the link to a generated file if it exists.
+fn generate_report(
+    entries: &[ReportEntry],
+    article_files: &[Option<String>],
+    partial_downloads: &[PartialDownload],
+    errors: &[PaperoniError],
+    start_time: &DateTime<Local>,
+    file_link: impl Fn(&str) -> Option<String>,
+) -> String {
+    let failed_sources = errors
+        .iter()
+        .filter_map(|error| error.article_source().as_deref())
+        .collect::<HashSet<_>>();
+    let partial_links = partial_downloads
+        .iter()
+        .map(|partial| partial.link.as_str())
+        .collect::<HashSet<_>>();
+    let successful_entries = entries
+        .iter()
+        .zip(article_files)
+        .filter(|(entry, _)| !failed_sources.contains(entry.url.as_str()))
+        .collect::<Vec<_>>();
+    let partial_count = successful_entries
+        .iter()
+        .filter(|(entry, _)| partial_links.contains(entry.url.as_str()))
+        .count();
+
+    let mut report = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Paperoni report</title>\n<style>{}</style>\n</head>\n<body>\n<h1>Paperoni report</h1>\n<p>Generated on {}. {} downloaded, {} partially downloaded, {} failed.</p>\n",
+        REPORT_STYLE,
+        start_time.format("%-d %B %Y %H:%M:%S"),
+        successful_entries.len() - partial_count,
+        partial_count,
+        errors.len()
+    );
+
+    if !successful_entries.is_empty() {
+        report.push_str("<h2>Articles</h2>\n<table>\n<tr><th></th><th>Title</th><th>File</th><th>Words</th></tr>\n");
+        for (entry, file_name) in successful_entries {
+            let thumbnail = entry
+                .thumbnail
+                .as_ref()
+                .map(|thumbnail| format!("<img src=\"{}\" alt=\"\">", escape_text(thumbnail)))
+                .unwrap_or_default();
+            // Articles are not written when the existing file is kept with --overwrite skip
+            let file = match file_name {
+                Some(file_name) => match file_link(file_name) {
+                    Some(link) => format!(
+                        "<a href=\"{}\">{}</a>",
+                        escape_text(&link),
+                        escape_text(file_name)
+                    ),
+                    None => escape_text(file_name),
+                },
+                None => String::new(),
+            };
+            let status = if partial_links.contains(entry.url.as_str()) {
+                " <span class=\"partial\">(some images failed to download)</span>"
+            } else {
+                ""
+            };
+            report.push_str(&format!(
+                "<tr><td class=\"thumbnail\">{}</td><td><a href=\"{}\">{}</a>{}</td><td>{}</td><td class=\"count\">{}</td></tr>\n",
+                thumbnail,
+                escape_text(&entry.url),
+                escape_text(&entry.title),
+                status,
+                file,
+                entry.word_count
+            ));
+        }
+        report.push_str("</table>\n");
+    }
+
+    if !errors.is_empty() {
+        report.push_str(
+            "<h2 class=\"failed\">Failed articles</h2>\n<table>\n<tr><th>Link</th><th>Reason</th></tr>\n",
+        );
+        for error in errors {
+            let source = match error.article_source() {
+                Some(source) => format!(
+                    "<a href=\"{}\">{}</a>",
+                    escape_text(source),
+                    escape_text(source)
+                ),
+                None => "&lt;unknown link&gt;".to_owned(),
+            };
+            report.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td></tr>\n",
+                source,
+                escape_text(&error.kind().to_string())
+            ));
+        }
+        report.push_str("</table>\n");
+    }
+
+    report.push_str("</body>\n</html>\n");
+    report
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace("&", "&amp;")
+        .replace("<", "&lt;")
+        .replace(">", "&gt;")
+        .replace("\"", "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::errors::ErrorKind;
+
+    #[test]
+    fn test_generate_report() {
+        let entries = vec![
+            ReportEntry {
+                title: "Pepperoni & Salami".to_owned(),
+                url: "https://example.com/pepperoni".to_owned(),
+                word_count: 1200,
+                thumbnail: Some("https://example.com/pepperoni.jpg".to_owned()),
+            },
+            ReportEntry {
+                title: "Salami".to_owned(),
+                url: "https://example.com/salami".to_owned(),
+                word_count: 300,
+                thumbnail: None,
+            },
+            ReportEntry {
+                title: "Failed export".to_owned(),
+                url: "https://example.com/failed-export".to_owned(),
+                word_count: 10,
+                thumbnail: None,
+            },
+            ReportEntry {
+                title: "Kept".to_owned(),
+                url: "https://example.com/kept".to_owned(),
+                word_count: 20,
+                thumbnail: None,
+            },
+        ];
+        let article_files = vec![
+            Some("./Pepperoni & Salami.epub".to_owned()),
+            Some("./Salami_1.epub".to_owned()),
+            None,
+            None,
+        ];
+        let partial_downloads = vec![PartialDownload::new("https://example.com/salami", "Salami")];
+        let errors = [
+            "https://example.com/missing",
+            "https://example.com/failed-export",
+        ]
+        .iter()
+        .map(|source| {
+            let mut error: PaperoniError =
+                ErrorKind::HTTPError("Non-success HTTP status code (404)".to_owned()).into();
+            error.set_article_source(source);
+            error
+        })
+        .collect::<Vec<_>>();
+
+        let report = generate_report(
+            &entries,
+            &article_files,
+            &partial_downloads,
+            &errors,
+            &Local::now(),
+            |file_name| {
+                if file_name.starts_with("./Pepperoni") {
+                    Some("file:///tmp/Pepperoni%20&%20Salami.epub".to_owned())
+                } else {
+                    None
+                }
+            },
+        );
+
+        assert!(report.contains("2 downloaded, 1 partially downloaded, 2 failed."));
+        assert!(report.contains(r#"<td class="thumbnail"><img src="https://example.com/pepperoni.jpg" alt=""></td><td><a href="https://example.com/pepperoni">Pepperoni &amp; Salami</a></td><td><a href="file:///tmp/Pepperoni%20&amp;%20Salami.epub">./Pepperoni &amp; Salami.epub</a></td><td class="count">1200</td>"#));
+        assert!(report.contains(r#"Salami</a> <span class="partial">(some images failed to download)</span></td><td>./Salami_1.epub</td>"#));
+        assert!(!report.contains("Failed export"));
+        // An article that was not written has no file
+        assert!(report.contains(
+            r#"<a href="https://example.com/kept">Kept</a></td><td></td><td class="count">20</td>"#
+        ));
+        assert!(report.contains(r#"<tr><td><a href="https://example.com/missing">https://example.com/missing</a></td><td>[HTTPError]: Non-success HTTP status code (404)</td></tr>"#));
+    }
+}
@@ -0,0 +1,74 @@
+#[cfg(feature = "reqwest-client")]
+use std::{convert::TryFrom, fs};
+
+#[cfg(feature = "reqwest-client")]
+use http_client::{Error, HttpClient, Request, Response};
+#[cfg(feature = "reqwest-client")]
+use surf::StatusCode;
+
+#[cfg(feature = "reqwest-client")]
+use crate::cli::TlsOptions;
+
+/// An HTTP backend built on reqwest which negotiates HTTP/2 with the servers that support it
+/// and keeps a pool of connections to each host. Responses are read whole before they are
+/// passed on as the pages and images they carry are read whole anyway.
+#[cfg(feature = "reqwest-client")]
+#[derive(Debug)]
+pub struct ReqwestClient {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "reqwest-client")]
+impl ReqwestClient {
+    /// Redirects are not followed by reqwest since they are followed by the redirect
+    /// middleware and when fetching HTML the same way with every backend
+    pub fn new(tls: &TlsOptions) -> Result<Self, String> {
+        let mut builder = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .danger_accept_invalid_certs(tls.is_insecure);
+        if let Some(ca_cert) = &tls.ca_cert {
+            let pem = fs::read(ca_cert).map_err(|e| e.to_string())?;
+            let certificate = reqwest::Certificate::from_pem(&pem).map_err(|e| e.to_string())?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        let client = builder.build().map_err(|e| e.to_string())?;
+        Ok(Self { client })
+    }
+}
+
+#[cfg(feature = "reqwest-client")]
+#[surf::utils::async_trait]
+impl HttpClient for ReqwestClient {
+    async fn send(&self, mut req: Request) -> Result<Response, Error> {
+        let method = reqwest::Method::from_bytes(req.method().to_string().as_bytes())
+            .map_err(|e| Error::new(StatusCode::BadRequest, e))?;
+        let mut builder = self.client.request(method, req.url().as_str());
+        for (name, values) in req.iter() {
+            for value in values {
+                builder = builder.header(name.as_str(), value.as_str());
+            }
+        }
+        let body = req.body_bytes().await?;
+        if !body.is_empty() {
+            builder = builder.body(body);
+        }
+        let res = builder
+            .send()
+            .await
+            .map_err(|e| Error::new(StatusCode::BadGateway, e))?;
+
+        let mut response = Response::new(StatusCode::try_from(res.status().as_u16())?);
+        for (name, value) in res.headers() {
+            // Header values that are not visible ASCII cannot be passed on
+            if let Ok(value) = value.to_str() {
+                response.append_header(name.as_str(), value);
+            }
+        }
+        let body = res
+            .bytes()
+            .await
+            .map_err(|e| Error::new(StatusCode::BadGateway, e))?;
+        response.set_body(body.to_vec());
+        Ok(response)
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashSet;
+
+use kuchiki::{NodeData, NodeRef};
+use log::debug;
+
+use crate::extractor::INTERNAL_ATTRS;
+
+/// The elements kept by default when sanitizing. Other elements are replaced by their content.
+const ALLOWED_TAGS: [&str; 69] = [
+    "a",
+    "abbr",
+    "address",
+    "article",
+    "aside",
+    "b",
+    "bdi",
+    "bdo",
+    "blockquote",
+    "br",
+    "caption",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "data",
+    "dd",
+    "del",
+    "details",
+    "dfn",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "main",
+    "mark",
+    "nav",
+    "ol",
+    "p",
+    "picture",
+    "pre",
+    "q",
+    "rp",
+    "rt",
+    "ruby",
+    "s",
+    "samp",
+    "section",
+    "small",
+    "source",
+    "span",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "time",
+    "tr",
+];
+/// Elements removed with their content as they run code, embed other pages or take input
+const REMOVED_TAGS: [&str; 22] = [
+    "applet", "base", "button", "datalist", "dialog", "embed", "form", "frame", "frameset",
+    "iframe", "input", "link", "meta", "noscript", "object", "optgroup", "option", "output",
+    "script", "select", "style", "textarea",
+];
+/// The attributes kept by default when sanitizing
+const ALLOWED_ATTRS: [&str; 28] = [
+    "abbr",
+    "alt",
+    "aria-label",
+    "cite",
+    "class",
+    "colspan",
+    "datetime",
+    "dir",
+    "headers",
+    "height",
+    "href",
+    "id",
+    "lang",
+    "media",
+    "name",
+    "open",
+    "reversed",
+    "role",
+    "rowspan",
+    "scope",
+    "sizes",
+    "span",
+    "src",
+    "srcset",
+    "start",
+    "title",
+    "type",
+    "width",
+];
+/// Attributes holding urls which are removed when they would run code
+const URL_ATTRS: [&str; 8] = [
+    "action",
+    "background",
+    "cite",
+    "formaction",
+    "href",
+    "longdesc",
+    "poster",
+    "src",
+];
+
+/// Removes the elements and attributes of articles that are not in a whitelist, along with
+/// event handlers and `javascript:` urls, as exported HTML may be opened locally where
+/// scripts in it would run
+#[derive(Clone, Debug)]
+pub struct Sanitizer {
+    tags: HashSet<String>,
+    attrs: HashSet<String>,
+}
+
+impl Default for Sanitizer {
+    fn default() -> Self {
+        Self::new(&[], &[])
+    }
+}
+
+impl Sanitizer {
+    /// A sanitizer keeping the given tags and attributes on top of the default whitelist
+    pub fn new(extra_tags: &[String], extra_attrs: &[String]) -> Self {
+        Self {
+            tags: ALLOWED_TAGS
+                .iter()
+                .map(|tag| tag.to_string())
+                .chain(extra_tags.iter().map(|tag| tag.to_lowercase()))
+                .collect(),
+            attrs: ALLOWED_ATTRS
+                .iter()
+                .chain(INTERNAL_ATTRS.iter())
+                .map(|attr| attr.to_string())
+                .chain(extra_attrs.iter().map(|attr| attr.to_lowercase()))
+                .collect(),
+        }
+    }
+
+    /// Sanitizes the body of a document, or the whole tree when it has no body
+    pub fn sanitize(&self, root: &NodeRef) {
+        let content = root
+            .select_first("body")
+            .map(|body| body.as_node().clone())
+            .unwrap_or_else(|_| root.clone());
+        let (mut removed_count, mut unwrapped_count) = (0, 0);
+        // The nodes are collected first as detaching them while iterating stops the traversal
+        for node in content.descendants().collect::<Vec<_>>() {
+            let elem = match node.data() {
+                NodeData::Element(elem) => elem,
+                NodeData::Comment(_) | NodeData::ProcessingInstruction(_) => {
+                    node.detach();
+                    continue;
+                }
+                _ => continue,
+            };
+            let tag = elem.name.local.to_lowercase();
+            // Scripts are removed even when allowed
+            if self.tags.contains(&tag) && tag != "script" {
+                elem.attributes
+                    .borrow_mut()
+                    .map
+                    .retain(|name, attr| self.is_allowed_attr(&name.local, &attr.value));
+            } else if REMOVED_TAGS.contains(&tag.as_str()) {
+                node.detach();
+                removed_count += 1;
+            } else {
+                for child in node.children().collect::<Vec<_>>() {
+                    node.insert_before(child);
+                }
+                node.detach();
+                unwrapped_count += 1;
+            }
+        }
+        debug!(
+            "Sanitized the content by removing {} elements and unwrapping {}",
+            removed_count, unwrapped_count
+        );
+    }
+
+    fn is_allowed_attr(&self, name: &str, value: &str) -> bool {
+        let name = name.to_lowercase();
+        // Event handlers are never kept even when allowed
+        if name.starts_with("on") || !self.attrs.contains(&name) {
+            return false;
+        }
+        if name == "srcset" {
+            return value
+                .split(',')
+                .all(|candidate| is_safe_url(candidate.trim(), true));
+        }
+        !URL_ATTRS.contains(&name.as_str()) || is_safe_url(value, name == "src")
+    }
+}
+
+/// Checks that a url does not run code when followed. Inline images are allowed in the
+/// sources of images.
+fn is_safe_url(url: &str, is_image_src: bool) -> bool {
+    // Browsers ignore whitespace and control characters in the scheme
+    let url = url
+        .chars()
+        .filter(|c| !(c.is_whitespace() || c.is_control()))
+        .collect::<String>()
+        .to_lowercase();
+    if url.starts_with("data:") {
+        return is_image_src && url.starts_with("data:image/");
+    }
+    !(url.starts_with("javascript:") || url.starts_with("vbscript:"))
+}
+
+#[cfg(test)]
+mod test {
+    use kuchiki::traits::*;
+
+    use super::*;
+
+    fn sanitized_body(html: &str, sanitizer: &Sanitizer) -> String {
+        let doc = kuchiki::parse_html().one(html);
+        sanitizer.sanitize(&doc);
+        let body = doc.select_first("body").unwrap();
+        let mut inner_html = Vec::new();
+        for child in body.as_node().children() {
+            child.serialize(&mut inner_html).unwrap();
+        }
+        String::from_utf8(inner_html).unwrap()
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let html = r#"<html><head><script>head()</script></head><body><div id="readability-page-1">
+<p onclick="steal()" class="lead" style="color: red">Read <a href=" JaVa&#9;script:steal()">this</a> and <a href="https://example.com" target="_blank">that</a><!-- hidden --></p>
+<script>steal()</script><form action="/login"><input name="password"><button>Log in</button></form>
+<center><font color="red">Unwrapped</font></center><iframe src="https://example.com/embed"></iframe>
+<img src="data:image/png;base64,AAAA" srcset="a.png 1x, javascript:steal() 2x" data-paperoni-src="https://example.com/a.png" onerror="steal()">
+<a href="data:text/html;base64,AAAA">Data</a></div></body></html>"#;
+        assert_eq!(
+            r#"<div id="readability-page-1">
+<p class="lead">Read <a>this</a> and <a href="https://example.com">that</a></p>
+
+Unwrapped
+<img src="data:image/png;base64,AAAA" data-paperoni-src="https://example.com/a.png">
+<a>Data</a></div>"#,
+            sanitized_body(html, &Sanitizer::default())
+        );
+    }
+
+    #[test]
+    fn test_extra_allowed() {
+        let sanitizer = Sanitizer::new(&["VIDEO".to_owned()], &["style".to_owned()]);
+        assert_eq!(
+            r#"<video src="clip.mp4" style="width: 100%">Fallback</video>"#,
+            sanitized_body(
+                r#"<video src="clip.mp4" style="width: 100%" onplay="steal()" autoplay>Fallback</video>"#,
+                &sanitizer
+            )
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use std::fs;
+use std::path::Path;
+
+use colored::Colorize;
+use comfy_table::Table;
+use kuchiki::traits::*;
+use kuchiki::NodeRef;
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+
+use crate::cli::{AppConfig, ExportType, SearchOptions};
+use crate::errors::{ErrorKind, PaperoniError};
+use crate::exporter::{export_articles, ExportedFiles};
+use crate::extractor::Article;
+use crate::site::{load_catalogue, SiteEntry, SITE_CATALOGUE};
+
+/// Characters of context shown on each side of the match in a snippet
+const SNIPPET_RADIUS: usize = 80;
+/// How much more a match in the title counts than one in the text
+const TITLE_WEIGHT: usize = 5;
+/// The elements added to the pages of a site that are not part of the article
+const SITE_ELEMENTS_SELECTOR: &str =
+    "nav.site-nav, footer, script, style, .paperoni-reader-toolbar, .paperoni-reader-progress";
+
+/// An article of the site matching every term of the query
+#[derive(Debug)]
+struct SearchHit<'a> {
+    entry: &'a SiteEntry,
+    score: usize,
+    snippet: String,
+}
+
+/// Searches the text of the articles of a site built with `--export site`, lists the matches
+/// with snippets and exports the picked ones to an EPUB
+pub fn run_search(app_config: &AppConfig, options: &SearchOptions) -> Result<(), PaperoniError> {
+    let site_dir = Path::new(&options.site_dir);
+    let entries = load_catalogue(&site_dir.join(SITE_CATALOGUE));
+    if entries.is_empty() {
+        return Err(ErrorKind::IOError(format!(
+            "No articles were found in the site at {}. Build one with --export site",
+            site_dir.display()
+        ))
+        .into());
+    }
+
+    let terms = options
+        .terms
+        .iter()
+        .flat_map(|term| term.split_whitespace())
+        .map(lower_chars)
+        .collect::<Vec<_>>();
+    let mut hits = Vec::new();
+    for entry in &entries {
+        let page_path = site_dir.join(&entry.file);
+        let html = match fs::read_to_string(&page_path) {
+            Ok(html) => html,
+            Err(e) => {
+                warn!("Skipping {}: {}", page_path.display(), e);
+                continue;
+            }
+        };
+        if let Some((score, snippet)) = match_article(&entry.title, &page_text(&html), &terms) {
+            hits.push(SearchHit {
+                entry,
+                score,
+                snippet,
+            });
+        }
+    }
+    hits.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| b.entry.date().cmp(a.entry.date()))
+    });
+    hits.truncate(options.limit);
+
+    if hits.is_empty() {
+        println!("No articles match {:?}", options.terms.join(" "));
+        return Ok(());
+    }
+    for (idx, hit) in hits.iter().enumerate() {
+        println!(
+            "{}. {} {}",
+            idx + 1,
+            hit.entry.title.bold(),
+            format!("({}, {})", hit.entry.domain, hit.entry.date()).dimmed()
+        );
+        if !hit.snippet.is_empty() {
+            println!("   {}", hit.snippet);
+        }
+        println!("   {}", site_dir.join(&hit.entry.file).display());
+    }
+
+    if let Some(epub_name) = &options.epub {
+        let picked = picked_hits(&hits, &options.picks)?;
+        export_hits(app_config, site_dir, &picked, epub_name)?;
+    }
+    Ok(())
+}
+
+/// The results picked by their numbers in the listing, all of them when none are picked
+fn picked_hits<'a>(
+    hits: &'a [SearchHit<'a>],
+    picks: &[usize],
+) -> Result<Vec<&'a SearchHit<'a>>, PaperoniError> {
+    if picks.is_empty() {
+        return Ok(hits.iter().collect());
+    }
+    picks
+        .iter()
+        .map(|pick| {
+            hits.get(pick - 1).ok_or_else(|| {
+                ErrorKind::IOError(format!(
+                    "There is no result {} as only {} were listed",
+                    pick,
+                    hits.len()
+                ))
+                .into()
+            })
+        })
+        .collect()
+}
+
+/// Rebuilds the articles from their pages in the site and exports them to a single EPUB
+fn export_hits(
+    app_config: &AppConfig,
+    site_dir: &Path,
+    hits: &[&SearchHit],
+    epub_name: &str,
+) -> Result<(), PaperoniError> {
+    let mut articles = Vec::new();
+    for hit in hits {
+        articles.push(archived_article(
+            &site_dir.join(&hit.entry.file),
+            hit.entry,
+        )?);
+    }
+
+    let mut epub_config = app_config.clone();
+    epub_config.export_type = ExportType::EPUB;
+    epub_config.output_directory = None;
+    epub_config.merged = Some(if epub_name.ends_with(".epub") {
+        epub_name.to_owned()
+    } else {
+        format!("{}.epub", epub_name)
+    });
+    let article_count = articles.len();
+    let mut table = Table::new();
+    export_articles(
+        articles,
+        &epub_config,
+        &mut table,
+        &mut ExportedFiles::default(),
+    )
+    .map_err(|mut errors| errors.remove(0))?;
+    println!(
+        "Exported {} articles to {}",
+        article_count,
+        epub_config.merged.as_deref().unwrap_or_default()
+    );
+    Ok(())
+}
+
+/// Extracts an article again from its page in the site. The images saved next to the page are
+/// copied to the temporary directory the EPUB exporter reads images from.
+fn archived_article(page_path: &Path, entry: &SiteEntry) -> Result<Article, PaperoniError> {
+    let html = fs::read_to_string(page_path)?;
+    let mut article = Article::from_html(&html, &entry.url);
+    remove_site_elements(article.source_document());
+    article.extract_content_with_selector("body")?;
+    article.set_title(&entry.title);
+
+    let page_dir = page_path.parent().unwrap_or_else(|| Path::new("."));
+    let mut img_urls = Vec::new();
+    let mut missing_imgs = Vec::new();
+    for img_ref in article.node_ref().select("img").unwrap() {
+        let mut attrs = img_ref.attributes.borrow_mut();
+        let src = match attrs.get("src") {
+            Some(src) if !src.starts_with("data:") => src.to_owned(),
+            _ => continue,
+        };
+        let img_path = [Path::new(&src).to_path_buf(), page_dir.join(&src)]
+            .iter()
+            .find(|path| path.is_file())
+            .cloned();
+        match img_path.and_then(|path| Some((image_mime(&path)?, path))) {
+            Some((mime, path)) => {
+                let temp_name = format!(
+                    "{:x}.{}",
+                    Sha256::digest(path.to_string_lossy().as_bytes()),
+                    path.extension().unwrap_or_default().to_string_lossy()
+                );
+                info!("Copying {:?} to the temporary directory", path);
+                fs::copy(&path, std::env::temp_dir().join(&temp_name))?;
+                attrs.insert("src", temp_name.clone());
+                if !img_urls.iter().any(|(name, _)| *name == temp_name) {
+                    img_urls.push((temp_name, Some(mime.to_owned())));
+                }
+            }
+            None => missing_imgs.push(src),
+        }
+    }
+    for src in missing_imgs {
+        warn!(
+            "Leaving out the image {} which is not saved in the site",
+            src
+        );
+        article.remove_image(&src);
+    }
+    article.img_urls = img_urls;
+    Ok(article)
+}
+
+fn remove_site_elements(document: &NodeRef) {
+    if let Ok(elems) = document.select(SITE_ELEMENTS_SELECTOR) {
+        for elem in elems.collect::<Vec<_>>() {
+            elem.as_node().detach();
+        }
+    }
+}
+
+/// The text of the article on a page of the site with its whitespace collapsed
+fn page_text(html: &str) -> String {
+    let doc = kuchiki::parse_html().one(html);
+    remove_site_elements(&doc);
+    doc.select_first("body")
+        .map(|body| body.text_contents())
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn image_mime(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    match ext.as_str() {
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        "gif" => Some("image/gif"),
+        "svg" => Some("image/svg+xml"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// Lowercases text one character at a time so that positions in it match the original text
+fn lower_chars(text: &str) -> Vec<char> {
+    text.chars()
+        .map(|c| c.to_lowercase().next().unwrap_or(c))
+        .collect()
+}
+
+fn find_chars(haystack: &[char], needle: &[char], from: usize) -> Option<usize> {
+    if needle.is_empty() || haystack.len() < needle.len() {
+        return None;
+    }
+    (from..=haystack.len() - needle.len()).find(|&idx| &haystack[idx..idx + needle.len()] == needle)
+}
+
+fn count_matches(haystack: &[char], needle: &[char]) -> usize {
+    let mut count = 0;
+    let mut from = 0;
+    while let Some(idx) = find_chars(haystack, needle, from) {
+        count += 1;
+        from = idx + needle.len();
+    }
+    count
+}
+
+/// Scores an article by how often the terms appear in it. Returns `None` unless every term
+/// appears in its title or text, otherwise the score with a snippet around the first match.
+fn match_article(title: &str, text: &str, terms: &[Vec<char>]) -> Option<(usize, String)> {
+    if terms.is_empty() {
+        return None;
+    }
+    let title = lower_chars(title);
+    let text_chars = text.chars().collect::<Vec<_>>();
+    let lowered = lower_chars(text);
+    let mut score = 0;
+    for term in terms {
+        let (in_title, in_text) = (count_matches(&title, term), count_matches(&lowered, term));
+        if in_title + in_text == 0 {
+            return None;
+        }
+        score += in_title * TITLE_WEIGHT + in_text;
+    }
+    let snippet = terms
+        .iter()
+        .find_map(|term| find_chars(&lowered, term, 0).map(|idx| (idx, term.len())))
+        .map(|(idx, len)| {
+            let start = idx.saturating_sub(SNIPPET_RADIUS);
+            let end = (idx + len + SNIPPET_RADIUS).min(text_chars.len());
+            format!(
+                "{}{}{}",
+                if start > 0 { "…" } else { "" },
+                text_chars[start..end].iter().collect::<String>(),
+                if end < text_chars.len() { "…" } else { "" }
+            )
+        })
+        .unwrap_or_default();
+    Some((score, snippet))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn terms(query: &str) -> Vec<Vec<char>> {
+        query.split_whitespace().map(lower_chars).collect()
+    }
+
+    #[test]
+    fn test_match_article() {
+        let text =
+            "Pepperoni is a variety of salami. Pepperoni is usually made from pork and beef.";
+        assert_eq!(
+            Some((2 * TITLE_WEIGHT + 2 + 1, text.to_owned())),
+            match_article(
+                "Pepperoni pizza and pepperoni",
+                text,
+                &terms("PEPPERONI beef")
+            )
+        );
+        assert_eq!(
+            None,
+            match_article("Pepperoni", text, &terms("pepperoni chicken"))
+        );
+        // Terms only in the title match without a snippet
+        assert_eq!(
+            Some((TITLE_WEIGHT, String::new())),
+            match_article("Pizza", text, &terms("pizza"))
+        );
+    }
+
+    #[test]
+    fn test_snippet() {
+        let text = format!("{}needle{}", "a".repeat(100), "b".repeat(100));
+        let (_, snippet) = match_article("", &text, &terms("needle")).unwrap();
+        assert_eq!(
+            format!("…{}needle{}…", "a".repeat(80), "b".repeat(80)),
+            snippet
+        );
+    }
+
+    #[test]
+    fn test_page_text() {
+        let html = r#"<html><head><style>p{}</style></head><body><nav class="site-nav"><a href="../index.html">All articles</a></nav><div><p>Salami  and
+            pepperoni</p></div><footer><h2>Appendix</h2></footer></body></html>"#;
+        assert_eq!("Salami and pepperoni", page_text(html));
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use comfy_table::presets::UTF8_HORIZONTAL_BORDERS_ONLY;
+use comfy_table::{Cell, CellAlignment, ContentArrangement, Table};
+use kuchiki::traits::*;
+use serde::Deserialize;
+
+use crate::cjk;
+use crate::cli::SelftestOptions;
+use crate::errors::{ErrorKind, PaperoniError};
+use crate::extractor::Article;
+
+/// The url the pages of the Mozilla readability test corpus were saved from
+const TEST_PAGE_URL: &str = "http://fakehost/test/page.html";
+
+/// The metadata expected for a page, read from its `expected-metadata.json`
+#[derive(Debug, Default, Deserialize)]
+struct ExpectedMetadata {
+    title: Option<String>,
+    byline: Option<String>,
+}
+
+/// How the article extracted from a page of the corpus compares with the expected one
+struct PageResult {
+    name: String,
+    /// The similarity of the extracted text to the expected text between 0 and 1
+    score: f64,
+    title_matches: Option<bool>,
+    byline_matches: Option<bool>,
+    error: Option<String>,
+}
+
+/// Extracts every page of a readability test corpus and compares the articles with the
+/// expected ones. The corpus has a directory for each page with its `source.html`, its
+/// `expected.html` and optionally its `expected-metadata.json` like the test pages of
+/// Mozilla's readability.
+pub fn run_selftest(options: &SelftestOptions) -> Result<(), PaperoniError> {
+    let mut page_dirs = fs::read_dir(&options.corpus)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.join("source.html").is_file() && path.join("expected.html").is_file())
+        .collect::<Vec<_>>();
+    page_dirs.sort();
+    if page_dirs.is_empty() {
+        return Err(ErrorKind::IOError(format!(
+            "No test pages with a source.html and expected.html found in {}",
+            options.corpus
+        ))
+        .into());
+    }
+
+    let results = page_dirs
+        .iter()
+        .map(|page_dir| compare_page(page_dir))
+        .collect::<Result<Vec<_>, _>>()?;
+    println!("{}", results_table(&results));
+
+    let mean_score = results.iter().map(|result| result.score).sum::<f64>() / results.len() as f64;
+    let failed_count = results
+        .iter()
+        .filter(|result| result.score < options.min_score)
+        .count();
+    println!(
+        "Mean similarity of {:.3} over {} pages. {} pages scored below {}",
+        mean_score,
+        results.len(),
+        failed_count,
+        options.min_score
+    );
+    if failed_count > 0 {
+        Err(ErrorKind::ReadabilityError(format!(
+            "{} of {} test pages scored below {}",
+            failed_count,
+            results.len(),
+            options.min_score
+        ))
+        .into())
+    } else {
+        Ok(())
+    }
+}
+
+fn compare_page(page_dir: &Path) -> Result<PageResult, PaperoniError> {
+    let name = page_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let source = fs::read_to_string(page_dir.join("source.html"))?;
+    let expected = fs::read_to_string(page_dir.join("expected.html"))?;
+    let expected_metadata = fs::read_to_string(page_dir.join("expected-metadata.json"))
+        .ok()
+        .and_then(|metadata| serde_json::from_str::<ExpectedMetadata>(&metadata).ok())
+        .unwrap_or_default();
+
+    let mut article = Article::from_html(&source, TEST_PAGE_URL);
+    if let Err(err) = article.extract_content() {
+        return Ok(PageResult {
+            name,
+            score: 0.0,
+            title_matches: None,
+            byline_matches: None,
+            error: Some(err.to_string()),
+        });
+    }
+    let expected_text = kuchiki::parse_html().one(expected).text_contents();
+    let extracted_text = match article.node_ref().select_first("body") {
+        Ok(body) => body.as_node().text_contents(),
+        Err(_) => article.node_ref().text_contents(),
+    };
+    let metadata = article.metadata();
+    Ok(PageResult {
+        name,
+        score: text_similarity(&extracted_text, &expected_text),
+        title_matches: expected_metadata
+            .title
+            .map(|title| title.trim() == metadata.title().trim()),
+        byline_matches: expected_metadata
+            .byline
+            .map(|byline| metadata.byline().map(|found| found.trim()) == Some(byline.trim())),
+        error: None,
+    })
+}
+
+/// Scores how similar two texts are by the words they share, regardless of whitespace
+/// and order. This is the Sørensen–Dice coefficient of their bags of words.
+fn text_similarity(text: &str, expected: &str) -> f64 {
+    let count_words = |text: &str| {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for word in cjk::split_words(text) {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+        counts
+    };
+    let words = count_words(text);
+    let expected_words = count_words(expected);
+    let total = words.values().sum::<usize>() + expected_words.values().sum::<usize>();
+    if total == 0 {
+        return 1.0;
+    }
+    let shared = words
+        .iter()
+        .map(|(word, count)| (*count).min(*expected_words.get(word).unwrap_or(&0)))
+        .sum::<usize>();
+    2.0 * shared as f64 / total as f64
+}
+
+fn results_table(results: &[PageResult]) -> Table {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_HORIZONTAL_BORDERS_ONLY)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["Page", "Score", "Title", "Byline", "Error"]);
+    let format_match = |matches: Option<bool>| match matches {
+        Some(true) => "match",
+        Some(false) => "differs",
+        None => "-",
+    };
+    for result in results {
+        table.add_row(vec![
+            Cell::new(&result.name),
+            Cell::new(format!("{:.3}", result.score)).set_alignment(CellAlignment::Right),
+            Cell::new(format_match(result.title_matches)),
+            Cell::new(format_match(result.byline_matches)),
+            Cell::new(result.error.as_deref().unwrap_or("")),
+        ]);
+    }
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_text_similarity() {
+        let assert_similarity = |expected: f64, text: &str, expected_text: &str| {
+            let similarity = text_similarity(text, expected_text);
+            assert!(
+                (similarity - expected).abs() < 1e-9,
+                "{} != {}",
+                similarity,
+                expected
+            );
+        };
+        assert_similarity(1.0, "", "");
+        assert_similarity(1.0, "The  quick\nfox", "The quick fox");
+        assert_similarity(0.0, "The quick fox", "");
+        assert_similarity(0.0, "The quick fox", "A lazy dog");
+        // 2 shared words out of 3 + 5
+        assert_similarity(0.5, "The quick fox", "The quick brown lazy dog");
+        // Repeated words are only shared as many times as they appear in both
+        assert_similarity(0.5, "ha ha ha ha", "ha ha ho ho");
+    }
+}
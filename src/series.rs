@@ -0,0 +1,299 @@
+use std::collections::{BTreeMap, HashSet};
+
+use async_std::task;
+use kuchiki::{traits::*, NodeRef};
+use log::{debug, info};
+use regex::Regex;
+use url::Url;
+
+use crate::cli::AppConfig;
+use crate::errors::PaperoniError;
+use crate::http::{build_client, fetch_html};
+
+/// The most pages fetched while following the links between the parts of a series
+const MAX_SERIES_PAGES: usize = 50;
+
+lazy_static! {
+    static ref PART_REGEX: Regex = Regex::new(
+        r"(?i)\bpart\s+(\d+|one|two|three|four|five|six|seven|eight|nine|ten)\b(?:\s*(?:of|/)\s*\d+)?"
+    )
+    .unwrap();
+}
+
+/// The parts of a series found from one of its articles
+#[derive(Debug, PartialEq)]
+pub struct Series {
+    /// The title of the series without the part numbers of its articles
+    pub name: Option<String>,
+    /// The urls of the parts in reading order
+    pub urls: Vec<String>,
+}
+
+/// The links to other parts of a series found in one of its pages
+#[derive(Debug, Default, PartialEq)]
+struct SeriesLinks {
+    title: Option<String>,
+    part: Option<u32>,
+    prev: Option<String>,
+    next: Option<String>,
+    parts: BTreeMap<u32, String>,
+}
+
+/// Finds the parts of the series that the first url belongs to by following the `rel=next`
+/// and `rel=prev` links between its pages and the links labelled "Part 2", "Part 3" and so on.
+pub fn follow_series(app_config: &AppConfig) -> Result<Series, PaperoniError> {
+    task::block_on(async {
+        let client = build_client(app_config, None);
+        let start_url = &app_config.urls[0];
+        let (start_url, html) = fetch_html(&client, start_url, app_config.max_redirects).await?;
+        let start_links = find_series_links(&html, &start_url);
+        let name = start_links.title.as_deref().and_then(series_name);
+
+        let mut visited = HashSet::new();
+        visited.insert(start_url.clone());
+        let mut parts = start_links.parts.clone();
+        if let Some(part) = start_links.part {
+            parts.entry(part).or_insert_with(|| start_url.clone());
+        }
+        let mut chain = vec![start_url.clone()];
+        // The previous parts are walked back to the first one before walking forward
+        for is_forward in [false, true].iter() {
+            let mut link = if *is_forward {
+                start_links.next.clone()
+            } else {
+                start_links.prev.clone()
+            };
+            while let Some(url) = link.take() {
+                if visited.len() >= MAX_SERIES_PAGES || !visited.insert(url.clone()) {
+                    break;
+                }
+                let (page_url, html) = fetch_html(&client, &url, app_config.max_redirects).await?;
+                debug!("Found part of the series at {}", page_url);
+                let links = find_series_links(&html, &page_url);
+                if let Some(part) = links.part {
+                    parts.entry(part).or_insert_with(|| page_url.clone());
+                }
+                for (part, part_url) in links.parts {
+                    parts.entry(part).or_insert(part_url);
+                }
+                link = if *is_forward { links.next } else { links.prev };
+                if *is_forward {
+                    chain.push(page_url);
+                } else {
+                    chain.insert(0, page_url);
+                }
+            }
+        }
+
+        let urls = order_parts(chain, parts);
+        info!(
+            "Found {} part(s) of the series from {}",
+            urls.len(),
+            start_url
+        );
+        Ok(Series { name, urls })
+    })
+}
+
+/// Orders the pages of a series. The chain of `rel` links is kept when it has more than the
+/// starting page and the numbered parts it does not reach are added after it.
+fn order_parts(chain: Vec<String>, parts: BTreeMap<u32, String>) -> Vec<String> {
+    let mut urls = if chain.len() > 1 {
+        chain
+    } else {
+        let mut urls = parts.values().cloned().collect::<Vec<_>>();
+        if !urls.contains(&chain[0]) {
+            urls.insert(0, chain[0].clone());
+        }
+        urls
+    };
+    for part_url in parts.values() {
+        if !urls.contains(part_url) {
+            urls.push(part_url.clone());
+        }
+    }
+    urls
+}
+
+/// Reads the links to the other parts of a series from a page of it. Only pages on the same
+/// site are considered parts.
+fn find_series_links(html: &str, page_url: &str) -> SeriesLinks {
+    let page_url = match Url::parse(page_url) {
+        Ok(url) => url,
+        Err(_) => return SeriesLinks::default(),
+    };
+    let doc = kuchiki::parse_html().one(html);
+    let resolve = |href: &str| {
+        let mut url = page_url.join(href).ok()?;
+        url.set_fragment(None);
+        if url.host_str() == page_url.host_str() && url != page_url {
+            Some(url.to_string())
+        } else {
+            None
+        }
+    };
+    let rel_link = |rel: &str| {
+        doc.select("link[rel][href], a[rel][href]")
+            .unwrap()
+            .find_map(|elem| {
+                let attrs = elem.attributes.borrow();
+                if attrs.get("rel")?.split_whitespace().any(|r| r == rel) {
+                    resolve(attrs.get("href")?)
+                } else {
+                    None
+                }
+            })
+    };
+
+    let title = page_title(&doc);
+    let mut parts = BTreeMap::new();
+    for anchor in doc.select("a[href]").unwrap() {
+        let text = anchor.text_contents();
+        let part = match PART_REGEX
+            .captures(&text)
+            .and_then(|caps| part_number(&caps[1]))
+        {
+            Some(part) => part,
+            None => continue,
+        };
+        if let Some(url) = resolve(anchor.attributes.borrow().get("href").unwrap()) {
+            parts.entry(part).or_insert(url);
+        }
+    }
+    SeriesLinks {
+        part: title
+            .as_deref()
+            .and_then(|title| PART_REGEX.captures(title))
+            .and_then(|caps| part_number(&caps[1])),
+        title,
+        prev: rel_link("prev"),
+        next: rel_link("next"),
+        parts,
+    }
+}
+
+fn page_title(doc: &NodeRef) -> Option<String> {
+    doc.select_first("meta[property=\"og:title\"][content]")
+        .ok()
+        .and_then(|meta| meta.attributes.borrow().get("content").map(str::to_owned))
+        .or_else(|| {
+            doc.select_first("title")
+                .ok()
+                .map(|title| title.text_contents())
+        })
+        .map(|title| title.trim().to_owned())
+        .filter(|title| !title.is_empty())
+}
+
+fn part_number(part: &str) -> Option<u32> {
+    const NUMBER_WORDS: [&str; 10] = [
+        "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    ];
+    part.parse().ok().or_else(|| {
+        NUMBER_WORDS
+            .iter()
+            .position(|word| word.eq_ignore_ascii_case(part))
+            .map(|idx| idx as u32 + 1)
+    })
+}
+
+/// Returns the name of a series from the title of one of its parts, which is the text before
+/// the part number or the rest of the title when it starts with the part number.
+fn series_name(title: &str) -> Option<String> {
+    let part = PART_REGEX.find(title)?;
+    let separators: &[char] = &[' ', '-', '–', '—', ':', '|', ',', '(', ')', '[', ']'];
+    let before = title[..part.start()].trim_matches(separators);
+    let name = if before.is_empty() {
+        title[part.end()..].trim_matches(separators)
+    } else {
+        before
+    };
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_find_series_links() {
+        let html = r#"
+            <html>
+                <head>
+                    <title>Writing a compiler, Part 2: Parsing</title>
+                    <link rel="prev" href="/compiler-1">
+                </head>
+                <body>
+                    <a href="/compiler-1">Part one</a>
+                    <a href="/compiler-3#top">Part 3 of 4</a>
+                    <a href="https://other.example.org/part-4">Part 4</a>
+                    <a rel="next noopener" href="compiler-3">Next</a>
+                </body>
+            </html>
+        "#;
+        let links = find_series_links(html, "https://example.com/compiler-2");
+        assert_eq!(
+            Some("Writing a compiler, Part 2: Parsing"),
+            links.title.as_deref()
+        );
+        assert_eq!(Some(2), links.part);
+        assert_eq!(
+            Some("https://example.com/compiler-1"),
+            links.prev.as_deref()
+        );
+        assert_eq!(
+            Some("https://example.com/compiler-3"),
+            links.next.as_deref()
+        );
+        assert_eq!(
+            vec![
+                (1, "https://example.com/compiler-1".to_owned()),
+                (3, "https://example.com/compiler-3".to_owned()),
+            ],
+            links.parts.into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_order_parts() {
+        let parts = vec![
+            (1, "a".to_owned()),
+            (2, "b".to_owned()),
+            (4, "d".to_owned()),
+        ]
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            vec!["a", "b", "c", "d"],
+            order_parts(
+                vec!["a".to_owned(), "b".to_owned(), "c".to_owned()],
+                parts.clone()
+            )
+        );
+        assert_eq!(
+            vec!["a", "b", "d"],
+            order_parts(vec!["b".to_owned()], parts)
+        );
+    }
+
+    #[test]
+    fn test_series_name() {
+        assert_eq!(
+            Some("Writing a compiler".to_owned()),
+            series_name("Writing a compiler, Part 2: Parsing")
+        );
+        assert_eq!(
+            Some("The history of tea".to_owned()),
+            series_name("Part One - The history of tea")
+        );
+        assert_eq!(
+            Some("Rust in production".to_owned()),
+            series_name("Rust in production (part 3 of 5)")
+        );
+        assert_eq!(None, series_name("A standalone article"));
+    }
+}
@@ -0,0 +1,378 @@
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use async_std::task;
+use comfy_table::Table;
+use indicatif::ProgressBar;
+use log::{error, info};
+use serde::Deserialize;
+use serde_json::json;
+use tide::{Body, Request, Response, StatusCode};
+
+use crate::cli::{AppConfig, ExportType};
+use crate::errors::PaperoniError;
+use crate::exporter::{export_articles, ExportedFiles};
+use crate::extractor::Article;
+use crate::http::{build_client, download, fetch_html};
+use crate::jobs::{JobQueue, JobStatus, QueuedJob};
+use crate::metrics::{Metrics, PROMETHEUS_CONTENT_TYPE};
+
+/// Used to give the EPUBs generated at the same time different temporary file names
+static EPUB_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone)]
+struct ServerState {
+    app_config: Arc<AppConfig>,
+    client: surf::Client,
+    jobs: Arc<JobQueue>,
+}
+
+#[derive(Deserialize)]
+struct ExtractRequest {
+    url: String,
+    /// Either "json" or "html". Defaults to "json"
+    format: Option<String>,
+}
+
+/// The articles of an EPUB requested with POST /epub or POST /jobs
+#[derive(Clone, Debug, Deserialize)]
+pub struct EpubRequest {
+    pub urls: Vec<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+}
+
+/// Runs the HTTP server until it is stopped. The options passed to paperoni apply to
+/// the articles extracted by the server.
+pub fn serve(app_config: AppConfig, address: &str) -> Result<(), PaperoniError> {
+    let client = build_client(&app_config, None);
+    let state = ServerState {
+        app_config: Arc::new(app_config),
+        client,
+        jobs: Arc::new(JobQueue::default()),
+    };
+    for _ in 0..state.app_config.max_server_jobs {
+        task::spawn(run_jobs(state.clone()));
+    }
+    let mut app = tide::with_state(state);
+    app.at("/extract").post(extract);
+    app.at("/epub").post(epub);
+    app.at("/jobs").post(submit_job);
+    app.at("/jobs/:id").get(job_status);
+    app.at("/jobs/:id/result").get(job_result);
+    app.at("/metrics").get(metrics);
+
+    println!("Listening on http://{}", address);
+    task::block_on(app.listen(address.to_owned()))?;
+    Ok(())
+}
+
+/// Returns the counters of the server in the Prometheus text format
+async fn metrics(req: Request<ServerState>) -> tide::Result {
+    let body = req
+        .state()
+        .app_config
+        .metrics()
+        .map(Metrics::render)
+        .unwrap_or_default();
+    Ok(Response::builder(StatusCode::Ok)
+        .body(body)
+        .content_type(PROMETHEUS_CONTENT_TYPE)
+        .build())
+}
+
+/// Returns the extracted article as JSON with its metadata or as an HTML document
+async fn extract(mut req: Request<ServerState>) -> tide::Result {
+    let ExtractRequest { url, format } = req.body_json().await?;
+    let state = req.state();
+    info!("Extracting {} for a server request", url);
+
+    let metrics = state.app_config.metrics();
+    let (final_url, html) = fetch_html(&state.client, &url, state.app_config.max_redirects)
+        .await
+        .map_err(|e| {
+            if let Some(metrics) = metrics {
+                metrics.record_failure(e.kind());
+            }
+            tide::Error::from_str(StatusCode::BadGateway, e.to_string())
+        })?;
+    let mut article = Article::from_html(&html, &final_url);
+    article.set_extraction_budget(state.app_config.extraction_budget);
+    let site_rule = state.app_config.site_rule(&final_url);
+    let extract_start = Instant::now();
+    let extract_result = match site_rule.and_then(|rule| rule.selector.as_ref()) {
+        Some(selector) => article.extract_content_with_selector(selector),
+        None => article.extract_content(),
+    };
+    if let Some(metrics) = metrics {
+        metrics.record_downloaded_bytes(html.len() as u64);
+        metrics.record_extraction(extract_start.elapsed());
+    }
+    extract_result.map_err(|e| {
+        if let Some(metrics) = metrics {
+            metrics.record_failure(e.kind());
+        }
+        tide::Error::from_str(StatusCode::UnprocessableEntity, e.to_string())
+    })?;
+    if let Some(metrics) = metrics {
+        metrics.record_article();
+    }
+    article.remove_elements(&state.app_config.remove_selectors);
+    if let Some(rule) = site_rule {
+        article.remove_elements(&rule.remove_selectors);
+    }
+
+    let content = article
+        .node_ref()
+        .select_first("div[id=\"readability-page-1\"]")
+        .map(|page| page.as_node().to_string())
+        .unwrap_or_default();
+    match format.as_deref().unwrap_or("json") {
+        "json" => {
+            let body = Body::from_json(&json!({
+                "url": article.url,
+                "title": article.metadata().title(),
+                "byline": article.metadata().byline(),
+                "published": article.metadata().published(),
+                "keywords": article.metadata().keywords(),
+                "image": article.metadata().image(),
+                "content": content,
+            }))?;
+            Ok(Response::builder(StatusCode::Ok).body(body).build())
+        }
+        "html" => Ok(Response::builder(StatusCode::Ok)
+            .body(article.node_ref().to_string())
+            .content_type(tide::http::mime::HTML)
+            .build()),
+        other => Err(tide::Error::from_str(
+            StatusCode::BadRequest,
+            format!("Unknown format {:?}. Expected json or html", other),
+        )),
+    }
+}
+
+/// Downloads the articles with their images and returns them merged into an EPUB
+async fn epub(mut req: Request<ServerState>) -> tide::Result {
+    let request: EpubRequest = req.body_json().await?;
+    check_epub_request(&request)?;
+
+    let mut epub_path = std::env::temp_dir();
+    epub_path.push(format!(
+        "paperoni-server-{}-{}.epub",
+        std::process::id(),
+        EPUB_COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    let app_config = epub_config(&req.state().app_config, &request, &epub_path);
+
+    // The download and EPUB generation block so they are run off the server's threads
+    let (epub_result, errors) = task::spawn_blocking(move || {
+        let (export_result, errors) =
+            download_epub(app_config, request.title, &ProgressBar::hidden());
+        let epub_result = export_result.map(|export_result| {
+            export_result.and_then(|_| fs::read(&epub_path).map_err(|e| vec![e.into()]))
+        });
+        let _ = fs::remove_file(&epub_path);
+        (epub_result, errors)
+    })
+    .await;
+
+    log_errors(&req.state().app_config, &errors);
+    match epub_result {
+        Some(Ok(epub_buf)) => Ok(Response::builder(StatusCode::Ok)
+            .body(epub_buf)
+            .content_type("application/epub+zip")
+            .header(
+                "Content-Disposition",
+                "attachment; filename=\"articles.epub\"",
+            )
+            .build()),
+        Some(Err(epub_errors)) => Err(errors_response(
+            StatusCode::InternalServerError,
+            &epub_errors,
+        )),
+        None => Err(errors_response(StatusCode::BadGateway, &errors)),
+    }
+}
+
+/// Queues the EPUB of the articles to be generated in the background and returns the id of
+/// its job
+async fn submit_job(mut req: Request<ServerState>) -> tide::Result {
+    let request: EpubRequest = req.body_json().await?;
+    check_epub_request(&request)?;
+    let id = req.state().jobs.submit(request).ok_or_else(|| {
+        tide::Error::from_str(
+            StatusCode::ServiceUnavailable,
+            "Too many jobs are queued. Try again later",
+        )
+    })?;
+    info!("Queued job {}", id);
+    Ok(Response::builder(StatusCode::Accepted)
+        .body(Body::from_json(&json!({ "id": id }))?)
+        .header("Location", format!("/jobs/{}", id))
+        .build())
+}
+
+/// Returns the status of a job with how many of its articles are downloaded and the reasons
+/// the others failed
+async fn job_status(req: Request<ServerState>) -> tide::Result {
+    let id = req.param("id")?;
+    match req.state().jobs.status(id) {
+        Some(status) => Ok(Response::builder(StatusCode::Ok)
+            .body(Body::from_json(&status)?)
+            .build()),
+        None => Err(unknown_job(id)),
+    }
+}
+
+/// Streams the EPUB of a finished job
+async fn job_result(req: Request<ServerState>) -> tide::Result {
+    let id = req.param("id")?;
+    match req.state().jobs.result(id) {
+        Some(Ok(epub_path)) => Ok(Response::builder(StatusCode::Ok)
+            .body(Body::from_file(epub_path).await?)
+            .content_type("application/epub+zip")
+            .header(
+                "Content-Disposition",
+                "attachment; filename=\"articles.epub\"",
+            )
+            .build()),
+        Some(Err(JobStatus::Failed)) => Err(tide::Error::from_str(
+            StatusCode::Conflict,
+            format!("The job failed. Its errors are listed at /jobs/{}", id),
+        )),
+        Some(Err(_)) => Err(tide::Error::from_str(
+            StatusCode::Conflict,
+            "The job has not finished yet",
+        )),
+        None => Err(unknown_job(id)),
+    }
+}
+
+fn unknown_job(id: &str) -> tide::Error {
+    tide::Error::from_str(StatusCode::NotFound, format!("There is no job {:?}", id))
+}
+
+/// Runs the queued jobs one after the other. The server starts as many runners as jobs can
+/// run at the same time.
+async fn run_jobs(state: ServerState) {
+    while let Some(QueuedJob {
+        id,
+        request,
+        bar,
+        epub_path,
+    }) = state.jobs.next().await
+    {
+        info!("Running job {} of {} article(s)", id, request.urls.len());
+        let app_config = epub_config(&state.app_config, &request, &epub_path);
+        let (export_result, errors) =
+            task::spawn_blocking(move || download_epub(app_config, request.title, &bar)).await;
+        log_errors(&state.app_config, &errors);
+        let mut reasons = error_reasons(&errors);
+        let is_done = match export_result {
+            Some(Ok(_)) => true,
+            Some(Err(export_errors)) => {
+                reasons.extend(error_reasons(&export_errors));
+                false
+            }
+            None => false,
+        };
+        info!("Job {} {}", id, if is_done { "is done" } else { "failed" });
+        state.jobs.finish(&id, is_done, reasons);
+    }
+}
+
+fn check_epub_request(request: &EpubRequest) -> Result<(), tide::Error> {
+    if request.urls.is_empty() {
+        Err(tide::Error::from_str(
+            StatusCode::BadRequest,
+            "No urls were provided",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// The config of the server with the articles of the request merged into an EPUB at
+/// `epub_path`
+fn epub_config(server_config: &AppConfig, request: &EpubRequest, epub_path: &Path) -> AppConfig {
+    let mut app_config = server_config.clone();
+    app_config.urls = request.urls.clone();
+    app_config.merged = Some(epub_path.to_string_lossy().into_owned());
+    app_config.export_type = ExportType::EPUB;
+    app_config.author = request.author.clone();
+    app_config.can_disable_progress_bar = true;
+    app_config.crawl_depth = 0;
+    app_config
+}
+
+/// Downloads the articles and merges them into the EPUB of the config. The result of the
+/// export is `None` when none of the articles could be downloaded, and is returned with the
+/// errors of the articles that failed to download.
+fn download_epub(
+    mut app_config: AppConfig,
+    title: Option<String>,
+    bar: &ProgressBar,
+) -> (Option<Result<(), Vec<PaperoniError>>>, Vec<PaperoniError>) {
+    let mut partial_downloads = Vec::new();
+    let mut errors = Vec::new();
+    let articles = download(
+        &app_config,
+        bar,
+        &mut partial_downloads,
+        &mut errors,
+        &mut Vec::new(),
+    );
+    if articles.is_empty() {
+        return (None, errors);
+    }
+    app_config.title = title.or_else(|| match articles.as_slice() {
+        [article] => Some(article.metadata().title().to_owned()),
+        articles => Some(format!("{} articles", articles.len())),
+    });
+    let export_start = Instant::now();
+    let export_result = export_articles(
+        articles,
+        &app_config,
+        &mut Table::new(),
+        &mut ExportedFiles::default(),
+    );
+    if let Some(metrics) = app_config.metrics() {
+        metrics.record_export(export_start.elapsed());
+        if let Err(export_errors) = &export_result {
+            for err in export_errors {
+                metrics.record_failure(err.kind());
+            }
+        }
+    }
+    (Some(export_result), errors)
+}
+
+fn log_errors(app_config: &AppConfig, errors: &[PaperoniError]) {
+    for err in errors {
+        if let Some(metrics) = app_config.metrics() {
+            metrics.record_failure(err.kind());
+        }
+        error!(
+            "{} - {}",
+            err,
+            err.article_source().as_deref().unwrap_or("<unknown link>")
+        );
+    }
+}
+
+fn error_reasons(errors: &[PaperoniError]) -> Vec<String> {
+    errors
+        .iter()
+        .map(|err| match err.article_source() {
+            Some(source) => format!("{}: {}", source, err),
+            None => err.to_string(),
+        })
+        .collect()
+}
+
+fn errors_response(status: StatusCode, errors: &[PaperoniError]) -> tide::Error {
+    tide::Error::from_str(status, error_reasons(errors).join("\n"))
+}
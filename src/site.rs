@@ -0,0 +1,329 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use comfy_table::Table;
+use kuchiki::traits::*;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::atomic_file::write_atomic;
+use crate::cli::{AppConfig, ExportType, OverwritePolicy};
+use crate::errors::PaperoniError;
+use crate::exporter::{export_articles, file_safe_title, ExportFileNames, ExportedFiles};
+use crate::extractor::{new_element, Article};
+use crate::feed::{escape_xml, percent_encode};
+
+/// The catalogue of every article in the site. It is kept next to the index so that articles
+/// saved in earlier runs stay listed.
+pub const SITE_CATALOGUE: &str = "site.json";
+const ARTICLES_DIR: &str = "articles";
+const ASSETS_DIR: &str = "assets";
+
+/// An article listed in the index of the site
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SiteEntry {
+    pub title: String,
+    pub url: String,
+    pub domain: String,
+    /// The day the article was published on as `YYYY-MM-DD`
+    #[serde(default)]
+    pub published: Option<String>,
+    /// The day the article was saved on as `YYYY-MM-DD`
+    pub added: String,
+    /// The path of the article page relative to the site directory
+    pub file: String,
+}
+
+impl SiteEntry {
+    /// The day the article is listed under, which is when it was published if known
+    pub fn date(&self) -> &str {
+        self.published.as_deref().unwrap_or(&self.added)
+    }
+}
+
+/// Builds a static reading site in the output directory. The articles are exported with the
+/// HTML exporter into `articles/` and listed in an `index.html` grouped by month and by site.
+pub fn generate_site(
+    articles: Vec<Article>,
+    app_config: &AppConfig,
+    successful_articles_table: &mut Table,
+    exported_files: &mut ExportedFiles,
+) -> Result<(), Vec<PaperoniError>> {
+    let site_dir = Path::new(app_config.output_directory.as_deref().unwrap_or("."));
+    let articles_dir = site_dir.join(ARTICLES_DIR);
+    let assets_dir = site_dir.join(ASSETS_DIR);
+    if let Err(err) =
+        fs::create_dir_all(&articles_dir).and_then(|_| fs::create_dir_all(&assets_dir))
+    {
+        return Err(vec![err.into()]);
+    }
+
+    let articles_dir = articles_dir.to_string_lossy().into_owned();
+    let added = app_config.start_time.format("%Y-%m-%d").to_string();
+    // The pages are named the same way by the HTML exporter
+    let mut file_names = ExportFileNames::default();
+    let new_entries = articles
+        .iter()
+        .map(|article| {
+            let file_name = file_names.unique(&articles_dir, &file_safe_title(article), "html");
+            site_entry(article, &file_name, &added)
+        })
+        .collect::<Vec<_>>();
+    for article in &articles {
+        insert_index_link(article);
+    }
+
+    let mut page_config = app_config.clone();
+    page_config.export_type = ExportType::HTML;
+    page_config.output_directory = Some(articles_dir);
+    page_config.is_preserving_meta = true;
+    // The pages of articles already on the site are written again under the same names
+    page_config.overwrite = OverwritePolicy::Replace;
+    let mut errors = match export_articles(
+        articles,
+        &page_config,
+        successful_articles_table,
+        exported_files,
+    ) {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors,
+    };
+
+    let catalogue_path = site_dir.join(SITE_CATALOGUE);
+    let mut entries = load_catalogue(&catalogue_path);
+    for entry in new_entries {
+        if errors
+            .iter()
+            .any(|err| err.article_source().as_deref() == Some(entry.url.as_str()))
+        {
+            continue;
+        }
+        entries.retain(|existing| existing.url != entry.url);
+        entries.push(entry);
+    }
+
+    let write_site = || -> Result<(), PaperoniError> {
+        let catalogue_json = serde_json::to_string_pretty(&entries)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_atomic(&catalogue_path, catalogue_json)?;
+        write_atomic(
+            assets_dir.join("site.css"),
+            include_str!("./assets/site.min.css"),
+        )?;
+        write_atomic(site_dir.join("index.html"), render_index(&entries))?;
+        Ok(())
+    };
+    match write_site() {
+        Ok(_) => {
+            info!(
+                "Wrote the site index of {} articles to {}",
+                entries.len(),
+                site_dir.display()
+            );
+            exported_files
+                .written
+                .push(site_dir.join("index.html").to_string_lossy().into_owned());
+        }
+        Err(mut err) => {
+            err.set_article_source(&site_dir.join("index.html").to_string_lossy());
+            errors.push(err);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn site_entry(article: &Article, file_name: &str, added: &str) -> SiteEntry {
+    let file = Path::new(file_name)
+        .file_name()
+        .map(|name| format!("{}/{}", ARTICLES_DIR, name.to_string_lossy()))
+        .unwrap_or_default();
+    SiteEntry {
+        title: article.metadata().title().to_owned(),
+        url: article.url.clone(),
+        domain: domain_of(&article.url),
+        published: article
+            .metadata()
+            .published_date()
+            .map(|date| date.format("%Y-%m-%d").to_string()),
+        added: added.to_owned(),
+        file,
+    }
+}
+
+/// The host of a url without its `www.` prefix
+fn domain_of(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(ToOwned::to_owned))
+        .map(|host| host.trim_start_matches("www.").to_owned())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+/// Adds a link back to the index at the top of an article page
+fn insert_index_link(article: &Article) {
+    if let Ok(body) = article.node_ref().select_first("body") {
+        let link = new_element("a", &[("href", "../index.html")]);
+        link.append(kuchiki::NodeRef::new_text("All articles"));
+        let nav = new_element("nav", &[("class", "site-nav")]);
+        nav.append(link);
+        body.as_node().prepend(nav);
+    }
+}
+
+pub fn load_catalogue(path: &Path) -> Vec<SiteEntry> {
+    match fs::read_to_string(path) {
+        Ok(catalogue_json) => serde_json::from_str(&catalogue_json).unwrap_or_else(|e| {
+            warn!(
+                "Ignoring the site catalogue in {} as it is invalid: {}",
+                path.display(),
+                e
+            );
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Groups the entries by the month of their date, newest first
+fn group_by_month(entries: &[SiteEntry]) -> Vec<(String, Vec<&SiteEntry>)> {
+    let mut groups: BTreeMap<String, Vec<&SiteEntry>> = BTreeMap::new();
+    for entry in entries {
+        let month = entry.date().get(..7).unwrap_or("").to_owned();
+        groups.entry(month).or_default().push(entry);
+    }
+    groups
+        .into_iter()
+        .rev()
+        .map(|(month, mut entries)| {
+            entries.sort_by(|a, b| b.date().cmp(a.date()));
+            let heading = NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d")
+                .map(|date| date.format("%B %Y").to_string())
+                .unwrap_or_else(|_| "Undated".to_owned());
+            (heading, entries)
+        })
+        .collect()
+}
+
+/// Groups the entries by their domain in alphabetical order, newest first within a domain
+fn group_by_domain(entries: &[SiteEntry]) -> Vec<(String, Vec<&SiteEntry>)> {
+    let mut groups: BTreeMap<String, Vec<&SiteEntry>> = BTreeMap::new();
+    for entry in entries {
+        groups.entry(entry.domain.clone()).or_default().push(entry);
+    }
+    groups
+        .into_iter()
+        .map(|(domain, mut entries)| {
+            entries.sort_by(|a, b| b.date().cmp(a.date()));
+            (domain, entries)
+        })
+        .collect()
+}
+
+fn render_section(heading: &str, groups: &[(String, Vec<&SiteEntry>)]) -> String {
+    let groups = groups
+        .iter()
+        .map(|(group, entries)| {
+            let items = entries
+                .iter()
+                .map(|entry| {
+                    let link = entry
+                        .file
+                        .split('/')
+                        .map(percent_encode)
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    format!(
+                        "<li><a href=\"{}\">{}</a> <span class=\"meta\">{} &middot; {}</span></li>\n",
+                        escape_xml(&link),
+                        escape_xml(&entry.title),
+                        escape_xml(&entry.domain),
+                        escape_xml(entry.date())
+                    )
+                })
+                .collect::<String>();
+            format!("<h3>{}</h3>\n<ul>\n{}</ul>\n", escape_xml(group), items)
+        })
+        .collect::<String>();
+    format!("<section>\n<h2>{}</h2>\n{}</section>\n", heading, groups)
+}
+
+fn render_index(entries: &[SiteEntry]) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"UTF-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>Saved articles</title>\n<link rel=\"stylesheet\" href=\"{}/site.css\">\n</head>\n<body>\n<h1>Saved articles</h1>\n<p class=\"meta\">{} articles</p>\n{}{}</body>\n</html>\n",
+        ASSETS_DIR,
+        entries.len(),
+        render_section("By date", &group_by_month(entries)),
+        render_section("By site", &group_by_domain(entries))
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(title: &str, url: &str, published: Option<&str>, added: &str) -> SiteEntry {
+        SiteEntry {
+            title: title.to_owned(),
+            url: url.to_owned(),
+            domain: domain_of(url),
+            published: published.map(ToOwned::to_owned),
+            added: added.to_owned(),
+            file: format!("{}/{}.html", ARTICLES_DIR, title),
+        }
+    }
+
+    #[test]
+    fn test_group_entries() {
+        let entries = vec![
+            entry("A", "https://www.a.com/1", Some("2023-01-15"), "2023-03-01"),
+            entry("B", "https://b.com/1", None, "2023-03-01"),
+            entry("C", "https://a.com/2", Some("2023-01-20"), "2023-03-01"),
+        ];
+        let by_month = group_by_month(&entries);
+        assert_eq!(
+            vec!["March 2023", "January 2023"],
+            by_month
+                .iter()
+                .map(|(month, _)| month.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["C", "A"],
+            by_month[1]
+                .1
+                .iter()
+                .map(|entry| entry.title.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        let by_domain = group_by_domain(&entries);
+        assert_eq!("a.com", by_domain[0].0);
+        assert_eq!(2, by_domain[0].1.len());
+        assert_eq!("b.com", by_domain[1].0);
+    }
+
+    #[test]
+    fn test_render_index() {
+        let index = render_index(&[entry(
+            "Tips & tricks",
+            "https://a.com/tips",
+            Some("2023-01-15"),
+            "2023-03-01",
+        )]);
+        assert!(
+            index.contains("<a href=\"articles/Tips%20%26%20tricks.html\">Tips &amp; tricks</a>")
+        );
+        assert!(index.contains("<h3>January 2023</h3>"));
+        assert!(index.contains("<h3>a.com</h3>"));
+        assert!(index.contains("href=\"assets/site.css\""));
+    }
+}
@@ -0,0 +1,228 @@
+use std::fs;
+use std::path::PathBuf;
+
+use log::{debug, warn};
+
+use crate::extractor::Article;
+
+/// How images are recompressed at each step of fitting an output in its size budget. Each
+/// step has a JPEG quality and the largest width or height images are scaled down to.
+const COMPRESSION_STEPS: [(u8, u32); 3] = [(80, 1600), (60, 1200), (40, 800)];
+
+/// Shrinks the images of the articles of each output file until the file fits in the size
+/// budget in bytes. Images are recompressed more aggressively at each step and dropped
+/// starting from the largest when that is not enough. The articles are a single output
+/// when they are merged.
+pub fn fit_size_budget(articles: &mut [Article], budget: u64, is_merged: bool) {
+    if is_merged {
+        fit_output(articles, budget, "the merged output");
+    } else {
+        for article in articles.iter_mut() {
+            let url = article.url.clone();
+            fit_output(std::slice::from_mut(article), budget, &url);
+        }
+    }
+}
+
+fn fit_output(articles: &mut [Article], budget: u64, output_name: &str) {
+    let mut size = output_size(articles);
+    if size <= budget {
+        return;
+    }
+    debug!(
+        "{} is {} bytes which is over the budget of {} bytes",
+        output_name, size, budget
+    );
+
+    for (step, (quality, max_dimension)) in COMPRESSION_STEPS.iter().enumerate() {
+        let compressed_count = articles
+            .iter()
+            .flat_map(|article| article.img_urls.iter())
+            .filter(|(file_name, mime)| {
+                compress_image(
+                    &img_path(file_name),
+                    mime.as_deref(),
+                    *quality,
+                    *max_dimension,
+                )
+            })
+            .count();
+        if compressed_count == 0 {
+            // Images cannot be compressed when Paperoni is built without image-compression
+            break;
+        }
+        size = output_size(articles);
+        warn!(
+            "Compressed {} image(s) of {} at quality {} to fit in {} bytes (step {} of {})",
+            compressed_count,
+            output_name,
+            quality,
+            budget,
+            step + 1,
+            COMPRESSION_STEPS.len()
+        );
+        if size <= budget {
+            return;
+        }
+    }
+
+    let mut imgs = articles
+        .iter()
+        .enumerate()
+        .flat_map(|(article_idx, article)| {
+            article
+                .img_urls
+                .iter()
+                .map(move |(file_name, _)| (article_idx, file_name.clone()))
+        })
+        .map(|(article_idx, file_name)| {
+            let img_size = file_size(&file_name);
+            (article_idx, file_name, img_size)
+        })
+        .collect::<Vec<_>>();
+    imgs.sort_by(|(_, _, a), (_, _, b)| b.cmp(a));
+    let mut dropped_count = 0;
+    for (article_idx, file_name, img_size) in imgs {
+        if size <= budget {
+            break;
+        }
+        let article = &mut articles[article_idx];
+        article.remove_image(&file_name);
+        article.img_urls.retain(|(name, _)| *name != file_name);
+        size = size.saturating_sub(img_size);
+        dropped_count += 1;
+    }
+    if dropped_count > 0 {
+        warn!(
+            "Dropped {} image(s) from {} to fit in {} bytes",
+            dropped_count, output_name, budget
+        );
+    }
+    if size > budget {
+        warn!(
+            "{} is still {} bytes after dropping its images which is over the budget of {} bytes",
+            output_name, size, budget
+        );
+    }
+}
+
+/// Estimates the size of an output from the HTML of its articles and their images. Exports
+/// are compressed so the estimate is on the safe side.
+fn output_size(articles: &[Article]) -> u64 {
+    articles
+        .iter()
+        .map(|article| {
+            let html_size = article.node_ref().to_string().len() as u64;
+            let imgs_size = article
+                .img_urls
+                .iter()
+                .chain(article.cover_img.iter())
+                .map(|(file_name, _)| file_size(file_name))
+                .sum::<u64>();
+            html_size + imgs_size
+        })
+        .sum()
+}
+
+fn img_path(file_name: &str) -> PathBuf {
+    std::env::temp_dir().join(file_name)
+}
+
+fn file_size(file_name: &str) -> u64 {
+    fs::metadata(img_path(file_name))
+        .map(|metadata| metadata.len())
+        .unwrap_or(0)
+}
+
+/// Recompresses a downloaded JPEG or PNG in place, scaling it down when it is larger than
+/// the max dimension. The image is only replaced when the result is smaller.
+#[cfg(feature = "image")]
+fn compress_image(
+    img_path: &std::path::Path,
+    mime: Option<&str>,
+    quality: u8,
+    max_dimension: u32,
+) -> bool {
+    use image::{imageops::FilterType, GenericImageView, ImageOutputFormat};
+
+    let format = match mime {
+        Some("image/jpeg") => ImageOutputFormat::Jpeg(quality),
+        Some("image/png") => ImageOutputFormat::Png,
+        _ => return false,
+    };
+    let img_bytes = match fs::read(img_path) {
+        Ok(img_bytes) => img_bytes,
+        Err(_) => return false,
+    };
+    let mut img = match image::load_from_memory(&img_bytes) {
+        Ok(img) => img,
+        Err(e) => {
+            debug!("Unable to decode {:?} for compression: {}", img_path, e);
+            return false;
+        }
+    };
+    let (width, height) = img.dimensions();
+    if width > max_dimension || height > max_dimension {
+        img = img.resize(max_dimension, max_dimension, FilterType::Triangle);
+    }
+    let mut compressed_bytes = Vec::new();
+    if img.write_to(&mut compressed_bytes, format).is_err()
+        || compressed_bytes.len() >= img_bytes.len()
+    {
+        return false;
+    }
+    fs::write(img_path, compressed_bytes).is_ok()
+}
+
+#[cfg(not(feature = "image"))]
+fn compress_image(
+    _img_path: &std::path::Path,
+    _mime: Option<&str>,
+    _quality: u8,
+    _max_dimension: u32,
+) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fit_size_budget_drops_largest_images() {
+        let prefix = format!("paperoni-size-budget-test-{}", std::process::id());
+        let small_name = format!("{}-small.bin", prefix);
+        let large_name = format!("{}-large.bin", prefix);
+        fs::write(img_path(&small_name), vec![0u8; 1_000]).unwrap();
+        fs::write(img_path(&large_name), vec![0u8; 50_000]).unwrap();
+
+        let html = r#"<div id="post"><p>Text</p><img src="a.png"><img src="b.png"></div>"#;
+        let mut article = Article::from_html(html, "http://example.com/");
+        article.extract_content_with_selector("#post").unwrap();
+        // The srcs point to the downloaded images like after download_images
+        for (img_ref, name) in article
+            .node_ref()
+            .select("img")
+            .unwrap()
+            .zip(&[&small_name, &large_name])
+        {
+            img_ref
+                .attributes
+                .borrow_mut()
+                .insert("src", name.to_string());
+        }
+        article.img_urls = vec![(small_name.clone(), None), (large_name.clone(), None)];
+        let mut articles = vec![article];
+
+        // Nothing is dropped when the output fits
+        fit_size_budget(&mut articles, 100_000, false);
+        assert_eq!(2, articles[0].img_urls.len());
+
+        fit_size_budget(&mut articles, 10_000, false);
+        assert_eq!(vec![(small_name.clone(), None)], articles[0].img_urls);
+        assert_eq!(1, articles[0].node_ref().select("img").unwrap().count());
+
+        fs::remove_file(img_path(&small_name)).unwrap();
+        fs::remove_file(img_path(&large_name)).unwrap();
+    }
+}
@@ -0,0 +1,38 @@
+#[cfg(feature = "source-qr")]
+use log::warn;
+#[cfg(feature = "source-qr")]
+use qrcode::{render::svg, QrCode};
+
+/// Renders a QR code of the url of an article as an SVG image so that readers of printed or
+/// e-ink copies can scan it to get back to the original. Returns None when the url does not
+/// fit in a QR code.
+#[cfg(feature = "source-qr")]
+pub fn source_qr_svg(url: &str) -> Option<String> {
+    match QrCode::new(url.as_bytes()) {
+        Ok(code) => Some(code.render::<svg::Color>().min_dimensions(160, 160).build()),
+        Err(e) => {
+            warn!("Unable to create a QR code of {}: {}", url, e);
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "source-qr"))]
+pub fn source_qr_svg(_url: &str) -> Option<String> {
+    None
+}
+
+#[cfg(all(test, feature = "source-qr"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_source_qr_svg() {
+        let svg = source_qr_svg("https://example.com/pizza").unwrap();
+        assert!(svg.starts_with("<?xml"));
+        assert!(svg.contains("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>"));
+        // A QR code holds at most 2953 bytes
+        assert_eq!(None, source_qr_svg(&"a".repeat(3000)));
+    }
+}
@@ -0,0 +1,311 @@
+use std::{collections::HashMap, io::Write};
+
+use kuchiki::{NodeData, NodeRef};
+use log::{debug, error};
+use regex::Regex;
+use url::Url;
+
+use crate::{
+    atomic_file::{write_atomic, AtomicFile},
+    cli::AppConfig,
+    errors::PaperoniError,
+    exporter::{file_safe_title, own_or_merged_file, ExportFileNames, Exporter},
+    extractor::Article,
+};
+
+const SSML_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<speak version="1.1" xmlns="http://www.w3.org/2001/10/synthesis">
+"#;
+const SSML_FOOTER: &str = "</speak>\n";
+/// Pause between the title of an article and its content
+const TITLE_BREAK: &str = r#"<break time="1s"/>"#;
+/// Pause between articles in a merged export
+const ARTICLE_BREAK: &str = r#"<break time="2s"/>"#;
+
+/// Exports each article to its own SSML file, or merges them into a single file with a
+/// pause between them
+pub struct SsmlExporter<'a> {
+    app_config: &'a AppConfig,
+    merged_ssml: String,
+    file_names: ExportFileNames,
+    /// The files of the articles written so far by their index
+    article_files: HashMap<usize, String>,
+    written_files: Vec<String>,
+}
+
+impl<'a> SsmlExporter<'a> {
+    pub fn new(app_config: &'a AppConfig) -> Self {
+        Self {
+            app_config,
+            merged_ssml: String::new(),
+            file_names: ExportFileNames::new(app_config.overwrite),
+            article_files: HashMap::new(),
+            written_files: Vec::new(),
+        }
+    }
+}
+
+impl Exporter for SsmlExporter<'_> {
+    fn format(&self) -> &'static str {
+        "ssml"
+    }
+
+    fn prepare(&mut self, _articles: &[Article]) -> Result<(), PaperoniError> {
+        if let Some(name) = &self.app_config.merged {
+            debug!("Creating {:?}", name);
+            self.merged_ssml.push_str(SSML_HEADER);
+        }
+        Ok(())
+    }
+
+    fn add_article(&mut self, idx: usize, article: &Article) -> Result<(), PaperoniError> {
+        if self.app_config.merged.is_some() {
+            if idx > 0 {
+                self.merged_ssml.push_str(ARTICLE_BREAK);
+                self.merged_ssml.push('\n');
+            }
+            self.merged_ssml.push_str(&article_to_ssml(article));
+            debug!(
+                "Added {} to the export SSML file",
+                article.metadata().title()
+            );
+            return Ok(());
+        }
+
+        let file_name = match self.file_names.target(
+            self.app_config.output_directory.as_deref().unwrap_or("."),
+            &file_safe_title(article),
+            "ssml",
+        ) {
+            Some(file_name) => file_name,
+            None => return Ok(()),
+        };
+        let mut out_file = AtomicFile::create(&file_name)?;
+        out_file.write_all(SSML_HEADER.as_bytes())?;
+        out_file.write_all(article_to_ssml(article).as_bytes())?;
+        out_file.write_all(SSML_FOOTER.as_bytes())?;
+        out_file.persist()?;
+        debug!("Created {:?}", file_name);
+        self.article_files.insert(idx, file_name.clone());
+        self.written_files.push(file_name);
+        Ok(())
+    }
+
+    fn finalize(
+        &mut self,
+        _articles: &[Article],
+    ) -> Result<(), Vec<(Option<usize>, PaperoniError)>> {
+        let name = match &self.app_config.merged {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        self.merged_ssml.push_str(SSML_FOOTER);
+        if let Err(mut err) =
+            write_atomic(name, &self.merged_ssml).map_err(|e| -> PaperoniError { e.into() })
+        {
+            error!("Failed to write articles to file: {}", name);
+            err.set_article_source(name);
+            return Err(vec![(None, err)]);
+        };
+
+        debug!("Created {:?}", name);
+        println!("Created {:?}", name);
+        self.written_files.push(name.clone());
+        Ok(())
+    }
+
+    fn written_files(&self) -> &[String] {
+        &self.written_files
+    }
+
+    fn article_file(&self, idx: usize) -> Option<&str> {
+        own_or_merged_file(
+            idx,
+            self.app_config.merged.as_deref(),
+            &self.article_files,
+            &self.written_files,
+        )
+    }
+}
+
+/// Converts an article to the SSML elements read out for it, starting with its title
+pub fn article_to_ssml(article: &Article) -> String {
+    let mut writer = SsmlWriter::default();
+    let title = article.metadata().title();
+    if !title.is_empty() {
+        writer.buf.push_str(&format!(
+            "<p><emphasis level=\"strong\">{}</emphasis></p>{}\n",
+            escape_text(title),
+            TITLE_BREAK
+        ));
+    }
+    match article.node_ref().select_first("body") {
+        Ok(body) => writer.write_children(body.as_node()),
+        Err(_) => writer.write_children(article.node_ref()),
+    }
+    writer.finish()
+}
+
+/// Builds the SSML of an article's content. Block elements are read as paragraphs
+/// and elements that cannot be read out such as images and code blocks are skipped.
+#[derive(Default)]
+struct SsmlWriter {
+    buf: String,
+    in_paragraph: bool,
+}
+
+impl SsmlWriter {
+    fn write_children(&mut self, node_ref: &NodeRef) {
+        for child in node_ref.children() {
+            self.write_node(&child);
+        }
+    }
+
+    fn write_node(&mut self, node_ref: &NodeRef) {
+        match node_ref.data() {
+            NodeData::Text(text) => {
+                self.buf.push_str(&escape_text(&text.borrow()));
+            }
+            NodeData::Element(elem) => match elem.name.local.as_ref() {
+                "script" | "style" | "noscript" | "img" | "picture" | "svg" | "math" | "pre"
+                | "iframe" | "video" | "audio" | "head" => (),
+                "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                    self.write_paragraph(node_ref, "<emphasis level=\"strong\">", "</emphasis>");
+                    if !(self.in_paragraph || node_ref.text_contents().trim().is_empty()) {
+                        self.buf.push_str(TITLE_BREAK);
+                        self.buf.push('\n');
+                    }
+                }
+                "p" | "li" | "dt" | "dd" | "figcaption" | "caption" | "tr" => {
+                    self.write_paragraph(node_ref, "", "")
+                }
+                "em" | "i" | "cite" => {
+                    self.write_inline(node_ref, "<emphasis level=\"moderate\">", "</emphasis>")
+                }
+                "strong" | "b" => {
+                    self.write_inline(node_ref, "<emphasis level=\"strong\">", "</emphasis>")
+                }
+                "a" => self.write_link(node_ref),
+                "br" => self.buf.push_str("<break strength=\"weak\"/>"),
+                "td" | "th" => {
+                    self.write_children(node_ref);
+                    self.buf.push_str("<break strength=\"weak\"/>");
+                }
+                _ => self.write_children(node_ref),
+            },
+            _ => (),
+        }
+    }
+
+    /// Wraps the content of a block element in a paragraph. Block elements nested in a
+    /// paragraph are read as part of it since SSML paragraphs cannot be nested.
+    fn write_paragraph(&mut self, node_ref: &NodeRef, open_tag: &str, close_tag: &str) {
+        if self.in_paragraph {
+            self.buf.push(' ');
+            self.write_inline(node_ref, open_tag, close_tag);
+            self.buf.push(' ');
+            return;
+        }
+        if node_ref.text_contents().trim().is_empty() {
+            return;
+        }
+        self.in_paragraph = true;
+        self.buf.push_str("<p>");
+        self.write_inline(node_ref, open_tag, close_tag);
+        self.buf.push_str("</p>\n");
+        self.in_paragraph = false;
+    }
+
+    fn write_inline(&mut self, node_ref: &NodeRef, open_tag: &str, close_tag: &str) {
+        self.buf.push_str(open_tag);
+        self.write_children(node_ref);
+        self.buf.push_str(close_tag);
+    }
+
+    /// Links whose text is a url are read as "link to" followed by the host instead of
+    /// having the full url spelled out
+    fn write_link(&mut self, node_ref: &NodeRef) {
+        let text = node_ref.text_contents();
+        let text = text.trim();
+        if text.starts_with("http://") || text.starts_with("https://") || text.starts_with("www.") {
+            let url_str = if text.starts_with("www.") {
+                format!("http://{}", text)
+            } else {
+                text.to_owned()
+            };
+            let alias = Url::parse(&url_str)
+                .ok()
+                .and_then(|url| {
+                    url.host_str()
+                        .map(|host| host.trim_start_matches("www.").to_owned())
+                })
+                .map(|host| format!("link to {}", host))
+                .unwrap_or_else(|| "link".to_owned());
+            self.buf.push_str(&format!(
+                "<sub alias=\"{}\">{}</sub>",
+                escape_text(&alias),
+                escape_text(text)
+            ));
+        } else {
+            self.write_children(node_ref);
+        }
+    }
+
+    /// Collapses the whitespace left over from the HTML formatting
+    fn finish(self) -> String {
+        lazy_static! {
+            static ref WHITESPACE_REGEX: Regex = Regex::new(r"[ \t\r\n]+").unwrap();
+            static ref PARAGRAPH_START_REGEX: Regex = Regex::new(r"<p> ").unwrap();
+            static ref PARAGRAPH_END_REGEX: Regex = Regex::new(r" </p>").unwrap();
+        }
+        let ssml = WHITESPACE_REGEX.replace_all(&self.buf, " ");
+        let ssml = PARAGRAPH_START_REGEX.replace_all(&ssml, "<p>");
+        let ssml = PARAGRAPH_END_REGEX.replace_all(&ssml, "</p>");
+        ssml.replace("</p> ", "</p>\n")
+            .replace("/> ", "/>\n")
+            .trim_start()
+            .to_owned()
+    }
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace("&", "&amp;")
+        .replace("<", "&lt;")
+        .replace(">", "&gt;")
+        .replace("\"", "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use kuchiki::traits::*;
+
+    #[test]
+    fn test_ssml_writer() {
+        let html = r#"
+            <div>
+                <h2>A <em>heading</em></h2>
+                <p>Some <strong>bold</strong> &amp; <i>italic</i> text.</p>
+                <p>   </p>
+                <img src="img.png">
+                <pre><code>fn main() {}</code></pre>
+                <ul>
+                    <li><p>First item</p></li>
+                    <li>Read <a href="https://example.com/docs">the docs</a> or visit
+                        <a href="https://www.example.org/a">https://www.example.org/a</a></li>
+                </ul>
+            </div>
+        "#;
+        let doc = kuchiki::parse_html().one(html);
+        let mut writer = SsmlWriter::default();
+        writer.write_children(doc.select_first("body").unwrap().as_node());
+        assert_eq!(
+            r#"<p><emphasis level="strong">A <emphasis level="moderate">heading</emphasis></emphasis></p><break time="1s"/>
+<p>Some <emphasis level="strong">bold</emphasis> &amp; <emphasis level="moderate">italic</emphasis> text.</p>
+<p>First item</p>
+<p>Read the docs or visit <sub alias="link to example.org">https://www.example.org/a</sub></p>
+"#,
+            writer.finish()
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use chrono::{TimeZone, Utc};
+use kuchiki::traits::*;
+use log::debug;
+use serde_json::Value;
+use url::Url;
+
+use crate::blog_api::ApiArticle;
+use crate::errors::{ErrorKind, PaperoniError};
+
+const API_URL: &str = "https://api.stackexchange.com/2.3";
+
+/// How many answers are kept besides the accepted answer
+const TOP_ANSWERS: usize = 3;
+
+/// Sites of the Stack Exchange network that are not subdomains of stackexchange.com
+const NETWORK_HOSTS: [&str; 6] = [
+    "stackoverflow.com",
+    "serverfault.com",
+    "superuser.com",
+    "askubuntu.com",
+    "mathoverflow.net",
+    "stackapps.com",
+];
+
+/// A question on a site of the Stack Exchange network
+#[derive(Clone, Debug, PartialEq)]
+pub struct Question {
+    /// The domain of the site which the API accepts as its site parameter
+    site: String,
+    id: u64,
+}
+
+/// An answer to a question returned by the API
+#[derive(Debug, PartialEq)]
+struct Answer {
+    author: Option<String>,
+    score: i64,
+    is_accepted: bool,
+    body: String,
+}
+
+impl Question {
+    /// Recognizes the urls of questions e.g `https://stackoverflow.com/questions/{id}/{slug}`
+    /// or the short `https://superuser.com/q/{id}` ones
+    pub fn from_url(url: &str) -> Option<Self> {
+        let url = Url::parse(url).ok()?;
+        let site = url.host_str()?.trim_start_matches("www.");
+        let is_network_site = NETWORK_HOSTS
+            .iter()
+            .any(|host| site == *host || site.ends_with(&format!(".{}", host)))
+            || site.ends_with(".stackexchange.com");
+        if !is_network_site {
+            return None;
+        }
+        let mut segments = url.path_segments()?;
+        match segments.next() {
+            Some("questions") | Some("q") => (),
+            _ => return None,
+        }
+        let id = segments.next()?.parse().ok()?;
+        Some(Question {
+            site: site.to_owned(),
+            id,
+        })
+    }
+
+    /// Fetches the question and its accepted and top voted answers from the Stack Exchange
+    /// API as an HTML document for extraction with the `API_ARTICLE_ID` selector
+    pub async fn fetch_document(&self, client: &surf::Client) -> Result<String, PaperoniError> {
+        debug!(
+            "Fetching question {} of {} from the API",
+            self.id, self.site
+        );
+        let question = fetch_json(
+            client,
+            &format!(
+                "{}/questions/{}?site={}&filter=withbody",
+                API_URL, self.id, self.site
+            ),
+        )
+        .await?;
+        let answers = fetch_json(
+            client,
+            &format!(
+                "{}/questions/{}/answers?site={}&filter=withbody&sort=votes&order=desc&pagesize=30",
+                API_URL, self.id, self.site
+            ),
+        )
+        .await?;
+        parse_question(&question["items"][0], &answers)
+            .map(|article| article.document())
+            .ok_or_else(|| {
+                ErrorKind::HTTPError("Unexpected response from the Stack Exchange API".into())
+                    .into()
+            })
+    }
+}
+
+async fn fetch_json(client: &surf::Client, url: &str) -> Result<Value, PaperoniError> {
+    let mut res = client
+        .send(surf::get(url).header("User-Agent", "paperoni"))
+        .await?;
+    if !res.status().is_success() {
+        let msg = format!("Stack Exchange API responded with {}", res.status());
+        return Err(ErrorKind::HTTPError(msg).into());
+    }
+    Ok(res.body_json::<Value>().await?)
+}
+
+/// The API escapes the titles and the names of users as HTML
+fn decode_entities(text: &str) -> String {
+    kuchiki::parse_html().one(text).text_contents()
+}
+
+fn votes(score: i64) -> String {
+    if score == 1 || score == -1 {
+        format!("{} vote", score)
+    } else {
+        format!("{} votes", score)
+    }
+}
+
+fn parse_answers(answers: &Value) -> Vec<Answer> {
+    answers["items"]
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|answer| {
+                    Some(Answer {
+                        author: answer["owner"]["display_name"]
+                            .as_str()
+                            .map(decode_entities),
+                        score: answer["score"].as_i64().unwrap_or_default(),
+                        is_accepted: answer["is_accepted"].as_bool().unwrap_or_default(),
+                        body: answer["body"].as_str()?.to_owned(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Lays out the question followed by its accepted answer and its top voted answers, each
+/// with the votes it got
+fn parse_question(question: &Value, answers: &Value) -> Option<ApiArticle> {
+    let title = decode_entities(question["title"].as_str()?);
+    let body = question["body"].as_str()?;
+    let author = question["owner"]["display_name"]
+        .as_str()
+        .map(decode_entities);
+    let score = question["score"].as_i64().unwrap_or_default();
+
+    let mut answers = parse_answers(answers);
+    // The answers are already sorted by votes so the accepted answer is moved to the front
+    if let Some(pos) = answers.iter().position(|answer| answer.is_accepted) {
+        let accepted = answers.remove(pos);
+        answers.insert(0, accepted);
+    }
+    let kept_answers = TOP_ANSWERS + answers.first().map_or(0, |a| a.is_accepted as usize);
+    answers.truncate(kept_answers);
+
+    let mut html = format!(
+        r#"<section class="question"><p><em>Question asked by {} · {}</em></p>{}</section>"#,
+        escape(author.as_deref().unwrap_or("an anonymous user")),
+        votes(score),
+        body
+    );
+    for answer in &answers {
+        html.push_str(&format!(
+            r#"<section class="answer"><h2>{} by {} · {}</h2>{}</section>"#,
+            if answer.is_accepted {
+                "Accepted answer"
+            } else {
+                "Answer"
+            },
+            escape(answer.author.as_deref().unwrap_or("an anonymous user")),
+            votes(answer.score),
+            answer.body
+        ));
+    }
+
+    Some(ApiArticle {
+        title,
+        html,
+        author,
+        published: question["creation_date"]
+            .as_i64()
+            .map(|secs| Utc.timestamp(secs, 0).to_rfc3339()),
+        description: None,
+        cover_image: None,
+        tags: question["tags"]
+            .as_array()
+            .map(|tags| {
+                tags.iter()
+                    .filter_map(|tag| tag.as_str().map(str::to_owned))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
+}
+
+fn escape(text: &str) -> String {
+    kuchiki::NodeRef::new_text(text).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blog_api::API_ARTICLE_ID;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_url() {
+        assert_eq!(
+            Some(Question {
+                site: "stackoverflow.com".into(),
+                id: 11227809
+            }),
+            Question::from_url("https://stackoverflow.com/questions/11227809/why-is-it-faster")
+        );
+        assert_eq!(
+            Some(Question {
+                site: "unix.stackexchange.com".into(),
+                id: 4126
+            }),
+            Question::from_url("https://unix.stackexchange.com/q/4126/1234")
+        );
+        assert_eq!(
+            None,
+            Question::from_url("https://stackoverflow.com/questions/tagged/rust")
+        );
+        assert_eq!(
+            None,
+            Question::from_url("https://example.com/questions/11227809")
+        );
+    }
+
+    #[test]
+    fn test_parse_question() {
+        let question = json!({
+            "title": "Why doesn&#39;t this compile?",
+            "body": "<p>My code:</p><pre><code>let x: i32 = &quot;1&quot;;</code></pre>",
+            "owner": {"display_name": "Jane"},
+            "score": 12,
+            "creation_date": 1680000000,
+            "tags": ["rust", "types"]
+        });
+        let answers = json!({"items": [
+            {"body": "<p>Top</p>", "score": 40, "is_accepted": false, "owner": {"display_name": "A"}},
+            {"body": "<p>Second</p>", "score": 20, "is_accepted": false, "owner": {"display_name": "B"}},
+            {"body": "<p>Third</p>", "score": 10, "is_accepted": false, "owner": {"display_name": "C"}},
+            {"body": "<p>Fourth</p>", "score": 5, "is_accepted": false, "owner": {"display_name": "D"}},
+            {"body": "<p>Accepted</p>", "score": 1, "is_accepted": true, "owner": {"display_name": "E"}}
+        ]});
+        let article = parse_question(&question, &answers).unwrap();
+        assert_eq!("Why doesn't this compile?", article.title);
+        assert_eq!(vec!["rust", "types"], article.tags);
+        assert_eq!(
+            Some("2023-03-28T10:40:00+00:00"),
+            article.published.as_deref()
+        );
+
+        let doc = kuchiki::parse_html().one(article.document());
+        let content = doc.select_first(&format!("#{}", API_ARTICLE_ID)).unwrap();
+        assert!(content.as_node().select_first(".question pre code").is_ok());
+        let headings = content
+            .as_node()
+            .select(".answer h2")
+            .unwrap()
+            .map(|h2| h2.text_contents())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "Accepted answer by E · 1 vote",
+                "Answer by A · 40 votes",
+                "Answer by B · 20 votes",
+                "Answer by C · 10 votes",
+            ],
+            headings
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use kuchiki::{traits::*, NodeRef};
+
+use crate::errors::{ErrorKind, ImgError};
+
+const SVG_NS: &str = "http://www.w3.org/2000/svg";
+/// SVG elements that can run scripts or embed content that readers are not able to display
+const UNSAFE_SVG_ELEMENTS: [&str; 3] = ["script", "foreignobject", "handler"];
+
+/// Removes the scripts, foreign objects and event handlers of the SVGs in a document
+pub fn sanitize_svg_elements(root: &NodeRef) {
+    let svg_roots = root
+        .inclusive_descendants()
+        .elements()
+        .filter(|elem| {
+            let ns: &str = &elem.name.ns;
+            ns == SVG_NS && &elem.name.local == "svg"
+        })
+        .collect::<Vec<_>>();
+    for svg in svg_roots {
+        sanitize_svg_node(svg.as_node());
+    }
+}
+
+fn sanitize_svg_node(svg: &NodeRef) {
+    // The unsafe elements are collected first as detaching nodes while iterating stops the traversal
+    let unsafe_elems = svg
+        .descendants()
+        .elements()
+        .filter(|elem| UNSAFE_SVG_ELEMENTS.contains(&elem.name.local.to_ascii_lowercase().as_str()))
+        .collect::<Vec<_>>();
+    for elem in unsafe_elems {
+        elem.as_node().detach();
+    }
+
+    for elem in svg.inclusive_descendants().elements() {
+        let mut attrs = elem.attributes.borrow_mut();
+        let unsafe_attrs = attrs
+            .map
+            .iter()
+            .filter(|(name, attr)| {
+                let local = name.local.to_ascii_lowercase();
+                let value = attr.value.trim_start().to_ascii_lowercase();
+                local.starts_with("on")
+                    || (local == "href"
+                        && (value.starts_with("javascript:")
+                            || value.starts_with("data:text/html")))
+            })
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        for name in unsafe_attrs {
+            attrs.map.remove(&name);
+        }
+    }
+}
+
+/// Checks whether a downloaded file is an SVG. Some servers send SVGs with a generic
+/// Content-Type so the url and the start of the file are checked as well.
+pub fn is_svg(mime: Option<&str>, url: &str, bytes: &[u8]) -> bool {
+    if mime == Some("image/svg+xml") {
+        return true;
+    }
+    let is_svg_url = url
+        .split(|c| c == '?' || c == '#')
+        .next()
+        .map(|path| path.to_ascii_lowercase().ends_with(".svg"))
+        .unwrap_or(false);
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]).to_ascii_lowercase();
+    is_svg_url && head.contains("<svg")
+}
+
+/// Sanitizes the contents of an SVG file and returns it as a standalone SVG document
+pub fn sanitize_svg_document(svg_bytes: &[u8]) -> Result<Vec<u8>, ImgError> {
+    let svg_str = String::from_utf8_lossy(svg_bytes);
+    let doc = kuchiki::parse_html().one(&*svg_str);
+    let svg = doc
+        .inclusive_descendants()
+        .elements()
+        .find(|elem| {
+            let ns: &str = &elem.name.ns;
+            ns == SVG_NS && &elem.name.local == "svg"
+        })
+        .ok_or_else(|| ErrorKind::SvgError("The file has no <svg> element".to_owned()))?;
+    let svg = svg.as_node();
+    sanitize_svg_node(svg);
+    {
+        let elem = svg.as_element().unwrap();
+        let mut attrs = elem.attributes.borrow_mut();
+        if !attrs.map.keys().any(|name| &name.local == "xmlns") {
+            attrs.insert("xmlns", SVG_NS.to_owned());
+        }
+    }
+    // The HTML serializer writes non-breaking spaces as an entity that is not defined in XML
+    let svg_str = svg.to_string().replace("&nbsp;", "&#160;");
+    Ok(format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}", svg_str).into_bytes())
+}
+
+/// Renders an SVG to a PNG for readers with poor SVG support
+#[cfg(feature = "svg-raster")]
+pub fn rasterize_svg(svg_bytes: &[u8]) -> Result<Vec<u8>, ImgError> {
+    let svg_err = |msg: String| -> ImgError { ErrorKind::SvgError(msg).into() };
+    let tree = usvg::Tree::from_data(svg_bytes, &usvg::Options::default())
+        .map_err(|e| svg_err(e.to_string()))?;
+    let size = tree.svg_node().size.to_screen_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| svg_err("The SVG has no size".to_owned()))?;
+    resvg::render(&tree, usvg::FitTo::Original, pixmap.as_mut())
+        .ok_or_else(|| svg_err("Unable to render the SVG".to_owned()))?;
+    pixmap.encode_png().map_err(|e| svg_err(e.to_string()))
+}
+
+/// Rasterizing is only enabled with the svg-raster feature which is checked when
+/// the CLI arguments are parsed so this is not expected to be reached
+#[cfg(not(feature = "svg-raster"))]
+pub fn rasterize_svg(_svg_bytes: &[u8]) -> Result<Vec<u8>, ImgError> {
+    Err(ErrorKind::SvgError("Paperoni was built without the svg-raster feature".to_owned()).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_svg_elements() {
+        let html_str = r##"
+            <p onclick="alert(1)">Text</p>
+            <svg viewBox="0 0 10 10" onload="alert(1)">
+                <script>alert(1)</script>
+                <foreignObject><p>Embedded HTML</p></foreignObject>
+                <a xlink:href="javascript:alert(1)"><circle r="1" onmouseover="alert(1)"/></a>
+                <use xlink:href="#dot"></use>
+            </svg>
+        "##;
+        let doc = kuchiki::parse_html().one(html_str);
+        sanitize_svg_elements(&doc);
+        let svg = doc.select_first("svg").unwrap();
+        let svg_str = svg.as_node().to_string();
+        assert!(!svg_str.contains("alert"));
+        assert!(!svg_str.contains("Embedded HTML"));
+        assert!(svg_str.contains("<circle r=\"1\">"));
+        assert!(svg_str.contains(r##"xlink:href="#dot""##));
+        // Only the SVGs are sanitized
+        let p = doc.select_first("p").unwrap();
+        assert!(p.attributes.borrow().contains("onclick"));
+    }
+
+    #[test]
+    fn test_is_svg() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg"></svg>"#;
+        assert!(is_svg(Some("image/svg+xml"), "http://example.com/img", b""));
+        assert!(is_svg(None, "http://example.com/logo.SVG?v=2", svg));
+        assert!(!is_svg(None, "http://example.com/logo.svg", b"not an svg"));
+        assert!(!is_svg(
+            Some("image/png"),
+            "http://example.com/logo.png",
+            svg
+        ));
+    }
+
+    #[test]
+    fn test_sanitize_svg_document() {
+        let svg = br#"<?xml version="1.0"?>
+<svg viewBox="0 0 10 10" onload="alert(1)"><script>alert(1)</script><circle r="1"/></svg>"#;
+        let sanitized = sanitize_svg_document(svg).unwrap();
+        let sanitized = std::str::from_utf8(&sanitized).unwrap();
+        assert!(sanitized.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<svg"));
+        assert!(sanitized.contains("xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(!sanitized.contains("alert"));
+        assert!(sanitized.contains("<circle r=\"1\"></circle>"));
+
+        assert!(sanitize_svg_document(b"<p>Not an svg</p>").is_err());
+    }
+}
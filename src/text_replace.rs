@@ -0,0 +1,163 @@
+use std::convert::TryFrom;
+
+use kuchiki::{NodeData, NodeRef};
+use regex::Regex;
+use serde::Deserialize;
+
+/// Elements without content which are kept when the text around them is removed
+const VOID_TAGS: [&str; 6] = ["br", "hr", "img", "picture", "source", "svg"];
+
+/// A regex find and replace applied to the text of articles, such as to strip the
+/// "Advertisement" lines of a site or to fix a recurring encoding artifact
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "ReplaceRuleFields")]
+pub struct ReplaceRule {
+    find: Regex,
+    /// The replacement of the matches which can refer to the groups of the pattern as `$1`
+    with: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReplaceRuleFields {
+    find: String,
+    #[serde(default)]
+    with: String,
+}
+
+impl TryFrom<ReplaceRuleFields> for ReplaceRule {
+    type Error = String;
+
+    fn try_from(fields: ReplaceRuleFields) -> Result<Self, Self::Error> {
+        let find = Regex::new(&fields.find)
+            .map_err(|e| format!("invalid find pattern {:?}: {}", fields.find, e))?;
+        Ok(Self {
+            find,
+            with: fields.with,
+        })
+    }
+}
+
+impl PartialEq for ReplaceRule {
+    fn eq(&self, other: &Self) -> bool {
+        self.find.as_str() == other.find.as_str() && self.with == other.with
+    }
+}
+
+/// Applies the rules in order to each text node of the content. The rules do not match
+/// across elements, so a pattern cannot span a link or emphasis. Elements left without any
+/// text are removed. Returns the number of text nodes that were changed.
+pub fn replace_text(root: &NodeRef, rules: &[ReplaceRule]) -> usize {
+    if rules.is_empty() {
+        return 0;
+    }
+    let mut replaced_count = 0;
+    // The nodes are collected first as detaching them while iterating stops the traversal
+    for text_node in root
+        .inclusive_descendants()
+        .text_nodes()
+        .collect::<Vec<_>>()
+    {
+        let text = text_node.borrow().clone();
+        let replaced = rules.iter().fold(text.clone(), |text, rule| {
+            rule.find
+                .replace_all(&text, rule.with.as_str())
+                .into_owned()
+        });
+        if replaced == text {
+            continue;
+        }
+        replaced_count += 1;
+        if replaced.trim().is_empty() && !text.trim().is_empty() {
+            let node = text_node.as_node().clone();
+            let parent = node.parent();
+            node.detach();
+            if let Some(parent) = parent {
+                remove_if_empty(parent, root);
+            }
+        } else {
+            *text_node.borrow_mut() = replaced;
+        }
+    }
+    replaced_count
+}
+
+/// Removes an element, and then its ancestors, as long as it is left without text or content
+/// such as images
+fn remove_if_empty(node: NodeRef, root: &NodeRef) {
+    let mut node = node;
+    while node != *root {
+        let is_removable = match node.data() {
+            NodeData::Element(elem) => {
+                let tag = elem.name.local.to_lowercase();
+                let is_page = elem.attributes.borrow().get("id") == Some("readability-page-1");
+                !(is_page || tag == "body" || VOID_TAGS.contains(&tag.as_str()))
+            }
+            _ => false,
+        };
+        let is_empty = node.children().all(|child| {
+            child
+                .as_text()
+                .map_or(false, |text| text.borrow().trim().is_empty())
+        });
+        if !(is_removable && is_empty) {
+            break;
+        }
+        let parent = node.parent();
+        node.detach();
+        match parent {
+            Some(parent) => node = parent,
+            None => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use kuchiki::traits::*;
+
+    use super::*;
+
+    fn rule(find: &str, with: &str) -> ReplaceRule {
+        ReplaceRule::try_from(ReplaceRuleFields {
+            find: find.to_owned(),
+            with: with.to_owned(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_replace_text() {
+        let doc = kuchiki::parse_html().one(
+            r#"<div id="readability-page-1"><p>Itâ€™s a pizza</p><div><p><b>Advertisement</b></p></div><p>Advertisement: <a href="/">pasta</a></p><p>Plain</p></div>"#,
+        );
+        let rules = vec![rule("â€™", "’"), rule(r"(?m)^\s*Advertisement\s*$", "")];
+        assert_eq!(2, replace_text(&doc, &rules));
+        let page = doc.select_first("div").unwrap();
+        let mut html = Vec::new();
+        for child in page.as_node().children() {
+            child.serialize(&mut html).unwrap();
+        }
+        assert_eq!(
+            r#"<p>It’s a pizza</p><p>Advertisement: <a href="/">pasta</a></p><p>Plain</p>"#,
+            String::from_utf8(html).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_replace_groups() {
+        let doc = kuchiki::parse_html().one("<p>Posted 2021-06-01 by the chef</p>");
+        replace_text(&doc, &[rule(r"(\d{4})-(\d{2})-(\d{2})", "$3/$2/$1")]);
+        assert_eq!("Posted 01/06/2021 by the chef", doc.text_contents());
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let err = ReplaceRule::try_from(ReplaceRuleFields {
+            find: "(unclosed".to_owned(),
+            with: String::new(),
+        })
+        .unwrap_err();
+        assert!(err.starts_with("invalid find pattern \"(unclosed\""));
+    }
+}
@@ -0,0 +1,288 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use async_std::task;
+use kuchiki::{traits::*, NodeRef};
+use log::debug;
+use serde_json::{json, Value};
+
+use crate::cli::{TranslateOptions, Translator};
+use crate::errors::{ErrorKind, PaperoniError};
+use crate::extractor::Article;
+
+/// The elements whose content is translated. Elements holding other translated elements are
+/// left for their children to be translated instead.
+const TRANSLATED_SELECTOR: &str =
+    "p, h1, h2, h3, h4, h5, h6, li, blockquote, figcaption, caption, dt, dd, td, th";
+
+/// How many paragraphs are sent to a translation service in a request
+const BATCH_SIZE: usize = 50;
+
+/// Translates the paragraphs of an extracted article in place, keeping their markup.
+/// Returns the number of paragraphs translated.
+pub async fn translate_article(
+    client: &surf::Client,
+    article: &Article,
+    options: &TranslateOptions,
+) -> Result<usize, PaperoniError> {
+    let blocks = translatable_blocks(article.node_ref());
+    debug!(
+        "Translating {} paragraphs of {} to {} with {}",
+        blocks.len(),
+        article.url,
+        options.target_lang,
+        options.translator.name()
+    );
+    for batch in blocks.chunks(BATCH_SIZE) {
+        let texts = batch.iter().map(inner_html).collect::<Vec<_>>();
+        let translations = match &options.translator {
+            Translator::LibreTranslate { url, api_key } => {
+                translate_with_libretranslate(
+                    client,
+                    url,
+                    api_key.as_deref(),
+                    &options.target_lang,
+                    texts,
+                )
+                .await?
+            }
+            Translator::DeepL { api_key } => {
+                translate_with_deepl(client, api_key, &options.target_lang, texts).await?
+            }
+            Translator::Command(command) => {
+                let command = command.replace("{lang}", &options.target_lang);
+                task::spawn_blocking(move || translate_with_command(&command, &texts)).await?
+            }
+        };
+        if translations.len() != batch.len() {
+            return Err(ErrorKind::TranslationError(format!(
+                "{} returned {} translations for {} paragraphs",
+                options.translator.name(),
+                translations.len(),
+                batch.len()
+            ))
+            .into());
+        }
+        for (block, translation) in batch.iter().zip(translations) {
+            set_inner_html(block, &translation);
+        }
+    }
+    Ok(blocks.len())
+}
+
+/// Finds the innermost elements with text to translate, leaving out code
+fn translatable_blocks(root: &NodeRef) -> Vec<NodeRef> {
+    root.select(TRANSLATED_SELECTOR)
+        .unwrap()
+        .filter(|block| {
+            let node = block.as_node();
+            node.select_first(TRANSLATED_SELECTOR).is_err()
+                && !node.text_contents().trim().is_empty()
+                && !node.ancestors().any(|ancestor| {
+                    ancestor
+                        .as_element()
+                        .map_or(false, |elem| matches!(&*elem.name.local, "pre" | "code"))
+                })
+        })
+        .map(|block| block.as_node().clone())
+        .collect()
+}
+
+/// The markup inside an element on a single line so that it can be passed to a command
+fn inner_html(node: &NodeRef) -> String {
+    node.children()
+        .map(|child| child.to_string())
+        .collect::<String>()
+        .replace(|c: char| c == '\n' || c == '\r', " ")
+}
+
+fn set_inner_html(node: &NodeRef, html: &str) {
+    let doc = kuchiki::parse_html().one(format!("<body>{}</body>", html));
+    let body = doc.select_first("body").unwrap();
+    for child in node.children().collect::<Vec<_>>() {
+        child.detach();
+    }
+    for child in body.as_node().children().collect::<Vec<_>>() {
+        node.append(child);
+    }
+}
+
+fn translation_error(translator: &str, reason: impl std::fmt::Display) -> PaperoniError {
+    ErrorKind::TranslationError(format!("{} failed: {}", translator, reason)).into()
+}
+
+async fn post_json(
+    client: &surf::Client,
+    req: surf::RequestBuilder,
+    translator: &str,
+) -> Result<Value, PaperoniError> {
+    let mut res = client.send(req.header("User-Agent", "paperoni")).await?;
+    if !res.status().is_success() {
+        let reason = res.body_string().await.unwrap_or_default();
+        return Err(translation_error(
+            translator,
+            format!("responded with {} {}", res.status(), reason.trim()),
+        ));
+    }
+    Ok(res.body_json::<Value>().await?)
+}
+
+async fn translate_with_libretranslate(
+    client: &surf::Client,
+    url: &str,
+    api_key: Option<&str>,
+    target_lang: &str,
+    texts: Vec<String>,
+) -> Result<Vec<String>, PaperoniError> {
+    let mut body = json!({
+        "q": texts,
+        "source": "auto",
+        "target": target_lang,
+        "format": "html",
+    });
+    if let Some(api_key) = api_key {
+        body["api_key"] = json!(api_key);
+    }
+    let req = surf::post(format!("{}/translate", url))
+        .body(body)
+        .content_type("application/json");
+    let res = post_json(client, req, "LibreTranslate").await?;
+    parse_translations(&res["translatedText"], None)
+        .ok_or_else(|| translation_error("LibreTranslate", "unexpected response"))
+}
+
+async fn translate_with_deepl(
+    client: &surf::Client,
+    api_key: &str,
+    target_lang: &str,
+    texts: Vec<String>,
+) -> Result<Vec<String>, PaperoniError> {
+    // Keys of the free plan end with :fx and can only be used with the free API
+    let url = if api_key.ends_with(":fx") {
+        "https://api-free.deepl.com/v2/translate"
+    } else {
+        "https://api.deepl.com/v2/translate"
+    };
+    let req = surf::post(url)
+        .header("Authorization", format!("DeepL-Auth-Key {}", api_key))
+        .body(json!({
+            "text": texts,
+            "target_lang": target_lang.to_uppercase(),
+            "tag_handling": "html",
+        }))
+        .content_type("application/json");
+    let res = post_json(client, req, "DeepL").await?;
+    parse_translations(&res["translations"], Some("text"))
+        .ok_or_else(|| translation_error("DeepL", "unexpected response"))
+}
+
+/// Reads a list of translations from a response, either as strings or as objects with the
+/// translation under `key`
+fn parse_translations(translations: &Value, key: Option<&str>) -> Option<Vec<String>> {
+    translations
+        .as_array()?
+        .iter()
+        .map(|translation| match key {
+            Some(key) => translation[key].as_str().map(str::to_owned),
+            None => translation.as_str().map(str::to_owned),
+        })
+        .collect()
+}
+
+fn translate_with_command(command: &str, texts: &[String]) -> Result<Vec<String>, PaperoniError> {
+    let (shell, shell_arg) = if cfg!(target_os = "windows") {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut child = Command::new(shell)
+        .args(&[shell_arg, command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| translation_error(command, e))?;
+    // The input is written from another thread so that a command writing its output while
+    // it reads does not block on a full pipe
+    let mut stdin = child.stdin.take().unwrap();
+    let input = texts
+        .iter()
+        .map(|text| format!("{}\n", text))
+        .collect::<String>();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child
+        .wait_with_output()
+        .map_err(|e| translation_error(command, e))?;
+    if let Ok(Err(e)) = writer.join() {
+        return Err(translation_error(command, e));
+    }
+    if !output.status.success() {
+        return Err(translation_error(
+            command,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_owned)
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_translatable_blocks() {
+        let doc = kuchiki::parse_html().one(
+            r#"<div>
+                <h2>Heading</h2>
+                <p>A <a href="/x">link</a> in <em>text</em></p>
+                <blockquote><p>Quoted</p></blockquote>
+                <ul><li>Item</li><li> </li></ul>
+                <pre><code>let p = 1;</code></pre>
+            </div>"#,
+        );
+        let blocks = translatable_blocks(&doc)
+            .iter()
+            .map(inner_html)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "Heading",
+                r#"A <a href="/x">link</a> in <em>text</em>"#,
+                "Quoted",
+                "Item"
+            ],
+            blocks
+        );
+    }
+
+    #[test]
+    fn test_set_inner_html() {
+        let doc = kuchiki::parse_html().one(r#"<p id="x">A <a href="/x">link</a></p>"#);
+        let p = doc.select_first("p").unwrap();
+        set_inner_html(p.as_node(), r#"Ein <a href="/x">Link</a>"#);
+        assert_eq!(
+            r#"<p id="x">Ein <a href="/x">Link</a></p>"#,
+            p.as_node().to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_translations() {
+        assert_eq!(
+            Some(vec!["Hallo".to_owned(), "Welt".to_owned()]),
+            parse_translations(&json!(["Hallo", "Welt"]), None)
+        );
+        assert_eq!(
+            Some(vec!["Hallo".to_owned()]),
+            parse_translations(
+                &json!([{"detected_source_language": "EN", "text": "Hallo"}]),
+                Some("text")
+            )
+        );
+        assert_eq!(None, parse_translations(&json!("Hallo"), None));
+    }
+}
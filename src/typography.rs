@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use html5ever::{LocalName, Namespace, QualName};
+use kuchiki::NodeRef;
+
+use crate::cli::AppConfig;
+
+/// The font family of the font embedded in epubs with --cjk-font
+const CJK_FONT_FAMILY: &str = "Paperoni CJK";
+
+/// Returns the stylesheet for the typographic and branding options that are enabled
+pub fn typography_css(app_config: &AppConfig) -> String {
+    // The line breaking rules of Chinese, Japanese and Korean only apply to articles in them
+    let mut css_str = include_str!("./assets/cjk.min.css").to_owned();
+    if let Some(font_path) = &app_config.cjk_font {
+        // The embedded font comes after the font of the body so that it is only used for the
+        // characters the body font does not have
+        css_str.push_str(&format!(
+            "@font-face{{font-family:\"{family}\";src:url(\"{file}\")}}[lang|=zh],[lang|=ja],[lang|=ko]{{font-family:Georgia,\"{family}\",serif}}",
+            family = CJK_FONT_FAMILY,
+            file = cjk_font_file_name(font_path)
+        ));
+    }
+    if app_config.drop_caps {
+        css_str.push_str(include_str!("./assets/drop-caps.min.css"));
+    }
+    if app_config.chapter_breaks {
+        css_str.push_str(include_str!("./assets/chapter-breaks.min.css"));
+    }
+    if app_config.has_site_logo {
+        css_str.push_str(include_str!("./assets/site-logo.min.css"));
+    }
+    css_str
+}
+
+/// Applies the DOM changes needed by the typographic options that are enabled. The chapter
+/// number is only passed for articles that are part of a merged output.
+pub fn apply_typography(
+    article_root: &NodeRef,
+    app_config: &AppConfig,
+    chapter_number: Option<usize>,
+) {
+    if app_config.drop_caps {
+        add_drop_cap(article_root);
+    }
+    if let (true, Some(chapter_number)) = (app_config.chapter_breaks, chapter_number) {
+        add_chapter_break(article_root, chapter_number);
+    }
+}
+
+/// Marks the first paragraph containing text so that its first letter is styled as a drop cap
+fn add_drop_cap(article_root: &NodeRef) {
+    if let Some(paragraph) = article_root
+        .select("p")
+        .unwrap()
+        .find(|paragraph| !paragraph.text_contents().trim().is_empty())
+    {
+        add_class(paragraph.as_node(), "drop-cap");
+    }
+}
+
+/// Marks the article as a chapter that starts on a new page and adds its number before it
+fn add_chapter_break(article_root: &NodeRef, chapter_number: usize) {
+    if let Ok(page) = article_root.select_first("div[id=\"readability-page-1\"]") {
+        let page = page.as_node();
+        add_class(page, "chapter");
+
+        let chapter_elem = NodeRef::new_element(
+            QualName::new(
+                None,
+                Namespace::from("http://www.w3.org/1999/xhtml"),
+                LocalName::from("p"),
+            ),
+            BTreeMap::new(),
+        );
+        add_class(&chapter_elem, "chapter-number");
+        chapter_elem.append(NodeRef::new_text(format!("Chapter {}", chapter_number)));
+        page.prepend(chapter_elem);
+    }
+}
+
+/// The path of the --cjk-font font in epubs
+pub fn cjk_font_file_name(font_path: &Path) -> String {
+    let extension = font_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    format!("fonts/cjk.{}", extension)
+}
+
+/// The MIME type of a font file from its extension. Returns None for files that are not fonts
+/// epub readers support.
+pub fn cjk_font_mime(font_path: &Path) -> Option<&'static str> {
+    let extension = font_path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "ttf" => Some("font/ttf"),
+        "otf" => Some("font/otf"),
+        "woff" => Some("font/woff"),
+        "woff2" => Some("font/woff2"),
+        _ => None,
+    }
+}
+
+fn add_class(node_ref: &NodeRef, class: &str) {
+    if let Some(elem) = node_ref.as_element() {
+        let mut attrs = elem.attributes.borrow_mut();
+        match attrs.get_mut("class") {
+            Some(class_list) => {
+                class_list.push(' ');
+                class_list.push_str(class);
+            }
+            None => {
+                attrs.insert("class", class.to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use kuchiki::traits::*;
+
+    const ARTICLE_HTML: &str = r#"
+        <html>
+            <body>
+                <div>
+                    <div id="readability-page-1" class="page">
+                        <p>  </p>
+                        <p>Once upon a time</p>
+                        <p>The end</p>
+                    </div>
+                </div>
+            </body>
+        </html>
+    "#;
+
+    #[test]
+    fn test_cjk_font_file() {
+        let font_path = Path::new("/usr/share/fonts/NotoSerifCJK.OTF");
+        assert_eq!("fonts/cjk.otf", cjk_font_file_name(font_path));
+        assert_eq!(Some("font/otf"), cjk_font_mime(font_path));
+        assert_eq!(None, cjk_font_mime(Path::new("font.ttc")));
+        assert_eq!(None, cjk_font_mime(Path::new("font")));
+    }
+
+    #[test]
+    fn test_add_drop_cap() {
+        let doc = kuchiki::parse_html().one(ARTICLE_HTML);
+        add_drop_cap(&doc);
+        let drop_cap = doc.select_first("p.drop-cap").unwrap();
+        assert_eq!("Once upon a time", drop_cap.text_contents());
+        assert_eq!(1, doc.select("p.drop-cap").unwrap().count());
+    }
+
+    #[test]
+    fn test_add_chapter_break() {
+        let doc = kuchiki::parse_html().one(ARTICLE_HTML);
+        add_chapter_break(&doc, 3);
+        let page = doc.select_first("div.chapter").unwrap();
+        assert_eq!(Some("page chapter"), page.attributes.borrow().get("class"));
+        let chapter_number = page.as_node().first_child().unwrap();
+        assert_eq!("Chapter 3", chapter_number.text_contents());
+        assert_eq!(
+            Some("chapter-number"),
+            chapter_number
+                .as_element()
+                .unwrap()
+                .attributes
+                .borrow()
+                .get("class")
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use std::{fs, path::Path, sync::Arc};
+
+use log::debug;
+use regex::Regex;
+
+/// A line of a url rewrite rules file replacing the urls matching its pattern
+#[derive(Debug)]
+struct RewriteRule {
+    pattern: Regex,
+    /// The replacement of the match which can refer to the groups of the pattern as `$1`
+    replacement: String,
+}
+
+/// Rewrites the urls of articles before they are fetched with the rules of a file, such as to
+/// fetch the canonical page of mobile or AMP urls or to route a site through a mirror
+#[derive(Clone, Debug, Default)]
+pub struct UrlRewriter {
+    rules: Arc<Vec<RewriteRule>>,
+}
+
+impl UrlRewriter {
+    /// Reads a rules file with a regex and its replacement separated by whitespace on each
+    /// line. Empty lines and lines starting with `#` are skipped.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::parse(&content)
+    }
+
+    fn parse(content: &str) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for (line_idx, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() != 2 {
+                return Err(format!(
+                    "line {} must have a pattern and its replacement separated by whitespace",
+                    line_idx + 1
+                ));
+            }
+            let pattern = Regex::new(fields[0])
+                .map_err(|e| format!("line {} has an invalid pattern: {}", line_idx + 1, e))?;
+            rules.push(RewriteRule {
+                pattern,
+                replacement: fields[1].to_owned(),
+            });
+        }
+        debug!("Loaded {} url rewrite rules", rules.len());
+        Ok(Self {
+            rules: Arc::new(rules),
+        })
+    }
+
+    /// Rewrites the url with the first rule matching it. Returns `None` when no rule matches.
+    pub fn rewrite(&self, url: &str) -> Option<String> {
+        self.rules
+            .iter()
+            .find(|rule| rule.pattern.is_match(url))
+            .map(|rule| {
+                rule.pattern
+                    .replace(url, rule.replacement.as_str())
+                    .into_owned()
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RULES: &str = r"# Canonical pages of mobile and AMP urls
+^https://m\.(.+)$ https://$1
+^(https://[^/]+/.+)/amp/?$ $1
+
+^https?://(www\.)?reddit\.com/ https://old.reddit.com/
+";
+
+    #[test]
+    fn test_rewrite() {
+        let rewriter = UrlRewriter::parse(RULES).unwrap();
+        assert_eq!(3, rewriter.rules.len());
+        assert_eq!(
+            Some("https://en.wikipedia.org/wiki/Pepperoni".to_owned()),
+            rewriter.rewrite("https://m.en.wikipedia.org/wiki/Pepperoni")
+        );
+        assert_eq!(
+            Some("https://example.com/2021/06/pizza".to_owned()),
+            rewriter.rewrite("https://example.com/2021/06/pizza/amp/")
+        );
+        assert_eq!(
+            Some("https://old.reddit.com/r/rust/comments/abc".to_owned()),
+            rewriter.rewrite("https://www.reddit.com/r/rust/comments/abc")
+        );
+        assert_eq!(None, rewriter.rewrite("https://example.com/amp-pizza"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            "line 2 must have a pattern and its replacement separated by whitespace",
+            UrlRewriter::parse("# rules\n^https://m\\.").unwrap_err()
+        );
+        assert!(UrlRewriter::parse("(unclosed https://example.com")
+            .unwrap_err()
+            .starts_with("line 1 has an invalid pattern"));
+    }
+}
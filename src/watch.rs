@@ -0,0 +1,420 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use chrono::Local;
+use itertools::Itertools;
+use log::{debug, info, warn};
+use url::Url;
+
+use crate::cli::{parse_url_file_line, AppConfig, ArticleOptions, WatchOptions};
+use crate::errors::{ErrorKind, PaperoniError};
+
+/// The folder inside the watched directory that processed files are moved to
+const DONE_DIR: &str = "done";
+/// How long a file must go unmodified before it is read so that files still being written
+/// by a sync app are not read halfway
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Checks the directory for `.txt` and `.url` files of links, passes the links of each file
+/// to `export` and moves the file to the done folder. The links of `.queue` files that are
+/// due are passed a batch at a time. The directory is checked every interval until paperoni
+/// is stopped unless only the existing files are processed.
+pub fn watch_dir(
+    app_config: &AppConfig,
+    options: &WatchOptions,
+    mut export: impl FnMut(AppConfig),
+) -> Result<(), PaperoniError> {
+    let dir = Path::new(&options.dir);
+    if !dir.is_dir() {
+        return Err(ErrorKind::IOError(format!("{} is not a directory", options.dir)).into());
+    }
+    let done_dir = dir.join(DONE_DIR);
+    fs::create_dir_all(&done_dir)?;
+
+    if !options.is_once {
+        println!("Watching {} for files of links", dir.display());
+    }
+    loop {
+        let (queue_paths, drop_paths): (Vec<_>, Vec<_>) = find_drop_files(dir, !options.is_once)?
+            .into_iter()
+            .partition(|path| is_queue_file(path));
+        for path in drop_paths {
+            let links = read_links(&path)?;
+            if links.is_empty() {
+                println!("No links found in {}", path.display());
+            } else {
+                println!(
+                    "Downloading {} link(s) from {}",
+                    links.len(),
+                    path.display()
+                );
+                let mut file_config = app_config.clone();
+                file_config.urls = links;
+                export(file_config);
+            }
+            let done_path = done_file_path(&done_dir, &path);
+            info!("Moving {:?} to {:?}", path, done_path);
+            fs::rename(&path, &done_path)?;
+        }
+        if !queue_paths.is_empty() {
+            process_queues(
+                &queue_paths,
+                app_config,
+                options.queue_batch,
+                &mut export,
+                &done_dir,
+            )?;
+        }
+        if options.is_once {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_secs(options.interval));
+    }
+}
+
+/// Returns the files of links in the directory in the order of their names. Hidden files
+/// such as the temporary files of sync apps are ignored.
+fn find_drop_files(dir: &Path, is_waiting_to_settle: bool) -> Result<Vec<PathBuf>, PaperoniError> {
+    let now = SystemTime::now();
+    let mut paths = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let is_settled = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .map(|modified| now.duration_since(modified).unwrap_or_default() >= SETTLE_TIME)
+                .unwrap_or(false);
+            if is_waiting_to_settle && !is_settled {
+                debug!("Waiting for {:?} to finish being written", entry.path());
+                return false;
+            }
+            true
+        })
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_drop_file(path))
+        .collect::<Vec<_>>();
+    paths.sort();
+    Ok(paths)
+}
+
+fn is_drop_file(path: &Path) -> bool {
+    let is_hidden = path
+        .file_name()
+        .map_or(true, |name| name.to_string_lossy().starts_with('.'));
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    !is_hidden
+        && matches!(
+            extension.as_deref(),
+            Some("txt") | Some("url") | Some("queue")
+        )
+}
+
+fn is_queue_file(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("queue"))
+}
+
+/// A link of a queue file with its options
+struct QueueEntry {
+    /// The index of the file of the link among the queue files
+    file_idx: usize,
+    line_idx: usize,
+    url: String,
+    options: ArticleOptions,
+}
+
+/// Passes the links of the queue files that are due to `export`, highest priority first and
+/// then in the order of the files, up to `batch` links. The links passed are removed from
+/// their files and the files without links left are moved to the done folder.
+fn process_queues(
+    paths: &[PathBuf],
+    app_config: &AppConfig,
+    batch: usize,
+    export: &mut impl FnMut(AppConfig),
+    done_dir: &Path,
+) -> Result<(), PaperoniError> {
+    let mut files_lines = Vec::new();
+    let mut entries = Vec::new();
+    for (file_idx, path) in paths.iter().enumerate() {
+        let content = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+        let lines = content.lines().map(ToOwned::to_owned).collect::<Vec<_>>();
+        for (line_idx, line) in lines.iter().enumerate() {
+            match parse_url_file_line(line) {
+                Ok(Some((url, options))) => entries.push(QueueEntry {
+                    file_idx,
+                    line_idx,
+                    url,
+                    options,
+                }),
+                Ok(None) => (),
+                // The line is kept in the file so that it can be fixed
+                Err(e) => warn!("Skipping line {} of {:?}: {}", line_idx + 1, path, e),
+            }
+        }
+        files_lines.push(lines);
+    }
+
+    let now = Local::now();
+    let mut due_entries = entries
+        .iter()
+        .filter(|entry| entry.options.not_before.map_or(true, |time| time <= now))
+        .collect::<Vec<_>>();
+    // The sort is stable so links of the same priority keep the order of their files
+    due_entries.sort_by_key(|entry| Reverse(entry.options.priority));
+    due_entries.truncate(batch);
+    if !due_entries.is_empty() {
+        println!(
+            "Downloading {} link(s) of {} queued in {} file(s)",
+            due_entries.len(),
+            entries.len(),
+            paths.len()
+        );
+        let mut queue_config = app_config.clone();
+        queue_config.urls = due_entries
+            .iter()
+            .map(|entry| entry.url.clone())
+            .unique()
+            .collect();
+        queue_config.article_options.extend(
+            due_entries
+                .iter()
+                .map(|entry| (entry.url.clone(), entry.options.clone())),
+        );
+        export(queue_config);
+    }
+
+    let taken_lines = due_entries
+        .iter()
+        .map(|entry| (entry.file_idx, entry.line_idx))
+        .collect::<HashSet<_>>();
+    for (file_idx, (path, lines)) in paths.iter().zip(files_lines).enumerate() {
+        let has_taken_lines = taken_lines.iter().any(|(idx, _)| *idx == file_idx);
+        if has_taken_lines {
+            let remaining_lines = lines
+                .iter()
+                .enumerate()
+                .filter(|(line_idx, _)| !taken_lines.contains(&(file_idx, *line_idx)))
+                .map(|(_, line)| format!("{}\n", line))
+                .collect::<String>();
+            fs::write(path, remaining_lines)?;
+        }
+        let has_links_left = entries.iter().any(|entry| {
+            entry.file_idx == file_idx && !taken_lines.contains(&(file_idx, entry.line_idx))
+        });
+        if !has_links_left {
+            let done_path = done_file_path(done_dir, path);
+            info!("Moving the finished queue {:?} to {:?}", path, done_path);
+            fs::rename(path, &done_path)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_links(path: &Path) -> Result<Vec<String>, PaperoniError> {
+    let content = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+    let is_shortcut = path
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("url"));
+    Ok(if is_shortcut {
+        parse_shortcut_links(&content)
+    } else {
+        parse_text_links(&content)
+    })
+}
+
+/// Reads the link of an internet shortcut which is saved as `URL=...` in its
+/// `[InternetShortcut]` section
+fn parse_shortcut_links(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut key_value = line.splitn(2, '=');
+            let key = key_value.next()?.trim();
+            let value = key_value.next()?.trim();
+            if key.eq_ignore_ascii_case("url") {
+                as_http_url(value)
+            } else {
+                None
+            }
+        })
+        .unique()
+        .collect()
+}
+
+/// Finds the http links in a text. The text may be a list of links or a note shared from
+/// another app with links in the middle of it.
+fn parse_text_links(content: &str) -> Vec<String> {
+    content
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| "<>()[]\"',;".contains(c)))
+        .filter_map(as_http_url)
+        .unique()
+        .collect()
+}
+
+fn as_http_url(link: &str) -> Option<String> {
+    Url::parse(link)
+        .ok()
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .map(String::from)
+}
+
+/// The path a processed file is moved to. A number is added to the name when a file with
+/// the same name was already processed.
+fn done_file_path(done_dir: &Path, path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default();
+    let mut done_path = done_dir.join(file_name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    let mut count = 1;
+    while done_path.exists() {
+        done_path = done_dir.join(format!("{}_{}.{}", stem, count, extension));
+        count += 1;
+    }
+    done_path
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_parse_links() {
+        assert_eq!(
+            vec![
+                "https://example.com/a".to_owned(),
+                "http://example.org/b?c=d".to_owned()
+            ],
+            parse_text_links(
+                "https://example.com/a\nCheck this out (http://example.org/b?c=d), https://example.com/a\nftp://example.com/file not-a-link"
+            )
+        );
+        assert_eq!(
+            vec!["https://example.com/post".to_owned()],
+            parse_shortcut_links(
+                "[InternetShortcut]\r\nURL=https://example.com/post\r\nIconIndex=0\r\n"
+            )
+        );
+        assert!(
+            parse_shortcut_links("[InternetShortcut]\nIconFile=https://example.com/icon.ico")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_is_drop_file() {
+        assert!(is_drop_file(Path::new("inbox/links.txt")));
+        assert!(is_drop_file(Path::new("inbox/Article.URL")));
+        assert!(!is_drop_file(Path::new("inbox/.syncthing.links.txt.tmp")));
+        assert!(!is_drop_file(Path::new("inbox/.links.txt")));
+        assert!(!is_drop_file(Path::new("inbox/article.html")));
+        assert!(is_drop_file(Path::new("inbox/backlog.queue")));
+        assert!(is_queue_file(Path::new("inbox/backlog.QUEUE")));
+    }
+
+    #[test]
+    fn test_process_queues() {
+        let dir = std::env::temp_dir().join(format!("paperoni-queue-test-{}", std::process::id()));
+        let done_dir = dir.join(DONE_DIR);
+        fs::create_dir_all(&done_dir).unwrap();
+        let paths = vec![dir.join("a.queue"), dir.join("b.queue")];
+        fs::write(
+            &paths[0],
+            "https://example.com/low\n\
+            https://example.com/high | priority=5 title=High\n\
+            https://example.com/later | not-before=2999-01-01\n",
+        )
+        .unwrap();
+        fs::write(&paths[1], "https://example.com/mid | priority=1\n").unwrap();
+
+        let app_config = AppConfig::try_from(
+            clap::App::from_yaml(clap::load_yaml!("cli_config.yml"))
+                .get_matches_from(vec!["paperoni", "watch", "dir"]),
+        )
+        .unwrap();
+        let mut exported_configs = Vec::new();
+        let mut export = |config| exported_configs.push(config);
+        process_queues(&paths, &app_config, 2, &mut export, &done_dir).unwrap();
+        process_queues(&paths[..1], &app_config, 2, &mut export, &done_dir).unwrap();
+
+        let exported_urls = exported_configs
+            .iter()
+            .map(|config: &AppConfig| config.urls.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                vec![
+                    "https://example.com/high".to_owned(),
+                    "https://example.com/mid".to_owned()
+                ],
+                vec!["https://example.com/low".to_owned()]
+            ],
+            exported_urls
+        );
+        assert_eq!(
+            Some("High"),
+            exported_configs[0].article_options["https://example.com/high"]
+                .title
+                .as_deref()
+        );
+        // The link that is not due yet is kept while the finished queue is moved
+        assert_eq!(
+            "https://example.com/later | not-before=2999-01-01\n",
+            fs::read_to_string(&paths[0]).unwrap()
+        );
+        assert!(!paths[1].exists());
+        assert!(done_dir.join("b.queue").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_watch_dir_once() {
+        let dir = std::env::temp_dir().join(format!("paperoni-watch-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join(DONE_DIR)).unwrap();
+        fs::write(
+            dir.join("links.txt"),
+            "https://example.com/a\nhttps://example.com/b",
+        )
+        .unwrap();
+        fs::write(dir.join("empty.txt"), "nothing here").unwrap();
+        fs::write(dir.join(DONE_DIR).join("links.txt"), "").unwrap();
+        fs::write(dir.join("notes.md"), "https://example.com/c").unwrap();
+
+        let app_config = AppConfig::try_from(
+            clap::App::from_yaml(clap::load_yaml!("cli_config.yml"))
+                .get_matches_from(vec!["paperoni", "watch", "dir"]),
+        )
+        .unwrap();
+        let options = WatchOptions {
+            dir: dir.to_string_lossy().into_owned(),
+            interval: 1,
+            is_once: true,
+            queue_batch: 10,
+        };
+        let mut exported_urls = Vec::new();
+        watch_dir(&app_config, &options, |config| {
+            exported_urls.push(config.urls)
+        })
+        .unwrap();
+
+        assert_eq!(
+            vec![vec![
+                "https://example.com/a".to_owned(),
+                "https://example.com/b".to_owned()
+            ]],
+            exported_urls
+        );
+        assert!(dir.join(DONE_DIR).join("empty.txt").exists());
+        assert!(dir.join(DONE_DIR).join("links_1.txt").exists());
+        assert!(!dir.join("links.txt").exists());
+        assert!(dir.join("notes.md").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,350 @@
+use kuchiki::{traits::*, NodeRef};
+use log::{debug, warn};
+use serde_json::Value;
+use url::Url;
+
+use crate::blog_api::ApiArticle;
+use crate::errors::{ErrorKind, PaperoniError};
+use crate::extractor::new_element;
+use crate::http::fetch_html;
+
+/// How many seconds of a transcript are grouped under each timestamp
+const SECTION_SECONDS: u64 = 60;
+
+const PLAYER_RESPONSE_VAR: &str = "ytInitialPlayerResponse";
+
+/// A line of the captions of a video
+#[derive(Debug, PartialEq)]
+struct Caption {
+    start_secs: u64,
+    text: String,
+}
+
+/// Returns the id of the video of a YouTube url e.g `https://www.youtube.com/watch?v={id}`,
+/// `https://youtu.be/{id}` or `https://www.youtube.com/shorts/{id}`
+pub fn video_id(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    let host = host
+        .trim_start_matches("www.")
+        .trim_start_matches("m.")
+        .trim_start_matches("music.");
+    let segments = url
+        .path_segments()?
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    let id = match (host, segments.as_slice()) {
+        ("youtu.be", [id]) => (*id).to_owned(),
+        ("youtube.com", ["watch"]) => url
+            .query_pairs()
+            .find(|(key, _)| key == "v")
+            .map(|(_, id)| id.into_owned())?,
+        ("youtube.com", ["shorts", id])
+        | ("youtube.com", ["live", id])
+        | ("youtube.com", ["embed", id]) => (*id).to_owned(),
+        _ => return None,
+    };
+    if !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Some(id)
+    } else {
+        None
+    }
+}
+
+/// Fetches a video as an HTML document of its transcript for extraction with the
+/// `API_ARTICLE_ID` selector. The transcript is read from the captions of the video, with
+/// captions written by its uploader preferred over the generated ones, and the thumbnail of
+/// the video is used as the image of the article.
+pub async fn fetch_video(
+    client: &surf::Client,
+    id: &str,
+    max_redirects: u8,
+) -> Result<(String, String), PaperoniError> {
+    let watch_url = format!("https://www.youtube.com/watch?v={}", id);
+    let (_, html) = fetch_html(client, &format!("{}&hl=en", watch_url), max_redirects).await?;
+    let player = player_response(&html).ok_or_else(|| {
+        PaperoniError::from(ErrorKind::HTTPError(format!(
+            "Unable to find the details of the video {}",
+            id
+        )))
+    })?;
+    let captions = match caption_track_url(&player) {
+        Some(track_url) => {
+            debug!("Fetching the captions of {} from {}", id, track_url);
+            match fetch_captions(client, &track_url).await {
+                Ok(captions) => captions,
+                Err(e) => {
+                    warn!("Unable to fetch the captions of {}: {}", watch_url, e);
+                    Vec::new()
+                }
+            }
+        }
+        None => {
+            warn!(
+                "{} has no captions so only its description is exported",
+                watch_url
+            );
+            Vec::new()
+        }
+    };
+    let article = video_article(&player, id, &captions).ok_or_else(|| {
+        PaperoniError::from(ErrorKind::HTTPError(format!(
+            "Unable to find the details of the video {}",
+            id
+        )))
+    })?;
+    Ok((watch_url, article.document()))
+}
+
+/// Reads the player response that the watch page assigns to a variable of its scripts
+fn player_response(html: &str) -> Option<Value> {
+    let start = html.find(PLAYER_RESPONSE_VAR)?;
+    let json_start = start + html[start..].find('{')?;
+    // Only the object is parsed, leaving the rest of the script
+    serde_json::Deserializer::from_str(&html[json_start..])
+        .into_iter::<Value>()
+        .next()?
+        .ok()
+}
+
+fn caption_track_url(player: &Value) -> Option<String> {
+    let tracks =
+        player["captions"]["playerCaptionsTracklistRenderer"]["captionTracks"].as_array()?;
+    let track = tracks
+        .iter()
+        .find(|track| track["kind"].as_str() != Some("asr"))
+        .or_else(|| tracks.first())?;
+    track["baseUrl"]
+        .as_str()
+        .map(|base_url| format!("{}&fmt=json3", base_url))
+}
+
+async fn fetch_captions(
+    client: &surf::Client,
+    track_url: &str,
+) -> Result<Vec<Caption>, PaperoniError> {
+    let mut res = client.get(track_url).await?;
+    if !res.status().is_success() {
+        let msg = format!("YouTube responded with {}", res.status());
+        return Err(ErrorKind::HTTPError(msg).into());
+    }
+    let body = res.body_json::<Value>().await?;
+    Ok(parse_captions(&body))
+}
+
+fn parse_captions(body: &Value) -> Vec<Caption> {
+    body["events"]
+        .as_array()
+        .map(|events| {
+            events
+                .iter()
+                .filter_map(|event| {
+                    let text = event["segs"]
+                        .as_array()?
+                        .iter()
+                        .filter_map(|seg| seg["utf8"].as_str())
+                        .collect::<String>();
+                    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                    if text.is_empty() {
+                        return None;
+                    }
+                    Some(Caption {
+                        start_secs: event["tStartMs"].as_u64().unwrap_or_default() / 1000,
+                        text,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Formats a position in a video as `m:ss` or `h:mm:ss`
+fn timestamp(secs: u64) -> String {
+    let (hours, mins, secs) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, mins, secs)
+    } else {
+        format!("{}:{:02}", mins, secs)
+    }
+}
+
+/// Groups the captions into sections headed by the timestamp where they start in the video
+fn transcript_sections(captions: &[Caption], id: &str) -> Vec<NodeRef> {
+    let mut sections: Vec<(u64, Vec<&str>)> = Vec::new();
+    for caption in captions {
+        match sections.last_mut() {
+            Some((start, lines)) if caption.start_secs < *start + SECTION_SECONDS => {
+                lines.push(caption.text.as_str())
+            }
+            _ => sections.push((caption.start_secs, vec![caption.text.as_str()])),
+        }
+    }
+    sections
+        .into_iter()
+        .flat_map(|(start, lines)| {
+            let heading = new_element("h2", &[]);
+            let link = new_element(
+                "a",
+                &[(
+                    "href",
+                    format!("https://www.youtube.com/watch?v={}&t={}s", id, start).as_str(),
+                )],
+            );
+            link.append(NodeRef::new_text(timestamp(start)));
+            heading.append(link);
+            let paragraph = new_element("p", &[]);
+            paragraph.append(NodeRef::new_text(lines.join(" ")));
+            vec![heading, paragraph]
+        })
+        .collect()
+}
+
+fn video_article(player: &Value, id: &str, captions: &[Caption]) -> Option<ApiArticle> {
+    let details = &player["videoDetails"];
+    let microformat = &player["microformat"]["playerMicroformatRenderer"];
+    let title = details["title"].as_str()?.to_owned();
+    let description = details["shortDescription"]
+        .as_str()
+        .map(str::trim)
+        .filter(|description| !description.is_empty());
+    // The thumbnails are listed from the smallest to the largest
+    let thumbnail = details["thumbnail"]["thumbnails"]
+        .as_array()
+        .and_then(|thumbnails| thumbnails.last())
+        .and_then(|thumbnail| thumbnail["url"].as_str())
+        .map(str::to_owned);
+
+    let content = new_element("div", &[]);
+    if let Some(thumbnail) = &thumbnail {
+        content.append(new_element(
+            "img",
+            &[("src", thumbnail.as_str()), ("alt", title.as_str())],
+        ));
+    }
+    if let Some(description) = description {
+        for line in description.lines().filter(|line| !line.trim().is_empty()) {
+            let paragraph = new_element("p", &[]);
+            paragraph.append(NodeRef::new_text(line));
+            content.append(paragraph);
+        }
+    }
+    for node in transcript_sections(captions, id) {
+        content.append(node);
+    }
+
+    Some(ApiArticle {
+        title,
+        html: content.to_string(),
+        author: details["author"].as_str().map(str::to_owned),
+        published: microformat["publishDate"].as_str().map(str::to_owned),
+        description: description
+            .and_then(|description| description.lines().next())
+            .map(str::to_owned),
+        cover_image: thumbnail,
+        tags: details["keywords"]
+            .as_array()
+            .map(|keywords| {
+                keywords
+                    .iter()
+                    .filter_map(|keyword| keyword.as_str().map(str::to_owned))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blog_api::API_ARTICLE_ID;
+    use serde_json::json;
+
+    #[test]
+    fn test_video_id() {
+        assert_eq!(
+            Some("dQw4w9WgXcQ".to_owned()),
+            video_id("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42s")
+        );
+        assert_eq!(
+            Some("dQw4w9WgXcQ".to_owned()),
+            video_id("https://youtu.be/dQw4w9WgXcQ")
+        );
+        assert_eq!(
+            Some("dQw4w9WgXcQ".to_owned()),
+            video_id("https://m.youtube.com/shorts/dQw4w9WgXcQ")
+        );
+        assert_eq!(None, video_id("https://www.youtube.com/@channel/videos"));
+        assert_eq!(None, video_id("https://example.com/watch?v=dQw4w9WgXcQ"));
+    }
+
+    #[test]
+    fn test_player_response() {
+        let html = r#"<script>var ytInitialPlayerResponse = {"videoDetails":{"title":"A talk; with {braces}"}};var meta = {};</script>"#;
+        let player = player_response(html).unwrap();
+        assert_eq!(
+            Some("A talk; with {braces}"),
+            player["videoDetails"]["title"].as_str()
+        );
+        assert_eq!(None, player_response("<html></html>"));
+    }
+
+    #[test]
+    fn test_video_article() {
+        let player = json!({
+            "videoDetails": {
+                "title": "A talk",
+                "author": "Jane Doe",
+                "shortDescription": "About the talk\n\nLinks below",
+                "keywords": ["rust"],
+                "thumbnail": {"thumbnails": [
+                    {"url": "https://i.ytimg.com/vi/abc/default.jpg"},
+                    {"url": "https://i.ytimg.com/vi/abc/maxresdefault.jpg"}
+                ]}
+            },
+            "microformat": {"playerMicroformatRenderer": {"publishDate": "2023-05-01"}},
+            "captions": {"playerCaptionsTracklistRenderer": {"captionTracks": [
+                {"baseUrl": "https://www.youtube.com/api/timedtext?v=abc&lang=en&kind=asr", "kind": "asr"},
+                {"baseUrl": "https://www.youtube.com/api/timedtext?v=abc&lang=en"}
+            ]}}
+        });
+        assert_eq!(
+            Some("https://www.youtube.com/api/timedtext?v=abc&lang=en&fmt=json3".to_owned()),
+            caption_track_url(&player)
+        );
+        let captions = parse_captions(&json!({"events": [
+            {"tStartMs": 0, "segs": [{"utf8": "Hello"}, {"utf8": " everyone"}]},
+            {"tStartMs": 1500, "aAppend": 1, "segs": [{"utf8": "\n"}]},
+            {"tStartMs": 30000, "segs": [{"utf8": "welcome"}]},
+            {"tStartMs": 3725000, "segs": [{"utf8": "Thanks"}]}
+        ]}));
+        assert_eq!(3, captions.len());
+
+        let article = video_article(&player, "abc", &captions).unwrap();
+        assert_eq!(Some("About the talk"), article.description.as_deref());
+        assert_eq!(
+            Some("https://i.ytimg.com/vi/abc/maxresdefault.jpg"),
+            article.cover_image.as_deref()
+        );
+        let doc = kuchiki::parse_html().one(article.document());
+        let content = doc.select_first(&format!("#{}", API_ARTICLE_ID)).unwrap();
+        let headings = content
+            .as_node()
+            .select("h2")
+            .unwrap()
+            .map(|h2| h2.text_contents())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["0:00", "1:02:05"], headings);
+        assert_eq!(
+            "Hello everyone welcome",
+            content
+                .as_node()
+                .select_first("h2 + p")
+                .unwrap()
+                .text_contents()
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! Runs the extractor against the test pages of Mozilla's readability to make regressions in
+//! the port visible. The test pages are not included in this repository so this test is
+//! ignored by default. To run it, clone https://github.com/mozilla/readability and run
+//!
+//! ```sh
+//! READABILITY_TEST_PAGES=../readability/test/test-pages cargo test --test readability_corpus -- --ignored --nocapture
+//! ```
+
+use std::env;
+use std::process::Command;
+
+#[test]
+#[ignore]
+fn test_readability_corpus() {
+    let test_pages = env::var("READABILITY_TEST_PAGES")
+        .expect("READABILITY_TEST_PAGES must be set to the test pages of Mozilla's readability");
+    let min_score = env::var("READABILITY_MIN_SCORE").unwrap_or_else(|_| "0.8".to_owned());
+    let output = Command::new(env!("CARGO_BIN_EXE_paperoni"))
+        .args(&["selftest", &test_pages, "--min-score", &min_score])
+        .output()
+        .expect("Failed to run paperoni");
+    println!("{}", String::from_utf8_lossy(&output.stdout));
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}